@@ -0,0 +1,113 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU Lesser General Public License as published by the Free
+ * Software Foundation, either version 3 of the License, or (at your option) any
+ * later version.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT ANY
+ * WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+ * PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License along
+ * with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/*
+ * File: examples/c/error_bench.c
+ *
+ * A benchmark of how quickly libpathrs can store and retrieve errors when many
+ * threads are hitting errors at the same time. Each thread repeatedly calls
+ * pathrs_set_error_mode() with an invalid mode (which fails without doing any
+ * syscalls), then retrieves and frees the error with pathrs_errorinfo().
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <time.h>
+#include <pthread.h>
+
+#include "../../include/pathrs.h"
+
+#define bail(fmt, ...) \
+	do { fprintf(stderr, fmt "\n", ##__VA_ARGS__); exit(1); } while (0)
+
+struct args {
+	pthread_barrier_t *barrier;
+	long iterations;
+};
+
+void *worker(void *_arg)
+{
+	struct args *arg = _arg;
+
+	pthread_barrier_wait(arg->barrier);
+
+	for (long i = 0; i < arg->iterations; i++) {
+		int liberr = pathrs_set_error_mode(0xdead);
+		if (liberr >= 0)
+			bail("pathrs_set_error_mode with an invalid mode succeeded");
+
+		pathrs_error_t *error = pathrs_errorinfo(liberr);
+		if (!error)
+			bail("pathrs_errorinfo(%d) returned no error", liberr);
+		pathrs_errorinfo_free(error);
+	}
+	return NULL;
+}
+
+void usage(void)
+{
+	printf("usage: error_bench <threads> <errors-per-thread>\n");
+	exit(1);
+}
+
+int main(int argc, char **argv)
+{
+	long num_threads, iterations;
+	pthread_barrier_t barrier;
+	pthread_t *threads;
+	struct args arg;
+	struct timespec start, end;
+	double elapsed;
+
+	if (argc != 3)
+		usage();
+
+	num_threads = atol(argv[1]);
+	iterations = atol(argv[2]);
+	if (num_threads <= 0 || iterations <= 0)
+		usage();
+
+	threads = calloc(num_threads, sizeof(*threads));
+	if (!threads)
+		bail("failed to allocate threads");
+
+	/* Include the main thread in the barrier so we only time the workers. */
+	pthread_barrier_init(&barrier, NULL, num_threads + 1);
+	arg = (struct args) {
+		.barrier = &barrier,
+		.iterations = iterations,
+	};
+	for (long i = 0; i < num_threads; i++)
+		pthread_create(&threads[i], NULL, worker, &arg);
+
+	pthread_barrier_wait(&barrier);
+	clock_gettime(CLOCK_MONOTONIC, &start);
+	for (long i = 0; i < num_threads; i++)
+		pthread_join(threads[i], NULL);
+	clock_gettime(CLOCK_MONOTONIC, &end);
+
+	elapsed = (end.tv_sec - start.tv_sec) * 1e3 +
+		  (end.tv_nsec - start.tv_nsec) / 1e6;
+	printf("%ld threads x %ld errors: %.1fms (%.0fns per error)\n",
+	       num_threads, iterations, elapsed,
+	       elapsed * 1e6 / (num_threads * iterations));
+
+	pthread_barrier_destroy(&barrier);
+	free(threads);
+	return 0;
+}
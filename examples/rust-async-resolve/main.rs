@@ -0,0 +1,93 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/*
+ * File: examples/rust-async-resolve/main.rs
+ *
+ * An example program which concurrently resolves many paths inside a root
+ * from a tokio runtime, using libpathrs's async wrappers.
+ */
+
+use pathrs::{flags::OpenFlags, Root};
+
+use std::{io::prelude::*, os::unix::fs::MetadataExt, sync::Arc};
+
+use anyhow::{Context, Error};
+use clap::{Arg, Command};
+
+fn main() -> Result<(), Error> {
+    let m = Command::new("async-resolve")
+        // MSRV(1.67): Use clap::crate_authors!.
+        .author("Aleksa Sarai <cyphar@cyphar.com>")
+        .version(clap::crate_version!())
+        .arg(Arg::new("root").value_name("ROOT").required(true))
+        .arg(
+            Arg::new("unsafe-path")
+                .value_name("PATH")
+                .multiple_values(true)
+                .required(true),
+        )
+        .about("Concurrently resolve paths inside a root with tokio.")
+        .get_matches();
+
+    let root_path = m
+        .get_one::<String>("root")
+        .context("required root argument not provided")?;
+    let unsafe_paths = m
+        .get_many::<String>("unsafe-path")
+        .context("required unsafe-path argument not provided")?
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // The Root is shared between all of the tasks.
+    let root = Arc::new(Root::open(root_path).context("open root failed")?);
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .context("create tokio runtime")?;
+    runtime.block_on(async {
+        let tasks = unsafe_paths
+            .into_iter()
+            .map(|path| {
+                let root = Arc::clone(&root);
+                tokio::spawn(async move {
+                    let handle = Arc::new(
+                        root.resolve_async(&path)
+                            .await
+                            .with_context(|| format!("resolve {path:?}"))?,
+                    );
+                    let mut file = handle
+                        .reopen_async(OpenFlags::O_RDONLY)
+                        .await
+                        .with_context(|| format!("reopen {path:?}"))?;
+                    let ino = file.metadata().context("fstat file")?.ino();
+                    let mut contents = Vec::new();
+                    file.read_to_end(&mut contents).context("read file")?;
+                    Ok::<_, Error>((path, ino, contents.len()))
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for task in tasks {
+            let (path, ino, len) = task.await.context("join resolve task")??;
+            println!("{path}: inode {ino}, {len} bytes");
+        }
+        Ok(())
+    })
+}
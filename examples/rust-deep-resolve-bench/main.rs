@@ -0,0 +1,94 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/*
+ * File: examples/rust-deep-resolve-bench/main.rs
+ *
+ * An example program which times resolving a deep (40-component) path with
+ * each resolver backend. Only the selected backend is used, so running this
+ * under `strace -c -f` gives the number of syscalls needed per resolution.
+ */
+
+use pathrs::{PublicResolverBackend, Root};
+
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Error};
+use clap::{Arg, Command};
+
+const DEPTH: usize = 40;
+
+fn main() -> Result<(), Error> {
+    let m = Command::new("deep-resolve-bench")
+        // MSRV(1.67): Use clap::crate_authors!.
+        .author("Aleksa Sarai <cyphar@cyphar.com>")
+        .version(clap::crate_version!())
+        .arg(
+            Arg::new("count")
+                .value_name("COUNT")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10000"),
+        )
+        .arg(
+            Arg::new("backend")
+                .long("backend")
+                .value_parser(["openat2", "emulated"])
+                .default_value("emulated"),
+        )
+        .about("Time resolutions of a 40-component path.")
+        .get_matches();
+
+    let count = *m.get_one::<usize>("count").expect("count has a default");
+    let backend = match m
+        .get_one::<String>("backend")
+        .expect("backend has a default")
+        .as_str()
+    {
+        "openat2" => PublicResolverBackend::Openat2,
+        _ => PublicResolverBackend::Emulated,
+    };
+
+    // Create d1/d2/.../d39/file.
+    let tmpdir = tempfile::TempDir::new().context("create temporary root")?;
+    let dir = (1..DEPTH).map(|i| format!("d{i}")).collect::<PathBuf>();
+    fs::create_dir_all(tmpdir.path().join(&dir)).context("create deep directory")?;
+    let path = dir.join("file");
+    fs::write(tmpdir.path().join(&path), "").context("create file")?;
+
+    let root = Root::open_with_backend(tmpdir.path(), backend).context("open root failed")?;
+
+    let start = Instant::now();
+    for _ in 0..count {
+        root.resolve(&path)
+            .with_context(|| format!("resolve {path:?}"))?;
+    }
+    let elapsed = start.elapsed();
+
+    println!("resolving a {DEPTH}-component path {count} times with the {backend:?} backend:");
+    println!("  total:          {elapsed:?}");
+    println!(
+        "  per resolution: {:?}",
+        elapsed.checked_div(count as u32).unwrap_or(Duration::ZERO)
+    );
+    Ok(())
+}
@@ -0,0 +1,111 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/*
+ * File: examples/rust-dircache-bench/main.rs
+ *
+ * An example program which compares the time taken to resolve many paths
+ * sharing the same directory prefix with Root::resolve and with
+ * Root::resolve_cached.
+ */
+
+use pathrs::{DirCache, PublicResolverBackend, Root};
+
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Error};
+use clap::{Arg, Command};
+
+const PREFIX: &str = "a/b/c/d/e/f/g/h";
+
+fn time_resolve<F>(paths: &[PathBuf], mut resolve: F) -> Result<Duration, Error>
+where
+    F: FnMut(&PathBuf) -> Result<(), Error>,
+{
+    let start = Instant::now();
+    for path in paths {
+        resolve(path).with_context(|| format!("resolve {path:?}"))?;
+    }
+    Ok(start.elapsed())
+}
+
+fn main() -> Result<(), Error> {
+    let m = Command::new("dircache-bench")
+        // MSRV(1.67): Use clap::crate_authors!.
+        .author("Aleksa Sarai <cyphar@cyphar.com>")
+        .version(clap::crate_version!())
+        .arg(
+            Arg::new("count")
+                .value_name("COUNT")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10000"),
+        )
+        .about("Compare Root::resolve and Root::resolve_cached for same-prefix paths.")
+        .get_matches();
+
+    let count = *m.get_one::<usize>("count").expect("count has a default");
+
+    let tmpdir = tempfile::TempDir::new().context("create temporary root")?;
+    let prefix = tmpdir.path().join(PREFIX);
+    fs::create_dir_all(&prefix).context("create directory prefix")?;
+    let paths = (0..count)
+        .map(|i| -> Result<PathBuf, Error> {
+            let name = format!("file{i}");
+            fs::write(prefix.join(&name), "").context("create file")?;
+            Ok(PathBuf::from(PREFIX).join(name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    println!("resolving {count} paths under {PREFIX:?}:");
+    for backend in [
+        PublicResolverBackend::Openat2,
+        PublicResolverBackend::Emulated,
+    ] {
+        if !backend.is_supported() {
+            continue;
+        }
+        let root = Root::open_with_backend(tmpdir.path(), backend).context("open root failed")?;
+
+        let plain = time_resolve(&paths, |path| {
+            root.resolve(path).map(|_| ()).map_err(Into::into)
+        })?;
+
+        let mut cache = DirCache::new();
+        let cached = time_resolve(&paths, |path| {
+            root.resolve_cached(path, &mut cache)
+                .map(|_| ())
+                .map_err(Into::into)
+        })?;
+
+        println!("  {backend:?} backend:");
+        println!("    Root::resolve:        {plain:?}");
+        println!("    Root::resolve_cached: {cached:?}");
+        let stats = cache.stats();
+        println!(
+            "    DirCache:             {} hits, {} misses",
+            stats.hits(),
+            stats.misses()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/*
+ * File: examples/rust-readlink-bench/main.rs
+ *
+ * An example program which compares the number of allocations and the time
+ * taken to read many symlinks with Handle::readlink and with
+ * Handle::readlink_into.
+ */
+
+use pathrs::{Handle, Root};
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs,
+    os::unix::fs as unixfs,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Error};
+use clap::{Arg, Command};
+
+/// Wrapper around the system allocator which counts allocations.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn count_readlink<F>(handles: &[Handle], mut readlink: F) -> Result<(usize, Duration), Error>
+where
+    F: FnMut(&Handle) -> Result<usize, Error>,
+{
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut total = 0;
+    for handle in handles {
+        total += readlink(handle).context("readlink")?;
+    }
+    let elapsed = start.elapsed();
+    // Make sure the reads are not optimised away.
+    assert!(total > 0, "symlink targets should not be empty");
+    Ok((ALLOCATIONS.load(Ordering::Relaxed) - allocations, elapsed))
+}
+
+fn main() -> Result<(), Error> {
+    let m = Command::new("readlink-bench")
+        // MSRV(1.67): Use clap::crate_authors!.
+        .author("Aleksa Sarai <cyphar@cyphar.com>")
+        .version(clap::crate_version!())
+        .arg(
+            Arg::new("count")
+                .value_name("COUNT")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("10000"),
+        )
+        .about("Compare Handle::readlink and Handle::readlink_into for many symlinks.")
+        .get_matches();
+
+    let count = *m.get_one::<usize>("count").expect("count has a default");
+
+    let tmpdir = tempfile::TempDir::new().context("create temporary root")?;
+    let linkdir = tmpdir.path().join("links");
+    fs::create_dir(&linkdir).context("create symlink directory")?;
+    for i in 0..count {
+        unixfs::symlink(
+            format!("../some/target/path/{i}"),
+            linkdir.join(i.to_string()),
+        )
+        .context("create symlink")?;
+    }
+
+    let root = Root::open(tmpdir.path()).context("open root failed")?;
+    let handles = (0..count)
+        .map(|i| root.resolve_nofollow(format!("links/{i}")))
+        .collect::<Result<Vec<_>, _>>()
+        .context("resolve symlinks")?;
+
+    let (plain_allocs, plain) =
+        count_readlink(&handles, |handle| Ok(handle.readlink()?.as_os_str().len()))?;
+
+    let mut buf = Vec::new();
+    let (into_allocs, into) = count_readlink(&handles, |handle| {
+        handle.readlink_into(&mut buf).map_err(Into::into)
+    })?;
+
+    println!("reading {count} symlinks:");
+    println!("  Handle::readlink:      {plain:?} ({plain_allocs} allocations)");
+    println!("  Handle::readlink_into: {into:?} ({into_allocs} allocations)");
+    Ok(())
+}
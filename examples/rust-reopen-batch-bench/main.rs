@@ -0,0 +1,97 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/*
+ * File: examples/rust-reopen-batch-bench/main.rs
+ *
+ * An example program which compares the time taken to re-open many handles
+ * with HandleRef::reopen (one at a time) and with HandleRef::reopen_batch
+ * (which uses io_uring with the io-uring feature).
+ */
+
+use pathrs::{flags::OpenFlags, Handle, HandleRef, Root};
+
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Error};
+use clap::{Arg, Command};
+
+fn time_reopen<F>(mut reopen: F) -> Result<(usize, Duration), Error>
+where
+    F: FnMut() -> Result<usize, Error>,
+{
+    let start = Instant::now();
+    let opened = reopen()?;
+    Ok((opened, start.elapsed()))
+}
+
+fn main() -> Result<(), Error> {
+    let m = Command::new("reopen-batch-bench")
+        // MSRV(1.67): Use clap::crate_authors!.
+        .author("Aleksa Sarai <cyphar@cyphar.com>")
+        .version(clap::crate_version!())
+        .arg(
+            Arg::new("count")
+                .value_name("COUNT")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1000"),
+        )
+        .about("Compare HandleRef::reopen and HandleRef::reopen_batch.")
+        .get_matches();
+
+    let count = *m.get_one::<usize>("count").expect("count has a default");
+
+    let tmpdir = tempfile::TempDir::new().context("create temporary root")?;
+    for i in 0..count {
+        fs::write(tmpdir.path().join(format!("file{i}")), "").context("create file")?;
+    }
+
+    let root = Root::open(tmpdir.path()).context("open root failed")?;
+    let handles = (0..count)
+        .map(|i| root.resolve(format!("file{i}")))
+        .collect::<Result<Vec<_>, _>>()
+        .context("resolve files")?;
+    let handle_refs = handles.iter().map(Handle::as_ref).collect::<Vec<_>>();
+
+    let (plain_opened, plain) = time_reopen(|| {
+        Ok(handle_refs
+            .iter()
+            .map(|handle| handle.reopen(OpenFlags::O_RDONLY))
+            .filter(Result::is_ok)
+            .count())
+    })?;
+    let (batch_opened, batch) = time_reopen(|| {
+        Ok(HandleRef::reopen_batch(&handle_refs, OpenFlags::O_RDONLY)
+            .into_iter()
+            .filter(Result::is_ok)
+            .count())
+    })?;
+    assert_eq!(
+        plain_opened, batch_opened,
+        "HandleRef::reopen and HandleRef::reopen_batch should open the same handles"
+    );
+
+    println!("re-opening {count} handles ({plain_opened} opened):");
+    println!("  HandleRef::reopen:       {plain:?}");
+    println!("  HandleRef::reopen_batch: {batch:?}");
+    Ok(())
+}
@@ -0,0 +1,105 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/*
+ * File: examples/rust-resolve-many-bench/main.rs
+ *
+ * An example program which compares the time taken to check whether many
+ * sibling paths exist (half of which do not) with Root::resolve and with
+ * Root::resolve_many using the emulated resolver.
+ */
+
+use pathrs::{PublicResolverBackend, Root};
+
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Error};
+use clap::{Arg, Command};
+
+const PREFIX: &str = "a/b/c/d";
+
+fn time_resolve<F>(mut resolve: F) -> Result<(usize, Duration), Error>
+where
+    F: FnMut() -> Result<usize, Error>,
+{
+    let start = Instant::now();
+    let found = resolve()?;
+    Ok((found, start.elapsed()))
+}
+
+fn main() -> Result<(), Error> {
+    let m = Command::new("resolve-many-bench")
+        // MSRV(1.67): Use clap::crate_authors!.
+        .author("Aleksa Sarai <cyphar@cyphar.com>")
+        .version(clap::crate_version!())
+        .arg(
+            Arg::new("count")
+                .value_name("COUNT")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1000"),
+        )
+        .about("Compare Root::resolve and Root::resolve_many for sibling paths.")
+        .get_matches();
+
+    let count = *m.get_one::<usize>("count").expect("count has a default");
+
+    let tmpdir = tempfile::TempDir::new().context("create temporary root")?;
+    let prefix = tmpdir.path().join(PREFIX);
+    fs::create_dir_all(&prefix).context("create directory prefix")?;
+    // Only every other path exists.
+    let paths = (0..count)
+        .map(|i| -> Result<PathBuf, Error> {
+            let name = format!("file{i}");
+            if i % 2 == 0 {
+                fs::write(prefix.join(&name), "").context("create file")?;
+            }
+            Ok(PathBuf::from(PREFIX).join(name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let root = Root::open_with_backend(tmpdir.path(), PublicResolverBackend::Emulated)
+        .context("open root failed")?;
+
+    let (plain_found, plain) = time_resolve(|| {
+        Ok(paths
+            .iter()
+            .filter(|path| root.resolve(path).is_ok())
+            .count())
+    })?;
+    let (many_found, many) = time_resolve(|| {
+        Ok(root
+            .resolve_many(&paths)
+            .into_iter()
+            .filter(Result::is_ok)
+            .count())
+    })?;
+    assert_eq!(
+        plain_found, many_found,
+        "Root::resolve and Root::resolve_many should find the same paths"
+    );
+
+    println!("resolving {count} sibling paths under {PREFIX:?} ({plain_found} exist):");
+    println!("  Root::resolve:      {plain:?}");
+    println!("  Root::resolve_many: {many:?}");
+    Ok(())
+}
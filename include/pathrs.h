@@ -38,6 +38,54 @@
 #include <stdlib.h>
 #include <sys/types.h>
 
+/**
+ * The size of the first published version of pathrs_metadata_t.
+ */
+#define PATHRS_METADATA_SIZE_VER0 112
+
+/**
+ * Do not overwrite the destination of pathrs_inroot_rename(). Identical to
+ * RENAME_NOREPLACE.
+ */
+#define PATHRS_RENAME_NOREPLACE (1 << 0)
+
+/**
+ * Atomically exchange the source and destination of pathrs_inroot_rename().
+ * Identical to RENAME_EXCHANGE.
+ */
+#define PATHRS_RENAME_EXCHANGE (1 << 1)
+
+/**
+ * Leave a whiteout at the source of pathrs_inroot_rename(). Identical to
+ * RENAME_WHITEOUT.
+ */
+#define PATHRS_RENAME_WHITEOUT (1 << 2)
+
+/**
+ * Select how errors from libpathrs operations on the current thread are
+ * stored, using pathrs_set_error_mode().
+ */
+enum pathrs_error_mode_t {
+    /**
+     * Store errors in a process-wide table. The returned error ids can be
+     * passed to pathrs_errorinfo() on any thread. This is the default.
+     */
+    PATHRS_ERRMODE_GLOBAL = 0,
+    /**
+     * Store the error in a per-thread "last error" slot, avoiding the locking
+     * required for the process-wide table. Only the most recent error on each
+     * thread is kept (any previous unretrieved error is discarded), and the
+     * returned error id can only be passed to pathrs_errorinfo() on the same
+     * thread.
+     *
+     * NOTE: This mode must not be used from languages where your code can
+     * change threads without warning (such as Go), unless the thread has been
+     * locked (such as with runtime.LockOSThread).
+     */
+    PATHRS_ERRMODE_THREAD_LOCAL = 1,
+};
+typedef uint32_t pathrs_error_mode_t;
+
 /**
  * Indicate what base directory should be used when doing operations with
  * pathrs_proc_*. This is necessary because /proc/thread-self is not present on
@@ -68,20 +116,143 @@ enum pathrs_proc_base_t {
 };
 typedef uint64_t pathrs_proc_base_t;
 
+/**
+ * A timestamp, in seconds and nanoseconds since the Unix epoch. This is
+ * equivalent to `struct statx_timestamp`.
+ */
+typedef struct {
+    /**
+     * Seconds since the Unix epoch.
+     */
+    int64_t sec;
+    /**
+     * Nanoseconds since `sec`.
+     */
+    uint32_t nsec;
+    uint32_t __reserved;
+} pathrs_timestamp_t;
+
+/**
+ * Metadata about an inode, filled by pathrs_handle_metadata().
+ *
+ * This structure is extensible. Callers must set `struct_size` to
+ * `sizeof(pathrs_metadata_t)` before calling pathrs_handle_metadata() -- only
+ * that many bytes will be written by libpathrs. On return, `struct_size` and
+ * `version` describe how much of the structure was actually filled, so
+ * programs compiled against newer headers can detect older versions of
+ * libpathrs (and vice versa).
+ */
+typedef struct {
+    /**
+     * Size of the structure (in bytes). Must be set by the caller, and is
+     * updated by libpathrs to the number of bytes that were filled.
+     */
+    uint32_t struct_size;
+    /**
+     * Version of the structure that was filled by libpathrs (starting at 0).
+     */
+    uint32_t version;
+    /**
+     * Mask of fields that were filled, using the `STATX_*` values from
+     * statx(2). Fields not included in the mask have unspecified values.
+     */
+    uint64_t mask;
+    /**
+     * The file type of the inode (`mode & S_IFMT`).
+     */
+    uint32_t file_type;
+    /**
+     * The file type and mode (`st_mode`) of the inode.
+     */
+    uint32_t mode;
+    /**
+     * The owner (`st_uid`) of the inode.
+     */
+    uint32_t uid;
+    /**
+     * The group owner (`st_gid`) of the inode.
+     */
+    uint32_t gid;
+    /**
+     * The number of hard links (`st_nlink`) to the inode.
+     */
+    uint64_t nlink;
+    /**
+     * The size (`st_size`) of the inode in bytes.
+     */
+    uint64_t size;
+    /**
+     * The inode number (`st_ino`).
+     */
+    uint64_t ino;
+    /**
+     * The device (`st_dev`) containing the inode.
+     */
+    uint64_t dev;
+    /**
+     * The last access time (`st_atime`) of the inode.
+     */
+    pathrs_timestamp_t atime;
+    /**
+     * The last modification time (`st_mtime`) of the inode.
+     */
+    pathrs_timestamp_t mtime;
+    /**
+     * The last status change time (`st_ctime`) of the inode.
+     */
+    pathrs_timestamp_t ctime;
+} pathrs_metadata_t;
+
+/**
+ * A single level in the chain of causes of a pathrs_error_t.
+ */
+typedef struct __CBINDGEN_ALIGNED(8) {
+    /**
+     * Raw errno(3) value of this cause (or 0 if this cause is not a syscall
+     * error).
+     */
+    uint64_t saved_errno;
+    /**
+     * Textual description of this cause.
+     */
+    const char *description;
+} pathrs_error_cause_t;
+
 /**
  * Attempts to represent a Rust Error type in C. This structure must be freed
  * using pathrs_errorinfo_free().
  */
 typedef struct __CBINDGEN_ALIGNED(8) {
     /**
-     * Raw errno(3) value of the underlying error (or 0 if the source of the
-     * error was not due to a syscall error).
+     * Raw errno(3) value of the underlying error. If the source of the error
+     * was not a syscall error, this is an errno describing the kind of error
+     * (such as EINVAL for invalid arguments or EXDEV for safety violations).
      */
     uint64_t saved_errno;
     /**
      * Textual description of the error.
      */
     const char *description;
+    /**
+     * Number of entries in the causes array.
+     */
+    uint64_t num_causes;
+    /**
+     * Array of num_causes entries describing each level of the error chain,
+     * starting with the error itself and ending with the root cause. Unlike
+     * description, the description of each entry does not include the
+     * descriptions of its own causes.
+     */
+    const pathrs_error_cause_t *causes;
+    /**
+     * The size of this structure (in bytes) as allocated by libpathrs. Fields
+     * which end beyond this offset are not present and must not be read.
+     *
+     * Note that this field was added after the fields above (and so older
+     * versions of libpathrs do not set it). Callers that need to support
+     * those versions should check the libpathrs version first.
+     */
+    uint32_t struct_size;
 } pathrs_error_t;
 
 /**
@@ -99,7 +270,8 @@ typedef struct __CBINDGEN_ALIGNED(8) {
  * # Return Value
  *
  * On success, this function returns a file descriptor that can be used as a
- * root handle in subsequent pathrs_inroot_* operations.
+ * root handle in subsequent pathrs_inroot_* operations. The file descriptor
+ * will have the `O_CLOEXEC` flag automatically applied.
  *
  * If an error occurs, this function will return a negative error code. To
  * retrieve information about the error (such as a string describing the error,
@@ -108,6 +280,35 @@ typedef struct __CBINDGEN_ALIGNED(8) {
  */
 int pathrs_open_root(const char *path);
 
+/**
+ * Duplicate a root handle.
+ *
+ * The C API does not store any resolver configuration alongside root handles
+ * (every pathrs_inroot_* operation uses the default resolver configuration),
+ * so this function is effectively equivalent to
+ *
+ * ```c
+ * newfd = fcntl(root_fd, F_DUPFD_CLOEXEC, 0);
+ * ```
+ *
+ * The returned file descriptor is independent of `root_fd`, and so it remains
+ * usable as a root handle even after `root_fd` has been closed. Any resolver
+ * configuration the caller tracks for `root_fd` applies equally to the new
+ * handle.
+ *
+ * # Return Value
+ *
+ * On success, this function returns a file descriptor that can be used as a
+ * root handle in subsequent pathrs_inroot_* operations. The file descriptor
+ * will have the `O_CLOEXEC` flag automatically applied.
+ *
+ * If an error occurs, this function will return a negative error code. To
+ * retrieve information about the error (such as a string describing the error,
+ * the system errno(7) value associated with the error, etc), use
+ * pathrs_errorinfo().
+ */
+int pathrs_root_clone(int root_fd);
+
 /**
  * "Upgrade" an O_PATH file descriptor to a usable fd, suitable for reading and
  * writing. This does not consume the original file descriptor. (This can be
@@ -123,7 +324,8 @@ int pathrs_open_root(const char *path);
  *
  * # Return Value
  *
- * On success, this function returns a file descriptor.
+ * On success, this function returns a file descriptor. The file descriptor
+ * will have the `O_CLOEXEC` flag automatically applied.
  *
  * If an error occurs, this function will return a negative error code. To
  * retrieve information about the error (such as a string describing the error,
@@ -132,6 +334,37 @@ int pathrs_open_root(const char *path);
  */
 int pathrs_reopen(int fd, int flags);
 
+/**
+ * Get the metadata of the inode referenced by a handle (such as one returned
+ * by pathrs_inroot_resolve()), without needing to re-open it. This is
+ * equivalent to fstat(2) (or statx(2) where available) on the handle.
+ *
+ * `out->struct_size` must be set to `sizeof(pathrs_metadata_t)` by the
+ * caller. Only the first `out->struct_size` bytes of `out` are written to,
+ * and on return `out->struct_size` and `out->version` describe which
+ * version of the structure was filled. `out->mask` indicates which of the
+ * fields contain valid data.
+ *
+ * ```c
+ * pathrs_metadata_t meta = { .struct_size = sizeof(meta) };
+ * int ret = pathrs_handle_metadata(handle_fd, &meta);
+ * if (ret < 0) {
+ *     liberr = ret; // for use with pathrs_errorinfo()
+ *     goto err;
+ * }
+ * ```
+ *
+ * # Return Value
+ *
+ * On success, this function returns 0.
+ *
+ * If an error occurs, this function will return a negative error code. To
+ * retrieve information about the error (such as a string describing the error,
+ * the system errno(7) value associated with the error, etc), use
+ * pathrs_errorinfo().
+ */
+int pathrs_handle_metadata(int handle_fd, pathrs_metadata_t *out);
+
 /**
  * Resolve the given path within the rootfs referenced by root_fd. The path
  * *must already exist*, otherwise an error will occur.
@@ -188,7 +421,8 @@ int pathrs_inroot_resolve_nofollow(int root_fd, const char *path);
  *
  * # Return Value
  *
- * On success, this function returns a file descriptor.
+ * On success, this function returns a file descriptor. The file descriptor
+ * will have the `O_CLOEXEC` flag automatically applied.
  *
  * If an error occurs, this function will return a negative error code. To
  * retrieve information about the error (such as a string describing the error,
@@ -241,7 +475,13 @@ int pathrs_inroot_readlink(int root_fd,
 
 /**
  * Rename a path within the rootfs referenced by root_fd. The flags argument is
- * identical to the renameat2(2) flags that are supported on the system.
+ * identical to the renameat2(2) flags that are supported on the system. The
+ * PATHRS_RENAME_* constants can be used instead of the RENAME_* definitions
+ * from <linux/fs.h> (which older libc headers do not provide).
+ *
+ * PATHRS_RENAME_EXCHANGE cannot be combined with PATHRS_RENAME_NOREPLACE or
+ * PATHRS_RENAME_WHITEOUT -- such combinations are rejected with an EINVAL
+ * error whose description explains the problem.
  *
  * # Return Value
  *
@@ -330,7 +570,8 @@ int pathrs_inroot_remove_all(int root_fd, const char *path);
  * # Return Value
  *
  * On success, this function returns a file descriptor to the requested file.
- * The open flags are based on the provided flags.
+ * The open flags are based on the provided flags. The file descriptor will
+ * have the `O_CLOEXEC` flag automatically applied.
  *
  * If an error occurs, this function will return a negative error code. To
  * retrieve information about the error (such as a string describing the error,
@@ -362,6 +603,14 @@ int pathrs_inroot_mkdir(int root_fd, const char *path, unsigned int mode);
  * Create a new directory (and any of its path components if they don't exist)
  * within the rootfs referenced by root_fd.
  *
+ * The path is resolved as far as possible within the rootfs, and each missing
+ * component is then created with mkdirat(2) relative to a file descriptor for
+ * its parent directory (never by path), so swapping a component for a symlink
+ * while directories are being created cannot redirect the remaining
+ * components outside of the rootfs. Components that already exist as
+ * directories are re-used, but an existing non-directory component is an
+ * error (ENOTDIR).
+ *
  * # Return Value
  *
  * On success, this function returns an O_DIRECTORY file descriptor to the
@@ -453,7 +702,8 @@ int pathrs_inroot_hardlink(int root_fd, const char *path, const char *target);
  *
  * # Return Value
  *
- * On success, this function returns a file descriptor.
+ * On success, this function returns a file descriptor. The file descriptor
+ * will have the `O_CLOEXEC` flag automatically applied.
  *
  * If an error occurs, this function will return a negative error code. To
  * retrieve information about the error (such as a string describing the error,
@@ -505,6 +755,31 @@ int pathrs_proc_readlink(pathrs_proc_base_t base,
                          char *linkbuf,
                          size_t linkbuf_size);
 
+/**
+ * Set how errors from libpathrs operations on the current thread are stored.
+ *
+ * By default (PATHRS_ERRMODE_GLOBAL), errors are stored in a process-wide
+ * table and the returned error ids can be passed to pathrs_errorinfo() on any
+ * thread. With PATHRS_ERRMODE_THREAD_LOCAL, errors are instead stored in a
+ * per-thread slot, which is cheaper but means that pathrs_errorinfo() must be
+ * called on the same thread as the failed operation (before any other
+ * libpathrs operation on that thread fails). See pathrs_error_mode_t for more
+ * details.
+ *
+ * The mode only applies to the calling thread. Errors already stored with the
+ * previous mode can still be retrieved after changing the mode.
+ *
+ * # Return Value
+ *
+ * On success, this function returns the previous error mode of the thread.
+ *
+ * If an error occurs, this function will return a negative error code. To
+ * retrieve information about the error (such as a string describing the error,
+ * the system errno(7) value associated with the error, etc), use
+ * pathrs_errorinfo().
+ */
+int pathrs_set_error_mode(pathrs_error_mode_t mode);
+
 /**
  * Retrieve error information about an error id returned by a pathrs operation.
  *
@@ -536,6 +811,15 @@ int pathrs_proc_readlink(pathrs_proc_base_t base,
  * particular note to green-thread language bindings like Go, where this is
  * important).
  *
+ * The exception to this is if the thread where the operation failed had
+ * switched to PATHRS_ERRMODE_THREAD_LOCAL with pathrs_set_error_mode(), in
+ * which case pathrs_errorinfo() must be called on that same thread (and only
+ * the most recent error on that thread can be retrieved).
+ *
+ * If an extremely large number of errors (over a million) have not been
+ * retrieved, new errors are dropped and replaced with an error (with errno set
+ * to ENOMEM) describing the problem.
+ *
  * # Return Value
  *
  * If there was a saved error with the provided id, a pathrs_error_t is
@@ -549,7 +833,7 @@ pathrs_error_t *pathrs_errorinfo(int err_id);
  */
 void pathrs_errorinfo_free(pathrs_error_t *ptr);
 
-#endif /* LIBPATHRS_H */
+#endif  /* LIBPATHRS_H */
 
 #ifdef __CBINDGEN_ALIGNED
 #undef __CBINDGEN_ALIGNED
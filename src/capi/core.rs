@@ -24,14 +24,18 @@ use crate::{
     },
     error::{Error, ErrorImpl},
     flags::{OpenFlags, RenameFlags},
+    handle::Timestamp,
     procfs::GLOBAL_PROCFS_HANDLE,
     utils::FdExt,
-    InodeType, Root, RootRef,
+    HandleRef, InodeType, Metadata, Root, RootRef,
 };
 
 use std::{
+    cmp,
     fs::Permissions,
+    mem,
     os::unix::{fs::PermissionsExt, io::RawFd},
+    ptr,
 };
 
 use libc::{c_char, c_int, c_uint, dev_t, size_t};
@@ -64,6 +68,40 @@ pub unsafe extern "C" fn pathrs_open_root(path: *const c_char) -> RawFd {
         .into_c_return()
 }
 
+/// Duplicate a root handle.
+///
+/// The C API does not store any resolver configuration alongside root handles
+/// (every pathrs_inroot_* operation uses the default resolver configuration),
+/// so this function is effectively equivalent to
+///
+/// ```c
+/// newfd = fcntl(root_fd, F_DUPFD_CLOEXEC, 0);
+/// ```
+///
+/// The returned file descriptor is independent of `root_fd`, and so it remains
+/// usable as a root handle even after `root_fd` has been closed. Any resolver
+/// configuration the caller tracks for `root_fd` applies equally to the new
+/// handle.
+///
+/// # Return Value
+///
+/// On success, this function returns a file descriptor that can be used as a
+/// root handle in subsequent pathrs_inroot_* operations. The file descriptor
+/// will have the `O_CLOEXEC` flag automatically applied.
+///
+/// If an error occurs, this function will return a negative error code. To
+/// retrieve information about the error (such as a string describing the error,
+/// the system errno(7) value associated with the error, etc), use
+/// pathrs_errorinfo().
+#[no_mangle]
+pub extern "C" fn pathrs_root_clone(root_fd: CBorrowedFd<'_>) -> RawFd {
+    || -> Result<_, Error> {
+        let root_fd = root_fd.try_as_borrowed_fd()?;
+        RootRef::from_fd(root_fd).try_clone()
+    }()
+    .into_c_return()
+}
+
 /// "Upgrade" an O_PATH file descriptor to a usable fd, suitable for reading and
 /// writing. This does not consume the original file descriptor. (This can be
 /// used with non-O_PATH file descriptors as well.)
@@ -96,6 +134,167 @@ pub extern "C" fn pathrs_reopen(fd: CBorrowedFd<'_>, flags: c_int) -> RawFd {
     .into_c_return()
 }
 
+/// A timestamp, in seconds and nanoseconds since the Unix epoch. This is
+/// equivalent to `struct statx_timestamp`.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CTimestamp {
+    /// Seconds since the Unix epoch.
+    pub sec: i64,
+    /// Nanoseconds since `sec`.
+    pub nsec: u32,
+    __reserved: u32,
+}
+
+impl From<Timestamp> for CTimestamp {
+    fn from(ts: Timestamp) -> Self {
+        Self {
+            sec: ts.sec,
+            nsec: ts.nsec,
+            __reserved: 0,
+        }
+    }
+}
+
+/// The size of the first published version of pathrs_metadata_t.
+pub const PATHRS_METADATA_SIZE_VER0: u32 = 112;
+
+/// Metadata about an inode, filled by pathrs_handle_metadata().
+///
+/// This structure is extensible. Callers must set `struct_size` to
+/// `sizeof(pathrs_metadata_t)` before calling pathrs_handle_metadata() -- only
+/// that many bytes will be written by libpathrs. On return, `struct_size` and
+/// `version` describe how much of the structure was actually filled, so
+/// programs compiled against newer headers can detect older versions of
+/// libpathrs (and vice versa).
+// NOTE: This structure may only ever be appended to, and each new version must
+//       have a corresponding PATHRS_METADATA_SIZE_VER* constant.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CMetadata {
+    /// Size of the structure (in bytes). Must be set by the caller, and is
+    /// updated by libpathrs to the number of bytes that were filled.
+    pub struct_size: u32,
+    /// Version of the structure that was filled by libpathrs (starting at 0).
+    pub version: u32,
+    /// Mask of fields that were filled, using the `STATX_*` values from
+    /// statx(2). Fields not included in the mask have unspecified values.
+    pub mask: u64,
+    /// The file type of the inode (`mode & S_IFMT`).
+    pub file_type: u32,
+    /// The file type and mode (`st_mode`) of the inode.
+    pub mode: u32,
+    /// The owner (`st_uid`) of the inode.
+    pub uid: u32,
+    /// The group owner (`st_gid`) of the inode.
+    pub gid: u32,
+    /// The number of hard links (`st_nlink`) to the inode.
+    pub nlink: u64,
+    /// The size (`st_size`) of the inode in bytes.
+    pub size: u64,
+    /// The inode number (`st_ino`).
+    pub ino: u64,
+    /// The device (`st_dev`) containing the inode.
+    pub dev: u64,
+    /// The last access time (`st_atime`) of the inode.
+    pub atime: CTimestamp,
+    /// The last modification time (`st_mtime`) of the inode.
+    pub mtime: CTimestamp,
+    /// The last status change time (`st_ctime`) of the inode.
+    pub ctime: CTimestamp,
+}
+
+const _: () = assert!(mem::size_of::<CMetadata>() == PATHRS_METADATA_SIZE_VER0 as usize);
+
+impl From<&Metadata> for CMetadata {
+    fn from(meta: &Metadata) -> Self {
+        Self {
+            struct_size: PATHRS_METADATA_SIZE_VER0,
+            version: 0,
+            mask: meta.mask.bits().into(),
+            file_type: meta.mode() & libc::S_IFMT,
+            mode: meta.mode(),
+            uid: meta.uid(),
+            gid: meta.gid(),
+            nlink: meta.nlink(),
+            size: meta.size(),
+            ino: meta.ino(),
+            dev: meta.dev(),
+            atime: meta.atime.into(),
+            mtime: meta.mtime.into(),
+            ctime: meta.ctime.into(),
+        }
+    }
+}
+
+/// Get the metadata of the inode referenced by a handle (such as one returned
+/// by pathrs_inroot_resolve()), without needing to re-open it. This is
+/// equivalent to fstat(2) (or statx(2) where available) on the handle.
+///
+/// `out->struct_size` must be set to `sizeof(pathrs_metadata_t)` by the
+/// caller. Only the first `out->struct_size` bytes of `out` are written to,
+/// and on return `out->struct_size` and `out->version` describe which
+/// version of the structure was filled. `out->mask` indicates which of the
+/// fields contain valid data.
+///
+/// ```c
+/// pathrs_metadata_t meta = { .struct_size = sizeof(meta) };
+/// int ret = pathrs_handle_metadata(handle_fd, &meta);
+/// if (ret < 0) {
+///     liberr = ret; // for use with pathrs_errorinfo()
+///     goto err;
+/// }
+/// ```
+///
+/// # Return Value
+///
+/// On success, this function returns 0.
+///
+/// If an error occurs, this function will return a negative error code. To
+/// retrieve information about the error (such as a string describing the error,
+/// the system errno(7) value associated with the error, etc), use
+/// pathrs_errorinfo().
+#[no_mangle]
+pub unsafe extern "C" fn pathrs_handle_metadata(
+    handle_fd: CBorrowedFd<'_>,
+    out: *mut CMetadata,
+) -> c_int {
+    || -> Result<_, Error> {
+        if out.is_null() {
+            Err(ErrorImpl::InvalidArgument {
+                name: "out".into(),
+                description: "cannot be NULL".into(),
+            })?
+        }
+        // SAFETY: The C caller guarantees that out is a valid pointer, and
+        //         struct_size is part of every version of the structure.
+        let struct_size = unsafe { (*out).struct_size };
+        if struct_size < PATHRS_METADATA_SIZE_VER0 {
+            Err(ErrorImpl::InvalidArgument {
+                name: "out->struct_size".into(),
+                description: "must be at least PATHRS_METADATA_SIZE_VER0".into(),
+            })?
+        }
+
+        let handle_fd = handle_fd.try_as_borrowed_fd()?;
+        let meta = CMetadata::from(&HandleRef::from_fd(handle_fd).stat()?);
+
+        // Only copy as much as both we and the caller know about.
+        let copy_size = cmp::min(struct_size as usize, mem::size_of::<CMetadata>());
+        // SAFETY: The C caller guarantees that out is valid to write to for
+        //         struct_size bytes, and copy_size is no larger than our copy.
+        unsafe {
+            ptr::copy_nonoverlapping(
+                &meta as *const CMetadata as *const u8,
+                out as *mut u8,
+                copy_size,
+            )
+        };
+        Ok(())
+    }()
+    .into_c_return()
+}
+
 /// Resolve the given path within the rootfs referenced by root_fd. The path
 /// *must already exist*, otherwise an error will occur.
 ///
@@ -248,8 +447,31 @@ pub unsafe extern "C" fn pathrs_inroot_readlink(
     .into_c_return()
 }
 
+/// Do not overwrite the destination of pathrs_inroot_rename(). Identical to
+/// RENAME_NOREPLACE.
+pub const PATHRS_RENAME_NOREPLACE: u32 = 1 << 0;
+/// Atomically exchange the source and destination of pathrs_inroot_rename().
+/// Identical to RENAME_EXCHANGE.
+pub const PATHRS_RENAME_EXCHANGE: u32 = 1 << 1;
+/// Leave a whiteout at the source of pathrs_inroot_rename(). Identical to
+/// RENAME_WHITEOUT.
+pub const PATHRS_RENAME_WHITEOUT: u32 = 1 << 2;
+
+// The PATHRS_RENAME_* values are passed straight through to renameat2(2).
+const _: () = assert!(
+    PATHRS_RENAME_NOREPLACE == libc::RENAME_NOREPLACE
+        && PATHRS_RENAME_EXCHANGE == libc::RENAME_EXCHANGE
+        && PATHRS_RENAME_WHITEOUT == libc::RENAME_WHITEOUT
+);
+
 /// Rename a path within the rootfs referenced by root_fd. The flags argument is
-/// identical to the renameat2(2) flags that are supported on the system.
+/// identical to the renameat2(2) flags that are supported on the system. The
+/// PATHRS_RENAME_* constants can be used instead of the RENAME_* definitions
+/// from <linux/fs.h> (which older libc headers do not provide).
+///
+/// PATHRS_RENAME_EXCHANGE cannot be combined with PATHRS_RENAME_NOREPLACE or
+/// PATHRS_RENAME_WHITEOUT -- such combinations are rejected with an EINVAL
+/// error whose description explains the problem.
 ///
 /// # Return Value
 ///
@@ -437,6 +659,14 @@ pub unsafe extern "C" fn pathrs_inroot_mkdir(
 /// Create a new directory (and any of its path components if they don't exist)
 /// within the rootfs referenced by root_fd.
 ///
+/// The path is resolved as far as possible within the rootfs, and each missing
+/// component is then created with mkdirat(2) relative to a file descriptor for
+/// its parent directory (never by path), so swapping a component for a symlink
+/// while directories are being created cannot redirect the remaining
+/// components outside of the rootfs. Components that already exist as
+/// directories are re-used, but an existing non-directory component is an
+/// error (ENOTDIR).
+///
 /// # Return Value
 ///
 /// On success, this function returns an O_DIRECTORY file descriptor to the
@@ -559,3 +789,108 @@ pub unsafe extern "C" fn pathrs_inroot_hardlink(
     }()
     .into_c_return()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capi::error as capi_error;
+
+    use std::{
+        fs,
+        os::unix::{fs::MetadataExt, io::AsFd},
+    };
+
+    use anyhow::Error;
+    use pretty_assertions::assert_eq;
+
+    fn ctimestamp(sec: i64, nsec: i64) -> CTimestamp {
+        CTimestamp {
+            sec,
+            nsec: nsec as u32,
+            __reserved: 0,
+        }
+    }
+
+    #[test]
+    fn handle_metadata() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        fs::write(dir.path().join("file"), b"some data")?;
+        fs::hard_link(dir.path().join("file"), dir.path().join("file-link"))?;
+        fs::create_dir(dir.path().join("dir"))?;
+        std::os::unix::fs::symlink("file", dir.path().join("link"))?;
+        let root = Root::open(dir.path())?;
+
+        for path in ["file", "dir", "link"] {
+            let handle = root.resolve_nofollow(path)?;
+            let mut meta = CMetadata {
+                struct_size: mem::size_of::<CMetadata>() as u32,
+                ..Default::default()
+            };
+            let ret = unsafe { pathrs_handle_metadata(handle.as_fd().into(), &mut meta) };
+            assert_eq!(ret, 0, "pathrs_handle_metadata({path:?}) should succeed");
+
+            let std_meta = fs::symlink_metadata(dir.path().join(path))?;
+            assert_eq!(
+                meta,
+                CMetadata {
+                    struct_size: PATHRS_METADATA_SIZE_VER0,
+                    version: 0,
+                    mask: meta.mask,
+                    file_type: std_meta.mode() & libc::S_IFMT,
+                    mode: std_meta.mode(),
+                    uid: std_meta.uid(),
+                    gid: std_meta.gid(),
+                    nlink: std_meta.nlink(),
+                    size: std_meta.size(),
+                    ino: std_meta.ino(),
+                    dev: std_meta.dev(),
+                    atime: ctimestamp(std_meta.atime(), std_meta.atime_nsec()),
+                    mtime: ctimestamp(std_meta.mtime(), std_meta.mtime_nsec()),
+                    ctime: ctimestamp(std_meta.ctime(), std_meta.ctime_nsec()),
+                },
+                "pathrs_handle_metadata({path:?}) should match stat(2)"
+            );
+            assert!(
+                meta.mask & libc::STATX_BASIC_STATS as u64 == libc::STATX_BASIC_STATS as u64,
+                "pathrs_handle_metadata({path:?}) should fill all basic fields (mask: {:#x})",
+                meta.mask
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn handle_metadata_bad_struct_size() -> Result<(), Error> {
+        let root = Root::open(".")?;
+        let handle = root.resolve(".")?;
+
+        let mut meta = CMetadata {
+            struct_size: PATHRS_METADATA_SIZE_VER0 - 1,
+            ..Default::default()
+        };
+        let ret = unsafe { pathrs_handle_metadata(handle.as_fd().into(), &mut meta) };
+        assert!(ret < 0, "too-small struct_size should fail (got {ret})");
+        let err = unsafe { capi_error::pathrs_errorinfo(ret) }.expect("error should be stored");
+        assert_eq!(
+            err.saved_errno,
+            libc::EINVAL as u64,
+            "too-small struct_size should fail with EINVAL"
+        );
+        unsafe { capi_error::pathrs_errorinfo_free(err) };
+        assert_eq!(
+            meta,
+            CMetadata {
+                struct_size: PATHRS_METADATA_SIZE_VER0 - 1,
+                ..Default::default()
+            },
+            "failed pathrs_handle_metadata should not modify the struct"
+        );
+
+        let ret = unsafe { pathrs_handle_metadata(handle.as_fd().into(), ptr::null_mut()) };
+        assert!(ret < 0, "NULL out should fail (got {ret})");
+        unsafe { capi_error::pathrs_errorinfo_free(capi_error::pathrs_errorinfo(ret).unwrap()) };
+
+        Ok(())
+    }
+}
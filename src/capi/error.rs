@@ -18,45 +18,315 @@
  */
 
 use crate::{
-    capi::{ret::CReturn, utils::Leakable},
-    error::Error,
+    capi::{
+        ret::{CReturn, IntoCReturn},
+        utils::Leakable,
+    },
+    error::{Error, ErrorExt, ErrorImpl},
+    syscalls::Error as SyscallError,
 };
 
 use std::{
-    collections::{hash_map::Entry as HashMapEntry, HashMap},
+    cell::{Cell, RefCell},
     error::Error as StdError,
     ffi::CString,
-    ptr,
-    sync::Mutex,
+    io::Error as IOError,
+    mem, ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
 };
 
 use libc::{c_char, c_int};
 use once_cell::sync::Lazy;
-use rand::{self, Rng};
+use open_enum::open_enum;
+use rustix::io::Errno;
+
+// Error ids are encoded as -(ERROR_ID_BASE + key), where the key is made up of
+// (from least to most significant bits) the shard, the slot index within the
+// shard, and the generation of the slot. We avoid using anything in 0..4096 to
+// avoid users interpreting the return value as an -errno (at the moment, the
+// largest errno is ~150 but the kernel currently reserves 4096 values as
+// possible ERR_PTR values). The key is limited to 30 bits, so all ids fit in a
+// c_int.
+const ERROR_ID_BASE: CReturn = 4096;
+const SHARD_BITS: u32 = 4;
+const INDEX_BITS: u32 = 16;
+const GENERATION_BITS: u32 = 10;
+
+const NUM_SHARDS: usize = 1 << SHARD_BITS;
+const MAX_SLOTS: usize = 1 << INDEX_BITS;
+const GENERATION_MASK: u32 = (1 << GENERATION_BITS) - 1;
+
+/// The location of a stored error in [`ERROR_MAP`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ErrorKey {
+    shard: usize,
+    index: usize,
+    generation: u32,
+}
+
+impl ErrorKey {
+    fn to_id(self) -> CReturn {
+        let key = (self.generation << (INDEX_BITS + SHARD_BITS))
+            | ((self.index as u32) << SHARD_BITS)
+            | self.shard as u32;
+        -(ERROR_ID_BASE + key as CReturn)
+    }
+
+    fn from_id(id: CReturn) -> Option<Self> {
+        let key = id.checked_neg()?.checked_sub(ERROR_ID_BASE)?;
+        let key = u32::try_from(key).ok()?;
+        if key >> (GENERATION_BITS + INDEX_BITS + SHARD_BITS) != 0 {
+            return None;
+        }
+        Some(Self {
+            shard: (key as usize) & (NUM_SHARDS - 1),
+            index: (key >> SHARD_BITS) as usize & (MAX_SLOTS - 1),
+            generation: key >> (INDEX_BITS + SHARD_BITS),
+        })
+    }
+}
+
+#[derive(Default)]
+struct ErrorSlot {
+    // Bumped every time the slot is freed, so that stale ids for a re-used
+    // slot do not match the new error.
+    generation: u32,
+    err: Option<Error>,
+}
+
+#[derive(Default)]
+struct ErrorSlab {
+    slots: Vec<ErrorSlot>,
+    free: Vec<usize>,
+}
+
+impl ErrorSlab {
+    /// Store `err` in a free slot, returning its index and generation. If the
+    /// slab is full, `err` is handed back to the caller.
+    fn insert(&mut self, err: Error) -> Result<(usize, u32), Error> {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None if self.slots.len() < MAX_SLOTS => {
+                self.slots.push(Default::default());
+                self.slots.len() - 1
+            }
+            None => return Err(err),
+        };
+        let slot = &mut self.slots[index];
+        debug_assert!(slot.err.is_none(), "free error slot should be empty");
+        slot.err = Some(err);
+        Ok((index, slot.generation))
+    }
+
+    fn remove(&mut self, index: usize, generation: u32) -> Option<Error> {
+        let slot = self.slots.get_mut(index)?;
+        if slot.generation != generation {
+            return None;
+        }
+        let err = slot.err.take()?;
+        slot.generation = slot.generation.wrapping_add(1) & GENERATION_MASK;
+        self.free.push(index);
+        Some(err)
+    }
+}
+
+/// Storage for errors which have not yet been retrieved with
+/// [`pathrs_errorinfo`]. The slots are split into several independently-locked
+/// shards to reduce lock contention when many threads are hitting errors.
+struct ErrorMap {
+    shards: [Mutex<ErrorSlab>; NUM_SHARDS],
+}
+
+impl ErrorMap {
+    fn new() -> Self {
+        Self {
+            shards: std::array::from_fn(|_| Default::default()),
+        }
+    }
+
+    fn insert(&self, mut err: Error) -> CReturn {
+        let home = home_shard();
+
+        // Fall back to the other shards if our shard is full.
+        for shard in (home..NUM_SHARDS).chain(0..home) {
+            match self.shards[shard].lock().unwrap().insert(err) {
+                Ok((index, generation)) => {
+                    return ErrorKey {
+                        shard,
+                        index,
+                        generation,
+                    }
+                    .to_id()
+                }
+                Err(e) => err = e,
+            }
+        }
+        // There are over a million unretrieved errors, which means the caller
+        // is leaking them. We cannot panic here (we may be called from an
+        // extern "C" function), so drop the error and return an id which
+        // describes the problem instead.
+        TOO_MANY_ERRORS_ID
+    }
+
+    fn remove(&self, id: CReturn) -> Option<Error> {
+        let key = ErrorKey::from_id(id)?;
+        self.shards[key.shard]
+            .lock()
+            .unwrap()
+            .remove(key.index, key.generation)
+    }
+}
 
-// TODO: Switch this to using a slab or similar structure, possibly using a less
-// heavy-weight lock?
 // MSRV(1.80): Use LazyLock.
-static ERROR_MAP: Lazy<Mutex<HashMap<CReturn, Error>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static ERROR_MAP: Lazy<ErrorMap> = Lazy::new(ErrorMap::new);
+
+/// The shard that the current thread should try first when storing an error.
+fn thread_shard() -> usize {
+    static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        // Spread threads over the shards so they don't share a lock.
+        static HOME_SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % NUM_SHARDS;
+    }
+    HOME_SHARD.with(|shard| *shard)
+}
+
+/// Pick the shard to try first when storing an error.
+#[cfg(not(test))]
+fn home_shard() -> usize {
+    thread_shard()
+}
+
+/// Pick the shard to try first when storing an error, using the generator
+/// installed with [`id_gen::with_generator`] (if any).
+#[cfg(test)]
+fn home_shard() -> usize {
+    id_gen::next().unwrap_or_else(thread_shard) % NUM_SHARDS
+}
+
+/// Hooks to make error id assignment deterministic in tests.
+#[cfg(test)]
+pub(crate) mod id_gen {
+    use std::cell::RefCell;
+
+    type Generator = Box<dyn FnMut() -> usize>;
+
+    thread_local! {
+        static GENERATOR: RefCell<Option<Generator>> = const { RefCell::new(None) };
+    }
+
+    pub(super) fn next() -> Option<usize> {
+        GENERATOR.with(|gen| gen.borrow_mut().as_mut().map(|gen| gen()))
+    }
+
+    /// Run `f` with the shard used for each error stored by this thread being
+    /// picked by `gen` rather than by the thread's home shard.
+    pub(crate) fn with_generator<T, G, F>(gen: G, f: F) -> T
+    where
+        G: FnMut() -> usize + 'static,
+        F: FnOnce() -> T,
+    {
+        let old = GENERATOR.with(|cur| cur.replace(Some(Box::new(gen))));
+        let ret = f();
+        GENERATOR.with(|cur| cur.replace(old));
+        ret
+    }
+}
+
+/// Select how errors from libpathrs operations on the current thread are
+/// stored, using pathrs_set_error_mode().
+#[open_enum]
+#[repr(u32)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[allow(non_camel_case_types, dead_code)]
+pub enum CErrorMode {
+    /// Store errors in a process-wide table. The returned error ids can be
+    /// passed to pathrs_errorinfo() on any thread. This is the default.
+    PATHRS_ERRMODE_GLOBAL = 0,
+
+    /// Store the error in a per-thread "last error" slot, avoiding the locking
+    /// required for the process-wide table. Only the most recent error on each
+    /// thread is kept (any previous unretrieved error is discarded), and the
+    /// returned error id can only be passed to pathrs_errorinfo() on the same
+    /// thread.
+    ///
+    /// NOTE: This mode must not be used from languages where your code can
+    /// change threads without warning (such as Go), unless the thread has been
+    /// locked (such as with runtime.LockOSThread).
+    PATHRS_ERRMODE_THREAD_LOCAL = 1,
+}
+
+// The error id returned for errors stored in the thread-local "last error"
+// slot. This is outside the range of ids used for ERROR_MAP.
+const THREAD_LOCAL_ERROR_ID: CReturn = CReturn::MIN;
+
+// The error id returned if ERROR_MAP is full (the original error is dropped).
+// pathrs_errorinfo() returns a fresh error describing the problem for this id.
+// This is outside the range of ids used for ERROR_MAP.
+const TOO_MANY_ERRORS_ID: CReturn = CReturn::MIN + 1;
+
+/// The error returned by pathrs_errorinfo() for [`TOO_MANY_ERRORS_ID`].
+fn too_many_errors() -> Error {
+    ErrorImpl::OsError {
+        operation: "store error".into(),
+        source: IOError::from_raw_os_error(libc::ENOMEM),
+    }
+    .wrap("too many errors have not been retrieved with pathrs_errorinfo")
+    .into()
+}
+
+thread_local! {
+    static ERROR_MODE: Cell<CErrorMode> = const { Cell::new(CErrorMode::PATHRS_ERRMODE_GLOBAL) };
+    static LAST_ERROR: RefCell<Option<Error>> = const { RefCell::new(None) };
+}
 
 pub(crate) fn store_error(err: Error) -> CReturn {
-    let mut err_map = ERROR_MAP.lock().unwrap();
-
-    // Try to find a negative error value we can use. We avoid using anything in
-    // 0..4096 to avoid users interpreting the return value as an -errno (at the
-    // moment, the largest errno is ~150 but the kernel currently reserves
-    // 4096 values as possible ERR_PTR values).
-    let mut g = rand::thread_rng();
-    loop {
-        let idx = g.gen_range(CReturn::MIN..=-4096);
-        match err_map.entry(idx) {
-            HashMapEntry::Occupied(_) => continue,
-            HashMapEntry::Vacant(slot) => {
-                slot.insert(err);
-                return idx;
+    if ERROR_MODE.with(Cell::get) == CErrorMode::PATHRS_ERRMODE_THREAD_LOCAL {
+        LAST_ERROR.with(|last| last.replace(Some(err)));
+        THREAD_LOCAL_ERROR_ID
+    } else {
+        ERROR_MAP.insert(err)
+    }
+}
+
+/// Set how errors from libpathrs operations on the current thread are stored.
+///
+/// By default (PATHRS_ERRMODE_GLOBAL), errors are stored in a process-wide
+/// table and the returned error ids can be passed to pathrs_errorinfo() on any
+/// thread. With PATHRS_ERRMODE_THREAD_LOCAL, errors are instead stored in a
+/// per-thread slot, which is cheaper but means that pathrs_errorinfo() must be
+/// called on the same thread as the failed operation (before any other
+/// libpathrs operation on that thread fails). See pathrs_error_mode_t for more
+/// details.
+///
+/// The mode only applies to the calling thread. Errors already stored with the
+/// previous mode can still be retrieved after changing the mode.
+///
+/// # Return Value
+///
+/// On success, this function returns the previous error mode of the thread.
+///
+/// If an error occurs, this function will return a negative error code. To
+/// retrieve information about the error (such as a string describing the error,
+/// the system errno(7) value associated with the error, etc), use
+/// pathrs_errorinfo().
+#[no_mangle]
+pub extern "C" fn pathrs_set_error_mode(mode: CErrorMode) -> c_int {
+    || -> Result<_, Error> {
+        match mode {
+            CErrorMode::PATHRS_ERRMODE_GLOBAL | CErrorMode::PATHRS_ERRMODE_THREAD_LOCAL => {
+                let old = ERROR_MODE.with(|cur| cur.replace(mode));
+                Ok(old.0 as CReturn)
             }
+            _ => Err(ErrorImpl::InvalidArgument {
+                name: "error mode".into(),
+                description: "the error mode must be one of the PATHRS_ERRMODE_* values".into(),
+            })?,
         }
-    }
+    }()
+    .into_c_return()
 }
 
 /// Attempts to represent a Rust Error type in C. This structure must be freed
@@ -65,10 +335,9 @@ pub(crate) fn store_error(err: Error) -> CReturn {
 //       management done by libpathrs -- so you may only ever append to it.
 #[repr(align(8), C)]
 pub struct CError {
-    // TODO: Put a version or size here so that C users can tell what fields are
-    // valid if we add fields in the future.
-    /// Raw errno(3) value of the underlying error (or 0 if the source of the
-    /// error was not due to a syscall error).
+    /// Raw errno(3) value of the underlying error. If the source of the error
+    /// was not a syscall error, this is an errno describing the kind of error
+    /// (such as EINVAL for invalid arguments or EXDEV for safety violations).
     // We can't call this field "errno" because glibc defines errno(3) as a
     // macro, causing all sorts of problems if you have a struct with an "errno"
     // field. Best to avoid those headaches.
@@ -76,21 +345,87 @@ pub struct CError {
 
     /// Textual description of the error.
     pub description: *const c_char,
+
+    /// Number of entries in the causes array.
+    pub num_causes: u64,
+
+    /// Array of num_causes entries describing each level of the error chain,
+    /// starting with the error itself and ending with the root cause. Unlike
+    /// description, the description of each entry does not include the
+    /// descriptions of its own causes.
+    pub causes: *const CErrorCause,
+
+    /// The size of this structure (in bytes) as allocated by libpathrs. Fields
+    /// which end beyond this offset are not present and must not be read.
+    ///
+    /// Note that this field was added after the fields above (and so older
+    /// versions of libpathrs do not set it). Callers that need to support
+    /// those versions should check the libpathrs version first.
+    // NOTE: This cannot be the first field because that would break the layout
+    //       for existing users that only read saved_errno and description.
+    pub struct_size: u32,
 }
 
 impl Leakable for CError {}
 
+/// A single level in the chain of causes of a pathrs_error_t.
+#[repr(align(8), C)]
+pub struct CErrorCause {
+    /// Raw errno(3) value of this cause (or 0 if this cause is not a syscall
+    /// error).
+    pub saved_errno: u64,
+
+    /// Textual description of this cause.
+    pub description: *const c_char,
+}
+
+impl From<&(dyn StdError + 'static)> for CErrorCause {
+    fn from(err: &(dyn StdError + 'static)) -> Self {
+        let desc = CString::new(err.to_string())
+            .expect("CString::new(description) failed in CErrorCause generation");
+
+        let saved_errno = if let Some(err) = err.downcast_ref::<IOError>() {
+            err.raw_os_error()
+        } else if let Some(err) = err.downcast_ref::<SyscallError>() {
+            Some(err.errno().raw_os_error())
+        } else {
+            err.downcast_ref::<Errno>()
+                .map(|errno| errno.raw_os_error())
+        };
+
+        CErrorCause {
+            saved_errno: saved_errno.unwrap_or(0).unsigned_abs().into(),
+            description: desc.into_raw(),
+        }
+    }
+}
+
+impl Drop for CErrorCause {
+    fn drop(&mut self) {
+        if !self.description.is_null() {
+            let description = self.description as *mut c_char;
+            // Clear the pointer to avoid double-frees.
+            self.description = ptr::null_mut();
+            // SAFETY: CString::from_raw is safe because the C caller guarantees
+            //         that the pointer we get is the same one we gave them.
+            let _ = unsafe { CString::from_raw(description) };
+            // drop the CString
+        }
+    }
+}
+
 impl From<&Error> for CError {
     /// Construct a new CError struct based on the given error. The description
     /// is pretty-printed in a C-like manner (causes are appended to one another
-    /// with separating colons). In addition, if the root-cause of the error is
-    /// an IOError then errno is populated with that value.
+    /// with separating colons). In addition, errno is populated with the errno
+    /// of the root-cause of the error (if it was a syscall error) or an errno
+    /// corresponding to the kind of error.
     fn from(err: &Error) -> Self {
         // TODO: Switch to Error::chain() once it's stabilised.
         //       <https://github.com/rust-lang/rust/issues/58520>
         let desc = {
             let mut desc = err.to_string();
-            let mut err: &(dyn StdError) = err;
+            let mut err: &dyn StdError = err;
             while let Some(next) = err.source() {
                 desc.push_str(": ");
                 desc.push_str(&next.to_string());
@@ -100,14 +435,26 @@ impl From<&Error> for CError {
             CString::new(desc).expect("CString::new(description) failed in CError generation")
         };
 
-        // Map the error to a C errno if possible.
-        // TODO: We might want to use ESERVERFAULT (An untranslatable error
-        // occurred) for untranslatable errors?
-        let saved_errno = err.kind().errno().unwrap_or(0).unsigned_abs();
+        // Map the error to a C errno.
+        let saved_errno = err.kind().errno().unsigned_abs();
+
+        // Separately describe each level of the error chain.
+        let causes = {
+            let mut causes = Vec::new();
+            let mut err: Option<&(dyn StdError + 'static)> = Some(err);
+            while let Some(cause) = err {
+                causes.push(CErrorCause::from(cause));
+                err = cause.source();
+            }
+            causes.into_boxed_slice()
+        };
 
         CError {
             saved_errno: saved_errno.into(),
             description: desc.into_raw(),
+            num_causes: causes.len() as u64,
+            causes: Box::into_raw(causes) as *const CErrorCause,
+            struct_size: mem::size_of::<CError>() as u32,
         }
     }
 }
@@ -123,6 +470,20 @@ impl Drop for CError {
             let _ = unsafe { CString::from_raw(description) };
             // drop the CString
         }
+        if !self.causes.is_null() {
+            let causes = ptr::slice_from_raw_parts_mut(
+                self.causes as *mut CErrorCause,
+                self.num_causes as usize,
+            );
+            // Clear the pointer to avoid double-frees.
+            self.causes = ptr::null();
+            self.num_causes = 0;
+            // SAFETY: Box::from_raw is safe because the C caller guarantees
+            //         that the pointer we get is the same one we gave them (and
+            //         num_causes is the length of the original slice).
+            let _ = unsafe { Box::from_raw(causes) };
+            // drop the causes
+        }
     }
 }
 
@@ -156,6 +517,15 @@ impl Drop for CError {
 /// particular note to green-thread language bindings like Go, where this is
 /// important).
 ///
+/// The exception to this is if the thread where the operation failed had
+/// switched to PATHRS_ERRMODE_THREAD_LOCAL with pathrs_set_error_mode(), in
+/// which case pathrs_errorinfo() must be called on that same thread (and only
+/// the most recent error on that thread can be retrieved).
+///
+/// If an extremely large number of errors (over a million) have not been
+/// retrieved, new errors are dropped and replaced with an error (with errno set
+/// to ENOMEM) describing the problem.
+///
 /// # Return Value
 ///
 /// If there was a saved error with the provided id, a pathrs_error_t is
@@ -163,13 +533,16 @@ impl Drop for CError {
 /// associated memory once you are done with the error.
 #[no_mangle]
 pub unsafe extern "C" fn pathrs_errorinfo(err_id: c_int) -> Option<&'static mut CError> {
-    let mut err_map = ERROR_MAP.lock().unwrap();
-
-    err_map
-        .remove(&err_id)
-        .as_ref()
-        .map(CError::from)
-        .map(Leakable::leak)
+    if err_id == THREAD_LOCAL_ERROR_ID {
+        LAST_ERROR.with(RefCell::take)
+    } else if err_id == TOO_MANY_ERRORS_ID {
+        Some(too_many_errors())
+    } else {
+        ERROR_MAP.remove(err_id)
+    }
+    .as_ref()
+    .map(CError::from)
+    .map(Leakable::leak)
 }
 
 /// Free the pathrs_error_t object returned by pathrs_errorinfo().
@@ -187,11 +560,336 @@ pub unsafe extern "C" fn pathrs_errorinfo_free(ptr: *mut CError) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::error::{Error, ErrorImpl};
+    use crate::error::{Error, ErrorExt, ErrorImpl, ErrorKind};
+
+    use std::ffi::CStr;
+
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    fn fake_error(i: usize) -> Error {
+        Error::from(ErrorImpl::NotImplemented {
+            feature: format!("fake feature {i}").into(),
+        })
+    }
+
+    fn errorinfo_description(id: CReturn) -> Option<String> {
+        // SAFETY: Test-only code.
+        let cerr = unsafe { pathrs_errorinfo(id) }?;
+        // SAFETY: CError::from returns a valid C string.
+        let desc = unsafe { CStr::from_ptr(cerr.description) }
+            .to_string_lossy()
+            .into_owned();
+        // SAFETY: Test-only code, and the pointer came from pathrs_errorinfo.
+        unsafe { pathrs_errorinfo_free(cerr) };
+        Some(desc)
+    }
+
+    #[test]
+    fn error_key_id_roundtrip() {
+        for key in [
+            ErrorKey {
+                shard: 0,
+                index: 0,
+                generation: 0,
+            },
+            ErrorKey {
+                shard: 3,
+                index: 1234,
+                generation: 17,
+            },
+            ErrorKey {
+                shard: NUM_SHARDS - 1,
+                index: MAX_SLOTS - 1,
+                generation: GENERATION_MASK,
+            },
+        ] {
+            let id = key.to_id();
+            assert!(id <= -4096, "error id {id} for {key:?} should be <= -4096");
+            assert_eq!(
+                ErrorKey::from_id(id),
+                Some(key),
+                "error id {id} should map back to {key:?}"
+            );
+        }
+
+        for id in [
+            CReturn::MIN,
+            -ERROR_ID_BASE - (1 << 30),
+            -4095,
+            -libc::ENOENT,
+            0,
+            1,
+            CReturn::MAX,
+        ] {
+            assert_eq!(
+                ErrorKey::from_id(id),
+                None,
+                "error id {id} should not be valid"
+            );
+            assert_eq!(
+                errorinfo_description(id),
+                None,
+                "pathrs_errorinfo({id}) should not return an error"
+            );
+        }
+    }
+
+    #[test]
+    fn store_error_collision() {
+        // Every error is stored starting from the same shard, so they all
+        // collide with each other and must be given distinct slots.
+        let ids = id_gen::with_generator(
+            || 5,
+            || {
+                (0..3)
+                    .map(|i| store_error(fake_error(i)))
+                    .collect::<Vec<_>>()
+            },
+        );
+        for (i, id) in ids.iter().copied().enumerate() {
+            assert_eq!(
+                ErrorKey::from_id(id).map(|key| key.shard),
+                Some(5),
+                "error id {id} should be in the requested shard"
+            );
+            assert_eq!(
+                ids.iter().filter(|&&other| other == id).count(),
+                1,
+                "error id {id} should be unique: {ids:?}"
+            );
+            assert_eq!(
+                errorinfo_description(id),
+                Some(format!("feature fake feature {i} is not implemented")),
+                "error id {id} should map to the right error"
+            );
+        }
+    }
+
+    #[test]
+    fn error_map_full() {
+        // Pretend every slot is in use, without having to store over a million
+        // errors.
+        let full_slab = || ErrorSlab {
+            slots: (0..MAX_SLOTS).map(|_| Default::default()).collect(),
+            free: Vec::new(),
+        };
+        let map = ErrorMap {
+            shards: std::array::from_fn(|_| Mutex::new(full_slab())),
+        };
+
+        let id = id_gen::with_generator(|| 3, || map.insert(fake_error(0)));
+        assert_eq!(
+            id, TOO_MANY_ERRORS_ID,
+            "storing an error in a full map should not panic"
+        );
+        // SAFETY: Test-only code.
+        let cerr = unsafe { pathrs_errorinfo(id) }.expect("error should be returned");
+        assert_eq!(
+            cerr.saved_errno,
+            libc::ENOMEM as u64,
+            "full error map should be reported with ENOMEM"
+        );
+        // SAFETY: Test-only code, and the pointer came from pathrs_errorinfo.
+        unsafe { pathrs_errorinfo_free(cerr) };
+
+        // If there is space in another shard, the error is stored there.
+        *map.shards[7].lock().unwrap() = ErrorSlab::default();
+        let id = id_gen::with_generator(|| 3, || map.insert(fake_error(1)));
+        assert_eq!(
+            ErrorKey::from_id(id).map(|key| key.shard),
+            Some(7),
+            "error should be stored in the shard with free slots"
+        );
+        assert!(
+            map.remove(id).is_some(),
+            "error id {id} should be retrievable"
+        );
+    }
 
-    use std::io::Error as IOError;
+    #[test]
+    fn store_error_stale_id() {
+        let id = store_error(fake_error(0));
+        assert_eq!(
+            errorinfo_description(id).as_deref(),
+            Some("feature fake feature 0 is not implemented"),
+            "error id {id} should map to the right error"
+        );
+        assert_eq!(
+            errorinfo_description(id),
+            None,
+            "error id {id} should only be usable once"
+        );
 
-    use pretty_assertions::assert_eq;
+        // The freed slot is re-used by this thread, but with a new generation.
+        let new_id = store_error(fake_error(1));
+        assert_ne!(new_id, id, "error ids should not be re-used immediately");
+        assert_eq!(
+            errorinfo_description(id),
+            None,
+            "stale error id {id} should not match the re-used slot"
+        );
+        assert_eq!(
+            errorinfo_description(new_id).as_deref(),
+            Some("feature fake feature 1 is not implemented"),
+            "error id {new_id} should map to the right error"
+        );
+    }
+
+    #[test]
+    fn error_slab_generation_wrap() {
+        let mut slab = ErrorSlab::default();
+        for generation in (0..=GENERATION_MASK).chain(0..2) {
+            let (index, got_generation) =
+                slab.insert(fake_error(0)).expect("slab should not be full");
+            assert_eq!(index, 0, "free slot should be re-used");
+            assert_eq!(
+                got_generation, generation,
+                "slot generation should increment and wrap"
+            );
+            assert!(
+                slab.remove(index, generation.wrapping_sub(1) & GENERATION_MASK)
+                    .is_none(),
+                "stale generation should not match"
+            );
+            assert!(
+                slab.remove(index, generation).is_some(),
+                "current generation should match"
+            );
+        }
+    }
+
+    #[test]
+    fn error_slab_full() {
+        let mut slab = ErrorSlab::default();
+        for i in 0..MAX_SLOTS {
+            assert_eq!(
+                slab.insert(fake_error(i)).map_err(|_| ()),
+                Ok((i, 0)),
+                "slot {i} should be allocated"
+            );
+        }
+        let err = slab
+            .insert(fake_error(MAX_SLOTS))
+            .expect_err("insert into full slab should fail");
+        assert_eq!(
+            err.to_string(),
+            format!("feature fake feature {MAX_SLOTS} is not implemented"),
+            "full slab should return the error"
+        );
+        assert!(slab.remove(42, 0).is_some(), "remove from full slab");
+        assert_eq!(
+            slab.insert(fake_error(0)).map_err(|_| ()),
+            Ok((42, 1)),
+            "freed slot should be re-used"
+        );
+    }
+
+    #[test]
+    fn store_error_threads() {
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                s.spawn(move || {
+                    let ids = (0..256)
+                        .map(|i| (i, store_error(fake_error(t * 1000 + i))))
+                        .collect::<Vec<_>>();
+                    for (i, id) in ids {
+                        assert_eq!(
+                            errorinfo_description(id),
+                            Some(format!(
+                                "feature fake feature {} is not implemented",
+                                t * 1000 + i
+                            )),
+                            "error id {id} should map to the right error"
+                        );
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn errmode_thread_local() {
+        // Run in a separate thread so the error mode is not leaked.
+        std::thread::spawn(|| {
+            let old = pathrs_set_error_mode(CErrorMode::PATHRS_ERRMODE_THREAD_LOCAL);
+            assert_eq!(
+                old,
+                CErrorMode::PATHRS_ERRMODE_GLOBAL.0 as CReturn,
+                "default error mode should be global"
+            );
+
+            let id = store_error(fake_error(0));
+            assert!(id <= -4096, "thread-local error id {id} should be <= -4096");
+            assert_eq!(
+                std::thread::spawn(move || errorinfo_description(id))
+                    .join()
+                    .unwrap(),
+                None,
+                "thread-local error should not be visible on other threads"
+            );
+            assert_eq!(
+                errorinfo_description(id).as_deref(),
+                Some("feature fake feature 0 is not implemented"),
+                "thread-local error should be retrievable on the same thread"
+            );
+            assert_eq!(
+                errorinfo_description(id),
+                None,
+                "thread-local error should only be retrievable once"
+            );
+
+            // Only the most recent error is kept.
+            let id1 = store_error(fake_error(1));
+            let id2 = store_error(fake_error(2));
+            assert_eq!(id1, id2, "thread-local error ids should be the same");
+            assert_eq!(
+                errorinfo_description(id1).as_deref(),
+                Some("feature fake feature 2 is not implemented"),
+                "only the last thread-local error should be kept"
+            );
+
+            // Errors stored before switching back are still retrievable.
+            let id = store_error(fake_error(3));
+            let old = pathrs_set_error_mode(CErrorMode::PATHRS_ERRMODE_GLOBAL);
+            assert_eq!(
+                old,
+                CErrorMode::PATHRS_ERRMODE_THREAD_LOCAL.0 as CReturn,
+                "previous error mode should be returned"
+            );
+            let global_id = store_error(fake_error(4));
+            assert_ne!(global_id, id, "global error ids should be distinct");
+            assert_eq!(
+                errorinfo_description(id).as_deref(),
+                Some("feature fake feature 3 is not implemented"),
+                "thread-local error should be retrievable after switching modes"
+            );
+            assert_eq!(
+                std::thread::spawn(move || errorinfo_description(global_id))
+                    .join()
+                    .unwrap()
+                    .as_deref(),
+                Some("feature fake feature 4 is not implemented"),
+                "global error should be visible on other threads"
+            );
+        })
+        .join()
+        .expect("error mode test thread panicked");
+    }
+
+    #[test]
+    fn errmode_invalid() {
+        let ret = pathrs_set_error_mode(CErrorMode(0xdead));
+        assert!(ret < 0, "invalid error mode should fail (got {ret})");
+        // SAFETY: Test-only code.
+        let cerr = unsafe { pathrs_errorinfo(ret) }.expect("error should be stored");
+        assert_eq!(
+            cerr.saved_errno,
+            libc::EINVAL as u64,
+            "invalid error mode should fail with EINVAL"
+        );
+        // SAFETY: Test-only code, and the pointer came from pathrs_errorinfo.
+        unsafe { pathrs_errorinfo_free(cerr) };
+    }
 
     #[test]
     fn cerror_ioerror_errno() {
@@ -202,7 +900,7 @@ mod tests {
 
         assert_eq!(
             err.kind().errno(),
-            Some(libc::ENOANO),
+            libc::ENOANO,
             "basic kind().errno() should return the right error"
         );
 
@@ -223,7 +921,7 @@ mod tests {
 
         assert_eq!(
             err.kind().errno(),
-            Some(libc::EINVAL),
+            libc::EINVAL,
             "InvalidArgument kind().errno() should return the right error"
         );
 
@@ -243,7 +941,7 @@ mod tests {
 
         assert_eq!(
             err.kind().errno(),
-            Some(libc::ENOSYS),
+            libc::ENOSYS,
             "NotImplemented kind().errno() should return the right error"
         );
 
@@ -263,7 +961,7 @@ mod tests {
 
         assert_eq!(
             err.kind().errno(),
-            Some(libc::EXDEV),
+            libc::EXDEV,
             "SafetyViolation kind().errno() should return the right error"
         );
 
@@ -276,20 +974,144 @@ mod tests {
     }
 
     #[test]
-    fn cerror_no_errno() {
+    fn cerror_causes() {
+        let err = Error::from(
+            ErrorImpl::OsError {
+                operation: "fake operation".into(),
+                source: IOError::from_raw_os_error(libc::ENOANO),
+            }
+            .wrap("fake context"),
+        );
+
+        let cerr = CError::from(&err);
+        // SAFETY: CError::from returns a valid causes array.
+        let causes = unsafe { std::slice::from_raw_parts(cerr.causes, cerr.num_causes as usize) };
+        let causes = causes
+            .iter()
+            .map(|cause| {
+                // SAFETY: CErrorCause::from returns a valid C string.
+                let desc = unsafe { CStr::from_ptr(cause.description) };
+                (cause.saved_errno, desc.to_string_lossy().into_owned())
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            causes,
+            vec![
+                (0, "fake context".to_string()),
+                (0, "fake operation failed".to_string()),
+                (
+                    libc::ENOANO as u64,
+                    IOError::from_raw_os_error(libc::ENOANO).to_string()
+                ),
+            ],
+            "cerror should describe each cause separately"
+        );
+    }
+
+    #[test]
+    fn cerror_layout() {
+        let err = Error::from(ErrorImpl::NotImplemented {
+            feature: "fake feature".into(),
+        });
+        let cerr = CError::from(&err);
+
+        // The layout of the original fields must never change.
+        // MSRV(1.77): Use mem::offset_of!.
+        let base = ptr::addr_of!(cerr) as usize;
+        assert_eq!(
+            ptr::addr_of!(cerr.saved_errno) as usize - base,
+            0,
+            "saved_errno offset"
+        );
+        assert_eq!(
+            ptr::addr_of!(cerr.description) as usize - base,
+            8,
+            "description offset"
+        );
+        assert_eq!(mem::align_of::<CError>(), 8, "alignment of cerror");
+
+        assert_eq!(
+            cerr.struct_size as usize,
+            mem::size_of::<CError>(),
+            "cerror should contain its own size"
+        );
+    }
+
+    #[test]
+    fn cerror_internal_errno() {
         let parse_err = "a123".parse::<i32>().unwrap_err();
         let err = Error::from(parse_err);
 
         assert_eq!(
             err.kind().errno(),
-            None,
-            "ParseIntError kind().errno() should return no errno"
+            libc::ENOTRECOVERABLE,
+            "ParseIntError kind().errno() should return ENOTRECOVERABLE"
         );
 
         let cerr = CError::from(&err);
         assert_eq!(
-            cerr.saved_errno, 0,
-            "cerror should contain zero errno for ParseIntError"
+            cerr.saved_errno,
+            libc::ENOTRECOVERABLE as u64,
+            "cerror should contain ENOTRECOVERABLE errno for internal errors"
+        );
+    }
+
+    #[test]
+    fn cerror_enotsup_errno() {
+        let err = Error::from(ErrorImpl::NotSupported {
+            feature: "fake feature".into(),
+        });
+
+        let cerr = CError::from(&err);
+        assert_eq!(
+            cerr.saved_errno,
+            libc::ENOTSUP as u64,
+            "cerror should contain ENOTSUP errno for NotSupported"
+        );
+    }
+
+    #[test]
+    fn cerror_no_os_errno() {
+        let err = Error::from(ErrorImpl::OsError {
+            operation: "fake operation".into(),
+            source: IOError::from(std::io::ErrorKind::UnexpectedEof),
+        });
+
+        assert_eq!(
+            err.kind(),
+            ErrorKind::OsError(None),
+            "OsError without an errno should have no errno in its kind"
+        );
+
+        let cerr = CError::from(&err);
+        assert_eq!(
+            cerr.saved_errno,
+            libc::EIO as u64,
+            "cerror should contain EIO errno for OsError without an errno"
+        );
+    }
+
+    #[test]
+    fn cerror_wrapped_errno() {
+        let err = Error::from(ErrorImpl::InvalidArgument {
+            name: "fake argument".into(),
+            description: "fake description".into(),
+        })
+        .wrap("fake context");
+
+        let cerr = CError::from(&err);
+        assert_eq!(
+            cerr.saved_errno,
+            libc::EINVAL as u64,
+            "cerror should contain errno of the wrapped error"
+        );
+        // SAFETY: CError::from returns a valid C string.
+        let desc = unsafe { CStr::from_ptr(cerr.description) };
+        assert_eq!(
+            desc.to_string_lossy(),
+            "fake context: invalid fake argument argument: fake description",
+            "cerror description should be unchanged"
         );
     }
 }
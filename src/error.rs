@@ -29,7 +29,7 @@
 
 use crate::{resolvers::opath::SymlinkStackError, syscalls::Error as SyscallError};
 
-use std::{borrow::Cow, io::Error as IOError};
+use std::{borrow::Cow, error::Error as StdError, io::Error as IOError};
 
 // TODO: Add a backtrace to Error. We would just need to add an automatic
 //       Backtrace::capture() in From. But it's not clear whether we want to
@@ -56,6 +56,55 @@ impl Error {
     pub fn kind(&self) -> ErrorKind {
         self.0.kind()
     }
+
+    /// Is this error the result of trying to write to an executable that is
+    /// currently being run (`ETXTBSY`)?
+    ///
+    /// This is most commonly hit when trying to [`Handle::reopen`] a running
+    /// program for writing in order to update it in-place. Rather than
+    /// modifying the program in-place, you should instead write the new
+    /// contents to a new file and `rename(2)` it over the old one (which can
+    /// be done with [`Root::replace_executable`]). This also avoids
+    /// processes seeing a partially-written binary.
+    ///
+    /// [`Handle::reopen`]: crate::Handle::reopen
+    /// [`Root::replace_executable`]: crate::Root::replace_executable
+    pub fn is_text_busy(&self) -> bool {
+        self.kind() == ErrorKind::OsError(Some(libc::ETXTBSY))
+    }
+
+    /// Is this error transient, meaning that retrying the same operation might
+    /// succeed?
+    ///
+    /// The following errors are considered retryable:
+    ///
+    ///  - `EINTR`, if the operation was interrupted by a signal.
+    ///  - `EAGAIN`, which `openat2(2)` returns if there was a racing `rename(2)`
+    ///    or mount operation during the lookup (libpathrs already retries a
+    ///    few times internally before returning it).
+    ///  - `ESTALE`, if a file handle became stale (most commonly on NFS).
+    ///
+    /// All other errors (such as `ENOENT` or `EACCES`, as well as
+    /// [`ErrorKind::SafetyViolation`] and every other non-[`ErrorKind::OsError`]
+    /// error) are permanent -- retrying the operation will produce the same
+    /// result unless something else modifies the filesystem.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.kind(),
+            ErrorKind::OsError(Some(libc::EINTR | libc::EAGAIN | libc::ESTALE))
+        )
+    }
+
+    /// Prepend `context` to the description of this error. Unlike
+    /// [`ErrorExt::wrap`], the description of the returned error also
+    /// includes the description of this error.
+    pub(crate) fn with_context<S: Into<Cow<'static, str>>>(self, context: S) -> Self {
+        ErrorImpl::Contextual {
+            context: context.into(),
+            inner: self.0,
+        }
+        .into()
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -76,6 +125,9 @@ pub(crate) enum ErrorImpl {
     #[error("violation of safety requirement: {description}")]
     SafetyViolation { description: Cow<'static, str> },
 
+    #[error("deleted inode encountered: {description}")]
+    DeletedInode { description: Cow<'static, str> },
+
     #[error("broken symlink stack during iteration: {description}")]
     BadSymlinkStackError {
         description: Cow<'static, str>,
@@ -102,6 +154,28 @@ pub(crate) enum ErrorImpl {
         context: Cow<'static, str>,
         source: Box<ErrorImpl>,
     },
+
+    // Unlike Wrapped, the description of the inner error (including all of
+    // its causes) is included in the description of this error, so that
+    // callers which only look at the outermost description (such as with
+    // Error::to_string) still see what went wrong.
+    #[error("{context}: {}", describe_chain(.inner))]
+    Contextual {
+        context: Cow<'static, str>,
+        inner: Box<ErrorImpl>,
+    },
+}
+
+/// Describe an error and all of its causes, separated by colons.
+fn describe_chain(err: &ErrorImpl) -> String {
+    let mut desc = err.to_string();
+    let mut err: &dyn StdError = err;
+    while let Some(next) = err.source() {
+        desc.push_str(": ");
+        desc.push_str(&next.to_string());
+        err = next;
+    }
+    desc
 }
 
 /// Underlying error class for libpathrs errors.
@@ -120,6 +194,12 @@ pub enum ErrorKind {
     /// be guaranteeed. This is usually the result of an attack by a malicious
     /// program.
     SafetyViolation,
+    /// Path resolution walked through a directory which has been deleted (its
+    /// link count is zero), and the resolver was configured to reject such
+    /// directories. See [`Root::set_reject_deleted_intermediates`].
+    ///
+    /// [`Root::set_reject_deleted_intermediates`]: crate::Root::set_reject_deleted_intermediates
+    DeletedInode,
     /// Some internal error occurred. For more information, see the string
     /// description of the original [`Error`].
     InternalError,
@@ -137,6 +217,7 @@ impl ErrorImpl {
             Self::NotSupported { .. } => ErrorKind::NotSupported,
             Self::InvalidArgument { .. } => ErrorKind::InvalidArgument,
             Self::SafetyViolation { .. } => ErrorKind::SafetyViolation,
+            Self::DeletedInode { .. } => ErrorKind::DeletedInode,
             // Any syscall-related errors get mapped to an OsError, since the
             // distinction doesn't matter to users checking error values.
             Self::OsError { source, .. } => ErrorKind::OsError(source.raw_os_error()),
@@ -148,6 +229,7 @@ impl ErrorImpl {
             // there was some internal error.
             Self::BadSymlinkStackError { .. } | Self::ParseIntError(_) => ErrorKind::InternalError,
             Self::Wrapped { source, .. } => source.kind(),
+            Self::Contextual { inner, .. } => inner.kind(),
         }
     }
 }
@@ -157,15 +239,34 @@ impl ErrorKind {
     ///
     /// Aside from fetching the errno represented by standard
     /// [`ErrorKind::OsError`] errors, pure-Rust errors are also mapped to C
-    /// errno values where appropriate.
+    /// errno values so that every error has a non-zero errno:
+    ///
+    /// | [`ErrorKind`]                   | errno             |
+    /// | ------------------------------- | ----------------- |
+    /// | [`ErrorKind::NotImplemented`]   | `ENOSYS`          |
+    /// | [`ErrorKind::NotSupported`]     | `ENOTSUP`         |
+    /// | [`ErrorKind::InvalidArgument`]  | `EINVAL`          |
+    /// | [`ErrorKind::SafetyViolation`]  | `EXDEV`           |
+    /// | [`ErrorKind::DeletedInode`]     | `ENOENT`          |
+    /// | [`ErrorKind::InternalError`]    | `ENOTRECOVERABLE` |
+    /// | [`ErrorKind::OsError`] (no errno) | `EIO`           |
     #[cfg(any(feature = "capi", test))]
-    pub(crate) fn errno(&self) -> Option<i32> {
+    pub(crate) fn errno(&self) -> i32 {
         match self {
-            ErrorKind::NotImplemented => Some(libc::ENOSYS),
-            ErrorKind::InvalidArgument => Some(libc::EINVAL),
-            ErrorKind::SafetyViolation => Some(libc::EXDEV),
-            ErrorKind::OsError(errno) => *errno,
-            _ => None,
+            ErrorKind::NotImplemented => libc::ENOSYS,
+            ErrorKind::NotSupported => libc::ENOTSUP,
+            ErrorKind::InvalidArgument => libc::EINVAL,
+            // Safety violations are usually the result of a lookup that
+            // escaped (or would have escaped) the root, which is what
+            // openat2(RESOLVE_IN_ROOT) and RESOLVE_BENEATH return as well.
+            ErrorKind::SafetyViolation => libc::EXDEV,
+            // This is what the kernel returns when looking up a name inside
+            // a deleted directory.
+            ErrorKind::DeletedInode => libc::ENOENT,
+            // There is no errno meaning "an untranslatable error occurred"
+            // (ESERVERFAULT is kernel-internal), so use the closest match.
+            ErrorKind::InternalError => libc::ENOTRECOVERABLE,
+            ErrorKind::OsError(errno) => errno.unwrap_or(libc::EIO),
         }
     }
 }
@@ -222,25 +323,61 @@ mod tests {
 
     #[test]
     fn error_kind_errno() {
-        assert_eq!(
-            ErrorKind::InvalidArgument.errno(),
-            Some(libc::EINVAL),
-            "ErrorKind::InvalidArgument is equivalent to EINVAL"
-        );
-        assert_eq!(
-            ErrorKind::NotImplemented.errno(),
-            Some(libc::ENOSYS),
-            "ErrorKind::NotImplemented is equivalent to ENOSYS"
-        );
-        assert_eq!(
-            ErrorKind::SafetyViolation.errno(),
-            Some(libc::EXDEV),
-            "ErrorKind::SafetyViolation is equivalent to EXDEV"
+        for (kind, errno) in [
+            (ErrorKind::NotImplemented, libc::ENOSYS),
+            (ErrorKind::NotSupported, libc::ENOTSUP),
+            (ErrorKind::InvalidArgument, libc::EINVAL),
+            (ErrorKind::SafetyViolation, libc::EXDEV),
+            (ErrorKind::DeletedInode, libc::ENOENT),
+            (ErrorKind::InternalError, libc::ENOTRECOVERABLE),
+            (ErrorKind::OsError(Some(libc::ENOANO)), libc::ENOANO),
+            (ErrorKind::OsError(None), libc::EIO),
+        ] {
+            assert_eq!(kind.errno(), errno, "{kind:?}.errno()");
+        }
+    }
+
+    #[test]
+    fn error_is_retryable() {
+        for errno in [libc::EINTR, libc::EAGAIN, libc::ESTALE] {
+            let err: Error = ErrorImpl::OsError {
+                operation: "test operation".into(),
+                source: IOError::from_raw_os_error(errno),
+            }
+            .into();
+            assert!(err.is_retryable(), "errno {errno} should be retryable");
+            assert!(
+                err.wrap("wrapped error").is_retryable(),
+                "wrapped errno {errno} should be retryable"
+            );
+        }
+
+        for errno in [libc::ENOENT, libc::EACCES, libc::EXDEV, libc::ELOOP] {
+            let err: Error = ErrorImpl::OsError {
+                operation: "test operation".into(),
+                source: IOError::from_raw_os_error(errno),
+            }
+            .into();
+            assert!(!err.is_retryable(), "errno {errno} should not be retryable");
+        }
+
+        let err: Error = ErrorImpl::SafetyViolation {
+            description: "test violation".into(),
+        }
+        .into();
+        assert!(
+            !err.is_retryable(),
+            "SafetyViolation should not be retryable"
         );
-        assert_eq!(
-            ErrorKind::OsError(Some(libc::ENOANO)).errno(),
-            Some(libc::ENOANO),
-            "ErrorKind::OsError(...)::errno() returns the inner errno"
+
+        let err: Error = ErrorImpl::InvalidArgument {
+            name: "test".into(),
+            description: "test argument".into(),
+        }
+        .into();
+        assert!(
+            !err.is_retryable(),
+            "InvalidArgument should not be retryable"
         );
     }
 }
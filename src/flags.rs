@@ -21,9 +21,12 @@
 
 //! Bit-flags for modifying the behaviour of libpathrs.
 
-use crate::syscalls;
+use crate::{
+    error::{Error, ErrorImpl},
+    kernel,
+};
 
-use bitflags::bitflags;
+use bitflags::{bitflags, Flags};
 
 bitflags! {
     /// Wrapper for the underlying `libc`'s `O_*` flags.
@@ -175,6 +178,186 @@ impl OpenFlags {
             }
         }
     }
+
+    /// The flags which libpathrs always adds when opening files (such as with
+    /// [`Handle::reopen`]).
+    ///
+    /// [`Handle::reopen`]: crate::Handle::reopen
+    pub(crate) const IMPLICIT_FLAGS: Self = Self::O_CLOEXEC.union(Self::O_NOCTTY);
+
+    /// Get an [`OpenFlagsBuilder`] to construct [`OpenFlags`] without having
+    /// to deal with the access mode bits directly.
+    ///
+    /// ```
+    /// # use pathrs::flags::OpenFlags;
+    /// let flags = OpenFlags::builder().read().write().append().build();
+    /// assert_eq!(flags, OpenFlags::O_RDWR | OpenFlags::O_APPEND);
+    /// ```
+    #[inline]
+    pub fn builder() -> OpenFlagsBuilder {
+        OpenFlagsBuilder::default()
+    }
+
+    /// Get the raw `O_*` flags value, suitable for passing to `open(2)`.
+    #[inline]
+    pub fn into_raw(self) -> libc::c_int {
+        self.bits()
+    }
+
+    /// Convert a raw `O_*` flags value into [`OpenFlags`].
+    ///
+    /// Unlike [`OpenFlags::from_bits_retain`], flags which are not known to
+    /// libpathrs are rejected rather than silently passed through to the
+    /// kernel.
+    ///
+    /// # Errors
+    ///
+    /// If `raw` contains unknown flags, an error with
+    /// [`ErrorKind::InvalidArgument`] is returned.
+    ///
+    /// [`ErrorKind::InvalidArgument`]: crate::error::ErrorKind::InvalidArgument
+    pub fn from_raw(raw: libc::c_int) -> Result<Self, Error> {
+        // O_LARGEFILE is auto-set by some libcs, so permit it even though it
+        // is not one of our flags.
+        let known = <Self as Flags>::FLAGS
+            .iter()
+            .filter(|flag| flag.is_named())
+            .fold(libc::O_LARGEFILE, |known, flag| known | flag.value().bits());
+        if raw & !known != 0 {
+            Err(ErrorImpl::InvalidArgument {
+                name: "flags".into(),
+                description: format!("unknown open flags {:#x}", raw & !known).into(),
+            })?
+        }
+        Ok(Self::from_bits_retain(raw))
+    }
+
+    /// Get the flags which libpathrs will implicitly add (`O_CLOEXEC` and
+    /// `O_NOCTTY`) when opening a file with these flags, which are not already
+    /// set in `self`.
+    ///
+    /// This lets callers tell whether the file they get from (for instance)
+    /// [`Handle::reopen`] was opened with different flags than they asked for.
+    ///
+    /// ```
+    /// # use pathrs::flags::OpenFlags;
+    /// assert_eq!(
+    ///     OpenFlags::O_RDONLY.implicit_flags(),
+    ///     OpenFlags::O_CLOEXEC | OpenFlags::O_NOCTTY,
+    /// );
+    /// assert_eq!(
+    ///     (OpenFlags::O_RDONLY | OpenFlags::O_CLOEXEC).implicit_flags(),
+    ///     OpenFlags::O_NOCTTY,
+    /// );
+    /// ```
+    ///
+    /// [`Handle::reopen`]: crate::Handle::reopen
+    #[inline]
+    pub fn implicit_flags(self) -> Self {
+        Self::IMPLICIT_FLAGS.difference(self)
+    }
+}
+
+/// A builder for [`OpenFlags`], similar to [`std::fs::OpenOptions`].
+///
+/// The access mode is computed from whether [`read`] and [`write`] were
+/// requested (if neither was requested, the access mode is `O_RDONLY` as with
+/// `open(2)`), so the order of the calls does not matter.
+///
+/// [`read`]: Self::read
+/// [`write`]: Self::write
+#[derive(Default, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct OpenFlagsBuilder {
+    read: bool,
+    write: bool,
+    flags: OpenFlags,
+}
+
+impl OpenFlagsBuilder {
+    /// Request read access.
+    #[inline]
+    pub fn read(mut self) -> Self {
+        self.read = true;
+        self
+    }
+
+    /// Request write access.
+    #[inline]
+    pub fn write(mut self) -> Self {
+        self.write = true;
+        self
+    }
+
+    /// Set `O_APPEND`. This implies [`write`].
+    ///
+    /// [`write`]: Self::write
+    #[inline]
+    pub fn append(self) -> Self {
+        self.write().flags(OpenFlags::O_APPEND)
+    }
+
+    /// Set `O_TRUNC`.
+    #[inline]
+    pub fn truncate(self) -> Self {
+        self.flags(OpenFlags::O_TRUNC)
+    }
+
+    /// Set `O_CREAT`.
+    #[inline]
+    pub fn create(self) -> Self {
+        self.flags(OpenFlags::O_CREAT)
+    }
+
+    /// Set `O_EXCL`.
+    #[inline]
+    pub fn exclusive(self) -> Self {
+        self.flags(OpenFlags::O_EXCL)
+    }
+
+    /// Set `O_DIRECTORY`.
+    #[inline]
+    pub fn directory(self) -> Self {
+        self.flags(OpenFlags::O_DIRECTORY)
+    }
+
+    /// Set `O_NOFOLLOW`.
+    #[inline]
+    pub fn nofollow(self) -> Self {
+        self.flags(OpenFlags::O_NOFOLLOW)
+    }
+
+    /// Set any other `flags`. The access mode bits of `flags` are ignored, use
+    /// [`read`] and [`write`] instead.
+    ///
+    /// [`read`]: Self::read
+    /// [`write`]: Self::write
+    #[inline]
+    pub fn flags(mut self, flags: OpenFlags) -> Self {
+        self.flags |= flags.difference(OpenFlags::from_bits_retain(libc::O_ACCMODE));
+        self
+    }
+
+    /// Construct the [`OpenFlags`].
+    pub fn build(self) -> OpenFlags {
+        let access = match (self.read, self.write) {
+            (_, false) => OpenFlags::O_RDONLY,
+            (false, true) => OpenFlags::O_WRONLY,
+            (true, true) => OpenFlags::O_RDWR,
+        };
+        self.flags | access
+    }
+
+    /// Get the raw `O_*` flags value. Shorthand for `build().into_raw()`.
+    #[inline]
+    pub fn into_raw(self) -> libc::c_int {
+        self.build().into_raw()
+    }
+}
+
+impl From<OpenFlagsBuilder> for OpenFlags {
+    fn from(builder: OpenFlagsBuilder) -> Self {
+        builder.build()
+    }
 }
 
 bitflags! {
@@ -184,9 +367,10 @@ bitflags! {
     /// [`renameat2(2)`] man page.
     ///
     /// [`renameat2(2)`] might not not be supported on your kernel -- in which
-    /// case [`Root::rename`] will fail if you specify any RenameFlags. You can
+    /// case [`Root::rename`] will fail if you specify any RenameFlags (other
+    /// than [`RenameFlags::RENAME_NOREPLACE`], which is emulated). You can
     /// verify whether [`renameat2(2)`] flags are supported by calling
-    /// [`RenameFlags::supported`].
+    /// [`RenameFlags::is_supported`].
     ///
     /// [`renameat2(2)`]: http://man7.org/linux/man-pages/man2/rename.2.html
     /// [`Root::rename`]: crate::Root::rename
@@ -211,15 +395,56 @@ impl RenameFlags {
     /// Is this set of RenameFlags supported by the running kernel?
     pub fn is_supported(self) -> bool {
         // TODO: This check won't work once new RENAME_* flags are added.
-        self.is_empty() || *syscalls::RENAME_FLAGS_SUPPORTED
+        self.is_empty() || kernel::kernel_features().has_renameat2
+    }
+}
+
+bitflags! {
+    /// Wrapper for the underlying `libc`'s `*_OK` access mode bits, as used by
+    /// [`Root::access`].
+    ///
+    /// The flag values and their meaning is identical to the description in the
+    /// [`access(2)`] man page. An empty [`AccessMode`] is equivalent to `F_OK`.
+    ///
+    /// [`access(2)`]: http://man7.org/linux/man-pages/man2/access.2.html
+    /// [`Root::access`]: crate::Root::access
+    #[derive(Default, PartialEq, Eq, Debug, Clone, Copy)]
+    pub struct AccessMode: libc::c_int {
+        const F_OK = libc::F_OK;
+        const R_OK = libc::R_OK;
+        const W_OK = libc::W_OK;
+        const X_OK = libc::X_OK;
+
+        // Don't clobber unknown *_OK bits.
+        const _ = !0;
+    }
+}
+
+bitflags! {
+    /// Wrapper for the subset of `libc`'s `AT_*` flags accepted by
+    /// [`Root::access`].
+    ///
+    /// The flag values and their meaning is identical to the description in the
+    /// [`faccessat2(2)`] man page.
+    ///
+    /// [`faccessat2(2)`]: http://man7.org/linux/man-pages/man2/access.2.html
+    /// [`Root::access`]: crate::Root::access
+    #[derive(Default, PartialEq, Eq, Debug, Clone, Copy)]
+    pub struct AccessFlags: libc::c_int {
+        const AT_EACCESS = libc::AT_EACCESS;
+        const AT_SYMLINK_NOFOLLOW = libc::AT_SYMLINK_NOFOLLOW;
+
+        // Don't clobber unknown AT_* bits.
+        const _ = !0;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
+        error::ErrorKind,
         flags::{OpenFlags, RenameFlags},
-        syscalls,
+        kernel,
     };
 
     macro_rules! openflags_tests {
@@ -268,6 +493,84 @@ mod tests {
         creat_path(O_PATH|O_CREAT) == {accmode: None, read: false, write: false};
     }
 
+    macro_rules! openflags_builder_tests {
+        ($($test_name:ident ( $($method:ident)* ) == $($flag:ident)|+);+ $(;)?) => {
+            $(
+                paste::paste! {
+                    #[test]
+                    fn [<openflags_builder_ $test_name>]() {
+                        let builder = OpenFlags::builder()$(.$method())*;
+                        let expected = $(OpenFlags::$flag)|*;
+                        assert_eq!(builder.build(), expected, "{builder:?} should build {expected:?}");
+                        assert_eq!(builder.into_raw(), expected.bits(), "{builder:?} raw flags should match {expected:?}");
+                    }
+                }
+            )*
+        }
+    }
+
+    openflags_builder_tests! {
+        empty() == O_RDONLY;
+        read(read) == O_RDONLY;
+        write(write) == O_WRONLY;
+        read_write(read write) == O_RDWR;
+        write_read(write read) == O_RDWR;
+        append(append) == O_WRONLY|O_APPEND;
+        read_append(read append) == O_RDWR|O_APPEND;
+        create_new(write create exclusive) == O_WRONLY|O_CREAT|O_EXCL;
+        truncate(read write truncate) == O_RDWR|O_TRUNC;
+        directory(directory nofollow) == O_RDONLY|O_DIRECTORY|O_NOFOLLOW;
+    }
+
+    #[test]
+    fn openflags_builder_flags_accmode() {
+        assert_eq!(
+            OpenFlags::builder()
+                .flags(OpenFlags::O_RDWR | OpenFlags::O_PATH)
+                .build(),
+            OpenFlags::O_PATH,
+            "access mode bits passed to flags() should be ignored"
+        );
+    }
+
+    #[test]
+    fn openflags_from_raw() {
+        for flags in [
+            OpenFlags::O_RDONLY,
+            OpenFlags::O_RDWR | OpenFlags::O_CREAT | OpenFlags::O_EXCL,
+            OpenFlags::O_PATH | OpenFlags::O_NOFOLLOW | OpenFlags::O_CLOEXEC,
+            OpenFlags::O_WRONLY | OpenFlags::O_TMPFILE,
+        ] {
+            assert_eq!(
+                OpenFlags::from_raw(flags.into_raw()).map_err(|err| err.kind()),
+                Ok(flags),
+                "{flags:?} should round-trip through from_raw"
+            );
+        }
+
+        assert_eq!(
+            OpenFlags::from_raw(libc::O_RDONLY | 0x4000_0000).map_err(|err| err.kind()),
+            Err(ErrorKind::InvalidArgument),
+            "from_raw with unknown flags should fail"
+        );
+    }
+
+    #[test]
+    fn openflags_implicit_flags() {
+        assert_eq!(
+            OpenFlags::O_RDWR.implicit_flags(),
+            OpenFlags::O_CLOEXEC | OpenFlags::O_NOCTTY,
+        );
+        assert_eq!(
+            (OpenFlags::O_RDWR | OpenFlags::O_NOCTTY).implicit_flags(),
+            OpenFlags::O_CLOEXEC,
+        );
+        assert_eq!(
+            (OpenFlags::O_PATH | OpenFlags::O_CLOEXEC | OpenFlags::O_NOCTTY).implicit_flags(),
+            OpenFlags::empty(),
+        );
+    }
+
     #[test]
     fn rename_flags_is_supported() {
         assert!(
@@ -276,8 +579,59 @@ mod tests {
         );
         assert_eq!(
             RenameFlags::RENAME_EXCHANGE.is_supported(),
-            *syscalls::RENAME_FLAGS_SUPPORTED,
-            "rename flags being supported should be identical to RENAME_FLAGS_SUPPORTED"
+            kernel::kernel_features().has_renameat2,
+            "rename flags being supported should be identical to has_renameat2"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn resolver_flags_serde() {
+        use crate::flags::ResolverFlags;
+
+        let flags = ResolverFlags::NO_SYMLINKS | ResolverFlags::NO_XDEV;
+        let json = serde_json::to_string(&flags).expect("serialize ResolverFlags");
+        assert_eq!(json, r#"["no_symlinks","no_xdev"]"#);
+        assert_eq!(
+            serde_json::from_str::<ResolverFlags>(&json).expect("deserialize ResolverFlags"),
+            flags,
+            "ResolverFlags should round-trip"
+        );
+        assert_eq!(
+            serde_json::from_str::<ResolverFlags>("[]").expect("deserialize empty ResolverFlags"),
+            ResolverFlags::empty(),
+        );
+
+        let err = serde_json::from_str::<ResolverFlags>(r#"["no_symlinks","no_such_flag"]"#)
+            .expect_err("unknown flag names should be rejected");
+        assert!(
+            err.to_string().contains("no_such_flag"),
+            "error should mention the unknown flag name: {err}"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn open_flags_serde() {
+        let flags = OpenFlags::O_PATH | OpenFlags::O_DIRECTORY | OpenFlags::O_NOFOLLOW;
+        let json = serde_json::to_string(&flags).expect("serialize OpenFlags");
+        assert_eq!(
+            serde_json::from_str::<OpenFlags>(&json).expect("deserialize OpenFlags"),
+            flags,
+            "OpenFlags {json} should round-trip"
+        );
+        assert_eq!(
+            serde_json::from_str::<OpenFlags>(r#"["o_wronly","o_creat"]"#)
+                .expect("deserialize OpenFlags"),
+            OpenFlags::O_WRONLY | OpenFlags::O_CREAT,
+        );
+        assert!(
+            serde_json::from_str::<OpenFlags>(r#"["o_bogus"]"#).is_err(),
+            "unknown flag names should be rejected"
+        );
+        assert!(
+            serde_json::to_string(&OpenFlags::from_bits_retain(1 << 30)).is_err(),
+            "flags with unnamed bits cannot be serialized"
         );
     }
 }
@@ -290,5 +644,200 @@ bitflags! {
     pub struct ResolverFlags: u64 {
         // TODO: We should probably have our own bits...
         const NO_SYMLINKS = libc::RESOLVE_NO_SYMLINKS;
+        /// Disallow traversal of mount points during path resolution
+        /// (including bind-mounts). This is equivalent to `RESOLVE_NO_XDEV`.
+        ///
+        /// The emulated resolver detects mount crossings by comparing the
+        /// device number of each component, and so it cannot detect
+        /// bind-mounts of the same filesystem.
+        const NO_XDEV = libc::RESOLVE_NO_XDEV;
+        /// Treat any attempt to escape the [`Root`] as an error, rather than
+        /// clamping the lookup to the [`Root`]. This is equivalent to
+        /// `RESOLVE_BENEATH` (the default behaviour is equivalent to
+        /// `RESOLVE_IN_ROOT`).
+        ///
+        /// With this flag set, lookups fail with `EXDEV` if:
+        ///
+        ///  * `path` is an absolute path.
+        ///  * A `..` component is used while at the [`Root`] itself (`..`
+        ///    components that stay within the [`Root`] are still permitted).
+        ///  * An absolute symlink is encountered during resolution (even if
+        ///    its target would be inside the [`Root`] when resolved with
+        ///    `RESOLVE_IN_ROOT` semantics).
+        ///
+        /// Without this flag, all of the above are instead resolved as though
+        /// the [`Root`] were `/`. Trailing slashes are handled identically in
+        /// both modes (a trailing slash on a non-directory results in
+        /// `ENOTDIR`).
+        ///
+        /// [`Root`]: crate::Root
+        const BENEATH = libc::RESOLVE_BENEATH;
+        /// Only permit paths which refer to an entry directly inside the
+        /// [`Root`] (or the [`Root`] itself). Any path containing more than
+        /// one component (after removing `.` components, duplicate slashes
+        /// and trailing slashes) or any `..` component is rejected with an
+        /// [`ErrorKind::InvalidArgument`] error before any lookup is done.
+        ///
+        /// This is useful for APIs which only ever need to operate on the
+        /// files within a single directory (such as simple key-value stores),
+        /// where even descending into subdirectories should not be
+        /// permitted. Note that the path is only checked lexically -- if the
+        /// single component is a symlink it will still be followed (as
+        /// usual), so you probably want to combine this with
+        /// [`ResolverFlags::NO_SYMLINKS`].
+        ///
+        /// This flag has no `RESOLVE_*` equivalent and is never passed to
+        /// the kernel.
+        ///
+        /// [`Root`]: crate::Root
+        /// [`ErrorKind::InvalidArgument`]: crate::error::ErrorKind::InvalidArgument
+        const SINGLE_COMPONENT = 1 << 32;
     }
 }
+
+bitflags! {
+    /// Wrapper for the `STATX_*` mask flags used by [`HandleRef::statx`].
+    ///
+    /// The flag values and their meaning is identical to the description in the
+    /// [`statx(2)`] man page. The same type is used both to request fields and
+    /// to report which fields were actually filled by the kernel (see
+    /// [`Statx::mask`]).
+    ///
+    /// [`statx(2)`]: http://man7.org/linux/man-pages/man2/statx.2.html
+    /// [`HandleRef::statx`]: crate::HandleRef::statx
+    /// [`Statx::mask`]: crate::Statx::mask
+    #[derive(Default, PartialEq, Eq, Debug, Clone, Copy)]
+    pub struct StatxMask: u32 {
+        const STATX_TYPE = 0x0001;
+        const STATX_MODE = 0x0002;
+        const STATX_NLINK = 0x0004;
+        const STATX_UID = 0x0008;
+        const STATX_GID = 0x0010;
+        const STATX_ATIME = 0x0020;
+        const STATX_MTIME = 0x0040;
+        const STATX_CTIME = 0x0080;
+        const STATX_INO = 0x0100;
+        const STATX_SIZE = 0x0200;
+        const STATX_BLOCKS = 0x0400;
+        const STATX_BASIC_STATS = 0x07ff;
+        const STATX_BTIME = 0x0800;
+        const STATX_MNT_ID = 0x1000;
+        const STATX_DIOALIGN = 0x2000;
+        const STATX_MNT_ID_UNIQUE = 0x4000;
+
+        // Don't clobber unknown STATX_* bits.
+        const _ = !0;
+    }
+}
+
+impl From<StatxMask> for rustix::fs::StatxFlags {
+    fn from(mask: StatxMask) -> Self {
+        Self::from_bits_retain(mask.bits())
+    }
+}
+
+bitflags! {
+    /// Wrapper for the `STATX_ATTR_*` attribute flags returned by
+    /// [`HandleRef::statx`].
+    ///
+    /// The flag values and their meaning is identical to the description in the
+    /// [`statx(2)`] man page.
+    ///
+    /// [`statx(2)`]: http://man7.org/linux/man-pages/man2/statx.2.html
+    /// [`HandleRef::statx`]: crate::HandleRef::statx
+    #[derive(Default, PartialEq, Eq, Debug, Clone, Copy)]
+    pub struct StatxAttributes: u64 {
+        const STATX_ATTR_COMPRESSED = 0x0004;
+        const STATX_ATTR_IMMUTABLE = 0x0010;
+        const STATX_ATTR_APPEND = 0x0020;
+        const STATX_ATTR_NODUMP = 0x0040;
+        const STATX_ATTR_ENCRYPTED = 0x0800;
+        const STATX_ATTR_AUTOMOUNT = 0x1000;
+        const STATX_ATTR_MOUNT_ROOT = 0x2000;
+        const STATX_ATTR_VERITY = 0x100000;
+        const STATX_ATTR_DAX = 0x200000;
+
+        // Don't clobber unknown STATX_ATTR_* bits.
+        const _ = !0;
+    }
+}
+
+/// Implement [`serde::Serialize`] and [`serde::Deserialize`] for flag types, as
+/// an array of lowercase flag names (such as `["no_symlinks", "no_xdev"]`).
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::{OpenFlags, ResolverFlags};
+
+    use std::{fmt, marker::PhantomData};
+
+    use bitflags::Flags;
+    use serde::{
+        de::{self, Deserialize, Deserializer, SeqAccess, Visitor},
+        ser::{self, Serialize, SerializeSeq, Serializer},
+    };
+
+    fn serialize_flags<F: Flags + Copy, S: Serializer>(
+        flags: &F,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut remaining = *flags;
+        let mut names = Vec::new();
+        for (name, flag) in flags.iter_names() {
+            names.push(name.to_ascii_lowercase());
+            remaining.remove(flag);
+        }
+        // Silently dropping bits would change the meaning of the flags when
+        // they are deserialised again.
+        if !remaining.is_empty() {
+            Err(ser::Error::custom(
+                "flags contain bits which do not have a name",
+            ))?
+        }
+
+        let mut seq = serializer.serialize_seq(Some(names.len()))?;
+        for name in names {
+            seq.serialize_element(&name)?;
+        }
+        seq.end()
+    }
+
+    struct FlagsVisitor<F>(PhantomData<F>);
+
+    impl<'de, F: Flags> Visitor<'de> for FlagsVisitor<F> {
+        type Value = F;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "an array of flag names")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<F, A::Error> {
+            let mut flags = F::empty();
+            while let Some(name) = seq.next_element::<String>()? {
+                let flag = F::from_name(&name.to_ascii_uppercase())
+                    .ok_or_else(|| de::Error::custom(format!("unknown flag name {name:?}")))?;
+                flags.insert(flag);
+            }
+            Ok(flags)
+        }
+    }
+
+    macro_rules! impl_serde_flags {
+        ($($flags:ty),* $(,)?) => {
+            $(
+                impl Serialize for $flags {
+                    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                        serialize_flags(self, serializer)
+                    }
+                }
+
+                impl<'de> Deserialize<'de> for $flags {
+                    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                        deserializer.deserialize_seq(FlagsVisitor(PhantomData))
+                    }
+                }
+            )*
+        };
+    }
+
+    impl_serde_flags!(OpenFlags, ResolverFlags);
+}
@@ -20,17 +20,40 @@
 #![forbid(unsafe_code)]
 
 use crate::{
-    error::{Error, ErrorImpl},
-    flags::OpenFlags,
-    procfs::GLOBAL_PROCFS_HANDLE,
+    error::{Error, ErrorExt, ErrorImpl, ErrorKind},
+    flags::{OpenFlags, StatxAttributes, StatxMask},
+    procfs::{ProcfsBase, GLOBAL_PROCFS_HANDLE},
+    syscalls,
     utils::FdExt,
 };
 
 use std::{
+    convert::Infallible,
+    ffi::{CString, OsStr, OsString},
     fs::File,
-    os::unix::io::{AsFd, BorrowedFd, OwnedFd},
+    io::{Error as IOError, ErrorKind as IOErrorKind, Read, Write},
+    ops::{ControlFlow, Deref},
+    os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
 };
 
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+
+use rustix::{
+    fs::{
+        self as rustix_fs, AtFlags, FileType, Stat, Statx as RawStatx, StatxFlags, StatxTimestamp,
+        XattrFlags,
+    },
+    io::Errno,
+    thread::UnshareFlags,
+};
+
+/// The size of the chunks used by [`HandleRef::copy_to_with_progress`].
+const COPY_CHUNK_SIZE: usize = 1 << 20;
+
 /// A handle to an existing inode within a [`Root`].
 ///
 /// This handle references an already-resolved path which can be used for the
@@ -58,6 +81,45 @@ impl Handle {
         Self { inner: fd.into() }
     }
 
+    /// Wrap an [`OwnedFd`] into a [`Handle`], verifying that it looks like a
+    /// handle returned by libpathrs.
+    ///
+    /// [`Handle::from_fd`] will accept any file descriptor, which can be a
+    /// footgun when receiving file descriptors from other processes. This
+    /// method instead requires that `fd` is an `O_PATH` descriptor (checked
+    /// with `fcntl(F_GETFL)`) referencing a regular file, directory or
+    /// symlink. If you need to wrap `O_PATH` handles to other inode types
+    /// (such as device nodes), use [`Handle::from_fd`] together with
+    /// [`Handle::is_opath`] and [`Handle::stat`].
+    ///
+    /// # Errors
+    ///
+    /// If `fd` does not meet these requirements, an error with
+    /// [`ErrorKind::InvalidArgument`] is returned.
+    ///
+    /// [`ErrorKind::InvalidArgument`]: crate::error::ErrorKind::InvalidArgument
+    pub fn from_fd_checked<Fd: Into<OwnedFd>>(fd: Fd) -> Result<Self, Error> {
+        let handle = Self::from_fd(fd);
+        if !handle.is_opath()? {
+            Err(ErrorImpl::InvalidArgument {
+                name: "fd".into(),
+                description: "file descriptor is not an O_PATH handle".into(),
+            })?
+        }
+        let meta = handle.stat()?;
+        if !(meta.is_file() || meta.is_dir() || meta.is_symlink()) {
+            Err(ErrorImpl::InvalidArgument {
+                name: "fd".into(),
+                description: format!(
+                    "O_PATH handle has unexpected inode type {:#o}",
+                    meta.mode() & libc::S_IFMT
+                )
+                .into(),
+            })?
+        }
+        Ok(handle)
+    }
+
     /// Borrow this [`Handle`] as a [`HandleRef`].
     // XXX: We can't use Borrow/Deref for this because HandleRef takes a
     //      lifetime rather than being a pure reference. Ideally we would use
@@ -79,6 +141,45 @@ impl Handle {
         self.as_ref().try_clone()
     }
 
+    /// Create a copy of an existing [`Handle`], with the new file descriptor
+    /// number being at least `minfd`.
+    ///
+    /// This is identical to [`Handle::try_clone`] except that the duplicate is
+    /// created with `fcntl(F_DUPFD_CLOEXEC, minfd)`. This is useful when
+    /// preparing the file descriptor table of a child process, to avoid
+    /// clobbering reserved low file descriptors (such as stdio).
+    #[inline]
+    pub fn try_clone_min(&self, minfd: RawFd) -> Result<Self, Error> {
+        self.as_ref().try_clone_min(minfd)
+    }
+
+    /// Convert a directory [`Handle`] into a [`Root`] confined to that
+    /// directory.
+    ///
+    /// This allows you to hand a subtree to some other code and have it use
+    /// the normal [`Root`] semantics within that subtree -- `..` components and
+    /// absolute symlinks are resolved relative to the directory referenced by
+    /// this handle, and so cannot be used to escape it (even into the original
+    /// [`Root`] the handle was resolved from). If you need to go from a path to
+    /// a nested [`Root`] directly, see [`Root::open_beneath`].
+    ///
+    /// This is equivalent to [`Root::from_fd_checked`], so the resolver
+    /// configuration of the new [`Root`] is set to the system default.
+    ///
+    /// # Errors
+    ///
+    /// If the handle does not reference a directory, an error with
+    /// [`ErrorKind::InvalidArgument`] is returned.
+    ///
+    /// [`Root`]: crate::Root
+    /// [`Root::open_beneath`]: crate::Root::open_beneath
+    /// [`Root::from_fd_checked`]: crate::Root::from_fd_checked
+    /// [`ErrorKind::InvalidArgument`]: crate::error::ErrorKind::InvalidArgument
+    #[inline]
+    pub fn into_root(self) -> Result<crate::Root, Error> {
+        crate::Root::from_fd_checked(self)
+    }
+
     /// "Upgrade" the handle to a usable [`File`] handle.
     ///
     /// This new [`File`] handle is suitable for reading and writing. This does
@@ -92,12 +193,442 @@ impl Handle {
     /// 2. `ioctl(fd, TIOCSCTTY, 0)` will set the fd as the controlling terminal
     ///    (if you don't have one already, and the fd references a TTY).
     ///
+    /// Note that clearing `O_CLOEXEC` after the fact is racy in multi-threaded
+    /// programs (another thread could `fork(2)` and `exec(2)` in between). If
+    /// this matters to you, use [`Handle::reopen_raw`] instead.
+    ///
     /// [`Root::create`]: crate::Root::create
     #[doc(alias = "pathrs_reopen")]
     #[inline]
     pub fn reopen<F: Into<OpenFlags>>(&self, flags: F) -> Result<File, Error> {
         self.as_ref().reopen(flags)
     }
+
+    /// Asynchronous version of [`Handle::reopen`], for use with [tokio].
+    ///
+    /// The re-open is done with [`Handle::reopen`] on tokio's blocking thread
+    /// pool (using [`tokio::task::spawn_blocking`]) so that it does not block
+    /// the async executor. Because the operation may outlive the caller's
+    /// borrow, the [`Handle`] has to be shared through an [`Arc`]. This method
+    /// must be awaited from within a tokio runtime.
+    ///
+    /// This method is only available with the `tokio` feature.
+    ///
+    /// [tokio]: https://tokio.rs/
+    #[cfg(feature = "tokio")]
+    pub async fn reopen_async<F: Into<OpenFlags>>(
+        self: &Arc<Self>,
+        flags: F,
+    ) -> Result<File, Error> {
+        let (handle, flags) = (Arc::clone(self), flags.into());
+        crate::utils::spawn_blocking(move || Handle::reopen(&handle, flags)).await
+    }
+
+    /// "Upgrade" the handle to a usable [`File`] handle, using exactly the
+    /// provided `flags`.
+    ///
+    /// This is identical to [`Handle::reopen`], except that `O_CLOEXEC` and
+    /// `O_NOCTTY` are *not* implicitly added to `flags`. The flags are applied
+    /// by the same `open(2)` call which creates the new file descriptor, so
+    /// there is no window where the file descriptor has the "wrong" flags set
+    /// (unlike clearing `O_CLOEXEC` with `fcntl(2)` after [`Handle::reopen`]).
+    ///
+    /// This is mostly useful for opening file descriptors that are meant to
+    /// be inherited by child processes, or for opening a TTY which should
+    /// become the controlling terminal of the process (by omitting
+    /// `O_NOCTTY`). If you do not have a specific need for this, use
+    /// [`Handle::reopen`] -- forgetting to set `O_CLOEXEC` will leak the file
+    /// descriptor into any programs you `exec(2)`.
+    #[inline]
+    pub fn reopen_raw<F: Into<OpenFlags>>(&self, flags: F) -> Result<File, Error> {
+        self.as_ref().reopen_raw(flags)
+    }
+
+    /// "Upgrade" the handle to a [`File`] handle opened for appending.
+    ///
+    /// This is shorthand for [`Handle::reopen`] with `O_WRONLY|O_APPEND`. Since
+    /// `O_APPEND` is set on the new file description, each write to the
+    /// returned [`File`] will atomically append to the end of the file (even if
+    /// other processes are also appending to the same file).
+    #[inline]
+    pub fn reopen_append(&self) -> Result<File, Error> {
+        self.as_ref().reopen_append()
+    }
+
+    /// Copy the contents of the file referenced by this [`Handle`] into the
+    /// file referenced by `dst`, reporting progress to `progress`.
+    ///
+    /// Both handles are re-opened (see [`Handle::reopen`]) -- the source with
+    /// `O_RDONLY` and `dst` with `O_WRONLY|O_TRUNC` -- so that after a
+    /// successful copy `dst` contains exactly the bytes that were copied. If
+    /// `len` is [`Some`], at most that many bytes are copied, otherwise the
+    /// copy continues until the end of the source file is reached.
+    ///
+    /// The data is copied in chunks, and after each chunk has been written
+    /// `progress` is called with the total number of bytes copied so far. If
+    /// `progress` returns [`ControlFlow::Break`], the copy is stopped early
+    /// (and `dst` will only contain the data copied so far). The total number
+    /// of bytes copied is returned in either case.
+    #[inline]
+    pub fn copy_to_with_progress<F>(
+        &self,
+        dst: &HandleRef<'_>,
+        len: Option<u64>,
+        progress: F,
+    ) -> Result<u64, Error>
+    where
+        F: FnMut(u64) -> ControlFlow<()>,
+    {
+        self.as_ref().copy_to_with_progress(dst, len, progress)
+    }
+
+    /// Create a private read-only memory mapping of the file referenced by
+    /// this [`Handle`].
+    ///
+    /// The file is re-opened with `O_RDONLY` (see [`Handle::reopen`]) and the
+    /// whole file is mapped with `PROT_READ` and `MAP_PRIVATE`. Because the
+    /// mapping is read-only, its pages are never copied and are shared with
+    /// the page cache. This means that the returned [`Mmap`] is **not** a
+    /// snapshot of the file: writes to the file made after the mapping was
+    /// created (by this process or any other) may or may not be visible
+    /// through the mapping. If you need a consistent snapshot, use
+    /// [`Handle::mmap_private_snapshot`].
+    ///
+    /// # Caveats #
+    ///
+    /// If the file is truncated while the mapping exists, accessing any of
+    /// the pages past the new end of the file will cause the kernel to send
+    /// `SIGBUS` to the process (which will kill it by default). This is an
+    /// inherent limitation of `mmap(2)`, so you should only use this method on
+    /// files which you do not expect to be concurrently truncated.
+    #[inline]
+    pub fn mmap_private(&self) -> Result<Mmap, Error> {
+        self.as_ref().mmap_private()
+    }
+
+    /// Create a private read-only snapshot of the file referenced by this
+    /// [`Handle`] in memory.
+    ///
+    /// The file is re-opened with `O_RDONLY` (see [`Handle::reopen`]) and its
+    /// contents are read into a new anonymous (`MAP_PRIVATE`) mapping, which
+    /// is then made read-only. Unlike [`Handle::mmap_private`], the returned
+    /// [`Mmap`] is a snapshot of the file contents: later writes to the file
+    /// (by this process or any other) are not visible through it, and
+    /// truncating the file cannot cause a `SIGBUS`. Note that the snapshot is
+    /// only consistent if nobody modifies the file while it is being read, and
+    /// an error is returned if the file is truncated while it is being read.
+    ///
+    /// Since the snapshot uses as much memory as the size of the file, an
+    /// [`ErrorKind::InvalidArgument`] is returned (without reading anything)
+    /// if the file is larger than `max_len` bytes.
+    #[inline]
+    pub fn mmap_private_snapshot(&self, max_len: u64) -> Result<Mmap, Error> {
+        self.as_ref().mmap_private_snapshot(max_len)
+    }
+
+    /// Check whether the underlying file descriptor is an `O_PATH` descriptor.
+    ///
+    /// All [`Handle`]s returned by libpathrs are `O_PATH` descriptors, but
+    /// [`Handle::from_fd`] will accept any file descriptor. This method lets
+    /// you verify that a file descriptor you received from elsewhere is
+    /// actually an `O_PATH` handle.
+    #[inline]
+    pub fn is_opath(&self) -> Result<bool, Error> {
+        self.as_ref().is_opath()
+    }
+
+    /// Get the [`Metadata`] of the inode referenced by this [`Handle`].
+    ///
+    /// This is done directly through the `O_PATH` file descriptor, so there is
+    /// no need to [`Handle::reopen`] the handle first (and thus it is safe to
+    /// use on inodes you would not want to open, such as FIFOs). If the
+    /// symlink was not followed when resolving the handle (such as with
+    /// [`Root::resolve_nofollow`]), the metadata of the symlink itself is
+    /// returned.
+    ///
+    /// [`statx(2)`] is used if available, in which case the creation time of
+    /// the inode is also returned (see [`Metadata::created`]). On older
+    /// kernels (where [`statx(2)`] fails with `ENOSYS` or `EOPNOTSUPP`),
+    /// `fstat(2)` is used instead. Any other [`statx(2)`] error is returned
+    /// as-is.
+    ///
+    /// [`Root::resolve_nofollow`]: crate::Root::resolve_nofollow
+    /// [`statx(2)`]: http://man7.org/linux/man-pages/man2/statx.2.html
+    #[inline]
+    pub fn stat(&self) -> Result<Metadata, Error> {
+        self.as_ref().stat()
+    }
+
+    /// Get the creation time (`btime`) of the inode referenced by this
+    /// [`Handle`].
+    ///
+    /// Many filesystems do not record the creation time of inodes (and
+    /// [`statx(2)`] is required to get it at all), so [`None`] is returned if
+    /// it is not available. This is distinct from the modification and status
+    /// change times, which are always available.
+    ///
+    /// [`statx(2)`]: http://man7.org/linux/man-pages/man2/statx.2.html
+    #[inline]
+    pub fn created(&self) -> Result<Option<SystemTime>, Error> {
+        self.as_ref().created()
+    }
+
+    /// Get the extended [`Statx`] metadata of the inode referenced by this
+    /// [`Handle`], using [`statx(2)`].
+    ///
+    /// `mask` specifies which fields are requested. Note that the kernel may
+    /// fill more or fewer fields than requested (depending on the filesystem),
+    /// so you should check [`Statx::mask`] before using optional fields.
+    ///
+    /// # Errors
+    ///
+    /// If [`statx(2)`] is not supported by the running kernel, an error with
+    /// [`ErrorKind::NotSupported`] is returned.
+    ///
+    /// [`statx(2)`]: http://man7.org/linux/man-pages/man2/statx.2.html
+    /// [`ErrorKind::NotSupported`]: crate::error::ErrorKind::NotSupported
+    #[inline]
+    pub fn statx(&self, mask: StatxMask) -> Result<Statx, Error> {
+        self.as_ref().statx(mask)
+    }
+
+    /// Get the target of the symlink referenced by this [`Handle`].
+    ///
+    /// This is primarily useful for handles returned by
+    /// [`Root::resolve_nofollow`] or [`Root::resolve_partial`] where the
+    /// trailing component is a symlink. The returned path is the raw contents
+    /// of the symlink, and is not modified to be "safe" in any way.
+    ///
+    /// # Errors
+    ///
+    /// If the handle does not reference a symlink, an error with
+    /// [`ErrorKind::OsError`] containing `EINVAL` is returned (matching
+    /// `readlink(2)`).
+    ///
+    /// [`Root::resolve_nofollow`]: crate::Root::resolve_nofollow
+    /// [`Root::resolve_partial`]: crate::Root::resolve_partial
+    /// [`ErrorKind::OsError`]: crate::error::ErrorKind::OsError
+    #[inline]
+    pub fn readlink(&self) -> Result<PathBuf, Error> {
+        self.as_ref().readlink()
+    }
+
+    /// Identical to [`Handle::readlink`], except that the target of the
+    /// symlink is read into `buf` and its length is returned.
+    ///
+    /// `buf` is cleared before reading the symlink, and is only grown if it is
+    /// too small to hold the target. This allows programs that read a large
+    /// number of symlinks to re-use a single buffer rather than allocating a
+    /// new [`PathBuf`] for every symlink. The contents of `buf` are unspecified
+    /// if an error is returned.
+    ///
+    /// # Errors
+    ///
+    /// See [`Handle::readlink`].
+    #[inline]
+    pub fn readlink_into(&self, buf: &mut Vec<u8>) -> Result<usize, Error> {
+        self.as_ref().readlink_into(buf)
+    }
+
+    /// Get the value of the extended attribute `name` of the inode referenced
+    /// by this [`Handle`].
+    ///
+    /// Because `fgetxattr(2)` does not work on `O_PATH` file descriptors, this
+    /// (and the other xattr methods) operate on the inode through its
+    /// `/proc/thread-self/fd/$n` magic-link, using `getxattrat(2)` relative to
+    /// the `fd` directory of the procfs handle (so a fake `/proc` mount is
+    /// never used). `name` is passed to the kernel unmodified, so all xattr
+    /// namespaces (including `security.*` and `trusted.*`) can be used, subject
+    /// to the usual permission checks.
+    ///
+    /// On kernels without the `*xattrat(2)` syscalls (before Linux 6.13), the
+    /// path-based `*xattr(2)` syscalls are used instead. These always resolve
+    /// relative paths from the current directory, so each call is done on a
+    /// short-lived helper thread which uses `unshare(CLONE_FS)` to get its own
+    /// current directory. If `unshare(2)` is blocked (by a seccomp filter, for
+    /// instance), the xattr methods fail on those kernels.
+    #[inline]
+    pub fn getxattr(&self, name: &OsStr) -> Result<Vec<u8>, Error> {
+        self.as_ref().getxattr(name)
+    }
+
+    /// Set the value of the extended attribute `name` of the inode referenced
+    /// by this [`Handle`], creating it if it does not exist.
+    ///
+    /// See [`Handle::getxattr`] for more details.
+    #[inline]
+    pub fn setxattr(&self, name: &OsStr, value: &[u8]) -> Result<(), Error> {
+        self.as_ref().setxattr(name, value)
+    }
+
+    /// Remove the extended attribute `name` from the inode referenced by this
+    /// [`Handle`].
+    ///
+    /// See [`Handle::getxattr`] for more details.
+    #[inline]
+    pub fn removexattr(&self, name: &OsStr) -> Result<(), Error> {
+        self.as_ref().removexattr(name)
+    }
+
+    /// List the names of the extended attributes of the inode referenced by
+    /// this [`Handle`].
+    ///
+    /// See [`Handle::getxattr`] for more details.
+    #[inline]
+    pub fn listxattr(&self) -> Result<Vec<OsString>, Error> {
+        self.as_ref().listxattr()
+    }
+
+    /// Enable fs-verity on the regular file referenced by this [`Handle`],
+    /// using [`FS_IOC_ENABLE_VERITY`].
+    ///
+    /// Once fs-verity is enabled, the file becomes read-only and the kernel
+    /// will verify the contents of the file against its Merkle tree whenever
+    /// it is read. The digest of the file can be retrieved with
+    /// [`Handle::fsverity_measure`].
+    ///
+    /// The `ioctl(2)` is done on a read-only re-opened copy of the [`Handle`]
+    /// (see [`Handle::reopen`]) since it does not work on `O_PATH` file
+    /// descriptors. Enabling fs-verity can take a while for large files, as
+    /// the kernel needs to build the Merkle tree before returning.
+    ///
+    /// # Errors
+    ///
+    /// If the filesystem does not support fs-verity (or it is not enabled on
+    /// the filesystem), an [`ErrorKind::OsError`] with `ENOTTY` or
+    /// `EOPNOTSUPP` is returned. If the file is currently open for writing,
+    /// `ETXTBSY` is returned, and if fs-verity is already enabled on the
+    /// file, `EEXIST` is returned.
+    ///
+    /// [`FS_IOC_ENABLE_VERITY`]: https://docs.kernel.org/filesystems/fsverity.html#fs-ioc-enable-verity
+    /// [`ErrorKind::OsError`]: crate::error::ErrorKind::OsError
+    #[inline]
+    pub fn fsverity_enable(&self, params: &FsVerityParams) -> Result<(), Error> {
+        self.as_ref().fsverity_enable(params)
+    }
+
+    /// Get the fs-verity digest of the file referenced by this [`Handle`],
+    /// using [`FS_IOC_MEASURE_VERITY`].
+    ///
+    /// See [`Handle::fsverity_enable`] for more details.
+    ///
+    /// # Errors
+    ///
+    /// If the filesystem does not support fs-verity, an
+    /// [`ErrorKind::OsError`] with `ENOTTY` or `EOPNOTSUPP` is returned. If
+    /// fs-verity is not enabled on the file, `ENODATA` is returned.
+    ///
+    /// [`FS_IOC_MEASURE_VERITY`]: https://docs.kernel.org/filesystems/fsverity.html#fs-ioc-measure-verity
+    /// [`ErrorKind::OsError`]: crate::error::ErrorKind::OsError
+    #[inline]
+    pub fn fsverity_measure(&self) -> Result<FsVerityDigest, Error> {
+        self.as_ref().fsverity_measure()
+    }
+
+    /// Execute the inode referenced by this [`Handle`] using [`execveat(2)`]
+    /// with `AT_EMPTY_PATH`.
+    ///
+    /// Since the program is executed through the file descriptor, the inode
+    /// that is executed is guaranteed to be the one that was resolved, even if
+    /// the path used to resolve the [`Handle`] has since been swapped out from
+    /// underneath us. `argv` and `envp` are passed as-is to the new program,
+    /// and `flags` are passed to [`execveat(2)`] (`AT_EMPTY_PATH` is always
+    /// set).
+    ///
+    /// In order for interpreted programs (such as `#!` scripts) to work, the
+    /// interpreter is given a path to the program of the form `/dev/fd/$n`,
+    /// and so the program is executed through a copy of the [`Handle`]'s file
+    /// descriptor which does not have `O_CLOEXEC` set. This file descriptor
+    /// will be inherited by the new program.
+    ///
+    /// # Errors
+    ///
+    /// This method only returns if the [`execveat(2)`] failed, in which case
+    /// the calling process is unaffected. In particular, if the inode is
+    /// currently open for writing an [`ErrorKind::OsError`] with `ETXTBSY` is
+    /// returned, and if the inode is not in a recognised executable format an
+    /// [`ErrorKind::OsError`] with `ENOEXEC` is returned.
+    ///
+    /// [`execveat(2)`]: http://man7.org/linux/man-pages/man2/execveat.2.html
+    /// [`ErrorKind::OsError`]: crate::error::ErrorKind::OsError
+    #[inline]
+    pub fn execveat(
+        &self,
+        argv: &[CString],
+        envp: &[CString],
+        flags: i32,
+    ) -> Result<Infallible, Error> {
+        self.as_ref().execveat(argv, envp, flags)
+    }
+
+    /// Get a copy of the file descriptor of this [`Handle`] together with a
+    /// [`HandleInfo`] describing it, suitable for sending to another process
+    /// (with `SCM_RIGHTS`).
+    ///
+    /// The receiver should use [`Handle::from_transfer`] (rather than
+    /// [`Handle::from_fd`]) to make sure that the file descriptor they received
+    /// is the one that was sent.
+    #[inline]
+    pub fn to_transfer(&self) -> Result<(OwnedFd, HandleInfo), Error> {
+        self.as_ref().to_transfer()
+    }
+
+    /// Wrap a file descriptor received from another process into a
+    /// [`Handle`], verifying that it matches the [`HandleInfo`] produced by
+    /// [`Handle::to_transfer`].
+    ///
+    /// Unlike [`Handle::from_fd`], this protects against the file descriptor
+    /// being substituted while in transit. The device, inode number,
+    /// modification time and file status flags of `fd` must all match `info`,
+    /// otherwise an error with [`ErrorKind::SafetyViolation`] is returned. Note
+    /// that this means that modifying the inode after [`Handle::to_transfer`]
+    /// will also cause this verification to fail.
+    ///
+    /// [`ErrorKind::SafetyViolation`]: crate::error::ErrorKind::SafetyViolation
+    pub fn from_transfer<Fd: Into<OwnedFd>>(fd: Fd, info: HandleInfo) -> Result<Self, Error> {
+        let handle = Self::from_fd(fd);
+        let actual = handle.as_ref().info()?;
+        if actual != info {
+            Err(ErrorImpl::SafetyViolation {
+                description: format!(
+                    "transferred handle {actual:?} does not match expected {info:?}"
+                )
+                .into(),
+            })?
+        }
+        Ok(handle)
+    }
+
+    /// Get a [`RevalToken`] describing the current state of the inode
+    /// referenced by this [`Handle`].
+    ///
+    /// The token can later be passed to [`Handle::is_still_valid`] to cheaply
+    /// check whether the inode has changed since the token was created,
+    /// without having to resolve the path again. This is intended for caches
+    /// of resolved [`Handle`]s.
+    #[inline]
+    pub fn revalidation_token(&self) -> Result<RevalToken, Error> {
+        self.as_ref().revalidation_token()
+    }
+
+    /// Check whether the inode referenced by this [`Handle`] still matches
+    /// the state captured in `token` by [`Handle::revalidation_token`].
+    ///
+    /// This returns `false` if `token` was created for a different inode, if
+    /// the inode has been changed since the token was created (as indicated by
+    /// its status change time, which is updated whenever the contents or
+    /// metadata of the inode are modified), or if the inode has been unlinked.
+    ///
+    /// Note that this is a weak check. Status change times have a limited
+    /// granularity (on older kernels, typically the timer tick), so a
+    /// modification made very soon after the token was created may not be
+    /// detected. In addition, this only checks the inode referenced by the
+    /// [`Handle`], so a path that has been renamed or replaced since it was
+    /// resolved will not be detected unless the old inode was also unlinked.
+    #[inline]
+    pub fn is_still_valid(&self, token: &RevalToken) -> Result<bool, Error> {
+        self.as_ref().is_still_valid(token)
+    }
 }
 
 impl From<OwnedFd> for Handle {
@@ -134,124 +665,2284 @@ impl AsFd for Handle {
     }
 }
 
-/// Borrowed version of [`Handle`].
+/// An opaque kernel file handle for an inode within a [`Root`].
 ///
-/// Unlike [`Handle`], when [`HandleRef`] is dropped the underlying file
-/// descriptor is *not* closed. This is mainly useful for programs and libraries
-/// that have to do operations on [`&File`][File]s and [`BorrowedFd`]s passed
-/// from elsewhere.
+/// Unlike [`Handle`], a [`FileHandle`] (as returned by
+/// [`Root::resolve_unpinned`]) does not contain a file descriptor and thus does
+/// not keep the mount containing the inode busy. This means that a
+/// [`FileHandle`] can be stored indefinitely without blocking the unmounting
+/// of the filesystem.
 ///
-/// [File]: std::fs::File
-// TODO: Is there any way we can restructure this to use Deref so that we don't
-//       need to copy all of the methods into Handle? Probably not... Maybe GATs
-//       will eventually support this but we'd still need a GAT-friendly Deref.
-#[derive(Copy, Clone, Debug)]
-pub struct HandleRef<'fd> {
-    inner: BorrowedFd<'fd>,
+/// A [`FileHandle`] can be converted back into a [`Handle`] with
+/// [`Root::open_unpinned`]. Note that (due to restrictions of
+/// `open_by_handle_at(2)`) this operation requires `CAP_DAC_READ_SEARCH`.
+///
+/// [`Root`]: crate::Root
+/// [`Root::resolve_unpinned`]: crate::Root::resolve_unpinned
+/// [`Root::open_unpinned`]: crate::Root::open_unpinned
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileHandle {
+    pub(crate) handle_type: i32,
+    pub(crate) data: Vec<u8>,
 }
 
-impl HandleRef<'_> {
-    /// Wrap a [`BorrowedFd`] into a [`HandleRef`].
-    pub fn from_fd(inner: BorrowedFd<'_>) -> HandleRef<'_> {
-        HandleRef { inner }
+impl FileHandle {
+    /// The filesystem-specific type of the file handle.
+    #[inline]
+    pub fn handle_type(&self) -> i32 {
+        self.handle_type
     }
 
-    /// Create a copy of a [`HandleRef`].
-    ///
-    /// Note that (unlike [`BorrowedFd::clone`]) this method creates a full copy
-    /// of the underlying file descriptor and thus is more equivalent to
-    /// [`BorrowedFd::try_clone_to_owned`].
-    ///
-    /// To create a shallow copy of a [`HandleRef`], you can use
-    /// [`Clone::clone`] (or just [`Copy`]).
-    // TODO: We might need to call this something other than try_clone(), since
-    //       it's a little too easy to confuse with Clone::clone() but we also
-    //       really want to have Copy.
-    pub fn try_clone(&self) -> Result<Handle, Error> {
-        self.as_fd()
-            .try_clone_to_owned()
-            .map_err(|err| {
-                ErrorImpl::OsError {
-                    operation: "clone underlying handle file".into(),
-                    source: err,
-                }
-                .into()
-            })
-            .map(Handle::from_fd)
+    /// The opaque filesystem-specific handle data.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
     }
+}
 
-    /// "Upgrade" the handle to a usable [`File`] handle.
-    ///
-    /// This new [`File`] handle is suitable for reading and writing. This does
-    /// not consume the original handle (allowing for it to be used many times).
-    ///
-    /// The [`File`] handle will be opened with `O_NOCTTY` and `O_CLOEXEC` set,
-    /// regardless of whether those flags are present in the `flags` argument.
-    /// You can correct these yourself if these defaults are not ideal for you:
-    ///
-    /// 1. `fcntl(fd, F_SETFD, 0)` will let you unset `O_CLOEXEC`.
-    /// 2. `ioctl(fd, TIOCSCTTY, 0)` will set the fd as the controlling terminal
-    ///    (if you don't have one already, and the fd references a TTY).
-    ///
-    /// [`Root::create`]: crate::Root::create
-    #[doc(alias = "pathrs_reopen")]
-    pub fn reopen<F: Into<OpenFlags>>(&self, flags: F) -> Result<File, Error> {
-        self.inner
-            .reopen(&GLOBAL_PROCFS_HANDLE, flags.into())
-            .map(File::from)
+/// A hash algorithm supported by fs-verity.
+///
+/// See [`Handle::fsverity_enable`] for more details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FsVerityHashAlgorithm {
+    /// SHA-256 (`FS_VERITY_HASH_ALG_SHA256`).
+    Sha256,
+    /// SHA-512 (`FS_VERITY_HASH_ALG_SHA512`).
+    Sha512,
+}
+
+impl FsVerityHashAlgorithm {
+    fn as_raw(self) -> u16 {
+        match self {
+            Self::Sha256 => 1,
+            Self::Sha512 => 2,
+        }
     }
 
-    // TODO: All the different stat* interfaces?
+    fn from_raw(alg: u16) -> Option<Self> {
+        match alg {
+            1 => Some(Self::Sha256),
+            2 => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+}
 
-    // TODO: bind(). This might be safe to do (set the socket path to
-    //       /proc/self/fd/...) but I'm a bit sad it'd be separate from
-    //       Handle::reopen().
+/// Parameters for enabling fs-verity with [`Handle::fsverity_enable`].
+///
+/// The defaults (SHA-256, 4096-byte Merkle tree blocks and no salt) match
+/// the defaults of the `fsverity` tool. Note that the digest returned by
+/// [`Handle::fsverity_measure`] depends on all of these parameters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsVerityParams {
+    hash_algorithm: FsVerityHashAlgorithm,
+    block_size: u32,
+    salt: Vec<u8>,
 }
 
-impl<'fd> From<BorrowedFd<'fd>> for HandleRef<'fd> {
-    /// Shorthand for [`HandleRef::from_fd`].
-    fn from(fd: BorrowedFd<'fd>) -> Self {
-        Self::from_fd(fd)
+impl Default for FsVerityParams {
+    fn default() -> Self {
+        Self {
+            hash_algorithm: FsVerityHashAlgorithm::Sha256,
+            block_size: 4096,
+            salt: Vec::new(),
+        }
     }
 }
 
-impl AsFd for HandleRef<'_> {
-    /// Access the underlying file descriptor for a [`HandleRef`].
-    ///
-    /// **Note**: This method is primarily intended to allow for tests and other
-    /// code to check the status of the underlying file descriptor. It is not
-    /// safe to use this [`BorrowedFd`] directly to do filesystem operations.
-    /// Please use the provided [`HandleRef`] methods.
+impl FsVerityParams {
+    /// Set the hash algorithm used for the Merkle tree.
     #[inline]
-    fn as_fd(&self) -> BorrowedFd<'_> {
-        self.inner.as_fd()
+    pub fn hash_algorithm(mut self, alg: FsVerityHashAlgorithm) -> Self {
+        self.hash_algorithm = alg;
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::{HandleRef, Root};
+    /// Set the size of the Merkle tree blocks. This must be a power of two
+    /// supported by the filesystem (usually between 1024 and the page size).
+    #[inline]
+    pub fn block_size(mut self, block_size: u32) -> Self {
+        self.block_size = block_size;
+        self
+    }
 
-    use std::os::unix::io::{AsFd, AsRawFd};
+    /// Set the salt which is prepended to every block before it is hashed
+    /// (at most 32 bytes).
+    #[inline]
+    pub fn salt<S: Into<Vec<u8>>>(mut self, salt: S) -> Self {
+        self.salt = salt.into();
+        self
+    }
+}
 
-    use anyhow::Error;
-    use pretty_assertions::assert_eq;
+/// The fs-verity digest of a file, as returned by
+/// [`Handle::fsverity_measure`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FsVerityDigest {
+    algorithm: FsVerityHashAlgorithm,
+    digest: Vec<u8>,
+}
 
-    #[test]
-    fn from_fd() -> Result<(), Error> {
-        let handle = Root::open(".")?.resolve(".")?;
-        let handle_ref1 = handle.as_ref();
-        let handle_ref2 = HandleRef::from_fd(handle.as_fd());
+impl FsVerityDigest {
+    /// The hash algorithm used to compute the digest.
+    #[inline]
+    pub fn algorithm(&self) -> FsVerityHashAlgorithm {
+        self.algorithm
+    }
 
-        assert_eq!(
-            handle.as_fd().as_raw_fd(),
-            handle_ref1.as_fd().as_raw_fd(),
+    /// The raw bytes of the digest.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.digest
+    }
+}
+
+/// A private read-only memory mapping of a file.
+///
+/// This is returned by [`Handle::mmap_private`] and
+/// [`Handle::mmap_private_snapshot`], and dereferences to the contents of the
+/// file (see those methods for details about when changes to the file are
+/// visible). The mapping is removed when the [`Mmap`] is dropped.
+#[derive(Debug)]
+pub struct Mmap {
+    mapping: syscalls::PrivateMapping,
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        self.mapping.as_slice()
+    }
+}
+
+impl AsRef<[u8]> for Mmap {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Identifying information about a [`Handle`] being passed to another
+/// process.
+///
+/// This is returned by [`Handle::to_transfer`] and is checked by
+/// [`Handle::from_transfer`]. It has a fixed `#[repr(C)]` layout so that it
+/// can be sent alongside the file descriptor (as the data of the
+/// `SCM_RIGHTS` message, for instance).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HandleInfo {
+    /// The device (`st_dev`) containing the inode.
+    pub dev: u64,
+    /// The inode number (`st_ino`).
+    pub ino: u64,
+    /// The seconds component of the modification time (`st_mtime`).
+    pub mtime_sec: i64,
+    /// The nanoseconds component of the modification time (`st_mtime_nsec`).
+    pub mtime_nsec: u32,
+    /// The file status flags of the file descriptor (from `F_GETFL`).
+    pub flags: i32,
+}
+
+/// A snapshot of the identity and status change time of the inode referenced
+/// by a [`Handle`].
+///
+/// This is returned by [`Handle::revalidation_token`] and is checked by
+/// [`Handle::is_still_valid`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RevalToken {
+    dev: u64,
+    ino: u64,
+    ctime_sec: i64,
+    ctime_nsec: u32,
+    nlink: u64,
+}
+
+impl From<&Metadata> for RevalToken {
+    fn from(meta: &Metadata) -> Self {
+        Self {
+            dev: meta.dev,
+            ino: meta.ino,
+            ctime_sec: meta.ctime.sec,
+            ctime_nsec: meta.ctime.nsec,
+            nlink: meta.nlink,
+        }
+    }
+}
+
+/// Metadata about an inode referenced by a [`Handle`].
+///
+/// This is returned by [`Handle::stat`] and is a slimmed-down version of
+/// [`std::fs::Metadata`] containing the fields most commonly needed to make
+/// decisions about a [`Handle`] (such as whether it should be re-opened).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    // The set of fields that were filled by the kernel. These are crate-public
+    // so that the C API can export the raw values.
+    pub(crate) mask: StatxMask,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u64,
+    size: u64,
+    ino: u64,
+    dev: u64,
+    pub(crate) atime: Timestamp,
+    pub(crate) mtime: Timestamp,
+    pub(crate) ctime: Timestamp,
+    btime: Option<SystemTime>,
+}
+
+#[allow(clippy::useless_conversion)] // 32-bit arches
+#[allow(clippy::unnecessary_cast)] // 32-bit arches
+impl Metadata {
+    pub(crate) fn from_stat(stat: &Stat) -> Self {
+        Self {
+            // fstat(2) always fills all of the basic fields.
+            mask: StatxMask::STATX_BASIC_STATS,
+            mode: stat.st_mode,
+            uid: stat.st_uid,
+            gid: stat.st_gid,
+            nlink: stat.st_nlink.into(),
+            size: stat.st_size as u64,
+            ino: stat.st_ino.into(),
+            dev: stat.st_dev.into(),
+            atime: Timestamp {
+                sec: stat.st_atime as i64,
+                nsec: stat.st_atime_nsec as u32,
+            },
+            mtime: Timestamp {
+                sec: stat.st_mtime as i64,
+                nsec: stat.st_mtime_nsec as u32,
+            },
+            ctime: Timestamp {
+                sec: stat.st_ctime as i64,
+                nsec: stat.st_ctime_nsec as u32,
+            },
+            btime: None,
+        }
+    }
+
+    fn from_statx(stx: &RawStatx) -> Self {
+        let mask = StatxMask::from_bits_retain(stx.stx_mask);
+        Self {
+            mask,
+            mode: stx.stx_mode.into(),
+            uid: stx.stx_uid,
+            gid: stx.stx_gid,
+            nlink: stx.stx_nlink.into(),
+            size: stx.stx_size,
+            ino: stx.stx_ino,
+            dev: rustix_fs::makedev(stx.stx_dev_major, stx.stx_dev_minor),
+            atime: (&stx.stx_atime).into(),
+            mtime: (&stx.stx_mtime).into(),
+            ctime: (&stx.stx_ctime).into(),
+            btime: mask
+                .contains(StatxMask::STATX_BTIME)
+                .then(|| statx_timestamp(&stx.stx_btime)),
+        }
+    }
+
+    /// The file type and mode (`st_mode`) of the inode.
+    #[inline]
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// The owner (`st_uid`) of the inode.
+    #[inline]
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// The group owner (`st_gid`) of the inode.
+    #[inline]
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// The number of hard links (`st_nlink`) to the inode.
+    #[inline]
+    pub fn nlink(&self) -> u64 {
+        self.nlink
+    }
+
+    /// The size (`st_size`) of the inode in bytes.
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The inode number (`st_ino`).
+    #[inline]
+    pub fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    /// The device (`st_dev`) containing the inode.
+    #[inline]
+    pub fn dev(&self) -> u64 {
+        self.dev
+    }
+
+    /// The last access time (`st_atime`) of the inode.
+    #[inline]
+    pub fn accessed(&self) -> SystemTime {
+        self.atime.to_system_time()
+    }
+
+    /// The last modification time (`st_mtime`) of the inode.
+    #[inline]
+    pub fn modified(&self) -> SystemTime {
+        self.mtime.to_system_time()
+    }
+
+    /// The last status change time (`st_ctime`) of the inode.
+    #[inline]
+    pub fn changed(&self) -> SystemTime {
+        self.ctime.to_system_time()
+    }
+
+    /// The creation time (`btime`) of the inode.
+    ///
+    /// This is [`None`] if the filesystem does not record the creation time of
+    /// inodes, or if the kernel does not support [`statx(2)`].
+    ///
+    /// [`statx(2)`]: http://man7.org/linux/man-pages/man2/statx.2.html
+    #[inline]
+    pub fn created(&self) -> Option<SystemTime> {
+        self.btime
+    }
+
+    /// Is the inode a directory?
+    #[inline]
+    pub fn is_dir(&self) -> bool {
+        self.mode & libc::S_IFMT == libc::S_IFDIR
+    }
+
+    /// Is the inode a regular file?
+    #[inline]
+    pub fn is_file(&self) -> bool {
+        self.mode & libc::S_IFMT == libc::S_IFREG
+    }
+
+    /// Is the inode a symlink?
+    #[inline]
+    pub fn is_symlink(&self) -> bool {
+        self.mode & libc::S_IFMT == libc::S_IFLNK
+    }
+}
+
+/// Extended metadata about an inode referenced by a [`Handle`].
+///
+/// This is returned by [`Handle::statx`]. Fields which are not included in
+/// [`Statx::mask`] were not filled by the kernel and their values are
+/// meaningless, so accessors for optional fields return [`None`] if the field
+/// was not filled.
+#[derive(Clone, Copy)]
+pub struct Statx(RawStatx);
+
+impl std::fmt::Debug for Statx {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Statx")
+            .field("mask", &self.mask())
+            .field("attributes", &self.attributes())
+            .field("mode", &self.mode())
+            .field("ino", &self.ino())
+            .field("dev", &self.dev())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A raw timestamp (seconds and nanoseconds since the Unix epoch), as returned
+/// by the kernel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Timestamp {
+    pub(crate) sec: i64,
+    pub(crate) nsec: u32,
+}
+
+impl Timestamp {
+    fn to_system_time(self) -> SystemTime {
+        let nsecs = Duration::from_nanos(self.nsec.into());
+        if self.sec >= 0 {
+            SystemTime::UNIX_EPOCH + Duration::from_secs(self.sec as u64) + nsecs
+        } else {
+            SystemTime::UNIX_EPOCH - Duration::from_secs(self.sec.unsigned_abs()) + nsecs
+        }
+    }
+}
+
+impl From<&StatxTimestamp> for Timestamp {
+    fn from(ts: &StatxTimestamp) -> Self {
+        Self {
+            sec: ts.tv_sec,
+            nsec: ts.tv_nsec,
+        }
+    }
+}
+
+fn statx_timestamp(ts: &StatxTimestamp) -> SystemTime {
+    Timestamp::from(ts).to_system_time()
+}
+
+impl Statx {
+    /// The set of fields which were actually filled by the kernel.
+    #[inline]
+    pub fn mask(&self) -> StatxMask {
+        StatxMask::from_bits_retain(self.0.stx_mask)
+    }
+
+    fn has(&self, mask: StatxMask) -> bool {
+        self.mask().contains(mask)
+    }
+
+    /// The `STATX_ATTR_*` attributes of the inode.
+    ///
+    /// Only attributes included in [`Statx::attributes_mask`] are meaningful.
+    #[inline]
+    pub fn attributes(&self) -> StatxAttributes {
+        StatxAttributes::from_bits_retain(self.0.stx_attributes)
+    }
+
+    /// The set of `STATX_ATTR_*` attributes supported by the filesystem.
+    #[inline]
+    pub fn attributes_mask(&self) -> StatxAttributes {
+        StatxAttributes::from_bits_retain(self.0.stx_attributes_mask)
+    }
+
+    /// The file type and mode (`stx_mode`) of the inode.
+    #[inline]
+    pub fn mode(&self) -> u32 {
+        self.0.stx_mode.into()
+    }
+
+    /// The owner (`stx_uid`) of the inode.
+    #[inline]
+    pub fn uid(&self) -> u32 {
+        self.0.stx_uid
+    }
+
+    /// The group owner (`stx_gid`) of the inode.
+    #[inline]
+    pub fn gid(&self) -> u32 {
+        self.0.stx_gid
+    }
+
+    /// The number of hard links (`stx_nlink`) to the inode.
+    #[inline]
+    pub fn nlink(&self) -> u32 {
+        self.0.stx_nlink
+    }
+
+    /// The size (`stx_size`) of the inode in bytes.
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.0.stx_size
+    }
+
+    /// The inode number (`stx_ino`).
+    #[inline]
+    pub fn ino(&self) -> u64 {
+        self.0.stx_ino
+    }
+
+    /// The device (`stx_dev_major` and `stx_dev_minor`) containing the inode.
+    #[inline]
+    pub fn dev(&self) -> u64 {
+        rustix_fs::makedev(self.0.stx_dev_major, self.0.stx_dev_minor)
+    }
+
+    /// The creation time (`stx_btime`) of the inode, if it was filled.
+    #[inline]
+    pub fn btime(&self) -> Option<SystemTime> {
+        self.has(StatxMask::STATX_BTIME)
+            .then(|| statx_timestamp(&self.0.stx_btime))
+    }
+
+    /// The mount ID (`stx_mnt_id`) of the mount containing the inode, if it
+    /// was filled (either with `STATX_MNT_ID` or `STATX_MNT_ID_UNIQUE`).
+    #[inline]
+    pub fn mnt_id(&self) -> Option<u64> {
+        self.mask()
+            .intersects(StatxMask::STATX_MNT_ID | StatxMask::STATX_MNT_ID_UNIQUE)
+            .then_some(self.0.stx_mnt_id)
+    }
+}
+
+/// Borrowed version of [`Handle`].
+///
+/// Unlike [`Handle`], when [`HandleRef`] is dropped the underlying file
+/// descriptor is *not* closed. This is mainly useful for programs and libraries
+/// that have to do operations on [`&File`][File]s and [`BorrowedFd`]s passed
+/// from elsewhere.
+///
+/// [File]: std::fs::File
+// TODO: Is there any way we can restructure this to use Deref so that we don't
+//       need to copy all of the methods into Handle? Probably not... Maybe GATs
+//       will eventually support this but we'd still need a GAT-friendly Deref.
+#[derive(Copy, Clone, Debug)]
+pub struct HandleRef<'fd> {
+    inner: BorrowedFd<'fd>,
+}
+
+impl HandleRef<'_> {
+    /// Wrap a [`BorrowedFd`] into a [`HandleRef`].
+    pub fn from_fd(inner: BorrowedFd<'_>) -> HandleRef<'_> {
+        HandleRef { inner }
+    }
+
+    /// Create a copy of a [`HandleRef`].
+    ///
+    /// Note that (unlike [`BorrowedFd::clone`]) this method creates a full copy
+    /// of the underlying file descriptor and thus is more equivalent to
+    /// [`BorrowedFd::try_clone_to_owned`].
+    ///
+    /// To create a shallow copy of a [`HandleRef`], you can use
+    /// [`Clone::clone`] (or just [`Copy`]).
+    // TODO: We might need to call this something other than try_clone(), since
+    //       it's a little too easy to confuse with Clone::clone() but we also
+    //       really want to have Copy.
+    pub fn try_clone(&self) -> Result<Handle, Error> {
+        self.as_fd()
+            .try_clone_to_owned()
+            .map_err(|err| {
+                ErrorImpl::OsError {
+                    operation: "clone underlying handle file".into(),
+                    source: err,
+                }
+                .into()
+            })
+            .map(Handle::from_fd)
+    }
+
+    /// Create a copy of a [`HandleRef`], with the new file descriptor number
+    /// being at least `minfd`.
+    ///
+    /// See [`Handle::try_clone_min`] for more details.
+    pub fn try_clone_min(&self, minfd: RawFd) -> Result<Handle, Error> {
+        syscalls::fcntl_dupfd_cloexec(self.as_fd(), minfd)
+            .map_err(|err| {
+                ErrorImpl::RawOsError {
+                    operation: "clone underlying handle file above minimum fd".into(),
+                    source: err,
+                }
+                .into()
+            })
+            .map(Handle::from_fd)
+    }
+
+    /// "Upgrade" the handle to a usable [`File`] handle.
+    ///
+    /// This new [`File`] handle is suitable for reading and writing. This does
+    /// not consume the original handle (allowing for it to be used many times).
+    ///
+    /// The [`File`] handle will be opened with `O_NOCTTY` and `O_CLOEXEC` set,
+    /// regardless of whether those flags are present in the `flags` argument.
+    /// You can correct these yourself if these defaults are not ideal for you:
+    ///
+    /// 1. `fcntl(fd, F_SETFD, 0)` will let you unset `O_CLOEXEC`.
+    /// 2. `ioctl(fd, TIOCSCTTY, 0)` will set the fd as the controlling terminal
+    ///    (if you don't have one already, and the fd references a TTY).
+    ///
+    /// [`Root::create`]: crate::Root::create
+    #[doc(alias = "pathrs_reopen")]
+    pub fn reopen<F: Into<OpenFlags>>(&self, flags: F) -> Result<File, Error> {
+        self.inner
+            .reopen(&GLOBAL_PROCFS_HANDLE, flags.into())
+            .map(File::from)
+    }
+
+    /// "Upgrade" the handle to a usable [`File`] handle, using exactly the
+    /// provided `flags`.
+    ///
+    /// See [`Handle::reopen_raw`] for more details.
+    pub fn reopen_raw<F: Into<OpenFlags>>(&self, flags: F) -> Result<File, Error> {
+        self.inner
+            .reopen_raw(&GLOBAL_PROCFS_HANDLE, flags.into())
+            .map(File::from)
+    }
+
+    /// "Upgrade" the handle to a [`File`] handle opened for appending.
+    ///
+    /// This is shorthand for [`HandleRef::reopen`] with `O_WRONLY|O_APPEND`.
+    /// Since `O_APPEND` is set on the new file description, each write to the
+    /// returned [`File`] will atomically append to the end of the file (even if
+    /// other processes are also appending to the same file).
+    pub fn reopen_append(&self) -> Result<File, Error> {
+        self.reopen(OpenFlags::O_WRONLY | OpenFlags::O_APPEND)
+    }
+
+    /// "Upgrade" a set of handles to usable [`File`] handles.
+    ///
+    /// This is equivalent to calling [`HandleRef::reopen`] on each handle in
+    /// `handles` with the same `flags`. The returned results are in the same
+    /// order as `handles`, and an error re-opening one handle does not affect
+    /// the others.
+    ///
+    /// If the `io-uring` feature is enabled, the final `openat(2)` of each
+    /// `/proc/thread-self/fd/$n` magic-link is submitted in bulk using
+    /// `io_uring`, which is faster than re-opening each handle in turn. Every
+    /// handle still goes through the same `procfs` safety checks as
+    /// [`HandleRef::reopen`] before its `openat(2)` is submitted. If
+    /// `io_uring` is not available (it is not supported by the kernel or has
+    /// been disabled with the `kernel.io_uring_disabled` sysctl), or the
+    /// feature is not enabled, the handles are re-opened one at a time.
+    pub fn reopen_batch<F: Into<OpenFlags>>(
+        handles: &[HandleRef<'_>],
+        flags: F,
+    ) -> Vec<Result<File, Error>> {
+        let flags = flags.into();
+        #[cfg(feature = "io-uring")]
+        {
+            let fds: Vec<_> = handles.iter().map(|handle| handle.inner).collect();
+            if let Ok(files) = crate::utils::reopen_batch(&fds, &GLOBAL_PROCFS_HANDLE, flags) {
+                return files.into_iter().map(|file| file.map(File::from)).collect();
+            }
+        }
+        handles.iter().map(|handle| handle.reopen(flags)).collect()
+    }
+
+    /// Copy the contents of the file referenced by this [`HandleRef`] into
+    /// the file referenced by `dst`, reporting progress to `progress`.
+    ///
+    /// See [`Handle::copy_to_with_progress`] for more details.
+    pub fn copy_to_with_progress<F>(
+        &self,
+        dst: &HandleRef<'_>,
+        len: Option<u64>,
+        mut progress: F,
+    ) -> Result<u64, Error>
+    where
+        F: FnMut(u64) -> ControlFlow<()>,
+    {
+        let mut src = self
+            .reopen(OpenFlags::O_RDONLY)
+            .wrap("re-open copy source")?;
+        let mut dst = dst
+            .reopen(OpenFlags::O_WRONLY | OpenFlags::O_TRUNC)
+            .wrap("re-open copy destination")?;
+
+        let mut buf = vec![0u8; COPY_CHUNK_SIZE];
+        let mut copied = 0u64;
+        loop {
+            let want = match len {
+                Some(len) => usize::try_from(len - copied)
+                    .unwrap_or(usize::MAX)
+                    .min(buf.len()),
+                None => buf.len(),
+            };
+            if want == 0 {
+                break;
+            }
+            let n = match src.read(&mut buf[..want]) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == IOErrorKind::Interrupted => continue,
+                Err(err) => Err(ErrorImpl::OsError {
+                    operation: "read from copy source".into(),
+                    source: err,
+                })?,
+            };
+            dst.write_all(&buf[..n]).map_err(|err| ErrorImpl::OsError {
+                operation: "write to copy destination".into(),
+                source: err,
+            })?;
+            copied += n as u64;
+            if progress(copied).is_break() {
+                break;
+            }
+        }
+        Ok(copied)
+    }
+
+    /// Re-open the regular file referenced by this [`HandleRef`] so that it
+    /// can be mapped, returning the file and its size.
+    fn reopen_for_mmap(&self) -> Result<(File, u64), Error> {
+        let file = self
+            .reopen(OpenFlags::O_RDONLY)
+            .wrap("re-open handle for mapping")?;
+        let stat = syscalls::fstatat(&file, "").map_err(|err| ErrorImpl::RawOsError {
+            operation: "stat file to be mapped".into(),
+            source: err,
+        })?;
+        if FileType::from_raw_mode(stat.st_mode) != FileType::RegularFile {
+            Err(ErrorImpl::InvalidArgument {
+                name: "handle".into(),
+                description: "only regular files can be mapped".into(),
+            })?
+        }
+        Ok((file, stat.st_size as u64))
+    }
+
+    /// Create a private read-only memory mapping of the file referenced by
+    /// this [`HandleRef`].
+    ///
+    /// See [`Handle::mmap_private`] for more details.
+    pub fn mmap_private(&self) -> Result<Mmap, Error> {
+        let (file, len) = self.reopen_for_mmap()?;
+        let len = usize::try_from(len).map_err(|_| ErrorImpl::InvalidArgument {
+            name: "handle".into(),
+            description: "file is too large to be mapped".into(),
+        })?;
+
+        syscalls::mmap_private(&file, len)
+            .map(|mapping| Mmap { mapping })
+            .map_err(|err| {
+                ErrorImpl::RawOsError {
+                    operation: "create private mapping of file".into(),
+                    source: err,
+                }
+                .into()
+            })
+    }
+
+    /// Create a private read-only snapshot of the file referenced by this
+    /// [`HandleRef`], which must be at most `max_len` bytes long.
+    ///
+    /// See [`Handle::mmap_private_snapshot`] for more details.
+    pub fn mmap_private_snapshot(&self, max_len: u64) -> Result<Mmap, Error> {
+        let (mut file, len) = self.reopen_for_mmap()?;
+        if len > max_len {
+            Err(ErrorImpl::InvalidArgument {
+                name: "max_len".into(),
+                description: format!("file is larger than the snapshot limit ({len} > {max_len})")
+                    .into(),
+            })?
+        }
+        let len = usize::try_from(len).map_err(|_| ErrorImpl::InvalidArgument {
+            name: "handle".into(),
+            description: "file is too large to be mapped".into(),
+        })?;
+
+        let mut mapping = syscalls::mmap_anonymous(len).map_err(|err| ErrorImpl::RawOsError {
+            operation: "create anonymous mapping for snapshot".into(),
+            source: err,
+        })?;
+        file.read_exact(mapping.as_mut_slice())
+            .map_err(|err| ErrorImpl::OsError {
+                operation: "read file contents into snapshot".into(),
+                source: err,
+            })?;
+        mapping
+            .make_readonly()
+            .map_err(|err| ErrorImpl::RawOsError {
+                operation: "make snapshot read-only".into(),
+                source: err,
+            })?;
+        Ok(Mmap { mapping })
+    }
+
+    /// Check whether the underlying file descriptor is an `O_PATH` descriptor.
+    ///
+    /// All [`Handle`]s returned by libpathrs are `O_PATH` descriptors, but
+    /// [`HandleRef::from_fd`] will accept any file descriptor. This method lets
+    /// you verify that a file descriptor you received from elsewhere is
+    /// actually an `O_PATH` handle.
+    pub fn is_opath(&self) -> Result<bool, Error> {
+        let flags = syscalls::fcntl_getfl(self).map_err(|err| ErrorImpl::RawOsError {
+            operation: "get handle file status flags".into(),
+            source: err,
+        })?;
+        Ok(flags.contains(OpenFlags::O_PATH))
+    }
+
+    /// Get the [`Metadata`] of the inode referenced by this [`HandleRef`].
+    ///
+    /// See [`Handle::stat`] for more details.
+    pub fn stat(&self) -> Result<Metadata, Error> {
+        let mask = StatxFlags::BASIC_STATS | StatxFlags::BTIME;
+        match syscalls::statx(self, "", mask) {
+            Ok(stx) => Ok(Metadata::from_statx(&stx)),
+            // Kernels older than Linux 4.11 don't have statx(2), so fall back
+            // to fstatat(2). Any other error is a real failure that must be
+            // returned as-is.
+            Err(err) if matches!(err.errno(), Errno::NOSYS | Errno::OPNOTSUPP) => {
+                syscalls::fstatat(self, "")
+                    .map(|stat| Metadata::from_stat(&stat))
+                    .map_err(|err| {
+                        ErrorImpl::RawOsError {
+                            operation: "stat handle".into(),
+                            source: err,
+                        }
+                        .into()
+                    })
+            }
+            Err(err) => Err(ErrorImpl::RawOsError {
+                operation: "statx handle".into(),
+                source: err,
+            })?,
+        }
+    }
+
+    /// Get the creation time (`btime`) of the inode referenced by this
+    /// [`HandleRef`].
+    ///
+    /// See [`Handle::created`] for more details.
+    pub fn created(&self) -> Result<Option<SystemTime>, Error> {
+        match self.statx(StatxMask::STATX_BTIME) {
+            Ok(stx) => Ok(stx.btime()),
+            Err(err) if err.kind() == ErrorKind::NotSupported => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Get the extended [`Statx`] metadata of the inode referenced by this
+    /// [`HandleRef`], using [`statx(2)`].
+    ///
+    /// See [`Handle::statx`] for more details.
+    ///
+    /// [`statx(2)`]: http://man7.org/linux/man-pages/man2/statx.2.html
+    pub fn statx(&self, mask: StatxMask) -> Result<Statx, Error> {
+        syscalls::statx(self, "", mask.into())
+            .map(Statx)
+            .map_err(|err| {
+                if err.root_cause().raw_os_error() == Some(libc::ENOSYS) {
+                    ErrorImpl::NotSupported {
+                        feature: "statx".into(),
+                    }
+                } else {
+                    ErrorImpl::RawOsError {
+                        operation: "statx handle".into(),
+                        source: err,
+                    }
+                }
+                .into()
+            })
+    }
+
+    /// Get the target of the symlink referenced by this [`HandleRef`].
+    ///
+    /// See [`Handle::readlink`] for more details.
+    pub fn readlink(&self) -> Result<PathBuf, Error> {
+        syscalls::readlinkat(self, "").or_else(|err| self.readlink_error(err))
+    }
+
+    /// Read the target of the symlink referenced by this [`HandleRef`] into
+    /// `buf`, returning its length.
+    ///
+    /// See [`Handle::readlink_into`] for more details.
+    pub fn readlink_into(&self, buf: &mut Vec<u8>) -> Result<usize, Error> {
+        syscalls::readlinkat_into(self, "", buf).or_else(|err| self.readlink_error(err))
+    }
+
+    /// Convert an error from `readlinkat(fd, "")` into the error returned by
+    /// [`HandleRef::readlink`].
+    fn readlink_error<T>(&self, err: syscalls::Error) -> Result<T, Error> {
+        // readlinkat(fd, "") on a non-symlink returns ENOENT, which is quite
+        // confusing. Return EINVAL like readlink(2) would.
+        if !self.metadata()?.is_symlink() {
+            Err(ErrorImpl::OsError {
+                operation: "readlink handle".into(),
+                source: IOError::from_raw_os_error(libc::EINVAL),
+            })
+            .wrap("handle is not a symlink")?
+        }
+        Err(ErrorImpl::RawOsError {
+            operation: "readlink handle".into(),
+            source: err,
+        })?
+    }
+
+    /// Run an xattr operation on this handle through its magic-link in the `fd`
+    /// directory of [`GLOBAL_PROCFS_HANDLE`].
+    ///
+    /// `at` is given the `fd` directory and the (relative) `$n` path of the
+    /// handle's magic-link in that directory, and should use one of the
+    /// `*xattrat(2)` syscalls. If those are not supported by the kernel, the
+    /// path-based `fallback` is used instead. Path-based syscalls always start
+    /// from the current directory (or `/`), so `fallback` is run on a separate
+    /// thread with its own filesystem information (`unshare(CLONE_FS)`) whose
+    /// current directory is the `fd` directory.
+    fn xattr_via_magiclink<T, F, G>(&self, operation: &str, at: F, fallback: G) -> Result<T, Error>
+    where
+        T: Send,
+        F: FnOnce(BorrowedFd<'_>, &Path) -> Result<T, syscalls::Error>,
+        G: FnOnce(&Path) -> Result<T, syscalls::Error> + Send,
+    {
+        let err = |err| ErrorImpl::RawOsError {
+            operation: operation.to_owned().into(),
+            source: err,
+        };
+        let open_fd_dir = || {
+            GLOBAL_PROCFS_HANDLE
+                .open(
+                    ProcfsBase::ProcThreadSelf,
+                    "fd",
+                    OpenFlags::O_PATH | OpenFlags::O_DIRECTORY,
+                )
+                .wrap("open procfs fd directory")
+        };
+        // The file descriptor table is shared by all threads, so the $n for
+        // our handle is the same in every thread's fd directory.
+        let magiclink = PathBuf::from(self.as_fd().as_raw_fd().to_string());
+
+        match at(open_fd_dir()?.as_fd(), &magiclink) {
+            Err(err) if err.errno() == Errno::NOSYS => {}
+            res => return res.map_err(|e| err(e).into()),
+        }
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                syscalls::unshare(UnshareFlags::FS).map_err(|err| ErrorImpl::RawOsError {
+                    operation: "unshare filesystem information".into(),
+                    source: err,
+                })?;
+                syscalls::fchdir(open_fd_dir()?).map_err(|err| ErrorImpl::RawOsError {
+                    operation: "change directory to procfs fd directory".into(),
+                    source: err,
+                })?;
+                fallback(&magiclink).map_err(|e| err(e).into())
+            })
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+
+    /// Get the value of the extended attribute `name` of the inode referenced
+    /// by this [`HandleRef`].
+    ///
+    /// See [`Handle::getxattr`] for more details.
+    pub fn getxattr(&self, name: &OsStr) -> Result<Vec<u8>, Error> {
+        self.xattr_via_magiclink(
+            "get handle xattr",
+            |dirfd, path| syscalls::getxattrat(dirfd, path, name),
+            |path| syscalls::getxattr(path, name),
+        )
+    }
+
+    /// Set the value of the extended attribute `name` of the inode referenced
+    /// by this [`HandleRef`], creating it if it does not exist.
+    ///
+    /// See [`Handle::getxattr`] for more details.
+    pub fn setxattr(&self, name: &OsStr, value: &[u8]) -> Result<(), Error> {
+        self.xattr_via_magiclink(
+            "set handle xattr",
+            |dirfd, path| syscalls::setxattrat(dirfd, path, name, value, XattrFlags::empty()),
+            |path| syscalls::setxattr(path, name, value, XattrFlags::empty()),
+        )
+    }
+
+    /// Remove the extended attribute `name` from the inode referenced by this
+    /// [`HandleRef`].
+    ///
+    /// See [`Handle::getxattr`] for more details.
+    pub fn removexattr(&self, name: &OsStr) -> Result<(), Error> {
+        self.xattr_via_magiclink(
+            "remove handle xattr",
+            |dirfd, path| syscalls::removexattrat(dirfd, path, name),
+            |path| syscalls::removexattr(path, name),
+        )
+    }
+
+    /// List the names of the extended attributes of the inode referenced by
+    /// this [`HandleRef`].
+    ///
+    /// See [`Handle::getxattr`] for more details.
+    pub fn listxattr(&self) -> Result<Vec<OsString>, Error> {
+        self.xattr_via_magiclink(
+            "list handle xattrs",
+            |dirfd, path| syscalls::listxattrat(dirfd, path),
+            |path| syscalls::listxattr(path),
+        )
+    }
+
+    /// Enable fs-verity on the regular file referenced by this [`HandleRef`].
+    ///
+    /// See [`Handle::fsverity_enable`] for more details.
+    pub fn fsverity_enable(&self, params: &FsVerityParams) -> Result<(), Error> {
+        let file = self
+            .reopen(OpenFlags::O_RDONLY)
+            .wrap("reopen handle to enable fs-verity")?;
+        syscalls::fsverity_enable(
+            &file,
+            params.hash_algorithm.as_raw().into(),
+            params.block_size,
+            &params.salt,
+        )
+        .map_err(|err| {
+            ErrorImpl::RawOsError {
+                operation: "enable fs-verity".into(),
+                source: err,
+            }
+            .into()
+        })
+    }
+
+    /// Get the fs-verity digest of the file referenced by this [`HandleRef`].
+    ///
+    /// See [`Handle::fsverity_measure`] for more details.
+    pub fn fsverity_measure(&self) -> Result<FsVerityDigest, Error> {
+        let file = self
+            .reopen(OpenFlags::O_RDONLY)
+            .wrap("reopen handle to measure fs-verity digest")?;
+        let (alg, digest) =
+            syscalls::fsverity_measure(&file).map_err(|err| ErrorImpl::RawOsError {
+                operation: "measure fs-verity digest".into(),
+                source: err,
+            })?;
+        let algorithm =
+            FsVerityHashAlgorithm::from_raw(alg).ok_or_else(|| ErrorImpl::NotSupported {
+                feature: format!("fs-verity hash algorithm {alg}").into(),
+            })?;
+        Ok(FsVerityDigest { algorithm, digest })
+    }
+
+    /// Execute the inode referenced by this [`HandleRef`] using
+    /// [`execveat(2)`] with `AT_EMPTY_PATH`.
+    ///
+    /// See [`Handle::execveat`] for more details.
+    ///
+    /// [`execveat(2)`]: http://man7.org/linux/man-pages/man2/execveat.2.html
+    pub fn execveat(
+        &self,
+        argv: &[CString],
+        envp: &[CString],
+        flags: i32,
+    ) -> Result<Infallible, Error> {
+        // execveat(2) on an O_CLOEXEC file descriptor fails for scripts (the
+        // interpreter cannot open /dev/fd/$n after the exec), so we need to use
+        // a copy without O_CLOEXEC.
+        let fd = syscalls::dup(self.as_fd()).map_err(|err| ErrorImpl::RawOsError {
+            operation: "dup handle for execveat".into(),
+            source: err,
+        })?;
+        let flags = AtFlags::from_bits_retain(flags as _) | AtFlags::EMPTY_PATH;
+
+        let err = match syscalls::execveat(&fd, "", argv, envp, flags) {
+            Ok(infallible) => match infallible {},
+            Err(err) => err,
+        };
+        let context = match err.errno() {
+            Errno::TXTBSY => "executable is currently open for writing",
+            Errno::NOEXEC => "executable is not in a recognised format",
+            _ => "execute handle",
+        };
+        Err(ErrorImpl::RawOsError {
+            operation: "execveat handle".into(),
+            source: err,
+        }
+        .wrap(context))?
+    }
+
+    /// Get a copy of the file descriptor of this [`HandleRef`] together with a
+    /// [`HandleInfo`] describing it.
+    ///
+    /// See [`Handle::to_transfer`] for more details.
+    pub fn to_transfer(&self) -> Result<(OwnedFd, HandleInfo), Error> {
+        let info = self.info()?;
+        let fd = self.try_clone()?;
+        Ok((fd.into(), info))
+    }
+
+    /// Get a [`RevalToken`] describing the current state of the inode
+    /// referenced by this [`HandleRef`].
+    ///
+    /// See [`Handle::revalidation_token`] for more details.
+    pub fn revalidation_token(&self) -> Result<RevalToken, Error> {
+        self.stat().map(|meta| RevalToken::from(&meta))
+    }
+
+    /// Check whether the inode referenced by this [`HandleRef`] still matches
+    /// the state captured in `token`.
+    ///
+    /// See [`Handle::is_still_valid`] for more details.
+    pub fn is_still_valid(&self, token: &RevalToken) -> Result<bool, Error> {
+        let current = self.revalidation_token()?;
+        Ok(current.nlink > 0 && current == *token)
+    }
+
+    fn info(&self) -> Result<HandleInfo, Error> {
+        let meta = self.stat()?;
+        let flags = syscalls::fcntl_getfl(self).map_err(|err| ErrorImpl::RawOsError {
+            operation: "get handle file status flags".into(),
+            source: err,
+        })?;
+        Ok(HandleInfo {
+            dev: meta.dev(),
+            ino: meta.ino(),
+            mtime_sec: meta.mtime.sec,
+            mtime_nsec: meta.mtime.nsec,
+            flags: flags.bits(),
+        })
+    }
+
+    // TODO: All the other stat* interfaces?
+
+    // TODO: bind(). This might be safe to do (set the socket path to
+    //       /proc/self/fd/...) but I'm a bit sad it'd be separate from
+    //       Handle::reopen().
+}
+
+impl<'fd> From<BorrowedFd<'fd>> for HandleRef<'fd> {
+    /// Shorthand for [`HandleRef::from_fd`].
+    fn from(fd: BorrowedFd<'fd>) -> Self {
+        Self::from_fd(fd)
+    }
+}
+
+impl AsFd for HandleRef<'_> {
+    /// Access the underlying file descriptor for a [`HandleRef`].
+    ///
+    /// **Note**: This method is primarily intended to allow for tests and other
+    /// code to check the status of the underlying file descriptor. It is not
+    /// safe to use this [`BorrowedFd`] directly to do filesystem operations.
+    /// Please use the provided [`HandleRef`] methods.
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::ErrorKind,
+        flags::{OpenFlags, StatxAttributes, StatxMask},
+        kernel::{self, KernelFeatures},
+        syscalls, FsVerityHashAlgorithm, FsVerityParams, Handle, HandleRef, Root,
+    };
+
+    use std::{
+        ffi::OsStr,
+        fs::File,
+        io::{Read, Write},
+        ops::ControlFlow,
+        os::unix::{
+            ffi::OsStrExt,
+            fs::MetadataExt,
+            io::{AsFd, AsRawFd, OwnedFd},
+            net::UnixStream,
+        },
+        thread,
+        time::{Duration, SystemTime},
+    };
+
+    use anyhow::Error;
+    use pretty_assertions::assert_eq;
+    use rustix::io::Errno;
+
+    #[test]
+    fn from_fd() -> Result<(), Error> {
+        let handle = Root::open(".")?.resolve(".")?;
+        let handle_ref1 = handle.as_ref();
+        let handle_ref2 = HandleRef::from_fd(handle.as_fd());
+
+        assert_eq!(
+            handle.as_fd().as_raw_fd(),
+            handle_ref1.as_fd().as_raw_fd(),
             "Handle::as_ref should have the same underlying fd"
         );
         assert_eq!(
-            handle.as_fd().as_raw_fd(),
-            handle_ref2.as_fd().as_raw_fd(),
-            "HandleRef::from_fd should have the same underlying fd"
+            handle.as_fd().as_raw_fd(),
+            handle_ref2.as_fd().as_raw_fd(),
+            "HandleRef::from_fd should have the same underlying fd"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_fd_checked() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"")?;
+        std::os::unix::fs::symlink("file", dir.path().join("link"))?;
+        let root = Root::open(&dir)?;
+
+        for handle in [
+            root.resolve(".")?,
+            root.resolve("file")?,
+            root.resolve_nofollow("link")?,
+        ] {
+            let meta = handle.stat()?;
+            let handle = Handle::from_fd_checked(OwnedFd::from(handle))?;
+            assert_eq!(
+                handle.stat()?,
+                meta,
+                "Handle::from_fd_checked should accept resolved handle"
+            );
+        }
+
+        // Not an O_PATH descriptor.
+        assert_eq!(
+            Handle::from_fd_checked(File::open(dir.path().join("file"))?)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::InvalidArgument),
+            "Handle::from_fd_checked should reject a non-O_PATH file"
+        );
+        let (sock, _) = UnixStream::pair()?;
+        assert_eq!(
+            Handle::from_fd_checked(sock)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::InvalidArgument),
+            "Handle::from_fd_checked should reject a socket"
+        );
+
+        // An O_PATH descriptor to an unexpected inode type.
+        let devnull = Root::open("/dev")?.resolve("null")?;
+        assert_eq!(
+            Handle::from_fd_checked(devnull)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::InvalidArgument),
+            "Handle::from_fd_checked should reject a device node"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn try_clone_min() -> Result<(), Error> {
+        let handle = Root::open(".")?.resolve(".")?;
+
+        for minfd in [0, 100, 1000] {
+            let cloned = handle.try_clone_min(minfd)?;
+            assert!(
+                cloned.as_fd().as_raw_fd() >= minfd,
+                "Handle::try_clone_min({minfd}) returned fd {}",
+                cloned.as_fd().as_raw_fd()
+            );
+            assert_eq!(
+                cloned.stat()?,
+                handle.stat()?,
+                "Handle::try_clone_min should reference the same inode"
+            );
+            assert!(
+                cloned.is_opath()?,
+                "Handle::try_clone_min should share the O_PATH file description"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn into_root() -> Result<(), Error> {
+        use crate::utils::FdExt;
+
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("sub/dir"))?;
+        std::fs::create_dir_all(dir.path().join("secret"))?;
+        std::fs::write(dir.path().join("file"), b"")?;
+        std::os::unix::fs::symlink("/secret", dir.path().join("sub/abs-link"))?;
+        std::os::unix::fs::symlink("../../secret", dir.path().join("sub/rel-link"))?;
+
+        let root = Root::open(&dir)?;
+        let real_root = root.as_unsafe_path_unchecked()?;
+
+        let subroot = root.resolve("sub")?.into_root()?;
+        assert_eq!(
+            subroot.as_unsafe_path_unchecked()?,
+            real_root.join("sub"),
+            "Handle::into_root should reference the handle's directory"
+        );
+        // ".." and symlinks are confined to the new root.
+        for (path, expected) in [
+            ("..", "sub"),
+            ("../../dir/..", "sub"),
+            ("../dir", "sub/dir"),
+        ] {
+            assert_eq!(
+                subroot.resolve(path)?.as_unsafe_path_unchecked()?,
+                real_root.join(expected),
+                "resolve({path:?}) in new root should not escape it"
+            );
+        }
+        for path in ["../secret", "abs-link", "rel-link"] {
+            assert_eq!(
+                subroot.resolve(path).map(|_| ()).map_err(|err| err.kind()),
+                Err(ErrorKind::OsError(Some(libc::ENOENT))),
+                "resolve({path:?}) in new root should not reach the original root"
+            );
+        }
+
+        // Non-directories cannot be roots.
+        assert_eq!(
+            root.resolve("file")?
+                .into_root()
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::InvalidArgument),
+            "Handle::into_root of non-directory should fail"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn is_opath() -> Result<(), Error> {
+        let handle = Root::open(".")?.resolve(".")?;
+        assert!(
+            handle.is_opath()?,
+            "resolved handle should be an O_PATH descriptor"
+        );
+        assert!(
+            handle.as_ref().is_opath()?,
+            "resolved handle ref should be an O_PATH descriptor"
+        );
+
+        let file = File::open(".")?;
+        let handle = Handle::from_fd(file);
+        assert!(
+            !handle.is_opath()?,
+            "regular file handle should not be an O_PATH descriptor"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn revalidation_token() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("a"), b"foo")?;
+        std::fs::write(dir.path().join("b"), b"bar")?;
+        let root = Root::open(&dir)?;
+        let handle = root.resolve("a")?;
+
+        let token = handle.revalidation_token()?;
+        assert!(
+            handle.is_still_valid(&token)?,
+            "unmodified handle should still be valid"
+        );
+        assert!(
+            root.resolve("a")?.is_still_valid(&token)?,
+            "another handle to the same unmodified inode should be valid"
+        );
+        assert!(
+            !root.resolve("b")?.is_still_valid(&token)?,
+            "token should not be valid for a different inode"
+        );
+
+        // Status change times only have timer tick granularity on older
+        // kernels, so make sure the modification happens in a later tick.
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        std::fs::write(dir.path().join("a"), b"modified")?;
+        assert!(
+            !handle.is_still_valid(&token)?,
+            "modified handle should no longer be valid"
+        );
+
+        let token = handle.revalidation_token()?;
+        assert!(
+            handle.is_still_valid(&token)?,
+            "new token for modified handle should be valid"
+        );
+        std::fs::remove_file(dir.path().join("a"))?;
+        assert!(
+            !handle.is_still_valid(&token)?,
+            "unlinked handle should no longer be valid"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn transfer() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("a"), b"foo")?;
+        std::fs::write(dir.path().join("b"), b"bar")?;
+        let root = Root::open(&dir)?;
+        let handle = root.resolve("a")?;
+        let meta = handle.stat()?;
+
+        let (fd, info) = handle.to_transfer()?;
+        assert_eq!(
+            (info.dev, info.ino),
+            (meta.dev(), meta.ino()),
+            "HandleInfo should describe the handle's inode"
+        );
+        let received = Handle::from_transfer(fd, info)?;
+        assert_eq!(
+            received.stat()?,
+            meta,
+            "Handle::from_transfer should accept an unmodified handle"
+        );
+
+        // A different inode.
+        assert_eq!(
+            Handle::from_transfer(root.resolve("b")?, info)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::SafetyViolation),
+            "Handle::from_transfer should reject a substituted inode"
+        );
+
+        // The same inode, but not an O_PATH descriptor.
+        assert_eq!(
+            Handle::from_transfer(root.open_subpath("a", OpenFlags::O_RDONLY)?, info)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::SafetyViolation),
+            "Handle::from_transfer should reject a different file description"
+        );
+
+        // The same inode, modified after the transfer.
+        let (fd, info) = handle.to_transfer()?;
+        rustix::fs::utimensat(
+            rustix::fs::CWD,
+            dir.path().join("a"),
+            &rustix::fs::Timestamps {
+                last_access: rustix::fs::Timespec {
+                    tv_sec: 1,
+                    tv_nsec: 0,
+                },
+                last_modification: rustix::fs::Timespec {
+                    tv_sec: 1,
+                    tv_nsec: 0,
+                },
+            },
+            rustix::fs::AtFlags::empty(),
+        )?;
+        assert_eq!(
+            Handle::from_transfer(fd, info)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::SafetyViolation),
+            "Handle::from_transfer should reject a modified inode"
+        );
+
+        Ok(())
+    }
+
+    /// Check that the results of a batch re-open of `handle_refs` match those
+    /// of [`HandleRef::reopen`] on each handle.
+    fn check_reopen_batch(
+        handle_refs: &[HandleRef<'_>],
+        files: Vec<Result<File, crate::error::Error>>,
+    ) -> Result<(), Error> {
+        assert_eq!(
+            files.len(),
+            handle_refs.len(),
+            "reopen_batch should return one result per handle"
+        );
+        for (handle, file) in handle_refs.iter().zip(files) {
+            match (handle.reopen(OpenFlags::O_RDONLY), file) {
+                (Ok(mut want), Ok(mut got)) => {
+                    let (mut want_buf, mut got_buf) = (String::new(), String::new());
+                    want.read_to_string(&mut want_buf)?;
+                    got.read_to_string(&mut got_buf)?;
+                    assert_eq!(
+                        got_buf, want_buf,
+                        "reopen_batch should give the same file as reopen"
+                    );
+                }
+                (Err(want), Err(got)) => assert_eq!(
+                    got.kind(),
+                    want.kind(),
+                    "reopen_batch should give the same error as reopen"
+                ),
+                (want, got) => panic!("reopen_batch gave {got:?} but reopen gave {want:?}"),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reopen_batch() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        for (name, contents) in [("a", "foo"), ("b", "bar"), ("c", "baz")] {
+            std::fs::write(dir.path().join(name), contents)?;
+        }
+
+        let root = Root::open(&dir)?;
+        let handles = ["a", "b", "c"]
+            .iter()
+            .map(|name| root.resolve(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let handle_refs = handles.iter().map(Handle::as_ref).collect::<Vec<_>>();
+        // Add a symlink handle, which cannot be re-opened.
+        let symlink_dir = tempfile::TempDir::new()?;
+        std::os::unix::fs::symlink("a", symlink_dir.path().join("link"))?;
+        let symlink = Root::open(&symlink_dir)?.resolve_nofollow("link")?;
+        let handle_refs = handle_refs
+            .into_iter()
+            .chain(std::iter::once(symlink.as_ref()))
+            .collect::<Vec<_>>();
+
+        let files = HandleRef::reopen_batch(&handle_refs, OpenFlags::O_RDONLY);
+        check_reopen_batch(&handle_refs, files)
+    }
+
+    #[cfg(feature = "io-uring")]
+    #[test]
+    fn reopen_batch_io_uring() -> Result<(), Error> {
+        use crate::{procfs::GLOBAL_PROCFS_HANDLE, utils};
+
+        // More handles than fit in a single io_uring submission.
+        const COUNT: usize = 600;
+
+        let dir = tempfile::TempDir::new()?;
+        for idx in 0..COUNT {
+            std::fs::write(dir.path().join(format!("file{idx}")), format!("{idx}"))?;
+        }
+        std::os::unix::fs::symlink("file0", dir.path().join("link"))?;
+
+        let root = Root::open(&dir)?;
+        let mut handles = (0..COUNT)
+            .map(|idx| root.resolve(format!("file{idx}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        handles.insert(COUNT / 2, root.resolve_nofollow("link")?);
+        let handle_refs = handles.iter().map(Handle::as_ref).collect::<Vec<_>>();
+        let fds = handle_refs
+            .iter()
+            .map(|handle| handle.inner)
+            .collect::<Vec<_>>();
+
+        let files = match utils::reopen_batch(&fds, &GLOBAL_PROCFS_HANDLE, OpenFlags::O_RDONLY) {
+            Ok(files) => files,
+            // io_uring may be disabled on this system.
+            Err(err) => {
+                println!("skipping io_uring reopen_batch test: {err}");
+                return Ok(());
+            }
+        };
+        let files: Vec<_> = files.into_iter().map(|file| file.map(File::from)).collect();
+        for file in files.iter().flatten() {
+            assert!(
+                !HandleRef::from_fd(file.as_fd()).is_opath()?,
+                "batch re-opened files are not O_PATH"
+            );
+        }
+        check_reopen_batch(&handle_refs, files)
+    }
+
+    #[test]
+    fn stat() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"some data")?;
+        std::fs::create_dir(dir.path().join("dir"))?;
+        std::os::unix::fs::symlink("file", dir.path().join("link"))?;
+        let root = Root::open(dir.path())?;
+
+        for (path, nofollow) in [
+            ("file", false),
+            ("dir", false),
+            ("link", true),
+            ("link", false),
+        ] {
+            let handle = if nofollow {
+                root.resolve_nofollow(path)?
+            } else {
+                root.resolve(path)?
+            };
+            let meta = handle.stat()?;
+            let std_meta = if nofollow {
+                std::fs::symlink_metadata(dir.path().join(path))?
+            } else {
+                std::fs::metadata(dir.path().join(path))?
+            };
+
+            assert_eq!(meta.mode(), std_meta.mode(), "mode of {path:?} must match");
+            assert_eq!(meta.uid(), std_meta.uid(), "uid of {path:?} must match");
+            assert_eq!(meta.gid(), std_meta.gid(), "gid of {path:?} must match");
+            assert_eq!(meta.size(), std_meta.size(), "size of {path:?} must match");
+            assert_eq!(meta.ino(), std_meta.ino(), "ino of {path:?} must match");
+            assert_eq!(meta.dev(), std_meta.dev(), "dev of {path:?} must match");
+            assert_eq!(
+                meta.nlink(),
+                std_meta.nlink(),
+                "nlink of {path:?} must match"
+            );
+            assert_eq!(
+                meta.modified(),
+                std_meta.modified()?,
+                "mtime of {path:?} must match"
+            );
+            assert_eq!(
+                meta.changed(),
+                SystemTime::UNIX_EPOCH
+                    + Duration::new(std_meta.ctime() as u64, std_meta.ctime_nsec() as u32),
+                "ctime of {path:?} must match"
+            );
+            assert_eq!(
+                meta.is_dir(),
+                std_meta.is_dir(),
+                "is_dir of {path:?} must match"
+            );
+            assert_eq!(
+                meta.is_file(),
+                std_meta.is_file(),
+                "is_file of {path:?} must match"
+            );
+            assert_eq!(
+                meta.is_symlink(),
+                std_meta.file_type().is_symlink(),
+                "is_symlink of {path:?} must match"
+            );
+            assert_eq!(handle.as_ref().stat()?, meta, "HandleRef::stat must match");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn stat_statx_errors() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"some data")?;
+        let handle = Root::open(dir.path())?.resolve("file")?;
+        let meta = handle.stat()?;
+
+        // Without statx(2) (or if the filesystem doesn't support it), stat()
+        // falls back to fstatat(2).
+        for errno in [Errno::NOSYS, Errno::OPNOTSUPP] {
+            let fallback = syscalls::hooks::with_injected_statx_errno(errno, || handle.stat())?;
+            assert_eq!(fallback.ino(), meta.ino(), "stat fallback ({errno:?})");
+            assert_eq!(fallback.size(), meta.size(), "stat fallback ({errno:?})");
+        }
+
+        // Any other statx(2) error is returned unchanged.
+        for errno in [Errno::ACCESS, Errno::IO, Errno::BADF] {
+            let err = syscalls::hooks::with_injected_statx_errno(errno, || handle.stat())
+                .expect_err("stat with a failing statx should fail");
+            assert_eq!(
+                err.kind(),
+                ErrorKind::OsError(Some(errno.raw_os_error())),
+                "stat should return the statx error ({errno:?})"
+            );
+            let desc = format!("{:#}", anyhow::Error::from(err));
+            assert!(
+                desc.contains("statx"),
+                "stat error should come from statx ({errno:?}): {desc}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn statx() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"some data")?;
+        let root = Root::open(dir.path())?;
+
+        let handle = root.resolve("file")?;
+        let mask = StatxMask::STATX_BASIC_STATS | StatxMask::STATX_BTIME | StatxMask::STATX_MNT_ID;
+        let stx = match handle.statx(mask) {
+            Err(err) if err.kind() == ErrorKind::NotSupported => return Ok(()), // skip
+            stx => stx?,
+        };
+        let std_meta = std::fs::metadata(dir.path().join("file"))?;
+
+        assert!(
+            stx.mask().contains(StatxMask::STATX_BASIC_STATS),
+            "kernel should fill in all basic stats (got {:?})",
+            stx.mask()
+        );
+        assert_eq!(stx.mode(), std_meta.mode(), "mode must match");
+        assert_eq!(stx.uid(), std_meta.uid(), "uid must match");
+        assert_eq!(stx.gid(), std_meta.gid(), "gid must match");
+        assert_eq!(stx.size(), std_meta.size(), "size must match");
+        assert_eq!(stx.ino(), std_meta.ino(), "ino must match");
+        assert_eq!(stx.dev(), std_meta.dev(), "dev must match");
+        assert_eq!(u64::from(stx.nlink()), std_meta.nlink(), "nlink must match");
+        assert_eq!(
+            stx.btime(),
+            std_meta.created().ok(),
+            "btime must match std::fs::Metadata::created"
+        );
+        assert_eq!(
+            stx.mnt_id().is_some(),
+            stx.mask().contains(StatxMask::STATX_MNT_ID),
+            "mnt_id should only be returned if filled"
+        );
+        assert!(!stx
+            .attributes()
+            .intersects(StatxAttributes::STATX_ATTR_MOUNT_ROOT));
+
+        // The root of the mount table is definitely a mount root.
+        let root_handle = Root::open("/")?.resolve("/")?;
+        let stx = root_handle.as_ref().statx(StatxMask::STATX_TYPE)?;
+        if stx
+            .attributes_mask()
+            .contains(StatxAttributes::STATX_ATTR_MOUNT_ROOT)
+        {
+            assert!(
+                stx.attributes()
+                    .contains(StatxAttributes::STATX_ATTR_MOUNT_ROOT),
+                "/ should be a mount root"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn readlink() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"")?;
+        std::fs::create_dir(dir.path().join("dir"))?;
+        let long_target = "a/".repeat(2000) + "b";
+        let odd_target = OsStr::from_bytes(b"\xff\xfe/../\x01 //.");
+        for (name, target) in [
+            ("rel", OsStr::new("file")),
+            ("abs", OsStr::new("/dir/../file")),
+            ("dangling", OsStr::new("does/not/exist")),
+            ("long", OsStr::new(&long_target)),
+            ("odd", odd_target),
+        ] {
+            std::os::unix::fs::symlink(target, dir.path().join(name))?;
+        }
+        let root = Root::open(dir.path())?;
+
+        for (name, target) in [
+            ("rel", OsStr::new("file")),
+            ("abs", OsStr::new("/dir/../file")),
+            ("dangling", OsStr::new("does/not/exist")),
+            ("long", OsStr::new(&long_target)),
+            ("odd", odd_target),
+        ] {
+            let handle = root.resolve_nofollow(name)?;
+            assert_eq!(
+                handle.readlink()?.as_os_str(),
+                target,
+                "readlink of symlink {name:?}"
+            );
+            assert_eq!(handle.as_ref().readlink()?.as_os_str(), target);
+        }
+
+        for name in ["file", "dir", "rel"] {
+            let handle = root.resolve(name)?;
+            assert_eq!(
+                handle.readlink().map_err(|err| err.kind()),
+                Err(ErrorKind::OsError(Some(libc::EINVAL))),
+                "readlink of non-symlink {name:?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn readlink_into() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"")?;
+        std::fs::create_dir(dir.path().join("dir"))?;
+        let long_target = "a/".repeat(2000) + "b";
+        let names = ["rel", "abs", "long", "odd", "short"];
+        for (name, target) in names.iter().zip([
+            OsStr::new("file"),
+            OsStr::new("/dir/../file"),
+            OsStr::new(&long_target),
+            OsStr::from_bytes(b"\xff\xfe/../\x01 //."),
+            OsStr::new("x"),
+        ]) {
+            std::os::unix::fs::symlink(target, dir.path().join(name))?;
+        }
+        let root = Root::open(dir.path())?;
+
+        // Start with some garbage in the buffer to make sure it is cleared.
+        let mut buf = b"garbage".to_vec();
+        let mut capacity = None;
+        // Go through the symlinks twice to make sure that the buffer is only
+        // grown when needed.
+        for name in names.iter().chain(names.iter()) {
+            let handle = root.resolve_nofollow(name)?;
+            let expected = handle.readlink()?;
+            let len = handle.readlink_into(&mut buf)?;
+            assert_eq!(
+                (len, OsStr::from_bytes(&buf)),
+                (expected.as_os_str().len(), expected.as_os_str()),
+                "readlink_into of symlink {name:?} should match readlink"
+            );
+            assert_eq!(
+                handle.as_ref().readlink_into(&mut buf)?,
+                len,
+                "HandleRef::readlink_into of symlink {name:?}"
+            );
+            assert_eq!(OsStr::from_bytes(&buf), expected.as_os_str());
+
+            let capacity = *capacity.get_or_insert(buf.capacity());
+            assert_eq!(
+                buf.capacity(),
+                capacity,
+                "readlink_into of symlink {name:?} should re-use the buffer"
+            );
+        }
+
+        for name in ["file", "dir", "rel"] {
+            let handle = root.resolve(name)?;
+            assert_eq!(
+                handle.readlink_into(&mut buf).map_err(|err| err.kind()),
+                Err(ErrorKind::OsError(Some(libc::EINVAL))),
+                "readlink_into of non-symlink {name:?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    fn check_xattr_ops() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"")?;
+        let root = Root::open(dir.path())?;
+        let handle = root.resolve("file")?;
+        assert!(handle.is_opath()?, "xattrs should work on O_PATH handles");
+
+        let name = OsStr::new("user.pathrs.test");
+        match handle.setxattr(name, b"some\0value") {
+            // Not all filesystems support user xattrs.
+            Err(err) if err.kind() == ErrorKind::OsError(Some(libc::EOPNOTSUPP)) => return Ok(()),
+            res => res?,
+        }
+        assert_eq!(handle.getxattr(name)?, b"some\0value");
+        assert_eq!(handle.as_ref().getxattr(name)?, b"some\0value");
+        assert!(
+            handle.listxattr()?.contains(&name.to_os_string()),
+            "listxattr should include newly-set xattr"
+        );
+
+        // Large values need the buffer to be grown.
+        let big_value = vec![b'x'; 3000];
+        handle.setxattr(name, &big_value)?;
+        assert_eq!(handle.getxattr(name)?, big_value);
+
+        // The xattr is set on the real inode.
+        let other_handle = root.resolve("file")?;
+        assert_eq!(other_handle.getxattr(name)?, big_value);
+
+        handle.removexattr(name)?;
+        assert_eq!(
+            handle.getxattr(name).map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::ENODATA))),
+        );
+        assert!(!handle.listxattr()?.contains(&name.to_os_string()));
+
+        // The magic-link of a symlink handle refers to the symlink itself.
+        std::os::unix::fs::symlink("file", dir.path().join("link"))?;
+        let link = root.resolve_nofollow("link")?;
+        handle.setxattr(name, b"file value")?;
+        assert!(
+            !link.listxattr()?.contains(&name.to_os_string()),
+            "listxattr of a symlink handle should not follow the symlink"
+        );
+        handle.removexattr(name)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn xattr() -> Result<(), Error> {
+        check_xattr_ops()
+    }
+
+    #[test]
+    fn xattr_without_xattrat() -> Result<(), Error> {
+        // Use the path-based fallback for kernels without *xattrat(2).
+        let features = KernelFeatures {
+            has_xattrat: false,
+            ..kernel::kernel_features()
+        };
+        kernel::with_kernel_features(features, check_xattr_ops)
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "_test_as_root"), ignore)]
+    fn xattr_trusted() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"")?;
+        let handle = Root::open(dir.path())?.resolve("file")?;
+
+        // trusted.* xattrs require CAP_SYS_ADMIN and are passed through as-is.
+        let name = OsStr::new("trusted.pathrs.test");
+        match handle.setxattr(name, b"trusted value") {
+            Err(err) if err.kind() == ErrorKind::OsError(Some(libc::EOPNOTSUPP)) => return Ok(()),
+            res => res?,
+        }
+        assert_eq!(handle.getxattr(name)?, b"trusted value");
+        assert!(handle.listxattr()?.contains(&name.to_os_string()));
+        handle.removexattr(name)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn fsverity() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), vec![b'x'; 10000])?;
+        let root = Root::open(dir.path())?;
+        let handle = root.resolve("file")?;
+
+        // Not all filesystems support fs-verity.
+        let unsupported = [
+            ErrorKind::OsError(Some(libc::ENOTTY)),
+            ErrorKind::OsError(Some(libc::EOPNOTSUPP)),
+        ];
+        let err = handle
+            .fsverity_measure()
+            .expect_err("measuring file without fs-verity should fail");
+        assert!(
+            err.kind() == ErrorKind::OsError(Some(libc::ENODATA))
+                || unsupported.contains(&err.kind()),
+            "measuring file without fs-verity should fail with ENODATA: {err:?}"
+        );
+        match handle.fsverity_enable(&FsVerityParams::default()) {
+            Err(err) if unsupported.contains(&err.kind()) => return Ok(()),
+            res => res?,
+        }
+
+        let digest = handle.fsverity_measure()?;
+        assert_eq!(digest.algorithm(), FsVerityHashAlgorithm::Sha256);
+        assert_eq!(digest.as_bytes().len(), 32, "SHA-256 digest size");
+        assert_eq!(
+            root.resolve("file")?.as_ref().fsverity_measure()?,
+            digest,
+            "fs-verity digest should be stable"
+        );
+
+        // The file is now read-only, and fs-verity cannot be enabled twice.
+        assert_eq!(
+            handle
+                .reopen(OpenFlags::O_WRONLY)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::EPERM))),
+        );
+        assert_eq!(
+            handle
+                .fsverity_enable(&FsVerityParams::default())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::EEXIST))),
+        );
+
+        // The digest depends on the parameters used.
+        std::fs::write(dir.path().join("file2"), vec![b'x'; 10000])?;
+        let handle = root.resolve("file2")?;
+        handle.fsverity_enable(
+            &FsVerityParams::default()
+                .hash_algorithm(FsVerityHashAlgorithm::Sha512)
+                .salt(*b"pathrs"),
+        )?;
+        let digest2 = handle.fsverity_measure()?;
+        assert_eq!(digest2.algorithm(), FsVerityHashAlgorithm::Sha512);
+        assert_eq!(digest2.as_bytes().len(), 64, "SHA-512 digest size");
+
+        Ok(())
+    }
+
+    #[test]
+    fn created() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"")?;
+        let root = Root::open(dir.path())?;
+        let handle = root.resolve("file")?;
+
+        // Most filesystems used for temporary directories (ext4, xfs, btrfs,
+        // tmpfs) record btime, but we can't guarantee that so we just check
+        // that we match what Rust reports.
+        let std_created = std::fs::metadata(dir.path().join("file"))?.created().ok();
+        assert_eq!(handle.created()?, std_created, "created() must match std");
+        assert_eq!(
+            handle.stat()?.created(),
+            std_created,
+            "stat().created() must match std"
+        );
+        if let Some(btime) = handle.created()? {
+            let mtime = std::fs::metadata(dir.path().join("file"))?.modified()?;
+            assert!(
+                btime <= mtime,
+                "btime {btime:?} must not be after mtime {mtime:?}"
+            );
+        }
+
+        // procfs doesn't record btime, so this tests the None case.
+        let proc_handle = Root::open("/proc")?.resolve("self/status")?;
+        assert_eq!(proc_handle.created()?, None, "procfs does not have btime");
+        assert_eq!(
+            proc_handle.stat()?.created(),
+            None,
+            "procfs does not have btime"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reopen_append() -> Result<(), Error> {
+        const NUM_WRITES: usize = 1000;
+        const CHUNK: &[u8] = b"0123456789abcdef";
+
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("log"), b"")?;
+        let handle = Root::open(&dir)?.resolve("log")?;
+
+        let file = handle.reopen_append()?;
+        let flags = rustix::fs::fcntl_getfl(&file)?;
+        assert!(
+            flags.contains(rustix::fs::OFlags::APPEND),
+            "reopen_append should return a file with O_APPEND set (got {flags:?})"
+        );
+
+        // Two threads appending to the same handle must never clobber each
+        // other's writes.
+        thread::scope(|s| -> Result<(), Error> {
+            let threads = (0..2)
+                .map(|_| {
+                    let mut file = handle.reopen_append()?;
+                    Ok(s.spawn(move || -> Result<(), std::io::Error> {
+                        for _ in 0..NUM_WRITES {
+                            file.write_all(CHUNK)?;
+                        }
+                        Ok(())
+                    }))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            for thread in threads {
+                thread.join().expect("append thread panicked")?;
+            }
+            Ok(())
+        })?;
+
+        let contents = std::fs::read(dir.path().join("log"))?;
+        assert_eq!(
+            contents.len(),
+            2 * NUM_WRITES * CHUNK.len(),
+            "concurrent appends should not overwrite each other"
+        );
+        assert!(
+            contents.chunks(CHUNK.len()).all(|chunk| chunk == CHUNK),
+            "concurrent appends should not interleave"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn mmap_private() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        let old = (0..3 * 4096 + 123)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        std::fs::write(dir.path().join("file"), &old)?;
+        let handle = Root::open(&dir)?.resolve("file")?;
+
+        let mapping = handle.mmap_private()?;
+        assert_eq!(&*mapping, &old[..], "mapping should contain file contents");
+
+        // Overwrite the file in-place. The mapping shares the page cache, so
+        // the new contents are visible (it is not a snapshot).
+        let new = vec![0xAAu8; old.len()];
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(dir.path().join("file"))?
+            .write_all(&new)?;
+        assert_eq!(
+            &*mapping,
+            &new[..],
+            "read-only private mapping should share the page cache"
+        );
+        assert_eq!(&*handle.mmap_private()?, &new[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mmap_private_snapshot() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        let old = (0..3 * 4096 + 123)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        std::fs::write(dir.path().join("file"), &old)?;
+        let handle = Root::open(&dir)?.resolve("file")?;
+
+        let snapshot = handle.mmap_private_snapshot(old.len() as u64)?;
+        assert_eq!(
+            &*snapshot,
+            &old[..],
+            "snapshot should contain file contents"
+        );
+
+        // Overwrite the file in-place. The snapshot must still show the old
+        // contents.
+        let new = vec![0xAAu8; old.len()];
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(dir.path().join("file"))?;
+        file.write_all(&new)?;
+        file.sync_all()?;
+        assert_eq!(
+            std::fs::read(dir.path().join("file"))?,
+            new,
+            "file on disk should have been modified"
+        );
+        assert_eq!(
+            &*snapshot,
+            &old[..],
+            "snapshot should not see later writes to the file"
+        );
+
+        // Truncating the file doesn't affect an existing snapshot.
+        file.set_len(0)?;
+        assert_eq!(&*snapshot, &old[..], "snapshot should survive truncation");
+
+        // Files larger than the limit are rejected.
+        std::fs::write(dir.path().join("file"), &new)?;
+        assert_eq!(
+            handle
+                .mmap_private_snapshot(new.len() as u64 - 1)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::InvalidArgument),
+            "snapshot of a file larger than max_len should fail"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn mmap_private_empty() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"")?;
+        let handle = Root::open(&dir)?.resolve("file")?;
+
+        let mapping = handle.mmap_private()?;
+        assert!(
+            mapping.is_empty(),
+            "empty file should give an empty mapping"
+        );
+        let snapshot = handle.mmap_private_snapshot(0)?;
+        assert!(
+            snapshot.is_empty(),
+            "empty file should give an empty snapshot"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn mmap_private_directory() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        let handle = Root::open(&dir)?.resolve(".")?;
+
+        assert_eq!(
+            handle.mmap_private().map(|_| ()).map_err(|err| err.kind()),
+            Err(ErrorKind::InvalidArgument),
+            "mapping a directory should fail"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reopen_raw() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"")?;
+        let handle = Root::open(&dir)?.resolve("file")?;
+
+        let file = handle.reopen(OpenFlags::O_RDONLY)?;
+        assert!(
+            rustix::io::fcntl_getfd(&file)?.contains(rustix::io::FdFlags::CLOEXEC),
+            "reopen should always set O_CLOEXEC"
+        );
+
+        let file = handle.reopen_raw(OpenFlags::O_RDONLY)?;
+        assert!(
+            !rustix::io::fcntl_getfd(&file)?.contains(rustix::io::FdFlags::CLOEXEC),
+            "reopen_raw should not set O_CLOEXEC implicitly"
+        );
+
+        let file = handle.reopen_raw(OpenFlags::O_RDONLY | OpenFlags::O_CLOEXEC)?;
+        assert!(
+            rustix::io::fcntl_getfd(&file)?.contains(rustix::io::FdFlags::CLOEXEC),
+            "reopen_raw should set O_CLOEXEC if requested"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reopen_raw_symlink() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::os::unix::fs::symlink("file", dir.path().join("link"))?;
+        let handle = Root::open(&dir)?.resolve_nofollow("link")?;
+
+        assert_eq!(
+            handle
+                .reopen_raw(OpenFlags::O_RDONLY)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::ELOOP))),
+            "reopen_raw of a symlink handle should fail"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn copy_to_with_progress() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        // Make sure the copy is done in several chunks, with a partial final
+        // chunk.
+        let data = (0..(5 << 20) + 1234)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        std::fs::write(dir.path().join("src"), &data)?;
+        std::fs::write(dir.path().join("dst"), b"old contents")?;
+        let root = Root::open(&dir)?;
+        let src = root.resolve("src")?;
+        let dst = root.resolve("dst")?;
+
+        let mut seen = Vec::new();
+        let copied = src.copy_to_with_progress(&dst.as_ref(), None, |copied| {
+            seen.push(copied);
+            ControlFlow::Continue(())
+        })?;
+        assert_eq!(copied, data.len() as u64, "whole file should be copied");
+        assert!(seen.len() > 1, "copy should be done in chunks: {seen:?}");
+        assert!(
+            seen.windows(2).all(|pair| pair[0] < pair[1]),
+            "progress should be monotonically increasing: {seen:?}"
+        );
+        assert_eq!(
+            seen.last().copied(),
+            Some(data.len() as u64),
+            "final progress should be the total size"
+        );
+        assert!(
+            std::fs::read(dir.path().join("dst"))? == data,
+            "destination should contain a copy of the source"
+        );
+
+        // Copying with a limit only copies the requested prefix.
+        let copied =
+            src.copy_to_with_progress(&dst.as_ref(), Some(1000), |_| ControlFlow::Continue(()))?;
+        assert_eq!(copied, 1000, "copy should stop at the requested length");
+        assert!(
+            std::fs::read(dir.path().join("dst"))? == data[..1000],
+            "destination should contain the requested prefix"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn copy_to_with_progress_cancel() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        let data = vec![0xAAu8; 3 << 20];
+        std::fs::write(dir.path().join("src"), &data)?;
+        std::fs::write(dir.path().join("dst"), b"")?;
+        let root = Root::open(&dir)?;
+        let src = root.resolve("src")?;
+        let dst = root.resolve("dst")?;
+
+        let mut calls = 0;
+        let copied = src.copy_to_with_progress(&dst.as_ref(), None, |_| {
+            calls += 1;
+            ControlFlow::Break(())
+        })?;
+        assert_eq!(calls, 1, "copy should stop after the callback breaks");
+        assert!(
+            copied > 0 && copied < data.len() as u64,
+            "copy should have been stopped part-way ({copied} bytes copied)"
+        );
+        assert_eq!(
+            std::fs::metadata(dir.path().join("dst"))?.len(),
+            copied,
+            "destination should only contain the copied data"
         );
 
         Ok(())
@@ -0,0 +1,652 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Self-tests for the kernel features that libpathrs relies on.
+
+#![forbid(unsafe_code)]
+
+use crate::{
+    syscalls::{self, OpenHow},
+    utils::FdExt,
+};
+
+use std::{
+    fs,
+    os::unix::{
+        fs::MetadataExt,
+        io::{AsFd, AsRawFd, OwnedFd},
+    },
+    path::{Path, PathBuf},
+    thread,
+};
+
+#[cfg(test)]
+use std::cell::Cell;
+
+use once_cell::sync::Lazy;
+use rustix::{
+    fs::{self as rustix_fs, AtFlags, StatxFlags},
+    io::Errno,
+    thread::UnshareFlags,
+};
+
+/// Whether a particular `RESOLVE_*` flag is enforced by the running kernel.
+///
+/// See [`kernel_support`] for more details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ResolveFlagStatus {
+    /// The kernel accepted the flag and enforced it.
+    Honored,
+    /// The kernel accepted the flag but did not enforce it. This should never
+    /// happen on a correctly functioning kernel.
+    Ignored,
+    /// The kernel rejected the flag (or does not support `openat2(2)` at all).
+    Unsupported,
+    /// The self-test for this flag could not be run (for instance, because
+    /// there was no usable temporary directory or `/proc` was not mounted).
+    Unknown,
+}
+
+/// The results of the kernel feature self-tests run by [`kernel_support`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct KernelSupport {
+    /// Whether `openat2(2)` is supported by the kernel.
+    pub openat2: bool,
+    /// Whether `RESOLVE_BENEATH` blocks lookups that escape the starting
+    /// directory.
+    pub resolve_beneath: ResolveFlagStatus,
+    /// Whether `RESOLVE_IN_ROOT` clamps `..` to the starting directory.
+    pub resolve_in_root: ResolveFlagStatus,
+    /// Whether `RESOLVE_NO_MAGICLINKS` blocks magic-link traversal.
+    pub resolve_no_magiclinks: ResolveFlagStatus,
+    /// Whether `RESOLVE_NO_SYMLINKS` blocks symlink traversal.
+    pub resolve_no_symlinks: ResolveFlagStatus,
+    /// Whether `RESOLVE_NO_XDEV` blocks mount-point crossings.
+    pub resolve_no_xdev: ResolveFlagStatus,
+}
+
+impl KernelSupport {
+    /// Returns whether every `RESOLVE_*` flag libpathrs uses with
+    /// `openat2(2)` was found to be enforced by the kernel.
+    pub fn resolve_flags_honored(&self) -> bool {
+        [
+            self.resolve_beneath,
+            self.resolve_in_root,
+            self.resolve_no_magiclinks,
+            self.resolve_no_symlinks,
+            self.resolve_no_xdev,
+        ]
+        .iter()
+        .all(|status| *status == ResolveFlagStatus::Honored)
+    }
+}
+
+/// Get the results of self-testing the kernel features used by libpathrs.
+///
+/// `openat2(2)` rejects unknown `RESOLVE_*` flags with `EINVAL`, so libpathrs
+/// assumes that any flag the kernel accepts is also enforced. Security
+/// conscious users can use this to verify that assumption -- each flag is
+/// checked by attempting a lookup the flag should block (such as traversing a
+/// symlink with `RESOLVE_NO_SYMLINKS`) in a scratch directory created inside
+/// [`std::env::temp_dir`], which is removed afterwards.
+///
+/// The self-tests are only run the first time this function is called, and
+/// the results are cached for the lifetime of the process.
+pub fn kernel_support() -> &'static KernelSupport {
+    // MSRV(1.80): Use LazyLock.
+    static KERNEL_SUPPORT: Lazy<KernelSupport> = Lazy::new(KernelSupport::probe);
+    &KERNEL_SUPPORT
+}
+
+/// The optional kernel features libpathrs makes use of.
+///
+/// Unlike [`KernelSupport`], this only records whether each feature is
+/// available at all (usually determined by whether the syscall or flag is
+/// rejected with `ENOSYS` or `EINVAL`) and is cheap to compute. All of
+/// libpathrs's feature detection goes through [`kernel_features`], so that
+/// tests can override the detected features with [`with_kernel_features`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct KernelFeatures {
+    /// Whether `openat2(2)` is supported (Linux 5.6).
+    pub(crate) has_openat2: bool,
+    /// Whether `statx(2)` is supported (Linux 4.11).
+    pub(crate) has_statx: bool,
+    /// Whether `renameat2(2)` is supported (Linux 3.15).
+    pub(crate) has_renameat2: bool,
+    /// Whether the `*xattrat(2)` syscalls are supported (Linux 6.13).
+    pub(crate) has_xattrat: bool,
+    /// Whether we can create private mount namespaces (which requires
+    /// `CAP_SYS_ADMIN`), as needed by the hardcore resolver. This is only
+    /// probed if `openat2(2)` is supported, since the hardcore resolver also
+    /// requires `openat2(2)`.
+    pub(crate) has_mount_namespaces: bool,
+    /// The set of `RESOLVE_*` flags accepted by `openat2(2)`. This is empty if
+    /// `openat2(2)` is not supported.
+    pub(crate) resolve_flags: u64,
+}
+
+impl KernelFeatures {
+    /// All of the `RESOLVE_*` flags we know about.
+    const RESOLVE_FLAGS: [u64; 6] = [
+        libc::RESOLVE_BENEATH,
+        libc::RESOLVE_IN_ROOT,
+        libc::RESOLVE_NO_MAGICLINKS,
+        libc::RESOLVE_NO_SYMLINKS,
+        libc::RESOLVE_NO_XDEV,
+        libc::RESOLVE_CACHED,
+    ];
+
+    fn probe() -> Self {
+        let probe_resolve = |resolve| {
+            let how = OpenHow {
+                flags: libc::O_PATH as u64,
+                resolve,
+                ..Default::default()
+            };
+            syscalls::openat2_unchecked(syscalls::AT_FDCWD, ".", &how)
+        };
+
+        // Only treat openat2(2) as supported if a trivial lookup succeeds, so
+        // that a seccomp filter (or anything else) which makes every
+        // openat2(2) call fail doesn't cause us to use it.
+        let has_openat2 = probe_resolve(0).is_ok();
+        let resolve_flags = Self::RESOLVE_FLAGS
+            .iter()
+            .filter(|&&flag| {
+                // Any error other than ENOSYS or EINVAL (such as the EAGAIN
+                // returned by RESOLVE_CACHED for uncached lookups) means the
+                // flag is supported.
+                has_openat2
+                    && match probe_resolve(flag) {
+                        Ok(_) => true,
+                        Err(err) => !matches!(err.errno(), Errno::NOSYS | Errno::INVAL),
+                    }
+            })
+            .fold(0, |acc, flag| acc | flag);
+
+        let has_statx = !matches!(
+            rustix_fs::statx(
+                rustix_fs::CWD,
+                ".",
+                AtFlags::SYMLINK_NOFOLLOW,
+                StatxFlags::empty(),
+            ),
+            Err(Errno::NOSYS)
+        );
+
+        // We expect EBUSY, but just to be safe we only check for ENOSYS.
+        let has_renameat2 = !matches!(
+            rustix_fs::renameat_with(
+                rustix_fs::CWD,
+                ".",
+                rustix_fs::CWD,
+                ".",
+                rustix_fs::RenameFlags::EXCHANGE,
+            ),
+            Err(Errno::NOSYS)
+        );
+
+        // As with openat2(2), only use listxattrat(2) if a trivial call
+        // succeeds.
+        let has_xattrat = syscalls::listxattrat_unchecked(syscalls::AT_FDCWD, "/").is_ok();
+
+        // unshare(2) affects the calling thread, so do it on a throwaway one.
+        let has_mount_namespaces = has_openat2
+            && thread::spawn(|| syscalls::unshare(UnshareFlags::FS | UnshareFlags::NEWNS).is_ok())
+                .join()
+                .unwrap_or(false);
+
+        Self {
+            has_openat2,
+            has_statx,
+            has_renameat2,
+            has_xattrat,
+            has_mount_namespaces,
+            resolve_flags,
+        }
+    }
+
+    /// Returns whether all of the given `RESOLVE_*` flags are supported.
+    pub(crate) fn has_resolve_flags(&self, flags: u64) -> bool {
+        self.resolve_flags & flags == flags
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    static KERNEL_FEATURES_OVERRIDE: Cell<Option<KernelFeatures>> = const { Cell::new(None) };
+}
+
+/// Get the set of optional kernel features supported by the running kernel.
+///
+/// The kernel is only probed the first time this function is called, and the
+/// results are cached for the lifetime of the process. In tests, the result
+/// can be overridden for the current thread with [`with_kernel_features`].
+pub(crate) fn kernel_features() -> KernelFeatures {
+    // MSRV(1.80): Use LazyLock.
+    static KERNEL_FEATURES: Lazy<KernelFeatures> = Lazy::new(KernelFeatures::probe);
+
+    #[cfg(test)]
+    if let Some(features) = KERNEL_FEATURES_OVERRIDE.with(Cell::get) {
+        return features;
+    }
+    *KERNEL_FEATURES
+}
+
+/// Run `f` with [`kernel_features`] returning `features` on the current thread,
+/// so that fallback paths can be tested regardless of the running kernel.
+///
+/// Note that objects which picked a backend before the override was installed
+/// (most notably the global procfs handle) will continue to use that backend.
+#[cfg(test)]
+pub(crate) fn with_kernel_features<T, F>(features: KernelFeatures, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    let old = KERNEL_FEATURES_OVERRIDE.with(|cur| cur.replace(Some(features)));
+    let ret = f();
+    KERNEL_FEATURES_OVERRIDE.with(|cur| cur.replace(old));
+    ret
+}
+
+/// Classify the outcome of a self-test lookup. `control` is the result of the
+/// same lookup without the flag being tested (which must succeed for the
+/// self-test to be meaningful), and `blocked` is the error the flag should
+/// cause the lookup to fail with.
+fn classify<T, U>(
+    control: Result<T, Errno>,
+    ret: Result<U, Errno>,
+    blocked: Errno,
+) -> ResolveFlagStatus {
+    match (control, ret) {
+        (_, Err(Errno::INVAL)) => ResolveFlagStatus::Unsupported,
+        (Err(_), _) => ResolveFlagStatus::Unknown,
+        (Ok(_), Err(err)) if err == blocked => ResolveFlagStatus::Honored,
+        (Ok(_), Ok(_)) => ResolveFlagStatus::Ignored,
+        (Ok(_), Err(_)) => ResolveFlagStatus::Unknown,
+    }
+}
+
+fn probe_openat2<Fd: AsFd, P: AsRef<Path>>(
+    dirfd: Fd,
+    path: P,
+    resolve: u64,
+) -> Result<OwnedFd, Errno> {
+    let how = OpenHow {
+        flags: (libc::O_PATH | libc::O_NOFOLLOW) as u64,
+        resolve,
+        ..Default::default()
+    };
+    syscalls::openat2(dirfd, path, &how).map_err(|err| err.errno())
+}
+
+/// A scratch directory tree for the self-tests, removed on drop.
+///
+/// ```text
+/// <tmp>/
+/// <tmp>/dir/
+/// <tmp>/link -> dir
+/// ```
+struct ScratchTree {
+    path: PathBuf,
+    root: OwnedFd,
+    dir: OwnedFd,
+}
+
+impl ScratchTree {
+    fn new() -> Option<Self> {
+        let tmpdir = std::env::temp_dir();
+        let path = (0..16)
+            .map(|attempt| {
+                tmpdir.join(format!(".pathrs-selftest-{}-{attempt}", std::process::id()))
+            })
+            .find(|path| fs::create_dir(path).is_ok())?;
+        // Construct the tree before opening it, so that a failure doesn't leave
+        // the directory behind.
+        let tree = (|| {
+            fs::create_dir(path.join("dir"))?;
+            std::os::unix::fs::symlink("dir", path.join("link"))?;
+            let root = OwnedFd::from(fs::File::open(&path)?);
+            let dir = OwnedFd::from(fs::File::open(path.join("dir"))?);
+            Ok::<_, std::io::Error>((root, dir))
+        })();
+        match tree {
+            Ok((root, dir)) => Some(Self { path, root, dir }),
+            Err(_) => {
+                let _ = fs::remove_dir_all(&path);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for ScratchTree {
+    fn drop(&mut self) {
+        // There's nothing useful we can do if this fails.
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+impl KernelSupport {
+    /// Check whether each `RESOLVE_*` flag accepted by the kernel (according to
+    /// [`kernel_features`]) is actually enforced. Flags which the kernel does
+    /// not accept are [`ResolveFlagStatus::Unsupported`] without any further
+    /// self-tests.
+    fn probe() -> Self {
+        let features = kernel_features();
+        let check = |flag, selftest: &dyn Fn() -> ResolveFlagStatus| {
+            if features.has_openat2 && features.has_resolve_flags(flag) {
+                selftest()
+            } else {
+                ResolveFlagStatus::Unsupported
+            }
+        };
+
+        let tree = if features.has_openat2 {
+            ScratchTree::new()
+        } else {
+            None
+        };
+
+        // "link" is a symlink to "dir".
+        let resolve_no_symlinks = check(libc::RESOLVE_NO_SYMLINKS, &|| match &tree {
+            Some(ScratchTree { root, .. }) => classify(
+                probe_openat2(root, "link/", 0),
+                probe_openat2(root, "link/", libc::RESOLVE_NO_SYMLINKS),
+                Errno::LOOP,
+            ),
+            None => ResolveFlagStatus::Unknown,
+        });
+
+        // ".." from "dir" escapes the starting directory.
+        let resolve_beneath = check(libc::RESOLVE_BENEATH, &|| match &tree {
+            Some(ScratchTree { dir, .. }) => classify(
+                probe_openat2(dir, "..", 0),
+                probe_openat2(dir, "..", libc::RESOLVE_BENEATH),
+                Errno::XDEV,
+            ),
+            None => ResolveFlagStatus::Unknown,
+        });
+
+        // ".." from "dir" should stay in "dir".
+        let resolve_in_root = check(libc::RESOLVE_IN_ROOT, &|| match &tree {
+            Some(ScratchTree { dir, .. }) => {
+                match probe_openat2(dir, "..", libc::RESOLVE_IN_ROOT) {
+                    Err(Errno::INVAL) => ResolveFlagStatus::Unsupported,
+                    Err(_) => ResolveFlagStatus::Unknown,
+                    Ok(fd) => match (fd.metadata(), dir.metadata()) {
+                        (Ok(got), Ok(want))
+                            if (got.dev(), got.ino()) == (want.dev(), want.ino()) =>
+                        {
+                            ResolveFlagStatus::Honored
+                        }
+                        (Ok(_), Ok(_)) => ResolveFlagStatus::Ignored,
+                        _ => ResolveFlagStatus::Unknown,
+                    },
+                }
+            }
+            None => ResolveFlagStatus::Unknown,
+        });
+
+        // /proc/self/fd/$n is a magic-link to the scratch directory.
+        let resolve_no_magiclinks = check(libc::RESOLVE_NO_MAGICLINKS, &|| match &tree {
+            Some(ScratchTree { root, .. }) => {
+                let magiclink = format!("/proc/self/fd/{}/dir", root.as_raw_fd());
+                classify(
+                    probe_openat2(syscalls::AT_FDCWD, &magiclink, 0),
+                    probe_openat2(syscalls::AT_FDCWD, &magiclink, libc::RESOLVE_NO_MAGICLINKS),
+                    Errno::LOOP,
+                )
+            }
+            None => ResolveFlagStatus::Unknown,
+        });
+
+        // Looking up "/proc" crosses from the root filesystem into procfs
+        // (assuming it is mounted).
+        let resolve_no_xdev = check(libc::RESOLVE_NO_XDEV, &|| match (
+            fs::metadata("/"),
+            fs::metadata("/proc"),
+        ) {
+            (Ok(root), Ok(proc)) if root.dev() != proc.dev() => classify(
+                probe_openat2(syscalls::AT_FDCWD, "/proc", 0),
+                probe_openat2(syscalls::AT_FDCWD, "/proc", libc::RESOLVE_NO_XDEV),
+                Errno::XDEV,
+            ),
+            _ => ResolveFlagStatus::Unknown,
+        });
+
+        Self {
+            openat2: features.has_openat2,
+            resolve_beneath,
+            resolve_in_root,
+            resolve_no_magiclinks,
+            resolve_no_symlinks,
+            resolve_no_xdev,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        flags::RenameFlags,
+        resolvers::{Resolver, ResolverBackend},
+        utils,
+    };
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn classify_results() {
+        for (control, ret, expected) in [
+            (Ok(()), Err(Errno::LOOP), ResolveFlagStatus::Honored),
+            (Ok(()), Ok(()), ResolveFlagStatus::Ignored),
+            (Ok(()), Err(Errno::INVAL), ResolveFlagStatus::Unsupported),
+            (
+                Err(Errno::NOENT),
+                Err(Errno::INVAL),
+                ResolveFlagStatus::Unsupported,
+            ),
+            (
+                Err(Errno::NOENT),
+                Err(Errno::LOOP),
+                ResolveFlagStatus::Unknown,
+            ),
+            (Err(Errno::NOENT), Ok(()), ResolveFlagStatus::Unknown),
+            (Ok(()), Err(Errno::NOENT), ResolveFlagStatus::Unknown),
+        ] {
+            assert_eq!(
+                classify(control, ret, Errno::LOOP),
+                expected,
+                "classify({control:?}, {ret:?}, ELOOP)"
+            );
+        }
+    }
+
+    #[test]
+    fn kernel_support_resolve_no_symlinks() {
+        let support = kernel_support();
+        assert_eq!(
+            support.openat2,
+            kernel_features().has_openat2,
+            "kernel_support().openat2 should match openat2 support"
+        );
+        if support.openat2 {
+            for (flag, status) in [
+                ("RESOLVE_NO_SYMLINKS", support.resolve_no_symlinks),
+                ("RESOLVE_BENEATH", support.resolve_beneath),
+                ("RESOLVE_IN_ROOT", support.resolve_in_root),
+                ("RESOLVE_NO_MAGICLINKS", support.resolve_no_magiclinks),
+            ] {
+                assert_eq!(
+                    status,
+                    ResolveFlagStatus::Honored,
+                    "{flag} should be honored by a kernel with openat2 support"
+                );
+            }
+        } else {
+            assert_eq!(
+                support.resolve_no_symlinks,
+                ResolveFlagStatus::Unsupported,
+                "RESOLVE_NO_SYMLINKS cannot be supported without openat2"
+            );
+            assert!(
+                !support.resolve_flags_honored(),
+                "resolve flags cannot be honored without openat2"
+            );
+        }
+    }
+
+    #[test]
+    fn kernel_support_from_features() {
+        let real = kernel_features();
+        if !real.has_openat2 {
+            return;
+        }
+
+        // Flags which KernelFeatures says the kernel rejects must be reported
+        // as unsupported without running their self-tests.
+        let features = KernelFeatures {
+            resolve_flags: real.resolve_flags & !(libc::RESOLVE_NO_XDEV | libc::RESOLVE_BENEATH),
+            ..real
+        };
+        let support = with_kernel_features(features, KernelSupport::probe);
+        assert_eq!(
+            support.resolve_no_xdev,
+            ResolveFlagStatus::Unsupported,
+            "RESOLVE_NO_XDEV should be unsupported if it was rejected"
+        );
+        assert_eq!(
+            support.resolve_beneath,
+            ResolveFlagStatus::Unsupported,
+            "RESOLVE_BENEATH should be unsupported if it was rejected"
+        );
+        assert_eq!(
+            support.resolve_no_symlinks,
+            ResolveFlagStatus::Honored,
+            "RESOLVE_NO_SYMLINKS should still be self-tested"
+        );
+        assert!(
+            !support.resolve_flags_honored(),
+            "resolve flags cannot all be honored if some were rejected"
+        );
+
+        let features = KernelFeatures {
+            has_openat2: false,
+            resolve_flags: 0,
+            ..real
+        };
+        let support = with_kernel_features(features, KernelSupport::probe);
+        assert!(!support.openat2, "openat2 should match kernel_features()");
+        assert_eq!(
+            support.resolve_in_root,
+            ResolveFlagStatus::Unsupported,
+            "RESOLVE_IN_ROOT cannot be supported without openat2"
+        );
+    }
+
+    #[test]
+    fn kernel_features_probe() {
+        let features = kernel_features();
+        assert_eq!(
+            features,
+            kernel_features(),
+            "kernel_features() should be stable"
+        );
+        assert!(
+            features.has_resolve_flags(0),
+            "the empty set of resolve flags is always supported"
+        );
+        if !features.has_openat2 {
+            assert!(
+                !features.has_mount_namespaces,
+                "mount namespaces are only probed with openat2 support"
+            );
+        }
+        if features.has_openat2 {
+            assert!(
+                features.has_resolve_flags(
+                    libc::RESOLVE_BENEATH
+                        | libc::RESOLVE_IN_ROOT
+                        | libc::RESOLVE_NO_MAGICLINKS
+                        | libc::RESOLVE_NO_SYMLINKS
+                        | libc::RESOLVE_NO_XDEV
+                ),
+                "all Linux 5.6 resolve flags should be supported with openat2: {features:?}"
+            );
+        } else {
+            assert_eq!(
+                features.resolve_flags, 0,
+                "no resolve flags can be supported without openat2"
+            );
+        }
+    }
+
+    #[test]
+    fn with_kernel_features_override() {
+        let real = kernel_features();
+        let ancient = KernelFeatures {
+            has_openat2: false,
+            has_statx: false,
+            has_renameat2: false,
+            has_xattrat: false,
+            has_mount_namespaces: false,
+            resolve_flags: 0,
+        };
+
+        with_kernel_features(ancient, || {
+            assert_eq!(kernel_features(), ancient, "override should be in effect");
+            assert_eq!(
+                Resolver::default().backend,
+                ResolverBackend::EmulatedOpath,
+                "default resolver without openat2"
+            );
+            assert!(
+                !RenameFlags::RENAME_EXCHANGE.is_supported(),
+                "rename flags cannot be supported without renameat2"
+            );
+
+            assert_eq!(
+                syscalls::openat2(syscalls::AT_FDCWD, ".", &Default::default())
+                    .map(|_| ())
+                    .map_err(|err| err.errno()),
+                Err(Errno::NOSYS),
+                "openat2 wrapper without openat2"
+            );
+            assert_eq!(
+                syscalls::statx(syscalls::AT_FDCWD, ".", StatxFlags::empty())
+                    .map(|_| ())
+                    .map_err(|err| err.errno()),
+                Err(Errno::NOSYS),
+                "statx wrapper without statx"
+            );
+            assert_eq!(
+                utils::fetch_mnt_id(syscalls::AT_FDCWD, ".").map_err(|err| err.kind()),
+                Ok(None),
+                "mount id cannot be fetched without statx"
+            );
+        });
+
+        assert_eq!(kernel_features(), real, "override should be reset");
+    }
+}
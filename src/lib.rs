@@ -161,12 +161,20 @@ mod root;
 #[doc(inline)]
 pub use root::*;
 
+// Kernel feature self-tests.
+mod kernel;
+#[doc(inline)]
+pub use kernel::*;
+
 pub mod error;
 pub mod flags;
 pub mod procfs;
 
 // Resolver backend implementations.
 mod resolvers;
+#[cfg(feature = "tracing")]
+pub use resolvers::set_tracing_redact_paths;
+pub use resolvers::{set_global_resolve_observer, ResolveObserver};
 
 // C API.
 #[cfg(feature = "capi")]
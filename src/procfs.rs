@@ -30,13 +30,13 @@ use crate::{
 };
 
 use std::{
-    fs::File,
+    fs::{self, File},
     io::Error as IOError,
     os::unix::{
         fs::MetadataExt,
         io::{AsFd, BorrowedFd, OwnedFd},
     },
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use once_cell::sync::Lazy;
@@ -105,16 +105,17 @@ impl ProcfsBase {
             ]
             .into_iter()
             // Return the first option that exists in proc_root.
-            .find(|base| {
-                match proc_root {
-                    Some(root) => syscalls::fstatat(root, base),
-                    None => {
-                        syscalls::fstatat(syscalls::AT_FDCWD, PathBuf::from("/proc").join(base))
-                    }
-                }
-                .is_ok()
+            .find(|base| match proc_root {
+                Some(root) => syscalls::fstatat(root, base).is_ok(),
+                // NOTE: This path is used when generating syscall error
+                // messages (through FrozenFd), so we must not use our syscall
+                // wrappers here -- an error would recurse back into this code.
+                None => fs::symlink_metadata(PathBuf::from("/proc").join(base)).is_ok(),
             })
-            .expect("at least one candidate /proc/thread-self path should work"),
+            // If none of the candidates exist (such as when there is no /proc
+            // mounted), the caller will get a proper error when trying to use
+            // the path.
+            .unwrap_or_else(|| "self".into()),
         }
     }
     // TODO: Add into_raw_path() that doesn't use symlinks?
@@ -161,6 +162,21 @@ impl ProcfsBase {
 /// safely provide handlers for `/proc/self` and `/proc/thread-self` (which are
 /// the main things a lot of libpathrs users care about).
 ///
+/// # Pinning
+///
+/// A `ProcfsHandle` opens (and verifies) its handle to the root of `/proc`
+/// once when it is created, and all operations are done relative to that
+/// pinned file descriptor -- `/proc` is never looked up by path again. This
+/// means that a `ProcfsHandle` is not affected if `/proc` is later remounted
+/// or overmounted, but it also means that it will keep referencing the
+/// original `/proc` even if the process moves to a different mount namespace
+/// (with `unshare(CLONE_NEWNS)` or `setns(2)`). If you need the handle to
+/// refer to the `/proc` of the new mount namespace, you must rebuild it (with
+/// [`ProcfsHandle::repin`] or by creating a new `ProcfsHandle`). Note that
+/// the internal `ProcfsHandle` used by libpathrs (for operations like
+/// [`Handle::reopen`]) is only created once and is never re-pinned.
+///
+/// [`Handle::reopen`]: crate::Handle::reopen
 /// [cve-2019-16884]: https://nvd.nist.gov/vuln/detail/CVE-2019-16884
 /// [cve-2019-19921]: https://nvd.nist.gov/vuln/detail/CVE-2019-19921
 /// [lca2020]: https://youtu.be/tGseJW_uBB8
@@ -172,6 +188,13 @@ pub struct ProcfsHandle {
     mnt_id: Option<u64>,
     is_subset: bool,
     pub(crate) resolver: ProcfsResolver,
+    /// Whether every path component must be verified to be on the same mount
+    /// as the procfs root (see [`ProcfsHandle::set_strict_mnt_id`]).
+    strict_mnt_id: bool,
+    /// The constructor used to create this handle, used by
+    /// [`ProcfsHandle::repin`]. This is [`None`] for handles created from an
+    /// existing file descriptor.
+    repin_with: Option<fn() -> Result<ProcfsHandle, Error>>,
 }
 
 // TODO: Implement Into<OwnedFd> or AsFd? In theory someone could use this to
@@ -231,7 +254,7 @@ impl ProcfsHandle {
         syscalls::open_tree(
             syscalls::AT_FDCWD,
             "/proc",
-            OpenTreeFlags::OPEN_TREE_CLONE | flags,
+            OpenTreeFlags::OPEN_TREE_CLONE | OpenTreeFlags::OPEN_TREE_CLOEXEC | flags,
         )
         .map_err(|err| {
             ErrorImpl::RawOsError {
@@ -288,6 +311,7 @@ impl ProcfsHandle {
         Self::new_fsopen(true)
             .or_else(|_| Self::new_open_tree(OpenTreeFlags::AT_RECURSIVE))
             .or_else(|_| Self::new_unsafe_open())
+            .map(|procfs| procfs.with_repin(Self::new))
     }
 
     /// Create a new handle, trying to create a non-masked handle.
@@ -299,11 +323,104 @@ impl ProcfsHandle {
         Self::new_fsopen(false)
             .or_else(|_| Self::new_open_tree(OpenTreeFlags::empty()))
             .or_else(|_| Self::new_unsafe_open())
+            .map(|procfs| procfs.with_repin(Self::new_unmasked))
+    }
+
+    fn with_repin(self, repin_with: fn() -> Result<Self, Error>) -> Self {
+        Self {
+            repin_with: Some(repin_with),
+            ..self
+        }
+    }
+
+    /// Re-open the pinned handle to `/proc`.
+    ///
+    /// The new handle is created in the same way as the original one (as with
+    /// [`ProcfsHandle::new`]) but using the `/proc` of the current mount
+    /// namespace. This should be used if the process has changed mount
+    /// namespaces (see the [pinning](#pinning) section for more details). If
+    /// creating the new handle fails, the existing handle is left unchanged.
+    ///
+    /// [`ProcfsHandle`]s created with [`ProcfsHandle::try_from_fd`] cannot be
+    /// re-pinned (because libpathrs does not know where the file descriptor
+    /// came from), and an [`ErrorKind::InvalidArgument`] error is returned.
+    pub fn repin(&mut self) -> Result<(), Error> {
+        let repin_with = self.repin_with.ok_or_else(|| ErrorImpl::InvalidArgument {
+            name: "procfs".into(),
+            description: "procfs handles created from a file descriptor cannot be re-pinned".into(),
+        })?;
+        *self = Self {
+            strict_mnt_id: self.strict_mnt_id,
+            ..repin_with()?
+        };
+        Ok(())
+    }
+
+    /// Returns whether strict per-component mount checking is enabled for this
+    /// [`ProcfsHandle`].
+    ///
+    /// See [`ProcfsHandle::set_strict_mnt_id`] for more details.
+    #[inline]
+    pub fn strict_mnt_id(&self) -> bool {
+        self.strict_mnt_id
+    }
+
+    /// Enable (or disable) strict per-component mount checking.
+    ///
+    /// By default, lookups inside `/proc` are done with `openat2(2)` and
+    /// `RESOLVE_NO_XDEV` where possible, and only the final file is checked
+    /// against the mount ID of the `/proc` root. If strict checking is enabled,
+    /// lookups are instead done one component at a time and the mount ID of
+    /// *every* path component (obtained with `statx(2)`'s `STATX_MNT_ID`) is
+    /// checked against the mount ID of the `/proc` root. This will detect an
+    /// individual entry (such as `/proc/self/fd`) being shadowed by a
+    /// bind-mount, even for handles that can see the host's overmounts (such
+    /// as those created by unprivileged users). If a component is on a
+    /// different mount, an [`ErrorKind::OsError`] with `EXDEV` is returned (as
+    /// with `RESOLVE_NO_XDEV`).
+    ///
+    /// Lookups with strict checking enabled are slower, as they require
+    /// several syscalls per path component.
+    ///
+    /// # Errors
+    ///
+    /// `STATX_MNT_ID` was added in Linux 5.8. If the running kernel does not
+    /// support it, enabling strict checking will fail with
+    /// [`ErrorKind::NotSupported`] (rather than silently skipping the checks)
+    /// and the configuration is left unchanged.
+    pub fn set_strict_mnt_id(&mut self, strict: bool) -> Result<&mut Self, Error> {
+        if strict && self.mnt_id.is_none() {
+            Err(ErrorImpl::NotSupported {
+                feature: "STATX_MNT_ID".into(),
+            })
+            .wrap("strict procfs mount id checking requires statx(STATX_MNT_ID)")?
+        }
+        self.strict_mnt_id = strict;
+        Ok(self)
+    }
+
+    /// Enable (or disable) strict per-component mount checking.
+    ///
+    /// This is identical to [`ProcfsHandle::set_strict_mnt_id`] except that
+    /// it can more ergonomically be used for method chaining.
+    pub fn with_strict_mnt_id(mut self, strict: bool) -> Result<Self, Error> {
+        self.set_strict_mnt_id(strict)?;
+        Ok(self)
+    }
+
+    /// The resolver to use for lookups. Only the restricted `O_PATH` resolver
+    /// checks the mount ID of each component.
+    fn resolver(&self) -> &ProcfsResolver {
+        if self.strict_mnt_id {
+            &ProcfsResolver::RestrictedOpath
+        } else {
+            &self.resolver
+        }
     }
 
     fn open_base(&self, base: ProcfsBase) -> Result<OwnedFd, Error> {
         let proc_rootfd = self.inner.as_fd();
-        let fd = self.resolver.resolve(
+        let fd = self.resolver().resolve(
             proc_rootfd,
             base.into_path(Some(proc_rootfd)),
             OpenFlags::O_PATH | OpenFlags::O_DIRECTORY,
@@ -340,9 +457,86 @@ impl ProcfsHandle {
         subpath: P,
         oflags: F,
     ) -> Result<File, Error> {
-        let subpath = subpath.as_ref();
-        let mut oflags = oflags.into();
+        self.do_open_follow(base, subpath.as_ref(), oflags.into(), false)
+    }
+
+    /// Like [`ProcfsHandle::open_follow`], except that the final magic-link is
+    /// opened with exactly `oflags` (`O_CLOEXEC` and `O_NOCTTY` are not added
+    /// implicitly). `subpath` must refer to a magic-link.
+    pub(crate) fn open_follow_raw<P: AsRef<Path>>(
+        &self,
+        base: ProcfsBase,
+        subpath: P,
+        oflags: OpenFlags,
+    ) -> Result<File, Error> {
+        self.do_open_follow(base, subpath.as_ref(), oflags, true)
+    }
+
+    /// Batched version of [`ProcfsHandle::open_follow`] for opening many
+    /// entries of a `procfs` directory that only contains magic-links (such as
+    /// `/proc/thread-self/fd`). The final `openat(2)` of each magic-link is
+    /// submitted in bulk using `io_uring`.
+    ///
+    /// The returned results are in the same order as `names`. The outer error
+    /// is returned (without opening any of the magic-links) if `io_uring`
+    /// cannot be used, in which case callers should fall back to
+    /// [`ProcfsHandle::open_follow`].
+    #[cfg(feature = "io-uring")]
+    pub(crate) fn open_follow_batch<P: AsRef<Path>>(
+        &self,
+        base: ProcfsBase,
+        dir: P,
+        names: &[String],
+        oflags: OpenFlags,
+    ) -> Result<Vec<Result<File, Error>>, Error> {
+        let parent = self.open(base, dir, OpenFlags::O_PATH | OpenFlags::O_DIRECTORY)?;
+        // See ProcfsHandle::do_open_follow for why we use the mount ID of the
+        // parent directory and why the overmount check is needed.
+        let parent_mnt_id = utils::fetch_mnt_id(&parent, "")?;
+        let checks: Vec<_> = names
+            .iter()
+            .map(|name| verify_same_mnt(parent_mnt_id, &parent, name))
+            .collect();
+
+        let to_open: Vec<_> = names
+            .iter()
+            .zip(&checks)
+            .filter(|(_, check)| check.is_ok())
+            .map(|(name, _)| name)
+            .collect();
+        let mut opened = syscalls::openat_follow_batch(&parent, &to_open, oflags)
+            .map_err(|err| ErrorImpl::RawOsError {
+                operation: "batch open magiclinks".into(),
+                source: err,
+            })?
+            .into_iter();
+
+        Ok(checks
+            .into_iter()
+            .map(|check| {
+                check?;
+                opened
+                    .next()
+                    .expect("every checked magic-link should have been opened")
+                    .map(File::from)
+                    .map_err(|err| {
+                        ErrorImpl::RawOsError {
+                            operation: "open final magiclink component".into(),
+                            source: err,
+                        }
+                        .into()
+                    })
+            })
+            .collect())
+    }
 
+    fn do_open_follow(
+        &self,
+        base: ProcfsBase,
+        subpath: &Path,
+        mut oflags: OpenFlags,
+        raw_flags: bool,
+    ) -> Result<File, Error> {
         // Drop any trailing /-es.
         let (subpath, trailing_slash) = utils::path_strip_trailing_slash(subpath);
         if trailing_slash {
@@ -363,7 +557,14 @@ impl ProcfsHandle {
         //       being a magic-link and then another thing being mounted on top.
         //       This is the same race as below.
         if self.readlink(base, subpath).is_err() {
-            return self.open(base, subpath, oflags).map(File::from);
+            if raw_flags {
+                // ProcfsHandle::open always sets O_CLOEXEC and O_NOCTTY.
+                Err(ErrorImpl::InvalidArgument {
+                    name: "path".into(),
+                    description: "raw open flags are only supported for magic-links".into(),
+                })?
+            }
+            return self.open(base, subpath, oflags);
         }
 
         // Get a no-follow handle to the parent of the magic-link.
@@ -394,15 +595,19 @@ impl ProcfsHandle {
         // for the ProcfsHandle::{new_fsopen,new_open_tree} cases.
         verify_same_mnt(parent_mnt_id, &parent, trailing)?;
 
-        syscalls::openat_follow(parent, trailing, oflags, 0)
-            .map(File::from)
-            .map_err(|err| {
-                ErrorImpl::RawOsError {
-                    operation: "open final magiclink component".into(),
-                    source: err,
-                }
-                .into()
-            })
+        if raw_flags {
+            syscalls::openat_follow_raw(parent, trailing, oflags, 0)
+        } else {
+            syscalls::openat_follow(parent, trailing, oflags, 0)
+        }
+        .map(File::from)
+        .map_err(|err| {
+            ErrorImpl::RawOsError {
+                operation: "open final magiclink component".into(),
+                source: err,
+            }
+            .into()
+        })
     }
 
     /// Safely open a path inside `procfs`.
@@ -453,7 +658,7 @@ impl ProcfsHandle {
         let basedir = self.open_base(base)?;
         let subpath = subpath.as_ref();
         let fd = self
-            .resolver
+            .resolver()
             .resolve(&basedir, subpath, oflags, ResolverFlags::empty())
             .and_then(|fd| {
                 self.verify_same_procfs_mnt(&fd)?;
@@ -465,6 +670,7 @@ impl ProcfsHandle {
                     // procfs handle is "masked" in some way, try to create a
                     // temporary unmasked handle and retry the operation.
                     Self::new_unmasked()
+                        .and_then(|procfs| procfs.with_strict_mnt_id(self.strict_mnt_id))
                         // Use the old error if creating a new handle failed.
                         .or(Err(err))?
                         .open(base, subpath, oflags)
@@ -477,6 +683,86 @@ impl ProcfsHandle {
         Ok(fd.into())
     }
 
+    /// Safely open a path inside `/proc/self`.
+    ///
+    /// This is shorthand for [`ProcfsHandle::open`] with
+    /// [`ProcfsBase::ProcSelf`], except that the returned [`File`] is
+    /// additionally checked (with `fstatfs(2)`) to be on a real `procfs`
+    /// filesystem (`PROC_SUPER_MAGIC`). All of the caveats of
+    /// [`ProcfsHandle::open`] apply to this method as well.
+    ///
+    /// # Errors
+    ///
+    /// If an attacker has mounted something on top of `subpath` (or one of
+    /// its components), an [`ErrorKind::OsError`] with `EXDEV` is returned.
+    /// This is the same error returned by [`ProcfsHandle::open`] when it
+    /// detects an overmount (and matches the error returned by
+    /// `RESOLVE_NO_XDEV`).
+    ///
+    /// If `subpath` contains a `..` component (which could be used to open
+    /// files belonging to another process), an [`ErrorKind::InvalidArgument`]
+    /// is returned.
+    pub fn open_self<P: AsRef<Path>, F: Into<OpenFlags>>(
+        &self,
+        subpath: P,
+        oflags: F,
+    ) -> Result<File, Error> {
+        let subpath = subpath.as_ref();
+        check_no_dotdot(subpath, "/proc/self")?;
+        self.open_verified(ProcfsBase::ProcSelf, subpath, oflags.into())
+    }
+
+    /// Safely open a path inside `/proc/$pid`.
+    ///
+    /// This is identical to [`ProcfsHandle::open_self`] except that `subpath`
+    /// is relative to the `/proc/$pid` directory of the given process. Note
+    /// that (as with any pid-based API) the process may have died and the pid
+    /// may have been recycled by the time this method returns.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors returned by [`ProcfsHandle::open_self`], an
+    /// [`ErrorKind::InvalidArgument`] is returned if `pid` is not positive or
+    /// `subpath` is an absolute path (`subpath` also cannot contain a `..`
+    /// component, as with [`ProcfsHandle::open_self`]).
+    pub fn open_pid<P: AsRef<Path>, F: Into<OpenFlags>>(
+        &self,
+        pid: libc::pid_t,
+        subpath: P,
+        oflags: F,
+    ) -> Result<File, Error> {
+        let subpath = subpath.as_ref();
+        if pid <= 0 {
+            Err(ErrorImpl::InvalidArgument {
+                name: "pid".into(),
+                description: "must be a positive process id".into(),
+            })?
+        }
+        if subpath.is_absolute() {
+            Err(ErrorImpl::InvalidArgument {
+                name: "subpath".into(),
+                description: "must be relative to /proc/$pid".into(),
+            })?
+        }
+        check_no_dotdot(subpath, "/proc/$pid")?;
+        let subpath = PathBuf::from(pid.to_string()).join(subpath);
+        self.open_verified(ProcfsBase::ProcRoot, &subpath, oflags.into())
+    }
+
+    fn open_verified(
+        &self,
+        base: ProcfsBase,
+        subpath: &Path,
+        oflags: OpenFlags,
+    ) -> Result<File, Error> {
+        let file = self.open(base, subpath, oflags)?;
+        // ProcfsHandle::open already checks this, but it is critical for
+        // callers reading process information (and it is very cheap) so make
+        // sure that the final file really is a procfs file.
+        verify_is_procfs(&file)?;
+        Ok(file)
+    }
+
     /// Safely read the contents of a symlink inside `procfs`.
     ///
     /// This method is effectively shorthand for doing [`readlinkat(2)`] on the
@@ -548,10 +834,28 @@ impl ProcfsHandle {
             mnt_id,
             is_subset,
             resolver,
+            strict_mnt_id: false,
+            repin_with: None,
         })
     }
 }
 
+/// Reject `subpath`s containing a `..` component, which would let callers of
+/// [`ProcfsHandle::open_self`] and [`ProcfsHandle::open_pid`] escape the
+/// `/proc/$pid` directory they asked for.
+fn check_no_dotdot(subpath: &Path, base: &str) -> Result<(), Error> {
+    if subpath
+        .components()
+        .any(|part| part == Component::ParentDir)
+    {
+        Err(ErrorImpl::InvalidArgument {
+            name: "subpath".into(),
+            description: format!("must not contain '..' components to escape {base}").into(),
+        })?
+    }
+    Ok(())
+}
+
 pub(crate) fn verify_is_procfs<Fd: AsFd>(fd: Fd) -> Result<(), Error> {
     let fs_type = syscalls::fstatfs(fd)
         .map_err(|err| ErrorImpl::RawOsError {
@@ -598,7 +902,7 @@ pub(crate) fn verify_same_mnt<Fd: AsFd, P: AsRef<Path>>(
 mod tests {
     use super::*;
 
-    use std::fs::File;
+    use std::{fs::File, io::Read, os::unix::io::AsRawFd};
 
     #[test]
     fn bad_root() {
@@ -641,4 +945,154 @@ mod tests {
             "new procfs handle should succeed, got {procfs:?}",
         );
     }
+
+    #[test]
+    fn cloexec() {
+        for (name, procfs) in [
+            ("fsopen(subset)", ProcfsHandle::new_fsopen(true)),
+            ("fsopen", ProcfsHandle::new_fsopen(false)),
+            (
+                "open_tree",
+                ProcfsHandle::new_open_tree(OpenTreeFlags::empty()),
+            ),
+            (
+                "open_tree(recursive)",
+                ProcfsHandle::new_open_tree(OpenTreeFlags::AT_RECURSIVE),
+            ),
+            ("open", ProcfsHandle::new_unsafe_open()),
+        ] {
+            // Not all of these are available to unprivileged users.
+            if let Ok(procfs) = procfs {
+                let fdflags = rustix::io::fcntl_getfd(&procfs.inner).expect("F_GETFD");
+                assert!(
+                    fdflags.contains(rustix::io::FdFlags::CLOEXEC),
+                    "{name} procfs handle should be O_CLOEXEC",
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn open_self() {
+        let procfs = ProcfsHandle::new().expect("new procfs handle");
+
+        let mut status = String::new();
+        procfs
+            .open_self("status", OpenFlags::O_RDONLY)
+            .expect("open_self(status)")
+            .read_to_string(&mut status)
+            .expect("read /proc/self/status");
+        assert!(
+            status
+                .lines()
+                .any(|line| line == format!("Pid:\t{}", syscalls::getpid())),
+            "/proc/self/status should be for our process: {status:?}"
+        );
+
+        for subpath in ["../1/status", "fd/../../1/status", ".."] {
+            assert_eq!(
+                procfs
+                    .open_self(subpath, OpenFlags::O_RDONLY)
+                    .map(|_| ())
+                    .map_err(|err| err.kind()),
+                Err(ErrorKind::InvalidArgument),
+                "open_self({subpath:?}) should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn open_pid() {
+        let procfs = ProcfsHandle::new().expect("new procfs handle");
+        let pid = syscalls::getpid();
+
+        let mut status = String::new();
+        procfs
+            .open_pid(pid, "status", OpenFlags::O_RDONLY)
+            .expect("open_pid(status)")
+            .read_to_string(&mut status)
+            .expect("read /proc/$pid/status");
+        assert!(
+            status.lines().any(|line| line == format!("Pid:\t{pid}")),
+            "/proc/{pid}/status should be for the requested process: {status:?}"
+        );
+
+        for (pid, subpath) in [
+            (0, "status"),
+            (-1, "status"),
+            (pid, "/status"),
+            (pid, "../1/status"),
+            (pid, "task/../../1/status"),
+            (pid, ".."),
+        ] {
+            assert_eq!(
+                procfs
+                    .open_pid(pid, subpath, OpenFlags::O_RDONLY)
+                    .map(|_| ())
+                    .map_err(|err| err.kind()),
+                Err(ErrorKind::InvalidArgument),
+                "open_pid({pid}, {subpath:?}) should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn repin() {
+        let mut procfs = ProcfsHandle::new().expect("new procfs handle");
+        let old_fd = procfs.inner.as_raw_fd();
+        procfs.repin().expect("repin procfs handle");
+        assert_ne!(
+            procfs.inner.as_raw_fd(),
+            old_fd,
+            "repin should replace the pinned /proc handle"
+        );
+        procfs
+            .open(ProcfsBase::ProcSelf, "status", OpenFlags::O_RDONLY)
+            .expect("re-pinned procfs handle should be usable");
+
+        let file = File::open("/proc").expect("open /proc");
+        let mut procfs = ProcfsHandle::try_from_fd(file).expect("procfs handle from fd");
+        assert_eq!(
+            procfs.repin().map_err(|err| err.kind()),
+            Err(ErrorKind::InvalidArgument),
+            "try_from_fd handles cannot be re-pinned"
+        );
+    }
+
+    #[test]
+    fn strict_mnt_id() {
+        let mut procfs = ProcfsHandle::new().expect("new procfs handle");
+        assert!(
+            !procfs.strict_mnt_id(),
+            "strict mount id checking should be disabled by default"
+        );
+        if procfs.mnt_id.is_none() {
+            assert_eq!(
+                procfs
+                    .set_strict_mnt_id(true)
+                    .map(|_| ())
+                    .map_err(|err| err.kind()),
+                Err(ErrorKind::NotSupported),
+                "strict mount id checking requires STATX_MNT_ID"
+            );
+            return;
+        }
+
+        procfs
+            .set_strict_mnt_id(true)
+            .expect("enable strict mount id checking");
+        let mut status = String::new();
+        procfs
+            .open(ProcfsBase::ProcSelf, "status", OpenFlags::O_RDONLY)
+            .expect("open /proc/self/status with strict mount id checking")
+            .read_to_string(&mut status)
+            .expect("read /proc/self/status");
+        assert!(status.contains("Pid:"), "status file should be readable");
+
+        procfs.repin().expect("repin procfs handle");
+        assert!(
+            procfs.strict_mnt_id(),
+            "repin should preserve strict mount id checking"
+        );
+    }
 }
@@ -22,23 +22,32 @@
 //! Resolver implementations for libpathrs.
 
 use crate::{
-    error::{Error, ErrorImpl, ErrorKind},
+    error::{Error, ErrorExt, ErrorImpl, ErrorKind},
     flags::{OpenFlags, ResolverFlags},
-    syscalls,
-    utils::FdExt,
-    Handle,
+    kernel, syscalls,
+    utils::{self, FdExt, PathIterExt},
+    Handle, PublicResolverBackend, ResolveInfo, SetidPolicy, TrailingSymlinkPolicy,
 };
 
 use std::{
+    ffi::OsStr,
+    fmt,
     fs::File,
     io::Error as IOError,
-    os::unix::io::{AsFd, OwnedFd},
-    path::{Path, PathBuf},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::MetadataExt,
+        io::{AsFd, BorrowedFd, OwnedFd},
+    },
+    panic::RefUnwindSafe,
+    path::{Component, Path, PathBuf},
     rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        PoisonError, RwLock,
+    },
 };
 
-use once_cell::sync::Lazy;
-
 /// `O_PATH`-based userspace resolver.
 pub(crate) mod opath {
     mod imp;
@@ -51,12 +60,26 @@ pub(crate) mod opath {
 /// `openat2(2)`-based in-kernel resolver.
 pub(crate) mod openat2;
 
+/// `pivot_root(2)`-based resolver using a private mount namespace.
+pub(crate) mod hardcore;
+
 /// A limited resolver only used for `/proc` lookups in `ProcfsHandle`.
 pub(crate) mod procfs;
 
-/// Maximum number of symlink traversals we will accept.
+/// Default maximum number of symlink traversals we will accept.
 const MAX_SYMLINK_TRAVERSALS: usize = 128;
 
+/// Default maximum number of bytes of symlink targets we will expand during a
+/// single resolution.
+///
+/// The kernel follows at most `MAXSYMLINKS` (40) symlinks in a single lookup,
+/// and a symlink target can be at most `PATH_MAX - 1` bytes long, so a kernel
+/// lookup can never expand more than this many bytes before failing with
+/// `ELOOP`. Using the same bound means that chains of long symlinks are
+/// rejected by the emulated resolver in the same cases as `openat2(2)` (even
+/// though our default symlink count limit is higher than the kernel's).
+const MAX_SYMLINK_TARGET_BYTES: usize = 40 * (libc::PATH_MAX as usize - 1);
+
 /// The backend used for path resolution within a [`Root`] to get a [`Handle`].
 ///
 /// We don't generally recommend specifying this, since libpathrs will
@@ -72,23 +95,25 @@ pub(crate) enum ResolverBackend {
     KernelOpenat2,
     /// Use the userspace "emulated" backend.
     EmulatedOpath,
-    // TODO: Implement a HardcoreEmulated which does pivot_root(2) and all the
-    //       rest of it. It'd be useful to compare against and for some
-    //       hyper-concerned users.
+    /// Do the lookup inside a private mount namespace which has been
+    /// `pivot_root(2)`-ed into the root, so that escaping the root is
+    /// physically impossible. This is much slower than the other backends and
+    /// requires `CAP_SYS_ADMIN` (to create a mount namespace), so it is never
+    /// selected by default.
+    HardcoreEmulated,
 }
 
-// MSRV(1.80): Use LazyLock.
-static DEFAULT_RESOLVER_TYPE: Lazy<ResolverBackend> = Lazy::new(|| {
-    if *syscalls::OPENAT2_IS_SUPPORTED {
-        ResolverBackend::KernelOpenat2
-    } else {
-        ResolverBackend::EmulatedOpath
-    }
-});
-
 impl Default for ResolverBackend {
+    /// Returns [`ResolverBackend::KernelOpenat2`] if `openat2(2)` is
+    /// supported by the running kernel, and
+    /// [`ResolverBackend::EmulatedOpath`] otherwise. The kernel is only probed
+    /// the first time this is called.
     fn default() -> Self {
-        *DEFAULT_RESOLVER_TYPE
+        if kernel::kernel_features().has_openat2 {
+            ResolverBackend::KernelOpenat2
+        } else {
+            ResolverBackend::EmulatedOpath
+        }
     }
 }
 
@@ -96,10 +121,7 @@ impl ResolverBackend {
     /// Checks if the resolver is supported on the current platform.
     #[cfg(test)]
     pub(crate) fn supported(self) -> bool {
-        match self {
-            ResolverBackend::KernelOpenat2 => *syscalls::OPENAT2_IS_SUPPORTED,
-            ResolverBackend::EmulatedOpath => true,
-        }
+        PublicResolverBackend::from(self).is_supported()
     }
 }
 
@@ -108,26 +130,97 @@ impl ResolverBackend {
 /// This is the primary structure used to configure how a given [`Root`] will
 /// conduct path resolutions.
 ///
+/// NOTE: This type is not exported from the crate, so it intentionally does
+///       not implement `serde::Serialize` or `serde::Deserialize`. Users who
+///       want to store this configuration serialise [`ResolverFlags`] and
+///       [`PublicResolverBackend`] instead.
+///
 /// [`Root`]: crate::Root
-#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct Resolver {
     /// Underlying resolution backend used.
     pub(crate) backend: ResolverBackend,
     /// Flags to pass to the resolution backend.
     pub flags: ResolverFlags,
+    /// If set, regular files returned by [`Resolver::resolve`] are eagerly
+    /// re-opened with these flags rather than returned as `O_PATH` handles.
+    pub(crate) eager_reopen: Option<OpenFlags>,
+    /// If set, overrides the maximum number of symlinks that will be followed
+    /// during a single resolution (the default is 128). This is only enforced
+    /// by the emulated backend.
+    pub(crate) max_symlink_traversals: Option<usize>,
+    /// If set, overrides the maximum total length (in bytes) of symlink
+    /// targets that will be expanded during a single resolution (the default
+    /// is [`MAX_SYMLINK_TARGET_BYTES`]). This is only enforced by the emulated
+    /// backend.
+    pub(crate) max_symlink_target_bytes: Option<usize>,
+    /// How setuid and setgid inodes encountered during resolution are
+    /// treated.
+    pub(crate) setid_policy: SetidPolicy,
+    /// Whether resolution fails if it walks through a directory which has
+    /// been deleted (its link count is zero). This is only enforced by the
+    /// emulated backend.
+    pub(crate) reject_deleted_intermediates: bool,
+    /// How symlinks in the final component of a path are followed. This is
+    /// only enforced by the emulated backend.
+    pub(crate) trailing_symlink_policy: TrailingSymlinkPolicy,
+    /// If set, the [`ResolveObserver`] notified about resolutions done with
+    /// this resolver (overriding any global observer).
+    pub(crate) observer: Option<&'static dyn ResolveObserver>,
 }
 
+// ResolveObserver doesn't implement PartialEq, so compare observers by their
+// address.
+impl PartialEq for Resolver {
+    fn eq(&self, other: &Self) -> bool {
+        self.backend == other.backend
+            && self.flags == other.flags
+            && self.eager_reopen == other.eager_reopen
+            && self.max_symlink_traversals == other.max_symlink_traversals
+            && self.max_symlink_target_bytes == other.max_symlink_target_bytes
+            && self.setid_policy == other.setid_policy
+            && self.reject_deleted_intermediates == other.reject_deleted_intermediates
+            && self.trailing_symlink_policy == other.trailing_symlink_policy
+            && self.observer.map(observer_addr) == other.observer.map(observer_addr)
+    }
+}
+
+impl Eq for Resolver {}
+
 /// Only used for internal resolver implementations.
 #[derive(Debug)]
 pub(crate) enum PartialLookup<H, E = Error> {
     Complete(H),
     Partial {
         handle: H,
+        /// A path (relative to the root) which refers to `handle`, describing
+        /// how far the lookup got before it failed. For the `openat2(2)` and
+        /// hardcore resolvers this is the walked prefix of the requested path
+        /// (with `remaining` being the rest of it), while the emulated
+        /// resolver reports the path it was tracking for `handle`.
+        resolved: PathBuf,
         remaining: PathBuf,
         last_error: E,
+        /// Whether `last_error` is an `ENOENT` caused by following a dangling
+        /// symlink (the first component of `remaining`), as opposed to a
+        /// genuinely missing directory entry. Callers creating the missing
+        /// components must not try to create the target of such a symlink.
+        dangling_symlink: bool,
     },
 }
 
+impl<H, E> PartialLookup<H, E> {
+    /// The portion of the path which was successfully resolved before the
+    /// lookup failed, or `None` if the lookup was complete.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn resolved_prefix(&self) -> Option<&Path> {
+        match self {
+            Self::Complete(_) => None,
+            Self::Partial { resolved, .. } => Some(resolved),
+        }
+    }
+}
+
 impl<H> AsRef<H> for PartialLookup<H> {
     fn as_ref(&self) -> &H {
         match self {
@@ -166,6 +259,7 @@ impl TryInto<(Handle, Option<PathBuf>)> for PartialLookup<Handle> {
                 handle,
                 remaining,
                 last_error,
+                ..
             } => match last_error.kind() {
                 ErrorKind::OsError(Some(libc::ENOENT)) => Ok((handle, Some(remaining))),
                 _ => Err(last_error),
@@ -180,9 +274,14 @@ impl From<PartialLookup<Rc<OwnedFd>>> for PartialLookup<Handle> {
             PartialLookup::Complete(rc) => (rc, None),
             PartialLookup::Partial {
                 handle,
+                resolved,
                 remaining,
                 last_error,
-            } => (handle, Some((remaining, last_error))),
+                dangling_symlink,
+            } => (
+                handle,
+                Some((resolved, remaining, last_error, dangling_symlink)),
+            ),
         };
 
         // We are now sure that there is only a single reference to whatever
@@ -196,23 +295,318 @@ impl From<PartialLookup<Rc<OwnedFd>>> for PartialLookup<Handle> {
 
         match partial {
             None => Self::Complete(handle),
-            Some((remaining, last_error)) => Self::Partial {
+            Some((resolved, remaining, last_error, dangling_symlink)) => Self::Partial {
                 handle,
+                resolved,
                 remaining,
                 last_error,
+                dangling_symlink,
             },
         }
     }
 }
 
+/// Check whether a partial lookup stopped because the first component of
+/// `remaining` is a dangling symlink. This is used by the resolvers which
+/// cannot keep track of this during the lookup itself.
+fn is_dangling_symlink<Fd: AsFd>(handle: Fd, remaining: &Path, last_error: &Error) -> bool {
+    if last_error.kind() != ErrorKind::OsError(Some(libc::ENOENT)) {
+        return false;
+    }
+    match remaining.raw_components().next() {
+        Some(first) if !first.is_empty() => syscalls::fstatat(handle, first)
+            .map(|stat| stat.st_mode & libc::S_IFMT == libc::S_IFLNK)
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Get the leading portion of `path` which was walked by a partial lookup that
+/// stopped at `remaining`, or `None` if `remaining` is not a suffix of `path`.
+/// An empty prefix is returned as `"."`.
+fn walked_prefix(path: &Path, remaining: &Path) -> Option<PathBuf> {
+    let prefix = path
+        .as_os_str()
+        .as_bytes()
+        .strip_suffix(remaining.as_os_str().as_bytes())
+        .map(|prefix| utils::path_strip_trailing_slash(Path::new(OsStr::from_bytes(prefix))).0)?;
+    Some(if prefix.as_os_str().is_empty() {
+        ".".into()
+    } else {
+        prefix.into()
+    })
+}
+
+/// Guard for the one-time resolver diagnostic message.
+#[cfg(feature = "tracing")]
+static RESOLVER_DIAGNOSTIC: std::sync::Once = std::sync::Once::new();
+
+/// Emit a one-time diagnostic message (guarded by `once`) describing the
+/// resolver configuration in use. This is only really useful for debugging
+/// issues in the field, where it is not obvious which resolver backend was
+/// picked for the running kernel.
+#[cfg(feature = "tracing")]
+fn log_resolver_diagnostic(once: &std::sync::Once, backend: ResolverBackend) {
+    once.call_once(|| {
+        tracing::info!(
+            ?backend,
+            default_backend = ?ResolverBackend::default(),
+            openat2_supported = kernel::kernel_features().has_openat2,
+            reopen_strategy = "procfs",
+            "libpathrs resolver configuration",
+        )
+    });
+}
+
+/// Whether paths are redacted from tracing spans and events. See
+/// [`set_tracing_redact_paths`].
+#[cfg(feature = "tracing")]
+static TRACING_REDACT_PATHS: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
+
+/// Configure whether the contents of paths are redacted from the `tracing`
+/// spans and events emitted by libpathrs.
+///
+/// Paths being resolved can contain sensitive information (such as user names
+/// or the names of private files), which you might not want to end up in your
+/// application's logs. If enabled, all path fields are replaced with
+/// `"<redacted>"` (and the `path_redacted` field of resolution spans is set to
+/// `true`). This setting applies to the whole process, and is disabled by
+/// default.
+///
+/// This is only available with the `tracing` feature.
+#[cfg(feature = "tracing")]
+pub fn set_tracing_redact_paths(redact: bool) {
+    TRACING_REDACT_PATHS.store(redact, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// Returns the value to use for a path in a `tracing` span or event, taking
+/// [`set_tracing_redact_paths`] into account.
+#[cfg(feature = "tracing")]
+pub(crate) fn traced_path(path: &Path) -> std::borrow::Cow<'_, str> {
+    if TRACING_REDACT_PATHS.load(std::sync::atomic::Ordering::Relaxed) {
+        "<redacted>".into()
+    } else {
+        path.to_string_lossy()
+    }
+}
+
+/// An observer which is notified about path resolutions, to allow for
+/// collecting metrics about resolutions without libpathrs depending on any
+/// particular metrics library.
+///
+/// Observers can be registered for the whole process (with
+/// [`set_global_resolve_observer`]) or for a single [`Root`] (with
+/// [`Root::set_resolve_observer`]), in which case the per-[`Root`] observer is
+/// used instead of the global one. All methods have empty default
+/// implementations, so you only need to implement the events you are
+/// interested in.
+///
+/// The methods are called synchronously from the thread doing the resolution,
+/// so they should be cheap (incrementing some counters is the intended use).
+/// Observers must be [`RefUnwindSafe`] so that [`Root`] stays unwind-safe when
+/// one is registered. All arguments are `Copy` so that no allocations are done
+/// on behalf of an observer.
+///
+/// Note that only the emulated resolver walks paths in userspace, so
+/// [`symlink_followed`] and [`escape_blocked`] are never called for
+/// resolutions done in-kernel by `openat2(2)`. If the symlink traversal limit
+/// is hit, the resolution fails with [`ErrorKind::OsError`]`(Some(ELOOP))`.
+///
+/// [`Root`]: crate::Root
+/// [`Root::set_resolve_observer`]: crate::Root::set_resolve_observer
+/// [`symlink_followed`]: Self::symlink_followed
+/// [`escape_blocked`]: Self::escape_blocked
+pub trait ResolveObserver: fmt::Debug + Send + Sync + RefUnwindSafe {
+    /// Called when a resolution starts, with the backend that will be used.
+    fn resolve_start(&self, _backend: PublicResolverBackend) {}
+
+    /// Called every time a symlink is followed. `symlink_traversals` is the
+    /// number of symlinks followed so far in this resolution (including this
+    /// one).
+    fn symlink_followed(&self, _symlink_traversals: usize) {}
+
+    /// Called when a `..` component which would have escaped the root was
+    /// blocked (either by clamping it to the root or by rejecting it with
+    /// [`ResolverFlags::BENEATH`]).
+    fn escape_blocked(&self) {}
+
+    /// Called when a resolution ends, with the backend used and the kind of
+    /// error if the resolution failed.
+    fn resolve_end(&self, _backend: PublicResolverBackend, _result: Result<(), ErrorKind>) {}
+}
+
+/// The address of an observer, used for comparing observers by identity.
+fn observer_addr(observer: &'static dyn ResolveObserver) -> *const () {
+    observer as *const dyn ResolveObserver as *const ()
+}
+
+/// The observer registered with [`set_global_resolve_observer`].
+static GLOBAL_RESOLVE_OBSERVER: RwLock<Option<&'static dyn ResolveObserver>> = RwLock::new(None);
+
+/// Whether [`GLOBAL_RESOLVE_OBSERVER`] is set, so that we can skip taking the
+/// lock in the (common) case where no observer has been registered.
+static HAS_GLOBAL_RESOLVE_OBSERVER: AtomicBool = AtomicBool::new(false);
+
+/// Register (or, with `None`, unregister) a [`ResolveObserver`] which is
+/// notified about all resolutions in this process.
+///
+/// [`Root`]s with their own observer (see [`Root::set_resolve_observer`]) do
+/// not notify the global observer. If no observer is registered, the overhead
+/// is a single atomic load per resolution.
+///
+/// [`Root`]: crate::Root
+/// [`Root::set_resolve_observer`]: crate::Root::set_resolve_observer
+pub fn set_global_resolve_observer(observer: Option<&'static dyn ResolveObserver>) {
+    let mut global = GLOBAL_RESOLVE_OBSERVER
+        .write()
+        .unwrap_or_else(PoisonError::into_inner);
+    *global = observer;
+    HAS_GLOBAL_RESOLVE_OBSERVER.store(observer.is_some(), Ordering::Release);
+}
+
 impl Resolver {
+    /// Force the use of a particular backend, if it is supported.
+    pub(crate) fn force_backend(&mut self, backend: PublicResolverBackend) -> Result<(), Error> {
+        if !backend.is_supported() {
+            Err(ErrorImpl::NotImplemented {
+                feature: format!("{backend:?} resolver backend").into(),
+            })?
+        }
+        self.backend = backend.into();
+        Ok(())
+    }
+
+    /// The [`ResolveObserver`] to notify about resolutions, if any.
+    #[inline]
+    pub(crate) fn observer(&self) -> Option<&'static dyn ResolveObserver> {
+        self.observer.or_else(|| {
+            if HAS_GLOBAL_RESOLVE_OBSERVER.load(Ordering::Acquire) {
+                *GLOBAL_RESOLVE_OBSERVER
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Run the resolution `f`, notifying the [`ResolveObserver`] (if any) when
+    /// it starts and ends.
+    #[inline]
+    fn observe<T, F: FnOnce() -> Result<T, Error>>(&self, f: F) -> Result<T, Error> {
+        match self.observer() {
+            None => f(),
+            Some(observer) => {
+                let backend = self.effective_backend().into();
+                observer.resolve_start(backend);
+                let ret = f();
+                observer.resolve_end(backend, ret.as_ref().map(|_| ()).map_err(Error::kind));
+                ret
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn max_symlinks(&self) -> usize {
+        self.max_symlink_traversals
+            .unwrap_or(MAX_SYMLINK_TRAVERSALS)
+    }
+
+    #[inline]
+    pub(crate) fn max_symlink_target_bytes(&self) -> usize {
+        self.max_symlink_target_bytes
+            .unwrap_or(MAX_SYMLINK_TARGET_BYTES)
+    }
+
+    /// The backend actually used for lookups. Rejecting setuid and setgid (or
+    /// deleted) intermediate components and limiting trailing symlinks can
+    /// only be done by the emulated resolver.
+    #[inline]
+    pub(crate) fn effective_backend(&self) -> ResolverBackend {
+        if self.setid_policy.rejects_intermediate()
+            || self.reject_deleted_intermediates
+            || self.trailing_symlink_policy != TrailingSymlinkPolicy::FollowUnlimited
+        {
+            ResolverBackend::EmulatedOpath
+        } else {
+            self.backend
+        }
+    }
+
+    /// Apply the `setid_policy` to the final target of a resolution.
+    fn check_setid_target(&self, handle: &Handle) -> Result<(), Error> {
+        if self.setid_policy.rejects_target() {
+            let meta = handle
+                .metadata()
+                .wrap("fstat resolved target for setid policy")?;
+            if meta.mode() & (libc::S_ISUID | libc::S_ISGID) != 0 {
+                Err(ErrorImpl::SafetyViolation {
+                    description: "resolved target has setuid or setgid bit set".into(),
+                })?
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply [`ResolverFlags::SINGLE_COMPONENT`] to `path`.
+    pub(crate) fn check_path(&self, path: &Path) -> Result<(), Error> {
+        if self.flags.contains(ResolverFlags::SINGLE_COMPONENT) {
+            let mut names = 0;
+            for component in path.components() {
+                match component {
+                    Component::Normal(_) => names += 1,
+                    Component::ParentDir => Err(ErrorImpl::InvalidArgument {
+                        name: "path".into(),
+                        description: "path cannot contain '..' components with SINGLE_COMPONENT"
+                            .into(),
+                    })?,
+                    // "/" and "." all refer to the root.
+                    Component::RootDir | Component::CurDir | Component::Prefix(_) => (),
+                }
+            }
+            if names > 1 {
+                Err(ErrorImpl::InvalidArgument {
+                    name: "path".into(),
+                    description: "path cannot have more than one component with SINGLE_COMPONENT"
+                        .into(),
+                })?
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn diagnostic(&self) {
+        #[cfg(feature = "tracing")]
+        log_resolver_diagnostic(&RESOLVER_DIAGNOSTIC, self.backend);
+    }
+
+    /// Create a `tracing` span for a resolution of `path` within `root`.
+    #[cfg(feature = "tracing")]
+    fn span<Fd: AsFd>(&self, operation: &'static str, root: Fd, path: &Path) -> tracing::Span {
+        use std::os::unix::io::AsRawFd;
+
+        tracing::debug_span!(
+            "resolve",
+            operation,
+            root_fd = root.as_fd().as_raw_fd(),
+            path = %traced_path(path),
+            path_redacted = TRACING_REDACT_PATHS.load(std::sync::atomic::Ordering::Relaxed),
+            backend = ?self.effective_backend(),
+        )
+    }
+
     pub(crate) fn open<Fd: AsFd, P: AsRef<Path>, F: Into<OpenFlags>>(
         &self,
         root: Fd,
+        pivot_scratch: Option<BorrowedFd<'_>>,
         path: P,
         flags: F,
     ) -> Result<File, Error> {
         let flags = flags.into();
+        self.diagnostic();
+        self.check_path(path.as_ref())?;
 
         // O_CREAT cannot be emulated by the O_PATH resolver (and in the
         // fallback case the flag gets silently ignored unless you also set
@@ -224,67 +618,283 @@ impl Resolver {
             })?
         }
 
-        match self.backend {
-            // openat2 can do the lookup and open in one syscall.
-            ResolverBackend::KernelOpenat2 => openat2::open(root, path.as_ref(), self.flags, flags),
+        match self.effective_backend() {
+            // openat2 can do the lookup and open in one syscall (unless we
+            // need to check the mode of the target).
+            ResolverBackend::KernelOpenat2 if self.setid_policy == SetidPolicy::Allow => self
+                .observe(|| {
+                    openat2::open(&root, path.as_ref(), self.flags, flags).or_else(|err| {
+                        let no_follow = flags.contains(OpenFlags::O_NOFOLLOW);
+                        // EPERM from an open without O_PATH is usually a real
+                        // permission error rather than openat2(2) being
+                        // blocked, so only fall back if an O_PATH lookup of
+                        // the same path is blocked as well.
+                        if !flags.contains(OpenFlags::O_PATH)
+                            && openat2::blocked_errno(&err) == Some(libc::EPERM)
+                        {
+                            match openat2::resolve(&root, path.as_ref(), self.flags, no_follow) {
+                                Err(probe) if openat2::blocked_errno(&probe).is_some() => {}
+                                _ => return Err(err),
+                            }
+                        }
+                        self.openat2_fallback("open", err, || {
+                            opath::resolve(&root, path.as_ref(), self, no_follow)
+                                .and_then(|handle| Self::open_handle(handle, flags))
+                        })
+                    })
+                }),
 
             // For backends without an accelerated one-shot open()
             // implementation, we can just do the lookup+reopen thing in one go.
             // For cffi users, this makes plain "open" operations faster.
             _ => {
-                let handle = self.resolve(root, path, flags.contains(OpenFlags::O_NOFOLLOW))?;
-
-                // O_NOFOLLOW makes things a little tricky. Unlike
-                // FdExt::reopen, we have to support O_NOFOLLOW|O_PATH of
-                // symlinks, but that is easily emulated by returning the handle
-                // directly without a reopen.
-                if handle.metadata()?.is_symlink() {
-                    // If the user also asked for O_DIRECTORY, make sure we
-                    // return the right error.
-                    if flags.contains(OpenFlags::O_DIRECTORY) {
-                        Err(ErrorImpl::OsError {
-                            operation: "emulated openat2".into(),
-                            source: IOError::from_raw_os_error(libc::ENOTDIR),
-                        })?;
-                    }
-
-                    // If the user requested O_PATH|O_NOFOLLOW, then the only
-                    // option we have is to return the handle we got. Without
-                    // O_EMPTYPATH there is no easy way to apply any extra flags
-                    // a user might've requested.
-                    // TODO: Should we error out if the user asks for extra
-                    // flags that don't match the flags for our handles?
-                    if flags.contains(OpenFlags::O_PATH) {
-                        return Ok(OwnedFd::from(handle).into());
-                    }
+                let handle = self.resolve(
+                    root,
+                    pivot_scratch,
+                    path,
+                    flags.contains(OpenFlags::O_NOFOLLOW),
+                )?;
+                Self::open_handle(handle, flags)
+            }
+        }
+    }
 
-                    // Otherwise, the user asked for O_NOFOLLOW and we saw a
-                    // symlink, so return ELOOP like openat2 would.
-                    Err(ErrorImpl::OsError {
-                        operation: "emulated openat2".into(),
-                        source: IOError::from_raw_os_error(libc::ELOOP),
-                    })?;
-                }
+    /// Re-open a handle returned by an emulated one-shot open with `flags`.
+    fn open_handle(handle: Handle, flags: OpenFlags) -> Result<File, Error> {
+        // O_NOFOLLOW makes things a little tricky. Unlike FdExt::reopen, we
+        // have to support O_NOFOLLOW|O_PATH of symlinks, but that is easily
+        // emulated by returning the handle directly without a reopen.
+        if handle.metadata()?.is_symlink() {
+            // If the user also asked for O_DIRECTORY, make sure we return the
+            // right error.
+            if flags.contains(OpenFlags::O_DIRECTORY) {
+                Err(ErrorImpl::OsError {
+                    operation: "emulated openat2".into(),
+                    source: IOError::from_raw_os_error(libc::ENOTDIR),
+                })?;
+            }
 
-                handle.reopen(flags)
+            // If the user requested O_PATH|O_NOFOLLOW, then the only option we
+            // have is to return the handle we got. Without O_EMPTYPATH there
+            // is no easy way to apply any extra flags a user might've
+            // requested.
+            // TODO: Should we error out if the user asks for extra flags that
+            // don't match the flags for our handles?
+            if flags.contains(OpenFlags::O_PATH) {
+                return Ok(OwnedFd::from(handle).into());
             }
+
+            // Otherwise, the user asked for O_NOFOLLOW and we saw a symlink,
+            // so return ELOOP like openat2 would.
+            Err(ErrorImpl::OsError {
+                operation: "emulated openat2".into(),
+                source: IOError::from_raw_os_error(libc::ELOOP),
+            })?;
         }
+
+        handle.reopen(flags)
+    }
+
+    /// Handle an error from an `openat2(2)`-based `operation`. If `openat2(2)`
+    /// was blocked for this call (see [`openat2::blocked_errno`]), the
+    /// operation is retried with the emulated resolver (`fallback`) and a
+    /// warning is emitted (with the `tracing` feature) so that seccomp
+    /// policies silently degrading the resolver can be detected.
+    fn openat2_fallback<T, F>(
+        &self,
+        operation: &'static str,
+        err: Error,
+        fallback: F,
+    ) -> Result<T, Error>
+    where
+        F: FnOnce() -> Result<T, Error>,
+    {
+        let errno = match openat2::blocked_errno(&err) {
+            Some(errno) => errno,
+            None => return Err(err),
+        };
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            operation,
+            errno,
+            fallback_backend = ?ResolverBackend::EmulatedOpath,
+            "openat2 was blocked, falling back to the emulated resolver",
+        );
+        #[cfg(not(feature = "tracing"))]
+        let _ = (operation, errno);
+        fallback()
     }
 
+    /// Resolve `path` within `root`. `pivot_scratch` is the scratch directory
+    /// used by [`ResolverBackend::HardcoreEmulated`] (if `None`, a private
+    /// `tmpfs` is created for each resolution) and is ignored by the other
+    /// backends. It is passed separately rather than stored in the
+    /// [`Resolver`] so that the configuration doesn't borrow any file
+    /// descriptors.
     #[inline]
     pub(crate) fn resolve<Fd: AsFd, P: AsRef<Path>>(
         &self,
         root: Fd,
+        pivot_scratch: Option<BorrowedFd<'_>>,
         path: P,
         no_follow_trailing: bool,
     ) -> Result<Handle, Error> {
-        match self.backend {
-            ResolverBackend::KernelOpenat2 => {
-                openat2::resolve(root, path, self.flags, no_follow_trailing)
+        #[cfg(feature = "tracing")]
+        let _span = self.span("resolve", &root, path.as_ref()).entered();
+        self.diagnostic();
+        self.observe(|| {
+            self.check_path(path.as_ref())?;
+            let handle = match self.effective_backend() {
+                ResolverBackend::KernelOpenat2 => {
+                    openat2::resolve(&root, path.as_ref(), self.flags, no_follow_trailing).or_else(
+                        |err| {
+                            self.openat2_fallback("resolve", err, || {
+                                opath::resolve(&root, path.as_ref(), self, no_follow_trailing)
+                            })
+                        },
+                    )
+                }
+                ResolverBackend::EmulatedOpath => {
+                    opath::resolve(root, path, self, no_follow_trailing)
+                }
+                ResolverBackend::HardcoreEmulated => {
+                    hardcore::resolve(root, pivot_scratch, path, self.flags, no_follow_trailing)
+                }
+            }?;
+            self.check_setid_target(&handle)?;
+            Ok(handle)
+        })
+    }
+
+    /// Like [`Resolver::resolve`], but also return the `(dev, ino)` of every
+    /// component visited during the walk. Only the emulated backend walks the
+    /// path in userspace, so the trail is empty for the other backends.
+    pub(crate) fn resolve_with_inode_trail<Fd: AsFd, P: AsRef<Path>>(
+        &self,
+        root: Fd,
+        pivot_scratch: Option<BorrowedFd<'_>>,
+        path: P,
+        no_follow_trailing: bool,
+    ) -> Result<(Handle, Vec<(u64, u64)>), Error> {
+        match self.effective_backend() {
+            ResolverBackend::EmulatedOpath => {
+                self.diagnostic();
+                self.observe(|| {
+                    self.check_path(path.as_ref())?;
+                    let (handle, trail) =
+                        opath::resolve_with_inode_trail(root, path, self, no_follow_trailing)?;
+                    self.check_setid_target(&handle)?;
+                    Ok((handle, trail))
+                })
             }
+            _ => Ok((
+                self.resolve(root, pivot_scratch, path, no_follow_trailing)?,
+                Vec::new(),
+            )),
+        }
+    }
+
+    /// Like [`Resolver::resolve`], but also return a [`ResolveInfo`] describing
+    /// the walk. Only the emulated backend walks the path in userspace, so the
+    /// walk statistics are only available for that backend.
+    pub(crate) fn resolve_with_info<Fd: AsFd, P: AsRef<Path>>(
+        &self,
+        root: Fd,
+        pivot_scratch: Option<BorrowedFd<'_>>,
+        path: P,
+        no_follow_trailing: bool,
+    ) -> Result<(Handle, ResolveInfo), Error> {
+        match self.effective_backend() {
             ResolverBackend::EmulatedOpath => {
-                opath::resolve(root, path, self.flags, no_follow_trailing)
+                self.diagnostic();
+                self.observe(|| {
+                    self.check_path(path.as_ref())?;
+                    let (handle, symlinks, clamped) =
+                        opath::resolve_with_info(root, path, self, no_follow_trailing)?;
+                    self.check_setid_target(&handle)?;
+                    let info = ResolveInfo {
+                        backend: PublicResolverBackend::Emulated,
+                        symlink_traversals: Some(symlinks),
+                        dotdot_clamped: Some(clamped),
+                    };
+                    Ok((handle, info))
+                })
+            }
+            backend => {
+                let info = ResolveInfo {
+                    backend: backend.into(),
+                    symlink_traversals: None,
+                    dotdot_clamped: None,
+                };
+                Ok((
+                    self.resolve(root, pivot_scratch, path, no_follow_trailing)?,
+                    info,
+                ))
+            }
+        }
+    }
+
+    /// Apply the `eager_reopen` policy to a resolved handle. This is only
+    /// applied by the public resolution methods of [`RootRef`], so that
+    /// internal lookups always get back an `O_PATH` handle.
+    ///
+    /// [`RootRef`]: crate::RootRef
+    pub(crate) fn maybe_eager_reopen(&self, handle: Handle) -> Result<Handle, Error> {
+        // NOTE: Deciding whether to re-open requires the inode type, so this
+        //       does one extra stat(2) -- but only when eager re-opening has
+        //       been requested. Plain resolutions do no extra stat(2) calls.
+        match self.eager_reopen {
+            // Only regular files are re-opened. Opening other inode types can
+            // have side-effects (opening a FIFO can block, for instance) and
+            // directories are usually only used for further lookups.
+            Some(flags) if handle.metadata()?.mode() & libc::S_IFMT == libc::S_IFREG => {
+                handle.reopen(flags).map(Handle::from_fd)
+            }
+            _ => Ok(handle),
+        }
+    }
+
+    pub(crate) fn resolve_many<Fd: AsFd, I>(
+        &self,
+        root: Fd,
+        pivot_scratch: Option<BorrowedFd<'_>>,
+        paths: I,
+    ) -> Vec<Result<Handle, Error>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        self.diagnostic();
+        match self.effective_backend() {
+            // The O_PATH resolver can share the walk of common prefixes (but
+            // single-component paths have no prefixes to share, and each path
+            // needs to be checked individually). Directory prefixes are walked
+            // one component at a time, so trailing symlinks cannot be told
+            // apart from directory symlinks.
+            ResolverBackend::EmulatedOpath
+                if !self.flags.contains(ResolverFlags::SINGLE_COMPONENT)
+                    && self.trailing_symlink_policy == TrailingSymlinkPolicy::FollowUnlimited =>
+            {
+                // The walks of the paths are interleaved, so each path is only
+                // reported to the observer once its result is known.
+                opath::resolve_many(&root, paths, self)
+                    .into_iter()
+                    .map(|handle| {
+                        self.observe(|| {
+                            handle.and_then(|handle| {
+                                self.check_setid_target(&handle)?;
+                                Ok(handle)
+                            })
+                        })
+                    })
+                    .collect()
             }
+            // The other backends do the lookup in a single syscall, so there
+            // is nothing to share between lookups.
+            _ => paths
+                .into_iter()
+                .map(|path| self.resolve(&root, pivot_scratch, path, false))
+                .collect(),
         }
     }
 
@@ -292,18 +902,377 @@ impl Resolver {
     pub(crate) fn resolve_partial<Fd: AsFd, P: AsRef<Path>>(
         &self,
         root: Fd,
+        pivot_scratch: Option<BorrowedFd<'_>>,
         path: P,
         no_follow_trailing: bool,
     ) -> Result<PartialLookup<Handle>, Error> {
-        match self.backend {
+        #[cfg(feature = "tracing")]
+        let _span = self.span("resolve_partial", &root, path.as_ref()).entered();
+        self.diagnostic();
+        self.check_path(path.as_ref())?;
+        let lookup = match self.effective_backend() {
             ResolverBackend::KernelOpenat2 => {
-                openat2::resolve_partial(root, path.as_ref(), self.flags, no_follow_trailing)
+                openat2::resolve_partial(&root, path.as_ref(), self.flags, no_follow_trailing)
+                    .or_else(|err| {
+                        self.openat2_fallback("resolve_partial", err, || {
+                            opath::resolve_partial(&root, path.as_ref(), self, no_follow_trailing)
+                                .map(Into::into)
+                        })
+                    })
             }
             ResolverBackend::EmulatedOpath => {
-                opath::resolve_partial(root, path.as_ref(), self.flags, no_follow_trailing)
+                opath::resolve_partial(root, path.as_ref(), self, no_follow_trailing)
                     // Rc<File> -> Handle
                     .map(Into::into)
             }
+            ResolverBackend::HardcoreEmulated => hardcore::resolve_partial(
+                root,
+                pivot_scratch,
+                path.as_ref(),
+                self.flags,
+                no_follow_trailing,
+            ),
+        }?;
+
+        if let PartialLookup::Complete(ref handle) = lookup {
+            self.check_setid_target(handle)?;
         }
+        Ok(lookup)
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::{
+        log_resolver_diagnostic, openat2, set_tracing_redact_paths, PartialLookup, Resolver,
+        ResolverBackend,
+    };
+    use crate::{flags::OpenFlags, utils::FdExt, Root};
+
+    use std::{
+        fmt,
+        os::unix::{
+            fs as unixfs,
+            io::{AsFd, AsRawFd},
+        },
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex, MutexGuard, Once, PoisonError,
+        },
+    };
+
+    use pretty_assertions::assert_eq;
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Metadata, Subscriber,
+    };
+
+    /// [`set_tracing_redact_paths`] is process-wide, so tests which change it
+    /// (or check the paths in spans and events) must hold this lock (using
+    /// [`redact_paths`]) to avoid racing with each other.
+    static REDACT_PATHS_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Guard returned by [`redact_paths`], which resets the path redaction
+    /// setting when dropped (even if the test panicked).
+    struct RedactPathsGuard {
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl Drop for RedactPathsGuard {
+        fn drop(&mut self) {
+            set_tracing_redact_paths(false);
+        }
+    }
+
+    /// Take [`REDACT_PATHS_LOCK`] and configure whether paths are redacted
+    /// until the returned guard is dropped.
+    fn redact_paths(redact: bool) -> RedactPathsGuard {
+        let lock = REDACT_PATHS_LOCK
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        set_tracing_redact_paths(redact);
+        RedactPathsGuard { _lock: lock }
+    }
+
+    /// Minimal [`Subscriber`] which just counts the number of events.
+    struct EventCounter(Arc<AtomicUsize>);
+
+    impl Subscriber for EventCounter {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    /// Formats all of the fields of a span or event as `name=value` pairs.
+    #[derive(Default)]
+    struct FieldRecorder(Vec<String>);
+
+    impl Visit for FieldRecorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push(format!("{}={:?}", field.name(), value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push(format!("{}={}", field.name(), value));
+        }
+    }
+
+    /// Minimal [`Subscriber`] which records the fields of every span and the
+    /// fields of every `DEBUG` event.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        spans: Arc<Mutex<Vec<Vec<String>>>>,
+        events: Arc<Mutex<Vec<Vec<String>>>>,
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, meta: &Metadata<'_>) -> bool {
+            *meta.level() <= tracing::Level::DEBUG
+        }
+
+        fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+            let mut fields = FieldRecorder::default();
+            attrs.record(&mut fields);
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(fields.0);
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = FieldRecorder::default();
+            event.record(&mut fields);
+            self.events.lock().unwrap().push(fields.0);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn resolve_span() -> Result<(), anyhow::Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("a/b"))?;
+        unixfs::symlink("a", dir.path().join("link1"))?;
+        unixfs::symlink("/link1/b", dir.path().join("link2"))?;
+        let root = Root::open(dir.path())?.with_resolver_backend(ResolverBackend::EmulatedOpath);
+
+        for redact in [false, true] {
+            let recorder = Recorder::default();
+            let _redact = redact_paths(redact);
+            tracing::subscriber::with_default(recorder.clone(), || root.resolve("link2"))?;
+
+            let path = if redact { "<redacted>" } else { "link2" };
+            let spans = recorder.spans.lock().unwrap().clone();
+            assert_eq!(
+                spans,
+                vec![vec![
+                    "operation=resolve".to_string(),
+                    format!("root_fd={}", root.as_fd().as_raw_fd()),
+                    format!("path={path}"),
+                    format!("path_redacted={redact}"),
+                    "backend=EmulatedOpath".to_string(),
+                ]],
+                "resolve span (redact={redact})"
+            );
+
+            // Only look at the symlink traversal events (the one-time
+            // diagnostic might also be emitted).
+            let events = recorder
+                .events
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|fields| {
+                    fields.first().map(String::as_str) == Some("message=following symlink")
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            let (link2, link2_target, link1, link1_target) = if redact {
+                ("<redacted>", "<redacted>", "<redacted>", "<redacted>")
+            } else {
+                ("/link2", "/link1/b", "/link1", "a")
+            };
+            assert_eq!(
+                events,
+                vec![
+                    vec![
+                        "message=following symlink".to_string(),
+                        format!("symlink={link2}"),
+                        format!("target={link2_target}"),
+                        "symlink_traversals=1".to_string(),
+                    ],
+                    vec![
+                        "message=following symlink".to_string(),
+                        format!("symlink={link1}"),
+                        format!("target={link1_target}"),
+                        "symlink_traversals=2".to_string(),
+                    ],
+                ],
+                "symlink traversal events (redact={redact})"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn openat2_fallback_event() -> Result<(), anyhow::Error> {
+        if !ResolverBackend::KernelOpenat2.supported() {
+            return Ok(());
+        }
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("a/b"))?;
+        std::fs::write(dir.path().join("a/b/file"), "")?;
+        unixfs::symlink("/a/b", dir.path().join("link"))?;
+        let root = Root::open(dir.path())?.with_resolver_backend(ResolverBackend::KernelOpenat2);
+
+        for errno in [libc::EPERM, libc::ENOSYS] {
+            let recorder = Recorder::default();
+            tracing::subscriber::with_default(recorder.clone(), || {
+                openat2::hooks::with_injected_errno(errno, || -> Result<(), anyhow::Error> {
+                    let handle = root.resolve("link/file")?;
+                    assert_eq!(
+                        handle.as_unsafe_path_unchecked()?,
+                        dir.path().join("a/b/file"),
+                        "resolve should succeed using the emulated resolver (errno={errno})"
+                    );
+                    let file = root.open_subpath("link/file", OpenFlags::O_RDONLY)?;
+                    assert_eq!(
+                        file.as_unsafe_path_unchecked()?,
+                        dir.path().join("a/b/file"),
+                        "open should succeed using the emulated resolver (errno={errno})"
+                    );
+                    let resolver = Resolver {
+                        backend: ResolverBackend::KernelOpenat2,
+                        ..Default::default()
+                    };
+                    match resolver.resolve_partial(&root, None, "link/nonexist", false)? {
+                        PartialLookup::Partial {
+                            handle, remaining, ..
+                        } => {
+                            assert_eq!(
+                                handle.as_unsafe_path_unchecked()?,
+                                dir.path().join("a/b"),
+                                "resolve_partial should succeed using the emulated resolver (errno={errno})"
+                            );
+                            assert_eq!(remaining, std::path::PathBuf::from("nonexist"));
+                        }
+                        PartialLookup::Complete(handle) => {
+                            panic!("resolve_partial should not be complete: {handle:?}")
+                        }
+                    }
+                    Ok(())
+                })
+            })?;
+
+            let events = recorder
+                .events
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|fields| {
+                    fields.first().map(String::as_str)
+                        == Some(
+                            "message=openat2 was blocked, falling back to the emulated resolver",
+                        )
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            let event = |operation: &str| {
+                vec![
+                    "message=openat2 was blocked, falling back to the emulated resolver"
+                        .to_string(),
+                    format!("operation={operation}"),
+                    format!("errno={errno}"),
+                    "fallback_backend=EmulatedOpath".to_string(),
+                ]
+            };
+            assert_eq!(
+                events,
+                vec![event("resolve"), event("open"), event("resolve_partial")],
+                "fallback events (errno={errno})"
+            );
+        }
+
+        // EPERM from an open without O_PATH is a real permission error if an
+        // O_PATH lookup works, and must not cause a fallback.
+        let recorder = Recorder::default();
+        let res = tracing::subscriber::with_default(recorder.clone(), || {
+            openat2::hooks::with_injected_errno_if(
+                libc::EPERM,
+                |how| how.flags & libc::O_PATH as u64 == 0,
+                || root.open_subpath("link/file", OpenFlags::O_RDONLY),
+            )
+        });
+        assert_eq!(
+            res.map(|_| ()).map_err(|err| err.kind()),
+            Err(crate::error::ErrorKind::OsError(Some(libc::EPERM))),
+            "EPERM from a non-O_PATH open should be returned as-is"
+        );
+        assert!(
+            recorder.events.lock().unwrap().iter().all(|fields| !fields
+                .iter()
+                .any(|field| field.starts_with("fallback_backend="))),
+            "no fallback event should be emitted for real EPERM errors"
+        );
+
+        // Other errors must not cause a fallback.
+        let recorder = Recorder::default();
+        let res = tracing::subscriber::with_default(recorder.clone(), || {
+            openat2::hooks::with_injected_errno(libc::EACCES, || root.resolve("link/file"))
+        });
+        assert_eq!(
+            res.map(|_| ()).map_err(|err| err.kind()),
+            Err(crate::error::ErrorKind::OsError(Some(libc::EACCES))),
+            "non-seccomp openat2 errors should be returned as-is"
+        );
+        assert!(
+            recorder.events.lock().unwrap().iter().all(|fields| !fields
+                .iter()
+                .any(|field| field.starts_with("fallback_backend="))),
+            "no fallback event should be emitted for other errors"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolver_diagnostic_once() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let once = Once::new();
+
+        tracing::subscriber::with_default(EventCounter(Arc::clone(&counter)), || {
+            for _ in 0..16 {
+                log_resolver_diagnostic(&once, ResolverBackend::default());
+            }
+        });
+
+        assert_eq!(
+            counter.load(Ordering::SeqCst),
+            1,
+            "resolver diagnostic should only be emitted once"
+        );
     }
 }
@@ -0,0 +1,394 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! "Hardcore" resolver which does the lookup inside a private mount namespace
+//! that has been `pivot_root(2)`-ed into the root.
+//!
+//! The lookup is done on a dedicated thread which unshares its mount namespace
+//! (and filesystem information) and then pivots into a bind-mount of the root.
+//! This means that the root of the resolution is the real root of the thread,
+//! and so it is physically impossible for `..` or absolute symlinks to escape
+//! it. The lookup inside the pivoted root is still done with `openat2(2)`
+//! (with `RESOLVE_IN_ROOT`, or `RESOLVE_BENEATH` if requested) so that the
+//! semantics (and [`ResolverFlags`]) are identical to the `openat2` resolver.
+//!
+//! The root mount tree is attached to a scratch mount point before pivoting.
+//! Users can provide a pre-created scratch directory (such as a dedicated
+//! `tmpfs`), otherwise a fresh `tmpfs` is mounted on top of `/` inside the
+//! private mount namespace. In either case, none of the mount operations are
+//! visible outside of the private mount namespace.
+//!
+//! Because the resulting file descriptor lives on a mount in the (now dead)
+//! private mount namespace, we only use it to compute the canonical path of
+//! the target within the root (as seen from the pivoted root). That path is
+//! then re-walked from the original root without following any symlinks or
+//! `..` components, and we verify that we ended up at the same inode.
+
+use crate::{
+    error::{Error, ErrorExt, ErrorImpl},
+    flags::{OpenFlags, ResolverFlags},
+    kernel,
+    procfs::GLOBAL_PROCFS_HANDLE,
+    resolvers::{is_dangling_symlink, opath, openat2, walked_prefix, PartialLookup, Resolver},
+    syscalls::{self, OpenHow},
+    utils::{FdExt, PathIterExt},
+    Handle,
+};
+
+use std::{
+    io::Error as IOError,
+    os::unix::{
+        fs::MetadataExt,
+        io::{AsFd, BorrowedFd},
+    },
+    path::{Path, PathBuf},
+    thread,
+};
+
+use once_cell::sync::Lazy;
+use rustix::{
+    mount::{
+        FsMountFlags, FsOpenFlags, MountAttrFlags, MountPropagationFlags, MoveMountFlags,
+        OpenTreeFlags, UnmountFlags,
+    },
+    thread::UnshareFlags,
+};
+
+/// Information about the target of a lookup done inside the pivoted root.
+struct PivotedLookup {
+    /// Path of the target, relative to the root.
+    path: PathBuf,
+    dev: u64,
+    ino: u64,
+}
+
+/// Make the mount `new_root` (which must be attached in our mount namespace)
+/// the root of the current thread, and detach the old root.
+fn pivot_into<Fd: AsFd>(new_root: Fd) -> Result<(), Error> {
+    // pivot_root(".", ".") stacks the old root on top of the new one, which
+    // we can then detach with umount2(".") (which operates on the top-most
+    // mount on our working directory).
+    syscalls::fchdir(new_root).map_err(|err| ErrorImpl::RawOsError {
+        operation: "chdir into new root".into(),
+        source: err,
+    })?;
+    syscalls::pivot_root(".", ".").map_err(|err| ErrorImpl::RawOsError {
+        operation: "pivot into new root".into(),
+        source: err,
+    })?;
+    syscalls::umount2(".", UnmountFlags::DETACH).map_err(|err| {
+        ErrorImpl::RawOsError {
+            operation: "detach old root".into(),
+            source: err,
+        }
+        .into()
+    })
+}
+
+/// Set up the scratch mount point that the root mount tree will be attached
+/// to, and `chdir(2)` into it.
+///
+/// If the user provided a scratch directory, we must `chdir(2)` into it
+/// *before* unsharing the mount namespace (the file descriptor references a
+/// mount in the original namespace, while `unshare(CLONE_NEWNS)` will move our
+/// working directory to the copy of that mount in the new namespace).
+/// Otherwise, we create a fresh `tmpfs` and pivot into it, so that it is the
+/// only mount left in the new mount namespace.
+fn enter_scratch<Fd: AsFd>(
+    scratch: Option<Fd>,
+    unshare: impl FnOnce() -> Result<(), Error>,
+) -> Result<(), Error> {
+    match scratch {
+        Some(scratch) => {
+            syscalls::fchdir(scratch).map_err(|err| ErrorImpl::RawOsError {
+                operation: "chdir into hardcore resolver scratch directory".into(),
+                source: err,
+            })?;
+            unshare()
+        }
+        None => {
+            unshare()?;
+
+            let sfd = syscalls::fsopen("tmpfs", FsOpenFlags::FSOPEN_CLOEXEC).map_err(|err| {
+                ErrorImpl::RawOsError {
+                    operation: "create scratch tmpfs superblock".into(),
+                    source: err,
+                }
+            })?;
+            syscalls::fsconfig_create(&sfd).map_err(|err| ErrorImpl::RawOsError {
+                operation: "instantiate scratch tmpfs superblock".into(),
+                source: err,
+            })?;
+            let tmpfs = syscalls::fsmount(
+                &sfd,
+                FsMountFlags::FSMOUNT_CLOEXEC,
+                MountAttrFlags::MOUNT_ATTR_NODEV
+                    | MountAttrFlags::MOUNT_ATTR_NOEXEC
+                    | MountAttrFlags::MOUNT_ATTR_NOSUID,
+            )
+            .map_err(|err| ErrorImpl::RawOsError {
+                operation: "mount scratch tmpfs".into(),
+                source: err,
+            })?;
+            syscalls::move_mount(
+                &tmpfs,
+                "",
+                syscalls::AT_FDCWD,
+                "/",
+                MoveMountFlags::MOVE_MOUNT_F_EMPTY_PATH,
+            )
+            .map_err(|err| ErrorImpl::RawOsError {
+                operation: "attach scratch tmpfs".into(),
+                source: err,
+            })?;
+            // We can't leave the tmpfs stacked on top of the old root, as
+            // umount2(".") would then only detach the tmpfs when we later
+            // pivot into the root mount tree.
+            pivot_into(&tmpfs).wrap("pivot into scratch tmpfs")
+        }
+    }
+}
+
+/// Do the lookup of `path` inside a private mount namespace pivoted into
+/// `root`. This must only be called on a dedicated thread, as the thread's
+/// mount namespace and root are permanently modified.
+fn pivoted_lookup<Fd1: AsFd, Fd2: AsFd>(
+    root: Fd1,
+    scratch: Option<Fd2>,
+    path: &Path,
+    rflags: ResolverFlags,
+    no_follow_trailing: bool,
+) -> Result<PivotedLookup, Error> {
+    let root = root.as_fd();
+
+    // Create a (recursive) bind-mount of the root. This has to be done before
+    // we unshare, because open_tree(2) can only clone mounts from the caller's
+    // mount namespace (and the root handle refers to the original namespace).
+    let tree = syscalls::open_tree(
+        root,
+        "",
+        OpenTreeFlags::OPEN_TREE_CLONE
+            | OpenTreeFlags::OPEN_TREE_CLOEXEC
+            | OpenTreeFlags::AT_EMPTY_PATH
+            | OpenTreeFlags::AT_RECURSIVE,
+    )
+    .map_err(|err| ErrorImpl::RawOsError {
+        operation: "clone root mount tree".into(),
+        source: err,
+    })?;
+
+    // Make sure that our chdir(2)s and pivot_root(2) only affect the current
+    // thread. This must be done before we touch our working directory.
+    syscalls::unshare(UnshareFlags::FS).map_err(|err| ErrorImpl::RawOsError {
+        operation: "unshare filesystem information".into(),
+        source: err,
+    })?;
+
+    enter_scratch(scratch, || {
+        syscalls::unshare(UnshareFlags::NEWNS)
+            .map_err(|err| ErrorImpl::RawOsError {
+                operation: "create private mount namespace".into(),
+                source: err,
+            })
+            .wrap("hardcore resolver requires CAP_SYS_ADMIN to create a mount namespace")?;
+
+        // Make sure none of our mount operations propagate to the host.
+        syscalls::mount_change(
+            "/",
+            MountPropagationFlags::PRIVATE | MountPropagationFlags::REC,
+        )
+        .map_err(|err| {
+            ErrorImpl::RawOsError {
+                operation: "make private mount namespace rprivate".into(),
+                source: err,
+            }
+            .into()
+        })
+    })?;
+
+    // Attach the cloned mount tree on top of the scratch mount point so we can
+    // pivot_root(2) into it.
+    syscalls::move_mount(
+        &tree,
+        "",
+        syscalls::AT_FDCWD,
+        ".",
+        MoveMountFlags::MOVE_MOUNT_F_EMPTY_PATH,
+    )
+    .map_err(|err| ErrorImpl::RawOsError {
+        operation: "attach root mount tree".into(),
+        source: err,
+    })?;
+
+    pivot_into(&tree).wrap("pivot into root mount tree")?;
+
+    // The lookup itself is done relative to our new root.
+    let mut oflags = OpenFlags::O_PATH;
+    if no_follow_trailing {
+        oflags.insert(OpenFlags::O_NOFOLLOW);
+    }
+    let how = OpenHow {
+        flags: oflags.bits() as u64,
+        resolve: openat2::resolve_bits(rflags),
+        ..Default::default()
+    };
+    let file =
+        syscalls::openat2(syscalls::AT_FDCWD, path, &how).map_err(|err| ErrorImpl::RawOsError {
+            operation: "hardcore subpath".into(),
+            source: err,
+        })?;
+
+    // Our root is now the root of the lookup, so the kernel will give us the
+    // path of the target relative to it.
+    let path = file
+        .as_unsafe_path(&GLOBAL_PROCFS_HANDLE)
+        .wrap("get path of target in pivoted root")?;
+    let meta = file.metadata().wrap("fstat target in pivoted root")?;
+
+    Ok(PivotedLookup {
+        path,
+        dev: meta.dev(),
+        ino: meta.ino(),
+    })
+}
+
+/// Resolve `path` within `root` by doing the lookup in a private mount
+/// namespace which has been `pivot_root(2)`-ed into `root`.
+pub(crate) fn resolve<Fd: AsFd, P: AsRef<Path>>(
+    root: Fd,
+    scratch: Option<BorrowedFd<'_>>,
+    path: P,
+    rflags: ResolverFlags,
+    no_follow_trailing: bool,
+) -> Result<Handle, Error> {
+    let features = kernel::kernel_features();
+    if !features.has_openat2 {
+        Err(ErrorImpl::NotSupported {
+            feature: "openat2".into(),
+        })?
+    }
+    if !features.has_mount_namespaces {
+        Err(ErrorImpl::NotSupported {
+            feature: "private mount namespaces".into(),
+        })?
+    }
+
+    let root = root.as_fd();
+    let path = path.as_ref();
+
+    // Make sure the global procfs handle is set up outside of the pivoted
+    // thread, since /proc will not be reachable from inside it.
+    Lazy::force(&GLOBAL_PROCFS_HANDLE);
+
+    let lookup = {
+        let root = root
+            .try_clone_to_owned()
+            .map_err(|err| ErrorImpl::OsError {
+                operation: "dup root handle for hardcore resolver".into(),
+                source: err,
+            })?;
+        let scratch = scratch
+            .map(|fd| fd.try_clone_to_owned())
+            .transpose()
+            .map_err(|err| ErrorImpl::OsError {
+                operation: "dup scratch directory handle for hardcore resolver".into(),
+                source: err,
+            })?;
+        let path = path.to_path_buf();
+        thread::spawn(move || pivoted_lookup(root, scratch, &path, rflags, no_follow_trailing))
+            .join()
+            .map_err(|_| ErrorImpl::SafetyViolation {
+                description: "hardcore resolver thread panicked".into(),
+            })??
+    };
+
+    // Re-walk the canonical path from the real root. The path has no symlinks
+    // or ".." components (other than possibly a trailing symlink, if we were
+    // asked not to follow it), so forbidding symlinks means this walk cannot
+    // escape the root.
+    let handle = opath::resolve(
+        root,
+        &lookup.path,
+        &Resolver {
+            flags: ResolverFlags::NO_SYMLINKS,
+            ..Default::default()
+        },
+        true,
+    )
+    .wrap("re-walk canonical path from hardcore resolver")?;
+
+    let meta = handle.metadata().wrap("fstat re-walked handle")?;
+    if (meta.dev(), meta.ino()) != (lookup.dev, lookup.ino) {
+        Err(ErrorImpl::OsError {
+            operation: "verify re-walked handle".into(),
+            source: IOError::from_raw_os_error(libc::EAGAIN),
+        })
+        .wrap("racing filesystem changes caused hardcore resolution to abort")?
+    }
+
+    Ok(handle)
+}
+
+/// Resolve as many components as possible in `path` within `root` using the
+/// hardcore resolver.
+pub(crate) fn resolve_partial<Fd: AsFd>(
+    root: Fd,
+    scratch: Option<BorrowedFd<'_>>,
+    path: &Path,
+    rflags: ResolverFlags,
+    no_follow_trailing: bool,
+) -> Result<PartialLookup<Handle>, Error> {
+    let root = root.as_fd();
+    let mut last_error = match resolve(root, scratch, path, rflags, no_follow_trailing) {
+        Ok(handle) => return Ok(PartialLookup::Complete(handle)),
+        Err(err) => err,
+    };
+
+    // Like the openat2 resolver, we just try successively shorter prefixes.
+    for (ancestor, remaining) in path.partial_ancestors() {
+        match resolve(root, scratch, ancestor, rflags, no_follow_trailing) {
+            Ok(handle) => {
+                let remaining = remaining.map(PathBuf::from).unwrap_or("".into());
+                let dangling_symlink = is_dangling_symlink(&handle, &remaining, &last_error);
+                return Ok(PartialLookup::Partial {
+                    handle,
+                    resolved: walked_prefix(path, &remaining).unwrap_or_else(|| ancestor.into()),
+                    remaining,
+                    last_error,
+                    dangling_symlink,
+                });
+            }
+            Err(err) => last_error = err,
+        }
+    }
+
+    Ok(PartialLookup::Partial {
+        handle: root
+            .try_clone_to_owned()
+            .map(Handle::from_fd)
+            .map_err(|err| ErrorImpl::OsError {
+                operation: "clone root".into(),
+                source: err,
+            })?,
+        resolved: ".".into(),
+        remaining: path.into(),
+        last_error,
+        dangling_symlink: false,
+    })
+}
@@ -37,22 +37,23 @@
 //! attempts.
 
 use crate::{
-    error::{Error, ErrorExt, ErrorImpl},
+    error::{Error, ErrorExt, ErrorImpl, ErrorKind},
     flags::{OpenFlags, ResolverFlags},
     procfs::GLOBAL_PROCFS_HANDLE,
-    resolvers::{opath::SymlinkStack, PartialLookup, MAX_SYMLINK_TRAVERSALS},
+    resolvers::{opath::SymlinkStack, walked_prefix, PartialLookup, ResolveObserver, Resolver},
     syscalls,
-    utils::{self, FdExt, PathIterExt},
-    Handle,
+    utils::{self, FdExt, Metadata, PathIterExt},
+    Handle, SetidPolicy, TrailingSymlinkPolicy,
 };
 
 use std::{
-    collections::VecDeque,
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
     ffi::{OsStr, OsString},
     io::Error as IOError,
     iter,
     os::unix::{
-        ffi::OsStrExt,
+        ffi::{OsStrExt, OsStringExt},
         fs::MetadataExt,
         io::{AsFd, OwnedFd},
     },
@@ -60,6 +61,9 @@ use std::{
     rc::Rc,
 };
 
+#[cfg(feature = "tracing")]
+use crate::resolvers::traced_path;
+
 use itertools::Itertools;
 use once_cell::sync::Lazy;
 
@@ -172,34 +176,248 @@ fn may_follow_link<DirFd: AsFd, Fd: AsFd>(dir: DirFd, link: Fd) -> Result<(), Er
     }
 }
 
+/// Construct the error returned when a lookup would escape the root with
+/// [`ResolverFlags::BENEATH`] set, to match `RESOLVE_BENEATH`.
+fn emulated_beneath_error(description: &str) -> Error {
+    ErrorImpl::OsError {
+        operation: "emulated RESOLVE_BENEATH".into(),
+        source: IOError::from_raw_os_error(libc::EXDEV),
+    }
+    .wrap(description.to_string())
+    .into()
+}
+
+/// The intermediate state of a path walk. This allows a lookup to be resumed
+/// from a previously-resolved directory (see [`resolve_many`]).
+#[derive(Clone, Debug)]
+struct WalkState {
+    current: Rc<OwnedFd>,
+    expected_path: PathBuf,
+    symlink_traversals: usize,
+    /// The total length of all symlink targets expanded so far.
+    symlink_target_bytes: usize,
+    /// Whether a ".." component was clamped to the root.
+    dotdot_clamped: bool,
+}
+
+impl WalkState {
+    fn new(root: &Rc<OwnedFd>) -> Self {
+        Self {
+            current: Rc::clone(root),
+            // What is the final path we expect to get after we do the final
+            // open? This allows us to track any attacker moving path components
+            // around and we can sanity-check at the very end. This does not
+            // include rootpath.
+            expected_path: PathBuf::from("/"),
+            symlink_traversals: 0,
+            symlink_target_bytes: 0,
+            dotdot_clamped: false,
+        }
+    }
+}
+
+/// Per-root configuration for a path walk.
+struct Walker {
+    root: Rc<OwnedFd>,
+    root_dev: Option<u64>,
+    flags: ResolverFlags,
+    max_symlinks: usize,
+    /// The maximum total length of symlink targets expanded by the walk.
+    max_symlink_target_bytes: usize,
+    setid_policy: SetidPolicy,
+    /// Whether intermediate directories which have been unlinked (but are
+    /// still reachable through our handle) cause the walk to fail.
+    reject_deleted: bool,
+    /// How symlinks in the final component of the path are treated.
+    trailing_symlinks: TrailingSymlinkPolicy,
+    /// The observer to notify about symlinks and blocked escapes.
+    observer: Option<&'static dyn ResolveObserver>,
+    /// If set, the `(dev, ino)` of every component opened during the walk.
+    inode_trail: Option<InodeTrail>,
+}
+
+/// The `(dev, ino)` of every component opened during a walk, used by
+/// [`resolve_with_inode_trail`].
+struct InodeTrail {
+    /// The `(dev, ino)` of the root, which is never included in the trail.
+    root: (u64, u64),
+    inodes: RefCell<Vec<(u64, u64)>>,
+}
+
+impl Walker {
+    fn new<Fd: AsFd>(root: Fd, resolver: &Resolver) -> Result<Self, Error> {
+        let flags = resolver.flags;
+
+        // We only need to keep track of our current dirfd, since we are
+        // applying the components one-by-one, and can always switch back to
+        // the root if we hit an absolute symlink.
+        let root =
+            Rc::new(
+                root.as_fd()
+                    .try_clone_to_owned()
+                    .map_err(|err| ErrorImpl::OsError {
+                        operation: "dup root handle as starting point of resolution".into(),
+                        source: err,
+                    })?,
+            );
+
+        // For RESOLVE_NO_XDEV emulation, we need to know the device of the
+        // root. Every component we walk into must have the same device as the
+        // one we walked from, so all components share the root's device.
+        let root_dev = if flags.contains(ResolverFlags::NO_XDEV) {
+            // MSRV(1.69): Remove *.
+            Some(
+                (*root)
+                    .metadata()
+                    .wrap("fstat root for RESOLVE_NO_XDEV")?
+                    .dev(),
+            )
+        } else {
+            None
+        };
+
+        Ok(Self {
+            root,
+            root_dev,
+            flags,
+            max_symlinks: resolver.max_symlinks(),
+            max_symlink_target_bytes: resolver.max_symlink_target_bytes(),
+            setid_policy: resolver.setid_policy,
+            reject_deleted: resolver.reject_deleted_intermediates,
+            trailing_symlinks: resolver.trailing_symlink_policy,
+            observer: resolver.observer(),
+            inode_trail: None,
+        })
+    }
+
+    /// Record the `(dev, ino)` of every component opened by the walk.
+    fn with_inode_trail(mut self) -> Result<Self, Error> {
+        // MSRV(1.69): Remove *.
+        let root_meta = (*self.root).metadata().wrap("fstat root for inode trail")?;
+        self.inode_trail = Some(InodeTrail {
+            root: (root_meta.dev(), root_meta.ino()),
+            inodes: RefCell::new(Vec::new()),
+        });
+        Ok(self)
+    }
+
+    /// Add the component `part` (with metadata `meta`) to the inode trail.
+    /// `"."` components do not visit a new inode and the root is never part of
+    /// the trail, so neither is recorded.
+    fn record_inode(&self, part: &OsStr, meta: &Metadata) {
+        if let Some(ref trail) = self.inode_trail {
+            let inode = (meta.dev(), meta.ino());
+            if part.as_bytes() != b"." && inode != trail.root {
+                trail.inodes.borrow_mut().push(inode);
+            }
+        }
+    }
+
+    /// Whether we need the metadata of every directory we walk through (for
+    /// `RESOLVE_NO_XDEV` emulation, the inode trail or [`check_intermediate`]).
+    ///
+    /// [`check_intermediate`]: Self::check_intermediate
+    fn needs_intermediate_metadata(&self) -> bool {
+        self.root_dev.is_some()
+            || self.inode_trail.is_some()
+            || self.setid_policy.rejects_intermediate()
+            || self.reject_deleted
+    }
+
+    /// Enforce the setid policy (and the deleted inode policy) for a
+    /// non-symlink component we are about to walk through (rather than stop
+    /// at).
+    fn check_intermediate(&self, meta: &Metadata, part: &OsStr) -> Result<(), Error> {
+        if self.setid_policy.rejects_intermediate()
+            && meta.mode() & (libc::S_ISUID | libc::S_ISGID) != 0
+        {
+            Err(ErrorImpl::SafetyViolation {
+                description: format!(
+                    "intermediate component {part:?} has setuid or setgid bit set"
+                )
+                .into(),
+            })?
+        }
+        // A directory with no links has been removed from the filesystem, so
+        // it no longer corresponds to the path we are resolving (even though
+        // we can still walk through it with our handle).
+        if self.reject_deleted && meta.nlink() == 0 {
+            Err(ErrorImpl::DeletedInode {
+                description: format!("intermediate component {part:?} has been deleted").into(),
+            })?
+        }
+        Ok(())
+    }
+
+    /// Make sure that the final handle of a completed walk is what we expect.
+    fn check_complete(&self, state: &WalkState) -> Result<(), Error> {
+        // MSRV(1.69): Remove &*.
+        check_current(&*state.current, &*self.root, &state.expected_path)
+            .wrap("check final handle didn't escape")
+    }
+}
+
 /// Common implementation used by `resolve_partial()` and `resolve()`. The main
 /// difference is that if `symlink_stack` is `true`, the returned paths
-// TODO: Make (flags, no_follow_trailing, symlink_stack) a single struct to
-//       avoid possible issues with passing a bool to the wrong argument.
-fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
-    root: Fd,
+///
+/// Note that `walker` holds a reference to the root, so it must be dropped
+/// before the returned lookup is converted into a [`Handle`].
+// TODO: Make (no_follow_trailing, symlink_stack) a single struct to avoid
+//       possible issues with passing a bool to the wrong argument.
+fn do_resolve<P: AsRef<Path>>(
+    walker: &Walker,
+    state: &mut WalkState,
+    path: P,
+    no_follow_trailing: bool,
+    symlink_stack: Option<&mut SymlinkStack<OwnedFd>>,
+) -> Result<PartialLookup<Rc<OwnedFd>>, Error> {
+    // With RESOLVE_BENEATH, absolute paths are never permitted (the kernel
+    // refuses to jump to the root rather than clamping to it).
+    let path = path.as_ref();
+    if walker.flags.contains(ResolverFlags::BENEATH) && path.is_absolute() {
+        return Ok(PartialLookup::Partial {
+            handle: Rc::clone(&state.current),
+            resolved: state.expected_path.clone(),
+            remaining: path.into(),
+            last_error: emulated_beneath_error("path is absolute"),
+            dangling_symlink: false,
+        });
+    }
+
+    let lookup = walk(walker, state, path, no_follow_trailing, symlink_stack)?;
+    if let PartialLookup::Complete(_) = lookup {
+        // Make sure that the path is what we expect...
+        walker.check_complete(state)?;
+    }
+    Ok(lookup)
+}
+
+/// Walk `path` starting from `state`. If the walk completes, `state` is
+/// updated to reflect the final handle. Note that the caller is responsible
+/// for calling [`Walker::check_complete`] on the final state.
+fn walk<P: AsRef<Path>>(
+    walker: &Walker,
+    state: &mut WalkState,
     path: P,
-    flags: ResolverFlags,
     no_follow_trailing: bool,
     mut symlink_stack: Option<&mut SymlinkStack<OwnedFd>>,
 ) -> Result<PartialLookup<Rc<OwnedFd>>, Error> {
-    // What is the final path we expect to get after we do the final open? This
-    // allows us to track any attacker moving path components around and we can
-    // sanity-check at the very end. This does not include rootpath.
-    let mut expected_path = PathBuf::from("/");
-
-    // We only need to keep track of our current dirfd, since we are applying
-    // the components one-by-one, and can always switch back to the root
-    // if we hit an absolute symlink.
-    let root = Rc::new(
-        root.as_fd()
-            .try_clone_to_owned()
-            .map_err(|err| ErrorImpl::OsError {
-                operation: "dup root handle as starting point of resolution".into(),
-                source: err,
-            })?,
+    let (root, root_dev, flags, max_symlinks) = (
+        Rc::clone(&walker.root),
+        walker.root_dev,
+        walker.flags,
+        walker.max_symlinks,
     );
-    let mut current = Rc::clone(&root);
+    // Symlinks followed as the final component of the path (including the
+    // final components of the targets of such symlinks).
+    let mut trailing_symlink_traversals = 0;
+    let WalkState {
+        mut current,
+        mut expected_path,
+        mut symlink_traversals,
+        mut symlink_target_bytes,
+        mut dotdot_clamped,
+    } = state.clone();
 
     // Get initial set of components from the passed path. We remove components
     // as we do the path walk, and update them with the contents of any symlinks
@@ -209,8 +427,18 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
         .map(|p| p.to_os_string())
         .collect::<VecDeque<_>>();
 
-    let mut symlink_traversals = 0;
     while let Some(part) = remaining_components.pop_front() {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            component = %traced_path(Path::new(&part)),
+            current = %traced_path(&expected_path),
+            "walking path component",
+        );
+
+        // Stash the path of current (before we update expected_path for this
+        // component), so we can report how far we got if the lookup fails.
+        let current_path = expected_path.clone();
+
         // Stash a copy of the real remaining path. We can't just use
         // ::collect<PathBuf> because we might have "" components, which
         // std::path::PathBuf don't like.
@@ -237,7 +465,30 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
                 // lexically. If pop() fails, then we are at the root.
                 // should .
                 if !expected_path.pop() {
+                    if let Some(observer) = walker.observer {
+                        observer.escape_blocked();
+                    }
+                    if flags.contains(ResolverFlags::BENEATH) {
+                        return Ok(PartialLookup::Partial {
+                            handle: current,
+                            resolved: current_path,
+                            remaining,
+                            last_error: emulated_beneath_error("'..' component escapes root"),
+                            dangling_symlink: false,
+                        });
+                    }
+                    // The clamped ".." was still walked, so it needs to be
+                    // cleared from the symlink stack like any other component.
+                    if let Some(ref mut stack) = symlink_stack {
+                        stack
+                            .pop_part(&part)
+                            .map_err(|err| ErrorImpl::BadSymlinkStackError {
+                                description: "walking into clamped '..' component".into(),
+                                source: err,
+                            })?;
+                    }
                     current = Rc::clone(&root);
+                    dotdot_clamped = true;
                     continue;
                 }
                 part
@@ -260,6 +511,50 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
             }
         };
 
+        // In the common case, intermediate components are directories we just
+        // need to walk through. Opening them with O_DIRECTORY|O_NOFOLLOW fails
+        // if the component is a symlink (or any other non-directory), so if
+        // the open succeeds we already know what the component is and can
+        // skip the fstat(2) below. Any failure (including a symlink) falls
+        // back to the full open below, so errors and symlinks are handled
+        // exactly as before.
+        if !walker.needs_intermediate_metadata()
+            && remaining_components
+                .iter()
+                .any(|p| !matches!(p.as_bytes(), b"" | b"."))
+        {
+            // MSRV(1.69): Remove &*.
+            if let Ok(next) = syscalls::openat(
+                &*current,
+                &part,
+                OpenFlags::O_PATH | OpenFlags::O_NOFOLLOW | OpenFlags::O_DIRECTORY,
+                0,
+            ) {
+                // See the ".." check below.
+                if part.as_bytes() == b".." {
+                    // MSRV(1.69): Remove &*.
+                    check_current(&next, &*root, &expected_path)
+                        .wrap("check next '..' component didn't escape")?;
+                }
+
+                #[cfg(test)]
+                hooks::after_open(&expected_path);
+
+                // We walked into a directory, so clear it from the symlink
+                // stack.
+                if let Some(ref mut stack) = symlink_stack {
+                    stack
+                        .pop_part(&part)
+                        .map_err(|err| ErrorImpl::BadSymlinkStackError {
+                            description: "walking into component".into(),
+                            source: err,
+                        })?;
+                }
+                current = next.into();
+                continue;
+            }
+        }
+
         // Get our next element.
         // MSRV(1.69): Remove &*.
         match syscalls::openat(
@@ -278,8 +573,10 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
             Err(err) => {
                 return Ok(PartialLookup::Partial {
                     handle: current,
+                    resolved: current_path,
                     remaining,
                     last_error: err,
+                    dangling_symlink: false,
                 });
             }
             Ok(next) => {
@@ -299,14 +596,43 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
                         .wrap("check next '..' component didn't escape")?;
                 }
 
+                #[cfg(test)]
+                hooks::after_open(&expected_path);
+
+                let next_meta = next.metadata().wrap("fstat of next component")?;
+
+                // Emulate RESOLVE_NO_XDEV by making sure the device hasn't
+                // changed. Note that this cannot detect bind-mounts of the
+                // same filesystem (they share a device number).
+                if root_dev.map_or(false, |dev| dev != next_meta.dev()) {
+                    return Ok(PartialLookup::Partial {
+                        handle: current,
+                        resolved: current_path,
+                        remaining,
+                        // Construct a fake OS error containing EXDEV.
+                        last_error: ErrorImpl::OsError {
+                            operation: "emulated RESOLVE_NO_XDEV".into(),
+                            source: IOError::from_raw_os_error(libc::EXDEV),
+                        }
+                        .wrap(format!("component {part:?} is on a different mount"))
+                        .into(),
+                        dangling_symlink: false,
+                    });
+                }
+                walker.record_inode(&part, &next_meta);
+
                 // Is the next dirfd a symlink or an ordinary path? If we're an
                 // ordinary dirent, we just update current and move on to the
                 // next component. Nothing special here.
-                if !next
-                    .metadata()
-                    .wrap("fstat of next component")?
-                    .is_symlink()
-                {
+                if !next_meta.is_symlink() {
+                    // Trailing "" and "." components refer to the same inode,
+                    // so they don't make this an intermediate component.
+                    if remaining_components
+                        .iter()
+                        .any(|p| !matches!(p.as_bytes(), b"" | b"."))
+                    {
+                        walker.check_intermediate(&next_meta, &part)?;
+                    }
                     // We hit a non-symlink component, so clear it from the
                     // symlink stack.
                     if let Some(ref mut stack) = symlink_stack {
@@ -322,7 +648,10 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
                     continue;
                 } else {
                     // If we hit the last component and we were told to not follow
-                    // the trailing symlink, just return the link we have.
+                    // the trailing symlink, just return the link we have. Note
+                    // that a trailing "/" leaves an empty component behind, so
+                    // "link/" is always followed (and the "" component then
+                    // requires the target to be a directory), like openat2(2).
                     if remaining_components.is_empty() && no_follow_trailing {
                         current = next.into();
                         break;
@@ -332,6 +661,7 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
                     if flags.contains(ResolverFlags::NO_SYMLINKS) {
                         return Ok(PartialLookup::Partial {
                             handle: current,
+                            resolved: current_path,
                             remaining,
                             // Construct a fake OS error containing ELOOP.
                             last_error: ErrorImpl::OsError {
@@ -342,9 +672,42 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
                                 "component {part:?} is a symlink but symlink resolution is disabled",
                             ))
                             .into(),
+                            dangling_symlink: false,
                         });
                     }
 
+                    // Enforce the trailing symlink policy. Trailing "" and "."
+                    // components refer to the symlink target, so the symlink
+                    // is still the final component.
+                    if remaining_components
+                        .iter()
+                        .all(|p| matches!(p.as_bytes(), b"" | b"."))
+                    {
+                        trailing_symlink_traversals += 1;
+                        if walker
+                            .trailing_symlinks
+                            .max_traversals()
+                            .map_or(false, |max| trailing_symlink_traversals > max)
+                        {
+                            return Ok(PartialLookup::Partial {
+                                handle: current,
+                                resolved: current_path,
+                                remaining,
+                                // Construct a fake OS error containing ELOOP.
+                                last_error: ErrorImpl::OsError {
+                                    operation: "emulated trailing symlink policy".into(),
+                                    source: IOError::from_raw_os_error(libc::ELOOP),
+                                }
+                                .wrap(format!(
+                                    "component {part:?} is a trailing symlink not permitted by {:?}",
+                                    walker.trailing_symlinks,
+                                ))
+                                .into(),
+                                dangling_symlink: false,
+                            });
+                        }
+                    }
+
                     // Verify that we can follow the link.
                     // MSRV(1.69): Remove &*.
                     may_follow_link(&*current, &next).with_wrap(|| {
@@ -355,10 +718,16 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
 
                     // We need a limit on the number of symlinks we traverse to
                     // avoid hitting filesystem loops and DoSing.
+                    //
+                    // NOTE: This counter must be incremented exactly once for
+                    //       every symlink we follow (regardless of whether the
+                    //       target is absolute or relative) so that chains are
+                    //       limited the same way no matter how they are built.
                     symlink_traversals += 1;
-                    if symlink_traversals >= MAX_SYMLINK_TRAVERSALS {
+                    if symlink_traversals >= max_symlinks {
                         return Ok(PartialLookup::Partial {
                             handle: current,
+                            resolved: current_path,
                             remaining,
                             // Construct a fake OS error containing ELOOP.
                             last_error: ErrorImpl::OsError {
@@ -367,15 +736,56 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
                             }
                             .wrap("exceeded symlink limit")
                             .into(),
+                            dangling_symlink: false,
                         });
                     }
 
+                    // NOTE: We intentionally do not cache symlink targets
+                    //       (even within a single Root). The contents of a
+                    //       symlink can be swapped by an attacker at any time,
+                    //       so a cached target would be a TOCTOU bug waiting
+                    //       to happen. As a result, every symlink expansion
+                    //       always requires a fresh readlink(2). Only directory
+                    //       prefixes are cached (see DirCache), and their hit
+                    //       rate is reported by Root::resolve_cached_with_stats.
                     let link_target =
                         syscalls::readlinkat(&next, "").map_err(|err| ErrorImpl::RawOsError {
                             operation: "readlink next symlink component".into(),
                             source: err,
                         })?;
 
+                    // Bound the total amount of symlink contents we expand, to
+                    // match the kernel (see MAX_SYMLINK_TARGET_BYTES). We
+                    // return ELOOP rather than ENAMETOOLONG because the kernel
+                    // would hit its MAXSYMLINKS limit before this limit.
+                    symlink_target_bytes += link_target.as_os_str().len();
+                    if symlink_target_bytes > walker.max_symlink_target_bytes {
+                        return Ok(PartialLookup::Partial {
+                            handle: current,
+                            resolved: current_path,
+                            remaining,
+                            // Construct a fake OS error containing ELOOP.
+                            last_error: ErrorImpl::OsError {
+                                operation: "emulated symlink resolution".into(),
+                                source: IOError::from_raw_os_error(libc::ELOOP),
+                            }
+                            .wrap("exceeded symlink target length limit")
+                            .into(),
+                            dangling_symlink: false,
+                        });
+                    }
+
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        symlink = %traced_path(&expected_path),
+                        target = %traced_path(&link_target),
+                        symlink_traversals,
+                        "following symlink",
+                    );
+                    if let Some(observer) = walker.observer {
+                        observer.symlink_followed(symlink_traversals);
+                    }
+
                     // Check if it's a good idea to walk this symlink. If we are on
                     // a filesystem that supports magic-links and we've hit an
                     // absolute symlink, it is incredibly likely that this component
@@ -406,6 +816,20 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
                         .wrap("walked into a potential magic-link")?
                     }
 
+                    // RESOLVE_BENEATH does not permit jumping to the root
+                    // through absolute symlinks.
+                    if link_target.is_absolute() && flags.contains(ResolverFlags::BENEATH) {
+                        return Ok(PartialLookup::Partial {
+                            handle: current,
+                            resolved: current_path,
+                            remaining,
+                            last_error: emulated_beneath_error(&format!(
+                                "component {part:?} is an absolute symlink"
+                            )),
+                            dangling_symlink: false,
+                        });
+                    }
+
                     // Swap out the symlink component in the symlink stack with
                     // a new entry for the link target.
                     if let Some(ref mut stack) = symlink_stack {
@@ -421,7 +845,9 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
                     expected_path.pop();
 
                     // Add contents of the symlink to the set of components we are
-                    // looping over.
+                    // looping over. Because they go through the same loop as
+                    // the input path, "", "." and ".." components in the
+                    // symlink target are handled identically to the input.
                     link_target
                         .raw_components()
                         .prepend(&mut remaining_components);
@@ -436,11 +862,14 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
         }
     }
 
-    // Make sure that the path is what we expect...
-    // MSRV(1.69): Remove &*.
-    check_current(&*current, &*root, &expected_path).wrap("check final handle didn't escape")?;
-
     // We finished the lookup with no remaining components.
+    *state = WalkState {
+        current: Rc::clone(&current),
+        expected_path,
+        symlink_traversals,
+        symlink_target_bytes,
+        dotdot_clamped,
+    };
     Ok(PartialLookup::Complete(current))
 }
 
@@ -449,16 +878,19 @@ fn do_resolve<Fd: AsFd, P: AsRef<Path>>(
 pub(crate) fn resolve_partial<Fd: AsFd, P: AsRef<Path>>(
     root: Fd,
     path: P,
-    flags: ResolverFlags,
+    resolver: &Resolver,
     no_follow_trailing: bool,
 ) -> Result<PartialLookup<Rc<OwnedFd>>, Error> {
     // For partial lookups, we need to use a SymlinkStack to match openat2.
     let mut symlink_stack = SymlinkStack::new();
+    let path = path.as_ref();
 
+    let walker = Walker::new(root, resolver)?;
+    let mut state = WalkState::new(&walker.root);
     match do_resolve(
-        root,
+        &walker,
+        &mut state,
         path,
-        flags,
         no_follow_trailing,
         Some(&mut symlink_stack),
     ) {
@@ -470,22 +902,36 @@ pub(crate) fn resolve_partial<Fd: AsFd, P: AsRef<Path>>(
         // based on the symlink stack if applicable.
         Ok(PartialLookup::Partial {
             handle,
+            resolved,
             remaining,
             last_error,
+            ..
         }) => match symlink_stack.pop_top_symlink() {
             // We were in the middle of symlink resolution, so return the error
             // from the context of the top symlink in the resolution, to match
-            // openat2(2).
-            Some((handle, remaining)) => Ok(PartialLookup::Partial {
-                handle,
-                remaining,
-                last_error,
-            }),
-            // Nothing in the symlink stack, return what we got.
+            // openat2(2). If the component didn't exist, the symlink is
+            // dangling.
+            Some((handle, remaining)) => {
+                let dangling_symlink = last_error.kind() == ErrorKind::OsError(Some(libc::ENOENT));
+                // The top symlink entry was stashed while walking the
+                // original path, so the prefix is relative to path as well.
+                let resolved = walked_prefix(path, &remaining).unwrap_or_else(|| ".".into());
+                Ok(PartialLookup::Partial {
+                    handle,
+                    resolved,
+                    remaining,
+                    last_error,
+                    dangling_symlink,
+                })
+            }
+            // Nothing in the symlink stack, return what we got (but report
+            // the prefix in terms of the original path, like openat2).
             None => Ok(PartialLookup::Partial {
+                resolved: walked_prefix(path, &remaining).unwrap_or(resolved),
                 handle,
                 remaining,
                 last_error,
+                dangling_symlink: false,
             }),
         },
     }
@@ -495,8 +941,710 @@ pub(crate) fn resolve_partial<Fd: AsFd, P: AsRef<Path>>(
 pub(crate) fn resolve<Fd: AsFd, P: AsRef<Path>>(
     root: Fd,
     path: P,
-    flags: ResolverFlags,
+    resolver: &Resolver,
     no_follow_trailing: bool,
 ) -> Result<Handle, Error> {
-    do_resolve(root, path, flags, no_follow_trailing, None).and_then(TryInto::try_into)
+    let walker = Walker::new(root, resolver)?;
+    let mut state = WalkState::new(&walker.root);
+    let lookup = do_resolve(&walker, &mut state, path, no_follow_trailing, None)?;
+    drop((walker, state));
+    lookup.try_into()
+}
+
+/// Resolve `path` within `root` through user-space emulation, returning the
+/// number of symlinks followed and whether any ".." components were clamped
+/// to the root.
+pub(crate) fn resolve_with_info<Fd: AsFd, P: AsRef<Path>>(
+    root: Fd,
+    path: P,
+    resolver: &Resolver,
+    no_follow_trailing: bool,
+) -> Result<(Handle, usize, bool), Error> {
+    let walker = Walker::new(root, resolver)?;
+    let mut state = WalkState::new(&walker.root);
+    let lookup = do_resolve(&walker, &mut state, path, no_follow_trailing, None)?;
+    let (symlink_traversals, dotdot_clamped) = (state.symlink_traversals, state.dotdot_clamped);
+    drop((walker, state));
+    Ok((lookup.try_into()?, symlink_traversals, dotdot_clamped))
+}
+
+/// Resolve `path` within `root` through user-space emulation, returning the
+/// `(dev, ino)` of every component opened during the walk (including symlinks
+/// and the final target) in the order they were visited.
+pub(crate) fn resolve_with_inode_trail<Fd: AsFd, P: AsRef<Path>>(
+    root: Fd,
+    path: P,
+    resolver: &Resolver,
+    no_follow_trailing: bool,
+) -> Result<(Handle, Vec<(u64, u64)>), Error> {
+    let mut walker = Walker::new(root, resolver)?.with_inode_trail()?;
+    let mut state = WalkState::new(&walker.root);
+    let lookup = do_resolve(&walker, &mut state, path, no_follow_trailing, None)?;
+    let trail = walker
+        .inode_trail
+        .take()
+        .map(|trail| trail.inodes.into_inner())
+        .unwrap_or_default();
+    drop((walker, state));
+    Ok((lookup.try_into()?, trail))
+}
+
+/// The minimum number of paths in a [`resolve_many`] batch whose final
+/// components are in the same directory before that directory is listed with
+/// `getdents(2)` (see [`DirListings`]). Listing a directory costs a handful of
+/// syscalls, so it is only worth it for sibling-heavy batches.
+const LISTING_MIN_SIBLINGS: usize = 4;
+
+/// The maximum number of entries read when listing a directory for
+/// [`DirListings`]. Larger directories are not listed, since reading them would
+/// likely be more expensive than looking up each path individually.
+const LISTING_MAX_ENTRIES: usize = 4096;
+
+/// Filesystems where a name can be looked up if and only if it is returned by
+/// `getdents(2)` (modulo case-folding, which is checked separately). Pseudo
+/// filesystems such as procfs have entries which can be looked up but are not
+/// listed, so a missing entry in a listing of those cannot be trusted. XFS is
+/// not included because filesystems created with `ascii-ci` do
+/// case-insensitive lookups without setting `FS_CASEFOLD_FL`.
+#[allow(clippy::unnecessary_cast)] // 32-bit arches
+const LISTING_FS_TYPES: &[u64] = &[
+    libc::TMPFS_MAGIC as u64,
+    libc::EXT4_SUPER_MAGIC as u64,
+    libc::BTRFS_SUPER_MAGIC as u64,
+];
+
+/// The `FS_CASEFOLD_FL` inode flag (not exposed by rustix).
+const FS_CASEFOLD_FL: u32 = 0x4000_0000;
+
+/// Directory listings used by [`resolve_many`] to check whether the final
+/// components of sibling paths exist without doing a lookup for each of them.
+///
+/// Only negative results are taken from the listing: names which are present
+/// in the listing are still looked up (and verified) as usual, so the listing
+/// cannot cause a path to be resolved differently. A name that is missing from
+/// the listing results in an `ENOENT` error, as though the name had been
+/// removed just before it was looked up.
+struct DirListings {
+    /// The number of paths in the batch with the final component in each
+    /// directory (keyed in the same way as the [`resolve_cached`] cache).
+    siblings: HashMap<OsString, usize>,
+    /// The names in each listed directory, or `None` if the directory could
+    /// not be listed (or the listing cannot be trusted).
+    listings: HashMap<OsString, Option<HashSet<OsString>>>,
+}
+
+impl DirListings {
+    fn new<P: AsRef<Path>>(paths: &[P]) -> Self {
+        let mut siblings = HashMap::new();
+        for path in paths {
+            if let Some(key) = Self::parent_key(path.as_ref()) {
+                *siblings.entry(key).or_default() += 1;
+            }
+        }
+        Self {
+            siblings,
+            listings: HashMap::new(),
+        }
+    }
+
+    /// Get the key for the parent directory of `path`, or `None` if the final
+    /// component of `path` is not a plain name.
+    fn parent_key(path: &Path) -> Option<OsString> {
+        let mut components = path.raw_components().collect::<Vec<_>>();
+        match components.pop()?.as_bytes() {
+            b"" | b"." | b".." => return None,
+            _ => (),
+        }
+        // This must match the prefix keys computed by resolve_cached().
+        let mut key = OsString::new();
+        for dir in components {
+            if !key.is_empty() || dir.is_empty() {
+                key.push("/");
+            }
+            key.push(dir);
+        }
+        Some(key)
+    }
+
+    /// Returns whether `name` is known to not exist in the directory `dir`
+    /// (reached through the prefix `key`).
+    fn is_missing(&mut self, key: &OsStr, dir: &OwnedFd, name: &OsStr) -> bool {
+        if self.siblings.get(key).copied().unwrap_or(0) < LISTING_MIN_SIBLINGS {
+            return false;
+        }
+        if !self.listings.contains_key(key) {
+            self.listings.insert(key.to_os_string(), Self::list(dir));
+        }
+        match self.listings.get(key) {
+            Some(Some(names)) => !names.contains(name),
+            _ => false,
+        }
+    }
+
+    /// List the names in `dir`, if the listing can be trusted.
+    fn list(dir: &OwnedFd) -> Option<HashSet<OsString>> {
+        #[allow(clippy::unnecessary_cast)] // 32-bit arches
+        let fs_type = syscalls::fstatfs(dir).ok()?.f_type as u64;
+        if !LISTING_FS_TYPES.contains(&fs_type) {
+            return None;
+        }
+        let dirfd = dir
+            .reopen(&GLOBAL_PROCFS_HANDLE, OpenFlags::O_DIRECTORY)
+            .ok()?;
+        // Lookups in case-folded directories are case-insensitive.
+        let flags = rustix::fs::ioctl_getflags(&dirfd).ok()?;
+        if flags.bits() & FS_CASEFOLD_FL != 0 {
+            return None;
+        }
+
+        let mut names = HashSet::new();
+        for dentry in rustix::fs::Dir::new(dirfd).ok()? {
+            let name = dentry.ok()?.file_name().to_bytes().to_vec();
+            if names.len() >= LISTING_MAX_ENTRIES {
+                return None;
+            }
+            names.insert(OsString::from_vec(name));
+        }
+        Some(names)
+    }
+}
+
+/// Resolve `path` within the walker's root, re-using (and adding to) the set
+/// of already-resolved directory prefixes in `cache`. `listings` is used to
+/// skip lookups of final components that do not exist.
+///
+/// `cache` is keyed by the raw (un-normalised) prefix of the path, so that the
+/// walk of any path is identical to what [`resolve`] would do.
+fn resolve_cached(
+    walker: &Walker,
+    cache: &mut HashMap<OsString, WalkState>,
+    listings: &mut DirListings,
+    path: &Path,
+) -> Result<Handle, Error> {
+    if walker.flags.contains(ResolverFlags::BENEATH) && path.is_absolute() {
+        Err(emulated_beneath_error("path is absolute"))?
+    }
+
+    let components = path.raw_components().collect::<Vec<_>>();
+    let (last, dirs) = components
+        .split_last()
+        .expect("raw_components should always return at least one component");
+
+    // Compute the key for each directory prefix of the path.
+    let prefixes = dirs
+        .iter()
+        .scan(OsString::new(), |prefix, dir| {
+            if !prefix.is_empty() || dir.is_empty() {
+                prefix.push("/");
+            }
+            prefix.push(dir);
+            Some(prefix.clone())
+        })
+        .collect::<Vec<_>>();
+
+    // Start from the longest prefix we have already resolved.
+    let (skip, mut state) = prefixes
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(idx, prefix)| cache.get(prefix).map(|state| (idx + 1, state.clone())))
+        .unwrap_or_else(|| (0, WalkState::new(&walker.root)));
+
+    // Each walk() only sees a single component, so we need to apply the setid
+    // policy for intermediate directories here. As with walk(), the root
+    // itself and directories followed only by "" and "." are not checked --
+    // which means cached entries may not have been checked yet either.
+    let check_intermediate = |state: &WalkState, idx: usize| -> Result<(), Error> {
+        if (walker.setid_policy.rejects_intermediate() || walker.reject_deleted)
+            && !Rc::ptr_eq(&state.current, &walker.root)
+            && components[idx + 1..]
+                .iter()
+                .any(|p| !matches!(p.as_bytes(), b"" | b"."))
+        {
+            // MSRV(1.69): Remove *.
+            let meta = (*state.current)
+                .metadata()
+                .wrap("fstat of next component")?;
+            walker.check_intermediate(&meta, dirs[idx])?;
+        }
+        Ok(())
+    };
+    if let Some(idx) = skip.checked_sub(1) {
+        check_intermediate(&state, idx)?;
+    }
+
+    // Walk the remaining directory components one at a time, caching them as
+    // we go. Symlink traversal counts are carried across components in the
+    // walk state, so the symlink limit applies to the path as a whole.
+    for (idx, (dir, prefix)) in dirs.iter().zip(&prefixes).enumerate().skip(skip) {
+        if let PartialLookup::Partial { last_error, .. } =
+            walk(walker, &mut state, dir, false, None)?
+        {
+            return Err(last_error);
+        }
+        check_intermediate(&state, idx)?;
+        cache.insert(prefix.clone(), state.clone());
+    }
+
+    // Only plain names can be checked against the listing (a trailing "/"
+    // leaves a "" component, which refers to the directory itself).
+    let key = prefixes.last().map(OsString::as_os_str).unwrap_or_default();
+    if !matches!(last.as_bytes(), b"" | b"." | b"..")
+        && listings.is_missing(key, &state.current, last)
+    {
+        Err(ErrorImpl::OsError {
+            operation: "open next component of resolution (from directory listing)".into(),
+            source: IOError::from_raw_os_error(libc::ENOENT),
+        })?
+    }
+
+    if let PartialLookup::Partial { last_error, .. } = walk(walker, &mut state, last, false, None)?
+    {
+        return Err(last_error);
+    }
+    walker.check_complete(&state)?;
+
+    // The final handle might be shared with the cache (or be the root), in
+    // which case we need to hand out a copy.
+    let WalkState { current, .. } = state;
+    // MSRV(1.70): Use Rc::into_inner().
+    let fd = Rc::try_unwrap(current).or_else(|current| -> Result<_, Error> {
+        current.try_clone().map_err(|err| {
+            ErrorImpl::OsError {
+                operation: "dup shared handle from resolution cache".into(),
+                source: err,
+            }
+            .into()
+        })
+    })?;
+    Ok(Handle::from_fd(fd))
+}
+
+/// Resolve each of `paths` within `root` through user-space emulation.
+///
+/// The resolution of directory prefixes shared between paths is only done
+/// once. The cache is local to this call, and so there is no need to worry
+/// about invalidation.
+pub(crate) fn resolve_many<Fd: AsFd, I>(
+    root: Fd,
+    paths: I,
+    resolver: &Resolver,
+) -> Vec<Result<Handle, Error>>
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    let mut walker = None;
+    let mut cache = HashMap::new();
+    let paths = paths.into_iter().collect::<Vec<_>>();
+    let mut listings = DirListings::new(&paths);
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let walker = match walker {
+                Some(ref walker) => walker,
+                None => walker.insert(Walker::new(&root, resolver)?),
+            };
+            resolve_cached(walker, &mut cache, &mut listings, path.as_ref())
+        })
+        .collect()
+}
+
+/// Test-only hooks to inject filesystem changes into the middle of a walk.
+#[cfg(test)]
+pub(crate) mod hooks {
+    use std::{cell::RefCell, path::Path};
+
+    type Hook = Box<dyn FnMut(&Path)>;
+
+    thread_local! {
+        static AFTER_OPEN: RefCell<Option<Hook>> = const { RefCell::new(None) };
+    }
+
+    /// Called after each component is opened (but before it is checked),
+    /// with the path the walk expects the component to be at.
+    pub(super) fn after_open(path: &Path) {
+        AFTER_OPEN.with(|hook| {
+            if let Some(ref mut hook) = *hook.borrow_mut() {
+                hook(path)
+            }
+        })
+    }
+
+    /// Run `f` with `hook` installed as the after-open hook.
+    pub(crate) fn with_after_open<T, H, F>(hook: H, f: F) -> T
+    where
+        H: FnMut(&Path) + 'static,
+        F: FnOnce() -> T,
+    {
+        let old = AFTER_OPEN.with(|cur| cur.replace(Some(Box::new(hook))));
+        let ret = f();
+        AFTER_OPEN.with(|cur| cur.replace(old));
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DirListings;
+    use crate::{
+        error::ErrorKind,
+        flags::OpenFlags,
+        resolvers::{opath, Resolver, MAX_SYMLINK_TRAVERSALS},
+        syscalls::trace::{self, TraceEntry},
+        utils::FdExt,
+        Handle, SetidPolicy,
+    };
+
+    use std::{
+        ffi::OsString,
+        fs::File,
+        os::unix::fs as unixfs,
+        path::{Path, PathBuf},
+    };
+
+    use anyhow::Error;
+    use pretty_assertions::assert_eq;
+    use rustix::io::Errno;
+
+    /// Create a chain of `len` symlinks (`chain/l0` -> ... -> `chain/l{len-1}`
+    /// -> `target`) where the links alternate between absolute and relative
+    /// targets.
+    fn make_alternating_chain(root: &Path, len: usize) -> Result<(), Error> {
+        std::fs::create_dir_all(root.join("chain"))?;
+        std::fs::create_dir_all(root.join("target"))?;
+        File::create(root.join("target/file"))?;
+        for idx in 0..len {
+            let next = if idx + 1 == len {
+                "target".to_string()
+            } else {
+                format!("chain/l{}", idx + 1)
+            };
+            let link_target = if idx % 2 == 0 {
+                format!("/{next}")
+            } else {
+                // Relative targets are resolved relative to chain/.
+                format!("../{next}")
+            };
+            unixfs::symlink(link_target, root.join(format!("chain/l{idx}")))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn alternating_symlink_chain() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        make_alternating_chain(dir.path(), MAX_SYMLINK_TRAVERSALS)?;
+        let root = File::open(dir.path())?;
+        let real_root = root.as_unsafe_path_unchecked()?;
+
+        // chain/l1 is MAX_SYMLINK_TRAVERSALS-1 links away from target, which is
+        // the longest chain we will follow.
+        for (path, expected) in [
+            ("chain/l1", "target"),
+            ("chain/l1/file", "target/file"),
+            ("/chain/l1/../target/file", "target/file"),
+            ("chain/l2/file", "target/file"),
+            (&format!("chain/l{}", MAX_SYMLINK_TRAVERSALS - 1), "target"),
+        ] {
+            let handle = opath::resolve(&root, path, &Resolver::default(), false)?;
+            assert_eq!(
+                handle.as_unsafe_path_unchecked()?,
+                real_root.join(expected),
+                "resolve({path:?}) through alternating symlink chain"
+            );
+        }
+
+        // chain/l0 is MAX_SYMLINK_TRAVERSALS links away from target.
+        for path in ["chain/l0", "chain/l0/file"] {
+            let err = opath::resolve(&root, path, &Resolver::default(), false)
+                .expect_err("symlink chain longer than the limit should fail");
+            assert_eq!(
+                err.kind(),
+                ErrorKind::OsError(Some(libc::ELOOP)),
+                "resolve({path:?}) through over-long alternating symlink chain"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn syscall_trace() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("a/b"))?;
+        unixfs::symlink("/a", dir.path().join("link"))?;
+        let root = File::open(dir.path())?;
+
+        // Only look at the lookups done relative to the root, rather than the
+        // procfs lookups done as part of the final path verification.
+        let lookups = |trace: Vec<TraceEntry>| -> Vec<_> {
+            trace
+                .into_iter()
+                .filter(|entry| ["a", "b", "link"].contains(&entry.path.to_str().unwrap_or("")))
+                .collect()
+        };
+        let lookup_flags =
+            OpenFlags::O_PATH | OpenFlags::O_NOFOLLOW | OpenFlags::O_CLOEXEC | OpenFlags::O_NOCTTY;
+
+        let (handle, trace) =
+            trace::capture(|| opath::resolve(&root, "a/b", &Resolver::default(), false));
+        handle?;
+        assert!(
+            trace
+                .iter()
+                .filter(|entry| entry.syscall == "openat")
+                .all(|entry| entry.flags.map(|f| f.contains(OpenFlags::O_NOFOLLOW)) == Some(true)),
+            "all openat calls should use O_NOFOLLOW: {trace:#?}"
+        );
+        // Intermediate directories are opened with O_DIRECTORY, which lets us
+        // skip the fstat(2) of each intermediate component.
+        assert_eq!(
+            trace
+                .iter()
+                .skip_while(|entry| entry.path != Path::new("a"))
+                .take_while(|entry| entry.path != Path::new("b"))
+                .map(|entry| entry.syscall)
+                .collect::<Vec<_>>(),
+            vec!["openat"],
+            "intermediate components should not need an fstat: {trace:#?}"
+        );
+        assert_eq!(
+            lookups(trace),
+            vec![
+                TraceEntry {
+                    syscall: "openat",
+                    path: "a".into(),
+                    flags: Some(lookup_flags | OpenFlags::O_DIRECTORY),
+                    result: Ok(()),
+                },
+                TraceEntry {
+                    syscall: "openat",
+                    path: "b".into(),
+                    flags: Some(lookup_flags),
+                    result: Ok(()),
+                },
+            ]
+        );
+
+        // Symlinks are expanded inline, restarting from the root for absolute
+        // symlinks. An intermediate symlink fails the O_DIRECTORY open and is
+        // then re-opened to check what it is.
+        let (handle, trace) =
+            trace::capture(|| opath::resolve(&root, "link/b", &Resolver::default(), false));
+        handle?;
+        assert_eq!(
+            lookups(trace)
+                .into_iter()
+                .map(|entry| (entry.path, entry.result))
+                .collect::<Vec<_>>(),
+            vec![
+                (PathBuf::from("link"), Err(Errno::NOTDIR)),
+                ("link".into(), Ok(())),
+                ("a".into(), Ok(())),
+                ("b".into(), Ok(())),
+            ],
+        );
+
+        // If we need the metadata of every component, the O_DIRECTORY
+        // shortcut is not used.
+        let (handle, trace) = trace::capture(|| {
+            opath::resolve(
+                &root,
+                "a/b",
+                &Resolver {
+                    setid_policy: SetidPolicy::RejectIntermediate,
+                    ..Default::default()
+                },
+                false,
+            )
+        });
+        handle?;
+        assert_eq!(
+            lookups(trace)
+                .into_iter()
+                .map(|entry| entry.flags)
+                .collect::<Vec<_>>(),
+            vec![Some(lookup_flags), Some(lookup_flags)],
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_many_shared_prefix() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("a/b"))?;
+        std::fs::write(dir.path().join("a/b/file1"), b"")?;
+        std::fs::write(dir.path().join("a/b/file2"), b"")?;
+        unixfs::symlink("/a", dir.path().join("link"))?;
+        let root = File::open(dir.path())?;
+
+        let (handles, trace) = trace::capture(|| {
+            opath::resolve_many(
+                &root,
+                [
+                    "a/b/file1",
+                    "a/b/file2",
+                    "a/b/nonexist",
+                    "a/b/..",
+                    "link/b/file1",
+                ],
+                &Resolver::default(),
+            )
+        });
+        let handles = handles
+            .into_iter()
+            .map(|handle| handle.and_then(|handle| handle.as_unsafe_path_unchecked()))
+            .map(|path| path.map_err(|err| err.kind()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            handles,
+            vec![
+                Ok(dir.path().join("a/b/file1")),
+                Ok(dir.path().join("a/b/file2")),
+                Err(ErrorKind::OsError(Some(libc::ENOENT))),
+                Ok(dir.path().join("a")),
+                Ok(dir.path().join("a/b/file1")),
+            ],
+            "resolve_many results should be in the same order as the input"
+        );
+
+        // The shared "a/b" prefix should only be walked once. "link/b" is a
+        // different raw prefix, and so it is walked again.
+        assert_eq!(
+            trace
+                .into_iter()
+                .filter(|entry| entry.syscall == "openat")
+                .map(|entry| entry.path)
+                .filter(|path| !path.is_absolute())
+                .collect::<Vec<_>>(),
+            vec![
+                PathBuf::from("a"),
+                "b".into(),
+                "file1".into(),
+                "file2".into(),
+                "nonexist".into(),
+                "..".into(),
+                "link".into(),
+                // The leading "/" of the symlink target is walked as ".".
+                ".".into(),
+                "a".into(),
+                "b".into(),
+                "file1".into(),
+            ],
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_many_sibling_listing() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("d/sub"))?;
+        for idx in 0..4 {
+            std::fs::write(dir.path().join(format!("d/f{idx}")), b"")?;
+            std::fs::write(dir.path().join(format!("d/sub/g{idx}")), b"")?;
+        }
+        unixfs::symlink("f0", dir.path().join("d/link"))?;
+        unixfs::symlink("nonexist", dir.path().join("d/dangling"))?;
+        unixfs::symlink("/../../f1", dir.path().join("d/escape"))?;
+        unixfs::symlink("/d", dir.path().join("dlink"))?;
+        let root = File::open(dir.path())?;
+
+        let paths = [
+            "d/f0",
+            "d/missing0",
+            "d/f1",
+            "d/missing1",
+            "d/f2",
+            "d/f3",
+            "d/missing2",
+            "d/link",
+            "d/dangling",
+            "d/escape",
+            "d/sub",
+            // d/sub has enough siblings to be listed, but the "", "." and ".."
+            // components are never in the listing.
+            "d/sub/g0",
+            "d/sub/g1",
+            "d/sub/g2",
+            "d/sub/g3",
+            "d/sub/",
+            "d/sub/.",
+            "d/sub/..",
+            "d/missing3/",
+            "d/..",
+            "d/f0/missing",
+            "dlink/missing4",
+            "/d/missing5",
+        ];
+        let (handles, trace) =
+            trace::capture(|| opath::resolve_many(&root, paths, &Resolver::default()));
+
+        // The results must be identical to resolving each path individually.
+        let to_result = |handle: Result<Handle, crate::error::Error>| {
+            handle
+                .and_then(|handle| handle.as_unsafe_path_unchecked())
+                .map_err(|err| err.kind())
+        };
+        for (path, handle) in paths.iter().zip(handles) {
+            assert_eq!(
+                to_result(handle),
+                to_result(opath::resolve(&root, path, &Resolver::default(), false)),
+                "resolve_many({path:?}) should match resolve"
+            );
+        }
+
+        // If the directory listing can be used on this filesystem, the
+        // non-existent siblings should not have been looked up.
+        let dfd = opath::resolve(&root, "d", &Resolver::default(), false)?.into();
+        if DirListings::list(&dfd).is_some() {
+            let lookups = trace
+                .into_iter()
+                .filter(|entry| entry.syscall == "openat")
+                .map(|entry| entry.path)
+                .collect::<Vec<_>>();
+            for name in ["missing0", "missing1", "missing2"] {
+                assert!(
+                    !lookups.contains(&PathBuf::from(name)),
+                    "resolve_many should not look up {name:?} if it is missing from the listing: {lookups:?}"
+                );
+            }
+            // Siblings that exist are still looked up.
+            for name in ["f0", "f1", "f2", "f3", "link", "dangling", "escape", "sub"] {
+                assert!(
+                    lookups.contains(&PathBuf::from(name)),
+                    "resolve_many should look up {name:?} if it is in the listing: {lookups:?}"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn dir_listings_parent_key() {
+        // The keys are the raw prefixes used by resolve_cached(), which are
+        // not normalised (the root component of an absolute path becomes an
+        // extra "/").
+        for (path, key) in [
+            ("a", Some("")),
+            ("a/b", Some("a")),
+            ("a//b/c", Some("a//b")),
+            ("/a", Some("/")),
+            ("/a/b", Some("//a")),
+            ("a/b/", None),
+            ("a/.", None),
+            ("a/..", None),
+            ("", None),
+        ] {
+            assert_eq!(
+                DirListings::parent_key(Path::new(path)),
+                key.map(OsString::from),
+                "DirListings::parent_key({path:?})"
+            );
+        }
+    }
 }
@@ -18,9 +18,10 @@
  */
 
 use crate::{
-    error::{Error, ErrorImpl},
+    error::{Error, ErrorImpl, ErrorKind},
     flags::{OpenFlags, ResolverFlags},
-    resolvers::PartialLookup,
+    kernel,
+    resolvers::{is_dangling_symlink, walked_prefix, PartialLookup},
     syscalls::{self, OpenHow},
     utils::PathIterExt,
     Handle,
@@ -28,10 +29,100 @@ use crate::{
 
 use std::{
     fs::File,
-    os::unix::io::AsFd,
+    os::unix::io::{AsFd, BorrowedFd, OwnedFd},
     path::{Path, PathBuf},
 };
 
+/// Compute the `RESOLVE_*` flags to pass to `openat2(2)` for the given
+/// [`ResolverFlags`].
+///
+/// By default we use `RESOLVE_IN_ROOT`, but [`ResolverFlags::BENEATH`] switches
+/// to `RESOLVE_BENEATH` (the kernel rejects setting both).
+pub(super) fn resolve_bits(rflags: ResolverFlags) -> u64 {
+    let scope = if rflags.contains(ResolverFlags::BENEATH) {
+        libc::RESOLVE_BENEATH
+    } else {
+        libc::RESOLVE_IN_ROOT
+    };
+    // SINGLE_COMPONENT is implemented entirely in userspace.
+    let rflags = rflags.difference(ResolverFlags::SINGLE_COMPONENT);
+    scope | libc::RESOLVE_NO_MAGICLINKS | rflags.bits()
+}
+
+/// The number of times an `openat2(2)` call that failed with `EAGAIN` is retried
+/// before giving up.
+pub(crate) const OPENAT2_EAGAIN_RETRIES: usize = 32;
+
+/// Call `openat2(2)`, retrying if it fails with `EAGAIN`.
+///
+/// With `RESOLVE_IN_ROOT` or `RESOLVE_BENEATH`, `openat2(2)` fails with `EAGAIN`
+/// if there was a racing rename or mount *anywhere on the system* (because the
+/// kernel cannot be sure the lookup did not escape the root), and expects the
+/// caller to retry. This can happen pretty frequently (especially during
+/// container startup), so we retry up to [`OPENAT2_EAGAIN_RETRIES`] times. If
+/// it still fails, we error out.
+fn openat2_retry(
+    root: BorrowedFd<'_>,
+    path: &Path,
+    how: &OpenHow,
+    operation: &'static str,
+) -> Result<OwnedFd, Error> {
+    for _ in 0..=OPENAT2_EAGAIN_RETRIES {
+        #[cfg(test)]
+        if hooks::inject_eagain() {
+            continue;
+        }
+        #[cfg(test)]
+        if let Some(errno) = hooks::injected_errno(how) {
+            Err(ErrorImpl::OsError {
+                operation: operation.into(),
+                source: std::io::Error::from_raw_os_error(errno),
+            })?
+        }
+
+        match syscalls::openat2(root, path, how) {
+            Ok(fd) => return Ok(fd),
+            // NOTE: ENOSYS is passed through (rather than being converted to
+            // NotSupported) so that callers can see that openat2 was blocked
+            // for this call. See blocked_errno().
+            Err(err) => match err.root_cause().raw_os_error() {
+                Some(libc::EAGAIN) => continue,
+                // TODO: Add wrapper for known-bad openat2 return codes.
+                //Some(libc::EXDEV) | Some(libc::ELOOP) => { ... }
+                _ => Err(ErrorImpl::RawOsError {
+                    operation: operation.into(),
+                    source: err,
+                })?,
+            },
+        }
+    }
+
+    Err(ErrorImpl::SafetyViolation {
+        description: format!(
+            "racing filesystem changes caused openat2 to abort (still failing with EAGAIN after {OPENAT2_EAGAIN_RETRIES} retries)"
+        )
+        .into(),
+    })?
+}
+
+/// If `err` indicates that the `openat2(2)` syscall itself was blocked (rather
+/// than the lookup failing), return the errno it failed with.
+///
+/// Seccomp filters which predate `openat2(2)` (such as some container runtime
+/// profiles) usually make it fail with `EPERM` or `ENOSYS`, even though the
+/// kernel supports it. In that case, the operation should be retried with the
+/// emulated resolver. Note that `EPERM` is also returned for genuine permission
+/// errors when opening without `O_PATH` (such as opening an immutable file for
+/// writing), so callers need to check whether an `O_PATH` lookup is also
+/// blocked before treating `EPERM` from such an open as `openat2(2)` being
+/// blocked.
+pub(crate) fn blocked_errno(err: &Error) -> Option<i32> {
+    match err.kind() {
+        ErrorKind::OsError(Some(errno @ (libc::EPERM | libc::ENOSYS))) => Some(errno),
+        _ => None,
+    }
+}
+
 /// Open `path` within `root` through `openat(2)`.
 ///
 /// This is an optimised version of `resolve(root, path, ...)?.reopen(flags)`.
@@ -41,28 +132,20 @@ pub(crate) fn open<Fd: AsFd, P: AsRef<Path>>(
     rflags: ResolverFlags,
     oflags: OpenFlags,
 ) -> Result<File, Error> {
-    if !*syscalls::OPENAT2_IS_SUPPORTED {
+    let rflags = resolve_bits(rflags);
+    if !kernel::kernel_features().has_resolve_flags(rflags) {
         Err(ErrorImpl::NotSupported {
             feature: "openat2".into(),
         })?
     }
 
-    let rflags = libc::RESOLVE_IN_ROOT | libc::RESOLVE_NO_MAGICLINKS | rflags.bits();
     let how = OpenHow {
         flags: oflags.bits() as u64,
         resolve: rflags,
         ..Default::default()
     };
 
-    syscalls::openat2(&root, path.as_ref(), &how)
-        .map(File::from)
-        .map_err(|err| {
-            ErrorImpl::RawOsError {
-                operation: "openat2 one-shot open".into(),
-                source: err,
-            }
-            .into()
-        })
+    openat2_retry(root.as_fd(), path.as_ref(), &how, "openat2 one-shot open").map(File::from)
 }
 
 /// Resolve `path` within `root` through `openat2(2)`.
@@ -72,18 +155,17 @@ pub(crate) fn resolve<Fd: AsFd, P: AsRef<Path>>(
     rflags: ResolverFlags,
     no_follow_trailing: bool,
 ) -> Result<Handle, Error> {
-    if !*syscalls::OPENAT2_IS_SUPPORTED {
-        Err(ErrorImpl::NotSupported {
-            feature: "openat2".into(),
-        })?
-    }
-
     // Copy the O_NOFOLLOW and RESOLVE_NO_SYMLINKS bits from flags.
     let mut oflags = OpenFlags::O_PATH;
     if no_follow_trailing {
         oflags.insert(OpenFlags::O_NOFOLLOW);
     }
-    let rflags = libc::RESOLVE_IN_ROOT | libc::RESOLVE_NO_MAGICLINKS | rflags.bits();
+    let rflags = resolve_bits(rflags);
+    if !kernel::kernel_features().has_resolve_flags(rflags) {
+        Err(ErrorImpl::NotSupported {
+            feature: "openat2".into(),
+        })?
+    }
 
     let how = OpenHow {
         flags: oflags.bits() as u64,
@@ -91,34 +173,7 @@ pub(crate) fn resolve<Fd: AsFd, P: AsRef<Path>>(
         ..Default::default()
     };
 
-    // openat2(2) can fail with -EAGAIN if there was a racing rename or mount
-    // *anywhere on the system*. This can happen pretty frequently, so what we
-    // do is attempt the openat2(2) a couple of times. If it still fails, just
-    // error out.
-    for _ in 0..16 {
-        match syscalls::openat2(&root, path.as_ref(), &how) {
-            Ok(file) => return Ok(Handle::from_fd(file)),
-            Err(err) => match err.root_cause().raw_os_error() {
-                Some(libc::ENOSYS) => {
-                    // shouldn't happen
-                    Err(ErrorImpl::NotSupported {
-                        feature: "openat2".into(),
-                    })?
-                }
-                Some(libc::EAGAIN) => continue,
-                // TODO: Add wrapper for known-bad openat2 return codes.
-                //Some(libc::EXDEV) | Some(libc::ELOOP) => { ... }
-                _ => Err(ErrorImpl::RawOsError {
-                    operation: "openat2 subpath".into(),
-                    source: err,
-                })?,
-            },
-        }
-    }
-
-    Err(ErrorImpl::SafetyViolation {
-        description: "racing filesystem changes caused openat2 to abort".into(),
-    })?
+    openat2_retry(root.as_fd(), path.as_ref(), &how, "openat2 subpath").map(Handle::from_fd)
 }
 
 /// Resolve as many components as possible in `path` within `root` using
@@ -132,6 +187,9 @@ pub(crate) fn resolve_partial<Fd: AsFd>(
     let root = root.as_fd();
     let mut last_error = match resolve(root, path, rflags, no_follow_trailing) {
         Ok(handle) => return Ok(PartialLookup::Complete(handle)),
+        // There is no point walking up the path if openat2 was blocked, every
+        // lookup will fail in the same way.
+        Err(err) if blocked_errno(&err).is_some() => return Err(err),
         Err(err) => err,
     };
 
@@ -139,14 +197,18 @@ pub(crate) fn resolve_partial<Fd: AsFd>(
     //       paths with a large number of components this could make a
     //       significant difference, though in practice you'll only see.
     //       really large paths this could make a significant difference.
-    for (path, remaining) in path.partial_ancestors() {
-        match resolve(root, path, rflags, no_follow_trailing) {
+    for (ancestor, remaining) in path.partial_ancestors() {
+        match resolve(root, ancestor, rflags, no_follow_trailing) {
             Ok(handle) => {
+                let remaining = remaining.map(PathBuf::from).unwrap_or("".into());
+                let dangling_symlink = is_dangling_symlink(&handle, &remaining, &last_error);
                 return Ok(PartialLookup::Partial {
                     handle,
-                    remaining: remaining.map(PathBuf::from).unwrap_or("".into()),
+                    resolved: walked_prefix(path, &remaining).unwrap_or_else(|| ancestor.into()),
+                    remaining,
                     last_error,
-                })
+                    dangling_symlink,
+                });
             }
             Err(err) => last_error = err,
         }
@@ -165,7 +227,226 @@ pub(crate) fn resolve_partial<Fd: AsFd>(
                 operation: "clone root".into(),
                 source: err,
             })?,
+        resolved: ".".into(),
         remaining: path.into(),
         last_error,
+        dangling_symlink: false,
     })
 }
+
+/// Test-only hooks to make `openat2(2)` fail with `EAGAIN` (since racing mount
+/// or rename operations cannot be triggered deterministically) or with other
+/// errors.
+#[cfg(test)]
+pub(crate) mod hooks {
+    use crate::syscalls::OpenHow;
+
+    use std::cell::Cell;
+
+    /// Decides which `openat2(2)` attempts an injected errno applies to.
+    type ErrnoFilter = fn(&OpenHow) -> bool;
+
+    thread_local! {
+        static INJECT_EAGAIN: Cell<usize> = const { Cell::new(0) };
+        static INJECT_ERRNO: Cell<Option<(i32, ErrnoFilter)>> = const { Cell::new(None) };
+    }
+
+    /// Returns whether the next `openat2(2)` attempt should be treated as
+    /// having failed with `EAGAIN`.
+    pub(super) fn inject_eagain() -> bool {
+        INJECT_EAGAIN.with(|left| match left.get() {
+            0 => false,
+            n => {
+                left.set(n - 1);
+                true
+            }
+        })
+    }
+
+    /// Run `f` with the next `count` `openat2(2)` attempts failing with
+    /// `EAGAIN`.
+    pub(crate) fn with_injected_eagain<T, F>(count: usize, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let old = INJECT_EAGAIN.with(|cur| cur.replace(count));
+        let ret = f();
+        INJECT_EAGAIN.with(|cur| cur.replace(old));
+        ret
+    }
+
+    /// Returns the errno that an `openat2(2)` attempt with `how` should fail
+    /// with, if any.
+    pub(super) fn injected_errno(how: &OpenHow) -> Option<i32> {
+        INJECT_ERRNO
+            .with(Cell::get)
+            .and_then(|(errno, filter)| filter(how).then_some(errno))
+    }
+
+    /// Run `f` with all `openat2(2)` attempts failing with `errno` (to simulate
+    /// `openat2(2)` being blocked by a seccomp filter).
+    pub(crate) fn with_injected_errno<T, F>(errno: i32, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        with_injected_errno_if(errno, |_| true, f)
+    }
+
+    /// Run `f` with the `openat2(2)` attempts for which `filter` returns
+    /// `true` failing with `errno`.
+    pub(crate) fn with_injected_errno_if<T, F>(errno: i32, filter: fn(&OpenHow) -> bool, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let old = INJECT_ERRNO.with(|cur| cur.replace(Some((errno, filter))));
+        let ret = f();
+        INJECT_ERRNO.with(|cur| cur.replace(old));
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::ErrorKind,
+        flags::{OpenFlags, ResolverFlags},
+        kernel,
+        resolvers::{
+            openat2::{self, hooks, OPENAT2_EAGAIN_RETRIES},
+            PartialLookup,
+        },
+        tests::common as tests_common,
+        utils::FdExt,
+    };
+
+    use anyhow::Error;
+    use pretty_assertions::assert_eq;
+
+    // Racing mount or rename operations cannot be triggered deterministically,
+    // so these tests use hooks::with_injected_eagain to simulate openat2(2)
+    // failing with EAGAIN.
+
+    #[test]
+    fn resolve_eagain_retry() -> Result<(), Error> {
+        if !kernel::kernel_features().has_openat2 {
+            return Ok(());
+        }
+        let root_dir = tests_common::create_basic_tree()?;
+        let root = std::fs::File::open(&root_dir)?;
+
+        let handle = hooks::with_injected_eagain(OPENAT2_EAGAIN_RETRIES, || {
+            openat2::resolve(&root, "b/c/file", ResolverFlags::empty(), false)
+        })?;
+        assert_eq!(
+            handle.as_unsafe_path_unchecked()?,
+            root_dir.path().join("b/c/file"),
+            "resolve should succeed on the last retry"
+        );
+
+        let partial = hooks::with_injected_eagain(OPENAT2_EAGAIN_RETRIES, || {
+            openat2::resolve_partial(&root, "b/c/file".as_ref(), ResolverFlags::empty(), false)
+        })?;
+        assert!(
+            matches!(partial, PartialLookup::Complete(_)),
+            "resolve_partial should succeed on the last retry: {partial:?}"
+        );
+
+        let file = hooks::with_injected_eagain(OPENAT2_EAGAIN_RETRIES, || {
+            openat2::open(
+                &root,
+                "b/c/file",
+                ResolverFlags::empty(),
+                OpenFlags::O_RDONLY,
+            )
+        })?;
+        assert_eq!(
+            file.as_unsafe_path_unchecked()?,
+            root_dir.path().join("b/c/file"),
+            "open should succeed on the last retry"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_eagain_exhausted() -> Result<(), Error> {
+        if !kernel::kernel_features().has_openat2 {
+            return Ok(());
+        }
+        let root_dir = tests_common::create_basic_tree()?;
+        let root = std::fs::File::open(&root_dir)?;
+
+        assert_eq!(
+            hooks::with_injected_eagain(OPENAT2_EAGAIN_RETRIES + 1, || {
+                openat2::resolve(&root, "b/c/file", ResolverFlags::empty(), false)
+            })
+            .map(|_| ())
+            .map_err(|err| err.kind()),
+            Err(ErrorKind::SafetyViolation),
+            "resolve should give up after {OPENAT2_EAGAIN_RETRIES} retries"
+        );
+        assert_eq!(
+            hooks::with_injected_eagain(OPENAT2_EAGAIN_RETRIES + 1, || {
+                openat2::open(
+                    &root,
+                    "b/c/file",
+                    ResolverFlags::empty(),
+                    OpenFlags::O_RDONLY,
+                )
+            })
+            .map(|_| ())
+            .map_err(|err| err.kind()),
+            Err(ErrorKind::SafetyViolation),
+            "open should give up after {OPENAT2_EAGAIN_RETRIES} retries"
+        );
+
+        // The injected failures are only used up by the first resolve, so
+        // resolve_partial falls back to resolving the parent directory.
+        let partial = hooks::with_injected_eagain(OPENAT2_EAGAIN_RETRIES + 1, || {
+            openat2::resolve_partial(&root, "b/c/file".as_ref(), ResolverFlags::empty(), false)
+        })?;
+        match partial {
+            PartialLookup::Partial {
+                handle,
+                remaining,
+                last_error,
+                ..
+            } => {
+                assert_eq!(
+                    handle.as_unsafe_path_unchecked()?,
+                    root_dir.path().join("b/c"),
+                    "resolve_partial should resolve the parent"
+                );
+                assert_eq!(remaining, std::path::PathBuf::from("file"));
+                assert_eq!(last_error.kind(), ErrorKind::SafetyViolation);
+            }
+            PartialLookup::Complete(handle) => {
+                panic!("resolve_partial should not be complete: {handle:?}")
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_partial_blocked() -> Result<(), Error> {
+        if !kernel::kernel_features().has_openat2 {
+            return Ok(());
+        }
+        let root_dir = tests_common::create_basic_tree()?;
+        let root = std::fs::File::open(&root_dir)?;
+
+        for errno in [libc::EPERM, libc::ENOSYS] {
+            let res = hooks::with_injected_errno(errno, || {
+                openat2::resolve_partial(&root, "b/c/file".as_ref(), ResolverFlags::empty(), false)
+            });
+            assert_eq!(
+                res.as_ref().map_err(openat2::blocked_errno).map(|_| ()),
+                Err(Some(errno)),
+                "resolve_partial should not walk up the path if openat2 is blocked: {res:?}"
+            );
+        }
+
+        Ok(())
+    }
+}
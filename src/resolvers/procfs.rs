@@ -32,7 +32,7 @@
 use crate::{
     error::{Error, ErrorExt, ErrorImpl},
     flags::{OpenFlags, ResolverFlags},
-    procfs,
+    kernel, procfs,
     resolvers::MAX_SYMLINK_TRAVERSALS,
     syscalls::{self, OpenHow},
     utils::{self, FdExt, PathIterExt},
@@ -58,7 +58,7 @@ pub(crate) enum ProcfsResolver {
 
 impl Default for ProcfsResolver {
     fn default() -> Self {
-        if *syscalls::OPENAT2_IS_SUPPORTED {
+        if kernel::kernel_features().has_openat2 {
             Self::Openat2
         } else {
             Self::RestrictedOpath
@@ -105,7 +105,7 @@ fn openat2_resolve<Fd: AsFd, P: AsRef<Path>>(
     oflags: OpenFlags,
     rflags: ResolverFlags,
 ) -> Result<OwnedFd, Error> {
-    if !*syscalls::OPENAT2_IS_SUPPORTED {
+    if !kernel::kernel_features().has_openat2 {
         Err(ErrorImpl::NotSupported {
             feature: "openat2".into(),
         })?
@@ -360,6 +360,7 @@ mod tests {
     use crate::{
         error::{Error as PathrsError, ErrorKind},
         flags::{OpenFlags, ResolverFlags},
+        kernel,
         resolvers::procfs::ProcfsResolver,
         syscalls,
         tests::common as tests_common,
@@ -378,7 +379,7 @@ mod tests {
                 paste::paste! {
                     #[test]
                     fn [<procfs_openat2_resolver_ $test_name>]() -> Result<(), Error> {
-                        if !*syscalls::OPENAT2_IS_SUPPORTED {
+                        if !kernel::kernel_features().has_openat2 {
                             // skip test
                             return Ok(());
                         }
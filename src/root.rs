@@ -21,26 +21,33 @@
 
 use crate::{
     error::{Error, ErrorExt, ErrorImpl},
-    flags::{OpenFlags, RenameFlags, ResolverFlags},
-    resolvers::Resolver,
+    flags::{AccessFlags, AccessMode, OpenFlags, RenameFlags, ResolverFlags},
+    kernel,
+    procfs::{ProcfsBase, GLOBAL_PROCFS_HANDLE},
+    resolvers::{ResolveObserver, Resolver, ResolverBackend},
     syscalls::{self, FrozenFd},
-    utils::{self, PathIterExt},
-    Handle,
+    utils::{self, FdExt, PathIterExt},
+    FileHandle, Handle, Metadata, RevalToken,
 };
 
 use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
     fs::{File, Permissions},
-    io::Error as IOError,
+    io::{Error as IOError, Read},
     os::unix::{
         ffi::OsStrExt,
-        fs::PermissionsExt,
-        io::{AsFd, BorrowedFd, OwnedFd},
+        fs::{MetadataExt, PermissionsExt},
+        io::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
     },
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+
 use rustix::{
-    fs::{self as rustix_fs, AtFlags},
+    fs::{self as rustix_fs, AtFlags, Dir},
     io::Errno,
 };
 
@@ -101,6 +108,203 @@ pub enum InodeType {
     //DetachedSocket(),
 }
 
+/// Policy for handling setuid and setgid inodes during path resolution, used
+/// by [`Root::set_setid_policy`].
+///
+/// This is primarily intended for scanners and other tools that want to treat
+/// any setuid or setgid inode as a policy violation. The [`Root`] itself is
+/// never checked.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SetidPolicy {
+    /// Do not check for setuid or setgid bits during resolution (the default).
+    #[default]
+    Allow,
+    /// Refuse to resolve a path whose final target has the setuid (`S_ISUID`)
+    /// or setgid (`S_ISGID`) bit set.
+    RejectTarget,
+    /// Refuse to resolve a path if any component walked through to reach the
+    /// final target (including the targets of any symlinks) has the setuid or
+    /// setgid bit set.
+    RejectIntermediate,
+    /// The combination of [`SetidPolicy::RejectTarget`] and
+    /// [`SetidPolicy::RejectIntermediate`].
+    RejectAll,
+}
+
+impl SetidPolicy {
+    pub(crate) fn rejects_target(self) -> bool {
+        matches!(self, Self::RejectTarget | Self::RejectAll)
+    }
+
+    pub(crate) fn rejects_intermediate(self) -> bool {
+        matches!(self, Self::RejectIntermediate | Self::RejectAll)
+    }
+}
+
+/// Policy for following symlinks in the final component of a path during path
+/// resolution, used by [`Root::set_trailing_symlink_policy`].
+///
+/// This is separate from the general limit on the number of symlinks followed
+/// during a resolution (see [`Root::set_max_symlink_traversals`]), and allows
+/// for symlinks to be used freely in the directory components of a path while
+/// restricting symlinks for the final target. A symlink counts as a trailing
+/// symlink if it is the final component of the path, or the final component of
+/// the target of another trailing symlink. Symlinks in the directory
+/// components of such a target are not trailing symlinks.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TrailingSymlinkPolicy {
+    /// Follow any number of trailing symlinks, subject only to the general
+    /// symlink limit (the default).
+    #[default]
+    FollowUnlimited,
+    /// Follow at most one trailing symlink, so the final component must
+    /// resolve to a non-symlink in at most one hop. Symlink-to-symlink chains
+    /// are rejected.
+    FollowOnce,
+    /// Do not follow any trailing symlinks.
+    Forbid,
+}
+
+impl TrailingSymlinkPolicy {
+    /// The maximum number of trailing symlinks that may be followed, if the
+    /// policy has a limit.
+    pub(crate) fn max_traversals(self) -> Option<usize> {
+        match self {
+            Self::FollowUnlimited => None,
+            Self::FollowOnce => Some(1),
+            Self::Forbid => Some(0),
+        }
+    }
+}
+
+/// A resolver backend that can be forced for a [`Root`] with
+/// [`Root::open_with_backend`] or [`Root::force_resolver_backend`].
+///
+/// libpathrs automatically picks the best supported backend by default, and
+/// that is what almost all users should use. Forcing a backend is only
+/// intended for diagnostics and testing (such as comparing the behaviour of
+/// the two backends) or for working around a kernel bug in `openat2(2)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PublicResolverBackend {
+    /// Use the native [`openat2(2)`] backend (requires kernel support).
+    ///
+    /// [`openat2(2)`]: https://man7.org/linux/man-pages/man2/openat2.2.html
+    Openat2,
+    /// Use the userspace emulated backend, which is supported on all kernels.
+    Emulated,
+    /// Do each lookup inside a private mount namespace which has been
+    /// [`pivot_root(2)`]-ed into the root, so that escaping the root is
+    /// physically impossible. This is much slower than the other backends,
+    /// requires [`openat2(2)`] and `CAP_SYS_ADMIN` (to create the mount
+    /// namespace) and is never selected by default. It is intended for
+    /// high-assurance deployments that do not want to rely solely on the
+    /// in-root resolution done by the other backends. See
+    /// [`Root::set_pivot_scratch_dir`] for configuring the scratch mount point
+    /// it uses.
+    ///
+    /// [`pivot_root(2)`]: https://man7.org/linux/man-pages/man2/pivot_root.2.html
+    /// [`openat2(2)`]: https://man7.org/linux/man-pages/man2/openat2.2.html
+    Hardcore,
+}
+
+impl PublicResolverBackend {
+    /// Checks if the backend is supported by the running kernel.
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::Openat2 => kernel::kernel_features().has_openat2,
+            Self::Emulated => true,
+            Self::Hardcore => {
+                let features = kernel::kernel_features();
+                features.has_openat2 && features.has_mount_namespaces
+            }
+        }
+    }
+}
+
+/// [`PublicResolverBackend`] is serialised as a lowercase string (`"openat2"`,
+/// `"emulated"` or `"hardcore"`).
+#[cfg(feature = "serde")]
+impl serde::Serialize for PublicResolverBackend {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self {
+            Self::Openat2 => "openat2",
+            Self::Emulated => "emulated",
+            Self::Hardcore => "hardcore",
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for PublicResolverBackend {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        const VARIANTS: &[&str] = &["openat2", "emulated", "hardcore"];
+        let name = String::deserialize(deserializer)?;
+        match name.as_str() {
+            "openat2" => Ok(Self::Openat2),
+            "emulated" => Ok(Self::Emulated),
+            "hardcore" => Ok(Self::Hardcore),
+            _ => Err(serde::de::Error::unknown_variant(&name, VARIANTS)),
+        }
+    }
+}
+
+impl From<PublicResolverBackend> for ResolverBackend {
+    fn from(backend: PublicResolverBackend) -> Self {
+        match backend {
+            PublicResolverBackend::Openat2 => Self::KernelOpenat2,
+            PublicResolverBackend::Emulated => Self::EmulatedOpath,
+            PublicResolverBackend::Hardcore => Self::HardcoreEmulated,
+        }
+    }
+}
+
+impl From<ResolverBackend> for PublicResolverBackend {
+    fn from(backend: ResolverBackend) -> Self {
+        match backend {
+            ResolverBackend::KernelOpenat2 => Self::Openat2,
+            ResolverBackend::EmulatedOpath => Self::Emulated,
+            ResolverBackend::HardcoreEmulated => Self::Hardcore,
+        }
+    }
+}
+
+/// Information about how a path was resolved, as returned by
+/// [`Root::resolve_with_info`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResolveInfo {
+    pub(crate) backend: PublicResolverBackend,
+    pub(crate) symlink_traversals: Option<usize>,
+    pub(crate) dotdot_clamped: Option<bool>,
+}
+
+impl ResolveInfo {
+    /// The resolver backend that did the resolution. This may differ from the
+    /// backend configured for the [`Root`] if the [`Root`] configuration
+    /// requires the emulated backend (see [`Root::open_with_backend`]).
+    #[inline]
+    pub fn backend(&self) -> PublicResolverBackend {
+        self.backend
+    }
+
+    /// The number of symlinks that were followed during the resolution, or
+    /// `None` if this cannot be observed (as is the case for `openat2(2)`).
+    #[inline]
+    pub fn symlink_traversals(&self) -> Option<usize> {
+        self.symlink_traversals
+    }
+
+    /// Whether any `..` components were clamped to the [`Root`] (because they
+    /// would otherwise have escaped it), or `None` if this cannot be observed
+    /// (as is the case for `openat2(2)`).
+    #[inline]
+    pub fn dotdot_clamped(&self) -> Option<bool> {
+        self.dotdot_clamped
+    }
+}
+
 /// The inode type for [`RootRef::remove_inode`]. This only used internally
 /// within libpathrs.
 #[derive(Clone, Copy, Debug)]
@@ -109,6 +313,216 @@ enum RemoveInodeType {
     Directory, // AT_REMOVEDIR
 }
 
+/// A cache of directory handles, for use with [`Root::resolve_cached`].
+///
+/// Each entry maps a directory prefix (relative to the root) to an `O_PATH`
+/// handle to that directory, along with the `(dev, ino, ctime)` of the
+/// directory when it was cached. Before an entry is reused, the prefix is
+/// looked up again (without following symlinks) from its parent and compared
+/// against the cached state. If the directory has been swapped, renamed,
+/// modified or over-mounted, the entry (and all entries beneath it) is dropped
+/// and re-populated.
+///
+/// A [`DirCache`] is tied to the root directory it was last used with, and is
+/// cleared automatically if it is used with a different [`Root`]. The cache is
+/// unbounded, so callers resolving paths across large trees should call
+/// [`DirCache::clear`] periodically.
+///
+/// The cache keeps running totals of how many cached prefixes were re-used and
+/// how many had to be (re-)opened, which can be used to judge whether the
+/// cache is effective for a given workload (see [`DirCache::stats`]).
+#[derive(Debug, Default)]
+pub struct DirCache {
+    root: Option<(u64, u64)>,
+    entries: HashMap<PathBuf, CachedDir>,
+    stats: DirCacheStats,
+}
+
+/// Hit and miss counters for a [`DirCache`], as returned by
+/// [`DirCache::stats`] and [`Root::resolve_cached_with_stats`].
+///
+/// Each directory prefix of a path that is looked up in the cache counts as
+/// either a hit (the cached handle was still valid and was re-used) or a miss
+/// (there was no entry, or the entry was stale and the prefix had to be opened
+/// again).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DirCacheStats {
+    hits: u64,
+    misses: u64,
+}
+
+impl DirCacheStats {
+    /// The number of directory prefixes that were served from the cache.
+    #[inline]
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// The number of directory prefixes that were not in the cache (or whose
+    /// cached entry was stale) and had to be opened again.
+    #[inline]
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    fn record(&mut self, lookup: DirCacheLookup) {
+        match lookup {
+            DirCacheLookup::Hit => self.hits += 1,
+            DirCacheLookup::Refreshed | DirCacheLookup::Missing => self.misses += 1,
+            DirCacheLookup::Unreachable => {}
+        }
+    }
+}
+
+/// The result of [`DirCache::revalidate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DirCacheLookup {
+    /// The cached entry was still valid.
+    Hit,
+    /// The entry was missing or stale, and has been re-populated.
+    Refreshed,
+    /// The entry was missing or stale, and the prefix could not be opened.
+    Missing,
+    /// The parent of the prefix is not in the cache, so the prefix was not
+    /// looked up at all.
+    Unreachable,
+}
+
+impl DirCacheLookup {
+    fn is_usable(self) -> bool {
+        matches!(self, Self::Hit | Self::Refreshed)
+    }
+}
+
+#[derive(Debug)]
+struct CachedDir {
+    handle: Handle,
+    token: RevalToken,
+}
+
+impl DirCache {
+    /// Create a new empty [`DirCache`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the number of directory handles in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the cache contains no directory handles.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drop all of the directory handles in the cache.
+    ///
+    /// This does not reset the counters returned by [`DirCache::stats`].
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.entries.clear();
+    }
+
+    /// Return the total number of cache hits and misses since the cache was
+    /// created (or since the last [`DirCache::reset_stats`]).
+    pub fn stats(&self) -> DirCacheStats {
+        self.stats
+    }
+
+    /// Reset the counters returned by [`DirCache::stats`] to zero.
+    pub fn reset_stats(&mut self) {
+        self.stats = DirCacheStats::default();
+    }
+
+    /// Clear the cache if it was last used with a different root directory.
+    fn bind_root(&mut self, root: BorrowedFd<'_>) -> Result<(), Error> {
+        let stat = syscalls::fstatat(root, "").map_err(|err| ErrorImpl::RawOsError {
+            operation: "stat root directory for dircache".into(),
+            source: err,
+        })?;
+        let meta = Metadata::from_stat(&stat);
+        let root = Some((meta.dev(), meta.ino()));
+        if self.root != root {
+            self.clear();
+            self.root = root;
+        }
+        Ok(())
+    }
+
+    /// Get the stat(2) token for `name` inside `dirfd` (without following
+    /// symlinks).
+    fn token<Fd: AsFd>(dirfd: Fd, name: &OsStr) -> Option<RevalToken> {
+        syscalls::fstatat(dirfd, name)
+            .ok()
+            .map(|stat| RevalToken::from(&Metadata::from_stat(&stat)))
+    }
+
+    /// Make sure that `prefix` (which is `parent` joined with `name`) has a
+    /// valid entry in the cache, re-populating it if the cached directory has
+    /// changed. The result is also recorded in the cache's counters.
+    fn revalidate(
+        &mut self,
+        root: BorrowedFd<'_>,
+        parent: &Path,
+        name: &OsStr,
+        prefix: &Path,
+    ) -> DirCacheLookup {
+        let lookup = self.do_revalidate(root, parent, name, prefix);
+        self.stats.record(lookup);
+        lookup
+    }
+
+    fn do_revalidate(
+        &mut self,
+        root: BorrowedFd<'_>,
+        parent: &Path,
+        name: &OsStr,
+        prefix: &Path,
+    ) -> DirCacheLookup {
+        let parent_fd = if parent.as_os_str().is_empty() {
+            root
+        } else {
+            match self.entries.get(parent) {
+                Some(dir) => dir.handle.as_fd(),
+                None => return DirCacheLookup::Unreachable,
+            }
+        };
+
+        let current = Self::token(parent_fd, name);
+        if let (Some(current), Some(dir)) = (current, self.entries.get(prefix)) {
+            if current == dir.token {
+                return DirCacheLookup::Hit;
+            }
+        }
+
+        // The entry is missing or stale. Note that syscalls::openat sets
+        // O_NOFOLLOW, so symlinks are never cached as prefixes.
+        let entry = syscalls::openat(
+            parent_fd,
+            name,
+            OpenFlags::O_PATH | OpenFlags::O_DIRECTORY,
+            0,
+        )
+        .ok()
+        .and_then(|fd| {
+            Self::token(&fd, OsStr::new("")).map(|token| CachedDir {
+                handle: Handle::from_fd(fd),
+                token,
+            })
+        });
+
+        self.entries.retain(|key, _| !key.starts_with(prefix));
+        match entry {
+            Some(entry) => {
+                self.entries.insert(prefix.to_path_buf(), entry);
+                DirCacheLookup::Refreshed
+            }
+            None => DirCacheLookup::Missing,
+        }
+    }
+}
+
 /// A handle to the root of a directory tree.
 ///
 /// # Safety
@@ -143,6 +557,11 @@ pub struct Root {
     ///
     /// [`resolve`]: Self::resolve
     resolver: Resolver,
+
+    /// The scratch directory used by the `pivot_root(2)`-based resolver (see
+    /// [`Root::set_pivot_scratch_dir`]). This is lent to [`RootRef`] through
+    /// [`Root::as_ref`].
+    pivot_scratch: Option<OwnedFd>,
 }
 
 impl Root {
@@ -171,10 +590,115 @@ impl Root {
         Ok(Self::from_fd(file))
     }
 
+    /// Open a [`Root`] handle which always uses the given resolver `backend`,
+    /// rather than the one chosen automatically by libpathrs.
+    ///
+    /// This is primarily intended for diagnostics and for working around
+    /// kernel bugs. See [`Root::force_resolver_backend`] for forcing the
+    /// backend of an existing [`Root`]. Note that some [`Root`] configuration (such as
+    /// [`SetidPolicy::RejectIntermediate`]) can only be implemented by the
+    /// emulated backend and will still override the backend for lookups.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors returned by [`Root::open`], if `backend` is
+    /// not supported by the running kernel (see
+    /// [`PublicResolverBackend::is_supported`]) an error with
+    /// [`ErrorKind::NotImplemented`] (`ENOSYS`) is returned.
+    ///
+    /// [`ErrorKind::NotImplemented`]: crate::error::ErrorKind::NotImplemented
+    pub fn open_with_backend<P: AsRef<Path>>(
+        path: P,
+        backend: PublicResolverBackend,
+    ) -> Result<Self, Error> {
+        Self::open(path)?.with_forced_resolver_backend(backend)
+    }
+
+    /// Open a [`Root`] handle, but only if it resides on one of the given
+    /// filesystem types.
+    ///
+    /// `allowed_magics` is a list of filesystem magic numbers (as returned in
+    /// the `f_type` field by [`fstatfs(2)`], such as `TMPFS_MAGIC`). This can
+    /// be used to refuse to operate on roots that are on filesystems where
+    /// the safety guarantees of libpathrs are weaker (such as network
+    /// filesystems).
+    ///
+    /// Note that this only checks the filesystem of the root directory itself.
+    /// Paths resolved inside the [`Root`] may still cross into other mounts.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors returned by [`Root::open`], if the root
+    /// directory is not on one of the `allowed_magics` filesystems then a
+    /// `SafetyViolation` error will be returned.
+    ///
+    /// [`fstatfs(2)`]: https://man7.org/linux/man-pages/man2/fstatfs.2.html
+    pub fn open_on_fstype<P: AsRef<Path>>(path: P, allowed_magics: &[u64]) -> Result<Self, Error> {
+        let root = Self::open(path)?;
+
+        let stat = syscalls::fstatfs(&root).map_err(|err| ErrorImpl::RawOsError {
+            operation: "check fstype of root".into(),
+            source: err,
+        })?;
+        // NOTE: f_type is signed on some architectures, but the magic values
+        //       are always treated as unsigned bit patterns.
+        #[allow(clippy::unnecessary_cast)]
+        let fstype = stat.f_type as u64;
+        if !allowed_magics.contains(&fstype) {
+            Err(ErrorImpl::SafetyViolation {
+                description: format!("root is on a disallowed filesystem type 0x{fstype:x}").into(),
+            })?
+        }
+
+        Ok(root)
+    }
+
+    /// Open a [`Root`] handle for `subpath` within an existing `base` [`Root`].
+    ///
+    /// Unlike [`Root::open`] (where the path to the root directory is resolved
+    /// normally by the operating system), `subpath` is resolved within `base`
+    /// using the same resolver as [`Root::resolve`]. This guarantees that the
+    /// new [`Root`] is beneath `base` (at least at the time of resolution),
+    /// which allows you to safely create nested roots from potentially
+    /// attacker-influenced paths.
+    ///
+    /// The new [`Root`] inherits the resolver configuration of `base`.
+    ///
+    /// # Errors
+    ///
+    /// If `subpath` could not be resolved within `base` or does not refer to a
+    /// directory, an error is returned.
+    pub fn open_beneath<P: AsRef<Path>>(base: &Root, subpath: P) -> Result<Self, Error> {
+        let handle = base
+            .resolve(subpath)
+            .wrap("resolve new root beneath base root")?;
+        if handle.metadata()?.mode() & libc::S_IFMT != libc::S_IFDIR {
+            Err(ErrorImpl::OsError {
+                operation: "open root beneath base root".into(),
+                source: IOError::from_raw_os_error(libc::ENOTDIR),
+            })?
+        }
+        Ok(Self {
+            inner: handle.into(),
+            resolver: base.resolver,
+            pivot_scratch: base
+                .pivot_scratch
+                .as_ref()
+                .map(OwnedFd::try_clone)
+                .transpose()
+                .map_err(|err| ErrorImpl::OsError {
+                    operation: "clone pivot scratch directory".into(),
+                    source: err,
+                })?,
+        })
+    }
+
     /// Wrap an [`OwnedFd`] into a [`Root`].
     ///
     /// The [`OwnedFd`] should be a file descriptor referencing a directory,
-    /// otherwise all [`Root`] operations will fail.
+    /// otherwise all [`Root`] operations will fail. No checks are done on the
+    /// file descriptor, see [`Root::from_fd_checked`] for a variant which
+    /// verifies that `fd` is a directory.
     ///
     /// The configuration is set to the system default and should be configured
     /// prior to usage, if appropriate.
@@ -183,9 +707,49 @@ impl Root {
         Self {
             inner: fd.into(),
             resolver: Default::default(),
+            pivot_scratch: None,
         }
     }
 
+    /// Wrap an [`OwnedFd`] into a [`Root`], verifying that it references a
+    /// directory.
+    ///
+    /// This is useful if you already have a trusted directory file descriptor
+    /// (such as one created by [`open_tree(2)`] or [`fsmount(2)`]), since it
+    /// avoids having to re-open the directory by path with [`Root::open`]
+    /// (which would be racy). `fd` may be an `O_PATH` descriptor.
+    ///
+    /// Note that only the inode type of `fd` is checked. The caller must make
+    /// sure that `fd` really is the directory they intend to use as the root
+    /// (as with [`Handle::from_fd`]), since all operations on the returned
+    /// [`Root`] are confined to it.
+    ///
+    /// The configuration is set to the system default and should be configured
+    /// prior to usage, if appropriate.
+    ///
+    /// # Errors
+    ///
+    /// If `fd` does not reference a directory, an error with
+    /// [`ErrorKind::InvalidArgument`] is returned.
+    ///
+    /// [`open_tree(2)`]: https://man7.org/linux/man-pages/man2/open_tree.2.html
+    /// [`fsmount(2)`]: https://man7.org/linux/man-pages/man2/fsmount.2.html
+    /// [`ErrorKind::InvalidArgument`]: crate::error::ErrorKind::InvalidArgument
+    pub fn from_fd_checked<Fd: Into<OwnedFd>>(fd: Fd) -> Result<Self, Error> {
+        let root = Self::from_fd(fd);
+        let file_type = root.metadata().wrap("fstat root directory")?.mode() & libc::S_IFMT;
+        if file_type != libc::S_IFDIR {
+            Err(ErrorImpl::InvalidArgument {
+                name: "fd".into(),
+                description: format!(
+                    "root file descriptor has unexpected inode type {file_type:#o}"
+                )
+                .into(),
+            })?
+        }
+        Ok(root)
+    }
+
     /// Borrow this [`Root`] as a [`RootRef`].
     ///
     /// The [`ResolverFlags`] of the [`Root`] are inherited by the [`RootRef`]
@@ -201,6 +765,7 @@ impl Root {
         RootRef {
             inner: self.as_fd(),
             resolver: self.resolver,
+            pivot_scratch: self.pivot_scratch.as_ref().map(AsFd::as_fd),
         }
     }
 
@@ -246,105 +811,873 @@ impl Root {
         self
     }
 
-    /// Create a copy of an existing [`Root`].
+    /// Get the current eager re-open flags for this [`Root`].
     ///
-    /// The new handle is completely independent from the original, but
-    /// references the same underlying file and has the same configuration.
+    /// See [`Root::set_eager_reopen`] for more details.
     #[inline]
-    pub fn try_clone(&self) -> Result<Root, Error> {
-        self.as_ref().try_clone()
+    pub fn eager_reopen(&self) -> Option<OpenFlags> {
+        self.resolver.eager_reopen
     }
 
-    /// Within the given [`Root`]'s tree, resolve `path` and return a
-    /// [`Handle`].
-    ///
-    /// All symlink path components are scoped to [`Root`]. Trailing symlinks
-    /// *are* followed, if you want to get a handle to a symlink use
-    /// [`resolve_nofollow`].
+    /// Configure whether [`Root::resolve`] (and [`Root::resolve_nofollow`])
+    /// should eagerly re-open regular files with the given [`OpenFlags`].
     ///
-    /// # Errors
+    /// By default, all [`Handle`]s returned by [`Root::resolve`] are `O_PATH`
+    /// handles which need to be re-opened with [`Handle::reopen`] before they
+    /// can be used for I/O. If `flags` is `Some`, any regular files resolved
+    /// by this [`Root`] will instead be returned as [`Handle`]s backed by a
+    /// file descriptor opened with `flags` (as though [`Handle::reopen`] had
+    /// been called). All other inode types (including directories) are still
+    /// returned as `O_PATH` handles.
     ///
-    /// If `path` doesn't exist, or an attack was detected during resolution, a
-    /// corresponding [`Error`] will be returned. If no error is returned, then
-    /// the path is guaranteed to have been reachable from the root of the
-    /// directory tree and thus have been inside the root at one point in the
-    /// resolution.
+    /// Note that this means the [`Handle`]s returned may not be `O_PATH`
+    /// handles (see [`Handle::is_opath`]). Calling [`Handle::reopen`] on such
+    /// handles is still permitted.
     ///
-    /// [`resolve_nofollow`]: Self::resolve_nofollow
-    #[doc(alias = "pathrs_inroot_resolve")]
+    /// Only the methods that return resolved [`Handle`]s to the caller (the
+    /// `resolve*` family) apply this policy. Other operations (such as
+    /// [`Root::hardlink`] or [`Root::access`]) resolve paths internally and
+    /// are not affected by it.
     #[inline]
-    pub fn resolve<P: AsRef<Path>>(&self, path: P) -> Result<Handle, Error> {
-        self.as_ref().resolve(path)
+    pub fn set_eager_reopen(&mut self, flags: Option<OpenFlags>) -> &mut Self {
+        self.resolver.eager_reopen = flags;
+        self
     }
 
-    /// Identical to [`resolve`], except that *trailing* symlinks are *not*
-    /// followed.
-    ///
-    /// If the trailing component is a symlink [`resolve_nofollow`] will return
-    /// a handle to the symlink itself. This is effectively equivalent to
-    /// `O_NOFOLLOW`.
+    /// Configure eager re-opening for all operations in this [`Root`].
     ///
-    /// [`resolve`]: Self::resolve
-    /// [`resolve_nofollow`]: Self::resolve_nofollow
-    #[doc(alias = "pathrs_inroot_resolve_nofollow")]
+    /// This is identical to [`Root::set_eager_reopen`] except that it can more
+    /// easily be used with chaining to configure a [`Root`] in a single line.
     #[inline]
-    pub fn resolve_nofollow<P: AsRef<Path>>(&self, path: P) -> Result<Handle, Error> {
-        self.as_ref().resolve_nofollow(path)
+    pub fn with_eager_reopen(mut self, flags: Option<OpenFlags>) -> Self {
+        self.set_eager_reopen(flags);
+        self
     }
 
-    /// Open a path without creating an intermediate [`Handle`] object.
+    /// Get the current maximum number of symlink traversals for this
+    /// [`Root`].
     ///
-    /// This is effectively just shorthand for [`resolve`] followed by
-    /// [`Handle::reopen`]. However, some resolvers (such as the `openat2`
-    /// resolver) can implement [`open_subpath`] slightly more efficiently than
-    /// naively doing a two-step open operation with [`Handle::reopen`]. If you
-    /// wish to create an [`OpenFlags::O_PATH`] file handle, it probably makes
-    /// more sense to use [`resolve`] or [`resolve_nofollow`].
+    /// See [`Root::set_max_symlink_traversals`] for more details.
+    #[inline]
+    pub fn max_symlink_traversals(&self) -> Option<usize> {
+        self.resolver.max_symlink_traversals
+    }
+
+    /// Set the symlink limit for a single path resolution within this
+    /// [`Root`]. Once the number of symlinks encountered during a resolution
+    /// reaches `limit`, the resolution fails with `ELOOP` (so at most
+    /// `limit - 1` symlinks are followed).
     ///
-    /// If `flags` contains [`OpenFlags::O_NOFOLLOW`] and the path refers to a
-    /// symlink then this method will match the behaviour of [`openat2`] (this
-    /// is in contrast to [`Handle::reopen`] which does not permit re-opening a
-    /// handle to a symlink):
+    /// `None` indicates that the default limit (128) should be used. Deeply
+    /// nested layouts may need a higher limit, while lowering it can reduce the
+    /// amount of work an attacker can make a resolution do.
     ///
-    ///  * If `flags` also contains [`OpenFlags::O_PATH`] then the returned file
-    ///    is equivalent to the [`Handle`] that would've been returned from
-    ///    [`resolve_nofollow`].
-    ///  * Otherwise, an error will be returned to match the behaviour of
-    ///    [`OpenFlags::O_NOFOLLOW`] when encountering a trailing symlink.
+    /// # Caveats
     ///
-    /// [`openat2`]: https://man7.org/linux/man-pages/man2/openat2.2.html
-    /// [`open_subpath`]: Self::open_subpath
-    /// [`resolve`]: Self::resolve
-    /// [`resolve_nofollow`]: Self::resolve_nofollow
-    #[doc(alias = "pathrs_inroot_open")]
+    /// This limit is only enforced by the emulated (`O_PATH`) resolver. The
+    /// `openat2(2)` resolver relies on the kernel to follow symlinks, and the
+    /// kernel has its own fixed limit (`MAXSYMLINKS`, currently 40) which
+    /// cannot be configured. When the `openat2(2)` resolver is used, this
+    /// setting is therefore only advisory.
     #[inline]
-    pub fn open_subpath<P: AsRef<Path>, F: Into<OpenFlags>>(
-        &self,
-        path: P,
-        flags: F,
-    ) -> Result<File, Error> {
-        self.as_ref().open_subpath(path, flags)
+    pub fn set_max_symlink_traversals(&mut self, limit: Option<usize>) -> &mut Self {
+        self.resolver.max_symlink_traversals = limit;
+        self
     }
 
-    /// Get the target of a symlink within a [`Root`].
+    /// Set the maximum number of symlink traversals for all operations in
+    /// this [`Root`].
     ///
-    /// **NOTE**: The returned path is not modified to be "safe" outside of the
-    /// root. You should not use this path for doing further path lookups -- use
-    /// [`resolve`] instead.
-    ///
-    /// This method is just shorthand for calling `readlinkat(2)` on the handle
-    /// returned by [`resolve_nofollow`].
+    /// This is identical to [`Root::set_max_symlink_traversals`] except that
+    /// it can more easily be used with chaining to configure a [`Root`] in a
+    /// single line.
+    #[inline]
+    pub fn with_max_symlink_traversals(mut self, limit: Option<usize>) -> Self {
+        self.set_max_symlink_traversals(limit);
+        self
+    }
+
+    /// Get the current maximum total length of symlink targets for this
+    /// [`Root`].
     ///
-    /// [`resolve`]: Self::resolve
-    /// [`resolve_nofollow`]: Self::resolve_nofollow
-    #[doc(alias = "pathrs_inroot_readlink")]
+    /// See [`Root::set_max_symlink_target_bytes`] for more details.
     #[inline]
-    pub fn readlink<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Error> {
-        self.as_ref().readlink(path)
+    pub fn max_symlink_target_bytes(&self) -> Option<usize> {
+        self.resolver.max_symlink_target_bytes
     }
 
-    /// Within the [`Root`]'s tree, create an inode at `path` as specified by
-    /// `inode_type`.
+    /// Set the maximum total length (in bytes) of the symlink targets that
+    /// will be expanded during a single path resolution within this [`Root`].
+    /// If the contents of the symlinks followed add up to more than this, the
+    /// resolution fails with `ELOOP`.
+    ///
+    /// `None` indicates that the default limit should be used. The default
+    /// (163800 bytes) is the most the kernel can ever expand in a single
+    /// lookup -- it follows at most `MAXSYMLINKS` (40) symlinks, each of which
+    /// is at most `PATH_MAX - 1` bytes long. This means that the emulated
+    /// resolver rejects chains of long symlinks the same way that
+    /// `openat2(2)` does, even though its default limit on the number of
+    /// symlinks is higher than the kernel's (see
+    /// [`Root::set_max_symlink_traversals`]).
+    ///
+    /// # Caveats
+    ///
+    /// This limit is only enforced by the emulated (`O_PATH`) resolver. The
+    /// kernel does not have a configurable limit, so when the `openat2(2)`
+    /// resolver is used this setting is only advisory.
+    #[inline]
+    pub fn set_max_symlink_target_bytes(&mut self, limit: Option<usize>) -> &mut Self {
+        self.resolver.max_symlink_target_bytes = limit;
+        self
+    }
+
+    /// Set the maximum total length of symlink targets for all operations in
+    /// this [`Root`].
+    ///
+    /// This is identical to [`Root::set_max_symlink_target_bytes`] except that
+    /// it can more easily be used with chaining to configure a [`Root`] in a
+    /// single line.
+    #[inline]
+    pub fn with_max_symlink_target_bytes(mut self, limit: Option<usize>) -> Self {
+        self.set_max_symlink_target_bytes(limit);
+        self
+    }
+
+    /// Get the current setuid/setgid policy for this [`Root`].
+    ///
+    /// See [`Root::set_setid_policy`] for more details.
+    #[inline]
+    pub fn setid_policy(&self) -> SetidPolicy {
+        self.resolver.setid_policy
+    }
+
+    /// Configure how setuid and setgid inodes are treated by path resolution
+    /// within this [`Root`]. If a resolution encounters an inode that violates
+    /// the [`SetidPolicy`], it fails with [`ErrorKind::SafetyViolation`].
+    ///
+    /// # Caveats
+    ///
+    /// Intermediate components can only be checked by the emulated (`O_PATH`)
+    /// resolver, so [`SetidPolicy::RejectIntermediate`] (and
+    /// [`SetidPolicy::RejectAll`]) force the use of the emulated resolver even
+    /// if `openat2(2)` is available. Checking the final target requires an
+    /// extra `fstat(2)` for each resolution.
+    ///
+    /// [`ErrorKind::SafetyViolation`]: crate::error::ErrorKind::SafetyViolation
+    #[inline]
+    pub fn set_setid_policy(&mut self, policy: SetidPolicy) -> &mut Self {
+        self.resolver.setid_policy = policy;
+        self
+    }
+
+    /// Set the setuid/setgid policy for all operations in this [`Root`].
+    ///
+    /// This is identical to [`Root::set_setid_policy`] except that it can more
+    /// easily be used with chaining to configure a [`Root`] in a single line.
+    #[inline]
+    pub fn with_setid_policy(mut self, policy: SetidPolicy) -> Self {
+        self.set_setid_policy(policy);
+        self
+    }
+
+    /// Get whether path resolution within this [`Root`] rejects deleted
+    /// intermediate directories.
+    ///
+    /// See [`Root::set_reject_deleted_intermediates`] for more details.
+    #[inline]
+    pub fn reject_deleted_intermediates(&self) -> bool {
+        self.resolver.reject_deleted_intermediates
+    }
+
+    /// Configure whether path resolution within this [`Root`] fails if it
+    /// walks through a directory which has been deleted.
+    ///
+    /// A directory that is `rmdir(2)`-ed while a resolution is walking through
+    /// it can still be used by the resolver (through the handle it already
+    /// has), even though it is no longer reachable from the [`Root`]. By
+    /// default the resolution continues (any lookup inside the deleted
+    /// directory other than `..` will fail with `ENOENT`), but operations that
+    /// need a consistent view of the tree can instead have the resolution fail
+    /// with [`ErrorKind::DeletedInode`] as soon as such a directory is seen.
+    ///
+    /// # Caveats
+    ///
+    /// Intermediate components can only be checked by the emulated (`O_PATH`)
+    /// resolver, so enabling this forces the use of the emulated resolver even
+    /// if `openat2(2)` is available. The check is done when each component is
+    /// walked into, so a directory deleted after that point is not detected.
+    ///
+    /// [`ErrorKind::DeletedInode`]: crate::error::ErrorKind::DeletedInode
+    #[inline]
+    pub fn set_reject_deleted_intermediates(&mut self, reject: bool) -> &mut Self {
+        self.resolver.reject_deleted_intermediates = reject;
+        self
+    }
+
+    /// Set whether deleted intermediate directories are rejected for all
+    /// operations in this [`Root`].
+    ///
+    /// This is identical to [`Root::set_reject_deleted_intermediates`] except
+    /// that it can more easily be used with chaining to configure a [`Root`]
+    /// in a single line.
+    #[inline]
+    pub fn with_reject_deleted_intermediates(mut self, reject: bool) -> Self {
+        self.set_reject_deleted_intermediates(reject);
+        self
+    }
+
+    /// Get the current trailing symlink policy for this [`Root`].
+    ///
+    /// See [`Root::set_trailing_symlink_policy`] for more details.
+    #[inline]
+    pub fn trailing_symlink_policy(&self) -> TrailingSymlinkPolicy {
+        self.resolver.trailing_symlink_policy
+    }
+
+    /// Configure how symlinks in the final component of a path are followed
+    /// by path resolution within this [`Root`]. If a resolution encounters a
+    /// trailing symlink that the [`TrailingSymlinkPolicy`] does not permit
+    /// following, it fails with [`ErrorKind::OsError`] (`ELOOP`).
+    ///
+    /// Operations that explicitly do not follow trailing symlinks (such as
+    /// [`Root::resolve_nofollow`]) are not affected by this policy.
+    ///
+    /// # Caveats
+    ///
+    /// Trailing symlinks can only be counted by the emulated (`O_PATH`)
+    /// resolver, so any policy other than
+    /// [`TrailingSymlinkPolicy::FollowUnlimited`] forces the use of the
+    /// emulated resolver even if `openat2(2)` is available.
+    ///
+    /// [`ErrorKind::OsError`]: crate::error::ErrorKind::OsError
+    #[inline]
+    pub fn set_trailing_symlink_policy(&mut self, policy: TrailingSymlinkPolicy) -> &mut Self {
+        self.resolver.trailing_symlink_policy = policy;
+        self
+    }
+
+    /// Set the trailing symlink policy for all operations in this [`Root`].
+    ///
+    /// This is identical to [`Root::set_trailing_symlink_policy`] except that
+    /// it can more easily be used with chaining to configure a [`Root`] in a
+    /// single line.
+    #[inline]
+    pub fn with_trailing_symlink_policy(mut self, policy: TrailingSymlinkPolicy) -> Self {
+        self.set_trailing_symlink_policy(policy);
+        self
+    }
+
+    /// Get the [`ResolveObserver`] registered for this [`Root`], if any.
+    ///
+    /// See [`Root::set_resolve_observer`] for more details.
+    #[inline]
+    pub fn resolve_observer(&self) -> Option<&'static dyn ResolveObserver> {
+        self.resolver.observer
+    }
+
+    /// Register a [`ResolveObserver`] which is notified about path
+    /// resolutions within this [`Root`], or unregister it with `None`.
+    ///
+    /// This is useful for collecting metrics about resolutions (such as how
+    /// many resolutions were done by each backend, or how often `..`
+    /// components tried to escape the [`Root`]). If set, the observer is used
+    /// instead of the one registered with [`set_global_resolve_observer`].
+    ///
+    /// [`set_global_resolve_observer`]: crate::set_global_resolve_observer
+    #[inline]
+    pub fn set_resolve_observer(
+        &mut self,
+        observer: Option<&'static dyn ResolveObserver>,
+    ) -> &mut Self {
+        self.resolver.observer = observer;
+        self
+    }
+
+    /// Set the [`ResolveObserver`] for all operations in this [`Root`].
+    ///
+    /// This is identical to [`Root::set_resolve_observer`] except that it can
+    /// more easily be used with chaining to configure a [`Root`] in a single
+    /// line.
+    #[inline]
+    pub fn with_resolve_observer(mut self, observer: Option<&'static dyn ResolveObserver>) -> Self {
+        self.set_resolve_observer(observer);
+        self
+    }
+
+    /// Force all operations in this [`Root`] to use the given resolver
+    /// `backend`, rather than the one chosen automatically by libpathrs.
+    ///
+    /// **You probably don't need this.** libpathrs picks the best backend
+    /// supported by the running kernel, and the two backends are intended to
+    /// behave identically. This is only intended for test suites that want to
+    /// exercise both backends (such as CI matrices that want to test the
+    /// emulated backend on kernels with `openat2(2)` support), for
+    /// reproducing backend-specific bugs, and for working around kernel bugs.
+    ///
+    /// Note that some [`Root`] configuration (such as
+    /// [`SetidPolicy::RejectIntermediate`]) can only be implemented by the
+    /// emulated backend and will still override the backend for lookups.
+    ///
+    /// # Errors
+    ///
+    /// If `backend` is not supported by the running kernel (see
+    /// [`PublicResolverBackend::is_supported`]) an error with
+    /// [`ErrorKind::NotImplemented`] (`ENOSYS`) is returned and the [`Root`]
+    /// is left unchanged.
+    ///
+    /// [`ErrorKind::NotImplemented`]: crate::error::ErrorKind::NotImplemented
+    #[inline]
+    pub fn force_resolver_backend(
+        &mut self,
+        backend: PublicResolverBackend,
+    ) -> Result<&mut Self, Error> {
+        self.resolver.force_backend(backend)?;
+        Ok(self)
+    }
+
+    /// Force all operations in this [`Root`] to use the given resolver
+    /// `backend`.
+    ///
+    /// This is identical to [`Root::force_resolver_backend`] except that it
+    /// can more easily be used with chaining to configure a [`Root`] in a
+    /// single line.
+    #[inline]
+    pub fn with_forced_resolver_backend(
+        mut self,
+        backend: PublicResolverBackend,
+    ) -> Result<Self, Error> {
+        self.force_resolver_backend(backend)?;
+        Ok(self)
+    }
+
+    /// Get the current scratch directory used by the `pivot_root(2)`-based
+    /// resolver for this [`Root`].
+    ///
+    /// See [`Root::set_pivot_scratch_dir`] for more details.
+    #[inline]
+    pub fn pivot_scratch_dir(&self) -> Option<BorrowedFd<'_>> {
+        self.pivot_scratch.as_ref().map(AsFd::as_fd)
+    }
+
+    /// Set the scratch directory used by the `pivot_root(2)`-based resolver
+    /// for this [`Root`].
+    ///
+    /// The `pivot_root(2)`-based resolver does each lookup inside a private
+    /// mount namespace, where a copy of the root mount tree is attached to a
+    /// scratch mount point and then pivoted into. By default, a new private
+    /// `tmpfs` is created for this purpose for every lookup. Users in
+    /// locked-down environments (where creating new filesystem instances may
+    /// not be permitted) can instead provide a pre-created directory (such as
+    /// a dedicated `tmpfs`) to use as the scratch mount point. A path can be
+    /// used by opening it with [`File::open`] (or `O_PATH|O_DIRECTORY`).
+    ///
+    /// The mount operations are only done within the private mount namespace,
+    /// so the scratch directory is never modified and the mounts are not
+    /// visible to other processes. The same scratch directory can be used for
+    /// multiple concurrent lookups.
+    ///
+    /// `None` indicates that a private `tmpfs` should be created.
+    ///
+    /// This setting has no effect on the other resolvers (and the
+    /// `pivot_root(2)`-based resolver is never selected by default).
+    ///
+    /// [`File::open`]: std::fs::File::open
+    #[inline]
+    pub fn set_pivot_scratch_dir(&mut self, dir: Option<OwnedFd>) -> &mut Self {
+        self.pivot_scratch = dir;
+        self
+    }
+
+    /// Set the scratch directory used by the `pivot_root(2)`-based resolver
+    /// for this [`Root`].
+    ///
+    /// This is identical to [`Root::set_pivot_scratch_dir`] except that it can
+    /// more easily be used with chaining to configure a [`Root`] in a single
+    /// line.
+    #[inline]
+    pub fn with_pivot_scratch_dir(mut self, dir: Option<OwnedFd>) -> Self {
+        self.set_pivot_scratch_dir(dir);
+        self
+    }
+
+    /// Create a copy of an existing [`Root`].
+    ///
+    /// The new handle is completely independent from the original, but
+    /// references the same underlying file and has the same configuration.
+    ///
+    /// The root file descriptor (and the pivot scratch directory, if one was
+    /// set with [`Root::set_pivot_scratch_dir`]) is duplicated with `dup(2)`,
+    /// so the two [`Root`]s share the same open file description but dropping
+    /// (or closing) one of them does not affect the other. Configuration
+    /// changes made to one copy after cloning are not seen by the other.
+    ///
+    /// [`Root`] is [`Send`] and [`Sync`], so you do not need to clone it to
+    /// use it from another thread -- it can be moved to that thread or shared
+    /// by reference (or with an [`Arc`]). The reference-counting done by the
+    /// emulated resolver is internal to each resolution and is never stored in
+    /// the [`Root`].
+    ///
+    /// [`Arc`]: std::sync::Arc
+    #[inline]
+    pub fn try_clone(&self) -> Result<Root, Error> {
+        self.as_ref().try_clone()
+    }
+
+    /// Within the given [`Root`]'s tree, resolve `path` and return a
+    /// [`Handle`].
+    ///
+    /// All symlink path components are scoped to [`Root`]. Trailing symlinks
+    /// *are* followed, if you want to get a handle to a symlink use
+    /// [`resolve_nofollow`].
+    ///
+    /// # Errors
+    ///
+    /// If `path` doesn't exist, or an attack was detected during resolution, a
+    /// corresponding [`Error`] will be returned. If no error is returned, then
+    /// the path is guaranteed to have been reachable from the root of the
+    /// directory tree and thus have been inside the root at one point in the
+    /// resolution.
+    ///
+    /// [`resolve_nofollow`]: Self::resolve_nofollow
+    #[doc(alias = "pathrs_inroot_resolve")]
+    #[inline]
+    pub fn resolve<P: AsRef<Path>>(&self, path: P) -> Result<Handle, Error> {
+        self.as_ref().resolve(path)
+    }
+
+    /// Identical to [`resolve`], except that *trailing* symlinks are *not*
+    /// followed.
+    ///
+    /// If the trailing component is a symlink [`resolve_nofollow`] will return
+    /// a handle to the symlink itself. This is effectively equivalent to
+    /// `O_NOFOLLOW`.
+    ///
+    /// [`resolve`]: Self::resolve
+    /// [`resolve_nofollow`]: Self::resolve_nofollow
+    #[doc(alias = "pathrs_inroot_resolve_nofollow")]
+    #[inline]
+    pub fn resolve_nofollow<P: AsRef<Path>>(&self, path: P) -> Result<Handle, Error> {
+        self.as_ref().resolve_nofollow(path)
+    }
+
+    /// Resolve `path` without following the trailing symlink (as with
+    /// [`resolve_nofollow`]), and also resolve the target of that symlink.
+    ///
+    /// This is intended for programs that display symlinks along with their
+    /// targets. The target is resolved using the contents of the symlink
+    /// referenced by the returned handle (relative to the directory containing
+    /// it) rather than by resolving `path` a second time, so the two handles
+    /// refer to the same symlink even if `path` is swapped concurrently. Note
+    /// that the parent directory components of `path` are walked again.
+    ///
+    /// If the final component of `path` is not a symlink, the second handle is
+    /// a copy of the first one.
+    ///
+    /// # Errors
+    ///
+    /// If `path` itself cannot be resolved, an [`Error`] is returned. Errors
+    /// resolving the target of the symlink (such as `ENOENT` for a dangling
+    /// symlink) are returned as the second element of the tuple.
+    ///
+    /// [`resolve_nofollow`]: Self::resolve_nofollow
+    #[inline]
+    pub fn resolve_symlink_and_target<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(Handle, Result<Handle, Error>), Error> {
+        self.as_ref().resolve_symlink_and_target(path)
+    }
+
+    /// Asynchronous version of [`resolve`], for use with [tokio].
+    ///
+    /// The lookup is done with [`resolve`] on tokio's blocking thread pool
+    /// (using [`tokio::task::spawn_blocking`]) so that it does not block the
+    /// async executor. Because the lookup may outlive the caller's borrow, the
+    /// [`Root`] has to be shared through an [`Arc`]. This method must be
+    /// awaited from within a tokio runtime.
+    ///
+    /// This method is only available with the `tokio` feature.
+    ///
+    /// [tokio]: https://tokio.rs/
+    /// [`resolve`]: Self::resolve
+    #[cfg(feature = "tokio")]
+    pub async fn resolve_async<P: AsRef<Path>>(self: &Arc<Self>, path: P) -> Result<Handle, Error> {
+        let (root, path) = (Arc::clone(self), path.as_ref().to_path_buf());
+        utils::spawn_blocking(move || root.resolve(path)).await
+    }
+
+    /// Resolve each path in `paths` within the [`Root`], as though by calling
+    /// [`resolve`] on each of them.
+    ///
+    /// The returned results are in the same order as `paths`, and an error
+    /// resolving one path does not affect the others. Some resolver backends
+    /// (namely the emulated `O_PATH` resolver) will only resolve directory
+    /// prefixes shared between multiple paths once, making this significantly
+    /// faster than calling [`resolve`] in a loop. If many of the paths are in
+    /// the same directory, that directory may also be listed once (with
+    /// `getdents(2)`) so that names which do not exist need not be looked up.
+    /// The resolution cache only lives for the duration of this call.
+    ///
+    /// Both the resolved directory prefixes and the directory listings are
+    /// re-used for the rest of the call, so the results reflect the tree as it
+    /// was when each directory was first reached. In particular, a name
+    /// created in a listed directory after it was listed is reported as not
+    /// existing (`ENOENT`) for the remainder of the call. If you need to see
+    /// concurrent changes, call [`resolve`] for each path instead.
+    ///
+    /// [`resolve`]: Self::resolve
+    #[inline]
+    pub fn resolve_many<I>(&self, paths: I) -> Vec<Result<Handle, Error>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        self.as_ref().resolve_many(paths)
+    }
+
+    /// Identical to [`resolve`], except that handles to the directory prefixes
+    /// of `path` are memoised in `cache` and re-used by later calls.
+    ///
+    /// This is intended for programs that resolve many paths sharing the same
+    /// directory prefixes (such as `a/b/c/file1`, `a/b/c/file2`, ...). Each
+    /// cached prefix is re-validated before it is used (see [`DirCache`] for
+    /// more details), and the remainder of the path is then resolved beneath
+    /// the deepest cached directory. If that lookup fails for any reason
+    /// (including a symlink in the remainder of the path that escapes the
+    /// cached directory), `path` is resolved again from scratch with
+    /// [`resolve`] so that the result (and any errors) are identical to
+    /// [`resolve`].
+    ///
+    /// The cache is only used by the emulated resolver backends. The native
+    /// `openat2(2)` backend resolves the whole path in a single syscall, which
+    /// is cheaper than re-validating the cached prefixes, so this method is
+    /// equivalent to [`resolve`] when `openat2(2)` is in use. The cache is also
+    /// not used for paths containing `..` components, or if
+    /// [`ResolverFlags::NO_XDEV`], [`ResolverFlags::SINGLE_COMPONENT`], a
+    /// [`SetidPolicy`] that restricts intermediate directories, or
+    /// [`Root::set_reject_deleted_intermediates`] is configured.
+    ///
+    /// # Errors
+    ///
+    /// See [`resolve`]. Note that a cached directory could be moved outside of
+    /// the [`Root`] after it has been re-validated, in which case the resolved
+    /// path will be beneath the old location of that directory. This is the
+    /// same race as a directory being moved during a regular [`resolve`].
+    ///
+    /// [`resolve`]: Self::resolve
+    #[inline]
+    pub fn resolve_cached<P: AsRef<Path>>(
+        &self,
+        path: P,
+        cache: &mut DirCache,
+    ) -> Result<Handle, Error> {
+        self.as_ref().resolve_cached(path, cache)
+    }
+
+    /// Identical to [`resolve_cached`], except that the number of directory
+    /// prefixes of `path` that were (and were not) served from `cache` is also
+    /// returned.
+    ///
+    /// If the cache was not used for this lookup (see [`resolve_cached`] for
+    /// the cases where the cache is bypassed), the returned [`DirCacheStats`]
+    /// are all zero. The same hits and misses are also added to the running
+    /// totals in [`DirCache::stats`].
+    ///
+    /// [`resolve_cached`]: Self::resolve_cached
+    #[inline]
+    pub fn resolve_cached_with_stats<P: AsRef<Path>>(
+        &self,
+        path: P,
+        cache: &mut DirCache,
+    ) -> Result<(Handle, DirCacheStats), Error> {
+        self.as_ref().resolve_cached_with_stats(path, cache)
+    }
+
+    /// Identical to [`resolve`], except that the [`Metadata`] of the resolved
+    /// inode (including its type) is also returned.
+    ///
+    /// Unless eager re-opening is enabled (see [`Root::set_eager_reopen`]),
+    /// [`resolve`] does not do any extra `stat(2)` calls on the final
+    /// component beyond what is needed to do the resolution, so if you only
+    /// need a [`Handle`] you should use [`resolve`]. This method is shorthand
+    /// for [`resolve`] followed by [`Handle::stat`].
+    ///
+    /// [`resolve`]: Self::resolve
+    #[inline]
+    pub fn resolve_typed<P: AsRef<Path>>(&self, path: P) -> Result<(Handle, Metadata), Error> {
+        self.as_ref().resolve_typed(path)
+    }
+
+    /// Identical to [`resolve`], except that the `(dev, ino)` of every
+    /// component visited during the resolution is also returned, in the order
+    /// they were visited.
+    ///
+    /// The trail includes intermediate symlinks (and the components of their
+    /// targets) as well as the final target, but not the [`Root`] itself (even
+    /// if `..` components walk back into it) or `.` components (which do not
+    /// visit a new inode). This
+    /// allows auditing tools to record (and later verify) the exact path taken
+    /// by a resolution.
+    ///
+    /// Only the emulated resolver backend walks the path in userspace, so the
+    /// trail is empty if the resolution was done in-kernel by `openat2(2)`. Use
+    /// [`Root::open_with_backend`] with [`PublicResolverBackend::Emulated`] if
+    /// you always need a trail.
+    ///
+    /// [`resolve`]: Self::resolve
+    #[inline]
+    pub fn resolve_with_inode_trail<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(Handle, Vec<(u64, u64)>), Error> {
+        self.as_ref().resolve_with_inode_trail(path)
+    }
+
+    /// Identical to [`resolve`], except that a [`ResolveInfo`] describing how
+    /// the path was resolved is also returned.
+    ///
+    /// This is intended for debugging and telemetry, such as figuring out why
+    /// some resolutions are slow or why a path was unexpectedly clamped to the
+    /// [`Root`]. The backend used is always reported, but the number of
+    /// symlinks followed and whether `..` components were clamped can only be
+    /// observed by the emulated resolver backend (they are `None` if the
+    /// resolution was done in-kernel by `openat2(2)`).
+    ///
+    /// [`resolve`]: Self::resolve
+    #[inline]
+    pub fn resolve_with_info<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(Handle, ResolveInfo), Error> {
+        self.as_ref().resolve_with_info(path)
+    }
+
+    /// Identical to [`Root::resolve`], except that any error is converted
+    /// using `f`.
+    ///
+    /// This is a convenience for libraries that want to convert [`Error`]s
+    /// into their own error type at the resolution boundary.
+    #[inline]
+    pub fn resolve_map_err<P, E, F>(&self, path: P, f: F) -> Result<Handle, E>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(Error) -> E,
+    {
+        self.as_ref().resolve_map_err(path, f)
+    }
+
+    /// Get a [`RootWithContext`] for this [`Root`], which prepends `context`
+    /// to any error returned by operations run with [`RootWithContext::run`].
+    ///
+    /// ```
+    /// # use pathrs::Root;
+    /// let root = Root::open("/")?;
+    /// let err = root
+    ///     .with_error_context("while loading config")
+    ///     .run(|root| root.resolve("/nonexistent/config.toml"))
+    ///     .unwrap_err();
+    /// assert!(err.to_string().starts_with("while loading config: "));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    #[inline]
+    pub fn with_error_context<'a>(&'a self, context: &'a str) -> RootWithContext<'a> {
+        self.as_ref().with_error_context(context)
+    }
+
+    /// Open a path without creating an intermediate [`Handle`] object.
+    ///
+    /// This is effectively just shorthand for [`resolve`] followed by
+    /// [`Handle::reopen`]. However, some resolvers (such as the `openat2`
+    /// resolver) can implement [`open_subpath`] slightly more efficiently than
+    /// naively doing a two-step open operation with [`Handle::reopen`]. If you
+    /// wish to create an [`OpenFlags::O_PATH`] file handle, it probably makes
+    /// more sense to use [`resolve`] or [`resolve_nofollow`].
+    ///
+    /// If `flags` contains [`OpenFlags::O_NOFOLLOW`] and the path refers to a
+    /// symlink then this method will match the behaviour of [`openat2`] (this
+    /// is in contrast to [`Handle::reopen`] which does not permit re-opening a
+    /// handle to a symlink):
+    ///
+    ///  * If `flags` also contains [`OpenFlags::O_PATH`] then the returned file
+    ///    is equivalent to the [`Handle`] that would've been returned from
+    ///    [`resolve_nofollow`].
+    ///  * Otherwise, an error will be returned to match the behaviour of
+    ///    [`OpenFlags::O_NOFOLLOW`] when encountering a trailing symlink.
+    ///
+    /// [`openat2`]: https://man7.org/linux/man-pages/man2/openat2.2.html
+    /// [`open_subpath`]: Self::open_subpath
+    /// [`resolve`]: Self::resolve
+    /// [`resolve_nofollow`]: Self::resolve_nofollow
+    #[doc(alias = "pathrs_inroot_open")]
+    #[inline]
+    pub fn open_subpath<P: AsRef<Path>, F: Into<OpenFlags>>(
+        &self,
+        path: P,
+        flags: F,
+    ) -> Result<File, Error> {
+        self.as_ref().open_subpath(path, flags)
+    }
+
+    /// Get the target of a symlink within a [`Root`].
+    ///
+    /// **NOTE**: The returned path is not modified to be "safe" outside of the
+    /// root. You should not use this path for doing further path lookups -- use
+    /// [`resolve`] instead.
+    ///
+    /// This method is just shorthand for calling `readlinkat(2)` on the handle
+    /// returned by [`resolve_nofollow`]. Symlinks in the intermediate
+    /// components of `path` are resolved as usual, but the final component is
+    /// never followed -- if it is a symlink to another symlink, the target of
+    /// the first symlink is returned.
+    ///
+    /// # Errors
+    ///
+    /// If `path` does not exist, an [`ErrorKind::OsError`] with `ENOENT` is
+    /// returned. If the final component of `path` is not a symlink, an
+    /// [`ErrorKind::OsError`] with `EINVAL` is returned (matching
+    /// `readlink(2)`).
+    ///
+    /// [`ErrorKind::OsError`]: crate::error::ErrorKind::OsError
+    /// [`resolve`]: Self::resolve
+    /// [`resolve_nofollow`]: Self::resolve_nofollow
+    #[doc(alias = "pathrs_inroot_readlink")]
+    #[inline]
+    pub fn readlink<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Error> {
+        self.as_ref().readlink(path)
+    }
+
+    /// Check whether the calling process can access the inode at `path` within
+    /// the [`Root`] with the given `mode`, using [`faccessat2(2)`].
+    ///
+    /// If `flags` contains [`AccessFlags::AT_SYMLINK_NOFOLLOW`], the final
+    /// component of `path` is not followed and the check applies to the
+    /// symlink itself. [`AccessFlags::AT_EACCESS`] causes the check to be done
+    /// using the effective (rather than real) credentials of the caller.
+    ///
+    /// The check is done by the kernel's own permission check rather than by
+    /// comparing the mode bits in userspace, so access granted or denied by
+    /// POSIX ACLs (or by filesystems with their own permission model, such as
+    /// NFSv4 ACLs) is reflected correctly.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Ok(false)` if access was denied with `EACCES`. All other
+    /// failures (including `path` not existing) are returned as errors.
+    ///
+    /// [`faccessat2(2)`]: http://man7.org/linux/man-pages/man2/access.2.html
+    #[doc(alias = "pathrs_inroot_access")]
+    #[inline]
+    pub fn access<P: AsRef<Path>>(
+        &self,
+        path: P,
+        mode: AccessMode,
+        flags: AccessFlags,
+    ) -> Result<bool, Error> {
+        self.as_ref().access(path, mode, flags)
+    }
+
+    /// Compute the relative path from the directory `from` to `to`, where both
+    /// are handles to inodes inside the [`Root`]'s tree.
+    ///
+    /// This is primarily intended for generating relative symlink targets
+    /// within the [`Root`], such that the symlink `from/<relative_path>` will
+    /// resolve to `to`. `..` components are inserted as necessary.
+    ///
+    /// # Caveats
+    ///
+    /// The paths of `from` and `to` (and the [`Root`] itself) are obtained using
+    /// `readlink(/proc/thread-self/fd/$n)`, and so this method is naturally
+    /// racy. If any of the inodes are renamed after they were resolved, the
+    /// current path of the inode will be used (which may not be the path used
+    /// to resolve the handle). If any of the inodes have been deleted, the
+    /// path returned by the kernel is no longer meaningful and thus this
+    /// method may return an error or a nonsensical result. The returned path
+    /// is computed purely lexically and does not take into account any
+    /// symlinks inside the [`Root`].
+    ///
+    /// # Errors
+    ///
+    /// If either `from` or `to` are not inside the [`Root`]'s tree, an error is
+    /// returned.
+    #[inline]
+    pub fn relative_path(&self, from: &Handle, to: &Handle) -> Result<PathBuf, Error> {
+        self.as_ref().relative_path(from, to)
+    }
+
+    /// Get the list of mounts at or below this [`Root`], as paths relative to
+    /// the [`Root`].
+    ///
+    /// This is intended for container teardown and inspection, where you need
+    /// to know what has been mounted inside a container's root filesystem. The
+    /// mount table is read from `/proc/thread-self/mountinfo` (using the safe
+    /// procfs handle) and the mount containing the [`Root`] is matched against
+    /// the [`Root`]'s device (and mount ID, if the kernel supports
+    /// `STATX_MNT_ID`). If the [`Root`] is itself a mount point, it is included
+    /// in the list as `.`.
+    ///
+    /// The mounts are returned in the order they were mounted, so they should
+    /// be unmounted in reverse order.
+    ///
+    /// # Errors
+    ///
+    /// This method has the same caveats as [`Root::relative_path`] -- the path
+    /// of the [`Root`] is fetched using `readlink(/proc/thread-self/fd/$n)` and
+    /// so this method is naturally racy. If the [`Root`] has been moved such
+    /// that its path no longer matches the mount table, an error is returned.
+    #[inline]
+    pub fn submounts(&self) -> Result<Vec<PathBuf>, Error> {
+        self.as_ref().submounts()
+    }
+
+    /// Within the given [`Root`]'s tree, resolve `path` and return a
+    /// [`FileHandle`] referencing the inode.
+    ///
+    /// This is similar to [`resolve`], except that the returned [`FileHandle`]
+    /// does not hold a file descriptor (the file descriptor used during
+    /// resolution is closed before returning) and so it does not keep the
+    /// mount containing the inode busy. This allows you to store references to
+    /// inodes without blocking the filesystem from being unmounted.
+    ///
+    /// The [`FileHandle`] can be converted back into a [`Handle`] with
+    /// [`open_unpinned`].
+    ///
+    /// # Errors
+    ///
+    /// Not all filesystems support file handles, in which case an
+    /// `EOPNOTSUPP` error will be returned.
+    ///
+    /// [`resolve`]: Self::resolve
+    /// [`open_unpinned`]: Self::open_unpinned
+    #[inline]
+    pub fn resolve_unpinned<P: AsRef<Path>>(&self, path: P) -> Result<FileHandle, Error> {
+        self.as_ref().resolve_unpinned(path)
+    }
+
+    /// Convert a [`FileHandle`] (returned by [`resolve_unpinned`]) back into a
+    /// [`Handle`].
+    ///
+    /// The [`Root`] must be on the same filesystem as the inode referenced by
+    /// `handle`. Because the inode may have been moved since
+    /// [`resolve_unpinned`] was called, the path of the new [`Handle`] is
+    /// checked to ensure that it is still inside the [`Root`]'s tree.
+    ///
+    /// # Errors
+    ///
+    /// `open_by_handle_at(2)` requires `CAP_DAC_READ_SEARCH`, and so this
+    /// method will fail with `EPERM` for unprivileged processes. If the inode
+    /// has been deleted, `ESTALE` is returned. If the inode is no longer inside
+    /// the [`Root`]'s tree (or the kernel cannot tell us where it is, which can
+    /// happen for non-directory inodes that are not in the dentry cache), a
+    /// `SafetyViolation` error is returned.
+    ///
+    /// [`resolve_unpinned`]: Self::resolve_unpinned
+    #[inline]
+    pub fn open_unpinned(&self, handle: &FileHandle) -> Result<Handle, Error> {
+        self.as_ref().open_unpinned(handle)
+    }
+
+    /// Within the [`Root`]'s tree, create an inode at `path` as specified by
+    /// `inode_type`.
     ///
     /// # Errors
     ///
@@ -371,28 +1704,180 @@ impl Root {
     /// let handle = root.resolve(path, perm)?;
     /// ```
     ///
-    /// [`create_file`] guarantees that the returned [`Handle`] is the same as
-    /// the file created by the operation. This is only possible to guarantee
-    /// for ordinary files because there is no [`O_CREAT`]-equivalent for other
-    /// inode types.
+    /// [`create_file`] guarantees that the returned [`Handle`] is the same as
+    /// the file created by the operation. This is only possible to guarantee
+    /// for ordinary files because there is no [`O_CREAT`]-equivalent for other
+    /// inode types.
+    ///
+    /// # Errors
+    ///
+    /// Identical to [`create`].
+    ///
+    /// [`create`]: Self::create
+    /// [`create_file`]: Self::create_file
+    /// [`O_CREAT`]: http://man7.org/linux/man-pages/man2/open.2.html
+    #[doc(alias = "pathrs_inroot_creat")]
+    #[doc(alias = "pathrs_inroot_create")]
+    #[inline]
+    pub fn create_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        flags: OpenFlags,
+        perm: &Permissions,
+    ) -> Result<File, Error> {
+        self.as_ref().create_file(path, flags, perm)
+    }
+
+    /// Within the [`Root`]'s tree, create a regular file at `path` if it does
+    /// not exist yet, or update the access and modification times of the
+    /// existing inode at `path` to the current time (like `touch(1)`). A
+    /// [`Handle`] to the created (or updated) inode is returned in either case.
+    ///
+    /// If a new file is created, it is created with the permission bits given
+    /// by `mode` (which cannot contain any non-`0o7777` bits), as with
+    /// [`create_with`].
+    ///
+    /// # Symlinks
+    ///
+    /// A trailing symlink is never followed. If the final component of `path`
+    /// is a (possibly dangling) symlink, no file is created and the times of
+    /// the symlink itself are updated (as with `touch -h`), so a symlink
+    /// cannot be used to create or modify a file elsewhere.
+    ///
+    /// # Errors
+    ///
+    /// If the parent directory of `path` cannot be resolved, or the times of
+    /// the existing inode could not be updated (such as if the caller is not
+    /// the owner and does not have write access to the inode), an error is
+    /// returned.
+    ///
+    /// On kernels older than Linux 5.8, `utimensat(2)` cannot operate on the
+    /// resolved handle directly, so the times are instead updated by name
+    /// relative to the (safely resolved) parent directory.
+    ///
+    /// [`create_with`]: Self::create_with
+    #[inline]
+    pub fn touch<P: AsRef<Path>>(&self, path: P, mode: u32) -> Result<Handle, Error> {
+        self.as_ref().touch(path, mode)
+    }
+
+    /// Create a regular file within the [`Root`]'s tree at `path` with the
+    /// given `flags` and creation `mode` (the equivalent of
+    /// `openat(O_CREAT|flags, mode)`), and return the opened [`File`].
+    ///
+    /// The parent directory of `path` is resolved within the [`Root`], and the
+    /// final component is created with a single [`openat(2)`] call. This means
+    /// that [`O_EXCL`] is enforced atomically by the kernel. The final
+    /// component is never followed if it is a symlink (regardless of whether
+    /// [`O_EXCL`] is set), so dangling symlinks cannot be used to create files
+    /// elsewhere.
+    ///
+    /// This is identical to [`create_file`], except that `mode` is a plain
+    /// permission mode rather than a [`Permissions`].
+    ///
+    /// # Errors
+    ///
+    /// If `mode` contains bits other than `0o7777`, an error with
+    /// [`ErrorKind::InvalidArgument`] is returned. If [`O_EXCL`] is set and
+    /// `path` already exists (even as a dangling symlink), `EEXIST` is
+    /// returned. Without [`O_EXCL`], `ELOOP` is returned if the final component
+    /// of `path` is a symlink.
+    ///
+    /// [`create_file`]: Self::create_file
+    /// [`openat(2)`]: http://man7.org/linux/man-pages/man2/openat.2.html
+    /// [`O_EXCL`]: OpenFlags::O_EXCL
+    /// [`ErrorKind::InvalidArgument`]: crate::error::ErrorKind::InvalidArgument
+    #[inline]
+    pub fn create_with<P: AsRef<Path>>(
+        &self,
+        path: P,
+        flags: OpenFlags,
+        mode: u32,
+    ) -> Result<File, Error> {
+        self.as_ref().create_with(path, flags, mode)
+    }
+
+    /// Within the [`Root`]'s tree, create a new regular file at `path` with the
+    /// given `mode`, but only if `predicate` returns `true` for the parent
+    /// directory of `path`.
+    ///
+    /// `predicate` is passed a [`Handle`] to the resolved parent directory, and
+    /// the file is then created using that same directory handle (so there is
+    /// no window where the parent could be swapped between the check and the
+    /// creation). The file is created with `O_EXCL`, so if `path` already
+    /// exists an error is returned.
+    ///
+    /// If `predicate` returns `false`, nothing is created and `None` is
+    /// returned. Otherwise, a [`Handle`] to the newly-created file is returned.
+    ///
+    /// Note that `predicate` can only verify the state of the parent directory
+    /// at the time it is run -- other processes may still modify the directory
+    /// before the file is created. This is intended to be used as part of
+    /// coordination schemes where all parties follow the same protocol.
+    ///
+    /// # Errors
+    ///
+    /// Identical to [`create_file`], with the additional error of `path`
+    /// already existing.
+    ///
+    /// [`create_file`]: Self::create_file
+    #[inline]
+    pub fn create_if<P, F>(&self, path: P, mode: u32, predicate: F) -> Result<Option<Handle>, Error>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&Handle) -> bool,
+    {
+        self.as_ref().create_if(path, mode, predicate)
+    }
+
+    /// Within the [`Root`]'s tree, create an inode at `path` with
+    /// [`mknodat(2)`] and return a [`Handle`] to the new inode.
+    ///
+    /// The type of inode is taken from the `S_IFMT` bits of `mode` (regular
+    /// files, FIFOs, sockets, and character or block devices are supported --
+    /// a type of `0` is treated as a regular file, as with [`mknodat(2)`]),
+    /// and `dev` is only used for device inodes. The parent directory of
+    /// `path` is resolved within the [`Root`], and the final component is
+    /// never followed.
+    ///
+    /// Due to kernel limitations, we cannot guarantee that the returned
+    /// [`Handle`] is the exact inode created (rather than one swapped in by an
+    /// attacker), but we do verify that it has the requested type.
+    ///
+    /// # Errors
+    ///
+    /// If the path already exists (regardless of the type of the existing
+    /// inode), an error is returned. If the final component of `path` is `.`
+    /// or `..`, or `mode` has an unsupported inode type, an error with
+    /// [`ErrorKind::InvalidArgument`] is returned.
+    ///
+    /// [`mknodat(2)`]: http://man7.org/linux/man-pages/man2/mknodat.2.html
+    /// [`ErrorKind::InvalidArgument`]: crate::error::ErrorKind::InvalidArgument
+    #[inline]
+    pub fn mknod<P: AsRef<Path>>(&self, path: P, mode: u32, dev: u64) -> Result<Handle, Error> {
+        self.as_ref().mknod(path, mode, dev)
+    }
+
+    /// Within the [`Root`]'s tree, create an overlayfs whiteout at `path`.
+    ///
+    /// A whiteout is a character device with device number `0:0`, which
+    /// overlayfs uses to mark a file from a lower layer as deleted. This is
+    /// intended for tools that author overlay layers. As with [`Root::mknod`],
+    /// the parent directory of `path` is resolved within the [`Root`], and the
+    /// final component is never followed (if it is an existing symlink, an
+    /// error is returned).
     ///
     /// # Errors
     ///
-    /// Identical to [`create`].
+    /// If the path already exists (regardless of the type of the existing
+    /// inode), an error is returned. Depending on the kernel and filesystem,
+    /// creating whiteouts may require `CAP_MKNOD`, in which case an error
+    /// with [`ErrorKind::OsError`] (`EPERM`) is returned.
     ///
-    /// [`create`]: Self::create
-    /// [`create_file`]: Self::create_file
-    /// [`O_CREAT`]: http://man7.org/linux/man-pages/man2/open.2.html
-    #[doc(alias = "pathrs_inroot_creat")]
-    #[doc(alias = "pathrs_inroot_create")]
+    /// [`ErrorKind::OsError`]: crate::error::ErrorKind::OsError
     #[inline]
-    pub fn create_file<P: AsRef<Path>>(
-        &self,
-        path: P,
-        flags: OpenFlags,
-        perm: &Permissions,
-    ) -> Result<File, Error> {
-        self.as_ref().create_file(path, flags, perm)
+    pub fn create_whiteout<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        self.as_ref().create_whiteout(path)
     }
 
     /// Within the [`Root`]'s tree, create a directory and any of its parent
@@ -475,36 +1960,290 @@ impl Root {
     /// before, since Linux does not invalidate file handles to unlinked files
     /// (though, directory handling is not as simple).
     ///
+    /// The tree is only ever walked using directory file descriptors (paths
+    /// are never re-resolved), and symlinks inside the tree are unlinked
+    /// rather than followed. This means that an attacker swapping parts of the
+    /// tree with symlinks cannot redirect the deletion outside of `path`.
+    ///
     /// # Errors
     ///
     /// If the path does not exist or some other error occurred during the
-    /// deletion process an error will be returned.
+    /// deletion process an error will be returned. If the final component of
+    /// `path` is `.` or `..`, an error with [`ErrorKind::InvalidArgument`] is
+    /// returned. To avoid running out of file descriptors, trees with more
+    /// than 512 levels of nested non-empty directories cannot be removed (an
+    /// error containing `ELOOP` is returned).
     ///
     /// [`os.RemoveAll`]: https://pkg.go.dev/os#RemoveAll
+    /// [`ErrorKind::InvalidArgument`]: crate::error::ErrorKind::InvalidArgument
     #[doc(alias = "pathrs_inroot_remove_all")]
     #[inline]
     pub fn remove_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
         self.as_ref().remove_all(path)
     }
 
+    /// Within the [`Root`]'s tree, recursively set the mode of `path` and
+    /// everything it contains. Directories have their mode set to `dir_mode`
+    /// and regular files have their mode set to `file_mode`. This is
+    /// effectively equivalent to `chmod -R`, except that directories and files
+    /// get different modes (matching the common pattern of using `0o755` for
+    /// directories and `0o644` for files).
+    ///
+    /// Symlinks within the tree (and other inodes such as device inodes,
+    /// fifos and sockets) are not modified or followed, so this will never
+    /// modify any inode outside of the tree. However, if `path` itself is a
+    /// symlink it is resolved within the [`Root`] (like [`Root::resolve`]).
+    ///
+    /// # Errors
+    ///
+    /// If the path does not exist or some other error occurred while walking
+    /// the tree, an error will be returned. Note that the modes of any inodes
+    /// that were already visited are not restored.
+    #[inline]
+    pub fn chmod_all<P: AsRef<Path>>(
+        &self,
+        path: P,
+        dir_mode: u32,
+        file_mode: u32,
+    ) -> Result<(), Error> {
+        self.as_ref().chmod_all(path, dir_mode, file_mode)
+    }
+
+    /// Within the [`Root`]'s tree, compute a digest of the structure and
+    /// metadata of the subtree at `path`.
+    ///
+    /// This is intended for change detection (such as checking whether two
+    /// extractions of the same archive are identical). The digest is a
+    /// Merkle-style SHA-256 hash, where each inode's hash covers its type and
+    /// mode as well as:
+    ///
+    /// * For regular files, the file size and (if `include_content` is set)
+    ///   the SHA-256 of its contents.
+    /// * For symlinks, the symlink target. Symlinks are never followed (not
+    ///   even if `path` itself is a symlink), so the digest only depends on
+    ///   the contents of the subtree.
+    /// * For device inodes, the device number.
+    /// * For directories, the name and hash of each directory entry, in sorted
+    ///   order.
+    ///
+    /// Ownership, timestamps, extended attributes and the name of `path`
+    /// itself do not contribute to the digest.
+    ///
+    /// This method is only available with the `tree-digest` feature.
+    ///
+    /// # Errors
+    ///
+    /// If the path does not exist or some other error occurred while walking
+    /// the tree, an error will be returned. Note that the tree is not locked
+    /// while it is being walked, so concurrent modifications may result in a
+    /// digest that does not match any state the tree was in.
+    #[cfg(feature = "tree-digest")]
+    #[inline]
+    pub fn tree_digest<P: AsRef<Path>>(
+        &self,
+        path: P,
+        include_content: bool,
+    ) -> Result<[u8; 32], Error> {
+        self.as_ref().tree_digest(path, include_content)
+    }
+
     /// Within the [`Root`]'s tree, perform a rename with the given `source` and
     /// `directory`. The `flags` argument is passed directly to
     /// [`renameat2(2)`].
     ///
+    /// The parent directories of both `source` and `destination` are resolved
+    /// within the [`Root`] (so neither can refer to a path outside of the
+    /// [`Root`] -- as with [`Root::resolve`], `..` components are clamped to
+    /// the [`Root`], or rejected if [`ResolverFlags::BENEATH`] is set), and the
+    /// rename is done on their final components.
+    ///
+    /// On kernels without [`renameat2(2)`] support (see
+    /// [`RenameFlags::is_supported`]), [`RenameFlags::RENAME_NOREPLACE`] on its
+    /// own is emulated by checking that `destination` does not exist before
+    /// doing a plain `renameat(2)`. Note that this emulation is racy -- if
+    /// `destination` is created concurrently it may still be replaced.
+    ///
     /// # Errors
     ///
-    /// The error rules are identical to [`renameat2(2)`].
+    /// The error rules are identical to [`renameat2(2)`], except that
+    /// combining [`RenameFlags::RENAME_EXCHANGE`] with
+    /// [`RenameFlags::RENAME_NOREPLACE`] or [`RenameFlags::RENAME_WHITEOUT`]
+    /// is rejected up-front with [`ErrorKind::InvalidArgument`] (rather than
+    /// the kernel's bare `EINVAL`). If the kernel does
+    /// not support [`renameat2(2)`] and `rflags` contains anything other than
+    /// [`RenameFlags::RENAME_NOREPLACE`] (such as
+    /// [`RenameFlags::RENAME_EXCHANGE`]), an error with
+    /// [`ErrorKind::NotSupported`] is returned.
     ///
     /// [`renameat2(2)`]: http://man7.org/linux/man-pages/man2/renameat2.2.html
+    /// [`ErrorKind::InvalidArgument`]: crate::error::ErrorKind::InvalidArgument
+    /// [`ErrorKind::NotSupported`]: crate::error::ErrorKind::NotSupported
     #[doc(alias = "pathrs_inroot_rename")]
-    pub fn rename<P: AsRef<Path>>(
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(
         &self,
         source: P,
-        destination: P,
+        destination: Q,
         rflags: RenameFlags,
     ) -> Result<(), Error> {
         self.as_ref().rename(source, destination, rflags)
     }
+
+    /// Within the [`Root`]'s tree, rename `from` to `to` (as with
+    /// [`Root::rename`] with no flags), and then call `on_moved` with the
+    /// old and new paths of the moved inode.
+    ///
+    /// This is intended for users that keep an index keyed by path, which
+    /// needs to be updated when a directory is renamed. See
+    /// [`Root::rename_with_callback_deep`] if you also need `on_moved` to be
+    /// called for every descendant of a renamed directory.
+    ///
+    /// The paths passed to `on_moved` are based on `from` and `to` as given
+    /// (they are not canonicalised).
+    ///
+    /// # Errors
+    ///
+    /// Identical to [`Root::rename`]. If the rename fails, `on_moved` is not
+    /// called.
+    #[inline]
+    pub fn rename_with_callback<P, Q, F>(&self, from: P, to: Q, on_moved: F) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        F: FnMut(&Path, &Path),
+    {
+        self.as_ref().rename_with_callback(from, to, on_moved)
+    }
+
+    /// Identical to [`Root::rename_with_callback`], except that if `from` is
+    /// a directory then `on_moved` is also called for every inode inside it
+    /// (after being called for `from` itself, with parent directories always
+    /// reported before their children).
+    ///
+    /// The subtree is enumerated before the rename is done (symlinks are not
+    /// followed), so any inodes created inside the directory concurrently with
+    /// this operation might not be reported.
+    ///
+    /// # Errors
+    ///
+    /// Identical to [`Root::rename`]. In addition, an error is returned if the
+    /// subtree could not be enumerated (in which case the rename is not done).
+    #[inline]
+    pub fn rename_with_callback_deep<P, Q, F>(
+        &self,
+        from: P,
+        to: Q,
+        on_moved: F,
+    ) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        F: FnMut(&Path, &Path),
+    {
+        self.as_ref().rename_with_callback_deep(from, to, on_moved)
+    }
+
+    /// Within the [`Root`]'s tree, create a new hard-link at `link` to the
+    /// existing inode at `target`, as in [`linkat(2)`].
+    ///
+    /// Unlike [`InodeType::Hardlink`], `target` is resolved fully within the
+    /// [`Root`] (like [`Root::resolve_nofollow`]) and the link is created
+    /// from the resulting handle, so there is no window where the final
+    /// component of `target` could be swapped. If `target` is a symlink, the
+    /// new link refers to the symlink itself (matching the default
+    /// [`linkat(2)`] behaviour without `AT_SYMLINK_FOLLOW`). Use
+    /// [`Root::hardlink_follow`] to link to the symlink's target instead.
+    ///
+    /// Creating a link from a handle with `AT_EMPTY_PATH` requires
+    /// `CAP_DAC_READ_SEARCH` on most kernels. If that fails, the link is
+    /// instead created through the handle's `/proc/thread-self/fd/$n`
+    /// magic-link.
+    ///
+    /// # Errors
+    ///
+    /// If `link` already exists, an error is returned. If `target` and the
+    /// parent directory of `link` are on different devices, an error
+    /// containing `EXDEV` is returned without attempting the link.
+    ///
+    /// [`linkat(2)`]: http://man7.org/linux/man-pages/man2/linkat.2.html
+    #[inline]
+    pub fn hardlink<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        target: P,
+        link: Q,
+    ) -> Result<(), Error> {
+        self.as_ref().hardlink(target, link)
+    }
+
+    /// Identical to [`Root::hardlink`], except that if `target` is a symlink
+    /// it is followed (within the [`Root`]) and the new link refers to the
+    /// final target of the symlink, as with `AT_SYMLINK_FOLLOW`.
+    ///
+    /// # Errors
+    ///
+    /// Identical to [`Root::hardlink`].
+    #[inline]
+    pub fn hardlink_follow<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        target: P,
+        link: Q,
+    ) -> Result<(), Error> {
+        self.as_ref().hardlink_follow(target, link)
+    }
+
+    /// Within the [`Root`]'s tree, atomically replace the file at `path` with
+    /// a new file containing `contents` with the mode given by `perm`, and
+    /// return a [`Handle`] to the new file.
+    ///
+    /// This is intended for updating executables which may currently be
+    /// running. Opening a running executable for writing fails with `ETXTBSY`
+    /// (see [`Error::is_text_busy`]), and even if it did not, modifying a
+    /// program in-place would cause running instances to see a
+    /// partially-written binary. Instead, the new contents are written to a
+    /// temporary file in the same directory as `path`, which is then
+    /// `rename(2)`-ed over `path`. Running instances of the old program are
+    /// unaffected.
+    ///
+    /// # Errors
+    ///
+    /// If an error occurs, `path` is left untouched and the temporary file is
+    /// removed.
+    #[inline]
+    pub fn replace_executable<P: AsRef<Path>, R: Read>(
+        &self,
+        path: P,
+        contents: R,
+        perm: &Permissions,
+    ) -> Result<Handle, Error> {
+        self.as_ref().replace_executable(path, contents, perm)
+    }
+
+    /// Within the [`Root`]'s tree, open all of the entries in the directory
+    /// `dir` whose names match the shell-style glob `pattern`, returning
+    /// [`Handle`]s to the matching entries sorted by name.
+    ///
+    /// `pattern` is matched against the names of the entries in `dir` only, so
+    /// it must be a single path component (it must not contain `/`). The
+    /// supported syntax is `*`, `?`, bracket expressions (`[abc]`, `[a-z]`,
+    /// `[!abc]`) and `\` escapes. As with shell globbing, names starting with
+    /// `.` only match patterns that start with a literal `.`, and `.` and `..`
+    /// are never returned.
+    ///
+    /// Symlinks among the matched entries are not followed (the returned
+    /// [`Handle`] refers to the symlink itself, as with
+    /// [`Root::resolve_nofollow`]). Entries which are removed while `dir` is
+    /// being scanned are silently skipped.
+    ///
+    /// # Errors
+    ///
+    /// If `pattern` is empty or contains `/`, an [`ErrorKind::InvalidArgument`]
+    /// is returned. Otherwise the error rules are the same as for
+    /// [`Root::resolve`] and reading the directory.
+    ///
+    /// [`ErrorKind::InvalidArgument`]: crate::error::ErrorKind::InvalidArgument
+    #[inline]
+    pub fn glob_in<P: AsRef<Path>>(&self, dir: P, pattern: &str) -> Result<Vec<Handle>, Error> {
+        self.as_ref().glob_in(dir, pattern)
+    }
 }
 
 impl From<OwnedFd> for Root {
@@ -556,102 +2295,387 @@ pub struct RootRef<'fd> {
     inner: BorrowedFd<'fd>,
     // TODO: Drop this and switch to builder-pattern.
     resolver: Resolver,
+    pivot_scratch: Option<BorrowedFd<'fd>>,
 }
 
-impl RootRef<'_> {
-    /// Wrap a [`BorrowedFd`] into a [`RootRef`].
+impl<'fd> RootRef<'fd> {
+    /// Wrap a [`BorrowedFd`] into a [`RootRef`].
+    ///
+    /// The [`BorrowedFd`] should be a file descriptor referencing a directory,
+    /// otherwise all [`Root`] operations will fail.
+    ///
+    /// The configuration is set to the system default and should be configured
+    /// prior to usage, if appropriate.
+    pub fn from_fd(inner: BorrowedFd<'_>) -> RootRef<'_> {
+        RootRef {
+            inner,
+            resolver: Default::default(),
+            pivot_scratch: None,
+        }
+    }
+
+    /// Get the current [`ResolverFlags`] for this [`RootRef`].
+    #[inline]
+    pub fn resolver_flags(&self) -> ResolverFlags {
+        self.resolver.flags
+    }
+
+    /// Set the [`ResolverFlags`] for all operations in this [`RootRef`].
+    ///
+    /// Note that this only affects this instance of [`RootRef`]. Neither the
+    /// original [`Root`] nor any other [`RootRef`] references to the same
+    /// underlying [`Root`] will have their [`ResolverFlags`] unchanged.
+    ///
+    /// [`RootRef::clone`] also copies the current [`ResolverFlags`] of the
+    /// [`RootRef`].
+    #[inline]
+    pub fn set_resolver_flags(&mut self, flags: ResolverFlags) -> &mut Self {
+        self.resolver.flags = flags;
+        self
+    }
+
+    /// Set the [`ResolverFlags`] for all operations in this [`RootRef`].
+    ///
+    /// This is identical to [`RootRef::set_resolver_flags`] except that it can
+    /// more easily be used with chaining to configure a [`RootRef`] in a single
+    /// line:
+    ///
+    /// ```rust
+    /// # use std::{
+    /// #     fs::{Permissions, File},
+    /// #     os::unix::{
+    /// #         fs::PermissionsExt,
+    /// #         io::{AsFd, OwnedFd},
+    /// #     },
+    /// # };
+    /// # use pathrs::{RootRef, flags::ResolverFlags, InodeType};
+    /// # let tmpdir = tempfile::TempDir::new()?;
+    /// # let rootdir = &tmpdir;
+    /// let fd: OwnedFd = File::open(rootdir)?.into();
+    /// let root = RootRef::from_fd(fd.as_fd())
+    ///     .with_resolver_flags(ResolverFlags::NO_SYMLINKS);
+    ///
+    /// // Continue to use RootRef.
+    /// # let perm = Permissions::from_mode(0o755);
+    /// root.mkdir_all("foo/bar/baz", &perm)?;
+    /// root.create("one", &InodeType::Directory(perm))?;
+    /// root.remove_all("foo")?;
+    /// # let _ = tmpdir; // make sure it is not dropped early
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    /// The other primary usecase for [`RootRef::with_resolver_flags`] is with
+    /// [`Root::as_ref`] to temporarily set some flags for a single one-line or
+    /// a few operations without modifying the original [`Root`] resolver flags:
+    ///
+    /// ```rust
+    /// # use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+    /// # use pathrs::{Root, flags::ResolverFlags, InodeType};
+    /// # let tmpdir = tempfile::TempDir::new()?;
+    /// # let rootdir = &tmpdir;
+    /// let root = Root::open(rootdir)?;
+    /// # let perm = Permissions::from_mode(0o755);
+    ///
+    /// // Apply ResolverFlags::NO_SYMLINKS for a single operation.
+    /// root.as_ref()
+    ///     .with_resolver_flags(ResolverFlags::NO_SYMLINKS)
+    ///     .mkdir_all("foo/bar/baz", &perm)?;
+    ///
+    /// // Create a temporary RootRef to do multiple operations.
+    /// let root2 = root
+    ///     .as_ref()
+    ///     .with_resolver_flags(ResolverFlags::NO_SYMLINKS);
+    /// root2.create("one", &InodeType::Directory(perm))?;
+    /// root2.remove_all("foo")?;
+    /// # let _ = tmpdir; // make sure it is not dropped early
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    #[inline]
+    pub fn with_resolver_flags(mut self, flags: ResolverFlags) -> Self {
+        self.set_resolver_flags(flags);
+        self
+    }
+
+    /// Get the current eager re-open flags for this [`RootRef`].
+    ///
+    /// See [`RootRef::set_eager_reopen`] for more details.
+    #[inline]
+    pub fn eager_reopen(&self) -> Option<OpenFlags> {
+        self.resolver.eager_reopen
+    }
+
+    /// Configure whether [`RootRef::resolve`] (and
+    /// [`RootRef::resolve_nofollow`]) should eagerly re-open regular files with
+    /// the given [`OpenFlags`].
+    ///
+    /// See [`Root::set_eager_reopen`] for more details. Note that this only
+    /// affects this instance of [`RootRef`].
+    #[inline]
+    pub fn set_eager_reopen(&mut self, flags: Option<OpenFlags>) -> &mut Self {
+        self.resolver.eager_reopen = flags;
+        self
+    }
+
+    /// Configure eager re-opening for all operations in this [`RootRef`].
+    ///
+    /// This is identical to [`RootRef::set_eager_reopen`] except that it can
+    /// more easily be used with chaining to configure a [`RootRef`] in a
+    /// single line.
+    #[inline]
+    pub fn with_eager_reopen(mut self, flags: Option<OpenFlags>) -> Self {
+        self.set_eager_reopen(flags);
+        self
+    }
+
+    /// Get the current maximum number of symlink traversals for this
+    /// [`RootRef`].
+    ///
+    /// See [`RootRef::set_max_symlink_traversals`] for more details.
+    #[inline]
+    pub fn max_symlink_traversals(&self) -> Option<usize> {
+        self.resolver.max_symlink_traversals
+    }
+
+    /// Set the maximum number of symlinks that will be followed during a
+    /// single path resolution within this [`RootRef`].
+    ///
+    /// See [`Root::set_max_symlink_traversals`] for more details. Note that
+    /// this only affects this instance of [`RootRef`].
+    #[inline]
+    pub fn set_max_symlink_traversals(&mut self, limit: Option<usize>) -> &mut Self {
+        self.resolver.max_symlink_traversals = limit;
+        self
+    }
+
+    /// Set the maximum number of symlink traversals for all operations in
+    /// this [`RootRef`].
+    ///
+    /// This is identical to [`RootRef::set_max_symlink_traversals`] except
+    /// that it can more easily be used with chaining to configure a
+    /// [`RootRef`] in a single line.
+    #[inline]
+    pub fn with_max_symlink_traversals(mut self, limit: Option<usize>) -> Self {
+        self.set_max_symlink_traversals(limit);
+        self
+    }
+
+    /// Get the current maximum total length of symlink targets for this
+    /// [`RootRef`].
+    ///
+    /// See [`RootRef::set_max_symlink_target_bytes`] for more details.
+    #[inline]
+    pub fn max_symlink_target_bytes(&self) -> Option<usize> {
+        self.resolver.max_symlink_target_bytes
+    }
+
+    /// Set the maximum total length (in bytes) of the symlink targets that
+    /// will be expanded during a single path resolution within this
+    /// [`RootRef`].
+    ///
+    /// See [`Root::set_max_symlink_target_bytes`] for more details. Note that
+    /// this only affects this instance of [`RootRef`].
+    #[inline]
+    pub fn set_max_symlink_target_bytes(&mut self, limit: Option<usize>) -> &mut Self {
+        self.resolver.max_symlink_target_bytes = limit;
+        self
+    }
+
+    /// Set the maximum total length of symlink targets for all operations in
+    /// this [`RootRef`].
+    ///
+    /// This is identical to [`RootRef::set_max_symlink_target_bytes`] except
+    /// that it can more easily be used with chaining to configure a
+    /// [`RootRef`] in a single line.
+    #[inline]
+    pub fn with_max_symlink_target_bytes(mut self, limit: Option<usize>) -> Self {
+        self.set_max_symlink_target_bytes(limit);
+        self
+    }
+
+    /// Get the current setuid/setgid policy for this [`RootRef`].
+    ///
+    /// See [`RootRef::set_setid_policy`] for more details.
+    #[inline]
+    pub fn setid_policy(&self) -> SetidPolicy {
+        self.resolver.setid_policy
+    }
+
+    /// Configure how setuid and setgid inodes are treated by path resolution
+    /// within this [`RootRef`].
+    ///
+    /// See [`Root::set_setid_policy`] for more details. Note that this only
+    /// affects this instance of [`RootRef`].
+    #[inline]
+    pub fn set_setid_policy(&mut self, policy: SetidPolicy) -> &mut Self {
+        self.resolver.setid_policy = policy;
+        self
+    }
+
+    /// Set the setuid/setgid policy for all operations in this [`RootRef`].
+    ///
+    /// This is identical to [`RootRef::set_setid_policy`] except that it can
+    /// more easily be used with chaining to configure a [`RootRef`] in a
+    /// single line.
+    #[inline]
+    pub fn with_setid_policy(mut self, policy: SetidPolicy) -> Self {
+        self.set_setid_policy(policy);
+        self
+    }
+
+    /// Get whether path resolution within this [`RootRef`] rejects deleted
+    /// intermediate directories.
     ///
-    /// The [`BorrowedFd`] should be a file descriptor referencing a directory,
-    /// otherwise all [`Root`] operations will fail.
+    /// See [`RootRef::set_reject_deleted_intermediates`] for more details.
+    #[inline]
+    pub fn reject_deleted_intermediates(&self) -> bool {
+        self.resolver.reject_deleted_intermediates
+    }
+
+    /// Configure whether path resolution within this [`RootRef`] fails if it
+    /// walks through a directory which has been deleted.
     ///
-    /// The configuration is set to the system default and should be configured
-    /// prior to usage, if appropriate.
-    pub fn from_fd(inner: BorrowedFd<'_>) -> RootRef<'_> {
-        RootRef {
-            inner,
-            resolver: Default::default(),
-        }
+    /// See [`Root::set_reject_deleted_intermediates`] for more details. Note
+    /// that this only affects this instance of [`RootRef`].
+    #[inline]
+    pub fn set_reject_deleted_intermediates(&mut self, reject: bool) -> &mut Self {
+        self.resolver.reject_deleted_intermediates = reject;
+        self
     }
 
-    /// Get the current [`ResolverFlags`] for this [`RootRef`].
+    /// Set whether deleted intermediate directories are rejected for all
+    /// operations in this [`RootRef`].
+    ///
+    /// This is identical to [`RootRef::set_reject_deleted_intermediates`]
+    /// except that it can more easily be used with chaining to configure a
+    /// [`RootRef`] in a single line.
     #[inline]
-    pub fn resolver_flags(&self) -> ResolverFlags {
-        self.resolver.flags
+    pub fn with_reject_deleted_intermediates(mut self, reject: bool) -> Self {
+        self.set_reject_deleted_intermediates(reject);
+        self
     }
 
-    /// Set the [`ResolverFlags`] for all operations in this [`RootRef`].
+    /// Get the current trailing symlink policy for this [`RootRef`].
     ///
-    /// Note that this only affects this instance of [`RootRef`]. Neither the
-    /// original [`Root`] nor any other [`RootRef`] references to the same
-    /// underlying [`Root`] will have their [`ResolverFlags`] unchanged.
+    /// See [`RootRef::set_trailing_symlink_policy`] for more details.
+    #[inline]
+    pub fn trailing_symlink_policy(&self) -> TrailingSymlinkPolicy {
+        self.resolver.trailing_symlink_policy
+    }
+
+    /// Configure how symlinks in the final component of a path are followed
+    /// by path resolution within this [`RootRef`].
     ///
-    /// [`RootRef::clone`] also copies the current [`ResolverFlags`] of the
-    /// [`RootRef`].
+    /// See [`Root::set_trailing_symlink_policy`] for more details. Note that
+    /// this only affects this instance of [`RootRef`].
     #[inline]
-    pub fn set_resolver_flags(&mut self, flags: ResolverFlags) -> &mut Self {
-        self.resolver.flags = flags;
+    pub fn set_trailing_symlink_policy(&mut self, policy: TrailingSymlinkPolicy) -> &mut Self {
+        self.resolver.trailing_symlink_policy = policy;
         self
     }
 
-    /// Set the [`ResolverFlags`] for all operations in this [`RootRef`].
+    /// Set the trailing symlink policy for all operations in this [`RootRef`].
     ///
-    /// This is identical to [`RootRef::set_resolver_flags`] except that it can
-    /// more easily be used with chaining to configure a [`RootRef`] in a single
-    /// line:
+    /// This is identical to [`RootRef::set_trailing_symlink_policy`] except
+    /// that it can more easily be used with chaining to configure a
+    /// [`RootRef`] in a single line.
+    #[inline]
+    pub fn with_trailing_symlink_policy(mut self, policy: TrailingSymlinkPolicy) -> Self {
+        self.set_trailing_symlink_policy(policy);
+        self
+    }
+
+    /// Get the [`ResolveObserver`] registered for this [`RootRef`], if any.
     ///
-    /// ```rust
-    /// # use std::{
-    /// #     fs::{Permissions, File},
-    /// #     os::unix::{
-    /// #         fs::PermissionsExt,
-    /// #         io::{AsFd, OwnedFd},
-    /// #     },
-    /// # };
-    /// # use pathrs::{RootRef, flags::ResolverFlags, InodeType};
-    /// # let tmpdir = tempfile::TempDir::new()?;
-    /// # let rootdir = &tmpdir;
-    /// let fd: OwnedFd = File::open(rootdir)?.into();
-    /// let root = RootRef::from_fd(fd.as_fd())
-    ///     .with_resolver_flags(ResolverFlags::NO_SYMLINKS);
+    /// See [`RootRef::set_resolve_observer`] for more details.
+    #[inline]
+    pub fn resolve_observer(&self) -> Option<&'static dyn ResolveObserver> {
+        self.resolver.observer
+    }
+
+    /// Register a [`ResolveObserver`] which is notified about path
+    /// resolutions within this [`RootRef`], or unregister it with `None`.
     ///
-    /// // Continue to use RootRef.
-    /// # let perm = Permissions::from_mode(0o755);
-    /// root.mkdir_all("foo/bar/baz", &perm)?;
-    /// root.create("one", &InodeType::Directory(perm))?;
-    /// root.remove_all("foo")?;
-    /// # let _ = tmpdir; // make sure it is not dropped early
-    /// # Ok::<(), anyhow::Error>(())
-    /// ```
+    /// See [`Root::set_resolve_observer`] for more details. Note that this
+    /// only affects this instance of [`RootRef`].
+    #[inline]
+    pub fn set_resolve_observer(
+        &mut self,
+        observer: Option<&'static dyn ResolveObserver>,
+    ) -> &mut Self {
+        self.resolver.observer = observer;
+        self
+    }
+
+    /// Set the [`ResolveObserver`] for all operations in this [`RootRef`].
     ///
-    /// The other primary usecase for [`RootRef::with_resolver_flags`] is with
-    /// [`Root::as_ref`] to temporarily set some flags for a single one-line or
-    /// a few operations without modifying the original [`Root`] resolver flags:
+    /// This is identical to [`RootRef::set_resolve_observer`] except that it
+    /// can more easily be used with chaining to configure a [`RootRef`] in a
+    /// single line.
+    #[inline]
+    pub fn with_resolve_observer(mut self, observer: Option<&'static dyn ResolveObserver>) -> Self {
+        self.set_resolve_observer(observer);
+        self
+    }
+
+    /// Force all operations in this [`RootRef`] to use the given resolver
+    /// `backend`, rather than the one chosen automatically by libpathrs.
     ///
-    /// ```rust
-    /// # use std::{fs::Permissions, os::unix::fs::PermissionsExt};
-    /// # use pathrs::{Root, flags::ResolverFlags, InodeType};
-    /// # let tmpdir = tempfile::TempDir::new()?;
-    /// # let rootdir = &tmpdir;
-    /// let root = Root::open(rootdir)?;
-    /// # let perm = Permissions::from_mode(0o755);
+    /// **You probably don't need this.** See [`Root::force_resolver_backend`]
+    /// for more details. Note that this only affects this instance of
+    /// [`RootRef`].
+    #[inline]
+    pub fn force_resolver_backend(
+        &mut self,
+        backend: PublicResolverBackend,
+    ) -> Result<&mut Self, Error> {
+        self.resolver.force_backend(backend)?;
+        Ok(self)
+    }
+
+    /// Force all operations in this [`RootRef`] to use the given resolver
+    /// `backend`.
     ///
-    /// // Apply ResolverFlags::NO_SYMLINKS for a single operation.
-    /// root.as_ref()
-    ///     .with_resolver_flags(ResolverFlags::NO_SYMLINKS)
-    ///     .mkdir_all("foo/bar/baz", &perm)?;
+    /// This is identical to [`RootRef::force_resolver_backend`] except that
+    /// it can more easily be used with chaining to configure a [`RootRef`] in
+    /// a single line.
+    #[inline]
+    pub fn with_forced_resolver_backend(
+        mut self,
+        backend: PublicResolverBackend,
+    ) -> Result<Self, Error> {
+        self.force_resolver_backend(backend)?;
+        Ok(self)
+    }
+
+    /// Get the current scratch directory used by the `pivot_root(2)`-based
+    /// resolver for this [`RootRef`].
     ///
-    /// // Create a temporary RootRef to do multiple operations.
-    /// let root2 = root
-    ///     .as_ref()
-    ///     .with_resolver_flags(ResolverFlags::NO_SYMLINKS);
-    /// root2.create("one", &InodeType::Directory(perm))?;
-    /// root2.remove_all("foo")?;
-    /// # let _ = tmpdir; // make sure it is not dropped early
-    /// # Ok::<(), anyhow::Error>(())
-    /// ```
+    /// See [`Root::set_pivot_scratch_dir`] for more details.
     #[inline]
-    pub fn with_resolver_flags(mut self, flags: ResolverFlags) -> Self {
-        self.set_resolver_flags(flags);
+    pub fn pivot_scratch_dir(&self) -> Option<BorrowedFd<'fd>> {
+        self.pivot_scratch
+    }
+
+    /// Set the scratch directory used by the `pivot_root(2)`-based resolver
+    /// for this [`RootRef`].
+    ///
+    /// See [`Root::set_pivot_scratch_dir`] for more details. Note that this
+    /// only affects this instance of [`RootRef`].
+    #[inline]
+    pub fn set_pivot_scratch_dir(&mut self, dir: Option<BorrowedFd<'fd>>) -> &mut Self {
+        self.pivot_scratch = dir;
+        self
+    }
+
+    /// Set the scratch directory used by the `pivot_root(2)`-based resolver
+    /// for this [`RootRef`].
+    ///
+    /// This is identical to [`RootRef::set_pivot_scratch_dir`] except that it
+    /// can more easily be used with chaining to configure a [`RootRef`] in a
+    /// single line.
+    #[inline]
+    pub fn with_pivot_scratch_dir(mut self, dir: Option<BorrowedFd<'fd>>) -> Self {
+        self.set_pivot_scratch_dir(dir);
         self
     }
 
@@ -659,7 +2683,8 @@ impl RootRef<'_> {
     ///
     /// Note that (unlike [`BorrowedFd::clone`]) this method creates a full copy
     /// of the underlying file descriptor and thus is more equivalent to
-    /// [`BorrowedFd::try_clone_to_owned`].
+    /// [`BorrowedFd::try_clone_to_owned`]. See [`Root::try_clone`] for more
+    /// details about the file descriptor semantics.
     ///
     /// To create a shallow copy of a [`RootRef`], you can use [`Clone::clone`]
     /// (or just [`Copy`]).
@@ -676,6 +2701,14 @@ impl RootRef<'_> {
                     source: err,
                 })?,
             resolver: self.resolver,
+            pivot_scratch: self
+                .pivot_scratch
+                .map(|fd| fd.try_clone_to_owned())
+                .transpose()
+                .map_err(|err| ErrorImpl::OsError {
+                    operation: "clone pivot scratch directory".into(),
+                    source: err,
+                })?,
         })
     }
 
@@ -698,7 +2731,9 @@ impl RootRef<'_> {
     #[doc(alias = "pathrs_inroot_resolve")]
     #[inline]
     pub fn resolve<P: AsRef<Path>>(&self, path: P) -> Result<Handle, Error> {
-        self.resolver.resolve(self, path, false)
+        self.resolver
+            .resolve(self, self.pivot_scratch, path, false)
+            .and_then(|handle| self.resolver.maybe_eager_reopen(handle))
     }
 
     /// Identical to [`resolve`], except that *trailing* symlinks are *not*
@@ -713,7 +2748,293 @@ impl RootRef<'_> {
     #[doc(alias = "pathrs_inroot_resolve_nofollow")]
     #[inline]
     pub fn resolve_nofollow<P: AsRef<Path>>(&self, path: P) -> Result<Handle, Error> {
-        self.resolver.resolve(self, path, true)
+        self.resolver
+            .resolve(self, self.pivot_scratch, path, true)
+            .and_then(|handle| self.resolver.maybe_eager_reopen(handle))
+    }
+
+    /// Identical to [`resolve`] (or [`resolve_nofollow`]), except that the
+    /// eager re-open policy is never applied. Internal lookups must use this
+    /// so that they always operate on `O_PATH` handles.
+    ///
+    /// [`resolve`]: Self::resolve
+    /// [`resolve_nofollow`]: Self::resolve_nofollow
+    #[inline]
+    fn resolve_opath<P: AsRef<Path>>(
+        &self,
+        path: P,
+        no_follow_trailing: bool,
+    ) -> Result<Handle, Error> {
+        self.resolver
+            .resolve(self, self.pivot_scratch, path, no_follow_trailing)
+    }
+
+    /// Resolve `path` without following the trailing symlink (as with
+    /// [`resolve_nofollow`]), and also resolve the target of that symlink.
+    ///
+    /// This is intended for programs that display symlinks along with their
+    /// targets. The target is resolved using the contents of the symlink
+    /// referenced by the returned handle (relative to the directory containing
+    /// it) rather than by resolving `path` a second time, so the two handles
+    /// refer to the same symlink even if `path` is swapped concurrently. Note
+    /// that the parent directory components of `path` are walked again.
+    ///
+    /// If the final component of `path` is not a symlink, the second handle is
+    /// a copy of the first one.
+    ///
+    /// # Errors
+    ///
+    /// If `path` itself cannot be resolved, an [`Error`] is returned. Errors
+    /// resolving the target of the symlink (such as `ENOENT` for a dangling
+    /// symlink) are returned as the second element of the tuple.
+    ///
+    /// [`resolve_nofollow`]: Self::resolve_nofollow
+    pub fn resolve_symlink_and_target<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(Handle, Result<Handle, Error>), Error> {
+        let path = path.as_ref();
+        let link = self
+            .resolve_nofollow(path)
+            .wrap("resolve symlink O_NOFOLLOW")?;
+        if !link.stat().wrap("fstat resolved symlink")?.is_symlink() {
+            let target = link.try_clone()?;
+            return Ok((link, Ok(target)));
+        }
+
+        // If the symlink target is absolute, the join is just the target
+        // (which is resolved relative to the root, as usual).
+        let (parent, _) = utils::path_split(path).wrap("split symlink path into (parent, name)")?;
+        let target = parent.join(link.readlink()?);
+        let target = self.resolve(target).wrap("resolve symlink target");
+        Ok((link, target))
+    }
+
+    /// Resolve each path in `paths` within the [`RootRef`], as though by calling
+    /// [`resolve`] on each of them.
+    ///
+    /// The returned results are in the same order as `paths`, and an error
+    /// resolving one path does not affect the others. Some resolver backends
+    /// (namely the emulated `O_PATH` resolver) will only resolve directory
+    /// prefixes shared between multiple paths once, making this significantly
+    /// faster than calling [`resolve`] in a loop. If many of the paths are in
+    /// the same directory, that directory may also be listed once (with
+    /// `getdents(2)`) so that names which do not exist need not be looked up.
+    /// The resolution cache only lives for the duration of this call.
+    ///
+    /// Both the resolved directory prefixes and the directory listings are
+    /// re-used for the rest of the call, so the results reflect the tree as it
+    /// was when each directory was first reached. In particular, a name
+    /// created in a listed directory after it was listed is reported as not
+    /// existing (`ENOENT`) for the remainder of the call. If you need to see
+    /// concurrent changes, call [`resolve`] for each path instead.
+    ///
+    /// [`resolve`]: Self::resolve
+    pub fn resolve_many<I>(&self, paths: I) -> Vec<Result<Handle, Error>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        self.resolver
+            .resolve_many(self, self.pivot_scratch, paths)
+            .into_iter()
+            .map(|handle| handle.and_then(|handle| self.resolver.maybe_eager_reopen(handle)))
+            .collect()
+    }
+
+    /// Identical to [`resolve`], except that handles to the directory prefixes
+    /// of `path` are memoised in `cache` and re-used by later calls.
+    ///
+    /// This is intended for programs that resolve many paths sharing the same
+    /// directory prefixes (such as `a/b/c/file1`, `a/b/c/file2`, ...). Each
+    /// cached prefix is re-validated before it is used (see [`DirCache`] for
+    /// more details), and the remainder of the path is then resolved beneath
+    /// the deepest cached directory. If that lookup fails for any reason
+    /// (including a symlink in the remainder of the path that escapes the
+    /// cached directory), `path` is resolved again from scratch with
+    /// [`resolve`] so that the result (and any errors) are identical to
+    /// [`resolve`].
+    ///
+    /// The cache is only used by the emulated resolver backends. The native
+    /// `openat2(2)` backend resolves the whole path in a single syscall, which
+    /// is cheaper than re-validating the cached prefixes, so this method is
+    /// equivalent to [`resolve`] when `openat2(2)` is in use. The cache is also
+    /// not used for paths containing `..` components, or if
+    /// [`ResolverFlags::NO_XDEV`], [`ResolverFlags::SINGLE_COMPONENT`], a
+    /// [`SetidPolicy`] that restricts intermediate directories, or
+    /// [`Root::set_reject_deleted_intermediates`] is configured.
+    ///
+    /// # Errors
+    ///
+    /// See [`resolve`]. Note that a cached directory could be moved outside of
+    /// the [`RootRef`] after it has been re-validated, in which case the resolved
+    /// path will be beneath the old location of that directory. This is the
+    /// same race as a directory being moved during a regular [`resolve`].
+    ///
+    /// [`resolve`]: Self::resolve
+    pub fn resolve_cached<P: AsRef<Path>>(
+        &self,
+        path: P,
+        cache: &mut DirCache,
+    ) -> Result<Handle, Error> {
+        self.resolve_cached_with_stats(path, cache)
+            .map(|(handle, _)| handle)
+    }
+
+    /// Identical to [`resolve_cached`], except that the number of directory
+    /// prefixes of `path` that were (and were not) served from `cache` is also
+    /// returned.
+    ///
+    /// See [`Root::resolve_cached_with_stats`] for more details.
+    ///
+    /// [`resolve_cached`]: Self::resolve_cached
+    pub fn resolve_cached_with_stats<P: AsRef<Path>>(
+        &self,
+        path: P,
+        cache: &mut DirCache,
+    ) -> Result<(Handle, DirCacheStats), Error> {
+        let path = path.as_ref();
+        let mut stats = DirCacheStats::default();
+        // Fall back to a regular lookup, reporting whatever cache lookups
+        // were done before giving up.
+        let uncached = |stats| self.resolve(path).map(|handle| (handle, stats));
+        if self.resolver.effective_backend() == ResolverBackend::KernelOpenat2
+            || self
+                .resolver
+                .flags
+                .intersects(ResolverFlags::NO_XDEV | ResolverFlags::SINGLE_COMPONENT)
+            || self.resolver.setid_policy.rejects_intermediate()
+            || self.resolver.reject_deleted_intermediates
+        {
+            return uncached(stats);
+        }
+
+        let (stripped, trailing_slash) = utils::path_strip_trailing_slash(path);
+        let mut names = Vec::new();
+        for component in stripped.components() {
+            match component {
+                Component::RootDir | Component::CurDir => {}
+                Component::Normal(name) => names.push(name),
+                // ".." can jump between cached prefixes, so just do a regular
+                // lookup.
+                _ => return uncached(stats),
+            }
+        }
+        // The final component is never cached.
+        let dirs = match names.split_last() {
+            Some((_, dirs)) if !dirs.is_empty() => dirs,
+            _ => return uncached(stats),
+        };
+
+        cache.bind_root(self.inner)?;
+        let mut prefix = PathBuf::new();
+        let mut depth = 0;
+        for name in dirs {
+            let next = prefix.join(name);
+            let lookup = cache.revalidate(self.inner, &prefix, name, &next);
+            stats.record(lookup);
+            if !lookup.is_usable() {
+                break;
+            }
+            prefix = next;
+            depth += 1;
+        }
+        let dir = match cache.entries.get(&prefix) {
+            Some(dir) if depth > 0 => dir,
+            _ => return uncached(stats),
+        };
+
+        let mut suffix: OsString = names[depth..].iter().collect::<PathBuf>().into_os_string();
+        if trailing_slash {
+            suffix.push("/");
+        }
+
+        // Resolve the remainder of the path beneath the cached directory.
+        // Anything that would require leaving the cached directory (such as
+        // absolute symlinks) fails with RESOLVE_BENEATH, and we fall back to a
+        // full lookup.
+        let mut resolver = self.resolver;
+        resolver.flags.insert(ResolverFlags::BENEATH);
+        match resolver.resolve(&dir.handle, self.pivot_scratch, suffix, false) {
+            Ok(handle) => self
+                .resolver
+                .maybe_eager_reopen(handle)
+                .map(|handle| (handle, stats)),
+            Err(_) => uncached(stats),
+        }
+    }
+
+    /// Identical to [`resolve`], except that the [`Metadata`] of the resolved
+    /// inode (including its type) is also returned.
+    ///
+    /// See [`Root::resolve_typed`] for more details.
+    ///
+    /// [`resolve`]: Self::resolve
+    pub fn resolve_typed<P: AsRef<Path>>(&self, path: P) -> Result<(Handle, Metadata), Error> {
+        let handle = self.resolve(path)?;
+        let meta = handle.stat().wrap("stat resolved handle")?;
+        Ok((handle, meta))
+    }
+
+    /// Identical to [`resolve`], except that the `(dev, ino)` of every
+    /// component visited during the resolution is also returned.
+    ///
+    /// See [`Root::resolve_with_inode_trail`] for more details.
+    ///
+    /// [`resolve`]: Self::resolve
+    pub fn resolve_with_inode_trail<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(Handle, Vec<(u64, u64)>), Error> {
+        let (handle, trail) =
+            self.resolver
+                .resolve_with_inode_trail(self, self.pivot_scratch, path, false)?;
+        Ok((self.resolver.maybe_eager_reopen(handle)?, trail))
+    }
+
+    /// Identical to [`resolve`], except that a [`ResolveInfo`] describing how
+    /// the path was resolved is also returned.
+    ///
+    /// See [`Root::resolve_with_info`] for more details.
+    ///
+    /// [`resolve`]: Self::resolve
+    pub fn resolve_with_info<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(Handle, ResolveInfo), Error> {
+        let (handle, info) =
+            self.resolver
+                .resolve_with_info(self, self.pivot_scratch, path, false)?;
+        Ok((self.resolver.maybe_eager_reopen(handle)?, info))
+    }
+
+    /// Identical to [`RootRef::resolve`], except that any error is converted
+    /// using `f`.
+    ///
+    /// See [`Root::resolve_map_err`] for more details.
+    #[inline]
+    pub fn resolve_map_err<P, E, F>(&self, path: P, f: F) -> Result<Handle, E>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(Error) -> E,
+    {
+        self.resolve(path).map_err(f)
+    }
+
+    /// Get a [`RootWithContext`] for this [`RootRef`], which prepends
+    /// `context` to any error returned by operations run with
+    /// [`RootWithContext::run`].
+    ///
+    /// See [`Root::with_error_context`] for more details.
+    #[inline]
+    pub fn with_error_context<'a>(&self, context: &'a str) -> RootWithContext<'a>
+    where
+        'fd: 'a,
+    {
+        RootWithContext {
+            root: *self,
+            context,
+        }
     }
 
     /// Open a path without creating an intermediate [`Handle`] object.
@@ -747,14 +3068,17 @@ impl RootRef<'_> {
         path: P,
         flags: F,
     ) -> Result<File, Error> {
-        self.resolver.open(self, path, flags)
+        self.resolver.open(self, self.pivot_scratch, path, flags)
     }
 
     // Used in operations where we need to get a handle to the parent directory.
     fn resolve_parent<'p>(&self, path: &'p Path) -> Result<(OwnedFd, Option<&'p Path>), Error> {
+        // Only the parent is resolved, so the path as a whole needs to be
+        // checked here.
+        self.resolver.check_path(path)?;
         let (parent, name) = utils::path_split(path).wrap("split path into (parent, name)")?;
         let dir = self
-            .resolve(parent)
+            .resolve_opath(parent, false)
             .wrap("resolve parent directory")?
             .into();
         Ok((dir, name))
@@ -767,22 +3091,230 @@ impl RootRef<'_> {
     /// [`resolve`] instead.
     ///
     /// This method is just shorthand for calling `readlinkat(2)` on the handle
-    /// returned by [`resolve_nofollow`].
+    /// returned by [`resolve_nofollow`]. Symlinks in the intermediate
+    /// components of `path` are resolved as usual, but the final component is
+    /// never followed -- if it is a symlink to another symlink, the target of
+    /// the first symlink is returned.
+    ///
+    /// # Errors
+    ///
+    /// If `path` does not exist, an [`ErrorKind::OsError`] with `ENOENT` is
+    /// returned. If the final component of `path` is not a symlink, an
+    /// [`ErrorKind::OsError`] with `EINVAL` is returned (matching
+    /// `readlink(2)`).
     ///
+    /// [`ErrorKind::OsError`]: crate::error::ErrorKind::OsError
     /// [`resolve`]: Self::resolve
     /// [`resolve_nofollow`]: Self::resolve_nofollow
     #[doc(alias = "pathrs_inroot_readlink")]
     pub fn readlink<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Error> {
         let link = self
-            .resolve_nofollow(path)
+            .resolve_opath(path.as_ref(), true)
             .wrap("resolve symlink O_NOFOLLOW for readlink")?;
-        syscalls::readlinkat(link, "").map_err(|err| {
-            ErrorImpl::RawOsError {
-                operation: "readlink resolve symlink".into(),
+        link.readlink()
+    }
+
+    /// Check whether the calling process can access the inode at `path` within
+    /// the [`RootRef`] with the given `mode`, using [`faccessat2(2)`].
+    ///
+    /// If `flags` contains [`AccessFlags::AT_SYMLINK_NOFOLLOW`], the final
+    /// component of `path` is not followed and the check applies to the
+    /// symlink itself. [`AccessFlags::AT_EACCESS`] causes the check to be done
+    /// using the effective (rather than real) credentials of the caller.
+    ///
+    /// The check is done by the kernel's own permission check rather than by
+    /// comparing the mode bits in userspace, so access granted or denied by
+    /// POSIX ACLs (or by filesystems with their own permission model, such as
+    /// NFSv4 ACLs) is reflected correctly.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Ok(false)` if access was denied with `EACCES`. All other
+    /// failures (including `path` not existing) are returned as errors.
+    ///
+    /// [`faccessat2(2)`]: http://man7.org/linux/man-pages/man2/access.2.html
+    #[doc(alias = "pathrs_inroot_access")]
+    pub fn access<P: AsRef<Path>>(
+        &self,
+        path: P,
+        mode: AccessMode,
+        flags: AccessFlags,
+    ) -> Result<bool, Error> {
+        let path = path.as_ref();
+        let res = if flags.contains(AccessFlags::AT_SYMLINK_NOFOLLOW) {
+            // faccessat2(2) will not follow the final component, so we can
+            // safely pass it to the kernel relative to the parent directory.
+            let (dir, name) = self
+                .resolve_parent(path)
+                .wrap("resolve access path parent")?;
+            syscalls::faccessat2(dir, name.unwrap_or_else(|| Path::new("")), mode, flags)
+        } else {
+            // We cannot let the kernel follow the final component (it might be
+            // a symlink to outside the root), so resolve the full path and
+            // check the handle itself with AT_EMPTY_PATH.
+            let handle = self
+                .resolve_opath(path, false)
+                .wrap("resolve access path")?;
+            syscalls::faccessat2(handle, "", mode, flags)
+        };
+
+        match res {
+            Ok(()) => Ok(true),
+            Err(err) if err.errno() == Errno::ACCESS => Ok(false),
+            Err(err) => Err(ErrorImpl::RawOsError {
+                operation: "pathrs access".into(),
                 source: err,
             }
-            .into()
-        })
+            .into()),
+        }
+    }
+
+    /// Compute the relative path from the directory `from` to `to`, where both
+    /// are handles to inodes inside the [`RootRef`]'s tree.
+    ///
+    /// This is primarily intended for generating relative symlink targets
+    /// within the [`RootRef`], such that the symlink `from/<relative_path>` will
+    /// resolve to `to`. `..` components are inserted as necessary.
+    ///
+    /// # Caveats
+    ///
+    /// The paths of `from` and `to` (and the [`RootRef`] itself) are obtained using
+    /// `readlink(/proc/thread-self/fd/$n)`, and so this method is naturally
+    /// racy. If any of the inodes are renamed after they were resolved, the
+    /// current path of the inode will be used (which may not be the path used
+    /// to resolve the handle). If any of the inodes have been deleted, the
+    /// path returned by the kernel is no longer meaningful and thus this
+    /// method may return an error or a nonsensical result. The returned path
+    /// is computed purely lexically and does not take into account any
+    /// symlinks inside the [`RootRef`].
+    ///
+    /// # Errors
+    ///
+    /// If either `from` or `to` are not inside the [`RootRef`]'s tree, an error is
+    /// returned.
+    pub fn relative_path(&self, from: &Handle, to: &Handle) -> Result<PathBuf, Error> {
+        let root_path = self
+            .as_unsafe_path(&GLOBAL_PROCFS_HANDLE)
+            .wrap("get root path")?;
+        let subpath = |handle: &Handle, name: &str| -> Result<PathBuf, Error> {
+            let path = handle
+                .as_unsafe_path(&GLOBAL_PROCFS_HANDLE)
+                .with_wrap(|| format!("get {name} handle path"))?;
+            path.strip_prefix(&root_path)
+                .map(PathBuf::from)
+                .map_err(|_| {
+                    ErrorImpl::SafetyViolation {
+                        description: format!(
+                            "{name} handle path {path:?} is not inside root {root_path:?}"
+                        )
+                        .into(),
+                    }
+                    .into()
+                })
+        };
+
+        Ok(utils::path_relative_to(
+            &subpath(from, "from")?,
+            &subpath(to, "to")?,
+        ))
+    }
+
+    /// Get the list of mounts at or below this [`RootRef`], as paths relative
+    /// to the [`RootRef`].
+    ///
+    /// See [`Root::submounts`] for more details.
+    pub fn submounts(&self) -> Result<Vec<PathBuf>, Error> {
+        utils::submounts(self)
+    }
+
+    /// Within the given [`RootRef`]'s tree, resolve `path` and return a
+    /// [`FileHandle`] referencing the inode.
+    ///
+    /// This is similar to [`resolve`], except that the returned [`FileHandle`]
+    /// does not hold a file descriptor (the file descriptor used during
+    /// resolution is closed before returning) and so it does not keep the
+    /// mount containing the inode busy. This allows you to store references to
+    /// inodes without blocking the filesystem from being unmounted.
+    ///
+    /// The [`FileHandle`] can be converted back into a [`Handle`] with
+    /// [`open_unpinned`].
+    ///
+    /// # Errors
+    ///
+    /// Not all filesystems support file handles, in which case an
+    /// `EOPNOTSUPP` error will be returned.
+    ///
+    /// [`resolve`]: Self::resolve
+    /// [`open_unpinned`]: Self::open_unpinned
+    pub fn resolve_unpinned<P: AsRef<Path>>(&self, path: P) -> Result<FileHandle, Error> {
+        let handle = self
+            .resolve_opath(path, false)
+            .wrap("resolve path for file handle")?;
+        let (handle_type, data) = syscalls::name_to_handle_at(&handle, "", AtFlags::EMPTY_PATH)
+            .map_err(|err| ErrorImpl::RawOsError {
+                operation: "get file handle for resolved path".into(),
+                source: err,
+            })?;
+        Ok(FileHandle { handle_type, data })
+    }
+
+    /// Convert a [`FileHandle`] (returned by [`resolve_unpinned`]) back into a
+    /// [`Handle`].
+    ///
+    /// The [`RootRef`] must be on the same filesystem as the inode referenced by
+    /// `handle`. Because the inode may have been moved since
+    /// [`resolve_unpinned`] was called, the path of the new [`Handle`] is
+    /// checked to ensure that it is still inside the [`RootRef`]'s tree.
+    ///
+    /// # Errors
+    ///
+    /// `open_by_handle_at(2)` requires `CAP_DAC_READ_SEARCH`, and so this
+    /// method will fail with `EPERM` for unprivileged processes. If the inode
+    /// has been deleted, `ESTALE` is returned. If the inode is no longer inside
+    /// the [`RootRef`]'s tree (or the kernel cannot tell us where it is, which can
+    /// happen for non-directory inodes that are not in the dentry cache), a
+    /// `SafetyViolation` error is returned.
+    ///
+    /// [`resolve_unpinned`]: Self::resolve_unpinned
+    pub fn open_unpinned(&self, handle: &FileHandle) -> Result<Handle, Error> {
+        // open_by_handle_at(2) does not accept O_PATH file descriptors as the
+        // mount_fd argument, so we need to re-open the root.
+        let mount_fd = self
+            .reopen(
+                &GLOBAL_PROCFS_HANDLE,
+                OpenFlags::O_RDONLY | OpenFlags::O_DIRECTORY,
+            )
+            .wrap("re-open root as mount_fd for file handle")?;
+        let fd = syscalls::open_by_handle_at(
+            mount_fd,
+            handle.handle_type,
+            &handle.data,
+            OpenFlags::O_PATH,
+        )
+        .map_err(|err| ErrorImpl::RawOsError {
+            operation: "open file handle".into(),
+            source: err,
+        })?;
+
+        // The inode might have been moved outside of the root since we got the
+        // file handle (or it might have always been outside the root if the
+        // handle was forged), so make sure it is still inside the root.
+        // SAFETY: as_unsafe_path is only used for a string-based check here,
+        //         and the result is not used for any further lookups.
+        let root_path = self
+            .as_unsafe_path(&GLOBAL_PROCFS_HANDLE)
+            .wrap("get root path")?;
+        let path = fd
+            .as_unsafe_path(&GLOBAL_PROCFS_HANDLE)
+            .wrap("get opened file handle path")?;
+        if !path.starts_with(&root_path) {
+            Err(ErrorImpl::SafetyViolation {
+                description: format!("file handle path {path:?} is not inside root {root_path:?}")
+                    .into(),
+            })?
+        }
+
+        Ok(Handle::from_fd(fd))
     }
 
     /// Within the [`RootRef`]'s tree, create an inode at `path` as specified by
@@ -881,13 +3413,106 @@ impl RootRef<'_> {
     pub fn create_file<P: AsRef<Path>>(
         &self,
         path: P,
-        mut flags: OpenFlags,
+        flags: OpenFlags,
         perm: &Permissions,
     ) -> Result<File, Error> {
+        self.do_create_file(path.as_ref(), flags, perm.mode())
+    }
+
+    /// Within the [`RootRef`]'s tree, create a regular file at `path` or update
+    /// the times of the existing inode at `path`, and return a [`Handle`] to it.
+    ///
+    /// See [`Root::touch`] for more details.
+    pub fn touch<P: AsRef<Path>>(&self, path: P, mode: u32) -> Result<Handle, Error> {
+        if mode & !0o7777 != 0 {
+            Err(ErrorImpl::InvalidArgument {
+                name: "mode".into(),
+                description: "mode cannot contain non-0o7777 bits".into(),
+            })?
+        }
+
+        let (dir, name) = self
+            .resolve_parent(path.as_ref())
+            .wrap("resolve touch path")?;
+        let name = name.ok_or_else(|| ErrorImpl::InvalidArgument {
+            name: "path".into(),
+            description: "touch path has trailing slash".into(),
+        })?;
+
+        // Try to create the file first. A newly-created file already has the
+        // current time as its timestamps, so there is nothing left to do.
+        match syscalls::openat(
+            &dir,
+            name,
+            OpenFlags::O_CREAT | OpenFlags::O_EXCL | OpenFlags::O_RDONLY,
+            mode,
+        ) {
+            Ok(file) => {
+                // Convert the newly-created file into an O_PATH handle. Going
+                // through procfs guarantees we get the same inode we just
+                // created.
+                return file
+                    .reopen(&GLOBAL_PROCFS_HANDLE, OpenFlags::O_PATH)
+                    .map(Handle::from_fd);
+            }
+            // With O_EXCL, the kernel returns EEXIST for any existing inode
+            // (including dangling symlinks), which we update below.
+            Err(err) if err.root_cause().raw_os_error() == Some(libc::EEXIST) => {}
+            Err(err) => Err(ErrorImpl::RawOsError {
+                operation: "pathrs touch create".into(),
+                source: err,
+            })?,
+        }
+
+        // Get a handle to the existing inode. syscalls::openat sets O_NOFOLLOW,
+        // so for symlinks we get a handle to the symlink itself.
+        let handle = syscalls::openat(&dir, name, OpenFlags::O_PATH, 0)
+            .map(Handle::from_fd)
+            .map_err(|err| ErrorImpl::RawOsError {
+                operation: "open existing touch target".into(),
+                source: err,
+            })?;
+
+        match syscalls::utimensat_now(&handle, "") {
+            // Pre-5.8 kernels do not support AT_EMPTY_PATH for utimensat(2),
+            // so we have to update the inode by name instead.
+            Err(err) if err.root_cause().raw_os_error() == Some(libc::EINVAL) => {
+                syscalls::utimensat_now(&dir, name)
+            }
+            res => res,
+        }
+        .map_err(|err| ErrorImpl::RawOsError {
+            operation: "pathrs touch update times".into(),
+            source: err,
+        })?;
+
+        Ok(handle)
+    }
+
+    /// Within the [`RootRef`]'s tree, create a regular file at `path` with
+    /// the given `flags` and creation `mode`, and return the opened [`File`].
+    ///
+    /// See [`Root::create_with`] for more details.
+    pub fn create_with<P: AsRef<Path>>(
+        &self,
+        path: P,
+        flags: OpenFlags,
+        mode: u32,
+    ) -> Result<File, Error> {
+        if mode & !0o7777 != 0 {
+            Err(ErrorImpl::InvalidArgument {
+                name: "mode".into(),
+                description: "mode cannot contain non-0o7777 bits".into(),
+            })?
+        }
+        self.do_create_file(path.as_ref(), flags, mode)
+    }
+
+    fn do_create_file(&self, path: &Path, mut flags: OpenFlags, mode: u32) -> Result<File, Error> {
         // The path doesn't exist yet, so we need to get a safe reference to the
         // parent and just operate on the final (slashless) component.
         let (dir, name) = self
-            .resolve_parent(path.as_ref())
+            .resolve_parent(path)
             .wrap("resolve file creation path")?;
         let name = name.ok_or_else(|| ErrorImpl::InvalidArgument {
             name: "path".into(),
@@ -898,14 +3523,154 @@ impl RootRef<'_> {
         // O_NOFOLLOW. We might want to expose that here, though because it
         // can't be done with the emulated backend that might be a bad idea.
         flags.insert(OpenFlags::O_CREAT);
-        let fd = syscalls::openat(dir, name, flags, perm.mode()).map_err(|err| {
+        let fd = match syscalls::openat(&dir, name, flags, mode) {
+            Ok(fd) => fd,
+            Err(err) => {
+                // Without O_EXCL, the kernel would create the target of a
+                // dangling symlink. We never follow the final component, so
+                // make it clear why the creation failed.
+                let trailing_symlink = err.root_cause().raw_os_error() == Some(libc::ELOOP)
+                    && syscalls::fstatat(&dir, name)
+                        .map_or(false, |st| st.st_mode & libc::S_IFMT == libc::S_IFLNK);
+                let err = Err(ErrorImpl::RawOsError {
+                    operation: "pathrs create_file".into(),
+                    source: err,
+                });
+                if trailing_symlink {
+                    err.wrap("file creation path has a trailing symlink, which is never followed")?
+                } else {
+                    err?
+                }
+            }
+        };
+
+        Ok(fd.into())
+    }
+
+    /// Within the [`RootRef`]'s tree, create a new regular file at `path` with
+    /// the given `mode`, but only if `predicate` returns `true` for the parent
+    /// directory of `path`.
+    ///
+    /// See [`Root::create_if`] for more details.
+    pub fn create_if<P, F>(&self, path: P, mode: u32, predicate: F) -> Result<Option<Handle>, Error>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&Handle) -> bool,
+    {
+        if mode & !0o7777 != 0 {
+            Err(ErrorImpl::InvalidArgument {
+                name: "mode".into(),
+                description: "mode cannot contain non-0o7777 bits".into(),
+            })?
+        }
+
+        let (dir, name) = self
+            .resolve_parent(path.as_ref())
+            .wrap("resolve file creation path")?;
+        let name = name.ok_or_else(|| ErrorImpl::InvalidArgument {
+            name: "path".into(),
+            description: "file creation path has trailing slash".into(),
+        })?;
+
+        let parent = Handle::from_fd(dir);
+        if !predicate(&parent) {
+            return Ok(None);
+        }
+
+        let file = syscalls::openat(
+            &parent,
+            name,
+            OpenFlags::O_CREAT | OpenFlags::O_EXCL | OpenFlags::O_NOFOLLOW | OpenFlags::O_RDONLY,
+            mode,
+        )
+        .map_err(|err| ErrorImpl::RawOsError {
+            operation: "pathrs create_if".into(),
+            source: err,
+        })?;
+
+        // Convert the newly-created file into an O_PATH handle. Going through
+        // procfs guarantees we get the same inode we just created.
+        file.reopen(&GLOBAL_PROCFS_HANDLE, OpenFlags::O_PATH)
+            .map(Handle::from_fd)
+            .map(Some)
+    }
+
+    /// Within the [`RootRef`]'s tree, create an inode at `path` with
+    /// [`mknodat(2)`] and return a [`Handle`] to the new inode.
+    ///
+    /// See [`Root::mknod`] for more details.
+    ///
+    /// [`mknodat(2)`]: http://man7.org/linux/man-pages/man2/mknodat.2.html
+    pub fn mknod<P: AsRef<Path>>(&self, path: P, mode: u32, dev: u64) -> Result<Handle, Error> {
+        let file_type = match mode & libc::S_IFMT {
+            0 => libc::S_IFREG,
+            ty @ (libc::S_IFREG
+            | libc::S_IFIFO
+            | libc::S_IFSOCK
+            | libc::S_IFCHR
+            | libc::S_IFBLK) => ty,
+            ty => Err(ErrorImpl::InvalidArgument {
+                name: "mode".into(),
+                description: format!("unsupported inode type {ty:#o}").into(),
+            })?,
+        };
+        if mode & !(libc::S_IFMT | 0o7777) != 0 {
+            Err(ErrorImpl::InvalidArgument {
+                name: "mode".into(),
+                description: "mode cannot contain non-0o7777 bits".into(),
+            })?
+        }
+
+        let (dir, name) = self
+            .resolve_parent(path.as_ref())
+            .wrap("resolve mknod path")?;
+        let name = name.ok_or_else(|| ErrorImpl::InvalidArgument {
+            name: "path".into(),
+            description: "mknod path has trailing slash".into(),
+        })?;
+        if matches!(name.as_os_str().as_bytes(), b"." | b"..") {
+            Err(ErrorImpl::InvalidArgument {
+                name: "path".into(),
+                description: "mknod path cannot end with '.' or '..'".into(),
+            })?
+        }
+
+        syscalls::mknodat(&dir, name, file_type | (mode & 0o7777), dev).map_err(|err| {
             ErrorImpl::RawOsError {
-                operation: "pathrs create_file".into(),
+                operation: "pathrs mknod".into(),
                 source: err,
             }
         })?;
 
-        Ok(fd.into())
+        // There is no O_CREAT-equivalent for mknodat(2), so the best we can do
+        // is open the new inode (without following it) and make sure it has the
+        // type we asked for.
+        let handle = syscalls::openat(&dir, name, OpenFlags::O_PATH | OpenFlags::O_NOFOLLOW, 0)
+            .map(Handle::from_fd)
+            .map_err(|err| ErrorImpl::RawOsError {
+                operation: "open created inode".into(),
+                source: err,
+            })?;
+        let got_type = handle.metadata().wrap("fstat created inode")?.mode() & libc::S_IFMT;
+        if got_type != file_type {
+            Err(ErrorImpl::SafetyViolation {
+                description: format!(
+                    "created inode has type {got_type:#o} rather than {file_type:#o}"
+                )
+                .into(),
+            })?
+        }
+        Ok(handle)
+    }
+
+    /// Within the [`RootRef`]'s tree, create an overlayfs whiteout at `path`.
+    ///
+    /// See [`Root::create_whiteout`] for more details.
+    pub fn create_whiteout<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        // overlayfs whiteouts are 0:0 character devices with no permission
+        // bits (see WHITEOUT_DEV and WHITEOUT_MODE in the kernel).
+        self.mknod(path, libc::S_IFCHR, 0).wrap("create whiteout")?;
+        Ok(())
     }
 
     /// Within the [`RootRef`]'s tree, create a directory and any of its parent
@@ -959,7 +3724,7 @@ impl RootRef<'_> {
 
         let (handle, remaining) = self
             .resolver
-            .resolve_partial(self, path.as_ref(), false)
+            .resolve_partial(self, self.pivot_scratch, path.as_ref(), false)
             .and_then(TryInto::try_into)?;
 
         // Re-open the handle with O_DIRECTORY to make sure it's a directory we
@@ -1178,22 +3943,70 @@ impl RootRef<'_> {
         utils::remove_all(&dir, name)
     }
 
+    /// Within the [`RootRef`]'s tree, recursively set the mode of `path` and
+    /// everything it contains, using `dir_mode` for directories and
+    /// `file_mode` for regular files.
+    ///
+    /// See [`Root::chmod_all`] for more details.
+    pub fn chmod_all<P: AsRef<Path>>(
+        &self,
+        path: P,
+        dir_mode: u32,
+        file_mode: u32,
+    ) -> Result<(), Error> {
+        let handle = self
+            .resolve_opath(path.as_ref(), false)
+            .wrap("resolve chmod-all path")?;
+        utils::chmod_all(handle, dir_mode, file_mode)
+    }
+
+    /// Within the [`RootRef`]'s tree, compute a digest of the structure and
+    /// metadata of the subtree at `path`.
+    ///
+    /// See [`Root::tree_digest`] for more details.
+    #[cfg(feature = "tree-digest")]
+    pub fn tree_digest<P: AsRef<Path>>(
+        &self,
+        path: P,
+        include_content: bool,
+    ) -> Result<[u8; 32], Error> {
+        let handle = self
+            .resolve_opath(path.as_ref(), true)
+            .wrap("resolve tree-digest path")?;
+        utils::tree_digest(handle, include_content)
+    }
+
     /// Within the [`RootRef`]'s tree, perform a rename with the given `source`
     /// and `directory`. The `flags` argument is passed directly to
     /// [`renameat2(2)`].
     ///
+    /// See [`Root::rename`] for more details.
+    ///
     /// # Errors
     ///
     /// The error rules are identical to [`renameat2(2)`].
     ///
     /// [`renameat2(2)`]: http://man7.org/linux/man-pages/man2/renameat2.2.html
     #[doc(alias = "pathrs_inroot_rename")]
-    pub fn rename<P: AsRef<Path>>(
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(
         &self,
         source: P,
-        destination: P,
+        destination: Q,
         rflags: RenameFlags,
     ) -> Result<(), Error> {
+        // The kernel would give us a bare EINVAL for this, which is hard to
+        // tell apart from the other reasons renameat2(2) returns EINVAL.
+        if rflags.contains(RenameFlags::RENAME_EXCHANGE)
+            && rflags.intersects(RenameFlags::RENAME_NOREPLACE | RenameFlags::RENAME_WHITEOUT)
+        {
+            Err(ErrorImpl::InvalidArgument {
+                name: "rflags".into(),
+                description:
+                    "RENAME_EXCHANGE cannot be combined with RENAME_NOREPLACE or RENAME_WHITEOUT"
+                        .into(),
+            })?
+        }
+
         // renameat2(2) doesn't let us rename paths using just handles. In
         // addition, the target path might not exist (except in the case of
         // RENAME_EXCHANGE and clobbering).
@@ -1212,13 +4025,405 @@ impl RootRef<'_> {
             description: "rename destination path has trailing slash".into(),
         })?;
 
-        syscalls::renameat2(src_dir, src_name, dst_dir, dst_name, rflags).map_err(|err| {
-            ErrorImpl::RawOsError {
-                operation: "pathrs rename".into(),
-                source: err,
-            }
-            .into()
-        })
+        if !rflags.is_supported() {
+            if rflags == RenameFlags::RENAME_NOREPLACE {
+                return rename_noreplace_emulated(&src_dir, src_name, &dst_dir, dst_name);
+            }
+            Err(ErrorImpl::NotSupported {
+                feature: format!("renameat2 flags {rflags:?}").into(),
+            })?
+        }
+
+        syscalls::renameat2(src_dir, src_name, dst_dir, dst_name, rflags).map_err(|err| {
+            ErrorImpl::RawOsError {
+                operation: "pathrs rename".into(),
+                source: err,
+            }
+            .into()
+        })
+    }
+
+    /// Within the [`RootRef`]'s tree, rename `from` to `to` and then call
+    /// `on_moved` with the old and new paths of the moved inode.
+    ///
+    /// See [`Root::rename_with_callback`] for more details.
+    pub fn rename_with_callback<P, Q, F>(&self, from: P, to: Q, on_moved: F) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        F: FnMut(&Path, &Path),
+    {
+        self.do_rename_with_callback(from.as_ref(), to.as_ref(), false, on_moved)
+    }
+
+    /// Identical to [`RootRef::rename_with_callback`], except that if `from`
+    /// is a directory then `on_moved` is also called for every inode inside
+    /// it.
+    ///
+    /// See [`Root::rename_with_callback_deep`] for more details.
+    pub fn rename_with_callback_deep<P, Q, F>(
+        &self,
+        from: P,
+        to: Q,
+        on_moved: F,
+    ) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+        F: FnMut(&Path, &Path),
+    {
+        self.do_rename_with_callback(from.as_ref(), to.as_ref(), true, on_moved)
+    }
+
+    fn do_rename_with_callback<F>(
+        &self,
+        from: &Path,
+        to: &Path,
+        deep: bool,
+        mut on_moved: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&Path, &Path),
+    {
+        // The subtree has to be enumerated before the rename, since we cannot
+        // safely resolve the old paths afterwards.
+        let descendants = if deep {
+            let handle = self
+                .resolve_opath(from, true)
+                .wrap("resolve rename source path")?;
+            let mode = handle.metadata().wrap("fstat rename source")?.mode();
+            if mode & libc::S_IFMT == libc::S_IFDIR {
+                utils::list_tree(&handle).wrap("enumerate rename source subtree")?
+            } else {
+                vec![]
+            }
+        } else {
+            vec![]
+        };
+
+        self.rename(from, to, RenameFlags::empty())?;
+
+        on_moved(from, to);
+        for subpath in descendants {
+            on_moved(&from.join(&subpath), &to.join(&subpath));
+        }
+        Ok(())
+    }
+
+    /// Within the [`RootRef`]'s tree, create a new hard-link at `link` to the
+    /// existing inode at `target`, as in [`linkat(2)`].
+    ///
+    /// See [`Root::hardlink`] for more details.
+    ///
+    /// [`linkat(2)`]: http://man7.org/linux/man-pages/man2/linkat.2.html
+    pub fn hardlink<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        target: P,
+        link: Q,
+    ) -> Result<(), Error> {
+        let target = self
+            .resolve_opath(target.as_ref(), true)
+            .wrap("resolve hardlink target")?;
+        self.hardlink_handle(target, link.as_ref())
+    }
+
+    /// Identical to [`RootRef::hardlink`], except that if `target` is a
+    /// symlink it is followed (within the [`RootRef`]).
+    ///
+    /// See [`Root::hardlink_follow`] for more details.
+    pub fn hardlink_follow<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        target: P,
+        link: Q,
+    ) -> Result<(), Error> {
+        let target = self
+            .resolve_opath(target.as_ref(), false)
+            .wrap("resolve hardlink target")?;
+        self.hardlink_handle(target, link.as_ref())
+    }
+
+    fn hardlink_handle(&self, target: Handle, link: &Path) -> Result<(), Error> {
+        let (dir, name) = self.resolve_parent(link).wrap("resolve hardlink path")?;
+        let name = name.ok_or_else(|| ErrorImpl::InvalidArgument {
+            name: "link".into(),
+            description: "hardlink path has trailing slash".into(),
+        })?;
+
+        // linkat(2) would give us EXDEV anyway, but it's nicer to give a
+        // clearer error (and avoid going through procfs needlessly).
+        let target_dev = target.metadata().wrap("fstat hardlink target")?.dev();
+        let dir_dev = dir
+            .metadata()
+            .wrap("fstat hardlink parent directory")?
+            .dev();
+        if target_dev != dir_dev {
+            Err(ErrorImpl::OsError {
+                operation: "pathrs hardlink".into(),
+                source: IOError::from_raw_os_error(libc::EXDEV),
+            })
+            .wrap("hardlink target and link are on different devices")?
+        }
+
+        match syscalls::linkat(&target, "", &dir, name, AtFlags::EMPTY_PATH) {
+            Ok(()) => return Ok(()),
+            // Without CAP_DAC_READ_SEARCH, AT_EMPTY_PATH gives us ENOENT.
+            Err(err) if err.root_cause().raw_os_error() == Some(libc::ENOENT) => (),
+            Err(err) => Err(ErrorImpl::RawOsError {
+                operation: "pathrs hardlink".into(),
+                source: err,
+            })?,
+        }
+
+        // Fall back to linking through the /proc/thread-self/fd/$n
+        // magic-link, which does not require any special privileges.
+        hardlink_procfs(&target, &dir, name)
+    }
+
+    /// Within the [`RootRef`]'s tree, atomically replace the file at `path`
+    /// with a new file containing `contents` with the mode given by `perm`,
+    /// and return a [`Handle`] to the new file.
+    ///
+    /// See [`Root::replace_executable`] for more details.
+    pub fn replace_executable<P: AsRef<Path>, R: Read>(
+        &self,
+        path: P,
+        mut contents: R,
+        perm: &Permissions,
+    ) -> Result<Handle, Error> {
+        let (dir, name) = self
+            .resolve_parent(path.as_ref())
+            .wrap("resolve executable replacement path")?;
+        let name = name.ok_or_else(|| ErrorImpl::InvalidArgument {
+            name: "path".into(),
+            description: "executable replacement path has trailing slash".into(),
+        })?;
+
+        // Create a new temporary file next to the target. We can't use
+        // O_TMPFILE because linking it into the filesystem requires either
+        // CAP_DAC_READ_SEARCH or going through procfs.
+        let mut tmp = None;
+        for attempt in 0..16 {
+            let mut tmp_name = OsString::from(".");
+            tmp_name.push(name);
+            tmp_name.push(format!(".pathrs-tmp-{}-{attempt}", std::process::id()));
+            match syscalls::openat(
+                &dir,
+                &tmp_name,
+                OpenFlags::O_CREAT | OpenFlags::O_EXCL | OpenFlags::O_WRONLY,
+                perm.mode(),
+            ) {
+                Ok(fd) => {
+                    tmp = Some((tmp_name, File::from(fd)));
+                    break;
+                }
+                Err(err) if err.root_cause().raw_os_error() == Some(libc::EEXIST) => continue,
+                Err(err) => Err(ErrorImpl::RawOsError {
+                    operation: "create temporary replacement file".into(),
+                    source: err,
+                })?,
+            }
+        }
+        let (tmp_name, mut tmp_file) = tmp.ok_or_else(|| ErrorImpl::OsError {
+            operation: "create temporary replacement file".into(),
+            source: IOError::from_raw_os_error(libc::EEXIST),
+        })?;
+
+        let ret = (|| -> Result<Handle, Error> {
+            std::io::copy(&mut contents, &mut tmp_file).map_err(|err| ErrorImpl::OsError {
+                operation: "write replacement contents".into(),
+                source: err,
+            })?;
+            // Make sure the mode is not affected by the umask.
+            tmp_file
+                .set_permissions(perm.clone())
+                .map_err(|err| ErrorImpl::OsError {
+                    operation: "set replacement file mode".into(),
+                    source: err,
+                })?;
+            tmp_file.sync_all().map_err(|err| ErrorImpl::OsError {
+                operation: "sync replacement file".into(),
+                source: err,
+            })?;
+            let handle = tmp_file
+                .reopen(&GLOBAL_PROCFS_HANDLE, OpenFlags::O_PATH)
+                .map(Handle::from_fd)?;
+
+            syscalls::renameat(&dir, &tmp_name, &dir, name).map_err(|err| {
+                ErrorImpl::RawOsError {
+                    operation: "rename replacement over target".into(),
+                    source: err,
+                }
+            })?;
+            Ok(handle)
+        })();
+
+        if ret.is_err() {
+            // Clean up the temporary file. We are already returning an error,
+            // so there's nothing useful to do if this fails.
+            let _ = syscalls::unlinkat(&dir, &tmp_name, AtFlags::empty());
+        }
+        ret
+    }
+
+    /// Within the [`RootRef`]'s tree, open all of the entries in the directory
+    /// `dir` whose names match the shell-style glob `pattern`, returning
+    /// [`Handle`]s to the matching entries sorted by name.
+    ///
+    /// See [`Root::glob_in`] for more details.
+    pub fn glob_in<P: AsRef<Path>>(&self, dir: P, pattern: &str) -> Result<Vec<Handle>, Error> {
+        if pattern.is_empty() || pattern.contains('/') {
+            Err(ErrorImpl::InvalidArgument {
+                name: "pattern".into(),
+                description: "glob pattern must be a single non-empty path component".into(),
+            })?
+        }
+
+        let dir = self
+            .resolve_opath(dir.as_ref(), false)
+            .wrap("resolve glob directory")?;
+        let dirfd = dir
+            .reopen(OpenFlags::O_DIRECTORY)
+            .wrap("open glob directory to scan entries")?;
+
+        let mut names = Vec::new();
+        // TODO: Dir creates a new file descriptor rather than re-using the one
+        //       we have, but this is okay for now...
+        for dentry in Dir::read_from(&dirfd).map_err(|err| ErrorImpl::OsError {
+            operation: "create directory iterator".into(),
+            source: err.into(),
+        })? {
+            let dentry = dentry.map_err(|err| ErrorImpl::OsError {
+                operation: "scan glob directory".into(),
+                source: err.into(),
+            })?;
+            let name = dentry.file_name().to_bytes();
+            if !matches!(name, b"." | b"..") && utils::glob_match(pattern.as_bytes(), name) {
+                names.push(OsStr::from_bytes(name).to_os_string());
+            }
+        }
+        names.sort();
+
+        let mut handles = Vec::with_capacity(names.len());
+        for name in names {
+            match syscalls::openat(&dirfd, &name, OpenFlags::O_PATH | OpenFlags::O_NOFOLLOW, 0) {
+                Ok(fd) => handles.push(Handle::from_fd(fd)),
+                // The entry was removed after we scanned the directory.
+                Err(err) if err.root_cause().raw_os_error() == Some(libc::ENOENT) => continue,
+                Err(err) => Err(ErrorImpl::RawOsError {
+                    operation: format!("open glob match {name:?}").into(),
+                    source: err,
+                })?,
+            }
+        }
+        Ok(handles)
+    }
+}
+
+/// Emulate `renameat2(RENAME_NOREPLACE)` on kernels without `renameat2(2)` by
+/// checking whether the destination exists before doing a plain `renameat(2)`.
+///
+/// This is inherently racy, since the destination could be created between the
+/// check and the rename.
+fn rename_noreplace_emulated<Fd1: AsFd, Fd2: AsFd>(
+    src_dir: Fd1,
+    src_name: &Path,
+    dst_dir: Fd2,
+    dst_name: &Path,
+) -> Result<(), Error> {
+    match syscalls::fstatat(&dst_dir, dst_name) {
+        Ok(_) => Err(ErrorImpl::OsError {
+            operation: "emulated RENAME_NOREPLACE".into(),
+            source: IOError::from_raw_os_error(libc::EEXIST),
+        })?,
+        Err(err) if err.root_cause().raw_os_error() == Some(libc::ENOENT) => (),
+        Err(err) => Err(ErrorImpl::RawOsError {
+            operation: "check rename destination".into(),
+            source: err,
+        })?,
+    }
+    syscalls::renameat(src_dir, src_name, dst_dir, dst_name).map_err(|err| {
+        ErrorImpl::RawOsError {
+            operation: "pathrs rename".into(),
+            source: err,
+        }
+        .into()
+    })
+}
+
+/// Create a hard-link to the inode referenced by `target` (which may be an
+/// `O_PATH` descriptor) through its `/proc/thread-self/fd/$n` magic-link.
+/// Unlike `AT_EMPTY_PATH`, this does not require `CAP_DAC_READ_SEARCH`.
+fn hardlink_procfs<Fd1: AsFd, Fd2: AsFd>(target: Fd1, dir: Fd2, name: &Path) -> Result<(), Error> {
+    let fd_dir = GLOBAL_PROCFS_HANDLE
+        .open(
+            ProcfsBase::ProcThreadSelf,
+            "fd",
+            OpenFlags::O_PATH | OpenFlags::O_DIRECTORY,
+        )
+        .wrap("open procfs fd directory")?;
+    syscalls::linkat(
+        &fd_dir,
+        target.as_fd().as_raw_fd().to_string(),
+        dir,
+        name,
+        AtFlags::SYMLINK_FOLLOW,
+    )
+    .map_err(|err| {
+        ErrorImpl::RawOsError {
+            operation: "pathrs hardlink".into(),
+            source: err,
+        }
+        .into()
+    })
+}
+
+/// A [`RootRef`] paired with a context string which is prepended to any error
+/// returned by operations run through it, created with
+/// [`Root::with_error_context`] or [`RootRef::with_error_context`].
+///
+/// This is intended for libraries wrapping libpathrs that want errors to
+/// describe what they were doing at a higher level, such as
+/// `"while loading config: <pathrs error>"`. Operations are run with
+/// [`RootWithContext::run`], which passes the underlying [`RootRef`] to a
+/// closure and adds the context to any error it returns.
+#[derive(Copy, Clone, Debug)]
+pub struct RootWithContext<'a> {
+    root: RootRef<'a>,
+    context: &'a str,
+}
+
+impl<'a> RootWithContext<'a> {
+    /// Get the underlying [`RootRef`] (without the error context).
+    #[inline]
+    pub fn as_root_ref(&self) -> RootRef<'a> {
+        self.root
+    }
+
+    /// Get the error context string.
+    #[inline]
+    pub fn error_context(&self) -> &'a str {
+        self.context
+    }
+
+    /// Run `f` with the underlying [`RootRef`], prepending the context string
+    /// to any error it returns.
+    ///
+    /// ```
+    /// # use pathrs::Root;
+    /// let root = Root::open("/")?;
+    /// let ctx = root.with_error_context("while loading config");
+    /// let err = ctx
+    ///     .run(|root| root.resolve("/nonexistent/config.toml"))
+    ///     .unwrap_err();
+    /// assert!(err.to_string().starts_with("while loading config: "));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    #[inline]
+    pub fn run<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(RootRef<'a>) -> Result<T, Error>,
+    {
+        f(self.root).map_err(|err| err.with_context(self.context.to_owned()))
     }
 }
 
@@ -1244,12 +4449,22 @@ impl AsFd for RootRef<'_> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{resolvers::ResolverBackend, Root, RootRef};
+    use crate::{
+        error::ErrorKind, flags::OpenFlags, resolvers::ResolverBackend, syscalls,
+        tests::common as tests_common, utils::FdExt, Handle, Root, RootRef,
+    };
 
-    use std::os::unix::io::{AsFd, AsRawFd};
+    use std::{
+        fs::File,
+        os::unix::{
+            fs::MetadataExt,
+            io::{AsFd, AsRawFd, OwnedFd},
+        },
+        path::{Path, PathBuf},
+    };
 
     use anyhow::Error;
-    use pretty_assertions::assert_eq;
+    use pretty_assertions::{assert_eq, assert_ne};
 
     impl Root {
         // TODO: Should we make this public? Is there any real benefit?
@@ -1314,4 +4529,893 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn from_fd_checked() -> Result<(), Error> {
+        let dir = tests_common::create_basic_tree()?;
+        let base = Root::open(&dir)?;
+
+        // Both O_PATH and regular directory file descriptors are accepted.
+        let fd = File::open(dir.path().join("b/c"))?;
+        Root::from_fd_checked(fd).expect("from_fd_checked should accept a directory");
+        for path in ["a", "b/c", "e"] {
+            let fd = OwnedFd::from(base.resolve(path)?);
+            let expected = fd.as_unsafe_path_unchecked()?;
+            let root = Root::from_fd_checked(fd)
+                .unwrap_or_else(|err| panic!("from_fd_checked({path:?}) should succeed: {err}"));
+            assert_eq!(
+                root.resolve("..")?.as_unsafe_path_unchecked()?,
+                expected,
+                "from_fd_checked({path:?}) should be confined to the directory"
+            );
+        }
+
+        for path in ["b/c/file", "b/fifo", "link3/target_abs"] {
+            let fd = OwnedFd::from(base.resolve_nofollow(path)?);
+            assert_eq!(
+                Root::from_fd_checked(fd)
+                    .map(|_| ())
+                    .map_err(|err| err.kind()),
+                Err(ErrorKind::InvalidArgument),
+                "from_fd_checked({path:?}) should fail for non-directories"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_symlink_and_target() -> Result<(), Error> {
+        let dir = tests_common::create_basic_tree()?;
+        let root = Root::open(&dir)?;
+
+        // Symlinks with absolute and relative targets.
+        for (path, target) in [
+            ("b-file", "b/c/file"),
+            ("e", "b/c/d/e"),
+            ("link1/target_abs", "target"),
+            ("link1/target_rel", "target"),
+        ] {
+            let (link, target_handle) = root.resolve_symlink_and_target(path)?;
+            assert!(
+                link.stat()?.is_symlink(),
+                "resolve_symlink_and_target({path:?}) should return the symlink"
+            );
+            assert_eq!(
+                link.as_unsafe_path_unchecked()?,
+                dir.path().join(path),
+                "resolve_symlink_and_target({path:?}) symlink path"
+            );
+            assert_eq!(
+                target_handle?.as_unsafe_path_unchecked()?,
+                dir.path().join(target),
+                "resolve_symlink_and_target({path:?}) target path"
+            );
+        }
+
+        // Dangling symlinks.
+        let (link, target) = root.resolve_symlink_and_target("a-fake1")?;
+        assert!(
+            link.stat()?.is_symlink(),
+            "resolve_symlink_and_target of a dangling symlink should return the symlink"
+        );
+        assert_eq!(
+            target.map(|_| ()).map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::ENOENT))),
+            "resolve_symlink_and_target of a dangling symlink should fail to resolve the target"
+        );
+
+        // Non-symlinks.
+        for path in ["b/c/file", "a", "."] {
+            let (handle, target) = root.resolve_symlink_and_target(path)?;
+            let target = target?;
+            assert_eq!(
+                handle.as_unsafe_path_unchecked()?,
+                target.as_unsafe_path_unchecked()?,
+                "resolve_symlink_and_target({path:?}) of a non-symlink should return the same handle twice"
+            );
+            assert_ne!(
+                handle.as_fd().as_raw_fd(),
+                target.as_fd().as_raw_fd(),
+                "resolve_symlink_and_target({path:?}) should return two separate file descriptors"
+            );
+        }
+
+        // Missing paths.
+        assert_eq!(
+            root.resolve_symlink_and_target("a/nonexist")
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::ENOENT))),
+            "resolve_symlink_and_target of a missing path should fail"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_on_fstype() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+
+        #[allow(clippy::unnecessary_cast)]
+        let fstype = syscalls::fstatfs(Root::open(&dir)?)?.f_type as u64;
+        let other_fstype = if fstype == libc::TMPFS_MAGIC as u64 {
+            libc::EXT4_SUPER_MAGIC as u64
+        } else {
+            libc::TMPFS_MAGIC as u64
+        };
+
+        Root::open_on_fstype(&dir, &[other_fstype, fstype])
+            .expect("open_on_fstype should succeed with matching fstype");
+
+        let err = Root::open_on_fstype(&dir, &[other_fstype])
+            .expect_err("open_on_fstype should fail with mismatched fstype");
+        assert_eq!(
+            err.kind(),
+            ErrorKind::SafetyViolation,
+            "open_on_fstype with mismatched fstype should be a safety violation"
+        );
+
+        let err = Root::open_on_fstype(&dir, &[])
+            .expect_err("open_on_fstype should fail with no allowed fstypes");
+        assert_eq!(
+            err.kind(),
+            ErrorKind::SafetyViolation,
+            "open_on_fstype with no allowed fstypes should be a safety violation"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn relative_path() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("a/b/c"))?;
+        std::fs::create_dir_all(dir.path().join("a/d/e"))?;
+
+        let root = Root::open(&dir)?;
+        for (from, to, want) in [
+            ("a/b/c", "a/d/e", "../../d/e"),
+            ("a/d/e", "a/b/c", "../../b/c"),
+            ("a", "a/b/c", "b/c"),
+            ("a/b/c", "a", "../.."),
+            ("a/b", "a/b", "."),
+            (".", "a/d", "a/d"),
+        ] {
+            let (from_handle, to_handle) = (root.resolve(from)?, root.resolve(to)?);
+            assert_eq!(
+                root.relative_path(&from_handle, &to_handle)?,
+                PathBuf::from(want),
+                "relative path from {from:?} to {to:?}"
+            );
+        }
+
+        // Handles outside the root must be rejected.
+        let outside = Root::open(dir.path().join("a/b"))?.resolve(".")?;
+        let subroot = Root::open(dir.path().join("a/d"))?;
+        let err = subroot
+            .relative_path(&subroot.resolve("e")?, &outside)
+            .expect_err("relative path to handle outside root should fail");
+        assert_eq!(
+            err.kind(),
+            ErrorKind::SafetyViolation,
+            "relative path to handle outside root should be a safety violation"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_beneath() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        let base_path = dir.path().join("base");
+        std::fs::create_dir_all(base_path.join("sub/dir"))?;
+        std::fs::write(base_path.join("file"), b"")?;
+        std::os::unix::fs::symlink("/sub", base_path.join("sub-link"))?;
+        std::os::unix::fs::symlink("../../..", base_path.join("escape-link"))?;
+
+        let base = Root::open(&base_path)?;
+        let real_base = base.as_unsafe_path_unchecked()?;
+
+        // An in-root symlink is resolved within the base root.
+        let subroot = Root::open_beneath(&base, "sub-link")?;
+        assert_eq!(
+            subroot.as_unsafe_path_unchecked()?,
+            real_base.join("sub"),
+            "sub-root opened through in-root symlink should be inside base root"
+        );
+        // And the new root is confined to the sub-directory.
+        let handle = subroot.resolve("../../../dir")?;
+        assert_eq!(
+            handle.as_unsafe_path_unchecked()?,
+            real_base.join("sub/dir"),
+            "resolution within sub-root should not escape sub-root"
+        );
+
+        // Attempts to escape the base root are clamped to the base root.
+        let subroot = Root::open_beneath(&base, "escape-link")?;
+        assert_eq!(
+            subroot.as_unsafe_path_unchecked()?,
+            real_base,
+            "sub-root opened through escaping symlink should not escape base root"
+        );
+
+        // Non-directories cannot be roots.
+        let err = Root::open_beneath(&base, "file")
+            .expect_err("open_beneath of non-directory should fail");
+        assert_eq!(
+            err.kind(),
+            ErrorKind::OsError(Some(libc::ENOTDIR)),
+            "open_beneath of non-directory should fail with ENOTDIR"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_unpinned() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("a/b"))?;
+        std::fs::write(dir.path().join("a/file"), b"")?;
+        std::os::unix::fs::symlink("a/b", dir.path().join("link"))?;
+
+        let root = Root::open(&dir)?;
+        let fh1 = match root.resolve_unpinned("a/b") {
+            Err(err) if err.kind() == ErrorKind::OsError(Some(libc::EOPNOTSUPP)) => {
+                // Skip if the filesystem doesn't support file handles.
+                return Ok(());
+            }
+            res => res?,
+        };
+        assert_eq!(
+            root.resolve_unpinned("link")?,
+            fh1,
+            "file handle for the same inode should be identical"
+        );
+        assert_ne!(
+            root.resolve_unpinned("a/file")?,
+            fh1,
+            "file handle for different inodes should be different"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "_test_as_root"), ignore)]
+    fn open_unpinned() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("a/b"))?;
+        std::fs::create_dir_all(dir.path().join("c"))?;
+
+        let root = Root::open(&dir)?;
+        let fh = match root.resolve_unpinned("a/b") {
+            Err(err) if err.kind() == ErrorKind::OsError(Some(libc::EOPNOTSUPP)) => {
+                // Skip if the filesystem doesn't support file handles.
+                return Ok(());
+            }
+            res => res?,
+        };
+
+        let handle = root.open_unpinned(&fh)?;
+        assert_eq!(
+            handle.as_unsafe_path_unchecked()?,
+            root.resolve("a/b")?.as_unsafe_path_unchecked()?,
+            "open_unpinned should return a handle to the original inode"
+        );
+
+        // The file handle is outside of this root.
+        let subroot = Root::open(dir.path().join("c"))?;
+        let err = subroot
+            .open_unpinned(&fh)
+            .expect_err("open_unpinned of handle outside root should fail");
+        assert_eq!(
+            err.kind(),
+            ErrorKind::SafetyViolation,
+            "open_unpinned of handle outside root should be a safety violation"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn eager_reopen() -> Result<(), Error> {
+        use std::{fs::File, io::Read, os::unix::io::OwnedFd};
+
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"eager contents")?;
+        std::fs::create_dir(dir.path().join("dir"))?;
+        std::os::unix::fs::symlink("file", dir.path().join("link"))?;
+
+        for backend in [
+            ResolverBackend::KernelOpenat2,
+            ResolverBackend::EmulatedOpath,
+        ] {
+            if !backend.supported() {
+                continue;
+            }
+            let root = Root::open(dir.path())?.with_resolver_backend(backend);
+            assert_eq!(
+                root.eager_reopen(),
+                None,
+                "eager reopen should be off by default"
+            );
+
+            // Without eager re-opening, we get O_PATH handles.
+            assert!(
+                root.resolve("file")?.is_opath()?,
+                "regular resolve is O_PATH"
+            );
+
+            let root = root.with_eager_reopen(Some(OpenFlags::O_RDONLY));
+            assert_eq!(root.eager_reopen(), Some(OpenFlags::O_RDONLY));
+
+            for path in ["file", "link"] {
+                let handle = root.resolve(path)?;
+                assert!(
+                    !handle.is_opath()?,
+                    "eager handle for {path:?} is not O_PATH"
+                );
+                let mut contents = String::new();
+                File::from(OwnedFd::from(handle)).read_to_string(&mut contents)?;
+                assert_eq!(
+                    contents, "eager contents",
+                    "eager handle for {path:?} is readable"
+                );
+            }
+
+            // Directories stay as O_PATH handles.
+            assert!(
+                root.resolve("dir")?.is_opath()?,
+                "directories are not re-opened"
+            );
+
+            // RootRef gets a copy of the configuration.
+            let root_ref = root.as_ref();
+            assert_eq!(root_ref.eager_reopen(), Some(OpenFlags::O_RDONLY));
+            assert!(!root_ref.resolve("file")?.is_opath()?);
+            assert!(root_ref
+                .with_eager_reopen(None)
+                .resolve("file")?
+                .is_opath()?);
+
+            // Only the resolve methods apply the eager re-open policy. If the
+            // internal lookups used by other operations applied it too, the
+            // file would be truncated here.
+            let root = root.with_eager_reopen(Some(OpenFlags::O_WRONLY | OpenFlags::O_TRUNC));
+            root.hardlink_follow("link", "file-link")?;
+            root.resolve_unpinned("file")?;
+            root.access(
+                "file",
+                crate::flags::AccessMode::R_OK,
+                crate::flags::AccessFlags::empty(),
+            )?;
+            assert_eq!(
+                std::fs::read(dir.path().join("file"))?,
+                b"eager contents",
+                "internal lookups do not eagerly re-open"
+            );
+            std::fs::remove_file(dir.path().join("file-link"))?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_typed() -> Result<(), Error> {
+        use syscalls::trace;
+
+        fn count_statx(entries: &[trace::TraceEntry]) -> usize {
+            entries
+                .iter()
+                .filter(|entry| entry.syscall == "statx")
+                .count()
+        }
+
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("a/b"))?;
+        std::fs::write(dir.path().join("a/b/file"), b"")?;
+        std::os::unix::fs::symlink("b/file", dir.path().join("a/link"))?;
+
+        for backend in [
+            ResolverBackend::KernelOpenat2,
+            ResolverBackend::EmulatedOpath,
+        ] {
+            if !backend.supported() {
+                continue;
+            }
+            let root = Root::open(dir.path())?.with_resolver_backend(backend);
+
+            for (path, is_dir) in [("a/b", true), ("a/b/file", false), ("a/link", false)] {
+                // Make sure any lazily-initialised state (such as the global
+                // procfs handle) is set up before we start counting.
+                let _ = root.resolve(path)?;
+
+                let (handle, entries) = trace::capture(|| root.resolve(path));
+                let handle = handle?;
+                let resolve_statx = count_statx(&entries);
+                // openat2 does the whole lookup in-kernel, so a plain resolve
+                // must not call statx at all. The emulated resolver only uses
+                // statx for the procfs safety checks done by the walk itself
+                // (which resolve_typed does identically).
+                if backend == ResolverBackend::KernelOpenat2 {
+                    assert_eq!(
+                        resolve_statx, 0,
+                        "{backend:?} resolve({path:?}) should not call statx: {entries:#?}"
+                    );
+                }
+
+                // resolve_typed does exactly one extra statx.
+                let (ret, entries) = trace::capture(|| root.resolve_typed(path));
+                let (typed_handle, meta) = ret?;
+                assert_eq!(
+                    count_statx(&entries),
+                    resolve_statx + 1,
+                    "{backend:?} resolve_typed({path:?}) should call statx once more than resolve: {entries:#?}"
+                );
+                assert_eq!(meta.is_dir(), is_dir, "resolve_typed({path:?}) type");
+                assert_eq!(meta.is_file(), !is_dir, "resolve_typed({path:?}) type");
+                assert_eq!(
+                    typed_handle.as_unsafe_path_unchecked()?,
+                    handle.as_unsafe_path_unchecked()?,
+                    "resolve_typed({path:?}) should resolve the same path"
+                );
+                assert!(
+                    handle.is_opath()? && typed_handle.is_opath()?,
+                    "{backend:?} resolve({path:?}) and resolve_typed({path:?}) should return O_PATH handles"
+                );
+
+                // With eager re-opening, only regular files are re-opened.
+                let eager_root = root
+                    .try_clone()?
+                    .with_eager_reopen(Some(OpenFlags::O_RDONLY));
+                let (eager_handle, eager_meta) = eager_root.resolve_typed(path)?;
+                assert_eq!(
+                    eager_handle.is_opath()?,
+                    is_dir,
+                    "{backend:?} eager resolve_typed({path:?}) should only re-open regular files"
+                );
+                assert_eq!(
+                    eager_meta.is_file(),
+                    !is_dir,
+                    "eager resolve_typed({path:?}) type"
+                );
+            }
+
+            // Lookup errors are returned as-is, with or without eager
+            // re-opening.
+            for root in [
+                root.try_clone()?,
+                root.try_clone()?
+                    .with_eager_reopen(Some(OpenFlags::O_RDONLY)),
+            ] {
+                assert_eq!(
+                    root.resolve_typed("a/nonexist")
+                        .map(|_| ())
+                        .map_err(|err| err.kind()),
+                    Err(ErrorKind::OsError(Some(libc::ENOENT))),
+                    "{backend:?} resolve_typed of a non-existent path"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn max_symlink_traversals() -> Result<(), Error> {
+        const CHAIN_LEN: usize = 200;
+
+        // Create a chain l0 -> l1 -> ... -> l199 -> target, so that l{n} is
+        // CHAIN_LEN-n symlinks away from target.
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir(dir.path().join("target"))?;
+        for idx in 0..CHAIN_LEN {
+            let next = if idx + 1 == CHAIN_LEN {
+                "target".to_string()
+            } else {
+                format!("l{}", idx + 1)
+            };
+            std::os::unix::fs::symlink(next, dir.path().join(format!("l{idx}")))?;
+        }
+
+        let root = Root::open(dir.path())?.with_resolver_backend(ResolverBackend::EmulatedOpath);
+        assert_eq!(
+            root.max_symlink_traversals(),
+            None,
+            "default symlink limit should be unset"
+        );
+
+        for (limit, ok_path, eloop_path) in [
+            // The default limit is 128 symlinks.
+            (None, "l73", "l72"),
+            (Some(CHAIN_LEN + 1), "l0", ""),
+            (Some(CHAIN_LEN), "l1", "l0"),
+            (Some(10), "l191", "l190"),
+            (Some(0), "target", "l199"),
+        ] {
+            let root = root.try_clone()?.with_max_symlink_traversals(limit);
+            assert_eq!(root.max_symlink_traversals(), limit);
+
+            let handle = root.resolve(ok_path)?;
+            assert_eq!(
+                handle.as_unsafe_path_unchecked()?,
+                root.resolve("target")?.as_unsafe_path_unchecked()?,
+                "resolve({ok_path:?}) with limit {limit:?} should succeed"
+            );
+            if !eloop_path.is_empty() {
+                assert_eq!(
+                    root.resolve(eloop_path)
+                        .map(|_| ())
+                        .map_err(|err| err.kind()),
+                    Err(ErrorKind::OsError(Some(libc::ELOOP))),
+                    "resolve({eloop_path:?}) with limit {limit:?} should fail"
+                );
+                // RootRef gets a copy of the configuration.
+                assert_eq!(
+                    root.as_ref()
+                        .resolve(eloop_path)
+                        .map(|_| ())
+                        .map_err(|err| err.kind()),
+                    Err(ErrorKind::OsError(Some(libc::ELOOP))),
+                    "RootRef resolve({eloop_path:?}) with limit {limit:?} should fail"
+                );
+            }
+        }
+
+        // The limit can be raised beyond the default for RootRef as well.
+        assert!(root
+            .as_ref()
+            .with_max_symlink_traversals(Some(CHAIN_LEN + 1))
+            .resolve("l0")
+            .is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn max_symlink_target_bytes() -> Result<(), Error> {
+        // a -> bb -> target (8 bytes of symlink targets in total).
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir(dir.path().join("target"))?;
+        std::os::unix::fs::symlink("target", dir.path().join("bb"))?;
+        std::os::unix::fs::symlink("bb", dir.path().join("a"))?;
+
+        let root = Root::open(dir.path())?.with_resolver_backend(ResolverBackend::EmulatedOpath);
+        assert_eq!(
+            root.max_symlink_target_bytes(),
+            None,
+            "default symlink target length limit should be unset"
+        );
+
+        for (limit, ok_path, eloop_path) in [
+            (None, "a", ""),
+            (Some(8), "a", ""),
+            (Some(7), "bb", "a"),
+            (Some(6), "bb", "a"),
+            (Some(0), "target", "bb"),
+        ] {
+            let root = root.try_clone()?.with_max_symlink_target_bytes(limit);
+            assert_eq!(root.max_symlink_target_bytes(), limit);
+
+            assert!(
+                root.resolve(ok_path).is_ok(),
+                "resolve({ok_path:?}) with limit {limit:?} should succeed"
+            );
+            if !eloop_path.is_empty() {
+                assert_eq!(
+                    root.resolve(eloop_path)
+                        .map(|_| ())
+                        .map_err(|err| err.kind()),
+                    Err(ErrorKind::OsError(Some(libc::ELOOP))),
+                    "resolve({eloop_path:?}) with limit {limit:?} should fail"
+                );
+                // RootRef gets a copy of the configuration.
+                assert_eq!(
+                    root.as_ref()
+                        .resolve(eloop_path)
+                        .map(|_| ())
+                        .map_err(|err| err.kind()),
+                    Err(ErrorKind::OsError(Some(libc::ELOOP))),
+                    "RootRef resolve({eloop_path:?}) with limit {limit:?} should fail"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn create_if() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("empty"))?;
+        std::fs::create_dir_all(dir.path().join("nonempty"))?;
+        std::fs::write(dir.path().join("nonempty/other"), b"")?;
+        let root = Root::open(dir.path())?;
+
+        let is_empty = |parent: &Handle| {
+            let dir = parent
+                .reopen(OpenFlags::O_RDONLY | OpenFlags::O_DIRECTORY)
+                .expect("reopen parent directory");
+            rustix::fs::Dir::read_from(dir)
+                .expect("read parent directory")
+                .filter(|dentry| {
+                    !matches!(
+                        dentry.as_ref().map(|dentry| dentry.file_name().to_bytes()),
+                        Ok(b".") | Ok(b"..")
+                    )
+                })
+                .count()
+                == 0
+        };
+
+        // Parent is empty, so the file is created.
+        let handle = root
+            .create_if("empty/marker", 0o600, is_empty)?
+            .expect("create_if should create marker in empty directory");
+        let meta = handle.stat()?;
+        assert!(meta.is_file(), "created marker should be a regular file");
+        assert_eq!(
+            meta.mode() & !0o600 & 0o7777,
+            0,
+            "marker mode is at most 0o600"
+        );
+        assert!(dir.path().join("empty/marker").exists());
+        assert_eq!(
+            handle.as_unsafe_path_unchecked()?,
+            root.as_unsafe_path_unchecked()?.join("empty/marker"),
+        );
+
+        // Parent is no longer empty, so nothing happens.
+        assert!(
+            root.create_if("empty/marker2", 0o600, is_empty)?.is_none(),
+            "create_if should not create marker in non-empty directory"
+        );
+        assert!(!dir.path().join("empty/marker2").exists());
+        assert!(root
+            .create_if("nonempty/marker", 0o600, is_empty)?
+            .is_none());
+        assert!(!dir.path().join("nonempty/marker").exists());
+
+        // Existing files are never overwritten.
+        assert_eq!(
+            root.create_if("nonempty/other", 0o600, |_| true)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::EEXIST))),
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn replace_executable() -> Result<(), Error> {
+        use std::{fs::Permissions, os::unix::fs::PermissionsExt, process::Command};
+
+        let dir = tempfile::TempDir::new()?;
+        let prog_path = dir.path().join("prog");
+        std::fs::copy("/bin/sleep", &prog_path)?;
+        let root = Root::open(dir.path())?;
+        let old_ino = root.resolve("prog")?.stat()?.ino();
+
+        // Other test threads may fork while the copy had the file open for
+        // writing, so retry spawning on ETXTBSY.
+        let mut child = loop {
+            match Command::new(&prog_path).arg("60").spawn() {
+                Err(err) if err.raw_os_error() == Some(libc::ETXTBSY) => continue,
+                child => break child?,
+            }
+        };
+
+        // Opening a running executable for writing gives ETXTBSY (though some
+        // kernel versions permitted this, so only check the error if we got
+        // one).
+        if let Err(err) = root.resolve("prog")?.reopen(OpenFlags::O_WRONLY) {
+            assert!(
+                err.is_text_busy(),
+                "writing to a running executable should be ETXTBSY: {err:?}"
+            );
+        }
+
+        let new_contents = b"#!/bin/sh\nexit 0\n";
+        let handle =
+            root.replace_executable("prog", &new_contents[..], &Permissions::from_mode(0o755))?;
+        let meta = handle.stat()?;
+        assert_ne!(meta.ino(), old_ino, "replacement should be a new inode");
+        assert_eq!(
+            meta.mode() & 0o7777,
+            0o755,
+            "replacement mode should ignore umask"
+        );
+        assert_eq!(root.resolve("prog")?.stat()?.ino(), meta.ino());
+        assert_eq!(std::fs::read(&prog_path)?, new_contents);
+
+        // The old program is still running, unaffected by the replacement.
+        assert!(
+            child.try_wait()?.is_none(),
+            "old program should still be running"
+        );
+        child.kill()?;
+        child.wait()?;
+
+        // No temporary files should be left behind.
+        assert_eq!(
+            std::fs::read_dir(dir.path())?
+                .map(|dentry| dentry.map(|dentry| dentry.file_name()))
+                .collect::<Result<Vec<_>, _>>()?,
+            vec![std::ffi::OsString::from("prog")],
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn pivot_scratch_dir() -> Result<(), Error> {
+        if !ResolverBackend::HardcoreEmulated.supported() {
+            // Skip if not supported.
+            return Ok(());
+        }
+
+        // Layout:
+        //   base/secret
+        //   base/root/a/b/file
+        //   base/root/escape -> ../../../../..
+        //   base/root/abs -> /a/b
+        //   base/root/leak -> ../secret
+        let base = tempfile::TempDir::new()?;
+        let root_dir = base.path().join("root");
+        std::fs::write(base.path().join("secret"), b"")?;
+        std::fs::create_dir_all(root_dir.join("a/b"))?;
+        std::fs::write(root_dir.join("a/b/file"), b"")?;
+        std::os::unix::fs::symlink("../../../../..", root_dir.join("escape"))?;
+        std::os::unix::fs::symlink("/a/b", root_dir.join("abs"))?;
+        std::os::unix::fs::symlink("../secret", root_dir.join("leak"))?;
+
+        let scratch = tempfile::TempDir::new()?;
+        let root = Root::open(&root_dir)?
+            .with_resolver_backend(ResolverBackend::HardcoreEmulated)
+            .with_pivot_scratch_dir(Some(File::open(scratch.path())?.into()));
+        assert!(
+            root.pivot_scratch_dir().is_some(),
+            "pivot scratch dir should be set"
+        );
+
+        for root in [root.try_clone()?, root.as_ref().try_clone()?] {
+            assert!(
+                root.pivot_scratch_dir().is_some(),
+                "cloned root should inherit pivot scratch dir"
+            );
+            for (path, expected) in [
+                ("a/b/file", "a/b/file"),
+                ("escape", "."),
+                ("escape/a/b/file", "a/b/file"),
+                ("abs/file", "a/b/file"),
+                ("/../../abs/../../a", "a"),
+            ] {
+                assert_eq!(
+                    root.resolve(path)?.as_unsafe_path_unchecked()?,
+                    root_dir.join(expected).canonicalize()?,
+                    "resolve({path:?}) with pivot scratch dir"
+                );
+                assert_eq!(
+                    root.as_ref().resolve(path)?.as_unsafe_path_unchecked()?,
+                    root_dir.join(expected).canonicalize()?,
+                    "RootRef resolve({path:?}) with pivot scratch dir"
+                );
+            }
+            assert_eq!(
+                root.resolve("leak").map(|_| ()).map_err(|err| err.kind()),
+                Err(ErrorKind::OsError(Some(libc::ENOENT))),
+                "resolve(\"leak\") must not escape the root"
+            );
+        }
+
+        // All of the mounts happened in a private mount namespace, so the
+        // scratch directory should be untouched.
+        assert_eq!(
+            std::fs::read_dir(scratch.path())?.count(),
+            0,
+            "pivot scratch dir should not be modified"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn rename_noreplace_emulated() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("a"), b"a")?;
+        std::fs::write(dir.path().join("b"), b"b")?;
+        std::os::unix::fs::symlink("nonexistent", dir.path().join("dangling"))?;
+        let dirfd = File::open(&dir)?;
+
+        for dst in ["b", "dangling"] {
+            assert_eq!(
+                super::rename_noreplace_emulated(&dirfd, "a".as_ref(), &dirfd, dst.as_ref())
+                    .map_err(|err| err.kind()),
+                Err(ErrorKind::OsError(Some(libc::EEXIST))),
+                "emulated RENAME_NOREPLACE onto {dst:?} should fail"
+            );
+        }
+        assert_eq!(
+            std::fs::read(dir.path().join("b"))?,
+            b"b",
+            "failed emulated RENAME_NOREPLACE should not modify destination"
+        );
+
+        super::rename_noreplace_emulated(&dirfd, "a".as_ref(), &dirfd, "c".as_ref())?;
+        assert!(
+            !dir.path().join("a").exists(),
+            "emulated RENAME_NOREPLACE should move the source"
+        );
+        assert_eq!(
+            std::fs::read(dir.path().join("c"))?,
+            b"a",
+            "emulated RENAME_NOREPLACE should move the source to the destination"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn hardlink_procfs() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("file"), b"data")?;
+        std::os::unix::fs::symlink("file", dir.path().join("link"))?;
+        let dirfd = File::open(&dir)?;
+
+        let file = syscalls::openat(&dirfd, "file", OpenFlags::O_PATH, 0)?;
+        super::hardlink_procfs(&file, &dirfd, "file2".as_ref())?;
+        let (meta, meta2) = (
+            std::fs::metadata(dir.path().join("file"))?,
+            std::fs::metadata(dir.path().join("file2"))?,
+        );
+        assert_eq!(
+            (meta2.ino(), meta2.nlink()),
+            (meta.ino(), 2),
+            "procfs hardlink should link to the same inode"
+        );
+
+        // The magic-link is followed, but not the symlink it points to.
+        let link = syscalls::openat(&dirfd, "link", OpenFlags::O_PATH | OpenFlags::O_NOFOLLOW, 0)?;
+        super::hardlink_procfs(&link, &dirfd, "link2".as_ref())?;
+        assert_eq!(
+            std::fs::read_link(dir.path().join("link2"))?,
+            Path::new("file"),
+            "procfs hardlink of a symlink handle should link the symlink"
+        );
+
+        assert_eq!(
+            super::hardlink_procfs(&file, &dirfd, "link".as_ref()).map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::EEXIST))),
+            "procfs hardlink onto an existing path should fail"
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn public_resolver_backend_serde() -> Result<(), Error> {
+        use crate::PublicResolverBackend;
+
+        for (backend, json) in [
+            (PublicResolverBackend::Openat2, r#""openat2""#),
+            (PublicResolverBackend::Emulated, r#""emulated""#),
+            (PublicResolverBackend::Hardcore, r#""hardcore""#),
+        ] {
+            assert_eq!(serde_json::to_string(&backend)?, json);
+            assert_eq!(
+                serde_json::from_str::<PublicResolverBackend>(json)?,
+                backend,
+                "{backend:?} should round-trip"
+            );
+        }
+        assert!(
+            serde_json::from_str::<PublicResolverBackend>(r#""kernel""#).is_err(),
+            "unknown backend names should be rejected"
+        );
+
+        Ok(())
+    }
 }
@@ -21,31 +21,41 @@
 #![allow(unsafe_code)]
 
 use crate::{
-    flags::{OpenFlags, RenameFlags},
+    flags::{AccessFlags, AccessMode, OpenFlags, RenameFlags},
+    kernel::kernel_features,
     utils::{FdExt, ToCString},
 };
 
 use std::{
-    ffi::OsStr,
+    convert::Infallible,
+    ffi::{CString, OsStr, OsString},
     fmt,
     io::Error as IOError,
+    iter,
     mem::MaybeUninit,
     os::unix::{
         ffi::OsStrExt,
         io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
     },
     path::{Path, PathBuf},
+    ptr::{self, NonNull},
+    slice,
 };
 
 use bitflags::bitflags;
-use once_cell::sync::Lazy;
 use rustix::{
     fs::{
         self as rustix_fs, AtFlags, Dev, FileType, Mode, RawMode, Stat, StatFs, Statx, StatxFlags,
+        XattrFlags,
     },
     io::Errno,
-    mount::{self as rustix_mount, FsMountFlags, FsOpenFlags, MountAttrFlags, OpenTreeFlags},
-    process as rustix_process, thread as rustix_thread,
+    mm::{self as rustix_mm, MapFlags, MprotectFlags, ProtFlags},
+    mount::{
+        self as rustix_mount, FsMountFlags, FsOpenFlags, MountAttrFlags, MountPropagationFlags,
+        MoveMountFlags, OpenTreeFlags, UnmountFlags,
+    },
+    process as rustix_process,
+    thread::{self as rustix_thread, UnshareFlags},
 };
 
 // TODO: Figure out how we can put a backtrace here (it seems we can't use
@@ -128,6 +138,15 @@ pub(crate) enum Error {
         source: Errno,
     },
 
+    #[error("faccessat2({dirfd}, {path}, {mode:?}, {flags:?})")]
+    Faccessat2 {
+        dirfd: FrozenFd,
+        path: PathBuf,
+        mode: AccessMode,
+        flags: AccessFlags,
+        source: Errno,
+    },
+
     #[error("readlinkat({dirfd}, {path})")]
     Readlinkat {
         dirfd: FrozenFd,
@@ -143,6 +162,22 @@ pub(crate) enum Error {
         source: Errno,
     },
 
+    #[error("fchmodat({dirfd}, {path}, 0o{mode:o})")]
+    Fchmodat {
+        dirfd: FrozenFd,
+        path: PathBuf,
+        mode: u32,
+        source: Errno,
+    },
+
+    #[error("utimensat({dirfd}, {path}, UTIME_NOW, 0x{flags:x})")]
+    Utimensat {
+        dirfd: FrozenFd,
+        path: PathBuf,
+        flags: AtFlags,
+        source: Errno,
+    },
+
     #[error("mknodat({dirfd}, {path}, 0o{mode:o}, {major}:{minor})")]
     Mknodat {
         dirfd: FrozenFd,
@@ -198,6 +233,116 @@ pub(crate) enum Error {
         source: Errno,
     },
 
+    #[error("name_to_handle_at({dirfd}, {path}, {flags:?})")]
+    NameToHandleAt {
+        dirfd: FrozenFd,
+        path: PathBuf,
+        flags: AtFlags,
+        source: Errno,
+    },
+
+    #[error("open_by_handle_at({mount_fd}, <handle type {handle_type}>, {flags:?})")]
+    OpenByHandleAt {
+        mount_fd: FrozenFd,
+        handle_type: i32,
+        flags: OpenFlags,
+        source: Errno,
+    },
+
+    #[error("dup({fd})")]
+    Dup { fd: FrozenFd, source: Errno },
+
+    #[error("fcntl({fd}, F_DUPFD_CLOEXEC, {minfd})")]
+    FcntlDupfdCloexec {
+        fd: FrozenFd,
+        minfd: RawFd,
+        source: Errno,
+    },
+
+    #[error("fcntl({fd}, F_GETFL)")]
+    FcntlGetfl { fd: FrozenFd, source: Errno },
+
+    #[error("execveat({dirfd}, {path}, {argv:?}, <{envc} env vars>, {flags:?})")]
+    Execveat {
+        dirfd: FrozenFd,
+        path: PathBuf,
+        argv: Vec<CString>,
+        envc: usize,
+        flags: AtFlags,
+        source: Errno,
+    },
+
+    #[error("getxattr({path}, {name:?})")]
+    Getxattr {
+        path: PathBuf,
+        name: OsString,
+        source: Errno,
+    },
+
+    #[error("setxattr({path}, {name:?}, <{size} bytes>, {flags:?})")]
+    Setxattr {
+        path: PathBuf,
+        name: OsString,
+        size: usize,
+        flags: XattrFlags,
+        source: Errno,
+    },
+
+    #[error("removexattr({path}, {name:?})")]
+    Removexattr {
+        path: PathBuf,
+        name: OsString,
+        source: Errno,
+    },
+
+    #[error("listxattr({path})")]
+    Listxattr { path: PathBuf, source: Errno },
+
+    #[error("getxattrat({dirfd}, {path}, {name:?})")]
+    Getxattrat {
+        dirfd: FrozenFd,
+        path: PathBuf,
+        name: OsString,
+        source: Errno,
+    },
+
+    #[error("setxattrat({dirfd}, {path}, {name:?}, <{size} bytes>, {flags:?})")]
+    Setxattrat {
+        dirfd: FrozenFd,
+        path: PathBuf,
+        name: OsString,
+        size: usize,
+        flags: XattrFlags,
+        source: Errno,
+    },
+
+    #[error("removexattrat({dirfd}, {path}, {name:?})")]
+    Removexattrat {
+        dirfd: FrozenFd,
+        path: PathBuf,
+        name: OsString,
+        source: Errno,
+    },
+
+    #[error("listxattrat({dirfd}, {path})")]
+    Listxattrat {
+        dirfd: FrozenFd,
+        path: PathBuf,
+        source: Errno,
+    },
+
+    #[error("ioctl({fd}, FS_IOC_ENABLE_VERITY, {{hash_algorithm: {hash_algorithm}, block_size: {block_size}, <{salt_size} byte salt>}})")]
+    FsverityEnable {
+        fd: FrozenFd,
+        hash_algorithm: u32,
+        block_size: u32,
+        salt_size: usize,
+        source: Errno,
+    },
+
+    #[error("ioctl({fd}, FS_IOC_MEASURE_VERITY)")]
+    FsverityMeasure { fd: FrozenFd, source: Errno },
+
     #[error("fstatfs({fd})")]
     Fstatfs { fd: FrozenFd, source: Errno },
 
@@ -251,6 +396,75 @@ pub(crate) enum Error {
         flags: OpenTreeFlags,
         source: Errno,
     },
+
+    #[error("move_mount({from_dirfd}, {from_path}, {to_dirfd}, {to_path}, {flags:?})")]
+    MoveMount {
+        from_dirfd: FrozenFd,
+        from_path: PathBuf,
+        to_dirfd: FrozenFd,
+        to_path: PathBuf,
+        flags: MoveMountFlags,
+        source: Errno,
+    },
+
+    #[error("mount(<none>, {target}, <none>, {flags:?})")]
+    MountChange {
+        target: PathBuf,
+        flags: MountPropagationFlags,
+        source: Errno,
+    },
+
+    #[error("umount2({target}, {flags:?})")]
+    Umount2 {
+        target: PathBuf,
+        flags: UnmountFlags,
+        source: Errno,
+    },
+
+    #[error("pivot_root({new_root}, {put_old})")]
+    PivotRoot {
+        new_root: PathBuf,
+        put_old: PathBuf,
+        source: Errno,
+    },
+
+    #[error("fchdir({fd})")]
+    Fchdir { fd: FrozenFd, source: Errno },
+
+    #[error("unshare({flags:?})")]
+    Unshare { flags: UnshareFlags, source: Errno },
+
+    #[error("mmap(NULL, {len}, {prot:?}, {flags:?}, {fd}, 0)")]
+    Mmap {
+        len: usize,
+        prot: ProtFlags,
+        flags: MapFlags,
+        fd: FrozenFd,
+        source: Errno,
+    },
+
+    #[error("mmap(NULL, {len}, {prot:?}, {flags:?} | MAP_ANONYMOUS)")]
+    MmapAnonymous {
+        len: usize,
+        prot: ProtFlags,
+        flags: MapFlags,
+        source: Errno,
+    },
+
+    #[error("mprotect(<mapping>, {len}, {prot:?})")]
+    Mprotect {
+        len: usize,
+        prot: MprotectFlags,
+        source: Errno,
+    },
+
+    #[cfg(feature = "io-uring")]
+    #[error("io_uring_setup({entries})")]
+    IoUringSetup { entries: u32, source: Errno },
+
+    #[cfg(feature = "io-uring")]
+    #[error("io_uring_register(IORING_REGISTER_PROBE)")]
+    IoUringProbe { source: Errno },
 }
 
 impl Error {
@@ -260,14 +474,33 @@ impl Error {
             Error::InvalidFd { source, .. } => source,
             Error::Openat { source, .. } => source,
             Error::Openat2 { source, .. } => source,
+            Error::Faccessat2 { source, .. } => source,
             Error::Readlinkat { source, .. } => source,
             Error::Mkdirat { source, .. } => source,
+            Error::Fchmodat { source, .. } => source,
+            Error::Utimensat { source, .. } => source,
             Error::Mknodat { source, .. } => source,
             Error::Unlinkat { source, .. } => source,
             Error::Linkat { source, .. } => source,
             Error::Symlinkat { source, .. } => source,
             Error::Renameat { source, .. } => source,
             Error::Renameat2 { source, .. } => source,
+            Error::NameToHandleAt { source, .. } => source,
+            Error::OpenByHandleAt { source, .. } => source,
+            Error::Dup { source, .. } => source,
+            Error::FcntlDupfdCloexec { source, .. } => source,
+            Error::FcntlGetfl { source, .. } => source,
+            Error::Execveat { source, .. } => source,
+            Error::Getxattr { source, .. } => source,
+            Error::Setxattr { source, .. } => source,
+            Error::Removexattr { source, .. } => source,
+            Error::Listxattr { source, .. } => source,
+            Error::Getxattrat { source, .. } => source,
+            Error::Setxattrat { source, .. } => source,
+            Error::Removexattrat { source, .. } => source,
+            Error::Listxattrat { source, .. } => source,
+            Error::FsverityEnable { source, .. } => source,
+            Error::FsverityMeasure { source, .. } => source,
             Error::Fstatfs { source, .. } => source,
             Error::Fstatat { source, .. } => source,
             Error::Statx { source, .. } => source,
@@ -276,6 +509,19 @@ impl Error {
             Error::FsconfigSetString { source, .. } => source,
             Error::Fsmount { source, .. } => source,
             Error::OpenTree { source, .. } => source,
+            Error::MoveMount { source, .. } => source,
+            Error::MountChange { source, .. } => source,
+            Error::Umount2 { source, .. } => source,
+            Error::PivotRoot { source, .. } => source,
+            Error::Fchdir { source, .. } => source,
+            Error::Unshare { source, .. } => source,
+            Error::Mmap { source, .. } => source,
+            Error::MmapAnonymous { source, .. } => source,
+            Error::Mprotect { source, .. } => source,
+            #[cfg(feature = "io-uring")]
+            Error::IoUringSetup { source, .. } => source,
+            #[cfg(feature = "io-uring")]
+            Error::IoUringProbe { source, .. } => source,
         }
     }
 
@@ -313,21 +559,35 @@ pub(crate) fn openat_follow<Fd: AsFd, P: AsRef<Path>>(
     mut flags: OpenFlags,
     mode: RawMode, // TODO: Should we take rustix::fs::Mode directly?
 ) -> Result<OwnedFd, Error> {
-    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
-    let path = path.as_ref();
-
     // O_CLOEXEC is needed for obvious reasons, and O_NOCTTY ensures that a
     // malicious file won't take control of our terminal.
-    flags.insert(OpenFlags::O_CLOEXEC | OpenFlags::O_NOCTTY);
+    flags.insert(OpenFlags::IMPLICIT_FLAGS);
+    openat_follow_raw(dirfd, path, flags, mode)
+}
 
-    rustix_fs::openat(dirfd, path, flags.into(), Mode::from_raw_mode(mode)).map_err(|errno| {
-        Error::Openat {
-            dirfd: dirfd.into(),
-            path: path.into(),
-            flags,
-            mode,
-            source: errno,
-        }
+/// Wrapper for `openat(2)` which uses exactly the provided `flags`.
+///
+/// Unlike [`openat_follow`], `O_CLOEXEC` and `O_NOCTTY` are *not* set
+/// automatically. This should only be used when the caller explicitly asked
+/// for those flags to be omitted.
+pub(crate) fn openat_follow_raw<Fd: AsFd, P: AsRef<Path>>(
+    dirfd: Fd,
+    path: P,
+    flags: OpenFlags,
+    mode: RawMode, // TODO: Should we take rustix::fs::Mode directly?
+) -> Result<OwnedFd, Error> {
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    let path = path.as_ref();
+
+    let ret = rustix_fs::openat(dirfd, path, flags.into(), Mode::from_raw_mode(mode));
+    #[cfg(test)]
+    trace::record("openat", path, Some(flags), ret.as_ref().err().copied());
+    ret.map_err(|errno| Error::Openat {
+        dirfd: dirfd.into(),
+        path: path.into(),
+        flags,
+        mode,
+        source: errno,
     })
 }
 
@@ -361,14 +621,14 @@ pub(crate) fn readlinkat<Fd: AsFd, P: AsRef<Path>>(dirfd: Fd, path: P) -> Result
     let mut linkbuf: [MaybeUninit<u8>; 32 * 4096] =
         [MaybeUninit::uninit(); 32 * libc::PATH_MAX as usize];
 
-    let (target, trailing) =
-        rustix_fs::readlinkat_raw(dirfd, path, &mut linkbuf[..]).map_err(|errno| {
-            Error::Readlinkat {
-                dirfd: dirfd.into(),
-                path: path.into(),
-                source: errno,
-            }
-        })?;
+    let ret = rustix_fs::readlinkat_raw(dirfd, path, &mut linkbuf[..]);
+    #[cfg(test)]
+    trace::record("readlinkat", path, None, ret.as_ref().err().copied());
+    let (target, trailing) = ret.map_err(|errno| Error::Readlinkat {
+        dirfd: dirfd.into(),
+        path: path.into(),
+        source: errno,
+    })?;
 
     if trailing.is_empty() {
         // The buffer was too small, return an error.
@@ -382,6 +642,56 @@ pub(crate) fn readlinkat<Fd: AsFd, P: AsRef<Path>>(dirfd: Fd, path: P) -> Result
     }
 }
 
+/// Like [`readlinkat`], but the symlink target is read into `buf` (which is
+/// cleared first) so that callers can re-use the allocation. `buf` is only
+/// grown if it is too small, up to the same size limit as [`readlinkat`].
+/// Returns the length of the symlink target.
+pub(crate) fn readlinkat_into<Fd: AsFd, P: AsRef<Path>>(
+    dirfd: Fd,
+    path: P,
+    buf: &mut Vec<u8>,
+) -> Result<usize, Error> {
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    let path = path.as_ref();
+
+    // Same limit as readlinkat() (there is no way to get the size of a
+    // symlink beforehand, so we need to bail out at some point).
+    const MAX_LINK_SIZE: usize = 32 * libc::PATH_MAX as usize;
+
+    buf.clear();
+    buf.reserve(libc::PATH_MAX as usize);
+    loop {
+        let ret = rustix_fs::readlinkat_raw(dirfd, path, buf.spare_capacity_mut());
+        #[cfg(test)]
+        trace::record("readlinkat", path, None, ret.as_ref().err().copied());
+        let (len, truncated) = ret
+            .map(|(target, trailing)| (target.len(), trailing.is_empty()))
+            .map_err(|errno| Error::Readlinkat {
+                dirfd: dirfd.into(),
+                path: path.into(),
+                source: errno,
+            })?;
+
+        if !truncated {
+            // SAFETY: readlinkat_raw has initialised the first len bytes of
+            // the spare capacity of buf (which is empty).
+            unsafe { buf.set_len(len) };
+            return Ok(len);
+        }
+        if buf.capacity() >= MAX_LINK_SIZE {
+            // The buffer was too small, return an error.
+            Err(Error::Readlinkat {
+                dirfd: dirfd.into(),
+                path: path.into(),
+                source: Errno::NAMETOOLONG,
+            })?
+        }
+        // The symlink target might have been truncated, so try again with a
+        // larger buffer.
+        buf.reserve((buf.capacity() * 2).min(MAX_LINK_SIZE));
+    }
+}
+
 /// Wrapper for `mkdirat(2)`.
 ///
 /// This is needed because Rust doesn't provide a way to access the dirfd
@@ -402,6 +712,61 @@ pub(crate) fn mkdirat<Fd: AsFd, P: AsRef<Path>>(
     })
 }
 
+/// Wrapper for `fchmodat(2)`.
+///
+/// Note that `fchmodat(2)` always follows trailing symlinks (the kernel does
+/// not support `AT_SYMLINK_NOFOLLOW` for `fchmodat(2)`), so callers must make
+/// sure `path` is not a symlink (or is a magic-link they want to follow).
+pub(crate) fn fchmodat<Fd: AsFd, P: AsRef<Path>>(
+    dirfd: Fd,
+    path: P,
+    mode: RawMode,
+) -> Result<(), Error> {
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    let path = path.as_ref();
+
+    rustix_fs::chmodat(dirfd, path, Mode::from_raw_mode(mode), AtFlags::empty()).map_err(|errno| {
+        Error::Fchmodat {
+            dirfd: dirfd.into(),
+            path: path.into(),
+            mode,
+            source: errno,
+        }
+    })
+}
+
+/// Wrapper for `utimensat(2)` which sets the access and modification times to
+/// the current time (`UTIME_NOW`), and auto-sets `AT_SYMLINK_NOFOLLOW |
+/// AT_EMPTY_PATH`.
+///
+/// `AT_EMPTY_PATH` support for `utimensat(2)` was only added in Linux 5.8, so
+/// older kernels will return `EINVAL` if `path` is empty.
+pub(crate) fn utimensat_now<Fd: AsFd, P: AsRef<Path>>(dirfd: Fd, path: P) -> Result<(), Error> {
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    let path = path.as_ref();
+    let flags = AtFlags::SYMLINK_NOFOLLOW | AtFlags::EMPTY_PATH;
+    let now = rustix_fs::Timespec {
+        tv_sec: 0,
+        tv_nsec: rustix_fs::UTIME_NOW,
+    };
+
+    rustix_fs::utimensat(
+        dirfd,
+        path,
+        &rustix_fs::Timestamps {
+            last_access: now,
+            last_modification: now,
+        },
+        flags,
+    )
+    .map_err(|errno| Error::Utimensat {
+        dirfd: dirfd.into(),
+        path: path.into(),
+        flags,
+        source: errno,
+    })
+}
+
 pub(crate) fn devmajorminor(dev: Dev) -> (u32, u32) {
     (rustix_fs::major(dev), rustix_fs::minor(dev))
 }
@@ -525,15 +890,6 @@ pub(crate) fn renameat<Fd1: AsFd, P1: AsRef<Path>, Fd2: AsFd, P2: AsRef<Path>>(
     })
 }
 
-// MSRV(1.80): Use LazyLock.
-pub(crate) static RENAME_FLAGS_SUPPORTED: Lazy<bool> = Lazy::new(|| {
-    match renameat2(AT_FDCWD, ".", AT_FDCWD, ".", RenameFlags::RENAME_EXCHANGE) {
-        Ok(_) => true,
-        // We expect EBUSY, but just to be safe we only check for ENOSYS.
-        Err(err) => err.root_cause().raw_os_error() != Some(libc::ENOSYS),
-    }
-});
-
 /// Wrapper for `renameat2(2)`.
 ///
 /// This is needed because Rust doesn't provide any interface for `renameat2(2)`
@@ -553,71 +909,808 @@ pub(crate) fn renameat2<Fd1: AsFd, P1: AsRef<Path>, Fd2: AsFd, P2: AsRef<Path>>(
     let (old_dirfd, old_path) = (old_dirfd.as_fd().hotfix_rustix_fd()?, old_path.as_ref());
     let (new_dirfd, new_path) = (new_dirfd.as_fd().hotfix_rustix_fd()?, new_path.as_ref());
 
-    rustix_fs::renameat_with(old_dirfd, old_path, new_dirfd, new_path, flags.into()).map_err(
-        |errno| Error::Renameat2 {
-            old_dirfd: old_dirfd.into(),
-            old_path: old_path.into(),
-            new_dirfd: new_dirfd.into(),
-            new_path: new_path.into(),
-            flags,
-            source: errno,
-        },
-    )
-}
-
-/// Wrapper for `fstatfs(2)`.
-///
-/// This is needed because Rust doesn't provide any interface for `fstatfs(2)`.
-pub(crate) fn fstatfs<Fd: AsFd>(fd: Fd) -> Result<StatFs, Error> {
-    let fd = fd.as_fd().hotfix_rustix_fd()?;
-
-    rustix_fs::fstatfs(fd).map_err(|errno| Error::Fstatfs {
-        fd: fd.into(),
+    let ret = if kernel_features().has_renameat2 {
+        rustix_fs::renameat_with(old_dirfd, old_path, new_dirfd, new_path, flags.into())
+    } else {
+        Err(Errno::NOSYS)
+    };
+    ret.map_err(|errno| Error::Renameat2 {
+        old_dirfd: old_dirfd.into(),
+        old_path: old_path.into(),
+        new_dirfd: new_dirfd.into(),
+        new_path: new_path.into(),
+        flags,
         source: errno,
     })
 }
 
-/// Wrapper for `fstatat(2)`, which auto-sets `AT_NO_AUTOMOUNT |
-/// AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH`.
-///
-/// This is needed because Rust doesn't provide any interface for `fstatat(2)`.
-pub(crate) fn fstatat<Fd: AsFd, P: AsRef<Path>>(dirfd: Fd, path: P) -> Result<Stat, Error> {
-    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
-    let path = path.as_ref();
-    let flags = AtFlags::NO_AUTOMOUNT | AtFlags::SYMLINK_NOFOLLOW | AtFlags::EMPTY_PATH;
+/// Maximum size of the opaque data in a file handle (`MAX_HANDLE_SZ`).
+const MAX_HANDLE_SZ: usize = 128;
 
-    rustix_fs::statat(dirfd, path, flags).map_err(|errno| Error::Fstatat {
-        dirfd: dirfd.into(),
-        path: path.into(),
-        flags,
-        source: errno,
-    })
+/// Internal representation of `struct file_handle`, with enough space for the
+/// largest possible handle.
+#[repr(C)]
+struct RawFileHandle {
+    handle_bytes: u32,
+    handle_type: i32,
+    f_handle: [u8; MAX_HANDLE_SZ],
 }
 
-pub(crate) fn statx<Fd: AsFd, P: AsRef<Path>>(
+/// Wrapper for `name_to_handle_at(2)`, which returns the handle type and opaque
+/// handle data.
+///
+/// This is needed because Rust doesn't provide any interface for
+/// `name_to_handle_at(2)`.
+pub(crate) fn name_to_handle_at<Fd: AsFd, P: AsRef<Path>>(
     dirfd: Fd,
     path: P,
-    mask: StatxFlags,
-) -> Result<Statx, Error> {
+    flags: AtFlags,
+) -> Result<(i32, Vec<u8>), Error> {
     let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
     let path = path.as_ref();
-    let flags = AtFlags::NO_AUTOMOUNT | AtFlags::SYMLINK_NOFOLLOW | AtFlags::EMPTY_PATH;
 
-    rustix_fs::statx(dirfd, path, flags, mask).map_err(|errno| Error::Statx {
-        dirfd: dirfd.into(),
-        path: path.into(),
-        flags,
-        mask,
-        source: errno,
-    })
+    let mut handle = RawFileHandle {
+        handle_bytes: MAX_HANDLE_SZ as u32,
+        handle_type: 0,
+        f_handle: [0; MAX_HANDLE_SZ],
+    };
+    let mut mount_id: libc::c_int = 0;
+
+    // SAFETY: Obviously safe-to-use Linux syscall, and handle has enough space
+    //         for the largest possible file handle.
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_name_to_handle_at,
+            dirfd.as_raw_fd(),
+            path.to_c_string().as_ptr(),
+            &mut handle as *mut RawFileHandle,
+            &mut mount_id as *mut libc::c_int,
+            flags.bits(),
+        )
+    };
+    let err = IOError::last_os_error();
+
+    if ret >= 0 {
+        let len = std::cmp::min(handle.handle_bytes as usize, MAX_HANDLE_SZ);
+        Ok((handle.handle_type, handle.f_handle[..len].to_vec()))
+    } else {
+        Err(Error::NameToHandleAt {
+            dirfd: dirfd.into(),
+            path: path.into(),
+            flags,
+            source: err
+                .raw_os_error()
+                .map(Errno::from_raw_os_error)
+                .expect("syscall failure must result in a real OS error"),
+        })
+    }
 }
 
-// MSRV(1.80): Use LazyLock.
-pub(crate) static OPENAT2_IS_SUPPORTED: Lazy<bool> =
-    Lazy::new(|| openat2(AT_FDCWD, ".", &Default::default()).is_ok());
+/// Wrapper for `open_by_handle_at(2)`, which auto-sets `O_CLOEXEC | O_NOCTTY`.
+///
+/// This is needed because Rust doesn't provide any interface for
+/// `open_by_handle_at(2)`.
+pub(crate) fn open_by_handle_at<Fd: AsFd>(
+    mount_fd: Fd,
+    handle_type: i32,
+    handle_data: &[u8],
+    mut flags: OpenFlags,
+) -> Result<OwnedFd, Error> {
+    let mount_fd = mount_fd.as_fd().hotfix_rustix_fd()?;
+    flags.insert(OpenFlags::O_CLOEXEC | OpenFlags::O_NOCTTY);
 
-bitflags! {
-    /// Wrapper for the underlying `libc`'s `RESOLVE_*` flags.
+    let mut handle = RawFileHandle {
+        handle_bytes: 0,
+        handle_type,
+        f_handle: [0; MAX_HANDLE_SZ],
+    };
+    if handle_data.len() > MAX_HANDLE_SZ {
+        return Err(Error::OpenByHandleAt {
+            mount_fd: mount_fd.into(),
+            handle_type,
+            flags,
+            source: Errno::INVAL,
+        });
+    }
+    handle.handle_bytes = handle_data.len() as u32;
+    handle.f_handle[..handle_data.len()].copy_from_slice(handle_data);
+
+    // SAFETY: Obviously safe-to-use Linux syscall.
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_open_by_handle_at,
+            mount_fd.as_raw_fd(),
+            &mut handle as *mut RawFileHandle,
+            flags.bits(),
+        )
+    } as RawFd;
+    let err = IOError::last_os_error();
+
+    if fd >= 0 {
+        // SAFETY: We know it's a real file descriptor.
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    } else {
+        Err(Error::OpenByHandleAt {
+            mount_fd: mount_fd.into(),
+            handle_type,
+            flags,
+            source: err
+                .raw_os_error()
+                .map(Errno::from_raw_os_error)
+                .expect("syscall failure must result in a real OS error"),
+        })
+    }
+}
+
+/// Wrapper for `fcntl(F_GETFL)`.
+///
+/// This is needed because Rust doesn't provide any interface for getting the
+/// file status flags of a file descriptor.
+pub(crate) fn fcntl_getfl<Fd: AsFd>(fd: Fd) -> Result<OpenFlags, Error> {
+    let fd = fd.as_fd().hotfix_rustix_fd()?;
+
+    rustix_fs::fcntl_getfl(fd)
+        .map(|flags| OpenFlags::from_bits_retain(flags.bits() as _))
+        .map_err(|errno| Error::FcntlGetfl {
+            fd: fd.into(),
+            source: errno,
+        })
+}
+
+/// Wrapper for `dup(2)`.
+///
+/// Unlike [`BorrowedFd::try_clone_to_owned`], the returned file descriptor
+/// does not have `O_CLOEXEC` set.
+pub(crate) fn dup<Fd: AsFd>(fd: Fd) -> Result<OwnedFd, Error> {
+    let fd = fd.as_fd().hotfix_rustix_fd()?;
+
+    rustix::io::dup(fd).map_err(|errno| Error::Dup {
+        fd: fd.into(),
+        source: errno,
+    })
+}
+
+/// Wrapper for `fcntl(F_DUPFD_CLOEXEC)`.
+///
+/// The returned file descriptor is the lowest available file descriptor
+/// greater than or equal to `minfd`.
+pub(crate) fn fcntl_dupfd_cloexec<Fd: AsFd>(fd: Fd, minfd: RawFd) -> Result<OwnedFd, Error> {
+    let fd = fd.as_fd().hotfix_rustix_fd()?;
+
+    rustix::io::fcntl_dupfd_cloexec(fd, minfd).map_err(|errno| Error::FcntlDupfdCloexec {
+        fd: fd.into(),
+        minfd,
+        source: errno,
+    })
+}
+
+/// Wrapper for `execveat(2)`.
+///
+/// This function only returns if the `execveat(2)` failed.
+pub(crate) fn execveat<Fd: AsFd, P: AsRef<Path>>(
+    dirfd: Fd,
+    path: P,
+    argv: &[CString],
+    envp: &[CString],
+    flags: AtFlags,
+) -> Result<Infallible, Error> {
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    let path = path.as_ref();
+
+    let c_path = path.to_c_string();
+    let c_argv = argv
+        .iter()
+        .map(|arg| arg.as_ptr())
+        .chain(iter::once(ptr::null()))
+        .collect::<Vec<_>>();
+    let c_envp = envp
+        .iter()
+        .map(|env| env.as_ptr())
+        .chain(iter::once(ptr::null()))
+        .collect::<Vec<_>>();
+
+    // SAFETY: Obviously safe-to-use Linux syscall. All of the pointer arrays
+    //         are NULL-terminated and outlive the syscall.
+    unsafe {
+        libc::syscall(
+            libc::SYS_execveat,
+            dirfd.as_raw_fd(),
+            c_path.as_ptr(),
+            c_argv.as_ptr(),
+            c_envp.as_ptr(),
+            flags.bits(),
+        )
+    };
+    let err = IOError::last_os_error();
+
+    Err(Error::Execveat {
+        dirfd: dirfd.into(),
+        path: path.into(),
+        argv: argv.to_vec(),
+        envc: envp.len(),
+        flags,
+        source: err
+            .raw_os_error()
+            .map(Errno::from_raw_os_error)
+            .expect("syscall failure must result in a real OS error"),
+    })
+}
+
+/// Wrapper for `getxattr(2)`.
+///
+/// Unlike the other wrappers, this operates on a path because `fgetxattr(2)`
+/// does not work on `O_PATH` file descriptors. Callers are expected to pass a
+/// `/proc/self/fd/$n` magic-link path. The buffer is grown until the value
+/// fits. This is only used on kernels without [`getxattrat`].
+pub(crate) fn getxattr<P: AsRef<Path>, N: AsRef<OsStr>>(
+    path: P,
+    name: N,
+) -> Result<Vec<u8>, Error> {
+    let (path, name) = (path.as_ref(), name.as_ref());
+    let err = |errno| Error::Getxattr {
+        path: path.into(),
+        name: name.into(),
+        source: errno,
+    };
+
+    loop {
+        let size = rustix_fs::getxattr(path, name, &mut []).map_err(err)?;
+        let mut value = vec![0; size];
+        match rustix_fs::getxattr(path, name, &mut value) {
+            Ok(size) => {
+                value.truncate(size);
+                return Ok(value);
+            }
+            // The value grew between the two calls, so try again.
+            Err(Errno::RANGE) => continue,
+            Err(errno) => return Err(err(errno)),
+        }
+    }
+}
+
+/// Wrapper for `setxattr(2)`.
+///
+/// See [`getxattr`] for why this operates on a path.
+pub(crate) fn setxattr<P: AsRef<Path>, N: AsRef<OsStr>>(
+    path: P,
+    name: N,
+    value: &[u8],
+    flags: XattrFlags,
+) -> Result<(), Error> {
+    let (path, name) = (path.as_ref(), name.as_ref());
+
+    rustix_fs::setxattr(path, name, value, flags).map_err(|errno| Error::Setxattr {
+        path: path.into(),
+        name: name.into(),
+        size: value.len(),
+        flags,
+        source: errno,
+    })
+}
+
+/// Wrapper for `removexattr(2)`.
+///
+/// See [`getxattr`] for why this operates on a path.
+pub(crate) fn removexattr<P: AsRef<Path>, N: AsRef<OsStr>>(path: P, name: N) -> Result<(), Error> {
+    let (path, name) = (path.as_ref(), name.as_ref());
+
+    rustix_fs::removexattr(path, name).map_err(|errno| Error::Removexattr {
+        path: path.into(),
+        name: name.into(),
+        source: errno,
+    })
+}
+
+/// Wrapper for `listxattr(2)`, which splits the returned list of names.
+///
+/// See [`getxattr`] for why this operates on a path. The buffer is grown until
+/// the list fits.
+pub(crate) fn listxattr<P: AsRef<Path>>(path: P) -> Result<Vec<OsString>, Error> {
+    let path = path.as_ref();
+    let err = |errno| Error::Listxattr {
+        path: path.into(),
+        source: errno,
+    };
+
+    let list = loop {
+        let size = rustix_fs::listxattr(path, &mut []).map_err(err)?;
+        let mut list = vec![0u8; size];
+        match rustix_fs::listxattr(path, &mut list) {
+            Ok(size) => {
+                list.truncate(size);
+                break list;
+            }
+            // The list grew between the two calls, so try again.
+            Err(Errno::RANGE) => continue,
+            Err(errno) => return Err(err(errno)),
+        }
+    };
+
+    Ok(split_xattr_names(&list))
+}
+
+/// The `*xattrat(2)` syscalls (Linux 6.13). The libc crate does not define
+/// these for most architectures yet, but (as with all recently added syscalls)
+/// they have the same number on every architecture other than alpha and mips.
+#[derive(Clone, Copy, Debug)]
+enum XattratSyscall {
+    Setxattrat = 463,
+    Getxattrat = 464,
+    Listxattrat = 465,
+    Removexattrat = 466,
+}
+
+/// Whether [`XattratSyscall`] has the right syscall numbers for this
+/// architecture.
+const XATTRAT_SYSCALLS_KNOWN: bool = cfg!(any(
+    all(target_arch = "x86_64", target_pointer_width = "64"),
+    target_arch = "x86",
+    target_arch = "aarch64",
+    target_arch = "arm",
+    target_arch = "riscv64",
+    target_arch = "powerpc64",
+    target_arch = "s390x",
+    target_arch = "loongarch64",
+));
+
+/// `struct xattr_args`, as used by `getxattrat(2)` and `setxattrat(2)`.
+#[repr(C)]
+#[derive(Debug)]
+struct XattrArgs {
+    value: u64,
+    size: u32,
+    flags: u32,
+}
+
+// Make sure the struct is the same size as the kernel's XATTR_ARGS_SIZE_VER0.
+const _: () = assert!(std::mem::size_of::<XattrArgs>() == 16);
+
+/// Call one of the `*xattrat(2)` syscalls on `dirfd` and `path` (following
+/// trailing symlinks), with `args` as the syscall-specific arguments.
+///
+/// # Safety
+///
+/// `args` must be valid arguments for `syscall`, and any pointers in `args`
+/// must remain valid for the duration of the call.
+unsafe fn xattrat_unchecked(
+    syscall: XattratSyscall,
+    dirfd: BorrowedFd<'_>,
+    path: &Path,
+    args: [usize; 3],
+) -> Result<usize, Errno> {
+    if !XATTRAT_SYSCALLS_KNOWN {
+        return Err(Errno::NOSYS);
+    }
+    let path = path.to_c_string();
+    let ret = libc::syscall(
+        syscall as libc::c_long,
+        dirfd.as_raw_fd(),
+        path.as_ptr(),
+        0 as libc::c_uint,
+        args[0],
+        args[1],
+        args[2],
+    );
+    if ret >= 0 {
+        Ok(ret as usize)
+    } else {
+        Err(IOError::last_os_error()
+            .raw_os_error()
+            .map(Errno::from_raw_os_error)
+            .expect("syscall failure must result in a real OS error"))
+    }
+}
+
+/// Call one of the `*xattrat(2)` syscalls, failing with `ENOSYS` without
+/// calling into the kernel if [`kernel_features`] reports that they are not
+/// available.
+///
+/// # Safety
+///
+/// See [`xattrat_unchecked`].
+unsafe fn xattrat(
+    syscall: XattratSyscall,
+    dirfd: BorrowedFd<'_>,
+    path: &Path,
+    args: [usize; 3],
+) -> Result<usize, Errno> {
+    if !kernel_features().has_xattrat {
+        return Err(Errno::NOSYS);
+    }
+    xattrat_unchecked(syscall, dirfd, path, args)
+}
+
+/// Wrapper for `getxattrat(2)`.
+///
+/// Unlike [`getxattr`], this can be used with a `/proc/self/fd` directory
+/// handle and a `$n` magic-link path, so the lookup does not depend on the
+/// current directory. If [`kernel_features`] reports that `getxattrat(2)` is
+/// not available, this fails with `ENOSYS`. The buffer is grown until the
+/// value fits.
+pub(crate) fn getxattrat<Fd: AsFd, P: AsRef<Path>, N: AsRef<OsStr>>(
+    dirfd: Fd,
+    path: P,
+    name: N,
+) -> Result<Vec<u8>, Error> {
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    let (path, name) = (path.as_ref(), name.as_ref());
+    let err = |errno| Error::Getxattrat {
+        dirfd: dirfd.into(),
+        path: path.into(),
+        name: name.into(),
+        source: errno,
+    };
+
+    let c_name = name.to_c_string();
+    let get = |value: &mut [u8]| {
+        let args = XattrArgs {
+            value: value.as_mut_ptr() as u64,
+            size: value.len() as u32,
+            flags: 0,
+        };
+        // SAFETY: args and c_name outlive the syscall, and value.len() is the
+        //         size of the buffer args.value points to.
+        unsafe {
+            xattrat(
+                XattratSyscall::Getxattrat,
+                dirfd,
+                path,
+                [
+                    c_name.as_ptr() as usize,
+                    &args as *const XattrArgs as usize,
+                    std::mem::size_of::<XattrArgs>(),
+                ],
+            )
+        }
+    };
+
+    loop {
+        let size = get(&mut []).map_err(err)?;
+        let mut value = vec![0; size];
+        match get(&mut value) {
+            Ok(size) => {
+                value.truncate(size);
+                return Ok(value);
+            }
+            // The value grew between the two calls, so try again.
+            Err(Errno::RANGE) => continue,
+            Err(errno) => return Err(err(errno)),
+        }
+    }
+}
+
+/// Wrapper for `setxattrat(2)`.
+///
+/// See [`getxattrat`] for more details.
+pub(crate) fn setxattrat<Fd: AsFd, P: AsRef<Path>, N: AsRef<OsStr>>(
+    dirfd: Fd,
+    path: P,
+    name: N,
+    value: &[u8],
+    flags: XattrFlags,
+) -> Result<(), Error> {
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    let (path, name) = (path.as_ref(), name.as_ref());
+
+    let c_name = name.to_c_string();
+    let args = XattrArgs {
+        value: value.as_ptr() as u64,
+        size: value.len() as u32,
+        flags: flags.bits(),
+    };
+    // SAFETY: args, value and c_name outlive the syscall.
+    unsafe {
+        xattrat(
+            XattratSyscall::Setxattrat,
+            dirfd,
+            path,
+            [
+                c_name.as_ptr() as usize,
+                &args as *const XattrArgs as usize,
+                std::mem::size_of::<XattrArgs>(),
+            ],
+        )
+    }
+    .map(|_| ())
+    .map_err(|errno| Error::Setxattrat {
+        dirfd: dirfd.into(),
+        path: path.into(),
+        name: name.into(),
+        size: value.len(),
+        flags,
+        source: errno,
+    })
+}
+
+/// Wrapper for `removexattrat(2)`.
+///
+/// See [`getxattrat`] for more details.
+pub(crate) fn removexattrat<Fd: AsFd, P: AsRef<Path>, N: AsRef<OsStr>>(
+    dirfd: Fd,
+    path: P,
+    name: N,
+) -> Result<(), Error> {
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    let (path, name) = (path.as_ref(), name.as_ref());
+
+    let c_name = name.to_c_string();
+    // SAFETY: c_name outlives the syscall.
+    unsafe {
+        xattrat(
+            XattratSyscall::Removexattrat,
+            dirfd,
+            path,
+            [c_name.as_ptr() as usize, 0, 0],
+        )
+    }
+    .map(|_| ())
+    .map_err(|errno| Error::Removexattrat {
+        dirfd: dirfd.into(),
+        path: path.into(),
+        name: name.into(),
+        source: errno,
+    })
+}
+
+/// Wrapper for `listxattrat(2)` which does not consult [`kernel_features`].
+/// This is only used for probing the kernel, all other users should use
+/// [`listxattrat`].
+pub(crate) fn listxattrat_unchecked<Fd: AsFd, P: AsRef<Path>>(
+    dirfd: Fd,
+    path: P,
+) -> Result<usize, Errno> {
+    // SAFETY: A NULL list with size 0 only asks for the size of the list.
+    unsafe {
+        xattrat_unchecked(
+            XattratSyscall::Listxattrat,
+            dirfd.as_fd(),
+            path.as_ref(),
+            [0, 0, 0],
+        )
+    }
+}
+
+/// Wrapper for `listxattrat(2)`, which splits the returned list of names.
+///
+/// See [`getxattrat`] for more details. The buffer is grown until the list
+/// fits.
+pub(crate) fn listxattrat<Fd: AsFd, P: AsRef<Path>>(
+    dirfd: Fd,
+    path: P,
+) -> Result<Vec<OsString>, Error> {
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    let path = path.as_ref();
+    let err = |errno| Error::Listxattrat {
+        dirfd: dirfd.into(),
+        path: path.into(),
+        source: errno,
+    };
+    let list_into = |list: &mut [u8]| {
+        // SAFETY: list.len() is the size of the buffer list points to.
+        unsafe {
+            xattrat(
+                XattratSyscall::Listxattrat,
+                dirfd,
+                path,
+                [list.as_mut_ptr() as usize, list.len(), 0],
+            )
+        }
+    };
+
+    let list = loop {
+        let size = list_into(&mut []).map_err(err)?;
+        let mut list = vec![0u8; size];
+        match list_into(&mut list) {
+            Ok(size) => {
+                list.truncate(size);
+                break list;
+            }
+            // The list grew between the two calls, so try again.
+            Err(Errno::RANGE) => continue,
+            Err(errno) => return Err(err(errno)),
+        }
+    };
+
+    Ok(split_xattr_names(&list))
+}
+
+/// Split the NUL-separated list of names returned by `listxattr(2)`.
+fn split_xattr_names(list: &[u8]) -> Vec<OsString> {
+    list.split(|&c| c == b'\0')
+        .filter(|name| !name.is_empty())
+        .map(|name| OsStr::from_bytes(name).into())
+        .collect()
+}
+
+/// `FS_IOC_ENABLE_VERITY`, which is `_IOW('f', 133, struct fsverity_enable_arg)`.
+const FS_IOC_ENABLE_VERITY: u32 = 0x4080_6685;
+/// `FS_IOC_MEASURE_VERITY`, which is `_IOWR('f', 134, struct fsverity_digest)`.
+const FS_IOC_MEASURE_VERITY: u32 = 0xc004_6686;
+/// `FS_VERITY_MAX_DIGEST_SIZE` (the size of a SHA-512 digest).
+const FS_VERITY_MAX_DIGEST_SIZE: usize = 64;
+
+/// `struct fsverity_enable_arg` from `<linux/fsverity.h>`.
+#[repr(C)]
+struct FsverityEnableArg {
+    version: u32,
+    hash_algorithm: u32,
+    block_size: u32,
+    salt_size: u32,
+    salt_ptr: u64,
+    sig_size: u32,
+    __reserved1: u32,
+    sig_ptr: u64,
+    __reserved2: [u64; 11],
+}
+
+// FS_IOC_ENABLE_VERITY encodes the size of struct fsverity_enable_arg.
+const _: () = assert!(std::mem::size_of::<FsverityEnableArg>() == 128);
+
+/// `struct fsverity_digest` from `<linux/fsverity.h>`, with enough space for
+/// the largest supported digest.
+#[repr(C)]
+struct FsverityDigest {
+    digest_algorithm: u16,
+    digest_size: u16,
+    digest: [u8; FS_VERITY_MAX_DIGEST_SIZE],
+}
+
+/// Wrapper for `ioctl(FS_IOC_ENABLE_VERITY)`.
+///
+/// This is needed because neither Rust nor rustix provide any interface for
+/// fs-verity. `fd` must be opened read-only, and there must be no writable
+/// file descriptors open for the file.
+pub(crate) fn fsverity_enable<Fd: AsFd>(
+    fd: Fd,
+    hash_algorithm: u32,
+    block_size: u32,
+    salt: &[u8],
+) -> Result<(), Error> {
+    let fd = fd.as_fd().hotfix_rustix_fd()?;
+    let err = |errno| Error::FsverityEnable {
+        fd: fd.into(),
+        hash_algorithm,
+        block_size,
+        salt_size: salt.len(),
+        source: errno,
+    };
+
+    let arg = FsverityEnableArg {
+        version: 1,
+        hash_algorithm,
+        block_size,
+        salt_size: salt.len().try_into().map_err(|_| err(Errno::INVAL))?,
+        salt_ptr: salt.as_ptr() as u64,
+        sig_size: 0,
+        __reserved1: 0,
+        sig_ptr: 0,
+        __reserved2: [0; 11],
+    };
+
+    // SAFETY: Obviously safe-to-use Linux ioctl, arg matches the layout of
+    //         struct fsverity_enable_arg and salt outlives the call.
+    let ret = unsafe {
+        libc::ioctl(
+            fd.as_raw_fd(),
+            FS_IOC_ENABLE_VERITY as _,
+            &arg as *const FsverityEnableArg,
+        )
+    };
+    let errno = IOError::last_os_error();
+
+    if ret >= 0 {
+        Ok(())
+    } else {
+        Err(err(errno
+            .raw_os_error()
+            .map(Errno::from_raw_os_error)
+            .expect("syscall failure must result in a real OS error")))
+    }
+}
+
+/// Wrapper for `ioctl(FS_IOC_MEASURE_VERITY)`, returning the digest algorithm
+/// and the digest.
+///
+/// This is needed because neither Rust nor rustix provide any interface for
+/// fs-verity.
+pub(crate) fn fsverity_measure<Fd: AsFd>(fd: Fd) -> Result<(u16, Vec<u8>), Error> {
+    let fd = fd.as_fd().hotfix_rustix_fd()?;
+
+    let mut digest = FsverityDigest {
+        digest_algorithm: 0,
+        digest_size: FS_VERITY_MAX_DIGEST_SIZE as u16,
+        digest: [0; FS_VERITY_MAX_DIGEST_SIZE],
+    };
+
+    // SAFETY: Obviously safe-to-use Linux ioctl, and digest has space for
+    //         digest_size bytes of digest.
+    let ret = unsafe {
+        libc::ioctl(
+            fd.as_raw_fd(),
+            FS_IOC_MEASURE_VERITY as _,
+            &mut digest as *mut FsverityDigest,
+        )
+    };
+    let err = IOError::last_os_error();
+
+    if ret >= 0 {
+        let len = std::cmp::min(digest.digest_size as usize, FS_VERITY_MAX_DIGEST_SIZE);
+        Ok((digest.digest_algorithm, digest.digest[..len].to_vec()))
+    } else {
+        Err(Error::FsverityMeasure {
+            fd: fd.into(),
+            source: err
+                .raw_os_error()
+                .map(Errno::from_raw_os_error)
+                .expect("syscall failure must result in a real OS error"),
+        })
+    }
+}
+
+/// Wrapper for `fstatfs(2)`.
+///
+/// This is needed because Rust doesn't provide any interface for `fstatfs(2)`.
+pub(crate) fn fstatfs<Fd: AsFd>(fd: Fd) -> Result<StatFs, Error> {
+    let fd = fd.as_fd().hotfix_rustix_fd()?;
+
+    rustix_fs::fstatfs(fd).map_err(|errno| Error::Fstatfs {
+        fd: fd.into(),
+        source: errno,
+    })
+}
+
+/// Wrapper for `fstatat(2)`, which auto-sets `AT_NO_AUTOMOUNT |
+/// AT_SYMLINK_NOFOLLOW | AT_EMPTY_PATH`.
+///
+/// This is needed because Rust doesn't provide any interface for `fstatat(2)`.
+pub(crate) fn fstatat<Fd: AsFd, P: AsRef<Path>>(dirfd: Fd, path: P) -> Result<Stat, Error> {
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    let path = path.as_ref();
+    let flags = AtFlags::NO_AUTOMOUNT | AtFlags::SYMLINK_NOFOLLOW | AtFlags::EMPTY_PATH;
+
+    let ret = rustix_fs::statat(dirfd, path, flags);
+    #[cfg(test)]
+    trace::record("fstatat", path, None, ret.as_ref().err().copied());
+    ret.map_err(|errno| Error::Fstatat {
+        dirfd: dirfd.into(),
+        path: path.into(),
+        flags,
+        source: errno,
+    })
+}
+
+pub(crate) fn statx<Fd: AsFd, P: AsRef<Path>>(
+    dirfd: Fd,
+    path: P,
+    mask: StatxFlags,
+) -> Result<Statx, Error> {
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    let path = path.as_ref();
+    let flags = AtFlags::NO_AUTOMOUNT | AtFlags::SYMLINK_NOFOLLOW | AtFlags::EMPTY_PATH;
+
+    let ret = if kernel_features().has_statx {
+        rustix_fs::statx(dirfd, path, flags, mask)
+    } else {
+        Err(Errno::NOSYS)
+    };
+    #[cfg(test)]
+    let ret = match hooks::injected_statx_errno() {
+        Some(errno) => Err(errno),
+        None => ret,
+    };
+    #[cfg(test)]
+    trace::record("statx", path, None, ret.as_ref().err().copied());
+    ret.map_err(|errno| Error::Statx {
+        dirfd: dirfd.into(),
+        path: path.into(),
+        flags,
+        mask,
+        source: errno,
+    })
+}
+
+bitflags! {
+    /// Wrapper for the underlying `libc`'s `RESOLVE_*` flags.
     ///
     /// The flag values and their meaning is identical to the description in the
     /// [`openat2(2)`] man page.
@@ -672,9 +1765,35 @@ impl fmt::Display for OpenHow {
     }
 }
 
+/// Wrapper for `openat2(2)`.
+///
+/// If [`kernel_features`] reports that `openat2(2)` is not available, this
+/// fails with `ENOSYS` without calling into the kernel.
+pub(crate) fn openat2<Fd: AsFd, P: AsRef<Path>>(
+    dirfd: Fd,
+    path: P,
+    how: &OpenHow,
+) -> Result<OwnedFd, Error> {
+    if !kernel_features().has_openat2 {
+        let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+        let mut how = how.clone();
+        how.flags |= libc::O_CLOEXEC as u64;
+        return Err(Error::Openat2 {
+            dirfd: dirfd.into(),
+            path: path.as_ref().into(),
+            how,
+            size: std::mem::size_of::<OpenHow>(),
+            source: Errno::NOSYS,
+        });
+    }
+    openat2_unchecked(dirfd, path, how)
+}
+
+/// Wrapper for `openat2(2)` which does not consult [`kernel_features`]. This is
+/// only used for probing the kernel, all other users should use [`openat2`].
 // NOTE: rustix's openat2 wrapper is not extensible-friendly so we use our own
 // for now. See <https://github.com/bytecodealliance/rustix/issues/1186>.
-pub(crate) fn openat2<Fd: AsFd, P: AsRef<Path>>(
+pub(crate) fn openat2_unchecked<Fd: AsFd, P: AsRef<Path>>(
     dirfd: Fd,
     path: P,
     how: &OpenHow,
@@ -716,6 +1835,54 @@ pub(crate) fn openat2<Fd: AsFd, P: AsRef<Path>>(
     }
 }
 
+/// Wrapper for `faccessat2(2)`.
+///
+/// If `path` is empty, `AT_EMPTY_PATH` is set automatically so that the access
+/// check applies to `dirfd` itself. Unlike `faccessat(2)`, the kernel honours
+/// `AT_EACCESS` and `AT_SYMLINK_NOFOLLOW` here, rather than emulating them in
+/// userspace (which glibc does by stat-ing the path).
+pub(crate) fn faccessat2<Fd: AsFd, P: AsRef<Path>>(
+    dirfd: Fd,
+    path: P,
+    mode: AccessMode,
+    flags: AccessFlags,
+) -> Result<(), Error> {
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    let path = path.as_ref();
+
+    let mut raw_flags = flags.bits();
+    if path.as_os_str().is_empty() {
+        raw_flags |= libc::AT_EMPTY_PATH;
+    }
+
+    // SAFETY: Obviously safe-to-use Linux syscall.
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_faccessat2,
+            dirfd.as_raw_fd(),
+            path.to_c_string().as_ptr(),
+            mode.bits(),
+            raw_flags,
+        )
+    };
+    let err = IOError::last_os_error();
+
+    if ret >= 0 {
+        Ok(())
+    } else {
+        Err(Error::Faccessat2 {
+            dirfd: dirfd.into(),
+            path: path.into(),
+            mode,
+            flags,
+            source: err
+                .raw_os_error()
+                .map(Errno::from_raw_os_error)
+                .expect("syscall failure must result in a real OS error"),
+        })
+    }
+}
+
 #[cfg(test)]
 pub(crate) fn getpid() -> rustix_process::RawPid {
     rustix_process::Pid::as_raw(Some(rustix_process::getpid()))
@@ -806,3 +1973,425 @@ pub(crate) fn open_tree<Fd: AsFd, P: AsRef<Path>>(
         source: errno,
     })
 }
+
+pub(crate) fn move_mount<Fd1: AsFd, P1: AsRef<Path>, Fd2: AsFd, P2: AsRef<Path>>(
+    from_dirfd: Fd1,
+    from_path: P1,
+    to_dirfd: Fd2,
+    to_path: P2,
+    flags: MoveMountFlags,
+) -> Result<(), Error> {
+    let from_dirfd = from_dirfd.as_fd().hotfix_rustix_fd()?;
+    let from_path = from_path.as_ref();
+    let to_dirfd = to_dirfd.as_fd().hotfix_rustix_fd()?;
+    let to_path = to_path.as_ref();
+
+    rustix_mount::move_mount(from_dirfd, from_path, to_dirfd, to_path, flags).map_err(|errno| {
+        Error::MoveMount {
+            from_dirfd: from_dirfd.into(),
+            from_path: from_path.into(),
+            to_dirfd: to_dirfd.into(),
+            to_path: to_path.into(),
+            flags,
+            source: errno,
+        }
+    })
+}
+
+pub(crate) fn mount_change<P: AsRef<Path>>(
+    target: P,
+    flags: MountPropagationFlags,
+) -> Result<(), Error> {
+    let target = target.as_ref();
+
+    rustix_mount::mount_change(target, flags).map_err(|errno| Error::MountChange {
+        target: target.into(),
+        flags,
+        source: errno,
+    })
+}
+
+pub(crate) fn umount2<P: AsRef<Path>>(target: P, flags: UnmountFlags) -> Result<(), Error> {
+    let target = target.as_ref();
+
+    rustix_mount::unmount(target, flags).map_err(|errno| Error::Umount2 {
+        target: target.into(),
+        flags,
+        source: errno,
+    })
+}
+
+pub(crate) fn pivot_root<P1: AsRef<Path>, P2: AsRef<Path>>(
+    new_root: P1,
+    put_old: P2,
+) -> Result<(), Error> {
+    let new_root = new_root.as_ref();
+    let put_old = put_old.as_ref();
+
+    rustix_process::pivot_root(new_root, put_old).map_err(|errno| Error::PivotRoot {
+        new_root: new_root.into(),
+        put_old: put_old.into(),
+        source: errno,
+    })
+}
+
+pub(crate) fn fchdir<Fd: AsFd>(fd: Fd) -> Result<(), Error> {
+    let fd = fd.as_fd().hotfix_rustix_fd()?;
+
+    rustix_process::fchdir(fd).map_err(|errno| Error::Fchdir {
+        fd: fd.into(),
+        source: errno,
+    })
+}
+
+pub(crate) fn unshare(flags: UnshareFlags) -> Result<(), Error> {
+    rustix_thread::unshare(flags).map_err(|errno| Error::Unshare {
+        flags,
+        source: errno,
+    })
+}
+
+/// A read-only `MAP_PRIVATE` mapping of a file, unmapped when dropped.
+#[derive(Debug)]
+pub(crate) struct PrivateMapping {
+    addr: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: The mapping is exclusively owned by PrivateMapping and can only be
+//         modified through &mut self, so it is safe to share or move between
+//         threads.
+unsafe impl Send for PrivateMapping {}
+unsafe impl Sync for PrivateMapping {}
+
+impl PrivateMapping {
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        // SAFETY: addr is either a dangling (but aligned) pointer with len 0,
+        //         or a live mapping of len bytes which is only unmapped when
+        //         self is dropped.
+        unsafe { slice::from_raw_parts(self.addr.as_ptr(), self.len) }
+    }
+
+    /// Get a mutable view of the mapping, which must still be writable (that
+    /// is, it must come from [`mmap_anonymous`] and not have been made
+    /// read-only yet).
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: As with as_slice, and &mut self guarantees that there are no
+        //         other references to the mapping.
+        unsafe { slice::from_raw_parts_mut(self.addr.as_ptr(), self.len) }
+    }
+
+    /// Make the mapping read-only.
+    pub(crate) fn make_readonly(&self) -> Result<(), Error> {
+        if self.len == 0 {
+            return Ok(());
+        }
+        let prot = MprotectFlags::READ;
+        // SAFETY: This only changes the protection of the mapping we own.
+        unsafe { rustix_mm::mprotect(self.addr.as_ptr().cast(), self.len, prot) }.map_err(|errno| {
+            Error::Mprotect {
+                len: self.len,
+                prot,
+                source: errno,
+            }
+        })
+    }
+}
+
+impl Drop for PrivateMapping {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: We own this mapping and no references to it can outlive
+            //         self. There is nothing useful we can do on error.
+            let _ = unsafe { rustix_mm::munmap(self.addr.as_ptr().cast(), self.len) };
+        }
+    }
+}
+
+/// Create a private read-only mapping of the first `len` bytes of `fd`.
+///
+/// The mapping is never written to, so its pages are shared with the page
+/// cache (and changes to the file may be visible through it). If the file is
+/// truncated, accessing the truncated pages will cause a `SIGBUS`.
+pub(crate) fn mmap_private<Fd: AsFd>(fd: Fd, len: usize) -> Result<PrivateMapping, Error> {
+    let fd = fd.as_fd().hotfix_rustix_fd()?;
+
+    if len == 0 {
+        // mmap(2) does not permit empty mappings.
+        return Ok(PrivateMapping {
+            addr: NonNull::dangling(),
+            len,
+        });
+    }
+
+    let prot = ProtFlags::READ;
+    let flags = MapFlags::PRIVATE;
+    // SAFETY: We are creating a new mapping (not replacing an existing one)
+    //         and the returned pointer is only used through PrivateMapping.
+    let addr =
+        unsafe { rustix_mm::mmap(ptr::null_mut(), len, prot, flags, fd, 0) }.map_err(|errno| {
+            Error::Mmap {
+                len,
+                prot,
+                flags,
+                fd: fd.into(),
+                source: errno,
+            }
+        })?;
+    Ok(PrivateMapping {
+        addr: NonNull::new(addr.cast()).expect("mmap(2) should never return NULL"),
+        len,
+    })
+}
+
+/// Create a private anonymous read-write mapping of `len` bytes.
+///
+/// The caller is expected to fill the mapping (with
+/// [`PrivateMapping::as_mut_slice`]) and then make it read-only (with
+/// [`PrivateMapping::make_readonly`]). Unlike a mapping of a file, the contents
+/// of the mapping are not affected by any later changes to the file they were
+/// copied from (including truncation).
+pub(crate) fn mmap_anonymous(len: usize) -> Result<PrivateMapping, Error> {
+    if len == 0 {
+        // mmap(2) does not permit empty mappings.
+        return Ok(PrivateMapping {
+            addr: NonNull::dangling(),
+            len,
+        });
+    }
+
+    let prot = ProtFlags::READ | ProtFlags::WRITE;
+    let flags = MapFlags::PRIVATE;
+    // SAFETY: We are creating a new mapping (not replacing an existing one)
+    //         and the returned pointer is only used through PrivateMapping.
+    let addr = unsafe { rustix_mm::mmap_anonymous(ptr::null_mut(), len, prot, flags) }.map_err(
+        |errno| Error::MmapAnonymous {
+            len,
+            prot,
+            flags,
+            source: errno,
+        },
+    )?;
+    Ok(PrivateMapping {
+        addr: NonNull::new(addr.cast()).expect("mmap(2) should never return NULL"),
+        len,
+    })
+}
+
+/// The maximum number of `openat(2)` operations submitted to the `io_uring` at
+/// once by [`openat_follow_batch`].
+#[cfg(feature = "io-uring")]
+const IO_URING_BATCH_SIZE: usize = 256;
+
+/// Batched version of [`openat_follow`], which opens every path in `paths`
+/// (relative to `dirfd`) using `IORING_OP_OPENAT` operations submitted to an
+/// `io_uring`.
+///
+/// The returned results are in the same order as `paths`. The outer error is
+/// only returned if the `io_uring` could not be set up (or does not support
+/// `IORING_OP_OPENAT`), in which case no paths were opened and the caller
+/// should fall back to [`openat_follow`].
+#[cfg(feature = "io-uring")]
+pub(crate) fn openat_follow_batch<Fd: AsFd, P: AsRef<Path>>(
+    dirfd: Fd,
+    paths: &[P],
+    mut flags: OpenFlags,
+) -> Result<Vec<Result<OwnedFd, Error>>, Error> {
+    use io_uring::{opcode, types, IoUring, Probe};
+
+    let dirfd = dirfd.as_fd().hotfix_rustix_fd()?;
+    // O_CLOEXEC is needed for obvious reasons, and O_NOCTTY ensures that a
+    // malicious file won't take control of our terminal.
+    flags.insert(OpenFlags::IMPLICIT_FLAGS);
+
+    let entries = paths.len().clamp(1, IO_URING_BATCH_SIZE);
+    // entries is at most IO_URING_BATCH_SIZE, so this cannot truncate.
+    let entries = entries as u32;
+    let mut ring = IoUring::new(entries).map_err(|err| Error::IoUringSetup {
+        entries,
+        source: Errno::from_io_error(&err).unwrap_or(Errno::IO),
+    })?;
+
+    // IORING_OP_OPENAT was only added in Linux 5.6, and older kernels will
+    // fail each operation with EINVAL rather than refusing to set up the ring.
+    let mut probe = Probe::new();
+    ring.submitter()
+        .register_probe(&mut probe)
+        .map_err(|err| Error::IoUringProbe {
+            source: Errno::from_io_error(&err).unwrap_or(Errno::IO),
+        })?;
+    if !probe.is_supported(opcode::OpenAt::CODE) {
+        Err(Error::IoUringProbe {
+            source: Errno::OPNOTSUPP,
+        })?
+    }
+
+    let mut results: Vec<Result<OwnedFd, Errno>> = Vec::with_capacity(paths.len());
+    let mut ring_error = None;
+    for chunk in paths.chunks(IO_URING_BATCH_SIZE) {
+        // If a previous io_uring_enter(2) failed, the ring is in an unknown
+        // state (there may be unsubmitted entries left in it) so we cannot
+        // submit anything else.
+        if let Some(errno) = ring_error {
+            results.extend(chunk.iter().map(|_| Err(errno)));
+            continue;
+        }
+
+        // The paths must stay alive until the operations have been submitted
+        // (the kernel copies the path when the operation is submitted).
+        let c_paths: Vec<CString> = chunk
+            .iter()
+            .map(|path| path.as_ref().as_os_str().to_c_string())
+            .collect();
+        {
+            let mut sq = ring.submission();
+            for (idx, c_path) in c_paths.iter().enumerate() {
+                let entry = opcode::OpenAt::new(types::Fd(dirfd.as_raw_fd()), c_path.as_ptr())
+                    .flags(flags.bits())
+                    .build()
+                    .user_data(idx as u64);
+                // SAFETY: The submission queue has room for the whole chunk,
+                //         and c_path outlives the submission (c_paths is only
+                //         dropped after io_uring_enter(2) has returned, and
+                //         the ring is never submitted again if it failed).
+                unsafe { sq.push(&entry) }.expect("submission queue should have room for chunk");
+            }
+        }
+
+        let mut chunk_results: Vec<Option<Result<OwnedFd, Errno>>> =
+            iter::repeat_with(|| None).take(chunk.len()).collect();
+        let mut pending = chunk.len();
+        while pending > 0 {
+            if let Err(err) = ring.submit_and_wait(pending) {
+                let errno = Errno::from_io_error(&err).unwrap_or(Errno::IO);
+                if errno == Errno::INTR {
+                    continue;
+                }
+                ring_error = Some(errno);
+            }
+            for cqe in ring.completion() {
+                let idx = cqe.user_data() as usize;
+                let ret = cqe.result();
+                chunk_results[idx] = Some(if ret >= 0 {
+                    // SAFETY: A successful IORING_OP_OPENAT returns a new file
+                    //         descriptor which we now own.
+                    Ok(unsafe { OwnedFd::from_raw_fd(ret) })
+                } else {
+                    Err(Errno::from_raw_os_error(-ret))
+                });
+            }
+            if ring_error.is_some() {
+                break;
+            }
+            pending = chunk_results.iter().filter(|res| res.is_none()).count();
+        }
+
+        // Any operations without a completion are reported as having failed
+        // with the io_uring_enter(2) error.
+        results.extend(chunk_results.into_iter().map(|result| {
+            result.unwrap_or_else(|| Err(ring_error.expect("incomplete operations imply an error")))
+        }));
+    }
+
+    Ok(paths
+        .iter()
+        .zip(results)
+        .map(|(path, result)| {
+            let path = path.as_ref();
+            #[cfg(test)]
+            trace::record("openat", path, Some(flags), result.as_ref().err().copied());
+            result.map_err(|errno| Error::Openat {
+                dirfd: dirfd.into(),
+                path: path.into(),
+                flags,
+                mode: 0,
+                source: errno,
+            })
+        })
+        .collect())
+}
+
+/// Test-only hooks for making syscalls fail with a specific errno, to exercise
+/// error paths that are hard to trigger with real filesystems.
+#[cfg(test)]
+pub(crate) mod hooks {
+    use std::cell::Cell;
+
+    use rustix::io::Errno;
+
+    thread_local! {
+        static INJECT_STATX_ERRNO: Cell<Option<Errno>> = const { Cell::new(None) };
+    }
+
+    /// Returns the errno that a `statx(2)` call should fail with, if any.
+    pub(super) fn injected_statx_errno() -> Option<Errno> {
+        INJECT_STATX_ERRNO.with(Cell::get)
+    }
+
+    /// Run `f` with all `statx(2)` calls on this thread failing with `errno`.
+    pub(crate) fn with_injected_statx_errno<T, F>(errno: Errno, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let old = INJECT_STATX_ERRNO.with(|cur| cur.replace(Some(errno)));
+        let ret = f();
+        INJECT_STATX_ERRNO.with(|cur| cur.replace(old));
+        ret
+    }
+}
+
+/// Test-only recorder for the syscalls made during an operation.
+///
+/// This lets tests assert the exact sequence of lookup syscalls made by a
+/// resolver (such as every intermediate `openat(2)` using `O_NOFOLLOW`). Only
+/// the path-based lookup syscalls (`openat(2)` and `readlinkat(2)`) are
+/// recorded, and only on the thread which called [`trace::capture`].
+#[cfg(test)]
+pub(crate) mod trace {
+    use crate::flags::OpenFlags;
+
+    use std::{cell::RefCell, path::Path, path::PathBuf};
+
+    use rustix::io::Errno;
+
+    /// A single recorded syscall.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub(crate) struct TraceEntry {
+        pub(crate) syscall: &'static str,
+        pub(crate) path: PathBuf,
+        pub(crate) flags: Option<OpenFlags>,
+        pub(crate) result: Result<(), Errno>,
+    }
+
+    thread_local! {
+        static TRACE: RefCell<Option<Vec<TraceEntry>>> = const { RefCell::new(None) };
+    }
+
+    pub(super) fn record(
+        syscall: &'static str,
+        path: &Path,
+        flags: Option<OpenFlags>,
+        err: Option<Errno>,
+    ) {
+        TRACE.with(|trace| {
+            if let Some(ref mut trace) = *trace.borrow_mut() {
+                trace.push(TraceEntry {
+                    syscall,
+                    path: path.into(),
+                    flags,
+                    result: err.map_or(Ok(()), Err),
+                })
+            }
+        })
+    }
+
+    /// Run `f` and return its result alongside the syscalls it made.
+    pub(crate) fn capture<T, F: FnOnce() -> T>(f: F) -> (T, Vec<TraceEntry>) {
+        let old = TRACE.with(|trace| trace.replace(Some(Vec::new())));
+        let ret = f();
+        let entries = TRACE
+            .with(|trace| trace.replace(old))
+            .expect("syscall trace should still be active");
+        (ret, entries)
+    }
+}
@@ -63,7 +63,9 @@ pub(in crate::tests) mod traits {
     pub(in crate::tests) use error::*;
 }
 
+mod test_cloexec;
 mod test_procfs;
 mod test_resolve;
+mod test_resolve_conformance;
 mod test_resolve_partial;
 mod test_root_ops;
@@ -57,8 +57,11 @@ impl CapiRoot {
         Self { inner: fd.into() }
     }
 
-    fn try_clone(&self) -> Result<Self, anyhow::Error> {
-        Ok(Self::from_fd(self.inner.try_clone()?))
+    fn try_clone(&self) -> Result<Self, CapiError> {
+        let root_fd = self.inner.as_fd();
+
+        capi_utils::call_capi_fd(|| capi::core::pathrs_root_clone(root_fd.into()))
+            .map(Self::from_fd)
     }
 
     fn resolve<P: AsRef<Path>>(&self, path: P) -> Result<CapiHandle, CapiError> {
@@ -273,7 +276,7 @@ impl RootImpl for CapiRoot {
     }
 
     fn try_clone(&self) -> Result<Self::Cloned, anyhow::Error> {
-        self.try_clone()
+        self.try_clone().map_err(From::from)
     }
 
     fn resolve<P: AsRef<Path>>(&self, path: P) -> Result<Self::Handle, Self::Error> {
@@ -360,7 +363,7 @@ impl RootImpl for &CapiRoot {
     }
 
     fn try_clone(&self) -> Result<Self::Cloned, anyhow::Error> {
-        CapiRoot::try_clone(self)
+        CapiRoot::try_clone(self).map_err(From::from)
     }
 
     fn resolve<P: AsRef<Path>>(&self, path: P) -> Result<Self::Handle, Self::Error> {
@@ -42,7 +42,7 @@ where
 
             if kind != *expected_kind && result_errno != expected_errno {
                 anyhow::bail!(
-                    "expected error {expected_kind:?} (errno: {expected_errno:?}) but got {error:?} (kind: {kind:?}, errno: {result_errno:?})"
+                    "expected error {expected_kind:?} (errno: {expected_errno}) but got {error:?} (kind: {kind:?}, errno: {result_errno})"
                 );
             }
         }
@@ -176,6 +176,17 @@ pub fn create_basic_tree() -> Result<TempDir, Error> {
         "link3/target_rel" => (symlink -> "../link2/link1_rel/target_rel");
         "link3/deep_dangling1" => (symlink -> "../link2/link1_rel/target_rel/nonexist");
         "link3/deep_dangling2" => (symlink -> "../link2/link1_abs/target_abs/nonexist");
+        // Symlinks with unnormalised targets.
+        "unclean/dot_rel" => (symlink -> "./.././b/./c/./d");
+        "unclean/dot_abs" => (symlink -> "/./b/./c/./d/.");
+        "unclean/slash_rel" => (symlink -> "..//b///c//d//");
+        "unclean/slash_abs" => (symlink -> "//b///c//d");
+        "unclean/dotdot_rel" => (symlink -> "../b/c/../c/d/e/../../d");
+        "unclean/dotdot_abs" => (symlink -> "/b/../b/c/d/../../c/d");
+        "unclean/mixed" => (symlink -> ".././/b/./c/..//c/.//d/e/f/../..//");
+        "unclean/file_slash" => (symlink -> "../b//c/file/");
+        "unclean/file_dot" => (symlink -> "../b/./c/file/.");
+        "unclean/file_dotdot" => (symlink -> "../b/c/file/../file");
         // Deep dangling symlinks (with single components).
         "dangling/a" => (symlink -> "b/c");
         "dangling/b/c" => (symlink -> "../c");
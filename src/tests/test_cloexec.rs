@@ -0,0 +1,140 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Make sure that none of the file descriptors we hand out are leaked across
+//! `execve(2)`.
+
+use crate::{flags::OpenFlags, resolvers::ResolverBackend, tests::common as tests_common, Root};
+
+use std::{
+    fs::Permissions,
+    os::unix::{
+        fs::PermissionsExt,
+        io::{AsFd, AsRawFd, RawFd},
+    },
+    process::Command,
+};
+
+use anyhow::{Context, Error};
+use pretty_assertions::assert_eq;
+
+/// Spawn a helper program which lists which of `fds` it inherited.
+fn inherited_fds(fds: &[RawFd]) -> Result<Vec<RawFd>, Error> {
+    // /proc/self is the shell itself here, because test(1) is a builtin.
+    let output = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(r#"for fd in "$@"; do if [ -e "/proc/self/fd/$fd" ]; then echo "$fd"; fi; done"#)
+        .arg("sh")
+        .args(fds.iter().map(RawFd::to_string))
+        .output()
+        .context("run fd listing helper")?;
+    assert!(
+        output.status.success(),
+        "fd listing helper failed: {output:?}"
+    );
+
+    String::from_utf8(output.stdout)?
+        .lines()
+        .map(|fd| fd.parse().context("parse fd from helper"))
+        .collect()
+}
+
+#[test]
+fn cloexec_helper_detects_leak() -> Result<(), Error> {
+    let root_dir = tests_common::create_basic_tree()?;
+    let root = Root::open(&root_dir)?;
+    // A plain dup(2) does not have O_CLOEXEC set, so it must be inherited.
+    let leaked = rustix::io::dup(&root)?;
+
+    assert_eq!(
+        inherited_fds(&[root.as_fd().as_raw_fd(), leaked.as_fd().as_raw_fd()])?,
+        vec![leaked.as_fd().as_raw_fd()],
+        "only the non-O_CLOEXEC dup should have been inherited"
+    );
+    Ok(())
+}
+
+#[test]
+fn cloexec_reopen_raw_inherited() -> Result<(), Error> {
+    let root_dir = tests_common::create_basic_tree()?;
+    let handle = Root::open(&root_dir)?.resolve("b-file")?;
+
+    let cloexec = handle.reopen_raw(OpenFlags::O_RDONLY | OpenFlags::O_CLOEXEC)?;
+    let inherited = handle.reopen_raw(OpenFlags::O_RDONLY)?;
+
+    assert_eq!(
+        inherited_fds(&[cloexec.as_fd().as_raw_fd(), inherited.as_fd().as_raw_fd()])?,
+        vec![inherited.as_fd().as_raw_fd()],
+        "only the reopen_raw fd without O_CLOEXEC should have been inherited"
+    );
+    Ok(())
+}
+
+macro_rules! cloexec_tests {
+    ($(fn $test_name:ident($backend:expr);)*) => {
+        paste::paste! {
+            $(
+                #[test]
+                fn [<cloexec_no_leak_ $test_name>]() -> Result<(), Error> {
+                    if !$backend.supported() {
+                        // Skip if not supported.
+                        return Ok(());
+                    }
+
+                    let root_dir = tests_common::create_basic_tree()?;
+                    let root = Root::open(&root_dir)?.with_resolver_backend($backend);
+
+                    let root_clone = root.try_clone()?;
+                    let dir = root.resolve("a")?;
+                    let dir_clone = dir.try_clone()?;
+                    let symlink = root.resolve_nofollow("b-file")?;
+                    let file = root.resolve("b-file")?.reopen(OpenFlags::O_RDWR)?;
+                    let new_file = root.create_file(
+                        "a/new-file",
+                        OpenFlags::O_EXCL | OpenFlags::O_WRONLY,
+                        &Permissions::from_mode(0o644),
+                    )?;
+                    let new_dir = root.mkdir_all("e/f/g", &Permissions::from_mode(0o755))?;
+
+                    let fds = [
+                        root.as_fd().as_raw_fd(),
+                        root_clone.as_fd().as_raw_fd(),
+                        dir.as_fd().as_raw_fd(),
+                        dir_clone.as_fd().as_raw_fd(),
+                        symlink.as_fd().as_raw_fd(),
+                        file.as_fd().as_raw_fd(),
+                        new_file.as_fd().as_raw_fd(),
+                        new_dir.as_fd().as_raw_fd(),
+                    ];
+                    assert_eq!(
+                        inherited_fds(&fds)?,
+                        Vec::<RawFd>::new(),
+                        "libpathrs file descriptors should not be inherited across exec"
+                    );
+                    Ok(())
+                }
+            )*
+        }
+    }
+}
+
+cloexec_tests! {
+    fn openat2(ResolverBackend::KernelOpenat2);
+    fn opath(ResolverBackend::EmulatedOpath);
+}
@@ -22,6 +22,7 @@ use crate::tests::capi::CapiProcfsHandle;
 use crate::{
     error::ErrorKind,
     flags::OpenFlags,
+    kernel,
     procfs::{ProcfsBase, ProcfsHandle},
     resolvers::procfs::ProcfsResolver,
     syscalls,
@@ -49,7 +50,7 @@ macro_rules! procfs_tests {
             #[test]
             $(#[$meta])*
             fn [<procfs_overmounts_ $func_prefix openat2_ $test_name>]() -> Result<(), Error> {
-                if !*syscalls::OPENAT2_IS_SUPPORTED {
+                if !kernel::kernel_features().has_openat2 {
                     // skip this test
                     return Ok(());
                 }
@@ -146,6 +147,13 @@ macro_rules! procfs_tests {
                 { ProcfsHandle::new_unsafe_open() }.$procfs_op($($args)*) => (over_mounts: true, $($tt)*);
         }
 
+        procfs_tests! {
+            @rust-fn [<new_unsafe_open_strict_mnt_id_ $test_name>]
+                {
+                    ProcfsHandle::new_unsafe_open().and_then(|proc| proc.with_strict_mnt_id(true))
+                }.$procfs_op($($args)*) => (over_mounts: true, $($tt)*);
+        }
+
         // Assume that GLOBAL_PROCFS_HANDLE is fsopen(2)-based.
         //
         // TODO: Figure out the fd type of GLOBAL_PROCFS_HANDLE. In principle we
@@ -271,6 +279,72 @@ procfs_tests! {
     proc_dir_odir_opath_onofollow: open(self, "fd", O_DIRECTORY|O_PATH) => (error: Ok);
 }
 
+// ProcfsHandle::{open_self,open_pid} are not exposed through the C API, so we
+// only test the Rust implementation.
+macro_rules! procfs_open_verified_tests {
+    ($($test_name:ident : $procfs_op:ident ($($args:expr),*) => ($($tt:tt)*));* $(;)?) => {
+        $(
+            procfs_tests! {
+                @rust-fn [<new_ $test_name>]
+                    { ProcfsHandle::new() }.$procfs_op($($args),*) => (over_mounts: false, $($tt)*);
+            }
+
+            procfs_tests! {
+                @rust-fn [<new_unsafe_open_ $test_name>]
+                    { ProcfsHandle::new_unsafe_open() }.$procfs_op($($args),*) => (over_mounts: true, $($tt)*);
+            }
+        )*
+    }
+}
+
+procfs_open_verified_tests! {
+    open_self_tmpfs_dir: open_self("fdinfo", OpenFlags::O_DIRECTORY) => (error: ErrOvermount("/proc/self/fdinfo", ErrorKind::OsError(Some(libc::EXDEV))));
+    open_self_proc_file_rd: open_self("mountinfo", OpenFlags::O_RDONLY) => (error: ErrOvermount("/proc/self/mountinfo", ErrorKind::OsError(Some(libc::EXDEV))));
+    open_self_nomount: open_self("status", OpenFlags::O_RDONLY) => (error: Ok);
+    open_pid_tmpfs_dir: open_pid(syscalls::getpid(), "fdinfo", OpenFlags::O_DIRECTORY) => (error: ErrOvermount("/proc/self/fdinfo", ErrorKind::OsError(Some(libc::EXDEV))));
+    open_pid_proc_file_rd: open_pid(syscalls::getpid(), "mountinfo", OpenFlags::O_RDONLY) => (error: ErrOvermount("/proc/self/mountinfo", ErrorKind::OsError(Some(libc::EXDEV))));
+    open_pid_nomount: open_pid(syscalls::getpid(), "status", OpenFlags::O_RDONLY) => (error: Ok);
+}
+
+// Make sure that the Handle xattr methods go through the procfs handle (rather
+// than whatever is mounted on /proc), by replacing /proc with an empty tmpfs.
+#[test]
+#[cfg_attr(not(feature = "_test_as_root"), ignore)]
+fn handle_xattr_fake_proc() -> Result<(), Error> {
+    use crate::{
+        procfs::GLOBAL_PROCFS_HANDLE,
+        tests::common::{self as tests_common, MountType},
+        Root,
+    };
+
+    use std::ffi::OsStr;
+
+    use once_cell::sync::Lazy;
+
+    let dir = tempfile::TempDir::new()?;
+    std::fs::write(dir.path().join("file"), b"")?;
+    let handle = Root::open(dir.path())?.resolve("file")?;
+    let name = OsStr::new("trusted.pathrs.test");
+
+    // The procfs handle must be set up before /proc is replaced.
+    Lazy::force(&GLOBAL_PROCFS_HANDLE);
+    tests_common::in_mnt_ns(|| {
+        tests_common::mount("/proc", MountType::Tmpfs)?;
+        handle.setxattr(name, b"value")?;
+        assert_eq!(
+            handle.getxattr(name)?,
+            b"value",
+            "getxattr should work with a fake /proc"
+        );
+        assert!(
+            handle.listxattr()?.contains(&name.to_os_string()),
+            "listxattr should work with a fake /proc"
+        );
+        handle.removexattr(name)?;
+        Ok(())
+    })
+}
+
 mod utils {
     use std::{
         collections::HashSet,
@@ -281,7 +355,7 @@ mod utils {
     use crate::{
         error::ErrorKind,
         flags::OpenFlags,
-        procfs::ProcfsBase,
+        procfs::{ProcfsBase, ProcfsHandle},
         syscalls,
         tests::{
             common::{self as tests_common, MountType},
@@ -581,4 +655,35 @@ mod utils {
             proc_fn()?.readlink(base, path)
         })
     }
+
+    pub(super) fn check_proc_open_self<ProcFn, P: AsRef<Path>>(
+        proc_fn: ProcFn,
+        path: P,
+        oflags: OpenFlags,
+        are_over_mounts_visible: bool,
+        expected: ExpectedResult,
+    ) -> Result<(), Error>
+    where
+        ProcFn: FnOnce() -> Result<ProcfsHandle, crate::error::Error>,
+    {
+        check_func(are_over_mounts_visible, expected, || {
+            proc_fn()?.open_self(path, oflags)
+        })
+    }
+
+    pub(super) fn check_proc_open_pid<ProcFn, P: AsRef<Path>>(
+        proc_fn: ProcFn,
+        pid: libc::pid_t,
+        path: P,
+        oflags: OpenFlags,
+        are_over_mounts_visible: bool,
+        expected: ExpectedResult,
+    ) -> Result<(), Error>
+    where
+        ProcFn: FnOnce() -> Result<ProcfsHandle, crate::error::Error>,
+    {
+        check_func(are_over_mounts_visible, expected, || {
+            proc_fn()?.open_pid(pid, path, oflags)
+        })
+    }
 }
@@ -20,11 +20,19 @@
 #[cfg(feature = "capi")]
 use crate::tests::capi::CapiRoot;
 use crate::{
-    error::ErrorKind, flags::ResolverFlags, resolvers::ResolverBackend,
-    tests::common as tests_common, Root,
+    error::ErrorKind,
+    flags::{OpenFlags, ResolverFlags},
+    resolvers::{opath::hooks as opath_hooks, ResolverBackend},
+    tests::common as tests_common,
+    utils::FdExt,
+    Root, SetidPolicy, TrailingSymlinkPolicy,
 };
 
-use std::path::Path;
+use std::{
+    fs::Permissions,
+    os::unix::fs::{self as unixfs, PermissionsExt},
+    path::Path,
+};
 
 use anyhow::Error;
 
@@ -126,6 +134,54 @@ macro_rules! resolve_tests {
                 Ok(())
             }
 
+            #[test]
+            $(#[cfg_attr(not($ignore_meta), ignore)])*
+            fn [<root_ $test_name _hardcore>]() -> Result<(), Error> {
+                let root_dir = $root_dir;
+                let mut $root_var = Root::open(&root_dir)?;
+                $root_var.set_resolver_backend(ResolverBackend::HardcoreEmulated);
+                assert_eq!(
+                    $root_var.resolver_backend(),
+                    ResolverBackend::HardcoreEmulated,
+                    "incorrect ResolverBackend despite using set_resolver_backend"
+                );
+
+                if !$root_var.resolver_backend().supported() {
+                    // Skip if not supported.
+                    return Ok(());
+                }
+
+                { $body }
+
+                // Make sure root_dir is not dropped earlier.
+                let _root_dir = root_dir;
+                Ok(())
+            }
+
+            #[test]
+            $(#[cfg_attr(not($ignore_meta), ignore)])*
+            fn [<rootref_ $test_name _hardcore>]() -> Result<(), Error> {
+                let root_dir = $root_dir;
+                let root = Root::open(&root_dir)?;
+                let mut $root_var = root.as_ref();
+                $root_var.set_resolver_backend(ResolverBackend::HardcoreEmulated);
+                assert_eq!(
+                    $root_var.resolver_backend(),
+                    ResolverBackend::HardcoreEmulated,
+                    "incorrect ResolverBackend despite using set_resolver_backend"
+                );
+                if !$root_var.resolver_backend().supported() {
+                    // Skip if not supported.
+                    return Ok(());
+                }
+
+                { $body }
+
+                // Make sure root_dir is not dropped earlier.
+                let _root_dir = root_dir;
+                Ok(())
+            }
+
             #[test]
             $(#[cfg_attr(not($ignore_meta), ignore)])*
             fn [<root_ $test_name _opath>]() -> Result<(), Error> {
@@ -366,6 +422,29 @@ resolve_tests! {
         nonlexical_level3_rel_complete: resolve("link3/target_rel") => Ok(("/target", libc::S_IFDIR));
         nonlexical_level3_rel_partial: resolve("link3/target_rel/foo") => Err(ErrorKind::OsError(Some(libc::ENOENT)));
         nonlexical_level3_rel_partial_dotdot: resolve("link3/target_rel/../target/foo/bar/../baz") => Err(ErrorKind::OsError(Some(libc::ENOENT)));
+        // Symlink targets are normalised the same way as the input path.
+        unclean_symlink_dot_rel: resolve("unclean/dot_rel") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_dot_rel_input: resolve("./unclean/.././b/./c/./d") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_dot_abs: resolve("unclean/dot_abs") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_dot_abs_input: resolve("/./b/./c/./d/.") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_slash_rel: resolve("unclean/slash_rel") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_slash_rel_input: resolve("unclean/..//b///c//d//") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_slash_abs: resolve("unclean/slash_abs") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_slash_abs_input: resolve("//b///c//d") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_dotdot_rel: resolve("unclean/dotdot_rel") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_dotdot_rel_input: resolve("unclean/../b/c/../c/d/e/../../d") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_dotdot_abs: resolve("unclean/dotdot_abs") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_dotdot_abs_input: resolve("/b/../b/c/d/../../c/d") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_mixed: resolve("unclean/mixed") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_mixed_input: resolve("unclean/.././/b/./c/..//c/.//d/e/f/../..//") => Ok(("/b/c/d", libc::S_IFDIR));
+        unclean_symlink_mixed_partial: resolve("unclean/mixed/./e//foo") => Err(ErrorKind::OsError(Some(libc::ENOENT)));
+        unclean_symlink_mixed_partial_input: resolve("unclean/.././/b/./c/..//c/.//d/e/f/../..///./e//foo") => Err(ErrorKind::OsError(Some(libc::ENOENT)));
+        unclean_symlink_file_slash: resolve("unclean/file_slash") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+        unclean_symlink_file_slash_input: resolve("unclean/../b//c/file/") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+        unclean_symlink_file_dot: resolve("unclean/file_dot") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+        unclean_symlink_file_dot_input: resolve("unclean/../b/./c/file/.") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+        unclean_symlink_file_dotdot: resolve("unclean/file_dotdot") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+        unclean_symlink_file_dotdot_input: resolve("unclean/../b/c/file/../file") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
         // Partial lookups due to hitting a non_directory.
         partial_nondir_slash1: resolve("b/c/file/") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
         partial_nondir_slash2: resolve("b/c/file//") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
@@ -391,6 +470,20 @@ resolve_tests! {
         partial_symlink_nofollow_slash1: resolve("link3/target_abs/", no_follow_trailing = true) => Ok(("/target", libc::S_IFDIR));
         partial_symlink_nofollow_slash2: resolve("link3/target_abs//", no_follow_trailing = true) => Ok(("/target", libc::S_IFDIR));
         partial_symlink_nofollow_dot: resolve("link3/target_abs/.", no_follow_trailing = true) => Ok(("/target", libc::S_IFDIR));
+        // A trailing slash forces the final component (and the target of a
+        // trailing symlink) to be a directory, even with O_NOFOLLOW.
+        trailing_slash_symlink_dir1: resolve("e/") => Ok(("/b/c/d/e", libc::S_IFDIR));
+        trailing_slash_symlink_dir2: resolve("e//") => Ok(("/b/c/d/e", libc::S_IFDIR));
+        trailing_slash_symlink_dir_nofollow1: resolve("e/", no_follow_trailing = true) => Ok(("/b/c/d/e", libc::S_IFDIR));
+        trailing_slash_symlink_dir_nofollow2: resolve("e//", no_follow_trailing = true) => Ok(("/b/c/d/e", libc::S_IFDIR));
+        trailing_slash_symlink_file_nofollow1: resolve("b-file/", no_follow_trailing = true) => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+        trailing_slash_symlink_file_nofollow2: resolve("b-file//", no_follow_trailing = true) => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+        trailing_slash_file_nofollow1: resolve("b/c/file/", no_follow_trailing = true) => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+        trailing_slash_file_nofollow2: resolve("b/c/file//", no_follow_trailing = true) => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+        trailing_slash_symlink_chain_file: resolve("unclean/file_slash/") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+        trailing_slash_symlink_chain_file_nofollow: resolve("unclean/file_slash/", no_follow_trailing = true) => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+        trailing_slash_symlink_dangling: resolve("a-fake1/") => Err(ErrorKind::OsError(Some(libc::ENOENT)));
+        trailing_slash_symlink_dangling_nofollow: resolve("a-fake1/", no_follow_trailing = true) => Err(ErrorKind::OsError(Some(libc::ENOENT)));
         // Dangling symlinks are treated as though they are non_existent.
         dangling1_inroot_trailing: resolve("a-fake1") => Err(ErrorKind::OsError(Some(libc::ENOENT)));
         dangling1_inroot_partial: resolve("a-fake1/foo") => Err(ErrorKind::OsError(Some(libc::ENOENT)));
@@ -446,6 +539,32 @@ resolve_tests! {
         symlink_component_nosym1: resolve("e/f", rflags = NO_SYMLINKS) => Err(ErrorKind::OsError(Some(libc::ELOOP)));
         symlink_component_nosym2: resolve("link2/link1_abs/target_rel", rflags = NO_SYMLINKS) => Err(ErrorKind::OsError(Some(libc::ELOOP)));
         loop_nosym: resolve("loop/link", rflags = NO_SYMLINKS) => Err(ErrorKind::OsError(Some(libc::ELOOP)));
+        // RESOLVE_BENEATH.
+        dir_beneath: resolve("b/c/d/e", rflags = BENEATH) => Ok(("b/c/d/e", libc::S_IFDIR));
+        dotdot_inside_beneath: resolve("b/c/../c/file", rflags = BENEATH) => Ok(("b/c/file", libc::S_IFREG));
+        symlink_rel_beneath: resolve("link3/target_rel", rflags = BENEATH) => Ok(("target", libc::S_IFDIR));
+        absolute_beneath: resolve("/b/c/file", rflags = BENEATH) => Err(ErrorKind::OsError(Some(libc::EXDEV)));
+        root_beneath: resolve("/", rflags = BENEATH) => Err(ErrorKind::OsError(Some(libc::EXDEV)));
+        dotdot_escape1_beneath: resolve("..", rflags = BENEATH) => Err(ErrorKind::OsError(Some(libc::EXDEV)));
+        dotdot_escape2_beneath: resolve("b/c/../../../b", rflags = BENEATH) => Err(ErrorKind::OsError(Some(libc::EXDEV)));
+        symlink_abs_beneath: resolve("e", rflags = BENEATH) => Err(ErrorKind::OsError(Some(libc::EXDEV)));
+        symlink_component_abs_beneath: resolve("link3/target_abs", rflags = BENEATH) => Err(ErrorKind::OsError(Some(libc::EXDEV)));
+        symlink_escape_beneath: resolve("root-link2", rflags = BENEATH) => Err(ErrorKind::OsError(Some(libc::EXDEV)));
+        trailing_slash_file_beneath: resolve("b/c/file/", rflags = BENEATH) => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+        // SINGLE_COMPONENT.
+        dir_single_component: resolve("a", rflags = SINGLE_COMPONENT) => Ok(("a", libc::S_IFDIR));
+        dir_curdir_single_component: resolve("./a/.", rflags = SINGLE_COMPONENT) => Ok(("a", libc::S_IFDIR));
+        dir_trailing_slash_single_component: resolve("a//", rflags = SINGLE_COMPONENT) => Ok(("a", libc::S_IFDIR));
+        absolute_single_component: resolve("/a", rflags = SINGLE_COMPONENT) => Ok(("a", libc::S_IFDIR));
+        nested_single_component: resolve("b/c", rflags = SINGLE_COMPONENT) => Err(ErrorKind::InvalidArgument);
+        nested_file_single_component: resolve("b/c/file", rflags = SINGLE_COMPONENT) => Err(ErrorKind::InvalidArgument);
+        dotdot_single_component: resolve("../a", rflags = SINGLE_COMPONENT) => Err(ErrorKind::InvalidArgument);
+        dotdot_only_single_component: resolve("..", rflags = SINGLE_COMPONENT) => Err(ErrorKind::InvalidArgument);
+        dotdot_trailing_single_component: resolve("a/..", rflags = SINGLE_COMPONENT) => Err(ErrorKind::InvalidArgument);
+        // The path check is purely lexical, so symlinks are still followed
+        // unless NO_SYMLINKS is also set.
+        symlink_single_component: resolve("b-file", rflags = SINGLE_COMPONENT) => Ok(("b/c/file", libc::S_IFREG));
+        symlink_nosym_single_component: resolve("b-file", rflags = SINGLE_COMPONENT | NO_SYMLINKS) => Err(ErrorKind::OsError(Some(libc::ELOOP)));
         // fs.protected_symlinks for a directory owned by us.
         protected_symlinks_selfdir_selfsym: resolve("tmpfs-self/link-self") => Ok(("tmpfs-self/file", libc::S_IFREG));
         protected_symlinks_selfdir_selfsym_nofollow: resolve("tmpfs-self/link-self", no_follow_trailing = true) => Ok(("tmpfs-self/link-self", libc::S_IFLNK));
@@ -489,6 +608,499 @@ resolve_tests! {
     }
 }
 
+// Make sure that RESOLVE_NO_XDEV is enforced (or emulated) by every backend.
+#[test]
+#[cfg_attr(not(feature = "_test_as_root"), ignore)]
+fn root_resolve_no_xdev() -> Result<(), Error> {
+    let root_dir = tests_common::create_basic_tree()?;
+    let mnt_path = root_dir.path().join("b/c/d");
+
+    tests_common::in_mnt_ns(|| {
+        tests_common::mount(&mnt_path, tests_common::MountType::Tmpfs)?;
+        std::fs::create_dir(mnt_path.join("e"))?;
+        std::fs::write(mnt_path.join("file"), b"")?;
+
+        for backend in [
+            ResolverBackend::KernelOpenat2,
+            ResolverBackend::EmulatedOpath,
+            ResolverBackend::HardcoreEmulated,
+        ] {
+            if !backend.supported() {
+                continue;
+            }
+
+            let root = Root::open(&root_dir)?
+                .with_resolver_backend(backend)
+                .with_resolver_flags(ResolverFlags::NO_XDEV);
+            for path in ["b/c", "b/c/file", "b/c/../c/file"] {
+                assert!(
+                    root.resolve(path).is_ok(),
+                    "{backend:?}: resolve({path:?}) without crossing a mount should succeed"
+                );
+            }
+            for path in [
+                "b/c/d",
+                "b/c/d/e",
+                "b/c/d/file",
+                "b/c/d/../file",
+                // Absolute symlink into the mount.
+                "e",
+            ] {
+                assert_eq!(
+                    root.resolve(path).map(|_| ()).map_err(|err| err.kind()),
+                    Err(ErrorKind::OsError(Some(libc::EXDEV))),
+                    "{backend:?}: resolve({path:?}) crossing a mount should fail"
+                );
+                // Without NO_XDEV, the same lookup succeeds.
+                assert!(
+                    root.as_ref()
+                        .with_resolver_flags(ResolverFlags::empty())
+                        .resolve(path)
+                        .is_ok(),
+                    "{backend:?}: resolve({path:?}) without NO_XDEV should succeed"
+                );
+            }
+
+            // Lookups entirely inside the mount are fine.
+            let root = Root::open(&mnt_path)?
+                .with_resolver_backend(backend)
+                .with_resolver_flags(ResolverFlags::NO_XDEV);
+            for path in ["e", "file", "/e", "../../e"] {
+                assert!(
+                    root.resolve(path).is_ok(),
+                    "{backend:?}: resolve({path:?}) inside mount root should succeed"
+                );
+            }
+        }
+
+        Ok(())
+    })
+}
+
+// Make sure that every SetidPolicy rejects (only) the components it should,
+// regardless of backend.
+#[test]
+fn root_resolve_setid_policy() -> Result<(), Error> {
+    let root_dir = tempfile::TempDir::new()?;
+    std::fs::create_dir(root_dir.path().join("sgid"))?;
+    std::fs::write(root_dir.path().join("sgid/file"), b"")?;
+    std::fs::write(root_dir.path().join("suid"), b"")?;
+    std::fs::create_dir(root_dir.path().join("clean"))?;
+    std::fs::write(root_dir.path().join("clean/file"), b"")?;
+    unixfs::symlink("sgid/file", root_dir.path().join("link-sgid-file"))?;
+    unixfs::symlink("/suid", root_dir.path().join("link-suid"))?;
+    std::fs::set_permissions(root_dir.path().join("sgid"), Permissions::from_mode(0o2755))?;
+    std::fs::set_permissions(root_dir.path().join("suid"), Permissions::from_mode(0o4755))?;
+
+    // (path, setid intermediate, setid target)
+    let paths = [
+        ("clean/file", false, false),
+        ("clean/../clean/file", false, false),
+        ("sgid", false, true),
+        ("sgid/", false, true),
+        ("sgid/.", false, true),
+        ("sgid/file", true, false),
+        ("sgid/../clean", true, false),
+        ("link-sgid-file", true, false),
+        ("suid", false, true),
+        ("link-suid", false, true),
+    ];
+
+    for backend in [
+        ResolverBackend::KernelOpenat2,
+        ResolverBackend::EmulatedOpath,
+        ResolverBackend::HardcoreEmulated,
+    ] {
+        if !backend.supported() {
+            continue;
+        }
+
+        for policy in [
+            SetidPolicy::Allow,
+            SetidPolicy::RejectTarget,
+            SetidPolicy::RejectIntermediate,
+            SetidPolicy::RejectAll,
+        ] {
+            let root = Root::open(&root_dir)?
+                .with_resolver_backend(backend)
+                .with_setid_policy(policy);
+
+            let expected = paths
+                .iter()
+                .map(|&(path, intermediate, target)| {
+                    let rejected = (intermediate && policy.rejects_intermediate())
+                        || (target && policy.rejects_target());
+                    (
+                        path,
+                        if rejected {
+                            Err(ErrorKind::SafetyViolation)
+                        } else {
+                            Ok(())
+                        },
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            let got = paths
+                .iter()
+                .map(|&(path, _, _)| {
+                    (
+                        path,
+                        root.resolve(path).map(|_| ()).map_err(|err| err.kind()),
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(got, expected, "{backend:?}: {policy:?}: resolve results");
+
+            let got = paths
+                .iter()
+                .map(|&(path, _, _)| path)
+                .zip(root.resolve_many(paths.iter().map(|&(path, _, _)| path)))
+                .map(|(path, res)| (path, res.map(|_| ()).map_err(|err| err.kind())))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                got, expected,
+                "{backend:?}: {policy:?}: resolve_many results"
+            );
+
+            // Root::open_subpath must not skip the target check.
+            assert_eq!(
+                root.open_subpath("suid", OpenFlags::O_RDONLY)
+                    .map(|_| ())
+                    .map_err(|err| err.kind()),
+                if policy.rejects_target() {
+                    Err(ErrorKind::SafetyViolation)
+                } else {
+                    Ok(())
+                },
+                "{backend:?}: {policy:?}: open_subpath(\"suid\")"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// Make sure that directories deleted in the middle of a walk are only rejected
+// if Root::set_reject_deleted_intermediates is set.
+#[test]
+fn root_resolve_reject_deleted_intermediates() -> Result<(), Error> {
+    let root_dir = tempfile::TempDir::new()?;
+    std::fs::create_dir(root_dir.path().join("a"))?;
+    std::fs::write(root_dir.path().join("a/c"), b"")?;
+
+    // (path, result if not rejected)
+    let paths = [
+        ("a/b/../c", Ok(())),
+        ("a/b/c", Err(ErrorKind::OsError(Some(libc::ENOENT)))),
+    ];
+
+    for backend in [
+        ResolverBackend::KernelOpenat2,
+        ResolverBackend::EmulatedOpath,
+        ResolverBackend::HardcoreEmulated,
+    ] {
+        if !backend.supported() {
+            continue;
+        }
+
+        for reject in [false, true] {
+            let root = Root::open(&root_dir)?
+                .with_resolver_backend(backend)
+                .with_reject_deleted_intermediates(reject);
+            assert_eq!(root.reject_deleted_intermediates(), reject);
+
+            // Delete "a/b" as soon as the walk has opened it.
+            let dir = root_dir.path().join("a/b");
+            let resolve_racing = |resolve: &dyn Fn() -> Result<(), ErrorKind>| {
+                std::fs::create_dir(&dir).expect("create a/b");
+                let hook_dir = dir.clone();
+                opath_hooks::with_after_open(
+                    move |path| {
+                        if path == Path::new("/a/b") {
+                            let _ = std::fs::remove_dir(&hook_dir);
+                        }
+                    },
+                    resolve,
+                )
+            };
+
+            for &(path, ref expected) in &paths {
+                let expected = if reject {
+                    Err(ErrorKind::DeletedInode)
+                } else {
+                    *expected
+                };
+
+                let got =
+                    resolve_racing(&|| root.resolve(path).map(|_| ()).map_err(|err| err.kind()));
+                assert_eq!(
+                    got, expected,
+                    "{backend:?}: reject={reject}: resolve({path:?})"
+                );
+                let _ = std::fs::remove_dir(&dir);
+
+                let got = resolve_racing(&|| {
+                    root.resolve_many([path])
+                        .pop()
+                        .expect("resolve_many should return one result")
+                        .map(|_| ())
+                        .map_err(|err| err.kind())
+                });
+                assert_eq!(
+                    got, expected,
+                    "{backend:?}: reject={reject}: resolve_many([{path:?}])"
+                );
+                let _ = std::fs::remove_dir(&dir);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Make sure that the emulated resolver rejects chains of long symlinks in the
+// same way as the kernel. The kernel only follows 40 symlinks (each at most
+// PATH_MAX-1 bytes long), while the emulated resolver follows up to 128
+// symlinks but limits the total length of the symlink targets it expands.
+#[test]
+fn root_resolve_symlink_target_length_differential() -> Result<(), Error> {
+    // Create a chain l0 -> l1 -> ... -> l40 -> target, where every symlink
+    // target is padded to ~4000 bytes. To keep the number of components (and
+    // thus the runtime of the emulated resolver) down, most of the padding is
+    // made of "<long name>/.." pairs.
+    const CHAIN_LEN: usize = 41;
+    const TARGET_LEN: usize = 4000;
+
+    let root_dir = tempfile::TempDir::new()?;
+    let long_name = "d".repeat(255);
+    std::fs::create_dir(root_dir.path().join(&long_name))?;
+    std::fs::create_dir(root_dir.path().join("target"))?;
+    for idx in 0..CHAIN_LEN {
+        let next = if idx + 1 == CHAIN_LEN {
+            "target".to_string()
+        } else {
+            format!("l{}", idx + 1)
+        };
+        let mut padding = format!("{long_name}/../").repeat((TARGET_LEN - next.len()) / 259);
+        padding += &"./".repeat((TARGET_LEN - next.len() - padding.len()) / 2);
+        unixfs::symlink(padding + &next, root_dir.path().join(format!("l{idx}")))?;
+    }
+
+    for backend in [
+        ResolverBackend::KernelOpenat2,
+        ResolverBackend::EmulatedOpath,
+    ] {
+        if !backend.supported() {
+            continue;
+        }
+        let root = Root::open(&root_dir)?.with_resolver_backend(backend);
+
+        // l1 is 40 symlinks away from target, which both resolvers permit.
+        assert_eq!(
+            root.resolve("l1")?.as_unsafe_path_unchecked()?,
+            root_dir.path().join("target"),
+            "resolve(l1) with {backend:?} should succeed"
+        );
+        // l0 is 41 symlinks away from target. The kernel rejects this because
+        // of MAXSYMLINKS, while the emulated resolver rejects this because of
+        // the total length of the symlink targets.
+        assert_eq!(
+            root.resolve("l0").map(|_| ()).map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::ELOOP))),
+            "resolve(l0) with {backend:?} should fail"
+        );
+    }
+
+    // The emulated resolver only rejects l0 because of the length limit.
+    let root = Root::open(&root_dir)?
+        .with_resolver_backend(ResolverBackend::EmulatedOpath)
+        .with_max_symlink_target_bytes(Some(CHAIN_LEN * TARGET_LEN));
+    assert_eq!(
+        root.resolve("l0")?.as_unsafe_path_unchecked()?,
+        root_dir.path().join("target"),
+        "resolve(l0) with a raised target length limit should succeed"
+    );
+
+    Ok(())
+}
+
+// Make sure that TrailingSymlinkPolicy only restricts symlinks in the final
+// component, regardless of backend.
+#[test]
+fn root_resolve_trailing_symlink_policy() -> Result<(), Error> {
+    let root_dir = tempfile::TempDir::new()?;
+    std::fs::write(root_dir.path().join("file"), b"")?;
+    std::fs::create_dir(root_dir.path().join("dir"))?;
+    std::fs::write(root_dir.path().join("dir/file"), b"")?;
+    unixfs::symlink("file", root_dir.path().join("link-file"))?;
+    unixfs::symlink("link-file", root_dir.path().join("link-link"))?;
+    unixfs::symlink("/dir", root_dir.path().join("dirlink"))?;
+    unixfs::symlink("dirlink/file", root_dir.path().join("link-dirlink"))?;
+    unixfs::symlink(
+        "dirlink/../link-file",
+        root_dir.path().join("link-dirlink-link"),
+    )?;
+
+    // (path, number of trailing symlinks)
+    let paths = [
+        ("file", 0),
+        ("dir/file", 0),
+        ("dirlink/file", 0),
+        ("dirlink/../dir/file", 0),
+        ("link-file", 1),
+        ("dirlink/", 1),
+        ("dirlink/.", 1),
+        ("link-dirlink", 1),
+        ("link-link", 2),
+        ("link-dirlink-link", 2),
+    ];
+
+    for backend in [
+        ResolverBackend::KernelOpenat2,
+        ResolverBackend::EmulatedOpath,
+        ResolverBackend::HardcoreEmulated,
+    ] {
+        if !backend.supported() {
+            continue;
+        }
+
+        for (policy, max) in [
+            (TrailingSymlinkPolicy::FollowUnlimited, None),
+            (TrailingSymlinkPolicy::FollowOnce, Some(1)),
+            (TrailingSymlinkPolicy::Forbid, Some(0)),
+        ] {
+            let root = Root::open(&root_dir)?
+                .with_resolver_backend(backend)
+                .with_trailing_symlink_policy(policy);
+            assert_eq!(root.trailing_symlink_policy(), policy);
+
+            let expected = paths
+                .iter()
+                .map(|&(path, trailing)| {
+                    (
+                        path,
+                        if max.map_or(false, |max| trailing > max) {
+                            Err(ErrorKind::OsError(Some(libc::ELOOP)))
+                        } else {
+                            Ok(())
+                        },
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            let got = paths
+                .iter()
+                .map(|&(path, _)| {
+                    (
+                        path,
+                        root.resolve(path).map(|_| ()).map_err(|err| err.kind()),
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(got, expected, "{backend:?}: {policy:?}: resolve results");
+
+            let got = paths
+                .iter()
+                .map(|&(path, _)| path)
+                .zip(root.resolve_many(paths.iter().map(|&(path, _)| path)))
+                .map(|(path, res)| (path, res.map(|_| ()).map_err(|err| err.kind())))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                got, expected,
+                "{backend:?}: {policy:?}: resolve_many results"
+            );
+
+            // Not following the trailing symlink is always permitted.
+            let handle = root.resolve_nofollow("link-link")?;
+            assert_eq!(
+                handle.as_unsafe_path_unchecked()?,
+                root_dir.path().join("link-link"),
+                "{backend:?}: {policy:?}: resolve_nofollow(\"link-link\")"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// Make sure that Root::resolve_many gives the same results as Root::resolve,
+// even when paths share (possibly symlinked) prefixes.
+#[test]
+fn root_resolve_many() -> Result<(), Error> {
+    let root_dir = tests_common::create_basic_tree()?;
+
+    let paths = [
+        "b/c/file",
+        "b/c/d/e",
+        "b/c/../c/file",
+        "b/c/file/",
+        "b/c/file/..",
+        "b/c/nonexist",
+        "b/c",
+        "",
+        "/",
+        "..",
+        "e/f",
+        "e/../../..",
+        "root-link3/b/c/file",
+        "link2/link1_abs/target_rel",
+        "link3/target_abs",
+        "link3/target_rel",
+        "link3/deep_dangling1",
+        "a-fake3",
+        "c/a-fake3",
+        "dangling/e",
+        "dangling-file/g",
+        "loop/link",
+        "loop/link/foo",
+        "b/c/file",
+    ];
+
+    for backend in [
+        ResolverBackend::KernelOpenat2,
+        ResolverBackend::EmulatedOpath,
+        ResolverBackend::HardcoreEmulated,
+    ] {
+        if !backend.supported() {
+            continue;
+        }
+        for rflags in [
+            ResolverFlags::empty(),
+            ResolverFlags::NO_SYMLINKS,
+            ResolverFlags::BENEATH,
+        ] {
+            let root = Root::open(&root_dir)?
+                .with_resolver_backend(backend)
+                .with_resolver_flags(rflags);
+
+            let expected = paths
+                .iter()
+                .map(|path| {
+                    root.resolve(path)
+                        .and_then(|handle| handle.as_unsafe_path_unchecked())
+                        .map_err(|err| err.kind())
+                })
+                .collect::<Vec<_>>();
+            let got = root
+                .resolve_many(paths)
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .and_then(|handle| handle.as_unsafe_path_unchecked())
+                        .map_err(|err| err.kind())
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                got, expected,
+                "{backend:?}: resolve_many with {rflags:?} should match resolve"
+            );
+        }
+    }
+
+    Ok(())
+}
+
 mod utils {
     use crate::{
         error::ErrorKind,
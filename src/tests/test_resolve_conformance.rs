@@ -0,0 +1,232 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Cross-backend conformance tests for partial lookups.
+//!
+//! Unlike the tests in `test_resolve_partial` (which check each backend
+//! against a hand-written expected result), these tests run the same lookup
+//! through both the `openat2(2)` and emulated `O_PATH` backends and check that
+//! the resulting [`PartialLookup`]s have the same shape. This catches cases
+//! where both backends are "reasonable" but disagree with each other.
+//!
+//! # Known divergences
+//!
+//! * If a symlink target has a trailing `/` (or `/.`) but refers to a
+//!   non-directory, the symlink itself cannot be resolved (`ENOTDIR`). The
+//!   `openat2(2)` backend can only retry with shorter prefixes of the requested
+//!   path, and so returns the directory containing the symlink. The emulated
+//!   backend walks the symlink target itself, and so returns the
+//!   non-directory with the trailing `/` of the target as part of `remaining`
+//!   (a split point which cannot be expressed in terms of the requested path).
+//!   For these lookups, only the error is compared.
+
+use crate::{
+    error::ErrorKind,
+    resolvers::{PartialLookup, Resolver, ResolverBackend},
+    tests::common as tests_common,
+    utils::FdExt,
+    Root,
+};
+
+use std::{os::unix::fs::MetadataExt, path::PathBuf};
+
+use anyhow::Error;
+use pretty_assertions::assert_eq;
+
+/// Which parts of a [`PartialLookup`] should be compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compare {
+    /// Compare everything.
+    Full,
+    /// Only compare the error of partial lookups (for known divergences).
+    ErrorOnly,
+}
+
+impl Compare {
+    /// Returns `value` only if everything is being compared.
+    fn full<T>(self, value: T) -> Option<T> {
+        Some(value).filter(|_| self == Compare::Full)
+    }
+}
+
+/// The backend-independent parts of a [`PartialLookup`].
+#[derive(Debug, PartialEq, Eq)]
+enum Shape {
+    Complete {
+        path: PathBuf,
+        file_type: u32,
+    },
+    Partial {
+        handle: Option<(PathBuf, u32)>,
+        resolved: Option<PathBuf>,
+        remaining: Option<PathBuf>,
+        last_error: ErrorKind,
+        dangling_symlink: bool,
+    },
+}
+
+fn partial_shape(
+    root: &Root,
+    backend: ResolverBackend,
+    path: &str,
+    no_follow_trailing: bool,
+    compare: Compare,
+) -> Result<Result<Shape, ErrorKind>, Error> {
+    let resolver = Resolver {
+        backend,
+        ..Default::default()
+    };
+    let root_dir = root.as_unsafe_path_unchecked()?;
+
+    let lookup = match resolver.resolve_partial(root, None, path, no_follow_trailing) {
+        Ok(lookup) => lookup,
+        Err(err) => return Ok(Err(err.kind())),
+    };
+    let handle = match lookup {
+        PartialLookup::Complete(ref handle) | PartialLookup::Partial { ref handle, .. } => handle,
+    };
+    let handle_path = handle
+        .as_unsafe_path_unchecked()?
+        .strip_prefix(&root_dir)?
+        .to_path_buf();
+    let file_type = handle.metadata()?.mode() & libc::S_IFMT;
+
+    Ok(Ok(match lookup {
+        PartialLookup::Complete(_) => Shape::Complete {
+            path: handle_path,
+            file_type,
+        },
+        PartialLookup::Partial {
+            resolved,
+            remaining,
+            last_error,
+            dangling_symlink,
+            ..
+        } => Shape::Partial {
+            handle: compare.full((handle_path, file_type)),
+            resolved: compare.full(resolved),
+            remaining: compare.full(remaining),
+            last_error: last_error.kind(),
+            dangling_symlink,
+        },
+    }))
+}
+
+fn check_conformance(path: &str, no_follow_trailing: bool, compare: Compare) -> Result<(), Error> {
+    if !ResolverBackend::KernelOpenat2.supported() {
+        // Skip if not supported.
+        return Ok(());
+    }
+
+    let root_dir = tests_common::create_basic_tree()?;
+    let root = Root::open(&root_dir)?;
+
+    let openat2 = partial_shape(
+        &root,
+        ResolverBackend::KernelOpenat2,
+        path,
+        no_follow_trailing,
+        compare,
+    )?;
+    let opath = partial_shape(
+        &root,
+        ResolverBackend::EmulatedOpath,
+        path,
+        no_follow_trailing,
+        compare,
+    )?;
+    assert_eq!(
+        opath, openat2,
+        "resolve_partial({path:?}, no_follow_trailing={no_follow_trailing}) should match between the emulated and openat2 backends"
+    );
+    Ok(())
+}
+
+macro_rules! conformance_tests {
+    // conformance_tests! {
+    //     test_name: resolve_partial("path");
+    //     test_name: resolve_partial("path", no_follow_trailing = true);
+    //     test_name: resolve_partial("path", error_only);
+    // }
+    (@impl $test_name:ident ($path:expr, $no_follow_trailing:expr, $compare:expr)) => {
+        paste::paste! {
+            #[test]
+            fn [<resolve_partial_conformance_ $test_name>]() -> Result<(), Error> {
+                check_conformance($path, $no_follow_trailing, $compare)
+            }
+        }
+    };
+
+    (@opts $test_name:ident ($path:expr)) => {
+        conformance_tests! { @impl $test_name ($path, false, Compare::Full) }
+    };
+    (@opts $test_name:ident ($path:expr) no_follow_trailing = $val:expr) => {
+        conformance_tests! { @impl $test_name ($path, $val, Compare::Full) }
+    };
+    (@opts $test_name:ident ($path:expr) error_only) => {
+        conformance_tests! { @impl $test_name ($path, false, Compare::ErrorOnly) }
+    };
+
+    ($($test_name:ident: resolve_partial($path:expr $(, $opt:ident $(= $val:expr)?)?));* $(;)?) => {
+        $(
+            conformance_tests! { @opts $test_name ($path) $($opt $(= $val)?)? }
+        )*
+    };
+}
+
+conformance_tests! {
+    // Complete lookups.
+    complete_dir: resolve_partial("b/c/d");
+    complete_symlink: resolve_partial("link3/target_abs");
+    complete_symlink_nofollow: resolve_partial("link3/target_abs", no_follow_trailing = true);
+    complete_dangling_nofollow: resolve_partial("a-fake1", no_follow_trailing = true);
+    // Missing components.
+    missing_trailing: resolve_partial("a/nonexist");
+    missing_middle: resolve_partial("a/nonexist/foo/bar");
+    missing_middle_dotdot: resolve_partial("a/nonexist/../b");
+    // Non-directory components.
+    nondir_middle: resolve_partial("b/c/file/foo/bar");
+    nondir_trailing_slash: resolve_partial("b/c/file/");
+    nondir_dot: resolve_partial("b/c/file/.");
+    nondir_dotdot: resolve_partial("b/c/file/../d");
+    nondir_fifo_middle: resolve_partial("b/fifo/foo");
+    nondir_symlink_middle: resolve_partial("b-file/foo");
+    nondir_symlink_trailing_slash: resolve_partial("b-file/");
+    // Dangling symlinks.
+    dangling_trailing: resolve_partial("a-fake1");
+    dangling_middle: resolve_partial("a-fake1/foo");
+    dangling_abs: resolve_partial("c/a-fake1");
+    dangling_deep1: resolve_partial("link3/deep_dangling1");
+    dangling_deep2: resolve_partial("link3/deep_dangling2");
+    dangling_chain: resolve_partial("dangling/a");
+    dangling_chain_file: resolve_partial("dangling-file/a");
+    dangling_trailing_slash: resolve_partial("a-fake1/");
+    dangling_dotdot: resolve_partial("a-fake3/c");
+    // Missing components and non-directories behind a symlink.
+    symlink_dir_missing: resolve_partial("e/nonexist/foo");
+    symlink_dir_slash_nofollow: resolve_partial("link3/target_abs/", no_follow_trailing = true);
+    // Symlinks to non-directories with a trailing slash (see "Known
+    // divergences" above).
+    unclean_file_slash: resolve_partial("unclean/file_slash/foo", error_only);
+    unclean_file_dot: resolve_partial("unclean/file_dot/foo", error_only);
+    // ".." escapes are clamped to the root.
+    dotdot_escape_missing: resolve_partial("../../../a/nonexist");
+    dotdot_escape_nondir: resolve_partial("../../b/c/file/foo");
+    dotdot_escape_symlink: resolve_partial("root-link3/a/nonexist");
+}
@@ -172,13 +172,17 @@ resolve_tests! {
         // Partial lookups.
         partial_dir_basic: resolve_partial("a/b/c/d/e/f/g/h") => Ok(PartialLookup::Partial {
             handle: ("a", libc::S_IFDIR),
+            resolved: "a".into(),
             remaining: "b/c/d/e/f/g/h".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         partial_dir_dotdot: resolve_partial("a/foo/../bar/baz") => Ok(PartialLookup::Partial {
             handle: ("a", libc::S_IFDIR),
+            resolved: "a".into(),
             remaining: "foo/../bar/baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         // Non-lexical symlinks.
         nonlexical_basic_complete: resolve_partial("target") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
@@ -186,450 +190,630 @@ resolve_tests! {
         nonlexical_basic_complete2: resolve_partial("target//") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_basic_partial: resolve_partial("target/foo") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "target".into(),
             remaining: "foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_basic_partial_dotdot: resolve_partial("target/../target/foo/bar/../baz") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "target/../target".into(),
             remaining: "foo/bar/../baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level1_abs_complete1: resolve_partial("link1/target_abs") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level1_abs_complete2: resolve_partial("link1/target_abs/") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level1_abs_complete3: resolve_partial("link1/target_abs//") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level1_abs_partial: resolve_partial("link1/target_abs/foo") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link1/target_abs".into(),
             remaining: "foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level1_abs_partial_dotdot: resolve_partial("link1/target_abs/../target/foo/bar/../baz") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link1/target_abs/../target".into(),
             remaining: "foo/bar/../baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level1_rel_complete1: resolve_partial("link1/target_rel") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level1_rel_complete2: resolve_partial("link1/target_rel/") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level1_rel_complete3: resolve_partial("link1/target_rel//") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level1_rel_partial: resolve_partial("link1/target_rel/foo") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link1/target_rel".into(),
             remaining: "foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level1_rel_partial_dotdot: resolve_partial("link1/target_rel/../target/foo/bar/../baz") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link1/target_rel/../target".into(),
             remaining: "foo/bar/../baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level2_abs_abs_complete1: resolve_partial("link2/link1_abs/target_abs") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_abs_abs_complete2: resolve_partial("link2/link1_abs/target_abs/") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_abs_abs_complete3: resolve_partial("link2/link1_abs/target_abs//") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_abs_abs_partial: resolve_partial("link2/link1_abs/target_abs/foo") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link2/link1_abs/target_abs".into(),
             remaining: "foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level2_abs_abs_partial_dotdot: resolve_partial("link2/link1_abs/target_abs/../target/foo/bar/../baz") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link2/link1_abs/target_abs/../target".into(),
             remaining: "foo/bar/../baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level2_abs_rel_complete1: resolve_partial("link2/link1_abs/target_rel") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_abs_rel_complete2: resolve_partial("link2/link1_abs/target_rel/") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_abs_rel_complete3: resolve_partial("link2/link1_abs/target_rel//") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_abs_rel_partial: resolve_partial("link2/link1_abs/target_rel/foo") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link2/link1_abs/target_rel".into(),
             remaining: "foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level2_abs_rel_partial_dotdot: resolve_partial("link2/link1_abs/target_rel/../target/foo/bar/../baz") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link2/link1_abs/target_rel/../target".into(),
             remaining: "foo/bar/../baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level2_abs_open_complete1: resolve_partial("link2/link1_abs/../target") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_abs_open_complete2: resolve_partial("link2/link1_abs/../target/") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_abs_open_complete3: resolve_partial("link2/link1_abs/../target//") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_abs_open_partial: resolve_partial("link2/link1_abs/../target/foo") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link2/link1_abs/../target".into(),
             remaining: "foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level2_abs_open_partial_dotdot: resolve_partial("link2/link1_abs/../target/../target/foo/bar/../baz") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link2/link1_abs/../target/../target".into(),
             remaining: "foo/bar/../baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level2_rel_abs_complete1: resolve_partial("link2/link1_rel/target_abs") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_rel_abs_complete2: resolve_partial("link2/link1_rel/target_abs/") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_rel_abs_complete3: resolve_partial("link2/link1_rel/target_abs//") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_rel_abs_partial: resolve_partial("link2/link1_rel/target_abs/foo") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link2/link1_rel/target_abs".into(),
             remaining: "foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level2_rel_abs_partial_dotdot: resolve_partial("link2/link1_rel/target_abs/../target/foo/bar/../baz") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link2/link1_rel/target_abs/../target".into(),
             remaining: "foo/bar/../baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level2_rel_rel_complete1: resolve_partial("link2/link1_rel/target_rel") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_rel_rel_complete2: resolve_partial("link2/link1_rel/target_rel/") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_rel_rel_complete3: resolve_partial("link2/link1_rel/target_rel//") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_rel_rel_partial: resolve_partial("link2/link1_rel/target_rel/foo") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link2/link1_rel/target_rel".into(),
             remaining: "foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level2_rel_rel_partial_dotdot: resolve_partial("link2/link1_rel/target_rel/../target/foo/bar/../baz") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link2/link1_rel/target_rel/../target".into(),
             remaining: "foo/bar/../baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level2_rel_open_complete1: resolve_partial("link2/link1_rel/../target") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_rel_open_complete2: resolve_partial("link2/link1_rel/../target/") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_rel_open_complete3: resolve_partial("link2/link1_rel/../target//") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level2_rel_open_partial: resolve_partial("link2/link1_rel/../target/foo") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link2/link1_rel/../target".into(),
             remaining: "foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level2_rel_open_partial_dotdot: resolve_partial("link2/link1_rel/../target/../target/foo/bar/../baz") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link2/link1_rel/../target/../target".into(),
             remaining: "foo/bar/../baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level3_abs_complete1: resolve_partial("link3/target_abs") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level3_abs_complete2: resolve_partial("link3/target_abs/") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level3_abs_complete3: resolve_partial("link3/target_abs//") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level3_abs_partial: resolve_partial("link3/target_abs/foo") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link3/target_abs".into(),
             remaining: "foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level3_abs_partial_dotdot: resolve_partial("link3/target_abs/../target/foo/bar/../baz") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link3/target_abs/../target".into(),
             remaining: "foo/bar/../baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level3_rel_complete: resolve_partial("link3/target_rel") => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         nonlexical_level3_rel_partial: resolve_partial("link3/target_rel/foo") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link3/target_rel".into(),
             remaining: "foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         nonlexical_level3_rel_partial_dotdot: resolve_partial("link3/target_rel/../target/foo/bar/../baz") => Ok(PartialLookup::Partial {
             handle: ("target", libc::S_IFDIR),
+            resolved: "link3/target_rel/../target".into(),
             remaining: "foo/bar/../baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
         });
         // Partial lookups due to hitting a non_directory.
         partial_nondir_slash1: resolve_partial("b/c/file/") => Ok(PartialLookup::Partial {
             handle: ("b/c/file", libc::S_IFREG),
+            resolved: "b/c/file".into(),
             remaining: "".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_nondir_slash2: resolve_partial("b/c/file//") => Ok(PartialLookup::Partial {
             handle: ("b/c/file", libc::S_IFREG),
+            resolved: "b/c/file".into(),
             remaining: "/".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_nondir_dot: resolve_partial("b/c/file/.") => Ok(PartialLookup::Partial {
             handle: ("b/c/file", libc::S_IFREG),
+            resolved: "b/c/file".into(),
             remaining: ".".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_nondir_dotdot1: resolve_partial("b/c/file/..") => Ok(PartialLookup::Partial {
             handle: ("b/c/file", libc::S_IFREG),
+            resolved: "b/c/file".into(),
             remaining: "..".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_nondir_dotdot2: resolve_partial("b/c/file/../foo/bar") => Ok(PartialLookup::Partial {
             handle: ("b/c/file", libc::S_IFREG),
+            resolved: "b/c/file".into(),
             remaining: "../foo/bar".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_nondir_symlink_slash1: resolve_partial("b-file/") => Ok(PartialLookup::Partial {
             handle: ("b/c/file", libc::S_IFREG),
+            resolved: "b-file".into(),
             remaining: "".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_nondir_symlink_slash2: resolve_partial("b-file//") => Ok(PartialLookup::Partial {
             handle: ("b/c/file", libc::S_IFREG),
+            resolved: "b-file".into(),
             remaining: "/".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_nondir_symlink_dot: resolve_partial("b-file/.") => Ok(PartialLookup::Partial {
             handle: ("b/c/file", libc::S_IFREG),
+            resolved: "b-file".into(),
             remaining: ".".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_nondir_symlink_dotdot1: resolve_partial("b-file/..") => Ok(PartialLookup::Partial {
             handle: ("b/c/file", libc::S_IFREG),
+            resolved: "b-file".into(),
             remaining: "..".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_nondir_symlink_dotdot2: resolve_partial("b-file/../foo/bar") => Ok(PartialLookup::Partial {
             handle: ("b/c/file", libc::S_IFREG),
+            resolved: "b-file".into(),
             remaining: "../foo/bar".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_fifo_slash1: resolve_partial("b/fifo/") => Ok(PartialLookup::Partial {
             handle: ("b/fifo", libc::S_IFIFO),
+            resolved: "b/fifo".into(),
             remaining: "".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_fifo_slash2: resolve_partial("b/fifo//") => Ok(PartialLookup::Partial {
             handle: ("b/fifo", libc::S_IFIFO),
+            resolved: "b/fifo".into(),
             remaining: "/".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_fifo_dot: resolve_partial("b/fifo/.") => Ok(PartialLookup::Partial {
             handle: ("b/fifo", libc::S_IFIFO),
+            resolved: "b/fifo".into(),
             remaining: ".".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_fifo_dotdot1: resolve_partial("b/fifo/..") => Ok(PartialLookup::Partial {
             handle: ("b/fifo", libc::S_IFIFO),
+            resolved: "b/fifo".into(),
             remaining: "..".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_fifo_dotdot2: resolve_partial("b/fifo/../foo/bar") => Ok(PartialLookup::Partial {
             handle: ("b/fifo", libc::S_IFIFO),
+            resolved: "b/fifo".into(),
             remaining: "../foo/bar".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_sock_slash1: resolve_partial("b/sock/") => Ok(PartialLookup::Partial {
             handle: ("b/sock", libc::S_IFSOCK),
+            resolved: "b/sock".into(),
             remaining: "".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_sock_slash2: resolve_partial("b/sock//") => Ok(PartialLookup::Partial {
             handle: ("b/sock", libc::S_IFSOCK),
+            resolved: "b/sock".into(),
             remaining: "/".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_sock_dot: resolve_partial("b/sock/.") => Ok(PartialLookup::Partial {
             handle: ("b/sock", libc::S_IFSOCK),
+            resolved: "b/sock".into(),
             remaining: ".".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_sock_dotdot1: resolve_partial("b/sock/..") => Ok(PartialLookup::Partial {
             handle: ("b/sock", libc::S_IFSOCK),
+            resolved: "b/sock".into(),
             remaining: "..".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         partial_sock_dotdot2: resolve_partial("b/sock/../foo/bar") => Ok(PartialLookup::Partial {
             handle: ("b/sock", libc::S_IFSOCK),
+            resolved: "b/sock".into(),
             remaining: "../foo/bar".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         // O_NOFOLLOW doesn't matter for trailing-slash paths.
         partial_symlink_nofollow_slash1: resolve_partial("link3/target_abs/", no_follow_trailing = true) => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         partial_symlink_nofollow_slash2: resolve_partial("link3/target_abs//", no_follow_trailing = true) => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
         partial_symlink_nofollow_dot: resolve_partial("link3/target_abs/.", no_follow_trailing = true) => Ok(PartialLookup::Complete(("/target", libc::S_IFDIR)));
-        // Dangling symlinks are treated as though they are non_existent.
+        // Plain missing components (unlike dangling symlinks below).
+        missing_sub_trailing: resolve_partial("c/nonexist") => Ok(PartialLookup::Partial {
+            handle: ("c", libc::S_IFDIR),
+            resolved: "c".into(),
+            remaining: "nonexist".into(),
+            last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
+        });
+        missing_sub_partial: resolve_partial("c/nonexist/foo") => Ok(PartialLookup::Partial {
+            handle: ("c", libc::S_IFDIR),
+            resolved: "c".into(),
+            remaining: "nonexist/foo".into(),
+            last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: false,
+        });
+        // Dangling symlinks are treated as though they are non_existent (but
+        // are marked as dangling symlinks).
         dangling1_inroot_trailing: resolve_partial("a-fake1") => Ok(PartialLookup::Partial {
             handle: ("", libc::S_IFDIR),
+            resolved: ".".into(),
             remaining: "a-fake1".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling1_inroot_partial: resolve_partial("a-fake1/foo") => Ok(PartialLookup::Partial {
             handle: ("", libc::S_IFDIR),
+            resolved: ".".into(),
             remaining: "a-fake1/foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling1_inroot_partial_dotdot: resolve_partial("a-fake1/../bar/baz") => Ok(PartialLookup::Partial {
             handle: ("", libc::S_IFDIR),
+            resolved: ".".into(),
             remaining: "a-fake1/../bar/baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling1_sub_trailing: resolve_partial("c/a-fake1") => Ok(PartialLookup::Partial {
             handle: ("c", libc::S_IFDIR),
+            resolved: "c".into(),
             remaining: "a-fake1".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling1_sub_partial: resolve_partial("c/a-fake1/foo") => Ok(PartialLookup::Partial {
             handle: ("c", libc::S_IFDIR),
+            resolved: "c".into(),
             remaining: "a-fake1/foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling1_sub_partial_dotdot: resolve_partial("c/a-fake1/../bar/baz") => Ok(PartialLookup::Partial {
             handle: ("c", libc::S_IFDIR),
+            resolved: "c".into(),
             remaining: "a-fake1/../bar/baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling2_inroot_trailing: resolve_partial("a-fake2") => Ok(PartialLookup::Partial {
             handle: ("", libc::S_IFDIR),
+            resolved: ".".into(),
             remaining: "a-fake2".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling2_inroot_partial: resolve_partial("a-fake2/foo") => Ok(PartialLookup::Partial {
             handle: ("", libc::S_IFDIR),
+            resolved: ".".into(),
             remaining: "a-fake2/foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling2_inroot_partial_dotdot: resolve_partial("a-fake2/../bar/baz") => Ok(PartialLookup::Partial {
             handle: ("", libc::S_IFDIR),
+            resolved: ".".into(),
             remaining: "a-fake2/../bar/baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling2_sub_trailing: resolve_partial("c/a-fake2") => Ok(PartialLookup::Partial {
             handle: ("c", libc::S_IFDIR),
+            resolved: "c".into(),
             remaining: "a-fake2".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling2_sub_partial: resolve_partial("c/a-fake2/foo") => Ok(PartialLookup::Partial {
             handle: ("c", libc::S_IFDIR),
+            resolved: "c".into(),
             remaining: "a-fake2/foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling2_sub_partial_dotdot: resolve_partial("c/a-fake2/../bar/baz") => Ok(PartialLookup::Partial {
             handle: ("c", libc::S_IFDIR),
+            resolved: "c".into(),
             remaining: "a-fake2/../bar/baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling3_inroot_trailing: resolve_partial("a-fake3") => Ok(PartialLookup::Partial {
             handle: ("", libc::S_IFDIR),
+            resolved: ".".into(),
             remaining: "a-fake3".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling3_inroot_partial: resolve_partial("a-fake3/foo") => Ok(PartialLookup::Partial {
             handle: ("", libc::S_IFDIR),
+            resolved: ".".into(),
             remaining: "a-fake3/foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling3_inroot_partial_dotdot: resolve_partial("a-fake3/../bar/baz") => Ok(PartialLookup::Partial {
             handle: ("", libc::S_IFDIR),
+            resolved: ".".into(),
             remaining: "a-fake3/../bar/baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling3_sub_trailing: resolve_partial("c/a-fake3") => Ok(PartialLookup::Partial {
             handle: ("c", libc::S_IFDIR),
+            resolved: "c".into(),
             remaining: "a-fake3".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling3_sub_partial: resolve_partial("c/a-fake3/foo") => Ok(PartialLookup::Partial {
             handle: ("c", libc::S_IFDIR),
+            resolved: "c".into(),
             remaining: "a-fake3/foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling3_sub_partial_dotdot: resolve_partial("c/a-fake3/../bar/baz") => Ok(PartialLookup::Partial {
             handle: ("c", libc::S_IFDIR),
+            resolved: "c".into(),
             remaining: "a-fake3/../bar/baz".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         // Tricky dangling symlinks.
         dangling_tricky1_trailing: resolve_partial("link3/deep_dangling1") => Ok(PartialLookup::Partial {
             handle: ("link3", libc::S_IFDIR),
+            resolved: "link3".into(),
             remaining: "deep_dangling1".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling_tricky1_partial: resolve_partial("link3/deep_dangling1/foo") => Ok(PartialLookup::Partial {
             handle: ("link3", libc::S_IFDIR),
+            resolved: "link3".into(),
             remaining: "deep_dangling1/foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling_tricky1_partial_dotdot: resolve_partial("link3/deep_dangling1/..") => Ok(PartialLookup::Partial {
             handle: ("link3", libc::S_IFDIR),
+            resolved: "link3".into(),
             remaining: "deep_dangling1/..".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling_tricky2_trailing: resolve_partial("link3/deep_dangling2") => Ok(PartialLookup::Partial {
             handle: ("link3", libc::S_IFDIR),
+            resolved: "link3".into(),
             remaining: "deep_dangling2".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling_tricky2_partial: resolve_partial("link3/deep_dangling2/foo") => Ok(PartialLookup::Partial {
             handle: ("link3", libc::S_IFDIR),
+            resolved: "link3".into(),
             remaining: "deep_dangling2/foo".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         dangling_tricky2_partial_dotdot: resolve_partial("link3/deep_dangling2/..") => Ok(PartialLookup::Partial {
             handle: ("link3", libc::S_IFDIR),
+            resolved: "link3".into(),
             remaining: "deep_dangling2/..".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         // Really deep dangling links.
         deep_dangling1: resolve_partial("dangling/a") => Ok(PartialLookup::Partial {
             handle: ("dangling", libc::S_IFDIR),
+            resolved: "dangling".into(),
             remaining: "a".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         deep_dangling2: resolve_partial("dangling/b/c") => Ok(PartialLookup::Partial {
             handle: ("dangling/b", libc::S_IFDIR),
+            resolved: "dangling/b".into(),
             remaining: "c".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         deep_dangling3: resolve_partial("dangling/c") => Ok(PartialLookup::Partial {
             handle: ("dangling", libc::S_IFDIR),
+            resolved: "dangling".into(),
             remaining: "c".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         deep_dangling4: resolve_partial("dangling/d/e") => Ok(PartialLookup::Partial {
             handle: ("dangling/d", libc::S_IFDIR),
+            resolved: "dangling/d".into(),
             remaining: "e".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         deep_dangling5: resolve_partial("dangling/e") => Ok(PartialLookup::Partial {
             handle: ("dangling", libc::S_IFDIR),
+            resolved: "dangling".into(),
             remaining: "e".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         deep_dangling6: resolve_partial("dangling/g") => Ok(PartialLookup::Partial {
             handle: ("dangling", libc::S_IFDIR),
+            resolved: "dangling".into(),
             remaining: "g".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOENT)),
+            dangling_symlink: true,
         });
         deep_dangling_fileasdir1: resolve_partial("dangling-file/a") => Ok(PartialLookup::Partial {
             handle: ("dangling-file", libc::S_IFDIR),
+            resolved: "dangling-file".into(),
             remaining: "a".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         deep_dangling_fileasdir2: resolve_partial("dangling-file/b/c") => Ok(PartialLookup::Partial {
             handle: ("dangling-file/b", libc::S_IFDIR),
+            resolved: "dangling-file/b".into(),
             remaining: "c".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         deep_dangling_fileasdir3: resolve_partial("dangling-file/c") => Ok(PartialLookup::Partial {
             handle: ("dangling-file", libc::S_IFDIR),
+            resolved: "dangling-file".into(),
             remaining: "c".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         deep_dangling_fileasdir4: resolve_partial("dangling-file/d/e") => Ok(PartialLookup::Partial {
             handle: ("dangling-file/d", libc::S_IFDIR),
+            resolved: "dangling-file/d".into(),
             remaining: "e".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         deep_dangling_fileasdir5: resolve_partial("dangling-file/e") => Ok(PartialLookup::Partial {
             handle: ("dangling-file", libc::S_IFDIR),
+            resolved: "dangling-file".into(),
             remaining: "e".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         deep_dangling_fileasdir6: resolve_partial("dangling-file/g") => Ok(PartialLookup::Partial {
             handle: ("dangling-file", libc::S_IFDIR),
+            resolved: "dangling-file".into(),
             remaining: "g".into(),
             last_error: ErrorKind::OsError(Some(libc::ENOTDIR)),
+            dangling_symlink: false,
         });
         // Symlink loops.
         loop1: resolve_partial("loop/link") => Ok(PartialLookup::Partial {
             handle: ("loop", libc::S_IFDIR),
+            resolved: "loop".into(),
             remaining: "link".into(),
             last_error: ErrorKind::OsError(Some(libc::ELOOP)),
+            dangling_symlink: false,
         });
         loop_basic1: resolve_partial("loop/basic-loop1") => Ok(PartialLookup::Partial {
             handle: ("loop", libc::S_IFDIR),
+            resolved: "loop".into(),
             remaining: "basic-loop1".into(),
             last_error: ErrorKind::OsError(Some(libc::ELOOP)),
+            dangling_symlink: false,
         });
         loop_basic2: resolve_partial("loop/basic-loop2") => Ok(PartialLookup::Partial {
             handle: ("loop", libc::S_IFDIR),
+            resolved: "loop".into(),
             remaining: "basic-loop2".into(),
             last_error: ErrorKind::OsError(Some(libc::ELOOP)),
+            dangling_symlink: false,
         });
         loop_basic3: resolve_partial("loop/basic-loop3") => Ok(PartialLookup::Partial {
             handle: ("loop", libc::S_IFDIR),
+            resolved: "loop".into(),
             remaining: "basic-loop3".into(),
             last_error: ErrorKind::OsError(Some(libc::ELOOP)),
+            dangling_symlink: false,
         });
         // NO_FOLLOW.
         symlink_nofollow: resolve_partial("link3/target_abs", no_follow_trailing = true) => Ok(PartialLookup::Complete(("link3/target_abs", libc::S_IFLNK)));
@@ -640,23 +824,31 @@ resolve_tests! {
         dir_nosym: resolve_partial("b/c/d/e", rflags = NO_SYMLINKS) => Ok(PartialLookup::Complete(("b/c/d/e", libc::S_IFDIR)));
         symlink_nosym: resolve_partial("link3/target_abs", rflags = NO_SYMLINKS) => Ok(PartialLookup::Partial {
             handle: ("link3", libc::S_IFDIR),
+            resolved: "link3".into(),
             remaining: "target_abs".into(),
             last_error: ErrorKind::OsError(Some(libc::ELOOP)),
+            dangling_symlink: false,
         });
         symlink_component_nosym1: resolve_partial("e/f", rflags = NO_SYMLINKS) => Ok(PartialLookup::Partial {
             handle: ("", libc::S_IFDIR),
+            resolved: ".".into(),
             remaining: "e/f".into(),
             last_error: ErrorKind::OsError(Some(libc::ELOOP)),
+            dangling_symlink: false,
         });
         symlink_component_nosym2: resolve_partial("link2/link1_abs/target_rel", rflags = NO_SYMLINKS) => Ok(PartialLookup::Partial {
             handle: ("link2", libc::S_IFDIR),
+            resolved: "link2".into(),
             remaining: "link1_abs/target_rel".into(),
             last_error: ErrorKind::OsError(Some(libc::ELOOP)),
+            dangling_symlink: false,
         });
         loop_nosym: resolve_partial("loop/link", rflags = NO_SYMLINKS) => Ok(PartialLookup::Partial {
             handle: ("loop", libc::S_IFDIR),
+            resolved: "loop".into(),
             remaining: "link".into(),
             last_error: ErrorKind::OsError(Some(libc::ELOOP)),
+            dangling_symlink: false,
         });
     }
 }
@@ -690,18 +882,24 @@ mod utils {
                 (
                     Self::Partial {
                         handle: left_handle,
+                        resolved: left_resolved,
                         remaining: left_remaining,
                         last_error: left_last_error,
+                        dangling_symlink: left_dangling_symlink,
                     },
                     Self::Partial {
                         handle: right_handle,
+                        resolved: right_resolved,
                         remaining: right_remaining,
                         last_error: right_last_error,
+                        dangling_symlink: right_dangling_symlink,
                     },
                 ) => {
                     left_handle == right_handle
+                        && left_resolved == right_resolved
                         && left_remaining == right_remaining
                         && left_last_error == right_last_error
+                        && left_dangling_symlink == right_dangling_symlink
                 }
                 _ => false,
             }
@@ -728,7 +926,7 @@ mod utils {
 
         let result = root
             .resolver()
-            .resolve_partial(root, unsafe_path, no_follow_trailing)
+            .resolve_partial(root, None, unsafe_path, no_follow_trailing)
             .map(|lookup_result| {
                 let (path, file_type) = {
                     let file = lookup_result.as_inner_handle();
@@ -748,14 +946,18 @@ mod utils {
                     }
                     PartialLookup::Partial {
                         handle,
+                        resolved,
                         remaining,
                         last_error,
+                        dangling_symlink,
                     } => (
                         handle,
                         PartialLookup::Partial {
                             handle: (path, file_type),
+                            resolved,
                             remaining,
                             last_error: last_error.kind(),
+                            dangling_symlink,
                         },
                     ),
                 }
@@ -771,12 +973,16 @@ mod utils {
                     PartialLookup::Complete(_) => PartialLookup::Complete((path, file_type)),
                     PartialLookup::Partial {
                         handle: _,
+                        resolved,
                         remaining,
                         last_error,
+                        dangling_symlink,
                     } => PartialLookup::Partial {
                         handle: (path, file_type),
+                        resolved,
                         remaining,
                         last_error,
+                        dangling_symlink,
                     },
                 };
 
@@ -799,6 +1005,23 @@ mod utils {
         );
 
         let (_, real_file_type) = lookup_result.as_inner_handle();
+
+        // Resolving the reported prefix should get us the same handle.
+        if let Some(resolved) = lookup_result.resolved_prefix() {
+            let prefix_handle = if *real_file_type == libc::S_IFLNK {
+                root.resolve_nofollow(resolved)
+            } else {
+                root.resolve(resolved)
+            }
+            .with_context(|| format!("resolve prefix {resolved:?} of {unsafe_path:?}"))?;
+            let (prefix_meta, meta) = (prefix_handle.metadata()?, handle.metadata()?);
+            assert_eq!(
+                (prefix_meta.dev(), prefix_meta.ino()),
+                (meta.dev(), meta.ino()),
+                "resolved prefix {resolved:?} of {unsafe_path:?} should refer to the partial handle",
+            );
+        }
+
         match *real_file_type {
             libc::S_IFDIR => {
                 tests_common::check_reopen(&handle, OpenFlags::O_RDONLY, None)?;
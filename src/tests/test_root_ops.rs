@@ -21,13 +21,29 @@
 use crate::tests::capi;
 use crate::{
     error::ErrorKind,
-    flags::{OpenFlags, RenameFlags},
+    flags::{AccessFlags, AccessMode, OpenFlags, RenameFlags, ResolverFlags},
     resolvers::ResolverBackend,
     tests::common as tests_common,
-    InodeType, Root,
+    utils::FdExt,
+    DirCache, DirCacheStats, Handle, InodeType, PublicResolverBackend, ResolveObserver, Root,
+    SetidPolicy,
 };
 
-use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+use std::{
+    ffi::CString,
+    fs::Permissions,
+    io::{Read, Write},
+    os::unix::{
+        fs::{MetadataExt, PermissionsExt},
+        io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    },
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
 
 use anyhow::Error;
 
@@ -214,6 +230,15 @@ macro_rules! root_op_tests {
         }
     };
 
+    ($(#[cfg($ignore_meta:meta)])* @impl readlink $test_name:ident ($path:expr) => $expected_result:expr) => {
+        root_op_tests!{
+            $(#[cfg_attr(not($ignore_meta), ignore)])*
+            fn $test_name(root) {
+                utils::check_root_readlink(&root, $path, $expected_result)
+            }
+        }
+    };
+
     ($(#[cfg($ignore_meta:meta)])* @impl remove_dir $test_name:ident ($path:expr) => $expected_result:expr) => {
         root_op_tests!{
             $(#[cfg_attr(not($ignore_meta), ignore)])*
@@ -389,9 +414,13 @@ root_op_tests! {
     empty_dir: remove_dir("a") => Ok(());
     empty_dir: remove_file("a") => Err(ErrorKind::OsError(Some(libc::EISDIR)));
     empty_dir: remove_all("a") => Ok(());
+    nested_empty_dir: remove_dir("b/c/d/e/f") => Ok(());
+    symlink_parent_empty_dir: remove_dir("e/f") => Ok(());
+    dot_empty_dir: remove_dir("a/.") => Err(ErrorKind::OsError(Some(libc::EINVAL)));
     nonempty_dir: remove_dir("b") => Err(ErrorKind::OsError(Some(libc::ENOTEMPTY)));
     nonempty_dir: remove_file("b") => Err(ErrorKind::OsError(Some(libc::EISDIR)));
     nonempty_dir: remove_all("b") => Ok(());
+    nested_nonempty_dir: remove_dir("b/c/d") => Err(ErrorKind::OsError(Some(libc::ENOTEMPTY)));
     file: remove_dir("b/c/file") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
     file: remove_file("b/c/file") => Ok(());
     file: remove_all("b/c/file") => Ok(());
@@ -407,6 +436,9 @@ root_op_tests! {
     symlink: remove_dir("b-file") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
     symlink: remove_file("b-file") => Ok(());
     symlink: remove_all("b-file") => Ok(());
+    symlink_dir: remove_dir("e") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
+    symlink_dir: remove_file("e") => Ok(());
+    symlink_dir: remove_all("e") => Ok(());
     dangling_symlink: remove_dir("a-fake1") => Err(ErrorKind::OsError(Some(libc::ENOTDIR)));
     dangling_symlink: remove_file("a-fake1") => Ok(());
     dangling_symlink: remove_all("a-fake1") => Ok(());
@@ -417,6 +449,19 @@ root_op_tests! {
     file_trailing_slash: remove_file("b/c/file/") => Err(ErrorKind::InvalidArgument);
     file_trailing_slash: remove_all("b/c/file/") => Err(ErrorKind::InvalidArgument);
 
+    plain: readlink("b-file") => Ok("b/c/file");
+    absolute: readlink("e") => Ok("/b/c/d/e");
+    dangling: readlink("a-fake1") => Ok("a/fake");
+    symlink_parent: readlink("link2/link1_abs/target_rel") => Ok("../target");
+    // The final component is never followed, even if it is a symlink to
+    // another symlink.
+    symlink_to_symlink: readlink("loop/link") => Ok("a/link");
+    enoent: readlink("nonexist") => Err(ErrorKind::OsError(Some(libc::ENOENT)));
+    enoent_parent: readlink("nonexist/b-file") => Err(ErrorKind::OsError(Some(libc::ENOENT)));
+    file: readlink("b/c/file") => Err(ErrorKind::OsError(Some(libc::EINVAL)));
+    dir: readlink("a") => Err(ErrorKind::OsError(Some(libc::EINVAL)));
+    symlink_parent_dir: readlink("e/f") => Err(ErrorKind::OsError(Some(libc::EINVAL)));
+
     plain: rename("a", "aa", RenameFlags::empty()) => Ok(());
     noreplace_plain: rename("a", "aa", RenameFlags::RENAME_NOREPLACE) => Ok(());
     noreplace_symlink: rename("a", "b-file", RenameFlags::RENAME_NOREPLACE) => Err(ErrorKind::OsError(Some(libc::EEXIST)));
@@ -425,6 +470,9 @@ root_op_tests! {
     whiteout_plain: rename("a", "aa", RenameFlags::RENAME_WHITEOUT) => Ok(());
     exchange_plain: rename("a", "e", RenameFlags::RENAME_EXCHANGE) => Ok(());
     exchange_enoent: rename("a", "aa", RenameFlags::RENAME_EXCHANGE) => Err(ErrorKind::OsError(Some(libc::ENOENT)));
+    // RENAME_EXCHANGE only makes sense on its own.
+    exchange_noreplace: rename("a", "e", RenameFlags::RENAME_EXCHANGE | RenameFlags::RENAME_NOREPLACE) => Err(ErrorKind::InvalidArgument);
+    exchange_whiteout: rename("a", "e", RenameFlags::RENAME_EXCHANGE | RenameFlags::RENAME_WHITEOUT) => Err(ErrorKind::InvalidArgument);
 
     invalid_mode_type: mkdir_all("foo", libc::S_IFDIR | 0o777) => Err(ErrorKind::InvalidArgument);
     invalid_mode_garbage: mkdir_all("foo", 0o12340777) => Err(ErrorKind::InvalidArgument);
@@ -486,6 +534,2109 @@ root_op_tests! {
     plain: mkdir_all_racing("a/b/c/d/e/f/g/h/i/j/k/l/m/n/o/p/q/r/s/t/u/v/w/x/y/z", 0o711) => Ok(());
 }
 
+// Make sure that file handles from resolve_unpinned don't keep a mount busy.
+#[test]
+#[cfg_attr(not(feature = "_test_as_root"), ignore)]
+fn root_resolve_unpinned_umount() -> Result<(), Error> {
+    let root_dir = tests_common::create_basic_tree()?;
+    let mnt_path = root_dir.path().join("a");
+
+    tests_common::in_mnt_ns(|| {
+        tests_common::mount(&mnt_path, tests_common::MountType::Tmpfs)?;
+        let root = Root::open(&root_dir)?;
+        std::fs::write(mnt_path.join("file"), b"")?;
+
+        // A regular handle keeps the mount busy.
+        let handle = root.resolve("a/file")?;
+        assert_eq!(
+            rustix::mount::unmount(&mnt_path, rustix::mount::UnmountFlags::empty()),
+            Err(rustix::io::Errno::BUSY),
+            "unmount with live handle should fail with EBUSY"
+        );
+        drop(handle);
+
+        // But a file handle does not.
+        let _fh = root.resolve_unpinned("a/file")?;
+        rustix::mount::unmount(&mnt_path, rustix::mount::UnmountFlags::empty())
+            .expect("unmount with only an unpinned file handle should succeed");
+
+        Ok(())
+    })
+}
+
+// Make sure that Root::submounts lists mounts inside the root.
+#[test]
+#[cfg_attr(not(feature = "_test_as_root"), ignore)]
+fn root_submounts() -> Result<(), Error> {
+    let root_dir = tests_common::create_basic_tree()?;
+
+    tests_common::in_mnt_ns(|| {
+        let root = Root::open(&root_dir)?;
+        assert_eq!(
+            root.submounts()?,
+            Vec::<PathBuf>::new(),
+            "fresh root should have no submounts"
+        );
+
+        tests_common::mount(
+            root_dir.path().join("a"),
+            tests_common::MountType::Bind {
+                src: root_dir.path().join("b/c"),
+            },
+        )?;
+        tests_common::mount(
+            root_dir.path().join("b/c/d"),
+            tests_common::MountType::Tmpfs,
+        )?;
+        assert_eq!(
+            root.submounts()?,
+            vec![PathBuf::from("a"), PathBuf::from("b/c/d")],
+            "submounts should list mounts in mount order"
+        );
+        assert_eq!(
+            root.with_error_context("submounts")
+                .run(|root| root.submounts())?,
+            vec![PathBuf::from("a"), PathBuf::from("b/c/d")],
+        );
+
+        // A root that is itself a mount point is included as ".".
+        let subroot = Root::open(root_dir.path().join("b/c/d"))?;
+        assert_eq!(subroot.submounts()?, vec![PathBuf::from(".")]);
+
+        Ok(())
+    })
+}
+
+// Make sure that Root::access checks permissions on the resolved inode.
+#[test]
+fn root_access() -> Result<(), Error> {
+    // Layout:
+    //   readable (0o644)
+    //   noperm (0o000)
+    //   link -> /readable
+    //   dangling -> /nonexistent
+    let dir = tempfile::TempDir::new()?;
+    std::fs::set_permissions(dir.path(), Permissions::from_mode(0o755))?;
+    std::fs::write(dir.path().join("readable"), b"")?;
+    std::fs::set_permissions(dir.path().join("readable"), Permissions::from_mode(0o644))?;
+    std::fs::write(dir.path().join("noperm"), b"")?;
+    std::fs::set_permissions(dir.path().join("noperm"), Permissions::from_mode(0o000))?;
+    std::os::unix::fs::symlink("/readable", dir.path().join("link"))?;
+    std::os::unix::fs::symlink("/nonexistent", dir.path().join("dangling"))?;
+
+    let root = Root::open(&dir)?;
+    let nofollow = AccessFlags::AT_SYMLINK_NOFOLLOW;
+
+    assert!(root.access("readable", AccessMode::R_OK, AccessFlags::empty())?);
+    assert!(root.access("noperm", AccessMode::F_OK, AccessFlags::empty())?);
+    // Even privileged users need at least one execute bit for X_OK.
+    assert!(!root.access("readable", AccessMode::X_OK, AccessFlags::empty())?);
+    // Symlinks are resolved inside the root.
+    assert!(root.access("link", AccessMode::R_OK, AccessFlags::empty())?);
+    assert!(root.access("link", AccessMode::F_OK, nofollow)?);
+    assert!(root.access("dangling", AccessMode::F_OK, nofollow)?);
+    assert_eq!(
+        root.access("dangling", AccessMode::F_OK, AccessFlags::empty())
+            .map_err(|err| err.kind()),
+        Err(ErrorKind::OsError(Some(libc::ENOENT))),
+        "access(\"dangling\") should follow the symlink"
+    );
+
+    // If we are privileged, drop our credentials for a single thread so
+    // that the permission checks actually apply to us.
+    let root = root.as_ref();
+    std::thread::scope(|s| {
+        s.spawn(|| -> Result<(), Error> {
+            if crate::syscalls::geteuid() == 0 {
+                // SAFETY: 65534 is the conventional "nobody" uid.
+                let nobody = unsafe { rustix::process::Uid::from_raw(65534) };
+                rustix::thread::set_thread_res_uid(nobody, nobody, nobody)?;
+            }
+            for flags in [AccessFlags::empty(), AccessFlags::AT_EACCESS] {
+                assert!(
+                    root.access("readable", AccessMode::R_OK, flags)?,
+                    "access(\"readable\", R_OK, {flags:?})"
+                );
+                assert!(
+                    !root.access("noperm", AccessMode::R_OK, flags)?,
+                    "access(\"noperm\", R_OK, {flags:?})"
+                );
+                assert!(
+                    !root.access("link", AccessMode::W_OK, flags)?,
+                    "access(\"link\", W_OK, {flags:?})"
+                );
+            }
+            Ok(())
+        })
+        .join()
+        .expect("access test thread panicked")
+    })
+}
+
+// Make sure that Root::access honours POSIX ACLs which grant access beyond
+// what the mode bits allow.
+#[test]
+#[cfg_attr(not(feature = "_test_as_root"), ignore)]
+fn root_access_acl() -> Result<(), Error> {
+    // 65534 is the conventional "nobody" uid.
+    const NOBODY: u32 = 65534;
+    // From <linux/posix_acl.h>.
+    const ACL_USER_OBJ: u16 = 0x01;
+    const ACL_USER: u16 = 0x02;
+    const ACL_GROUP_OBJ: u16 = 0x04;
+    const ACL_MASK: u16 = 0x10;
+    const ACL_OTHER: u16 = 0x20;
+
+    // Layout:
+    //   secret (0o600, owned by root)
+    let dir = tempfile::TempDir::new()?;
+    std::fs::set_permissions(dir.path(), Permissions::from_mode(0o755))?;
+    std::fs::write(dir.path().join("secret"), b"")?;
+    std::fs::set_permissions(dir.path().join("secret"), Permissions::from_mode(0o600))?;
+
+    let root = Root::open(&dir)?;
+    let check_as_nobody = |mode: AccessMode| -> Result<bool, Error> {
+        let root = root.as_ref();
+        std::thread::scope(|s| {
+            s.spawn(|| -> Result<bool, Error> {
+                // SAFETY: NOBODY is a valid uid.
+                let nobody = unsafe { rustix::process::Uid::from_raw(NOBODY) };
+                rustix::thread::set_thread_res_uid(nobody, nobody, nobody)?;
+                Ok(root.access("secret", mode, AccessFlags::empty())?)
+            })
+            .join()
+            .expect("access test thread panicked")
+        })
+    };
+
+    assert!(
+        !check_as_nobody(AccessMode::R_OK)?,
+        "access(\"secret\", R_OK) without an ACL"
+    );
+
+    // system.posix_acl_access: user::rw-,user:nobody:r--,group::---,mask::r--,other::---
+    let acl = [
+        (ACL_USER_OBJ, 0o6u16, u32::MAX),
+        (ACL_USER, 0o4, NOBODY),
+        (ACL_GROUP_OBJ, 0o0, u32::MAX),
+        (ACL_MASK, 0o4, u32::MAX),
+        (ACL_OTHER, 0o0, u32::MAX),
+    ]
+    .iter()
+    .fold(2u32.to_le_bytes().to_vec(), |mut acl, (tag, perm, id)| {
+        acl.extend(tag.to_le_bytes());
+        acl.extend(perm.to_le_bytes());
+        acl.extend(id.to_le_bytes());
+        acl
+    });
+    match rustix::fs::setxattr(
+        dir.path().join("secret"),
+        "system.posix_acl_access",
+        &acl,
+        rustix::fs::XattrFlags::empty(),
+    ) {
+        // The filesystem doesn't support ACLs, so there's nothing to test.
+        Err(rustix::io::Errno::OPNOTSUPP) => return Ok(()),
+        ret => ret?,
+    }
+
+    assert!(
+        check_as_nobody(AccessMode::R_OK)?,
+        "access(\"secret\", R_OK) with an ACL granting read access"
+    );
+    assert!(
+        !check_as_nobody(AccessMode::W_OK)?,
+        "access(\"secret\", W_OK) with an ACL granting only read access"
+    );
+
+    Ok(())
+}
+
+// Make sure that Handle::execveat executes the exact inode that was resolved.
+#[test]
+fn root_resolve_execveat() -> Result<(), Error> {
+    let dir = tempfile::TempDir::new()?;
+    std::fs::create_dir(dir.path().join("bin"))?;
+    std::fs::write(dir.path().join("bin/script"), "#!/bin/sh\nexit 42\n")?;
+    std::fs::set_permissions(dir.path().join("bin/script"), Permissions::from_mode(0o755))?;
+    std::fs::write(dir.path().join("bin/garbage"), b"\x00\x01\x02\x03")?;
+    std::fs::set_permissions(
+        dir.path().join("bin/garbage"),
+        Permissions::from_mode(0o755),
+    )?;
+
+    let root = Root::open(&dir)?;
+    let argv = [CString::new("script")?];
+    let envp = [CString::new("PATH=/usr/bin:/bin")?];
+
+    // A failed execveat(2) returns to the caller, so this is safe to do
+    // without forking.
+    let garbage = root.resolve("bin/garbage")?;
+    assert_eq!(
+        garbage
+            .execveat(&argv, &envp, 0)
+            .map(|_| ())
+            .map_err(|err| err.kind()),
+        Err(ErrorKind::OsError(Some(libc::ENOEXEC))),
+        "execveat of a non-executable format should fail"
+    );
+
+    // Swap out the script after resolving it. The original inode should still
+    // be the one that gets executed.
+    let script = root.resolve("/bin/script")?;
+    std::fs::rename(dir.path().join("bin/script"), dir.path().join("bin/old"))?;
+    std::fs::write(dir.path().join("bin/script"), "#!/bin/sh\nexit 1\n")?;
+
+    // A successful execveat(2) replaces the calling process, so it has to be
+    // done in a child. Forking the (multi-threaded) test harness is not safe
+    // if the child allocates, so re-run this test binary to do the execveat
+    // with a copy of the handle (without O_CLOEXEC so it is inherited).
+    let script_fd = rustix::io::dup(&script)?;
+    let (_, helper) = module_path!()
+        .split_once("::")
+        .expect("module path should include the crate name");
+    let output = Command::new(std::env::current_exe()?)
+        .args([
+            "--exact",
+            &format!("{helper}::root_resolve_execveat_helper"),
+            "--nocapture",
+        ])
+        .env(EXECVEAT_HELPER_FD, script_fd.as_raw_fd().to_string())
+        .output()?;
+    drop(script_fd);
+    assert_eq!(
+        output.status.code(),
+        Some(42),
+        "child should have executed the resolved script: {output:?}"
+    );
+
+    Ok(())
+}
+
+/// Environment variable telling [`root_resolve_execveat_helper`] which file
+/// descriptor to execute.
+const EXECVEAT_HELPER_FD: &str = "PATHRS_TEST_EXECVEAT_HELPER_FD";
+
+// Helper for root_resolve_execveat, run in a separate process. This does
+// nothing unless EXECVEAT_HELPER_FD is set.
+#[test]
+fn root_resolve_execveat_helper() -> Result<(), Error> {
+    let fd: RawFd = match std::env::var(EXECVEAT_HELPER_FD) {
+        Ok(fd) => fd.parse()?,
+        Err(_) => return Ok(()),
+    };
+    // SAFETY: root_resolve_execveat passed us ownership of this file
+    //         descriptor.
+    let script = Handle::from_fd(unsafe { OwnedFd::from_raw_fd(fd) });
+    let argv = [CString::new("script")?];
+    let envp = [CString::new("PATH=/usr/bin:/bin")?];
+    // Only returns if execveat(2) failed.
+    let err = script.execveat(&argv, &envp, 0).unwrap_err();
+    Err(err.into())
+}
+
+// Make sure that Root::glob_in only returns the matching entries of the
+// directory.
+#[test]
+fn root_glob_in() -> Result<(), Error> {
+    // Layout:
+    //   dir/a.txt
+    //   dir/b.txt
+    //   dir/c.conf
+    //   dir/.hidden.txt
+    //   dir/sub.txt/
+    //   dir/link.txt -> /dir/c.conf
+    //   dir/txt
+    let dir = tempfile::TempDir::new()?;
+    let subdir = dir.path().join("dir");
+    std::fs::create_dir(&subdir)?;
+    for name in ["a.txt", "b.txt", "c.conf", ".hidden.txt", "txt"] {
+        std::fs::write(subdir.join(name), b"")?;
+    }
+    std::fs::create_dir(subdir.join("sub.txt"))?;
+    std::os::unix::fs::symlink("/dir/c.conf", subdir.join("link.txt"))?;
+
+    let root = Root::open(&dir)?;
+    let glob_names = |dir: &str, pattern: &str| -> Result<Vec<String>, Error> {
+        root.glob_in(dir, pattern)?
+            .into_iter()
+            .map(|handle| {
+                let path = handle.as_unsafe_path_unchecked()?;
+                Ok(path.file_name().unwrap().to_string_lossy().into_owned())
+            })
+            .collect()
+    };
+
+    assert_eq!(
+        glob_names("dir", "*.txt")?,
+        ["a.txt", "b.txt", "link.txt", "sub.txt"],
+        "glob_in(\"dir\", \"*.txt\")"
+    );
+    assert_eq!(
+        glob_names("/dir/", ".*")?,
+        [".hidden.txt"],
+        "glob_in(\"/dir/\", \".*\")"
+    );
+    assert_eq!(
+        glob_names("dir", "[ab].txt")?,
+        ["a.txt", "b.txt"],
+        "glob_in(\"dir\", \"[ab].txt\")"
+    );
+    assert!(
+        glob_names("dir", "*.nomatch")?.is_empty(),
+        "glob_in with no matches should return nothing"
+    );
+
+    // Symlinks are not followed.
+    let link = root.glob_in("dir", "link.*")?;
+    assert_eq!(link.len(), 1, "glob_in(\"dir\", \"link.*\")");
+    assert!(
+        link[0].metadata()?.is_symlink(),
+        "glob_in should not follow symlinks"
+    );
+
+    for pattern in ["", "dir/*.txt", "*/"] {
+        assert_eq!(
+            root.glob_in("/", pattern)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::InvalidArgument),
+            "glob_in(\"/\", {pattern:?}) should fail"
+        );
+    }
+    assert_eq!(
+        root.glob_in("dir/a.txt", "*")
+            .map(|_| ())
+            .map_err(|err| err.kind()),
+        Err(ErrorKind::OsError(Some(libc::ENOTDIR))),
+        "glob_in on a non-directory should fail"
+    );
+
+    Ok(())
+}
+
+// Make sure that Root::chmod_all applies the right modes and doesn't touch
+// symlinks (or what they point to).
+#[test]
+fn root_chmod_all() -> Result<(), Error> {
+    // Layout:
+    //   outside (0o600)
+    //   dir/ (0o700)
+    //   dir/a (0o600)
+    //   dir/sub/ (0o700)
+    //   dir/sub/b (0o600)
+    //   dir/sub/link -> ../../outside
+    //   dir/abslink -> /outside
+    //   dir/dirlink -> /
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join(subpath);
+    std::fs::write(path("outside"), b"")?;
+    std::fs::create_dir_all(path("dir/sub"))?;
+    std::fs::write(path("dir/a"), b"")?;
+    std::fs::write(path("dir/sub/b"), b"")?;
+    std::os::unix::fs::symlink("../../outside", path("dir/sub/link"))?;
+    std::os::unix::fs::symlink("/outside", path("dir/abslink"))?;
+    std::os::unix::fs::symlink("/", path("dir/dirlink"))?;
+    for (subpath, mode) in [
+        ("outside", 0o600),
+        ("dir", 0o700),
+        ("dir/a", 0o600),
+        ("dir/sub", 0o700),
+        ("dir/sub/b", 0o600),
+    ] {
+        std::fs::set_permissions(path(subpath), Permissions::from_mode(mode))?;
+    }
+    let mode_of = |subpath: &str| -> Result<u32, Error> {
+        Ok(std::fs::symlink_metadata(path(subpath))?
+            .permissions()
+            .mode()
+            & 0o7777)
+    };
+    let link_modes = ["dir/sub/link", "dir/abslink", "dir/dirlink"]
+        .iter()
+        .map(|subpath| mode_of(subpath))
+        .collect::<Result<Vec<_>, _>>()?;
+    let root_mode = mode_of("")?;
+
+    let root = Root::open(&tmpdir)?;
+    root.chmod_all("dir", 0o751, 0o640)?;
+
+    for (subpath, mode) in [
+        ("dir", 0o751),
+        ("dir/a", 0o640),
+        ("dir/sub", 0o751),
+        ("dir/sub/b", 0o640),
+        // Inodes outside of the tree are untouched.
+        ("outside", 0o600),
+        ("", root_mode),
+    ] {
+        assert_eq!(
+            mode_of(subpath)?,
+            mode,
+            "chmod_all(\"dir\") should set the mode of {subpath:?} to {mode:o}"
+        );
+    }
+    assert_eq!(
+        ["dir/sub/link", "dir/abslink", "dir/dirlink"]
+            .iter()
+            .map(|subpath| mode_of(subpath))
+            .collect::<Result<Vec<_>, _>>()?,
+        link_modes,
+        "chmod_all should not modify symlinks"
+    );
+
+    // A single file only gets file_mode.
+    root.chmod_all("dir/a", 0o700, 0o604)?;
+    assert_eq!(mode_of("dir/a")?, 0o604, "chmod_all of a regular file");
+
+    assert_eq!(
+        root.chmod_all("nonexistent", 0o755, 0o644)
+            .map_err(|err| err.kind()),
+        Err(ErrorKind::OsError(Some(libc::ENOENT))),
+        "chmod_all of a non-existent path should fail"
+    );
+
+    Ok(())
+}
+
+// Make sure that Root::tree_digest is stable for identical trees and changes
+// when the tree does.
+#[cfg(feature = "tree-digest")]
+#[test]
+fn root_tree_digest() -> Result<(), Error> {
+    // Layout (for both tree1 and tree2):
+    //   dir/ (0o755)
+    //   dir/a ("hello", 0o644)
+    //   dir/sub/ (0o700)
+    //   dir/sub/b ("world", 0o600)
+    //   dir/sub/link -> ../a
+    //   dir/abslink -> /outside
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join(subpath);
+    for tree in ["tree1", "tree2"] {
+        let path = |subpath: &str| path(tree).join(subpath);
+        std::fs::create_dir_all(path("dir/sub"))?;
+        std::fs::write(path("dir/a"), b"hello")?;
+        std::fs::write(path("dir/sub/b"), b"world")?;
+        std::os::unix::fs::symlink("../a", path("dir/sub/link"))?;
+        std::os::unix::fs::symlink("/outside", path("dir/abslink"))?;
+        for (subpath, mode) in [
+            ("dir", 0o755),
+            ("dir/a", 0o644),
+            ("dir/sub", 0o700),
+            ("dir/sub/b", 0o600),
+        ] {
+            std::fs::set_permissions(path(subpath), Permissions::from_mode(mode))?;
+        }
+    }
+
+    let root = Root::open(&tmpdir)?;
+    let digests = |include_content: bool| -> Result<_, Error> {
+        Ok((
+            root.tree_digest("tree1/dir", include_content)?,
+            root.tree_digest("tree2/dir", include_content)?,
+        ))
+    };
+
+    // Identical trees have identical digests (and the name of the subtree
+    // doesn't matter).
+    let (digest1, digest2) = digests(false)?;
+    assert_eq!(
+        digest1, digest2,
+        "identical trees should have the same digest"
+    );
+    let (content1, content2) = digests(true)?;
+    assert_eq!(
+        content1, content2,
+        "identical trees should have the same digest"
+    );
+    assert_ne!(digest1, content1, "content should contribute to the digest");
+
+    // A single mode change alters the digest.
+    std::fs::set_permissions(path("tree2/dir/sub/b"), Permissions::from_mode(0o640))?;
+    let (digest1, digest2) = digests(false)?;
+    assert_ne!(digest1, digest2, "mode change should alter the digest");
+    std::fs::set_permissions(path("tree2/dir/sub/b"), Permissions::from_mode(0o600))?;
+    assert_eq!(digests(false)?, (digest1, digest1), "reverting the mode");
+
+    // Content changes (of the same size) only alter the digest with
+    // include_content.
+    std::fs::write(path("tree2/dir/sub/b"), b"WORLD")?;
+    let (digest1, digest2) = digests(false)?;
+    assert_eq!(digest1, digest2, "content change without include_content");
+    let (content1, content2) = digests(true)?;
+    assert_ne!(content1, content2, "content change with include_content");
+    std::fs::write(path("tree2/dir/sub/b"), b"world")?;
+
+    // Symlinks contribute their target, not what they point to.
+    std::fs::write(path("tree2/dir/a"), b"HELLO")?;
+    assert_ne!(
+        root.tree_digest("tree1/dir/sub/link", true)?,
+        root.tree_digest("tree1/dir/a", true)?,
+        "symlinks should not be followed"
+    );
+    assert_eq!(
+        root.tree_digest("tree1/dir/sub/link", true)?,
+        root.tree_digest("tree2/dir/sub/link", true)?,
+        "symlinks with the same target should have the same digest"
+    );
+
+    assert_eq!(
+        root.tree_digest("nonexistent", false)
+            .map_err(|err| err.kind()),
+        Err(ErrorKind::OsError(Some(libc::ENOENT))),
+        "tree_digest of a non-existent path should fail"
+    );
+
+    Ok(())
+}
+
+// Make sure that Root::rename with RENAME_EXCHANGE and RENAME_NOREPLACE moves
+// the right inodes, and that neither path can escape the root.
+#[test]
+fn root_rename_flags() -> Result<(), Error> {
+    // Layout:
+    //   outside/root/a ("a")
+    //   outside/root/dir/b ("b")
+    //   outside/a ("outside")
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join(subpath);
+    std::fs::create_dir_all(path("root/dir"))?;
+    std::fs::write(path("root/a"), b"a")?;
+    std::fs::write(path("root/dir/b"), b"b")?;
+    std::fs::write(path("a"), b"outside")?;
+
+    let mut root = Root::open(path("root"))?;
+
+    root.rename("a", "dir/b", RenameFlags::RENAME_EXCHANGE)?;
+    assert_eq!(
+        (
+            std::fs::read_to_string(path("root/a"))?,
+            std::fs::read_to_string(path("root/dir/b"))?
+        ),
+        ("b".to_string(), "a".to_string()),
+        "RENAME_EXCHANGE should swap the two files"
+    );
+
+    assert_eq!(
+        root.rename("a", "dir/b", RenameFlags::RENAME_NOREPLACE)
+            .map_err(|err| err.kind()),
+        Err(ErrorKind::OsError(Some(libc::EEXIST))),
+        "RENAME_NOREPLACE onto an existing file should fail"
+    );
+    assert_eq!(
+        (
+            std::fs::read_to_string(path("root/a"))?,
+            std::fs::read_to_string(path("root/dir/b"))?
+        ),
+        ("b".to_string(), "a".to_string()),
+        "failed RENAME_NOREPLACE should not modify either file"
+    );
+
+    // ".." components cannot escape the root.
+    root.rename(
+        "dir/../../../a",
+        Path::new("/../../dir/c"),
+        RenameFlags::RENAME_NOREPLACE,
+    )?;
+    assert_eq!(
+        std::fs::read_to_string(path("root/dir/c"))?,
+        "b",
+        "rename with escaping paths should stay inside the root"
+    );
+    assert_eq!(
+        std::fs::read_to_string(path("a"))?,
+        "outside",
+        "rename with escaping paths should not touch files outside the root"
+    );
+
+    // With ResolverFlags::BENEATH, escaping paths are rejected outright.
+    root.set_resolver_flags(ResolverFlags::BENEATH);
+    for (src, dst) in [("../a", "dir/d"), ("dir/b", "dir/../../d")] {
+        assert_eq!(
+            root.rename(src, dst, RenameFlags::empty())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::EXDEV))),
+            "rename({src:?}, {dst:?}) with BENEATH should fail"
+        );
+    }
+
+    Ok(())
+}
+
+// Make sure that Root::hardlink and Root::hardlink_follow link the right inode
+// and that neither path can escape the root.
+#[test]
+fn root_hardlink() -> Result<(), Error> {
+    // Layout:
+    //   outside/root/file ("file")
+    //   outside/root/dir/
+    //   outside/root/link -> /file
+    //   outside/file ("outside")
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join(subpath);
+    std::fs::create_dir_all(path("root/dir"))?;
+    std::fs::write(path("root/file"), b"file")?;
+    std::os::unix::fs::symlink("/file", path("root/link"))?;
+    std::fs::write(path("file"), b"outside")?;
+    let file_ino = std::fs::metadata(path("root/file"))?.ino();
+
+    let mut root = Root::open(path("root"))?;
+
+    root.hardlink("file", "dir/file")?;
+    let meta = std::fs::symlink_metadata(path("root/dir/file"))?;
+    assert_eq!(
+        (meta.ino(), meta.nlink()),
+        (file_ino, 2),
+        "hardlink should link to the target inode"
+    );
+
+    // Symlinks are only followed with hardlink_follow.
+    root.hardlink("link", "dir/link")?;
+    assert_eq!(
+        std::fs::read_link(path("root/dir/link"))?,
+        Path::new("/file"),
+        "hardlink of a symlink should link to the symlink itself"
+    );
+    root.hardlink_follow("link", "dir/link-follow")?;
+    assert_eq!(
+        std::fs::symlink_metadata(path("root/dir/link-follow"))?.ino(),
+        file_ino,
+        "hardlink_follow of a symlink should link to the symlink target"
+    );
+
+    assert_eq!(
+        root.hardlink("file", "dir/file").map_err(|err| err.kind()),
+        Err(ErrorKind::OsError(Some(libc::EEXIST))),
+        "hardlink onto an existing path should fail"
+    );
+    assert_eq!(
+        root.hardlink("dir", "dir2").map_err(|err| err.kind()),
+        Err(ErrorKind::OsError(Some(libc::EPERM))),
+        "hardlink of a directory should fail"
+    );
+    assert_eq!(
+        root.hardlink("file", "dir/").map_err(|err| err.kind()),
+        Err(ErrorKind::InvalidArgument),
+        "hardlink with a trailing slash should fail"
+    );
+
+    // ".." components cannot escape the root.
+    root.hardlink("dir/../../file", "../../file2")?;
+    assert_eq!(
+        std::fs::read_to_string(path("root/file2"))?,
+        "file",
+        "hardlink with escaping paths should stay inside the root"
+    );
+    assert!(
+        !path("file2").exists(),
+        "hardlink with escaping paths should not create files outside the root"
+    );
+
+    // With ResolverFlags::BENEATH, escaping paths are rejected outright.
+    root.set_resolver_flags(ResolverFlags::BENEATH);
+    for (target, link) in [("../file", "dir/file3"), ("file", "dir/../../file3")] {
+        assert_eq!(
+            root.hardlink(target, link).map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::EXDEV))),
+            "hardlink({target:?}, {link:?}) with BENEATH should fail"
+        );
+    }
+
+    Ok(())
+}
+
+// Make sure that Root::hardlink refuses to link across devices.
+#[test]
+fn root_hardlink_xdev() -> Result<(), Error> {
+    let root = Root::open("/")?;
+    let err = root
+        .hardlink("proc/self/status", "tmp/pathrs-hardlink-xdev")
+        .expect_err("hardlink across devices should fail");
+    assert_eq!(
+        err.kind(),
+        ErrorKind::OsError(Some(libc::EXDEV)),
+        "hardlink across devices should fail with EXDEV"
+    );
+    assert!(
+        err.to_string().contains("different devices"),
+        "hardlink across devices should have a clear error: {err}"
+    );
+
+    Ok(())
+}
+
+// Make sure that Root::open_with_backend forces the requested backend (or
+// fails if it is unsupported), and that the backend is actually usable.
+#[test]
+fn root_open_with_backend() -> Result<(), Error> {
+    let dir = tests_common::create_basic_tree()?;
+
+    for (backend, internal) in [
+        (
+            PublicResolverBackend::Openat2,
+            ResolverBackend::KernelOpenat2,
+        ),
+        (
+            PublicResolverBackend::Emulated,
+            ResolverBackend::EmulatedOpath,
+        ),
+        (
+            PublicResolverBackend::Hardcore,
+            ResolverBackend::HardcoreEmulated,
+        ),
+    ] {
+        if !backend.is_supported() {
+            assert_eq!(
+                Root::open_with_backend(&dir, backend)
+                    .map(|_| ())
+                    .map_err(|err| err.kind()),
+                Err(ErrorKind::NotImplemented),
+                "open_with_backend with unsupported {backend:?} backend should fail"
+            );
+            continue;
+        }
+
+        let root = Root::open_with_backend(&dir, backend)?;
+        assert_eq!(
+            root.resolver_backend(),
+            internal,
+            "open_with_backend({backend:?}) should force the backend"
+        );
+        let handle = root.resolve("link3/target_abs")?;
+        assert_eq!(
+            handle.as_unsafe_path_unchecked()?,
+            dir.path().join("target"),
+            "resolve symlink with forced {backend:?} backend"
+        );
+    }
+
+    Ok(())
+}
+
+// Make sure that pathrs_root_clone() returns an independent root handle which
+// still works after the original has been closed.
+#[cfg(feature = "capi")]
+#[test]
+fn capi_root_clone() -> Result<(), Error> {
+    use crate::tests::traits::RootImpl;
+
+    use std::os::unix::io::{AsFd, AsRawFd};
+
+    let dir = tests_common::create_basic_tree()?;
+
+    let root = capi::CapiRoot::open(&dir)?;
+    let root_clone = RootImpl::try_clone(&root)?;
+    assert_ne!(
+        root_clone.as_fd().as_raw_fd(),
+        root.as_fd().as_raw_fd(),
+        "pathrs_root_clone should return a new fd"
+    );
+    assert!(
+        rustix::io::fcntl_getfd(&root_clone)?.contains(rustix::io::FdFlags::CLOEXEC),
+        "pathrs_root_clone should return an O_CLOEXEC fd"
+    );
+    assert_eq!(
+        root_clone.as_fd().as_unsafe_path_unchecked()?,
+        dir.path(),
+        "cloned root should refer to the same directory"
+    );
+    drop(root);
+
+    let handle = RootImpl::resolve(&root_clone, "link3/target_abs")?;
+    assert_eq!(
+        handle.as_fd().as_unsafe_path_unchecked()?,
+        dir.path().join("target"),
+        "resolve through cloned root after closing the original"
+    );
+
+    Ok(())
+}
+
+// Make sure that pathrs_inroot_readlink() truncates the symlink target to the
+// size of the buffer (without adding a NUL terminator) and returns the full
+// length of the target, so that callers can retry with a larger buffer.
+#[cfg(feature = "capi")]
+#[test]
+fn capi_readlink_truncate() -> Result<(), Error> {
+    use crate::capi::core::pathrs_inroot_readlink;
+
+    use std::os::unix::io::AsFd;
+
+    let dir = tests_common::create_basic_tree()?;
+    let root = Root::open(&dir)?;
+    let path = CString::new("link3/target_abs")?;
+    let target = b"/link2/link1_rel/target_rel";
+
+    for bufsize in [0, 1, 5, target.len() - 1, target.len(), target.len() + 5] {
+        let mut buf = vec![b'!'; target.len() + 8];
+        let ret = unsafe {
+            pathrs_inroot_readlink(
+                root.as_fd().into(),
+                path.as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_char,
+                bufsize,
+            )
+        };
+        assert_eq!(
+            ret,
+            target.len() as libc::c_int,
+            "pathrs_inroot_readlink should return the untruncated length (bufsize={bufsize})"
+        );
+        let copied = bufsize.min(target.len());
+        assert_eq!(
+            &buf[..copied],
+            &target[..copied],
+            "pathrs_inroot_readlink should copy the truncated target (bufsize={bufsize})"
+        );
+        assert!(
+            buf[copied..].iter().all(|&b| b == b'!'),
+            "pathrs_inroot_readlink should not write past the target or add a NUL terminator (bufsize={bufsize}): {buf:?}"
+        );
+    }
+
+    Ok(())
+}
+
+// Make sure that pathrs_inroot_mkdir_all() cannot be tricked into creating
+// directories outside of the root by an attacker swapping a path component
+// with a symlink while the directories are being created.
+#[cfg(feature = "capi")]
+#[test]
+fn capi_mkdir_all_swap_race() -> Result<(), Error> {
+    use crate::capi::{core::pathrs_inroot_mkdir_all, error as capi_error};
+
+    use std::{
+        os::unix::io::{AsFd, FromRawFd, OwnedFd},
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    // Layout:
+    //   outside/
+    //   root/a/b/
+    //   root/a/b-link -> ../../outside
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join(subpath);
+    std::fs::create_dir_all(path("outside"))?;
+    std::fs::create_dir_all(path("root/a/b"))?;
+    // If the symlink were followed outside of the root, it would resolve to
+    // the "outside" directory we check below.
+    std::os::unix::fs::symlink("../../outside", path("root/a/b-link"))?;
+
+    let root = Root::open(path("root"))?;
+    let stop = AtomicBool::new(false);
+    std::thread::scope(|s| -> Result<(), Error> {
+        let swapper = s.spawn(|| -> Result<(), Error> {
+            // Keep swapping a/b between the directory and the symlink.
+            while !stop.load(Ordering::Relaxed) {
+                rustix::fs::renameat_with(
+                    rustix::fs::CWD,
+                    path("root/a/b"),
+                    rustix::fs::CWD,
+                    path("root/a/b-link"),
+                    rustix::fs::RenameFlags::EXCHANGE,
+                )?;
+            }
+            Ok(())
+        });
+
+        for i in 0..1000 {
+            let subpath = CString::new(format!("a/b/c{i}/d/e"))?;
+            let ret =
+                unsafe { pathrs_inroot_mkdir_all(root.as_fd().into(), subpath.as_ptr(), 0o755) };
+            if ret >= 0 {
+                // SAFETY: pathrs_inroot_mkdir_all returned a new fd.
+                drop(unsafe { OwnedFd::from_raw_fd(ret) });
+            } else if let Some(err) = unsafe { capi_error::pathrs_errorinfo(ret) } {
+                // Errors are expected (the tree is changing underneath us),
+                // but they still need to be freed.
+                // SAFETY: We don't touch err after freeing it.
+                unsafe { capi_error::pathrs_errorinfo_free(err as *mut _) }
+            }
+        }
+        stop.store(true, Ordering::Relaxed);
+        swapper.join().expect("swapper thread should not panic")
+    })?;
+
+    assert_eq!(
+        std::fs::read_dir(path("outside"))?.count(),
+        0,
+        "pathrs_inroot_mkdir_all should never create directories outside the root"
+    );
+
+    Ok(())
+}
+
+// Make sure that Root::try_clone returns an independent root (with the same
+// configuration) which still works after the original has been dropped, even
+// when used from another thread.
+#[test]
+fn root_clone_after_drop() -> Result<(), Error> {
+    use std::{
+        os::unix::io::{AsFd, AsRawFd},
+        thread,
+    };
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Root>();
+
+    let dir = tests_common::create_basic_tree()?;
+
+    for backend in [
+        ResolverBackend::KernelOpenat2,
+        ResolverBackend::EmulatedOpath,
+    ] {
+        if !backend.supported() {
+            continue;
+        }
+        let root = Root::open(&dir)?
+            .with_resolver_backend(backend)
+            .with_resolver_flags(ResolverFlags::NO_SYMLINKS);
+        let expected = root
+            .resolve("b/c/file")?
+            .as_fd()
+            .as_unsafe_path_unchecked()?;
+
+        let root_clone = root.try_clone()?;
+        assert_ne!(
+            root_clone.as_fd().as_raw_fd(),
+            root.as_fd().as_raw_fd(),
+            "Root::try_clone should return a new fd"
+        );
+        drop(root);
+
+        let handle = thread::spawn(move || {
+            assert_eq!(
+                root_clone.resolver_backend(),
+                backend,
+                "cloned root should have the same resolver backend"
+            );
+            assert_eq!(
+                root_clone
+                    .resolve("link3/target_abs")
+                    .map(|_| ())
+                    .map_err(|err| err.kind()),
+                Err(ErrorKind::OsError(Some(libc::ELOOP))),
+                "cloned root should have the same resolver flags"
+            );
+            root_clone.resolve("b/c/file")
+        })
+        .join()
+        .expect("resolve thread should not panic")?;
+        assert_eq!(
+            handle.as_fd().as_unsafe_path_unchecked()?,
+            expected,
+            "resolve through cloned root after dropping the original (backend={backend:?})"
+        );
+    }
+
+    Ok(())
+}
+
+// Make sure that Root::force_resolver_backend (and the RootRef equivalent)
+// can force the backend of an existing handle.
+#[test]
+fn root_force_resolver_backend() -> Result<(), Error> {
+    let dir = tests_common::create_basic_tree()?;
+
+    for (backend, internal) in [
+        (
+            PublicResolverBackend::Openat2,
+            ResolverBackend::KernelOpenat2,
+        ),
+        (
+            PublicResolverBackend::Emulated,
+            ResolverBackend::EmulatedOpath,
+        ),
+        (
+            PublicResolverBackend::Hardcore,
+            ResolverBackend::HardcoreEmulated,
+        ),
+    ] {
+        let mut root = Root::open(&dir)?;
+        let old_backend = root.resolver_backend();
+
+        if !backend.is_supported() {
+            assert_eq!(
+                root.force_resolver_backend(backend)
+                    .map(|_| ())
+                    .map_err(|err| err.kind()),
+                Err(ErrorKind::NotImplemented),
+                "force_resolver_backend with unsupported {backend:?} backend should fail"
+            );
+            assert_eq!(
+                root.resolver_backend(),
+                old_backend,
+                "failed force_resolver_backend({backend:?}) should not change the backend"
+            );
+            continue;
+        }
+
+        root.force_resolver_backend(backend)?;
+        assert_eq!(
+            root.resolver_backend(),
+            internal,
+            "Root::force_resolver_backend({backend:?}) should force the backend"
+        );
+
+        let root_ref = Root::open(&dir)?;
+        let root_ref = root_ref.as_ref().with_forced_resolver_backend(backend)?;
+        assert_eq!(
+            root_ref.resolver_backend(),
+            internal,
+            "RootRef::with_forced_resolver_backend({backend:?}) should force the backend"
+        );
+
+        for root in [root.as_ref(), root_ref] {
+            let handle = root.resolve("link3/target_abs")?;
+            assert_eq!(
+                handle.as_unsafe_path_unchecked()?,
+                dir.path().join("target"),
+                "resolve symlink with forced {backend:?} backend"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn root_resolve_with_inode_trail() -> Result<(), Error> {
+    // Layout:
+    //   root/a/b/file
+    //   root/link -> a/b
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join("root").join(subpath);
+    std::fs::create_dir_all(path("a/b"))?;
+    std::fs::write(path("a/b/file"), b"file")?;
+    std::os::unix::fs::symlink("a/b", path("link"))?;
+    let inode = |subpath: &str| -> Result<(u64, u64), Error> {
+        let meta = std::fs::symlink_metadata(path(subpath))?;
+        Ok((meta.dev(), meta.ino()))
+    };
+
+    let root = Root::open_with_backend(path(""), PublicResolverBackend::Emulated)?;
+    for (subpath, expected) in [
+        ("a/b/file", vec!["a", "a/b", "a/b/file"]),
+        ("link/file", vec!["link", "a", "a/b", "a/b/file"]),
+        // "." components and the root itself are not part of the trail.
+        (
+            "a/../link/./file",
+            vec!["a", "link", "a", "a/b", "a/b/file"],
+        ),
+        ("a/b/..", vec!["a", "a/b", "a"]),
+        (".", vec![]),
+        ("/", vec![]),
+        ("a/..", vec!["a"]),
+    ] {
+        let (handle, trail) = root.resolve_with_inode_trail(subpath)?;
+        let expected = expected
+            .into_iter()
+            .map(inode)
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            trail, expected,
+            "inode trail of {subpath:?} should match the components walked"
+        );
+        let meta = handle.metadata()?;
+        if (meta.dev(), meta.ino()) != inode("")? {
+            assert_eq!(
+                trail.last(),
+                Some(&(meta.dev(), meta.ino())),
+                "inode trail of {subpath:?} should end with the target"
+            );
+        }
+    }
+
+    for subpath in ["nonexist", "a/nonexist/file", "link/nonexist", "a/b/file/"] {
+        let expected = if subpath.ends_with('/') {
+            libc::ENOTDIR
+        } else {
+            libc::ENOENT
+        };
+        assert_eq!(
+            root.resolve_with_inode_trail(subpath)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(expected))),
+            "resolve_with_inode_trail of a partial path {subpath:?} should fail"
+        );
+    }
+
+    if PublicResolverBackend::Openat2.is_supported() {
+        let root = Root::open_with_backend(path(""), PublicResolverBackend::Openat2)?;
+        let (handle, trail) = root.resolve_with_inode_trail("link/file")?;
+        assert_eq!(
+            trail,
+            Vec::new(),
+            "openat2 resolutions should have an empty inode trail"
+        );
+        assert_eq!(
+            handle.as_unsafe_path_unchecked()?,
+            path("a/b/file"),
+            "resolve_with_inode_trail should still resolve the path"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn root_resolve_with_info() -> Result<(), Error> {
+    // Layout:
+    //   root/a/b/file
+    //   root/link -> a/b
+    //   root/abslink -> /link
+    //   root/dotdotlink -> ../../a
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join("root").join(subpath);
+    std::fs::create_dir_all(path("a/b"))?;
+    std::fs::write(path("a/b/file"), b"file")?;
+    std::os::unix::fs::symlink("a/b", path("link"))?;
+    std::os::unix::fs::symlink("/link", path("abslink"))?;
+    std::os::unix::fs::symlink("../../a", path("dotdotlink"))?;
+
+    // (path, expected target, symlinks followed, ".." clamped)
+    let paths = [
+        ("a/b/file", "a/b/file", 0, false),
+        ("link/file", "a/b/file", 1, false),
+        ("abslink/file", "a/b/file", 2, false),
+        ("a/../a/b/file", "a/b/file", 0, false),
+        ("../a/b/file", "a/b/file", 0, true),
+        ("a/../../a/b", "a/b", 0, true),
+        ("dotdotlink/b/file", "a/b/file", 1, true),
+    ];
+
+    let root = Root::open_with_backend(path(""), PublicResolverBackend::Emulated)?;
+    for (subpath, target, symlinks, clamped) in paths {
+        let (handle, info) = root.resolve_with_info(subpath)?;
+        assert_eq!(
+            handle.as_unsafe_path_unchecked()?,
+            path(target),
+            "resolve_with_info({subpath:?}) should resolve the path"
+        );
+        assert_eq!(
+            info.backend(),
+            PublicResolverBackend::Emulated,
+            "resolve_with_info({subpath:?}) backend"
+        );
+        assert_eq!(
+            info.symlink_traversals(),
+            Some(symlinks),
+            "resolve_with_info({subpath:?}) symlink traversals"
+        );
+        assert_eq!(
+            info.dotdot_clamped(),
+            Some(clamped),
+            "resolve_with_info({subpath:?}) dotdot clamping"
+        );
+    }
+
+    if PublicResolverBackend::Openat2.is_supported() {
+        let root = Root::open_with_backend(path(""), PublicResolverBackend::Openat2)?;
+        for (subpath, target, _, _) in paths {
+            let (handle, info) = root.resolve_with_info(subpath)?;
+            assert_eq!(
+                handle.as_unsafe_path_unchecked()?,
+                path(target),
+                "resolve_with_info({subpath:?}) should resolve the path"
+            );
+            assert_eq!(
+                (
+                    info.backend(),
+                    info.symlink_traversals(),
+                    info.dotdot_clamped()
+                ),
+                (PublicResolverBackend::Openat2, None, None),
+                "openat2 resolve_with_info({subpath:?}) should only report the backend"
+            );
+        }
+
+        // Configuration that requires the emulated backend is reported.
+        let root = root.with_setid_policy(SetidPolicy::RejectIntermediate);
+        let (_, info) = root.resolve_with_info("link/file")?;
+        assert_eq!(info.backend(), PublicResolverBackend::Emulated);
+        assert_eq!(info.symlink_traversals(), Some(1));
+    }
+
+    Ok(())
+}
+
+// Make sure that a ResolveObserver is notified about resolutions.
+#[test]
+fn root_resolve_observer() -> Result<(), Error> {
+    use std::panic::{RefUnwindSafe, UnwindSafe};
+
+    fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe>() {}
+    assert_unwind_safe::<Root>();
+
+    #[derive(Debug, Default)]
+    struct CountingObserver {
+        starts: AtomicUsize,
+        symlinks: AtomicUsize,
+        escapes: AtomicUsize,
+        successes: AtomicUsize,
+        errors: Mutex<Vec<ErrorKind>>,
+    }
+
+    impl ResolveObserver for CountingObserver {
+        fn resolve_start(&self, backend: PublicResolverBackend) {
+            assert_eq!(backend, PublicResolverBackend::Emulated);
+            self.starts.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn symlink_followed(&self, _symlink_traversals: usize) {
+            self.symlinks.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn escape_blocked(&self) {
+            self.escapes.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn resolve_end(&self, backend: PublicResolverBackend, result: Result<(), ErrorKind>) {
+            assert_eq!(backend, PublicResolverBackend::Emulated);
+            match result {
+                Ok(()) => {
+                    self.successes.fetch_add(1, Ordering::SeqCst);
+                }
+                Err(kind) => self.errors.lock().unwrap().push(kind),
+            }
+        }
+    }
+
+    // Layout:
+    //   root/a/b/file
+    //   root/link -> a/b
+    //   root/loop -> loop
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join("root").join(subpath);
+    std::fs::create_dir_all(path("a/b"))?;
+    std::fs::write(path("a/b/file"), b"file")?;
+    std::os::unix::fs::symlink("a/b", path("link"))?;
+    std::os::unix::fs::symlink("loop", path("loop"))?;
+
+    let observer: &'static CountingObserver = Box::leak(Box::default());
+    let root = Root::open_with_backend(path(""), PublicResolverBackend::Emulated)?
+        .with_max_symlink_traversals(Some(8))
+        .with_resolve_observer(Some(observer));
+
+    root.resolve("a/b/file")?;
+    root.resolve("link/file")?;
+    root.resolve("../../link/../b/file")?;
+    root.resolve_with_info("link")?;
+    assert_eq!(
+        root.resolve("loop").map_err(|err| err.kind()).unwrap_err(),
+        ErrorKind::OsError(Some(libc::ELOOP)),
+        "resolving a symlink loop should fail"
+    );
+
+    assert_eq!(
+        observer.starts.load(Ordering::SeqCst),
+        5,
+        "resolutions started"
+    );
+    assert_eq!(
+        observer.successes.load(Ordering::SeqCst),
+        4,
+        "successful resolutions"
+    );
+    assert_eq!(
+        *observer.errors.lock().unwrap(),
+        vec![ErrorKind::OsError(Some(libc::ELOOP))],
+        "failed resolutions"
+    );
+    // The loop symlink is followed until the limit is hit (the symlink which
+    // hits the limit is not followed, so at most limit-1 are followed).
+    assert_eq!(
+        observer.symlinks.load(Ordering::SeqCst),
+        3 + 7,
+        "symlinks followed"
+    );
+    assert_eq!(
+        observer.escapes.load(Ordering::SeqCst),
+        2,
+        "escapes blocked"
+    );
+
+    // Unregistering the observer stops any further notifications.
+    let root = root.with_resolve_observer(None);
+    root.resolve("link/file")?;
+    assert_eq!(
+        observer.starts.load(Ordering::SeqCst),
+        5,
+        "resolutions started"
+    );
+
+    Ok(())
+}
+
+// Make sure that a global ResolveObserver is notified about resolutions.
+#[test]
+fn root_resolve_global_observer() -> Result<(), Error> {
+    #[derive(Debug, Default)]
+    struct CountingObserver {
+        ends: AtomicUsize,
+    }
+
+    impl ResolveObserver for CountingObserver {
+        fn resolve_end(&self, _backend: PublicResolverBackend, _result: Result<(), ErrorKind>) {
+            self.ends.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let root_dir = tests_common::create_basic_tree()?;
+    let root = Root::open(&root_dir)?;
+
+    // NOTE: Other tests are running in parallel, so we can only check that
+    // our resolutions were observed (not that nothing else was).
+    let observer: &'static CountingObserver = Box::leak(Box::default());
+    crate::set_global_resolve_observer(Some(observer));
+    let ret = root.resolve("b/c/file");
+    crate::set_global_resolve_observer(None);
+    ret?;
+
+    assert!(
+        observer.ends.load(Ordering::SeqCst) >= 1,
+        "global observer should have been notified"
+    );
+
+    Ok(())
+}
+
+// Make sure that Root::mknod creates the right inode types and returns a
+// handle to the new inode.
+#[test]
+fn root_mknod() -> Result<(), Error> {
+    // Layout:
+    //   outside/root/dir/
+    //   outside/root/link -> /dir
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join(subpath);
+    std::fs::create_dir_all(path("root/dir"))?;
+    std::os::unix::fs::symlink("/dir", path("root/link"))?;
+
+    let root = Root::open(path("root"))?;
+
+    for (name, mode, expected) in [
+        ("dir/fifo", libc::S_IFIFO | 0o644, libc::S_IFIFO | 0o644),
+        ("dir/sock", libc::S_IFSOCK | 0o600, libc::S_IFSOCK | 0o600),
+        ("dir/file", libc::S_IFREG | 0o640, libc::S_IFREG | 0o640),
+        ("dir/file-notype", 0o600, libc::S_IFREG | 0o600),
+        (
+            "link/../../fifo-link",
+            libc::S_IFIFO | 0o600,
+            libc::S_IFIFO | 0o600,
+        ),
+    ] {
+        let handle = root.mknod(name, mode, 0)?;
+        let meta = handle.metadata()?;
+        assert_eq!(
+            meta.mode(),
+            expected,
+            "mknod({name:?}, {mode:#o}) handle should have the right mode"
+        );
+        assert_eq!(
+            root.resolve_nofollow(name)?.metadata()?.ino(),
+            meta.ino(),
+            "mknod({name:?}, {mode:#o}) handle should be the created inode"
+        );
+    }
+    assert_eq!(
+        std::fs::symlink_metadata(path("root/fifo-link"))?.mode() & libc::S_IFMT,
+        libc::S_IFIFO,
+        "mknod through a symlink parent should stay inside the root"
+    );
+
+    assert_eq!(
+        root.mknod("dir/fifo", libc::S_IFIFO | 0o644, 0)
+            .map(|_| ())
+            .map_err(|err| err.kind()),
+        Err(ErrorKind::OsError(Some(libc::EEXIST))),
+        "mknod onto an existing path should fail"
+    );
+    for (name, mode) in [
+        ("dir/.", libc::S_IFIFO | 0o644),
+        ("dir/..", libc::S_IFIFO | 0o644),
+        ("dir/", libc::S_IFIFO | 0o644),
+        ("dir/subdir", libc::S_IFDIR | 0o755),
+        ("dir/symlink", libc::S_IFLNK | 0o777),
+        ("dir/bad-mode", libc::S_IFIFO | 0o200000),
+    ] {
+        assert_eq!(
+            root.mknod(name, mode, 0)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::InvalidArgument),
+            "mknod({name:?}, {mode:#o}) should fail"
+        );
+    }
+
+    #[cfg(feature = "_test_as_root")]
+    {
+        let dev = libc::makedev(1, 3);
+        let meta = root
+            .mknod("dir/null", libc::S_IFCHR | 0o666, dev)?
+            .metadata()?;
+        assert_eq!(
+            (meta.mode() & libc::S_IFMT, meta.rdev()),
+            (libc::S_IFCHR, dev),
+            "mknod of a character device should have the right type and device"
+        );
+    }
+
+    Ok(())
+}
+
+// Make sure that Root::create_whiteout creates a 0:0 character device and
+// never follows a trailing symlink. Whiteouts need CAP_MKNOD on some kernels
+// and filesystems, so unprivileged runs may get EPERM instead.
+#[test]
+fn root_create_whiteout() -> Result<(), Error> {
+    // Layout:
+    //   outside/root/dir/
+    //   outside/root/dangling -> /dir/target
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join(subpath);
+    std::fs::create_dir_all(path("root/dir"))?;
+    std::os::unix::fs::symlink("/dir/target", path("root/dangling"))?;
+
+    let root = Root::open(path("root"))?;
+
+    assert_eq!(
+        root.create_whiteout("dangling").map_err(|err| err.kind()),
+        Err(ErrorKind::OsError(Some(libc::EEXIST))),
+        "create_whiteout on a dangling symlink should fail"
+    );
+    assert!(
+        std::fs::symlink_metadata(path("root/dir/target")).is_err(),
+        "create_whiteout should not follow a trailing symlink"
+    );
+    assert_eq!(
+        root.create_whiteout("dir/").map_err(|err| err.kind()),
+        Err(ErrorKind::InvalidArgument),
+        "create_whiteout with a trailing slash should fail"
+    );
+
+    match root.create_whiteout("dir/whiteout") {
+        Err(err) if !cfg!(feature = "_test_as_root") => assert_eq!(
+            err.kind(),
+            ErrorKind::OsError(Some(libc::EPERM)),
+            "unprivileged create_whiteout should only fail with EPERM"
+        ),
+        res => {
+            res?;
+            let meta = std::fs::symlink_metadata(path("root/dir/whiteout"))?;
+            assert_eq!(
+                (meta.mode(), meta.rdev()),
+                (libc::S_IFCHR, 0),
+                "create_whiteout should create a 0:0 character device"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn root_create_with() -> Result<(), Error> {
+    // Layout:
+    //   outside/root/dir/
+    //   outside/root/dangling -> /dir/target
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join(subpath);
+    std::fs::create_dir_all(path("root/dir"))?;
+    std::os::unix::fs::symlink("/dir/target", path("root/dangling"))?;
+
+    let root = Root::open(path("root"))?;
+
+    let mut file = root.create_with("dir/file", OpenFlags::O_EXCL | OpenFlags::O_RDWR, 0o640)?;
+    file.write_all(b"data")?;
+    let meta = std::fs::symlink_metadata(path("root/dir/file"))?;
+    assert_eq!(
+        meta.mode() & libc::S_IFMT,
+        libc::S_IFREG,
+        "create_with should create a regular file"
+    );
+    assert_eq!(
+        meta.mode() & 0o7777 & !0o640,
+        0,
+        "create_with should use the requested mode"
+    );
+
+    assert_eq!(
+        root.create_with("dir/file", OpenFlags::O_EXCL | OpenFlags::O_RDWR, 0o640)
+            .map_err(|err| err.kind())
+            .err(),
+        Some(ErrorKind::OsError(Some(libc::EEXIST))),
+        "create_with(O_EXCL) of an existing file should fail"
+    );
+    let mut contents = String::new();
+    root.create_with("dir/file", OpenFlags::O_RDONLY, 0o640)?
+        .read_to_string(&mut contents)?;
+    assert_eq!(
+        contents, "data",
+        "create_with without O_EXCL should open the existing file"
+    );
+
+    // Dangling symlinks are never followed.
+    assert_eq!(
+        root.create_with("dangling", OpenFlags::O_EXCL | OpenFlags::O_RDWR, 0o640)
+            .map_err(|err| err.kind())
+            .err(),
+        Some(ErrorKind::OsError(Some(libc::EEXIST))),
+        "create_with(O_EXCL) of a dangling symlink should fail"
+    );
+    assert_eq!(
+        root.create_with("dangling", OpenFlags::O_RDWR, 0o640)
+            .map_err(|err| err.kind())
+            .err(),
+        Some(ErrorKind::OsError(Some(libc::ELOOP))),
+        "create_with of a dangling symlink should fail"
+    );
+    assert!(
+        !path("root/dir/target").exists(),
+        "create_with should not create the target of a dangling symlink"
+    );
+
+    for (name, mode) in [("dir/bad-mode", 0o10644), ("dir/", 0o644)] {
+        assert_eq!(
+            root.create_with(name, OpenFlags::O_RDWR, mode)
+                .map_err(|err| err.kind())
+                .err(),
+            Some(ErrorKind::InvalidArgument),
+            "create_with({name:?}, {mode:o}) should fail"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn root_single_component() -> Result<(), Error> {
+    for backend in [
+        ResolverBackend::KernelOpenat2,
+        ResolverBackend::EmulatedOpath,
+    ] {
+        if !backend.supported() {
+            continue;
+        }
+        let tmpdir = tempfile::TempDir::new()?;
+        let path = |subpath: &str| tmpdir.path().join(subpath);
+        std::fs::create_dir_all(path("root/dir"))?;
+
+        let root = Root::open(path("root"))?
+            .with_resolver_backend(backend)
+            .with_resolver_flags(ResolverFlags::SINGLE_COMPONENT);
+
+        root.create_file(
+            "foo",
+            OpenFlags::O_EXCL | OpenFlags::O_WRONLY,
+            &Permissions::from_mode(0o644),
+        )?
+        .write_all(b"value")?;
+        let mut contents = String::new();
+        root.open_subpath("foo", OpenFlags::O_RDONLY)?
+            .read_to_string(&mut contents)?;
+        assert_eq!(
+            contents, "value",
+            "{backend:?}: open_subpath(foo) should work"
+        );
+        root.create(
+            "newdir",
+            &InodeType::Directory(Permissions::from_mode(0o755)),
+        )?;
+        assert!(
+            path("root/newdir").is_dir(),
+            "{backend:?}: create(newdir) should work"
+        );
+
+        for bad in ["dir/foo", "../foo", "./dir/../foo"] {
+            assert_eq!(
+                root.resolve(bad).map(|_| ()).map_err(|err| err.kind()),
+                Err(ErrorKind::InvalidArgument),
+                "{backend:?}: resolve({bad:?}) should be rejected"
+            );
+            assert_eq!(
+                root.create_file(
+                    bad,
+                    OpenFlags::O_EXCL | OpenFlags::O_WRONLY,
+                    &Permissions::from_mode(0o644)
+                )
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+                Err(ErrorKind::InvalidArgument),
+                "{backend:?}: create_file({bad:?}) should be rejected"
+            );
+        }
+        assert!(
+            !path("root/dir/foo").exists() && !path("foo").exists(),
+            "{backend:?}: rejected paths should not have been created"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn root_resolve_cached() -> Result<(), Error> {
+    for backend in [
+        ResolverBackend::KernelOpenat2,
+        ResolverBackend::EmulatedOpath,
+    ] {
+        if !backend.supported() {
+            continue;
+        }
+        let tmpdir = tempfile::TempDir::new()?;
+        let path = |subpath: &str| tmpdir.path().join(subpath);
+        std::fs::create_dir_all(path("root/a/b/c"))?;
+        std::fs::create_dir_all(path("root/target"))?;
+        std::os::unix::fs::symlink("/target", path("root/a/link"))?;
+        for i in 0..16 {
+            std::fs::write(path(&format!("root/a/b/c/file{i}")), "")?;
+        }
+        std::fs::write(path("root/target/file"), "")?;
+
+        let root = Root::open(path("root"))?.with_resolver_backend(backend);
+        let mut cache = DirCache::new();
+        // openat2(2) does the whole lookup in one syscall, so nothing is cached.
+        let num_prefixes = match backend {
+            ResolverBackend::KernelOpenat2 => 0,
+            _ => 3,
+        };
+
+        let same_inode = |cached: &Path, plain: &Path| -> Result<(), Error> {
+            let (cached, plain) = (
+                root.resolve_cached(cached, &mut DirCache::new())?.stat()?,
+                root.resolve(plain)?.stat()?,
+            );
+            assert_eq!(
+                (cached.dev(), cached.ino()),
+                (plain.dev(), plain.ino()),
+                "{backend:?}: resolve_cached and resolve should agree"
+            );
+            Ok(())
+        };
+
+        for i in 0..16 {
+            let subpath = format!("a/b/c/file{i}");
+            let (cached, plain) = (
+                root.resolve_cached(&subpath, &mut cache)?.stat()?,
+                root.resolve(&subpath)?.stat()?,
+            );
+            assert_eq!(
+                (cached.dev(), cached.ino()),
+                (plain.dev(), plain.ino()),
+                "{backend:?}: resolve_cached({subpath:?}) should match resolve"
+            );
+        }
+        assert_eq!(
+            cache.len(),
+            num_prefixes,
+            "{backend:?}: only the directory prefixes should be cached"
+        );
+
+        // Symlinks are resolved correctly but never cached as prefixes, and
+        // ".." falls back to a regular lookup.
+        same_inode(Path::new("a/link/file"), Path::new("target/file"))?;
+        same_inode(Path::new("a/b/../b/c/file0"), Path::new("a/b/c/file0"))?;
+        root.resolve_cached("a/link/file", &mut cache)?;
+        assert_eq!(
+            cache.len(),
+            num_prefixes,
+            "{backend:?}: symlink prefixes should not be cached"
+        );
+        assert_eq!(
+            root.resolve_cached("a/b/c/nonexist", &mut cache)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::ENOENT))),
+            "{backend:?}: resolve_cached of a missing path should fail like resolve"
+        );
+
+        // Swap a/b for a different directory. The stale prefix (and everything
+        // beneath it) must be dropped from the cache.
+        std::fs::rename(path("root/a/b"), path("root/old-b"))?;
+        std::fs::create_dir_all(path("root/a/b/c"))?;
+        std::fs::write(path("root/a/b/c/file0"), "")?;
+        let (cached, plain) = (
+            root.resolve_cached("a/b/c/file0", &mut cache)?.stat()?,
+            root.resolve("a/b/c/file0")?.stat()?,
+        );
+        assert_eq!(
+            (cached.dev(), cached.ino()),
+            (plain.dev(), plain.ino()),
+            "{backend:?}: resolve_cached should notice the swapped directory"
+        );
+        assert_eq!(
+            root.resolve_cached("a/b/c/file1", &mut cache)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::ENOENT))),
+            "{backend:?}: resolve_cached should not use the old a/b/c"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn root_resolve_cached_stats() -> Result<(), Error> {
+    for backend in [
+        ResolverBackend::KernelOpenat2,
+        ResolverBackend::EmulatedOpath,
+    ] {
+        if !backend.supported() {
+            continue;
+        }
+        let tmpdir = tempfile::TempDir::new()?;
+        let path = |subpath: &str| tmpdir.path().join(subpath);
+        std::fs::create_dir_all(path("root/a/b/c"))?;
+        std::fs::write(path("root/a/b/c/file"), "")?;
+
+        let root = Root::open(path("root"))?.with_resolver_backend(backend);
+        let mut cache = DirCache::new();
+        let (_, first) = root.resolve_cached_with_stats("a/b/c/file", &mut cache)?;
+        let (_, second) = root.resolve_cached_with_stats("a/b/c/file", &mut cache)?;
+
+        if backend == ResolverBackend::KernelOpenat2 {
+            // openat2(2) never uses the cache.
+            assert_eq!(first, DirCacheStats::default(), "{backend:?}: first lookup");
+            assert_eq!(
+                second,
+                DirCacheStats::default(),
+                "{backend:?}: second lookup"
+            );
+            assert_eq!(
+                cache.stats(),
+                DirCacheStats::default(),
+                "{backend:?}: totals"
+            );
+            continue;
+        }
+
+        assert_eq!(
+            (first.hits(), first.misses()),
+            (0, 3),
+            "{backend:?}: first lookup should miss for every prefix"
+        );
+        assert_eq!(
+            (second.hits(), second.misses()),
+            (3, 0),
+            "{backend:?}: identical second lookup should hit for every prefix"
+        );
+        assert_eq!(
+            (cache.stats().hits(), cache.stats().misses()),
+            (3, 3),
+            "{backend:?}: cache totals should include both lookups"
+        );
+
+        // A swapped prefix is a miss, even though it was cached.
+        std::fs::rename(path("root/a/b"), path("root/old-b"))?;
+        std::fs::create_dir_all(path("root/a/b/c"))?;
+        std::fs::write(path("root/a/b/c/file"), "")?;
+        let (_, swapped) = root.resolve_cached_with_stats("a/b/c/file", &mut cache)?;
+        assert_ne!(
+            swapped.misses(),
+            0,
+            "{backend:?}: swapped prefix should be a cache miss"
+        );
+
+        cache.reset_stats();
+        assert_eq!(
+            cache.stats(),
+            DirCacheStats::default(),
+            "{backend:?}: reset totals"
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn root_resolve_async() -> Result<(), Error> {
+    use std::sync::Arc;
+
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join(subpath);
+    std::fs::create_dir_all(path("root/dir"))?;
+    for i in 0..8 {
+        std::fs::write(path(&format!("root/dir/file{i}")), format!("file{i}"))?;
+    }
+
+    let root = Arc::new(Root::open(path("root"))?);
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+    runtime.block_on(async {
+        let tasks = (0..8)
+            .map(|i| {
+                let root = Arc::clone(&root);
+                tokio::spawn(async move {
+                    let handle = Arc::new(root.resolve_async(format!("dir/file{i}")).await?);
+                    let mut contents = String::new();
+                    handle
+                        .reopen_async(OpenFlags::O_RDONLY)
+                        .await?
+                        .read_to_string(&mut contents)?;
+                    Ok::<_, Error>((i, contents))
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            let (i, contents) = task.await??;
+            assert_eq!(
+                contents,
+                format!("file{i}"),
+                "resolve_async(dir/file{i}) should resolve the right file"
+            );
+        }
+
+        assert_eq!(
+            root.resolve_async("dir/nonexist")
+                .await
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::ENOENT))),
+            "resolve_async should return the same errors as resolve"
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn root_touch() -> Result<(), Error> {
+    // Set the times of path (without following symlinks) to the epoch.
+    fn reset_times(path: &Path) -> Result<(), Error> {
+        let epoch = rustix::fs::Timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        rustix::fs::utimensat(
+            rustix::fs::CWD,
+            path,
+            &rustix::fs::Timestamps {
+                last_access: epoch,
+                last_modification: epoch,
+            },
+            rustix::fs::AtFlags::SYMLINK_NOFOLLOW,
+        )?;
+        Ok(())
+    }
+
+    for backend in [
+        ResolverBackend::KernelOpenat2,
+        ResolverBackend::EmulatedOpath,
+    ] {
+        if !backend.supported() {
+            continue;
+        }
+        let tmpdir = tempfile::TempDir::new()?;
+        let path = |subpath: &str| tmpdir.path().join(subpath);
+        std::fs::create_dir_all(path("root/dir"))?;
+        let root = Root::open(path("root"))?.with_resolver_backend(backend);
+
+        // Create a new file.
+        let handle = root.touch("dir/new", 0o640)?;
+        let meta = std::fs::symlink_metadata(path("root/dir/new"))?;
+        assert!(meta.is_file(), "{backend:?}: touch should create a file");
+        assert_eq!(
+            meta.mode() & 0o7777 & !0o640,
+            0,
+            "{backend:?}: touch should create the file with the given mode"
+        );
+        assert_eq!(
+            handle.stat()?.ino(),
+            meta.ino(),
+            "{backend:?}: touch should return a handle to the new file"
+        );
+
+        // Update an existing file.
+        std::fs::write(path("root/dir/existing"), "contents")?;
+        reset_times(&path("root/dir/existing"))?;
+        let handle = root.touch("dir/existing", 0o644)?;
+        let meta = std::fs::symlink_metadata(path("root/dir/existing"))?;
+        assert_ne!(
+            meta.mtime(),
+            0,
+            "{backend:?}: touch should update the mtime of an existing file"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path("root/dir/existing"))?,
+            "contents",
+            "{backend:?}: touch should not modify the contents of an existing file"
+        );
+        assert_eq!(
+            handle.stat()?.ino(),
+            meta.ino(),
+            "{backend:?}: touch should return a handle to the existing file"
+        );
+
+        // A dangling trailing symlink is not followed, and the symlink itself
+        // is updated.
+        std::os::unix::fs::symlink("/dir/target", path("root/dir/link"))?;
+        reset_times(&path("root/dir/link"))?;
+        root.touch("dir/link", 0o644)?;
+        assert!(
+            !path("root/dir/target").exists() && !path("dir/target").exists(),
+            "{backend:?}: touch should not create the target of a dangling symlink"
+        );
+        assert_ne!(
+            std::fs::symlink_metadata(path("root/dir/link"))?.mtime(),
+            0,
+            "{backend:?}: touch should update the mtime of the symlink itself"
+        );
+
+        assert_eq!(
+            root.touch("dir/bad-mode", 0o10644)
+                .map(|_| ())
+                .map_err(|err| err.kind()),
+            Err(ErrorKind::InvalidArgument),
+            "{backend:?}: touch should reject non-0o7777 mode bits"
+        );
+    }
+
+    Ok(())
+}
+
+// Make sure that Root::rename_with_callback{,_deep} report the right from/to
+// mapping for the renamed directory (and its descendants).
+#[test]
+fn root_rename_with_callback() -> Result<(), Error> {
+    // Layout:
+    //   root/dir/file
+    //   root/dir/sub/file
+    //   root/dir/link -> /outside
+    //   root/outside/file
+    let tmpdir = tempfile::TempDir::new()?;
+    let path = |subpath: &str| tmpdir.path().join(subpath);
+    std::fs::create_dir_all(path("root/dir/sub"))?;
+    std::fs::create_dir_all(path("root/outside"))?;
+    std::fs::write(path("root/dir/file"), b"")?;
+    std::fs::write(path("root/dir/sub/file"), b"")?;
+    std::fs::write(path("root/outside/file"), b"")?;
+    std::os::unix::fs::symlink("/outside", path("root/dir/link"))?;
+
+    let root = Root::open(path("root"))?;
+
+    let mut moved = vec![];
+    root.rename_with_callback("dir", "dir2", |from, to| {
+        moved.push((from.to_path_buf(), to.to_path_buf()))
+    })?;
+    assert_eq!(
+        moved,
+        [("dir".into(), "dir2".into())],
+        "rename_with_callback should only report the renamed path"
+    );
+
+    let mut moved = vec![];
+    root.rename_with_callback_deep("dir2", "/dir3", |from, to| {
+        moved.push((from.to_path_buf(), to.to_path_buf()))
+    })?;
+    // The first entry is always the renamed path, but the order of the
+    // descendants depends on the directory iteration order.
+    let first = moved.remove(0);
+    moved.sort();
+    assert_eq!(
+        (first, moved),
+        (
+            ("dir2".into(), "/dir3".into()),
+            vec![
+                ("dir2/file".into(), "/dir3/file".into()),
+                ("dir2/link".into(), "/dir3/link".into()),
+                ("dir2/sub".into(), "/dir3/sub".into()),
+                ("dir2/sub/file".into(), "/dir3/sub/file".into()),
+            ]
+        ),
+        "rename_with_callback_deep should report every descendant"
+    );
+    assert!(
+        path("root/dir3/sub/file").exists(),
+        "rename_with_callback_deep should rename the directory"
+    );
+
+    let mut called = false;
+    assert_eq!(
+        root.rename_with_callback_deep("nonexistent", "dir4", |_, _| called = true)
+            .map_err(|err| err.kind()),
+        Err(ErrorKind::OsError(Some(libc::ENOENT))),
+        "rename_with_callback_deep of a non-existent path should fail"
+    );
+    assert!(
+        !called,
+        "on_moved should not be called if the rename failed"
+    );
+
+    Ok(())
+}
+
+// Make sure that RootWithContext::run prepends the context to errors (and
+// only errors), and that Root::resolve_map_err converts errors.
+#[test]
+fn root_error_context() -> Result<(), Error> {
+    let dir = tests_common::create_basic_tree()?;
+    let root = Root::open(&dir)?;
+    let ctx_root = root.with_error_context("while loading config");
+
+    assert_eq!(
+        ctx_root
+            .run(|root| root.resolve("b/c/file"))?
+            .as_unsafe_path_unchecked()?,
+        dir.path().join("b/c/file"),
+        "resolve with error context should still work"
+    );
+
+    let err = ctx_root
+        .run(|root| root.resolve("nonexistent/config.toml"))
+        .expect_err("resolve of non-existent path should fail");
+    assert_eq!(
+        err.kind(),
+        ErrorKind::OsError(Some(libc::ENOENT)),
+        "error context should not change the error kind"
+    );
+    let err = err.to_string();
+    assert!(
+        err.starts_with("while loading config: "),
+        "resolve error should have the context prepended: {err}"
+    );
+    let plain_err = root
+        .resolve("nonexistent/config.toml")
+        .expect_err("resolve of non-existent path should fail");
+    assert_eq!(
+        err,
+        format!("while loading config: {:#}", anyhow::Error::from(plain_err)),
+        "resolve error should be the context followed by the pathrs error"
+    );
+
+    let err = ctx_root
+        .run(|root| root.mkdir_all("b/c/file/foo", &Permissions::from_mode(0o755)))
+        .expect_err("mkdir_all through a file should fail");
+    let err = format!("{:#}", anyhow::Error::from(err));
+    assert!(
+        err.starts_with("while loading config: "),
+        "mkdir_all error should have the context prepended: {err}"
+    );
+    assert_eq!(
+        err.matches("while loading config").count(),
+        1,
+        "error context should only be prepended once: {err}"
+    );
+
+    #[derive(Debug, PartialEq)]
+    struct MyError(ErrorKind);
+    assert_eq!(
+        root.resolve_map_err("nonexistent", |err| MyError(err.kind()))
+            .map(|_| ()),
+        Err(MyError(ErrorKind::OsError(Some(libc::ENOENT)))),
+        "resolve_map_err should convert the error"
+    );
+    assert!(
+        root.resolve_map_err("b/c/file", |err| MyError(err.kind()))
+            .is_ok(),
+        "resolve_map_err should return the handle on success"
+    );
+
+    Ok(())
+}
+
 mod utils {
     use crate::{
         error::{ErrorExt, ErrorKind},
@@ -731,6 +2882,10 @@ mod utils {
         // was unlinked.
         let handle = root.resolve_nofollow(path); // do not unwrap
 
+        // If the path is a symlink, also get a handle to the target so we can
+        // make sure we didn't touch it.
+        let target_handle = root.resolve(path); // do not unwrap
+
         let res = remove_fn(&root, path);
         tests_common::check_err(&res, &expected_result)
             .with_context(|| format!("root remove {path:?}"))?;
@@ -741,6 +2896,17 @@ mod utils {
             let meta = handle.as_fd().metadata()?;
             assert_eq!(meta.nlink(), 0, "deleted file should have a 0 nlink");
 
+            if meta.is_symlink() {
+                if let Ok(target_handle) = target_handle {
+                    let target_meta = target_handle.as_fd().metadata()?;
+                    assert_ne!(
+                        target_meta.nlink(),
+                        0,
+                        "target of deleted symlink {path:?} should not be deleted"
+                    );
+                }
+            }
+
             let root = root_roundtrip(root)?;
             let new_lookup = root.resolve_nofollow(path);
             assert_eq!(
@@ -752,6 +2918,28 @@ mod utils {
         Ok(())
     }
 
+    pub(super) fn check_root_readlink<R: RootImpl, P: AsRef<Path>>(
+        root: R,
+        path: P,
+        expected_result: Result<&str, ErrorKind>,
+    ) -> Result<(), Error> {
+        let path = path.as_ref();
+
+        let res = root.readlink(path);
+        tests_common::check_err(&res, &expected_result)
+            .with_context(|| format!("root readlink {path:?}"))?;
+
+        if let Ok(expected_target) = expected_result {
+            assert_eq!(
+                res.unwrap(),
+                PathBuf::from(expected_target),
+                "readlink({path:?}) returned unexpected target",
+            );
+        }
+
+        Ok(())
+    }
+
     pub(super) fn check_root_remove_dir<R: RootImpl, P: AsRef<Path>>(
         root: R,
         path: P,
@@ -890,7 +3078,9 @@ mod utils {
 
         // Before trying to create the directory tree, figure out what
         // components don't exist yet so we can check them later.
-        let before_partial_lookup = root.resolver().resolve_partial(root, unsafe_path, false)?;
+        let before_partial_lookup =
+            root.resolver()
+                .resolve_partial(root, None, unsafe_path, false)?;
 
         let expected_subdir_state: Option<((_, _), _)> = match expected_result {
             Err(_) => None,
@@ -918,9 +3108,7 @@ mod utils {
         tests_common::check_err(&res, &expected_result)?;
 
         if let PartialLookup::Partial {
-            handle,
-            remaining,
-            last_error: _,
+            handle, remaining, ..
         } = before_partial_lookup
         {
             let mut subpaths = remaining
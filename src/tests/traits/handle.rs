@@ -29,6 +29,7 @@ pub(in crate::tests) trait HandleImpl: AsFd + std::fmt::Debug + Sized {
     type Error: ErrorImpl;
 
     // NOTE: We return Self::Cloned so that we can share types with HandleRef.
+    #[allow(dead_code)]
     fn from_fd<Fd: Into<OwnedFd>>(fd: Fd) -> Self::Cloned;
 
     fn try_clone(&self) -> Result<Self::Cloned, anyhow::Error>;
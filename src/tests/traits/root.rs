@@ -94,6 +94,13 @@ impl RootImpl for Root {
         Resolver {
             backend: self.resolver_backend(),
             flags: self.resolver_flags(),
+            eager_reopen: self.eager_reopen(),
+            max_symlink_traversals: self.max_symlink_traversals(),
+            max_symlink_target_bytes: self.max_symlink_target_bytes(),
+            setid_policy: self.setid_policy(),
+            reject_deleted_intermediates: self.reject_deleted_intermediates(),
+            trailing_symlink_policy: self.trailing_symlink_policy(),
+            observer: self.resolve_observer(),
         }
     }
 
@@ -101,6 +108,13 @@ impl RootImpl for Root {
         Self::Cloned::from_fd(fd)
             .with_resolver_backend(resolver.backend)
             .with_resolver_flags(resolver.flags)
+            .with_eager_reopen(resolver.eager_reopen)
+            .with_max_symlink_traversals(resolver.max_symlink_traversals)
+            .with_max_symlink_target_bytes(resolver.max_symlink_target_bytes)
+            .with_setid_policy(resolver.setid_policy)
+            .with_reject_deleted_intermediates(resolver.reject_deleted_intermediates)
+            .with_trailing_symlink_policy(resolver.trailing_symlink_policy)
+            .with_resolve_observer(resolver.observer)
     }
 
     fn try_clone(&self) -> Result<Self::Cloned, anyhow::Error> {
@@ -179,6 +193,13 @@ impl RootImpl for &Root {
         Resolver {
             backend: self.resolver_backend(),
             flags: self.resolver_flags(),
+            eager_reopen: self.eager_reopen(),
+            max_symlink_traversals: self.max_symlink_traversals(),
+            max_symlink_target_bytes: self.max_symlink_target_bytes(),
+            setid_policy: self.setid_policy(),
+            reject_deleted_intermediates: self.reject_deleted_intermediates(),
+            trailing_symlink_policy: self.trailing_symlink_policy(),
+            observer: self.resolve_observer(),
         }
     }
 
@@ -186,6 +207,13 @@ impl RootImpl for &Root {
         Self::Cloned::from_fd(fd)
             .with_resolver_backend(resolver.backend)
             .with_resolver_flags(resolver.flags)
+            .with_eager_reopen(resolver.eager_reopen)
+            .with_max_symlink_traversals(resolver.max_symlink_traversals)
+            .with_max_symlink_target_bytes(resolver.max_symlink_target_bytes)
+            .with_setid_policy(resolver.setid_policy)
+            .with_reject_deleted_intermediates(resolver.reject_deleted_intermediates)
+            .with_trailing_symlink_policy(resolver.trailing_symlink_policy)
+            .with_resolve_observer(resolver.observer)
     }
 
     fn try_clone(&self) -> Result<Self::Cloned, anyhow::Error> {
@@ -264,6 +292,13 @@ impl RootImpl for RootRef<'_> {
         Resolver {
             backend: self.resolver_backend(),
             flags: self.resolver_flags(),
+            eager_reopen: self.eager_reopen(),
+            max_symlink_traversals: self.max_symlink_traversals(),
+            max_symlink_target_bytes: self.max_symlink_target_bytes(),
+            setid_policy: self.setid_policy(),
+            reject_deleted_intermediates: self.reject_deleted_intermediates(),
+            trailing_symlink_policy: self.trailing_symlink_policy(),
+            observer: self.resolve_observer(),
         }
     }
 
@@ -271,6 +306,13 @@ impl RootImpl for RootRef<'_> {
         Self::Cloned::from_fd(fd)
             .with_resolver_backend(resolver.backend)
             .with_resolver_flags(resolver.flags)
+            .with_eager_reopen(resolver.eager_reopen)
+            .with_max_symlink_traversals(resolver.max_symlink_traversals)
+            .with_max_symlink_target_bytes(resolver.max_symlink_target_bytes)
+            .with_setid_policy(resolver.setid_policy)
+            .with_reject_deleted_intermediates(resolver.reject_deleted_intermediates)
+            .with_trailing_symlink_policy(resolver.trailing_symlink_policy)
+            .with_resolve_observer(resolver.observer)
     }
 
     fn try_clone(&self) -> Result<Self::Cloned, anyhow::Error> {
@@ -349,6 +391,13 @@ impl RootImpl for &RootRef<'_> {
         Resolver {
             backend: self.resolver_backend(),
             flags: self.resolver_flags(),
+            eager_reopen: self.eager_reopen(),
+            max_symlink_traversals: self.max_symlink_traversals(),
+            max_symlink_target_bytes: self.max_symlink_target_bytes(),
+            setid_policy: self.setid_policy(),
+            reject_deleted_intermediates: self.reject_deleted_intermediates(),
+            trailing_symlink_policy: self.trailing_symlink_policy(),
+            observer: self.resolve_observer(),
         }
     }
 
@@ -356,6 +405,13 @@ impl RootImpl for &RootRef<'_> {
         Self::Cloned::from_fd(fd)
             .with_resolver_backend(resolver.backend)
             .with_resolver_flags(resolver.flags)
+            .with_eager_reopen(resolver.eager_reopen)
+            .with_max_symlink_traversals(resolver.max_symlink_traversals)
+            .with_max_symlink_target_bytes(resolver.max_symlink_target_bytes)
+            .with_setid_policy(resolver.setid_policy)
+            .with_reject_deleted_intermediates(resolver.reject_deleted_intermediates)
+            .with_trailing_symlink_policy(resolver.trailing_symlink_policy)
+            .with_resolve_observer(resolver.observer)
     }
 
     fn try_clone(&self) -> Result<Self::Cloned, anyhow::Error> {
@@ -28,5 +28,16 @@ pub(crate) use path::*;
 mod fd;
 pub(crate) use fd::*;
 
+mod glob;
+pub(crate) use glob::*;
+
+mod mountinfo;
+pub(crate) use mountinfo::*;
+
 mod sysctl;
 pub(crate) use sysctl::*;
+
+#[cfg(feature = "tokio")]
+mod blocking;
+#[cfg(feature = "tokio")]
+pub(crate) use blocking::*;
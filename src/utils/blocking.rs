@@ -0,0 +1,46 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+#![forbid(unsafe_code)]
+
+use crate::error::{Error, ErrorImpl};
+
+use std::{
+    io::{Error as IOError, ErrorKind as IOErrorKind},
+    panic,
+};
+
+/// Run `func` on tokio's blocking thread pool and wait for it to complete.
+///
+/// If `func` panics, the panic is propagated to the caller.
+pub(crate) async fn spawn_blocking<F, T>(func: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(func).await {
+        Ok(res) => res,
+        Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+        // The task can only be cancelled if the runtime is shutting down.
+        Err(err) => Err(ErrorImpl::OsError {
+            operation: "run blocking libpathrs operation".into(),
+            source: IOError::new(IOErrorKind::Other, err),
+        })?,
+    }
+}
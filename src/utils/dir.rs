@@ -18,18 +18,28 @@
  */
 
 use crate::{
-    error::{Error, ErrorExt, ErrorImpl},
+    error::{Error, ErrorExt, ErrorImpl, ErrorKind},
     flags::OpenFlags,
+    procfs::{ProcfsBase, GLOBAL_PROCFS_HANDLE},
     syscalls,
+    utils::FdExt,
 };
 
 use std::{
     ffi::OsStr,
-    os::unix::{ffi::OsStrExt, io::AsFd},
-    path::Path,
+    fs::File,
+    io::Error as IOError,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::MetadataExt,
+        io::{AsFd, AsRawFd, OwnedFd},
+    },
+    path::{Path, PathBuf},
 };
 
 use rustix::fs::{AtFlags, Dir};
+#[cfg(feature = "tree-digest")]
+use sha2::{Digest, Sha256};
 
 fn remove_inode<Fd: AsFd>(dirfd: Fd, name: &Path) -> Result<(), Error> {
     let dirfd = dirfd.as_fd();
@@ -55,6 +65,72 @@ fn remove_inode<Fd: AsFd>(dirfd: Fd, name: &Path) -> Result<(), Error> {
         })
 }
 
+/// The maximum depth of nested directories that the tree walks in this module
+/// (such as [`remove_all`]) will descend into. We keep a file descriptor open
+/// for every level, so we need a limit to avoid hitting `EMFILE` part-way
+/// through operating on a very deep tree.
+const TREE_WALK_MAX_DEPTH: usize = 512;
+
+/// Return an error if a tree walk (described by `operation`) which already has
+/// `depth` directories open cannot descend any further.
+fn check_tree_depth(operation: &'static str, depth: usize) -> Result<(), Error> {
+    if depth >= TREE_WALK_MAX_DEPTH {
+        Err(ErrorImpl::OsError {
+            operation: operation.into(),
+            source: IOError::from_raw_os_error(libc::ELOOP),
+        })
+        .with_wrap(|| format!("directory tree is deeper than {TREE_WALK_MAX_DEPTH} levels"))?
+    }
+    Ok(())
+}
+
+/// Open the directory `name` inside `dirfd` for scanning, without following
+/// symlinks.
+fn open_subdir<Fd: AsFd>(dirfd: Fd, name: &Path) -> Result<OwnedFd, Error> {
+    syscalls::openat(
+        dirfd,
+        name,
+        OpenFlags::O_DIRECTORY | OpenFlags::O_NOFOLLOW,
+        0,
+    )
+    .map_err(|err| {
+        ErrorImpl::RawOsError {
+            operation: "open directory to scan entries".into(),
+            source: err,
+        }
+        .into()
+    })
+}
+
+/// Get the names of all of the entries (other than `.` and `..`) in `dir`.
+fn read_dir_names<Fd: AsFd>(dir: Fd) -> Result<Vec<PathBuf>, Error> {
+    // TODO: Dir creates a new file descriptor rather than re-using the one we
+    //       have, and RawDir can't be used as an Iterator yet (rustix needs GAT
+    //       to make that work). But this is okay for now...
+    Dir::read_from(dir)
+        .map_err(|err| ErrorImpl::OsError {
+            operation: "create directory iterator".into(),
+            source: err.into(),
+        })?
+        .filter(|res| {
+            !matches!(
+                res.as_ref().map(|dentry| dentry.file_name().to_bytes()),
+                Ok(b".") | Ok(b"..")
+            )
+        })
+        .map(|res| {
+            res.map(|dentry| OsStr::from_bytes(dentry.file_name().to_bytes()).into())
+                .map_err(|err| {
+                    ErrorImpl::OsError {
+                        operation: "scan directory".into(),
+                        source: err.into(),
+                    }
+                    .into()
+                })
+        })
+        .collect()
+}
+
 pub(crate) fn remove_all<Fd: AsFd>(dirfd: Fd, name: &Path) -> Result<(), Error> {
     let dirfd = dirfd.as_fd();
 
@@ -63,77 +139,390 @@ pub(crate) fn remove_all<Fd: AsFd>(dirfd: Fd, name: &Path) -> Result<(), Error>
             description: "remove_all reached a component containing '/'".into(),
         })?;
     }
+    // Opening ".." would let us delete the contents of the parent directory.
+    if matches!(name.as_os_str().as_bytes(), b"." | b"..") {
+        Err(ErrorImpl::InvalidArgument {
+            name: "path".into(),
+            description: "remove_all path cannot end with '.' or '..'".into(),
+        })?;
+    }
 
     // Fast path -- try to remove it with unlink/rmdir.
     if remove_inode(dirfd, name).is_ok() {
         return Ok(());
     }
 
-    // Try to delete all children. We need to re-do the iteration until there
-    // are no components left because deleting entries while iterating over a
-    // directory can lead to the iterator skipping components. An attacker could
-    // try to make this loop forever by consistently creating inodes, but
-    // there's not much we can do about it and I suspect they would eventually
-    // lose the race.
-    let subdir = syscalls::openat(dirfd, name, OpenFlags::O_DIRECTORY, 0).map_err(|err| {
+    // Walk the tree depth-first, using an explicit stack of directory file
+    // descriptors (each paired with the name of that directory in its parent).
+    // We only ever operate on the entries of the directory at the top of the
+    // stack, so paths are never re-resolved and an attacker swapping a
+    // directory for a symlink can't redirect us outside the tree (symlinks are
+    // never followed, they just get unlinked).
+    let mut stack = vec![(
+        open_subdir(dirfd, name).with_wrap(|| format!("scan directory {name:?} for deletion"))?,
+        name.to_path_buf(),
+    )];
+    while let Some((subdir, subname)) = stack.last() {
+        // Try to delete all children. We need to re-do the iteration until
+        // there are no components left because deleting entries while
+        // iterating over a directory can lead to the iterator skipping
+        // components. An attacker could try to make this loop forever by
+        // consistently creating inodes, but there's not much we can do about
+        // it and I suspect they would eventually lose the race.
+        let children = read_dir_names(subdir)
+            .with_wrap(|| format!("scan directory {subname:?} for deletion"))?;
+
+        // We can stop iterating when a fresh directory scan is empty.
+        if children.is_empty() {
+            // We have deleted all of the children of the directory, let's try
+            // to delete the inode again (it should be empty now -- an attacker
+            // could add things but we can just error out in that case, and if
+            // they swapped it to a file then remove_inode will take care of
+            // that).
+            let (_, subname) = stack.pop().expect("stack must be non-empty");
+            let parent = stack.last().map(|(fd, _)| fd.as_fd()).unwrap_or(dirfd);
+            remove_inode(parent, &subname)
+                .with_wrap(|| format!("deleting emptied directory {subname:?}"))?;
+            continue;
+        }
+
+        // Delete any children we can, and descend into the first one we can't
+        // (it is presumably a non-empty directory).
+        let mut descend = None;
+        for child in children {
+            if let Err(err) = remove_inode(subdir, &child) {
+                descend = Some((child, err));
+                break;
+            }
+        }
+        if let Some((child, remove_err)) = descend {
+            check_tree_depth("remove_all", stack.len())?;
+            let child_dir = match open_subdir(subdir, &child) {
+                Ok(fd) => fd,
+                // If the child is not a directory then the original error
+                // from remove_inode is far more useful.
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        ErrorKind::OsError(Some(libc::ENOTDIR | libc::ELOOP))
+                    ) =>
+                {
+                    Err(remove_err).with_wrap(|| format!("deleting {child:?}"))?
+                }
+                Err(err) => {
+                    Err(err).with_wrap(|| format!("scan directory {child:?} for deletion"))?
+                }
+            };
+            stack.push((child_dir, child));
+        }
+    }
+    Ok(())
+}
+
+/// Set the mode of the inode referenced by `fd` (which may be an `O_PATH`
+/// descriptor) through its `/proc/thread-self/fd/$n` magic-link.
+fn chmod_fd<Fd: AsFd>(fd_dir: &File, fd: Fd, mode: u32) -> Result<(), Error> {
+    syscalls::fchmodat(fd_dir, fd.as_fd().as_raw_fd().to_string(), mode).map_err(|err| {
         ErrorImpl::RawOsError {
-            operation: "open directory to scan entries".into(),
+            operation: "chmod inode".into(),
             source: err,
         }
-    })?;
-    loop {
-        // TODO: Dir creates a new file descriptor rather than re-using the one
-        //       we have, and RawDir can't be used as an Iterator yet (rustix
-        //       needs GAT to make that work). But this is okay for now...
-        let mut iter = Dir::read_from(&subdir)
-            // TODO: We probably want to just break out of the loop here, rather
-            //       than return an error? We can at least try to do
-            //       remove_inode() again in case the directory got swapped with
-            //       a non-directory.
-            .map_err(|err| ErrorImpl::OsError {
-                operation: "create directory iterator".into(),
-                source: err.into(),
-            })
-            .with_wrap(|| format!("scan directory {name:?} for deletion"))?
-            .filter(|res| {
-                !matches!(
-                    res.as_ref().map(|dentry| dentry.file_name().to_bytes()),
-                    Ok(b".") | Ok(b"..")
-                )
-            })
-            .peekable();
+        .into()
+    })
+}
 
-        // We can stop iterating when a fresh directory iterator is empty.
-        if iter.peek().is_none() {
-            break;
+/// Open the entry `name` inside `dirfd` as an `O_PATH` descriptor, without
+/// following symlinks.
+fn open_entry<Fd: AsFd>(dirfd: Fd, name: &Path) -> Result<OwnedFd, Error> {
+    syscalls::openat(dirfd, name, OpenFlags::O_PATH | OpenFlags::O_NOFOLLOW, 0).map_err(|err| {
+        ErrorImpl::RawOsError {
+            operation: "open directory entry".into(),
+            source: err,
+        }
+        .into()
+    })
+}
+
+fn chmod_tree(fd_dir: &File, fd: OwnedFd, dir_mode: u32, file_mode: u32) -> Result<(), Error> {
+    // Open a directory for reading and get the names of its entries. We do
+    // this before changing its mode, so that we can still scan it even if
+    // dir_mode doesn't permit it. The children are updated before the
+    // directory itself for the same reason.
+    let scan_dir = |fd: &OwnedFd| -> Result<(OwnedFd, Vec<PathBuf>), Error> {
+        let dir = fd
+            .reopen(&GLOBAL_PROCFS_HANDLE, OpenFlags::O_DIRECTORY)
+            .wrap("open directory to scan entries")?;
+        let names = read_dir_names(&dir)?;
+        Ok((dir, names))
+    };
+
+    match fd.metadata()?.mode() & libc::S_IFMT {
+        libc::S_IFREG => return chmod_fd(fd_dir, fd, file_mode),
+        libc::S_IFDIR => (),
+        // Symlinks (and other special inodes) are left alone. In particular,
+        // we never follow a symlink since it might point outside the tree.
+        _ => return Ok(()),
+    }
+
+    // Walk the tree depth-first with an explicit stack (like remove_all), so
+    // that a very deep tree can't overflow our stack. Each entry holds the
+    // O_PATH handle of a directory (which gets its mode changed once all of
+    // its children are done), a readable handle for the directory, and the
+    // names of the children we have yet to handle.
+    let (dir, names) = scan_dir(&fd)?;
+    let mut stack = vec![(fd, dir, names)];
+    while let Some((_, dir, names)) = stack.last_mut() {
+        let name = match names.pop() {
+            Some(name) => name,
+            None => {
+                let (fd, _, _) = stack.pop().expect("stack must be non-empty");
+                chmod_fd(fd_dir, fd, dir_mode)?;
+                continue;
+            }
+        };
+        let child = open_entry(&*dir, &name)?;
+        match child
+            .metadata()
+            .with_wrap(|| format!("chmod directory entry {name:?}"))?
+            .mode()
+            & libc::S_IFMT
+        {
+            libc::S_IFREG => chmod_fd(fd_dir, child, file_mode)
+                .with_wrap(|| format!("chmod directory entry {name:?}"))?,
+            libc::S_IFDIR => {
+                check_tree_depth("chmod_all", stack.len())?;
+                let (dir, names) =
+                    scan_dir(&child).with_wrap(|| format!("chmod directory entry {name:?}"))?;
+                stack.push((child, dir, names));
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Recursively set the mode of every directory (to `dir_mode`) and regular file
+/// (to `file_mode`) in the tree rooted at `fd`. Symlinks and other inodes are
+/// not modified.
+pub(crate) fn chmod_all<Fd: Into<OwnedFd>>(
+    fd: Fd,
+    dir_mode: u32,
+    file_mode: u32,
+) -> Result<(), Error> {
+    // O_PATH descriptors cannot be used with fchmod(2), so we have to go
+    // through procfs instead.
+    let fd_dir = GLOBAL_PROCFS_HANDLE
+        .open(
+            ProcfsBase::ProcThreadSelf,
+            "fd",
+            OpenFlags::O_PATH | OpenFlags::O_DIRECTORY,
+        )
+        .wrap("open procfs fd directory")?;
+    chmod_tree(&fd_dir, fd.into(), dir_mode, file_mode)
+}
+
+fn list_tree_into<Fd: AsFd>(fd: Fd, prefix: &Path, out: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let dir = fd
+        .reopen(&GLOBAL_PROCFS_HANDLE, OpenFlags::O_DIRECTORY)
+        .wrap("open directory to scan entries")?;
+    // TODO: Dir creates a new file descriptor rather than re-using the one we
+    //       have, but this is okay for now...
+    let iter = Dir::read_from(&dir).map_err(|err| ErrorImpl::OsError {
+        operation: "create directory iterator".into(),
+        source: err.into(),
+    })?;
+    for child in iter {
+        let child = child.map_err(|err| ErrorImpl::OsError {
+            operation: "scan directory".into(),
+            source: err.into(),
+        })?;
+        let name = child.file_name().to_bytes();
+        if matches!(name, b"." | b"..") {
+            continue;
+        }
+        let name: &Path = OsStr::from_bytes(name).as_ref();
+        let path = prefix.join(name);
+        let child = syscalls::openat(&dir, name, OpenFlags::O_PATH | OpenFlags::O_NOFOLLOW, 0)
+            .map_err(|err| ErrorImpl::RawOsError {
+                operation: "open directory entry".into(),
+                source: err,
+            })?;
+        out.push(path.clone());
+        // Only recurse into real directories -- symlinks are never followed.
+        if child.metadata()?.mode() & libc::S_IFMT == libc::S_IFDIR {
+            list_tree_into(child, &path, out).with_wrap(|| format!("scan directory {path:?}"))?;
         }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "tree-digest")]
+/// Prefix for [`tree_digest`], so that the encoding can be changed in the
+/// future without new digests colliding with old ones.
+const TREE_DIGEST_TAG: &[u8] = b"libpathrs tree digest v1\0";
 
-        // Recurse into all of the children and try to delete them.
-        for child in iter {
-            // TODO: We probably want to break out of the scan loop here if this
-            //       is an error as well.
-            let child = child.map_err(|err| ErrorImpl::OsError {
-                operation: format!("scan directory {name:?}").into(),
-                source: err.into(),
+#[cfg(feature = "tree-digest")]
+/// Start computing the digest of the inode referenced by `fd`. For everything
+/// other than directories, the returned hasher already covers the whole inode.
+/// For directories, the caller still needs to add the entries (with
+/// [`tree_digest_add_entry`]) to the hasher, and so a readable handle to the
+/// directory is returned as well.
+fn tree_digest_begin<Fd: AsFd>(
+    fd: Fd,
+    include_content: bool,
+) -> Result<(Sha256, Option<OwnedFd>), Error> {
+    let fd = fd.as_fd();
+    let meta = fd.metadata().wrap("fstat tree entry")?;
+
+    // Every entry starts with its type and permission bits (including the
+    // setuid, setgid and sticky bits).
+    let mut hasher = Sha256::new();
+    hasher.update((meta.mode() & (libc::S_IFMT | 0o7777)).to_le_bytes());
+    match meta.mode() & libc::S_IFMT {
+        libc::S_IFREG => {
+            hasher.update(meta.size().to_le_bytes());
+            if include_content {
+                // The handle refers to the inode we checked above, so this
+                // cannot be swapped for a fifo or device behind our back.
+                let mut file: File = fd
+                    .reopen(&GLOBAL_PROCFS_HANDLE, OpenFlags::O_RDONLY)
+                    .wrap("open regular file to hash contents")?
+                    .into();
+                let mut content = Sha256::new();
+                std::io::copy(&mut file, &mut content).map_err(|err| ErrorImpl::OsError {
+                    operation: "read regular file contents".into(),
+                    source: err,
+                })?;
+                hasher.update(content.finalize());
+            }
+        }
+        // Symlinks are never followed, only their contents are hashed.
+        libc::S_IFLNK => {
+            let target = syscalls::readlinkat(fd, "").map_err(|err| ErrorImpl::RawOsError {
+                operation: "readlink tree entry".into(),
+                source: err,
             })?;
-            let name: &Path = OsStr::from_bytes(child.file_name().to_bytes()).as_ref();
-            remove_all(&subdir, name)?
+            let target = target.as_os_str().as_bytes();
+            hasher.update((target.len() as u64).to_le_bytes());
+            hasher.update(target);
         }
+        libc::S_IFDIR => {
+            let dir = fd
+                .reopen(&GLOBAL_PROCFS_HANDLE, OpenFlags::O_DIRECTORY)
+                .wrap("open directory to scan entries")?;
+            return Ok((hasher, Some(dir)));
+        }
+        libc::S_IFCHR | libc::S_IFBLK => hasher.update(meta.rdev().to_le_bytes()),
+        _ => (),
     }
+    Ok((hasher, None))
+}
+
+#[cfg(feature = "tree-digest")]
+/// Add the directory entry `name` (with the given digest) to the digest of
+/// its parent directory.
+fn tree_digest_add_entry(hasher: &mut Sha256, name: &Path, digest: [u8; 32]) {
+    let name = name.as_os_str().as_bytes();
+    hasher.update((name.len() as u64).to_le_bytes());
+    hasher.update(name);
+    hasher.update(digest);
+}
 
-    // We have deleted all of the children of the directory, let's try to delete
-    // the inode again (it should be empty now -- an attacker could add things
-    // but we can just error out in that case, and if they swapped it to a file
-    // then remove_inode will take care of that).
-    remove_inode(dirfd, name).with_wrap(|| format!("deleting emptied directory {name:?}"))
+#[cfg(feature = "tree-digest")]
+/// Get the names of the entries in `dir`, in the order they should be added to
+/// the digest of `dir`.
+fn tree_digest_names(dir: &OwnedFd) -> Result<Vec<PathBuf>, Error> {
+    // The order of getdents(2) is not stable, so sort the entries to make the
+    // digest deterministic. They are sorted in reverse because we pop them off
+    // the end of the list.
+    let mut names = read_dir_names(dir)?;
+    names.sort_by(|a, b| b.as_os_str().as_bytes().cmp(a.as_os_str().as_bytes()));
+    Ok(names)
+}
+
+#[cfg(feature = "tree-digest")]
+fn tree_digest_entry<Fd: AsFd>(fd: Fd, include_content: bool) -> Result<[u8; 32], Error> {
+    let (hasher, dir) = tree_digest_begin(fd, include_content)?;
+    let dir = match dir {
+        Some(dir) => dir,
+        None => return Ok(hasher.finalize().into()),
+    };
+
+    // Walk the tree depth-first with an explicit stack (like remove_all), so
+    // that a very deep tree can't overflow our stack. Each entry holds the
+    // name of a directory in its parent, the (partial) digest of the
+    // directory, a readable handle for the directory, and the names of the
+    // children we have yet to add to the digest.
+    let names = tree_digest_names(&dir)?;
+    let mut stack = vec![(PathBuf::new(), hasher, dir, names)];
+    while let Some((_, hasher, dir, names)) = stack.last_mut() {
+        let name = match names.pop() {
+            Some(name) => name,
+            None => {
+                // All of the children have been added, so the directory's
+                // digest is complete and can be added to its parent.
+                let (name, hasher, _, _) = stack.pop().expect("stack must be non-empty");
+                let digest = hasher.finalize().into();
+                match stack.last_mut() {
+                    Some((_, parent, _, _)) => tree_digest_add_entry(parent, &name, digest),
+                    None => return Ok(digest),
+                }
+                continue;
+            }
+        };
+        let child = open_entry(&*dir, &name)?;
+        match tree_digest_begin(child, include_content)
+            .with_wrap(|| format!("digest directory entry {name:?}"))?
+        {
+            (child_hasher, None) => {
+                tree_digest_add_entry(hasher, &name, child_hasher.finalize().into())
+            }
+            (child_hasher, Some(child_dir)) => {
+                check_tree_depth("tree_digest", stack.len())?;
+                let child_names = tree_digest_names(&child_dir)
+                    .with_wrap(|| format!("digest directory entry {name:?}"))?;
+                stack.push((name, child_hasher, child_dir, child_names));
+            }
+        }
+    }
+    unreachable!("tree walk must return once the stack is empty")
+}
+
+#[cfg(feature = "tree-digest")]
+/// Compute a Merkle-style SHA-256 digest of the tree rooted at `fd`. The
+/// digest of each inode covers its type, mode and (depending on the type) its
+/// size and contents, symlink target, device number, or the names and
+/// digests of its directory entries (in sorted order). Symlinks are never
+/// followed.
+pub(crate) fn tree_digest<Fd: AsFd>(fd: Fd, include_content: bool) -> Result<[u8; 32], Error> {
+    let digest = tree_digest_entry(fd, include_content)?;
+    let mut hasher = Sha256::new();
+    hasher.update(TREE_DIGEST_TAG);
+    hasher.update(digest);
+    Ok(hasher.finalize().into())
+}
+
+/// List every inode in the tree rooted at the directory `fd` (not including
+/// `fd` itself), as paths relative to `fd`. Parent directories are always
+/// listed before their children, and symlinks are not followed.
+pub(crate) fn list_tree<Fd: AsFd>(fd: Fd) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = Vec::new();
+    list_tree_into(fd, Path::new(""), &mut paths)?;
+    Ok(paths)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::remove_all;
+    #[cfg(feature = "tree-digest")]
+    use super::tree_digest;
+    use super::{chmod_all, remove_all, TREE_WALK_MAX_DEPTH};
     use crate::{error::ErrorKind, tests::common as tests_common, Root};
 
-    use std::{os::unix::io::OwnedFd, path::Path};
+    use std::{
+        fs::File,
+        os::unix::{fs::PermissionsExt, io::OwnedFd},
+        path::Path,
+    };
 
     use anyhow::Error;
     use pretty_assertions::assert_eq;
@@ -187,4 +576,136 @@ mod tests {
         let _dir = dir; // make sure the tempdir is not dropped early
         Ok(())
     }
+
+    #[test]
+    fn remove_all_dotdot() -> Result<(), Error> {
+        let dir = tests_common::create_basic_tree()?;
+        let dirfd = File::open(dir.path().join("b/c"))?;
+
+        for name in [".", ".."] {
+            assert_eq!(
+                remove_all(&dirfd, Path::new(name)).map_err(|err| err.kind()),
+                Err(ErrorKind::InvalidArgument),
+                "removeall(b/c, {name:?}) should fail",
+            );
+        }
+        assert!(
+            dir.path().join("b/c/file").exists() && dir.path().join("a").exists(),
+            "failed removeall should not remove anything"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_all_symlinks() -> Result<(), Error> {
+        // Layout:
+        //   target/file
+        //   dir/sub/link -> ../../target
+        //   dir/link -> /target
+        let dir = tempfile::TempDir::new()?;
+        let path = |subpath: &str| dir.path().join(subpath);
+        std::fs::create_dir_all(path("target"))?;
+        std::fs::create_dir_all(path("dir/sub"))?;
+        std::fs::write(path("target/file"), b"")?;
+        std::os::unix::fs::symlink("../../target", path("dir/sub/link"))?;
+        std::os::unix::fs::symlink(path("target"), path("dir/link"))?;
+        let dirfd = File::open(&dir)?;
+
+        remove_all(&dirfd, Path::new("dir"))?;
+        assert!(!path("dir").exists(), "removeall should remove the tree");
+        assert!(
+            path("target/file").exists(),
+            "removeall should not follow symlinks"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_all_deep() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+        let dirfd = File::open(&dir)?;
+
+        let mkdeep = |depth| {
+            let subpath = ["d"; TREE_WALK_MAX_DEPTH + 2][..depth].join("/");
+            std::fs::create_dir_all(dir.path().join(subpath))
+        };
+
+        // The deepest (empty) directory doesn't need to be opened.
+        mkdeep(TREE_WALK_MAX_DEPTH + 1)?;
+        remove_all(&dirfd, Path::new("d"))?;
+        assert!(
+            !dir.path().join("d").exists(),
+            "removeall of a tree with {TREE_WALK_MAX_DEPTH} non-empty levels should work"
+        );
+
+        mkdeep(TREE_WALK_MAX_DEPTH + 2)?;
+        assert_eq!(
+            remove_all(&dirfd, Path::new("d")).map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::ELOOP))),
+            "removeall of a tree deeper than {TREE_WALK_MAX_DEPTH} levels should fail",
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn chmod_all_deep() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+
+        let mkdeep = |depth| {
+            let subpath = ["d"; TREE_WALK_MAX_DEPTH + 1][..depth].join("/");
+            std::fs::create_dir_all(dir.path().join(subpath))
+        };
+        let open_top =
+            || -> Result<OwnedFd, Error> { Ok(File::open(dir.path().join("d"))?.into()) };
+
+        mkdeep(TREE_WALK_MAX_DEPTH)?;
+        chmod_all(open_top()?, 0o711, 0o600)?;
+        let deepest = dir.path().join(["d"; TREE_WALK_MAX_DEPTH].join("/"));
+        assert_eq!(
+            std::fs::metadata(deepest)?.permissions().mode() & 0o7777,
+            0o711,
+            "chmod_all of a tree with {TREE_WALK_MAX_DEPTH} levels should reach the deepest directory"
+        );
+
+        mkdeep(TREE_WALK_MAX_DEPTH + 1)?;
+        assert_eq!(
+            chmod_all(open_top()?, 0o755, 0o644).map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::ELOOP))),
+            "chmod_all of a tree deeper than {TREE_WALK_MAX_DEPTH} levels should fail",
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tree-digest")]
+    #[test]
+    fn tree_digest_deep() -> Result<(), Error> {
+        let dir = tempfile::TempDir::new()?;
+
+        let mkdeep = |top, depth| {
+            let subpath = ["d"; TREE_WALK_MAX_DEPTH + 1][..depth].join("/");
+            std::fs::create_dir_all(dir.path().join(top).join(subpath))
+        };
+        let open = |top| File::open(dir.path().join(top));
+
+        mkdeep("a", TREE_WALK_MAX_DEPTH - 1)?;
+        mkdeep("b", TREE_WALK_MAX_DEPTH - 1)?;
+        assert_eq!(
+            tree_digest(open("a")?, false).map_err(|err| err.kind()),
+            tree_digest(open("b")?, false).map_err(|err| err.kind()),
+            "tree_digest of identical trees with {TREE_WALK_MAX_DEPTH} levels should match",
+        );
+
+        mkdeep("b", TREE_WALK_MAX_DEPTH)?;
+        assert_eq!(
+            tree_digest(open("b")?, false).map_err(|err| err.kind()),
+            Err(ErrorKind::OsError(Some(libc::ELOOP))),
+            "tree_digest of a tree deeper than {TREE_WALK_MAX_DEPTH} levels should fail",
+        );
+
+        Ok(())
+    }
 }
@@ -29,7 +29,7 @@ use std::{
     io::Error as IOError,
     os::unix::{
         fs::MetadataExt,
-        io::{AsFd, AsRawFd, OwnedFd},
+        io::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
     },
     path::{Path, PathBuf},
 };
@@ -120,6 +120,10 @@ pub(crate) trait FdExt {
     /// Re-open a file descriptor.
     fn reopen(&self, procfs: &ProcfsHandle, flags: OpenFlags) -> Result<OwnedFd, Error>;
 
+    /// Re-open a file descriptor with exactly the provided `flags` (that is,
+    /// without implicitly adding `O_CLOEXEC` and `O_NOCTTY`).
+    fn reopen_raw(&self, procfs: &ProcfsHandle, flags: OpenFlags) -> Result<OwnedFd, Error>;
+
     /// Get the path this RawFd is referencing.
     ///
     /// This is done through `readlink(/proc/self/fd)` and is naturally racy
@@ -181,6 +185,97 @@ const DANGEROUS_FILESYSTEMS: [rustix_fs::FsWord; 2] = [
     0x5a3c_69f0,                 // apparmorfs
 ];
 
+/// Make sure that `fd` can be re-opened through procfs.
+fn check_reopenable(fd: BorrowedFd<'_>) -> Result<(), Error> {
+    // For file descriptors referencing a symlink (i.e. opened with
+    // O_PATH|O_NOFOLLOW) there is no logic behind trying to do a "reopen"
+    // operation, and you just get confusing results because the reopen
+    // itself is done through a symlink. Even with O_EMPTYPATH you probably
+    // wouldn't ever want to re-open it (all you can get is another
+    // O_PATH|O_EMPTYPATH).
+    if fd.metadata()?.is_symlink() {
+        Err(Error::from(ErrorImpl::OsError {
+            operation: "reopen".into(),
+            source: IOError::from_raw_os_error(libc::ELOOP),
+        }))
+        .wrap("symlink file handles cannot be reopened")?
+    }
+    Ok(())
+}
+
+fn do_reopen(
+    fd: BorrowedFd<'_>,
+    procfs: &ProcfsHandle,
+    mut flags: OpenFlags,
+    raw_flags: bool,
+) -> Result<OwnedFd, Error> {
+    check_reopenable(fd)?;
+
+    // Now that we are sure the file descriptor is not a symlink, we can
+    // clear O_NOFOLLOW since it is a no-op (but due to the procfs reopening
+    // implementation, O_NOFOLLOW will cause strange behaviour).
+    flags.remove(OpenFlags::O_NOFOLLOW);
+
+    // TODO: Add support for O_EMPTYPATH once that exists...
+    let subpath = proc_subpath(fd)?;
+    if raw_flags {
+        procfs.open_follow_raw(ProcfsBase::ProcThreadSelf, subpath, flags)
+    } else {
+        procfs.open_follow(ProcfsBase::ProcThreadSelf, subpath, flags)
+    }
+    .map(OwnedFd::from)
+}
+
+/// Re-open each of `fds` with `flags` (as with [`FdExt::reopen`]), with the
+/// final `openat(2)` calls submitted in bulk using `io_uring`.
+///
+/// The returned results are in the same order as `fds`. The outer error is
+/// returned (without re-opening any of the file descriptors) if `io_uring`
+/// cannot be used, in which case callers should fall back to
+/// [`FdExt::reopen`].
+#[cfg(feature = "io-uring")]
+pub(crate) fn reopen_batch(
+    fds: &[BorrowedFd<'_>],
+    procfs: &ProcfsHandle,
+    mut flags: OpenFlags,
+) -> Result<Vec<Result<OwnedFd, Error>>, Error> {
+    // See do_reopen for why we can clear O_NOFOLLOW.
+    flags.remove(OpenFlags::O_NOFOLLOW);
+
+    // Only the file descriptors that pass the same checks as do_reopen are
+    // submitted. AT_FDCWD is not in /proc/thread-self/fd, so leave it to
+    // do_reopen.
+    let names: Vec<Result<Option<String>, Error>> = fds
+        .iter()
+        .map(|&fd| {
+            check_reopenable(fd)?;
+            Ok(fd
+                .as_raw_fd()
+                .is_positive()
+                .then(|| fd.as_raw_fd().to_string()))
+        })
+        .collect();
+    let batch: Vec<_> = names
+        .iter()
+        .filter_map(|name| name.as_ref().ok().cloned().flatten())
+        .collect();
+    let mut opened = procfs
+        .open_follow_batch(ProcfsBase::ProcThreadSelf, "fd", &batch, flags)?
+        .into_iter();
+
+    Ok(fds
+        .iter()
+        .zip(names)
+        .map(|(&fd, name)| match name? {
+            Some(_) => opened
+                .next()
+                .expect("every submitted fd should have a result")
+                .map(OwnedFd::from),
+            None => do_reopen(fd, procfs, flags, false),
+        })
+        .collect())
+}
+
 impl<Fd: AsFd> FdExt for Fd {
     fn metadata(&self) -> Result<Metadata, Error> {
         let stat = syscalls::fstatat(self.as_fd(), "").map_err(|err| ErrorImpl::RawOsError {
@@ -190,32 +285,12 @@ impl<Fd: AsFd> FdExt for Fd {
         Ok(Metadata(stat))
     }
 
-    fn reopen(&self, procfs: &ProcfsHandle, mut flags: OpenFlags) -> Result<OwnedFd, Error> {
-        let fd = self.as_fd();
-
-        // For file descriptors referencing a symlink (i.e. opened with
-        // O_PATH|O_NOFOLLOW) there is no logic behind trying to do a "reopen"
-        // operation, and you just get confusing results because the reopen
-        // itself is done through a symlink. Even with O_EMPTYPATH you probably
-        // wouldn't ever want to re-open it (all you can get is another
-        // O_PATH|O_EMPTYPATH).
-        if self.metadata()?.is_symlink() {
-            Err(Error::from(ErrorImpl::OsError {
-                operation: "reopen".into(),
-                source: IOError::from_raw_os_error(libc::ELOOP),
-            }))
-            .wrap("symlink file handles cannot be reopened")?
-        }
-
-        // Now that we are sure the file descriptor is not a symlink, we can
-        // clear O_NOFOLLOW since it is a no-op (but due to the procfs reopening
-        // implementation, O_NOFOLLOW will cause strange behaviour).
-        flags.remove(OpenFlags::O_NOFOLLOW);
+    fn reopen(&self, procfs: &ProcfsHandle, flags: OpenFlags) -> Result<OwnedFd, Error> {
+        do_reopen(self.as_fd(), procfs, flags, false)
+    }
 
-        // TODO: Add support for O_EMPTYPATH once that exists...
-        procfs
-            .open_follow(ProcfsBase::ProcThreadSelf, proc_subpath(fd)?, flags)
-            .map(OwnedFd::from)
+    fn reopen_raw(&self, procfs: &ProcfsHandle, flags: OpenFlags) -> Result<OwnedFd, Error> {
+        do_reopen(self.as_fd(), procfs, flags, true)
     }
 
     fn as_unsafe_path(&self, procfs: &ProcfsHandle) -> Result<PathBuf, Error> {
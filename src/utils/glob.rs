@@ -0,0 +1,165 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// Parse the bracket expression at the start of `pattern` and check whether it
+/// matches `ch`. Returns whether it matched and the length of the bracket
+/// expression, or [`None`] if `pattern` does not start with a valid bracket
+/// expression (in which case the `[` should be treated literally).
+fn match_bracket(pattern: &[u8], ch: u8) -> Option<(bool, usize)> {
+    debug_assert_eq!(pattern.first(), Some(&b'['));
+
+    let mut idx = 1;
+    let negate = matches!(pattern.get(idx), Some(b'!') | Some(b'^'));
+    if negate {
+        idx += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while let Some(&c) = pattern.get(idx) {
+        match c {
+            // A "]" at the start of the expression is a literal.
+            b']' if !first => return Some((matched != negate, idx + 1)),
+            _ => {
+                let (lo, len) = match (c, pattern.get(idx + 1)) {
+                    (b'\\', Some(&escaped)) => (escaped, 2),
+                    _ => (c, 1),
+                };
+                idx += len;
+                match (pattern.get(idx), pattern.get(idx + 1)) {
+                    // A range like "a-z" (a trailing "-" is a literal).
+                    (Some(b'-'), Some(&hi)) if hi != b']' => {
+                        matched |= (lo..=hi).contains(&ch);
+                        idx += 2;
+                    }
+                    _ => matched |= lo == ch,
+                }
+            }
+        }
+        first = false;
+    }
+    // Unterminated bracket expression.
+    None
+}
+
+/// Check whether `name` matches the shell-style glob `pattern` (see
+/// `glob(7)`).
+///
+/// Only `*`, `?`, bracket expressions (such as `[abc]`, `[a-z]` and `[!abc]`)
+/// and `\` escapes are supported. As with shell globbing, a leading `.` in
+/// `name` is only matched by a literal `.` in `pattern`. `pattern` is matched
+/// against the whole of `name`, and `/` has no special meaning.
+pub(crate) fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    // A leading "." in name can only be matched by a literal "." at the start
+    // of the pattern.
+    if name.first() == Some(&b'.') && !(pattern.starts_with(b".") || pattern.starts_with(b"\\.")) {
+        return false;
+    }
+
+    let (mut p, mut n) = (0, 0);
+    // The pattern position after the last "*" we saw, and the position in name
+    // up to which that "*" has matched.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        let matched = match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(1),
+            Some(b'[') => match match_bracket(&pattern[p..], name[n]) {
+                Some((true, len)) => Some(len),
+                Some((false, _)) => None,
+                None => (name[n] == b'[').then_some(1),
+            },
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == name[n]).then_some(2),
+            Some(&c) => (c == name[n]).then_some(1),
+            None => None,
+        };
+        match matched {
+            Some(len) => {
+                p += len;
+                n += 1;
+            }
+            None => match star {
+                // Let the last "*" match one more character and try again.
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p;
+                    n = star_n + 1;
+                }
+                _ => return false,
+            },
+        }
+    }
+    // Whatever is left of the pattern has to match the empty string.
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn glob_match_basic() {
+        for (pattern, name, expected) in [
+            ("foo", "foo", true),
+            ("foo", "foobar", false),
+            ("*", "foo", true),
+            ("*.txt", "a.txt", true),
+            ("*.txt", ".txt", false),
+            ("*.txt", "a.txt.bak", false),
+            ("a*b*c", "aXXbYYc", true),
+            ("a*b*c", "aXXbYY", false),
+            ("a*", "a", true),
+            ("**", "abc", true),
+            ("?", "a", true),
+            ("?", "ab", false),
+            ("a?c", "abc", true),
+            ("[abc]", "b", true),
+            ("[abc]", "d", false),
+            ("[a-c]x", "bx", true),
+            ("[!a-c]x", "bx", false),
+            ("[^a-c]x", "dx", true),
+            ("[]]", "]", true),
+            ("[a-]", "-", true),
+            ("[", "[", true),
+            ("[ab", "[ab", true),
+            ("\\*", "*", true),
+            ("\\*", "a", false),
+            ("[\\]]", "]", true),
+            // Leading dots must be matched explicitly.
+            ("*", ".hidden", false),
+            ("?hidden", ".hidden", false),
+            ("[.]hidden", ".hidden", false),
+            (".*", ".hidden", true),
+            (".h*", ".hidden", true),
+            ("a*", "a.b", true),
+        ] {
+            assert_eq!(
+                glob_match(pattern.as_bytes(), name.as_bytes()),
+                expected,
+                "glob_match({pattern:?}, {name:?})"
+            );
+        }
+    }
+}
@@ -0,0 +1,244 @@
+/*
+ * libpathrs: safe path resolution on Linux
+ * Copyright (C) 2019-2024 Aleksa Sarai <cyphar@cyphar.com>
+ * Copyright (C) 2019-2024 SUSE LLC
+ *
+ * This program is free software: you can redistribute it and/or modify it
+ * under the terms of the GNU Lesser General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or (at your
+ * option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of MERCHANTABILITY
+ * or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General Public License
+ * for more details.
+ *
+ * You should have received a copy of the GNU Lesser General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    error::{Error, ErrorExt, ErrorImpl},
+    flags::OpenFlags,
+    procfs::{ProcfsBase, GLOBAL_PROCFS_HANDLE},
+    syscalls,
+    utils::FdExt,
+};
+
+use std::{
+    ffi::OsString,
+    io::Read,
+    os::unix::{ffi::OsStringExt, fs::MetadataExt, io::AsFd},
+    path::PathBuf,
+};
+
+use rustix::fs::{major, minor, StatxFlags};
+
+/// A single entry from `/proc/[pid]/mountinfo`. Only the fields we actually
+/// use are parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MountInfoEntry {
+    pub(crate) mount_id: u64,
+    pub(crate) dev: (u32, u32),
+    pub(crate) mount_point: PathBuf,
+}
+
+/// Undo the octal escaping (`\040` for spaces, `\011` for tabs, `\012` for
+/// newlines and `\134` for backslashes) the kernel applies to paths in
+/// mountinfo.
+fn unescape_field(field: &[u8]) -> PathBuf {
+    let mut out = Vec::with_capacity(field.len());
+    let mut idx = 0;
+    while idx < field.len() {
+        let octal = field
+            .get(idx + 1..idx + 4)
+            .filter(|_| field[idx] == b'\\')
+            .filter(|digits| digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+            .map(|digits| {
+                digits
+                    .iter()
+                    .fold(0u32, |acc, d| acc * 8 + u32::from(d - b'0'))
+            })
+            .and_then(|val| u8::try_from(val).ok());
+        match octal {
+            Some(ch) => {
+                out.push(ch);
+                idx += 4;
+            }
+            None => {
+                out.push(field[idx]);
+                idx += 1;
+            }
+        }
+    }
+    OsString::from_vec(out).into()
+}
+
+fn parse_line(line: &[u8]) -> Result<MountInfoEntry, Error> {
+    let bad_line = || -> Error {
+        ErrorImpl::SafetyViolation {
+            description: format!(
+                "malformed mountinfo line {:?}",
+                String::from_utf8_lossy(line)
+            )
+            .into(),
+        }
+        .into()
+    };
+
+    // 36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+    let mut fields = line.split(|&ch| ch == b' ');
+    let mut next_str = || -> Result<&str, Error> {
+        fields
+            .next()
+            .and_then(|field| std::str::from_utf8(field).ok())
+            .ok_or_else(bad_line)
+    };
+
+    let mount_id = next_str()?.parse().map_err(ErrorImpl::from)?;
+    let _parent_id = next_str()?;
+    let (dev_major, dev_minor) = next_str()?.split_once(':').ok_or_else(bad_line)?;
+    let dev = (
+        dev_major.parse().map_err(ErrorImpl::from)?,
+        dev_minor.parse().map_err(ErrorImpl::from)?,
+    );
+    let _root = fields.next().ok_or_else(bad_line)?;
+    let mount_point = unescape_field(fields.next().ok_or_else(bad_line)?);
+
+    if !mount_point.is_absolute() {
+        Err(bad_line())?
+    }
+
+    Ok(MountInfoEntry {
+        mount_id,
+        dev,
+        mount_point,
+    })
+}
+
+pub(crate) fn parse_mountinfo(data: &[u8]) -> Result<Vec<MountInfoEntry>, Error> {
+    data.split(|&ch| ch == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(parse_line)
+        .collect()
+}
+
+/// Get the list of mounts at or below the directory `root`, as paths relative
+/// to `root` (with `root` itself being `.`). The mounts are returned in the
+/// order they appear in `mountinfo`, which is the order they were mounted.
+pub(crate) fn submounts<Fd: AsFd>(root: Fd) -> Result<Vec<PathBuf>, Error> {
+    let root = root.as_fd();
+
+    let root_path = root
+        .as_unsafe_path(&GLOBAL_PROCFS_HANDLE)
+        .wrap("get root path")?;
+    let root_meta = root.metadata().wrap("fstat root")?;
+    let root_dev = (major(root_meta.dev()), minor(root_meta.dev()));
+    // Only STATX_MNT_ID gives us the same mount ID as mountinfo
+    // (STATX_MNT_ID_UNIQUE is a different namespace of IDs).
+    let root_mnt_id = syscalls::statx(root, "", StatxFlags::MNT_ID)
+        .ok()
+        .filter(|stx| StatxFlags::from_bits_retain(stx.stx_mask).contains(StatxFlags::MNT_ID))
+        .map(|stx| stx.stx_mnt_id);
+
+    // NOTE: Mount namespaces are per-thread (unshare(CLONE_NEWNS) only
+    // affects the calling thread), so we need to use thread-self rather than
+    // self to get the mount table that applies to the root handle's path.
+    let mut mountinfo = GLOBAL_PROCFS_HANDLE
+        .open(ProcfsBase::ProcThreadSelf, "mountinfo", OpenFlags::O_RDONLY)
+        .wrap("open mountinfo")?;
+    let mut data = Vec::new();
+    mountinfo
+        .read_to_end(&mut data)
+        .map_err(|err| ErrorImpl::OsError {
+            operation: "read mountinfo".into(),
+            source: err,
+        })?;
+    let mounts = parse_mountinfo(&data)?;
+
+    // Find the mount which contains the root. If we know the mount ID of the
+    // root we can match it exactly, otherwise we fall back to the longest
+    // mount point that contains the root path (mounts can be stacked on top
+    // of each other, so the last such entry is the one visible at that path).
+    let owner = match root_mnt_id {
+        Some(mnt_id) => mounts.iter().find(|mnt| mnt.mount_id == mnt_id),
+        None => mounts
+            .iter()
+            .filter(|mnt| root_path.starts_with(&mnt.mount_point))
+            .max_by_key(|mnt| mnt.mount_point.as_os_str().len()),
+    };
+    // Make sure the mount we found actually matches the root. A mismatch means
+    // the root was moved or the mount table changed underneath us, so the
+    // mount points we would return cannot be trusted.
+    match owner {
+        Some(mnt) if mnt.dev == root_dev && root_path.starts_with(&mnt.mount_point) => (),
+        _ => Err(ErrorImpl::SafetyViolation {
+            description: format!(
+                "could not find mount for root {root_path:?} (dev {}:{}) in mountinfo",
+                root_dev.0, root_dev.1
+            )
+            .into(),
+        })?,
+    }
+
+    Ok(mounts
+        .into_iter()
+        .filter_map(|mnt| {
+            mnt.mount_point
+                .strip_prefix(&root_path)
+                .ok()
+                .map(|subpath| match subpath.as_os_str().is_empty() {
+                    true => PathBuf::from("."),
+                    false => subpath.to_path_buf(),
+                })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn unescape_field() {
+        assert_eq!(
+            super::unescape_field(b"/foo/bar"),
+            PathBuf::from("/foo/bar")
+        );
+        assert_eq!(
+            super::unescape_field(b"/foo\\040bar\\011baz\\012\\134"),
+            PathBuf::from("/foo bar\tbaz\n\\")
+        );
+        // Incomplete or invalid escapes are left alone.
+        assert_eq!(super::unescape_field(b"/a\\08"), PathBuf::from("/a\\08"));
+        assert_eq!(super::unescape_field(b"/a\\04"), PathBuf::from("/a\\04"));
+    }
+
+    #[test]
+    fn parse_mountinfo() {
+        let data =
+            b"36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue\n\
+                     37 36 0:22 / /mnt2/with\\040space rw - tmpfs tmpfs rw\n";
+        assert_eq!(
+            super::parse_mountinfo(data).expect("parse valid mountinfo"),
+            vec![
+                MountInfoEntry {
+                    mount_id: 36,
+                    dev: (98, 0),
+                    mount_point: "/mnt2".into(),
+                },
+                MountInfoEntry {
+                    mount_id: 37,
+                    dev: (0, 22),
+                    mount_point: "/mnt2/with space".into(),
+                },
+            ]
+        );
+
+        assert!(super::parse_mountinfo(b"36 35 98:0\n").is_err());
+        assert!(super::parse_mountinfo(b"36 35 98-0 / / rw - tmpfs tmpfs rw\n").is_err());
+        assert!(super::parse_mountinfo(b"36 35 98:0 / rel rw - tmpfs tmpfs rw\n").is_err());
+    }
+}
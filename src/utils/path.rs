@@ -23,7 +23,7 @@ use std::{
     collections::VecDeque,
     ffi::{CString, OsStr, OsString},
     os::unix::ffi::OsStrExt,
-    path::Path,
+    path::{Component, Path, PathBuf},
 };
 
 pub(crate) trait ToCString {
@@ -100,6 +100,34 @@ pub(crate) fn path_split(path: &'_ Path) -> Result<(&'_ Path, Option<&'_ Path>),
     Ok((dir, base))
 }
 
+/// Helper to compute the lexical relative path from the directory `from` to
+/// `to`. Both paths must be normalised (with no `.` or `..` components) and
+/// either both be absolute or both be relative.
+pub(crate) fn path_relative_to(from: &Path, to: &Path) -> PathBuf {
+    let mut from_iter = from.components().peekable();
+    let mut to_iter = to.components().peekable();
+
+    // Skip the common prefix.
+    while let (Some(a), Some(b)) = (from_iter.peek(), to_iter.peek()) {
+        if a != b {
+            break;
+        }
+        from_iter.next();
+        to_iter.next();
+    }
+
+    // Every remaining component in "from" needs to be undone with a "..".
+    let relpath = from_iter
+        .map(|_| Component::ParentDir)
+        .chain(to_iter)
+        .collect::<PathBuf>();
+    if relpath.as_os_str().is_empty() {
+        ".".into()
+    } else {
+        relpath
+    }
+}
+
 /// RawComponents is like [`Components`] except that no normalisation is done
 /// for any path components ([`Components`] normalises "/./" components), and
 /// all of the components are simply [`OsStr`].
@@ -272,7 +300,7 @@ impl<P: AsRef<Path>> PathIterExt for P {
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::{path_split, path_strip_trailing_slash, PathIterExt};
+    use crate::utils::{path_relative_to, path_split, path_strip_trailing_slash, PathIterExt};
 
     use std::path::{Path, PathBuf};
 
@@ -395,6 +423,46 @@ mod tests {
         complex3("../foo/bar/.///baz" => "../foo/bar/.//", Some("baz"));
     }
 
+    macro_rules! path_relative_to_tests {
+        // path_relative_to_tests! {
+        //      abc("/a/b", "/a/c" => "../c");
+        // }
+        ($($test_name:ident ($from:expr, $to:expr => $want:expr));* $(;)? ) => {
+            paste::paste! {
+                $(
+                    #[test]
+                    fn [<path_relative_to_ $test_name>]() {
+                        let (from, to) = (Path::new($from), Path::new($to));
+                        let got = path_relative_to(from, to);
+                        let want: PathBuf = $want.into();
+                        assert_eq!(
+                            got, want,
+                            "unexpected relative path from {from:?} to {to:?}",
+                        );
+                    }
+                )*
+            }
+        };
+    }
+
+    path_relative_to_tests! {
+        same1("/" , "/" => ".");
+        same2("/a/b/c" , "/a/b/c" => ".");
+
+        child1("/", "/a" => "a");
+        child2("/a/b", "/a/b/c/d" => "c/d");
+
+        parent1("/a", "/" => "..");
+        parent2("/a/b/c/d", "/a/b" => "../..");
+
+        sibling1("/a/b", "/a/c" => "../c");
+        sibling2("/a/b/c", "/a/d/e/f" => "../../d/e/f");
+        sibling3("/foo", "/bar/baz" => "../bar/baz");
+
+        relative1("a/b", "a/c/d" => "../c/d");
+        relative2("", "a/b" => "a/b");
+    }
+
     macro_rules! path_ancestor_tests {
         ($($test_name:ident ($path:expr => { $(($ancestor:expr, $remaining:expr)),* }));* $(;)? ) => {
             paste::paste! {